@@ -0,0 +1,278 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use sysinfo::{Disks, Networks, System};
+
+/// Start of every fingerprint machine id; the number is the format version
+const FINGERPRINT_PREFIX: &str = "M2";
+
+/// Share (in percent) of a licensed fingerprint's weight this machine has to match
+pub const MATCH_PERCENT: u32 = 60;
+
+/// One piece of hardware or installation identity that goes into the machine id
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Component {
+    /// Id the OS gives the installation or board (machine-id file, MachineGuid, IOPlatformUUID)
+    MachineUuid,
+    Cpu,
+    /// Fixed (non-removable) disks and their sizes
+    Disks,
+    /// MAC addresses of the network interfaces
+    Network,
+    Host,
+}
+
+impl Component {
+    pub const ALL: &'static [Component] =
+        &[Component::MachineUuid, Component::Cpu, Component::Disks, Component::Network, Component::Host];
+
+    /// How much the component counts towards a match; stable ones count more
+    pub fn weight(self) -> u32 {
+        match self {
+            Component::MachineUuid => 4,
+            Component::Cpu | Component::Disks => 2,
+            Component::Network | Component::Host => 1,
+        }
+    }
+
+    /// Letter in front of the component's hash in a machine id
+    fn tag(self) -> char {
+        match self {
+            Component::MachineUuid => 'u',
+            Component::Cpu => 'c',
+            Component::Disks => 'd',
+            Component::Network => 'n',
+            Component::Host => 'h',
+        }
+    }
+
+    fn from_tag(tag: char) -> Option<Component> {
+        Component::ALL.iter().copied().find(|component| component.tag() == tag)
+    }
+}
+
+/// Hashes of the components that could be read on a machine
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Fingerprint {
+    parts: BTreeMap<Component, String>,
+}
+
+/// How one component of a licensed fingerprint compares with this machine
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComponentCheck {
+    pub component: Component,
+    pub weight: u32,
+    /// Found on this machine at all
+    pub present: bool,
+    pub matches: bool,
+}
+
+/// Component-by-component comparison of a licensed fingerprint with this machine
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FingerprintMatch {
+    pub components: Vec<ComponentCheck>,
+    /// Share of the licensed weight that matches, in percent
+    pub score: u32,
+    pub matches: bool,
+}
+
+fn short_hash(component: Component, value: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{:?}:{}", component, value).as_bytes());
+    hex::encode(&hasher.finalize()[..4])
+}
+
+impl Fingerprint {
+    /// Fingerprint from raw component values; empty values are left out
+    pub fn from_values<'a>(values: impl IntoIterator<Item = (Component, &'a str)>) -> Self {
+        let parts = values
+            .into_iter()
+            .map(|(component, value)| (component, value.trim()))
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(component, value)| (component, short_hash(component, value)))
+            .collect();
+        Fingerprint { parts }
+    }
+
+    /// Fingerprint of this machine
+    pub fn current(system: &System) -> Self {
+        let cpu = system
+            .cpus()
+            .first()
+            .map(|cpu| format!("{}|{}", cpu.brand().trim(), system.cpus().len()))
+            .unwrap_or_default();
+
+        let disks = Disks::new_with_refreshed_list();
+        let mut fixed: Vec<String> = disks
+            .list()
+            .iter()
+            .filter(|disk| !disk.is_removable() && disk.total_space() > 0)
+            .map(|disk| format!("{}:{}", disk.name().to_string_lossy(), disk.total_space()))
+            .collect();
+        fixed.sort();
+        fixed.dedup();
+
+        let networks = Networks::new_with_refreshed_list();
+        let mut macs: Vec<String> = networks
+            .values()
+            .map(|data| data.mac_address())
+            .filter(|mac| !mac.is_unspecified())
+            .map(|mac| mac.to_string())
+            .collect();
+        macs.sort();
+        macs.dedup();
+
+        Fingerprint::from_values([
+            (Component::MachineUuid, machine_uuid().unwrap_or_default().as_str()),
+            (Component::Cpu, cpu.as_str()),
+            (Component::Disks, fixed.join(",").as_str()),
+            (Component::Network, macs.join(",").as_str()),
+            (Component::Host, System::host_name().unwrap_or_default().to_lowercase().as_str()),
+        ])
+    }
+
+    /// Machine id shown to users and put in licenses, e.g. `M2-u1a2b3c4d-c5e6f7a8b-...`
+    pub fn encode(&self) -> String {
+        std::iter::once(FINGERPRINT_PREFIX.to_string())
+            .chain(self.parts.iter().map(|(component, hash)| format!("{}{}", component.tag(), hash)))
+            .collect::<Vec<_>>()
+            .join("-")
+    }
+
+    /// Read a machine id made by `encode`; `None` for anything else, such as older machine ids
+    pub fn parse(machine_id: &str) -> Option<Self> {
+        let mut groups = machine_id.trim().split('-');
+        if groups.next()? != FINGERPRINT_PREFIX {
+            return None;
+        }
+        let mut parts = BTreeMap::new();
+        for group in groups {
+            let mut chars = group.chars();
+            let component = Component::from_tag(chars.next()?)?;
+            let hash = chars.as_str().to_ascii_lowercase();
+            if hash.len() != 8 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                return None;
+            }
+            parts.insert(component, hash);
+        }
+        (!parts.is_empty()).then_some(Fingerprint { parts })
+    }
+
+    /// Compare the fingerprint a license was issued for with this one
+    pub fn compare(&self, licensed: &Fingerprint) -> FingerprintMatch {
+        let components: Vec<ComponentCheck> = licensed
+            .parts
+            .iter()
+            .map(|(component, hash)| ComponentCheck {
+                component: *component,
+                weight: component.weight(),
+                present: self.parts.contains_key(component),
+                matches: self.parts.get(component) == Some(hash),
+            })
+            .collect();
+        let total: u32 = components.iter().map(|check| check.weight).sum();
+        let matched: u32 = components.iter().filter(|check| check.matches).map(|check| check.weight).sum();
+        let score = (matched * 100).checked_div(total).unwrap_or(0);
+        FingerprintMatch { components, score, matches: total > 0 && score >= MATCH_PERCENT }
+    }
+}
+
+/// Id the OS keeps for this installation, if it can be read
+#[cfg(target_os = "linux")]
+fn machine_uuid() -> Option<String> {
+    ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+        .iter()
+        .find_map(|path| std::fs::read_to_string(path).ok())
+        .map(|id| id.trim().to_string())
+}
+
+#[cfg(target_os = "windows")]
+fn machine_uuid() -> Option<String> {
+    use std::os::windows::process::CommandExt;
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    let output = std::process::Command::new("reg")
+        .args(["query", r"HKLM\SOFTWARE\Microsoft\Cryptography", "/v", "MachineGuid"])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find(|line| line.contains("MachineGuid"))
+        .and_then(|line| line.split_whitespace().last())
+        .map(str::to_string)
+}
+
+#[cfg(target_os = "macos")]
+fn machine_uuid() -> Option<String> {
+    let output = std::process::Command::new("ioreg").args(["-rd1", "-c", "IOPlatformExpertDevice"]).output().ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find(|line| line.contains("IOPlatformUUID"))
+        .and_then(|line| line.split('"').nth(3))
+        .map(str::to_string)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+fn machine_uuid() -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fingerprint(uuid: &str, cpu: &str, disks: &str, network: &str, host: &str) -> Fingerprint {
+        Fingerprint::from_values([
+            (Component::MachineUuid, uuid),
+            (Component::Cpu, cpu),
+            (Component::Disks, disks),
+            (Component::Network, network),
+            (Component::Host, host),
+        ])
+    }
+
+    #[test]
+    fn test_encode_parse() {
+        let machine = fingerprint("4c4c4544", "Intel Core i5|8", "C::512000", "aa:bb:cc:dd:ee:ff", "shop-pc");
+        let id = machine.encode();
+        assert!(id.starts_with("M2-u"));
+        assert_eq!(Fingerprint::parse(&id), Some(machine.clone()));
+        assert_eq!(Fingerprint::parse(&id.replacen("M2", "M3", 1)), None);
+        assert_eq!(Fingerprint::parse("0123456789abcdef0123456789abcdef"), None);
+        assert_eq!(Fingerprint::parse("M2"), None);
+        assert_eq!(Fingerprint::parse("M2-x12345678"), None);
+
+        // Components that can't be read are left out
+        let partial = fingerprint("", "Intel Core i5|8", "", "", "shop-pc");
+        assert_eq!(partial.encode().matches('-').count(), 2);
+    }
+
+    #[test]
+    fn test_tolerance() {
+        let licensed = fingerprint("4c4c4544", "Intel Core i5|8", "C::512000", "aa:bb:cc:dd:ee:ff", "shop-pc");
+        assert_eq!(licensed.compare(&licensed).score, 100);
+
+        // New network card and a new name: still the same machine
+        let renamed = fingerprint("4c4c4544", "Intel Core i5|8", "C::512000", "11:22:33:44:55:66", "front-desk");
+        let result = renamed.compare(&licensed);
+        assert_eq!((result.score, result.matches), (80, true));
+        assert!(!result.components.iter().find(|c| c.component == Component::Host).unwrap().matches);
+
+        // Reinstalled OS: the machine id file is new but the hardware is the same
+        let reinstalled = fingerprint("9f9f9f9f", "Intel Core i5|8", "C::512000", "aa:bb:cc:dd:ee:ff", "shop-pc");
+        assert!(reinstalled.compare(&licensed).matches);
+
+        // A different computer only shares the CPU model
+        let other = fingerprint("12345678", "Intel Core i5|8", "C::256000", "01:02:03:04:05:06", "office");
+        assert_eq!(other.compare(&licensed).score, 20);
+        assert!(!other.compare(&licensed).matches);
+
+        let missing = fingerprint("", "Intel Core i5|8", "C::512000", "", "");
+        let result = missing.compare(&licensed);
+        assert!(!result.matches);
+        assert!(!result.components.iter().find(|c| c.component == Component::MachineUuid).unwrap().present);
+        assert!(!licensed.compare(&Fingerprint::default()).matches);
+    }
+}
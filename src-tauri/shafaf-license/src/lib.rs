@@ -0,0 +1,421 @@
+//! License keys of the Shafaf app and the machine ids they are issued for.
+//!
+//! Everything here works without the app, so the `shafaf-license` tool issues and checks keys
+//! with exactly the code the app checks them with.
+
+pub mod fingerprint;
+
+use crate::fingerprint::{Fingerprint, FingerprintMatch};
+use chrono::{NaiveDate, Utc};
+use data_encoding::BASE64URL_NOPAD;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use sysinfo::System;
+
+/// Start of every signed license key; the number is the format version
+const LICENSE_PREFIX: &str = "SHAFAF1";
+
+/// Public half (hex) of the key licenses are signed with. Only this half ships in the app,
+/// so keys can be checked but not made. Set `SHAFAF_LICENSE_PUBLIC_KEY` when building to
+/// use a different issuing key.
+const LICENSE_PUBLIC_KEY: &str = match option_env!("SHAFAF_LICENSE_PUBLIC_KEY") {
+    Some(key) => key,
+    None => "93e20a21149d61c2af0276dc1483d40823f0f14ba5e6bbda7f32f4a4ee538bcc",
+};
+
+/// The machine licenses are checked on
+pub struct Machine {
+    pub fingerprint: Fingerprint,
+    /// Machine id of versions before fingerprints, still accepted in licenses issued for it
+    pub legacy_id: Option<String>,
+}
+
+impl Machine {
+    pub fn current() -> Self {
+        let mut system = System::new();
+        system.refresh_all();
+        Machine { fingerprint: Fingerprint::current(&system), legacy_id: Some(legacy_machine_id(&system)) }
+    }
+
+    /// The machine with id `machine_id`, as shown in the app; for checking licenses on another computer
+    pub fn from_machine_id(machine_id: &str) -> Self {
+        match Fingerprint::parse(machine_id) {
+            Some(fingerprint) => Machine { fingerprint, legacy_id: None },
+            None => Machine { fingerprint: Fingerprint::default(), legacy_id: Some(machine_id.trim().to_string()) },
+        }
+    }
+
+    /// Whether a license issued for `machine_id` belongs to this machine
+    pub fn matches(&self, machine_id: &str) -> bool {
+        match Fingerprint::parse(machine_id) {
+            Some(licensed) => self.fingerprint.compare(&licensed).matches,
+            None => self.legacy_id.as_deref().is_some_and(|id| id.eq_ignore_ascii_case(machine_id.trim())),
+        }
+    }
+}
+
+/// Machine id to issue licenses for: hashes of several hardware components, so a license
+/// survives an OS update or one or two hardware changes
+pub fn generate_machine_id() -> String {
+    Machine::current().fingerprint.encode()
+}
+
+/// Machine id of versions before fingerprints. It changes with every kernel update or memory upgrade.
+fn legacy_machine_id(system: &System) -> String {
+    let mut components = Vec::new();
+
+    if let Some(cpu) = system.cpus().first() {
+        components.push(format!("cpu:{}", cpu.brand()));
+    }
+    if let Some(hostname) = System::host_name() {
+        components.push(format!("host:{}", hostname));
+    }
+    if let Some(name) = System::name() {
+        components.push(format!("sys:{}", name));
+    }
+    if let Some(kernel) = System::kernel_version() {
+        components.push(format!("kernel:{}", kernel));
+    }
+    components.push(format!("mem:{}", system.total_memory()));
+    components.push(format!("cpu_count:{}", system.cpus().len()));
+
+    let mut hasher = Sha256::new();
+    hasher.update(components.join("|").as_bytes());
+    hex::encode(&hasher.finalize()[..16])
+}
+
+/// What a license grants; the signed part of a license key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct License {
+    /// Unique id of the issued license
+    pub id: String,
+    pub machine_id: String,
+    pub customer: String,
+    pub edition: String,
+    #[serde(default)]
+    pub features: Vec<String>,
+    pub issued_at: NaiveDate,
+    /// Last day the license is valid; `None` never expires
+    pub expires_at: Option<NaiveDate>,
+    /// Most users that may be active at once; `None` is unlimited
+    #[serde(default)]
+    pub seats: Option<u32>,
+}
+
+/// Optional part of the app a license has to include
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Module {
+    Payroll,
+    Accounting,
+    MultiCurrency,
+}
+
+impl Module {
+    pub const ALL: &'static [Module] = &[Module::Payroll, Module::Accounting, Module::MultiCurrency];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Module::Payroll => "payroll",
+            Module::Accounting => "accounting",
+            Module::MultiCurrency => "multi_currency",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Module> {
+        Module::ALL.iter().copied().find(|module| module.name() == name)
+    }
+}
+
+impl License {
+    /// Modules of the edition plus those added as features. The full and trial editions
+    /// have every module, other editions only what their features list.
+    pub fn modules(&self) -> BTreeSet<Module> {
+        let mut modules: BTreeSet<Module> = match self.edition.as_str() {
+            "full" | "trial" => Module::ALL.iter().copied().collect(),
+            _ => BTreeSet::new(),
+        };
+        modules.extend(self.features.iter().filter_map(|feature| Module::parse(feature)));
+        modules
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LicenseStatus {
+    Valid,
+    Malformed,
+    InvalidSignature,
+    WrongMachine,
+    Expired,
+}
+
+/// Result of checking a license key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LicenseInfo {
+    pub valid: bool,
+    pub status: LicenseStatus,
+    /// Why the license was rejected
+    pub reason: Option<String>,
+    /// The license details, whenever the signature checked out
+    pub license: Option<License>,
+}
+
+impl LicenseInfo {
+    pub fn rejected(status: LicenseStatus, reason: &str, license: Option<License>) -> Self {
+        LicenseInfo { valid: false, status, reason: Some(reason.to_string()), license }
+    }
+}
+
+/// The key licenses are checked against
+pub fn public_key() -> Result<VerifyingKey, String> {
+    parse_public_key(LICENSE_PUBLIC_KEY)
+}
+
+/// A public key written as hex
+pub fn parse_public_key(key: &str) -> Result<VerifyingKey, String> {
+    let bytes: [u8; 32] = hex::decode(key.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or("Invalid license public key")?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| format!("Invalid license public key: {}", e))
+}
+
+/// Sign `license` into a license key
+pub fn sign_license(license: &License, key: &SigningKey) -> Result<String, String> {
+    let payload = serde_json::to_vec(license).map_err(|e| format!("Failed to encode license: {}", e))?;
+    let signature = key.sign(&payload);
+    Ok(format!(
+        "{}.{}.{}",
+        LICENSE_PREFIX,
+        BASE64URL_NOPAD.encode(&payload),
+        BASE64URL_NOPAD.encode(&signature.to_bytes())
+    ))
+}
+
+/// The license in `key` if it is well formed and signed by `public_key`
+fn decode_license(key: &str, public_key: &VerifyingKey) -> Result<License, (LicenseStatus, &'static str)> {
+    const DAMAGED: (LicenseStatus, &str) = (LicenseStatus::Malformed, "The license key is damaged");
+    let parts: Vec<&str> = key.split('.').collect();
+    let [prefix, payload, signature] = parts[..] else {
+        if key.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err((LicenseStatus::Malformed, "This license key is from an older version of the app; please ask for a new license"));
+        }
+        return Err((LicenseStatus::Malformed, "This is not a license key"));
+    };
+    if prefix != LICENSE_PREFIX {
+        return Err((LicenseStatus::Malformed, "This license key is for a different version of the app"));
+    }
+    let payload = BASE64URL_NOPAD.decode(payload.as_bytes()).map_err(|_| DAMAGED)?;
+    let signature: [u8; 64] = BASE64URL_NOPAD
+        .decode(signature.as_bytes())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(DAMAGED)?;
+    public_key
+        .verify(&payload, &Signature::from_bytes(&signature))
+        .map_err(|_| (LicenseStatus::InvalidSignature, "The license key was not issued for this app or was changed"))?;
+    serde_json::from_slice(&payload).map_err(|_| DAMAGED)
+}
+
+/// The license in `key` without checking who signed it, to see what a rejected key was issued for
+pub fn read_license(key: &str) -> Option<License> {
+    let key = strip_whitespace(key);
+    let payload = key.strip_prefix(LICENSE_PREFIX)?.strip_prefix('.')?.split('.').next()?;
+    let payload = BASE64URL_NOPAD.decode(payload.as_bytes()).ok()?;
+    serde_json::from_slice(&payload).ok()
+}
+
+/// Keys are often pasted with line breaks
+fn strip_whitespace(key: &str) -> String {
+    key.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+/// Check a license key for `machine` on `today`
+pub fn check_license(key: &str, public_key: &VerifyingKey, machine: &Machine, today: NaiveDate) -> LicenseInfo {
+    let license = match decode_license(&strip_whitespace(key), public_key) {
+        Ok(license) => license,
+        Err((status, reason)) => return LicenseInfo::rejected(status, reason, None),
+    };
+    if !machine.matches(&license.machine_id) {
+        return LicenseInfo::rejected(LicenseStatus::WrongMachine, "The license was issued for a different computer", Some(license));
+    }
+    if license.expires_at.is_some_and(|expires_at| expires_at < today) {
+        return LicenseInfo::rejected(LicenseStatus::Expired, "The license has expired", Some(license));
+    }
+    LicenseInfo { valid: true, status: LicenseStatus::Valid, reason: None, license: Some(license) }
+}
+
+/// Validate a license key against this machine
+pub fn validate_license_key(entered_key: &str) -> Result<LicenseInfo, String> {
+    Ok(check_license(entered_key, &public_key()?, &Machine::current(), Utc::now().date_naive()))
+}
+
+/// Which parts of this machine still match the machine a license was issued for
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MachineDiagnosis {
+    pub machine_id: String,
+    /// Machine id in the license, if a license could be read
+    pub licensed_machine_id: Option<String>,
+    /// Component by component, if the license has a fingerprint machine id
+    pub comparison: Option<FingerprintMatch>,
+    pub matches: bool,
+}
+
+/// Compare `machine` with the machine the license in `key` was issued for
+pub fn diagnose_machine(key: Option<&str>, public_key: &VerifyingKey, machine: &Machine) -> MachineDiagnosis {
+    let licensed_machine_id = key
+        .and_then(|key| decode_license(&strip_whitespace(key), public_key).ok())
+        .map(|license| license.machine_id);
+    let comparison = licensed_machine_id
+        .as_deref()
+        .and_then(Fingerprint::parse)
+        .map(|licensed| machine.fingerprint.compare(&licensed));
+    MachineDiagnosis {
+        machine_id: machine.fingerprint.encode(),
+        matches: licensed_machine_id.as_deref().is_some_and(|id| machine.matches(id)),
+        licensed_machine_id,
+        comparison,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fingerprint::Component;
+
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    fn machine(uuid: &str, host: &str) -> Machine {
+        let fingerprint = Fingerprint::from_values([
+            (Component::MachineUuid, uuid),
+            (Component::Cpu, "Intel Core i5|8"),
+            (Component::Disks, "C::512000"),
+            (Component::Network, "aa:bb:cc:dd:ee:ff"),
+            (Component::Host, host),
+        ]);
+        Machine { fingerprint, legacy_id: None }
+    }
+
+    fn license(expires_at: Option<&str>) -> License {
+        License {
+            id: "lic-1".to_string(),
+            machine_id: machine("4c4c4544", "shop-pc").fingerprint.encode(),
+            customer: "Karimi Traders".to_string(),
+            edition: "full".to_string(),
+            features: vec!["accounting".to_string()],
+            issued_at: date("2026-01-01"),
+            expires_at: expires_at.map(date),
+            seats: None,
+        }
+    }
+
+    #[test]
+    fn test_machine_id_consistency() {
+        let id1 = generate_machine_id();
+        let id2 = generate_machine_id();
+        // Machine ID should be consistent within the same session
+        assert_eq!(id1, id2);
+        assert!(Fingerprint::parse(&id1).is_some());
+    }
+
+    #[test]
+    fn test_signed_license() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let verifying_key = signing_key.verifying_key();
+        let today = date("2026-06-01");
+        let key = sign_license(&license(Some("2026-12-31")), &signing_key).unwrap();
+
+        let info = check_license(&key, &verifying_key, &machine("4c4c4544", "shop-pc"), today);
+        assert!(info.valid, "{:?}", info.reason);
+        assert_eq!(info.license.unwrap().customer, "Karimi Traders");
+        // Wrapped over several lines still works
+        let wrapped = format!("{}\n{}", &key[..20], &key[20..]);
+        assert!(check_license(&wrapped, &verifying_key, &machine("4c4c4544", "shop-pc"), today).valid);
+        // Renaming the computer doesn't lose the license, reinstalling and renaming it does
+        assert!(check_license(&key, &verifying_key, &machine("4c4c4544", "front-desk"), today).valid);
+        let other_machine = check_license(&key, &verifying_key, &machine("9f9f9f9f", "front-desk"), today);
+        assert_eq!(other_machine.status, LicenseStatus::WrongMachine);
+        assert!(other_machine.license.is_some());
+        let expired = check_license(&key, &verifying_key, &machine("4c4c4544", "shop-pc"), date("2027-01-01"));
+        assert_eq!((expired.valid, expired.status), (false, LicenseStatus::Expired));
+        assert!(expired.reason.is_some());
+    }
+
+    #[test]
+    fn test_rejected_licenses() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let verifying_key = signing_key.verifying_key();
+        let machine = machine("4c4c4544", "shop-pc");
+        let today = date("2026-06-01");
+        let status = |key: &str| check_license(key, &verifying_key, &machine, today).status;
+
+        // Signed with another key, or with the payload changed after signing
+        let forged = sign_license(&license(None), &SigningKey::from_bytes(&[8u8; 32])).unwrap();
+        assert_eq!(status(&forged), LicenseStatus::InvalidSignature);
+        let key = sign_license(&license(Some("2026-12-31")), &signing_key).unwrap();
+        let parts: Vec<&str> = key.split('.').collect();
+        let longer = BASE64URL_NOPAD.encode(&serde_json::to_vec(&license(None)).unwrap());
+        assert_eq!(status(&format!("{}.{}.{}", parts[0], longer, parts[2])), LicenseStatus::InvalidSignature);
+
+        assert_eq!(status("a3f09c11b2"), LicenseStatus::Malformed);
+        assert_eq!(status("not a key"), LicenseStatus::Malformed);
+        assert_eq!(status(&key.replacen("SHAFAF1", "SHAFAF9", 1)), LicenseStatus::Malformed);
+        assert_eq!(status(&format!("{}.{}.AAAA", parts[0], parts[1])), LicenseStatus::Malformed);
+    }
+
+    #[test]
+    fn test_legacy_machine_id() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let legacy = License { machine_id: "0123456789abcdef0123456789abcdef".to_string(), ..license(None) };
+        let key = sign_license(&legacy, &signing_key).unwrap();
+        let machine = Machine { legacy_id: Some("0123456789ABCDEF0123456789ABCDEF".to_string()), ..machine("4c4c4544", "shop-pc") };
+        let today = date("2026-06-01");
+        assert!(check_license(&key, &signing_key.verifying_key(), &machine, today).valid);
+        let moved = Machine { legacy_id: Some("ffffffffffffffffffffffffffffffff".to_string()), ..machine };
+        assert!(!check_license(&key, &signing_key.verifying_key(), &moved, today).valid);
+    }
+
+    #[test]
+    fn test_read_license() {
+        let forged = sign_license(&license(None), &SigningKey::from_bytes(&[8u8; 32])).unwrap();
+        assert_eq!(read_license(&forged), Some(license(None)));
+        assert_eq!(read_license("not a key"), None);
+
+        // Checking for another computer by its machine id
+        let key = sign_license(&license(None), &SigningKey::from_bytes(&[7u8; 32])).unwrap();
+        let verifying_key = SigningKey::from_bytes(&[7u8; 32]).verifying_key();
+        let today = date("2026-06-01");
+        let machine = Machine::from_machine_id(&license(None).machine_id);
+        assert!(check_license(&key, &verifying_key, &machine, today).valid);
+        let other = Machine::from_machine_id("0123456789abcdef0123456789abcdef");
+        assert_eq!(check_license(&key, &verifying_key, &other, today).status, LicenseStatus::WrongMachine);
+        assert_eq!(parse_public_key(&hex::encode(verifying_key.as_bytes())), Ok(verifying_key));
+    }
+
+    #[test]
+    fn test_diagnose_machine() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let key = sign_license(&license(None), &signing_key).unwrap();
+        let diagnosis = diagnose_machine(Some(&key), &signing_key.verifying_key(), &machine("9f9f9f9f", "shop-pc"));
+        assert!(diagnosis.matches);
+        assert_eq!(diagnosis.licensed_machine_id, Some(license(None).machine_id));
+        let comparison = diagnosis.comparison.unwrap();
+        let changed: Vec<Component> =
+            comparison.components.iter().filter(|c| !c.matches).map(|c| c.component).collect();
+        assert_eq!((comparison.score, changed), (60, vec![Component::MachineUuid]));
+
+        let unreadable = diagnose_machine(Some("not a key"), &signing_key.verifying_key(), &machine("4c4c4544", "shop-pc"));
+        assert_eq!((unreadable.licensed_machine_id, unreadable.matches), (None, false));
+        assert!(diagnose_machine(None, &signing_key.verifying_key(), &machine("4c4c4544", "shop-pc")).comparison.is_none());
+    }
+
+    #[test]
+    fn test_public_key() {
+        assert!(public_key().is_ok());
+    }
+}
@@ -0,0 +1,567 @@
+//! Issue, inspect and verify Shafaf license keys, and keep a ledger of what was issued and revoked.
+//!
+//! Run `shafaf-license help` for usage.
+
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use shafaf_license::{
+    check_license, parse_public_key, public_key, read_license, sign_license, License, LicenseStatus, Machine, Module,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: shafaf-license <command> [options]
+
+Commands:
+  keygen [--out <file>]
+      Create a signing key. Build the app with the printed public key in SHAFAF_LICENSE_PUBLIC_KEY.
+  issue --machine-id <id> --customer <name> [--edition <name>] [--feature <module>]...
+        [--expires <YYYY-MM-DD> | --days <n>] [--seats <n>]
+      Issue a license and print its key.
+  batch <file.csv>
+      Issue a license per row of machine_id,customer,edition,features,expires_at,seats
+      (header required, features separated by ';'); prints id,customer,machine_id,key.
+  inspect <key>
+      Show what a key grants and whether its signature is valid.
+  verify <key> --machine-id <id> [--date <YYYY-MM-DD>]
+      Check a key the way the app on that machine would, including the revocation list.
+  revoke <license-id> [--reason <text>]
+      Add a license to the revocation list.
+  list
+      Show every license in the ledger.
+  revocations [--json]
+      Print the revoked license ids.
+
+Options for every command:
+  --key <file>         Signing key (hex); defaults to SHAFAF_LICENSE_SIGNING_KEY
+  --public-key <hex>   Key to verify with instead of the one built into the app
+  --ledger <file>      Ledger of issued and revoked licenses (default: licenses.jsonl)
+";
+
+/// Options that don't take a value
+const FLAGS: &[&str] = &["json"];
+
+/// One line of the ledger
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum LedgerEntry {
+    Issued { at: DateTime<Utc>, license: License, key: String },
+    Revoked { at: DateTime<Utc>, id: String, reason: Option<String> },
+}
+
+/// Positional arguments and `--name value` options
+#[derive(Debug, Default)]
+struct Args {
+    positional: Vec<String>,
+    options: BTreeMap<String, Vec<String>>,
+}
+
+impl Args {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                parsed.positional.push(arg);
+                continue;
+            };
+            let value = if FLAGS.contains(&name) {
+                String::new()
+            } else {
+                args.next().ok_or(format!("--{} needs a value", name))?
+            };
+            parsed.options.entry(name.to_string()).or_default().push(value);
+        }
+        Ok(parsed)
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.options.get(name).and_then(|values| values.last()).map(String::as_str)
+    }
+
+    fn require(&self, name: &str) -> Result<&str, String> {
+        self.get(name).ok_or(format!("--{} is required", name))
+    }
+
+    fn all(&self, name: &str) -> Vec<String> {
+        self.options.get(name).cloned().unwrap_or_default()
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.options.contains_key(name)
+    }
+
+    fn positional(&self, index: usize, what: &str) -> Result<&str, String> {
+        self.positional.get(index).map(String::as_str).ok_or(format!("Missing {}", what))
+    }
+
+    fn ledger(&self) -> &str {
+        self.get("ledger").unwrap_or("licenses.jsonl")
+    }
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => return usage_error(&e),
+    };
+    let Some(command) = args.positional.first().cloned() else {
+        return usage_error("Missing command");
+    };
+    let result = match command.as_str() {
+        "keygen" => keygen(&args),
+        "issue" => issue(&args),
+        "batch" => batch(&args),
+        "inspect" => inspect(&args),
+        "verify" => verify(&args),
+        "revoke" => revoke(&args),
+        "list" => list(&args),
+        "revocations" => revocations(&args),
+        "help" | "-h" => {
+            print!("{}", USAGE);
+            Ok(true)
+        }
+        other => return usage_error(&format!("Unknown command {}", other)),
+    };
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn usage_error(message: &str) -> ExitCode {
+    eprintln!("{}\n\n{}", message, USAGE);
+    ExitCode::from(2)
+}
+
+fn keygen(args: &Args) -> Result<bool, String> {
+    let mut secret = [0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    let key = SigningKey::from_bytes(&secret);
+    match args.get("out") {
+        Some(path) => {
+            if fs::metadata(path).is_ok() {
+                return Err(format!("{} already exists; refusing to overwrite a signing key", path));
+            }
+            fs::write(path, format!("{}\n", hex::encode(secret))).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+            eprintln!("Signing key written to {}; keep it private", path);
+        }
+        None => println!("signing key: {}", hex::encode(secret)),
+    }
+    println!("public key:  {}", hex::encode(key.verifying_key().as_bytes()));
+    Ok(true)
+}
+
+fn signing_key(args: &Args) -> Result<SigningKey, String> {
+    let text = match args.get("key") {
+        Some(path) => fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?,
+        None => std::env::var("SHAFAF_LICENSE_SIGNING_KEY").map_err(|_| "Pass --key or set SHAFAF_LICENSE_SIGNING_KEY")?,
+    };
+    let bytes: [u8; 32] = hex::decode(text.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or("The signing key must be 32 bytes of hex")?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+/// Key to check licenses with: `--public-key`, the signing key's if one is given, else the app's
+fn verifying_key(args: &Args) -> Result<VerifyingKey, String> {
+    if let Some(key) = args.get("public-key") {
+        return parse_public_key(key);
+    }
+    if args.has("key") || std::env::var("SHAFAF_LICENSE_SIGNING_KEY").is_ok() {
+        return Ok(signing_key(args)?.verifying_key());
+    }
+    public_key()
+}
+
+fn parse_date(text: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d").map_err(|_| format!("{} is not a YYYY-MM-DD date", text))
+}
+
+fn new_license_id(today: NaiveDate) -> String {
+    let mut bytes = [0u8; 4];
+    OsRng.fill_bytes(&mut bytes);
+    format!("L{}-{}", today.format("%Y%m%d"), hex::encode(bytes))
+}
+
+/// A license checked for the mistakes that would only show once the customer enters it
+fn new_license(
+    machine_id: &str,
+    customer: &str,
+    edition: &str,
+    features: Vec<String>,
+    expires_at: Option<NaiveDate>,
+    seats: Option<u32>,
+    today: NaiveDate,
+) -> Result<License, String> {
+    if machine_id.trim().is_empty() || customer.trim().is_empty() {
+        return Err("A machine id and a customer are required".to_string());
+    }
+    if let Some(feature) = features.iter().find(|feature| Module::parse(feature).is_none()) {
+        return Err(format!("Unknown module {}; use payroll, accounting or multi_currency", feature));
+    }
+    if expires_at.is_some_and(|expires_at| expires_at < today) {
+        return Err("The license would already have expired".to_string());
+    }
+    if seats == Some(0) {
+        return Err("A license needs at least one seat".to_string());
+    }
+    Ok(License {
+        id: new_license_id(today),
+        machine_id: machine_id.trim().to_string(),
+        customer: customer.trim().to_string(),
+        edition: edition.trim().to_string(),
+        features,
+        issued_at: today,
+        expires_at,
+        seats,
+    })
+}
+
+fn issue(args: &Args) -> Result<bool, String> {
+    let key = signing_key(args)?;
+    let today = Utc::now().date_naive();
+    let expires_at = match (args.get("expires"), args.get("days")) {
+        (Some(_), Some(_)) => return Err("Use either --expires or --days".to_string()),
+        (Some(date), None) => Some(parse_date(date)?),
+        (None, Some(days)) => {
+            let days: i64 = days.parse().map_err(|_| "--days must be a number")?;
+            Some(today + TimeDelta::days(days))
+        }
+        (None, None) => None,
+    };
+    let seats = args.get("seats").map(|seats| seats.parse().map_err(|_| "--seats must be a number")).transpose()?;
+    let license = new_license(
+        args.require("machine-id")?,
+        args.require("customer")?,
+        args.get("edition").unwrap_or("full"),
+        args.all("feature"),
+        expires_at,
+        seats,
+        today,
+    )?;
+    let license_key = sign_license(&license, &key)?;
+    record(args.ledger(), &[LedgerEntry::Issued { at: Utc::now(), license: license.clone(), key: license_key.clone() }])?;
+    eprintln!("Issued {} for {}", license.id, license.customer);
+    println!("{}", license_key);
+    Ok(true)
+}
+
+/// Split a CSV line, allowing quoted fields with commas and doubled quotes
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields.into_iter().map(|field| field.trim().to_string()).collect()
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Licenses for every row of a batch CSV; nothing is issued if any row is wrong
+fn batch_licenses(csv: &str, today: NaiveDate) -> Result<Vec<License>, String> {
+    let mut lines = csv.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let (_, header) = lines.next().ok_or("The CSV file is empty")?;
+    let header = csv_fields(header);
+    let column = |name: &str| header.iter().position(|column| column.eq_ignore_ascii_case(name));
+    let (Some(machine_column), Some(customer_column)) = (column("machine_id"), column("customer")) else {
+        return Err("The CSV header needs machine_id and customer columns".to_string());
+    };
+    let (edition_column, features_column, expires_column, seats_column) =
+        (column("edition"), column("features"), column("expires_at"), column("seats"));
+
+    let mut licenses = Vec::new();
+    for (index, line) in lines {
+        let fields = csv_fields(line);
+        let field = |column: Option<usize>| column.and_then(|column| fields.get(column)).map(String::as_str).unwrap_or("");
+        let row = |e: String| format!("Line {}: {}", index + 1, e);
+        let features = field(features_column)
+            .split(';')
+            .map(str::trim)
+            .filter(|feature| !feature.is_empty())
+            .map(str::to_string)
+            .collect();
+        let expires_at = match field(expires_column) {
+            "" => None,
+            date => Some(parse_date(date).map_err(row)?),
+        };
+        let seats = match field(seats_column) {
+            "" => None,
+            seats => Some(seats.parse().map_err(|_| row(format!("{} is not a number of seats", seats)))?),
+        };
+        let edition = match field(edition_column) {
+            "" => "full",
+            edition => edition,
+        };
+        licenses.push(
+            new_license(field(Some(machine_column)), field(Some(customer_column)), edition, features, expires_at, seats, today)
+                .map_err(row)?,
+        );
+    }
+    Ok(licenses)
+}
+
+fn batch(args: &Args) -> Result<bool, String> {
+    let key = signing_key(args)?;
+    let path = args.positional(1, "CSV file")?;
+    let csv = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let licenses = batch_licenses(&csv, Utc::now().date_naive())?;
+
+    let mut entries = Vec::new();
+    println!("id,customer,machine_id,key");
+    for license in licenses {
+        let license_key = sign_license(&license, &key)?;
+        println!(
+            "{},{},{},{}",
+            license.id,
+            csv_escape(&license.customer),
+            csv_escape(&license.machine_id),
+            license_key
+        );
+        entries.push(LedgerEntry::Issued { at: Utc::now(), license, key: license_key });
+    }
+    record(args.ledger(), &entries)?;
+    eprintln!("Issued {} license(s)", entries.len());
+    Ok(true)
+}
+
+fn inspect(args: &Args) -> Result<bool, String> {
+    let key = args.positional(1, "license key")?;
+    let Some(license) = read_license(key) else {
+        println!("Not a readable license key");
+        return Ok(false);
+    };
+    println!("{}", serde_json::to_string_pretty(&license).map_err(|e| e.to_string())?);
+    let modules: Vec<&str> = license.modules().into_iter().map(|module| module.name()).collect();
+    println!("modules: {}", if modules.is_empty() { "none".to_string() } else { modules.join(", ") });
+    let info = check_license(
+        key,
+        &verifying_key(args)?,
+        &Machine::from_machine_id(&license.machine_id),
+        Utc::now().date_naive(),
+    );
+    let signed = !matches!(info.status, LicenseStatus::InvalidSignature | LicenseStatus::Malformed);
+    println!("signature: {}", if signed { "valid" } else { "INVALID" });
+    if let Some(revoked) = revocation(&read_ledger(args.ledger())?, &license.id) {
+        println!("revoked: {}", revoked);
+    }
+    Ok(signed)
+}
+
+fn verify(args: &Args) -> Result<bool, String> {
+    let key = args.positional(1, "license key")?;
+    let machine = Machine::from_machine_id(args.require("machine-id")?);
+    let today = match args.get("date") {
+        Some(date) => parse_date(date)?,
+        None => Utc::now().date_naive(),
+    };
+    let info = check_license(key, &verifying_key(args)?, &machine, today);
+    let revoked = match &info.license {
+        Some(license) => revocation(&read_ledger(args.ledger())?, &license.id),
+        None => None,
+    };
+    match (&info.reason, &revoked) {
+        (_, Some(revoked)) => println!("REVOKED: {}", revoked),
+        (Some(reason), None) => println!("INVALID ({:?}): {}", info.status, reason),
+        (None, None) => println!("VALID"),
+    }
+    Ok(info.valid && revoked.is_none())
+}
+
+fn revoke(args: &Args) -> Result<bool, String> {
+    let id = args.positional(1, "license id")?;
+    let entries = read_ledger(args.ledger())?;
+    if !entries.iter().any(|entry| matches!(entry, LedgerEntry::Issued { license, .. } if license.id == id)) {
+        return Err(format!("License {} isn't in the ledger", id));
+    }
+    if revocation(&entries, id).is_some() {
+        return Err(format!("License {} is already revoked", id));
+    }
+    let reason = args.get("reason").map(str::to_string);
+    record(args.ledger(), &[LedgerEntry::Revoked { at: Utc::now(), id: id.to_string(), reason }])?;
+    eprintln!("Revoked {}", id);
+    Ok(true)
+}
+
+fn list(args: &Args) -> Result<bool, String> {
+    let entries = read_ledger(args.ledger())?;
+    let revoked = revoked_ids(&entries);
+    println!("id\tissued\texpires\tedition\tseats\tstatus\tcustomer\tmachine_id");
+    for entry in &entries {
+        if let LedgerEntry::Issued { license, .. } = entry {
+            println!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                license.id,
+                license.issued_at,
+                license.expires_at.map(|date| date.to_string()).unwrap_or_else(|| "never".to_string()),
+                license.edition,
+                license.seats.map(|seats| seats.to_string()).unwrap_or_else(|| "-".to_string()),
+                if revoked.contains(license.id.as_str()) { "revoked" } else { "issued" },
+                license.customer,
+                license.machine_id
+            );
+        }
+    }
+    Ok(true)
+}
+
+fn revocations(args: &Args) -> Result<bool, String> {
+    let entries = read_ledger(args.ledger())?;
+    let revoked = revoked_ids(&entries);
+    if args.has("json") {
+        println!("{}", serde_json::to_string(&revoked).map_err(|e| e.to_string())?);
+    } else {
+        for id in revoked {
+            println!("{}", id);
+        }
+    }
+    Ok(true)
+}
+
+fn revoked_ids(entries: &[LedgerEntry]) -> BTreeSet<&str> {
+    entries
+        .iter()
+        .filter_map(|entry| match entry {
+            LedgerEntry::Revoked { id, .. } => Some(id.as_str()),
+            LedgerEntry::Issued { .. } => None,
+        })
+        .collect()
+}
+
+/// When and why license `id` was revoked, if it was
+fn revocation(entries: &[LedgerEntry], id: &str) -> Option<String> {
+    entries.iter().find_map(|entry| match entry {
+        LedgerEntry::Revoked { at, id: revoked, reason } if revoked == id => Some(match reason {
+            Some(reason) => format!("{} ({})", at.format("%Y-%m-%d"), reason),
+            None => at.format("%Y-%m-%d").to_string(),
+        }),
+        _ => None,
+    })
+}
+
+/// Entries of the ledger at `path`; none if it doesn't exist yet
+fn read_ledger(path: &str) -> Result<Vec<LedgerEntry>, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read {}: {}", path, e)),
+    };
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| serde_json::from_str(line).map_err(|e| format!("{} line {}: {}", path, index + 1, e)))
+        .collect()
+}
+
+/// Append `entries` to the ledger at `path`
+fn record(path: &str, entries: &[LedgerEntry]) -> Result<(), String> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    for entry in entries {
+        let line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        writeln!(file, "{}", line).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn today() -> NaiveDate {
+        parse_date("2026-06-01").unwrap()
+    }
+
+    #[test]
+    fn test_args() {
+        let args = Args::parse(
+            ["issue", "--customer", "Karimi", "--feature", "payroll", "--json", "--feature", "accounting"].map(String::from),
+        )
+        .unwrap();
+        assert_eq!(args.positional, vec!["issue"]);
+        assert_eq!(args.get("customer"), Some("Karimi"));
+        assert_eq!(args.all("feature"), vec!["payroll", "accounting"]);
+        assert!(args.has("json"));
+        assert!(args.require("machine-id").is_err());
+        assert!(Args::parse(["issue", "--customer"].map(String::from)).is_err());
+    }
+
+    #[test]
+    fn test_csv_fields() {
+        assert_eq!(csv_fields("a, b ,c"), vec!["a", "b", "c"]);
+        assert_eq!(csv_fields(r#""Karimi, Sons","say ""hi""",,"#), vec!["Karimi, Sons", r#"say "hi""#, "", ""]);
+        assert_eq!(csv_escape("Karimi, Sons"), r#""Karimi, Sons""#);
+        assert_eq!(csv_escape("plain"), "plain");
+    }
+
+    #[test]
+    fn test_batch_licenses() {
+        let csv = "machine_id,customer,edition,features,expires_at,seats\n\
+                   M2-u1a2b3c4d,Karimi Traders,basic,payroll;accounting,2027-06-01,5\n\
+                   \n\
+                   M2-u5e6f7a8b,\"Noori, Sons\",,,,\n";
+        let licenses = batch_licenses(csv, today()).unwrap();
+        assert_eq!(licenses.len(), 2);
+        assert_eq!(licenses[0].features, vec!["payroll", "accounting"]);
+        assert_eq!((licenses[0].seats, licenses[0].expires_at), (Some(5), Some(parse_date("2027-06-01").unwrap())));
+        assert_eq!((licenses[1].customer.as_str(), licenses[1].edition.as_str()), ("Noori, Sons", "full"));
+        assert_ne!(licenses[0].id, licenses[1].id);
+
+        let bad_module = "machine_id,customer,features\nM2-u1a2b3c4d,Karimi,inventory\n";
+        assert!(batch_licenses(bad_module, today()).unwrap_err().starts_with("Line 2:"));
+        assert!(batch_licenses("customer\nKarimi\n", today()).is_err());
+        assert!(batch_licenses("machine_id,customer,expires_at\nM2-u1a2b3c4d,Karimi,2020-01-01\n", today()).is_err());
+    }
+
+    #[test]
+    fn test_ledger() {
+        let path = std::env::temp_dir().join(format!("shafaf-ledger-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let license = new_license("M2-u1a2b3c4d", "Karimi", "full", Vec::new(), None, Some(3), today()).unwrap();
+        let license_key = sign_license(&license, &key).unwrap();
+
+        assert!(read_ledger(path).unwrap().is_empty());
+        record(path, &[LedgerEntry::Issued { at: Utc::now(), license: license.clone(), key: license_key }]).unwrap();
+        record(path, &[LedgerEntry::Revoked { at: Utc::now(), id: license.id.clone(), reason: Some("refund".to_string()) }])
+            .unwrap();
+        let entries = read_ledger(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(revoked_ids(&entries), BTreeSet::from([license.id.as_str()]));
+        assert!(revocation(&entries, &license.id).unwrap().ends_with("(refund)"));
+        assert_eq!(revocation(&entries, "L20260601-ffffffff"), None);
+    }
+}
@@ -0,0 +1,322 @@
+use crate::api_tokens;
+use crate::api_types::{ApiError, ListParams, PeriodParams, Summary, WithItems};
+use crate::audit::{self, AuditActor};
+use crate::license::LicenseGate;
+use crate::permissions::{self, Session};
+use crate::repository::{CustomerInput, ProductInput, Repositories};
+use crate::{
+    Account, Customer, PaginatedResponse, Product, Purchase, PurchaseInput, PurchaseItem, Sale, SaleInput, SaleItem,
+};
+use axum::{
+    async_trait,
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection},
+    extract::{FromRequestParts, Path, Query, State},
+    http::{header, request::Parts, StatusCode},
+    routing::get,
+    Json, Router,
+};
+use chrono::{NaiveDate, Utc};
+use std::future::Future;
+use tauri::{AppHandle, Manager};
+
+/// Routes of the REST API, mounted at `/api/v1`
+pub fn router<S: Clone + Send + Sync + 'static>(app: AppHandle) -> Router<S> {
+    Router::new()
+        .route("/me", get(me))
+        .route("/customers", get(list_customers).post(create_customer))
+        .route("/customers/:id", get(get_customer).put(update_customer))
+        .route("/products", get(list_products).post(create_product))
+        .route("/products/:id", get(get_product).put(update_product))
+        .route("/sales", get(list_sales).post(create_sale))
+        .route("/sales/:id", get(get_sale))
+        .route("/purchases", get(list_purchases).post(create_purchase))
+        .route("/purchases/:id", get(get_purchase))
+        .route("/accounts", get(list_accounts))
+        .route("/accounts/:id", get(get_account))
+        .route("/reports/summary", get(report_summary))
+        .fallback(|| async { ApiError::not_found("No such endpoint") })
+        .with_state(app)
+}
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+/// Repositories of the open backend, as the Tauri commands use them
+fn repositories(app: &AppHandle) -> Result<Box<dyn Repositories>, ApiError> {
+    crate::repositories(&app.state(), &app.state())
+        .map_err(ApiError::database_unavailable)
+}
+
+/// The user a request acts as, from its `Authorization: Bearer <token>` API token
+pub struct Caller(Session);
+
+#[async_trait]
+impl FromRequestParts<AppHandle> for Caller {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, app: &AppHandle) -> Result<Self, ApiError> {
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(api_tokens::bearer_token)
+            .ok_or_else(|| ApiError::unauthenticated("Send an API token as `Authorization: Bearer <token>`"))?;
+        let repo = repositories(app)?;
+        let session = api_tokens::authenticate(repo.as_ref(), token, Utc::now())
+            .await?
+            .ok_or_else(|| ApiError::unauthenticated("The API token is unknown, expired or revoked"))?;
+        Ok(Caller(session))
+    }
+}
+
+impl Caller {
+    /// Check the caller may run the Tauri command `command`, under their role and the license
+    fn allow(&self, app: &AppHandle, command: &str) -> Result<(), ApiError> {
+        permissions::authorize(command, Some(&self.0))?;
+        permissions::check_account_setup(command, &self.0)?;
+        app.state::<LicenseGate>().state(Utc::now().date_naive()).authorize(command)?;
+        Ok(())
+    }
+
+    /// Make a change as the Tauri command `command` would, attributed to the caller in the audit log
+    async fn change<T>(
+        &self,
+        app: &AppHandle,
+        command: &str,
+        change: impl Future<Output = Result<T, ApiError>>,
+    ) -> Result<T, ApiError> {
+        self.allow(app, command)?;
+        let actor = AuditActor {
+            user_id: Some(self.0.user_id),
+            username: Some(self.0.username.clone()),
+            command: command.to_string(),
+        };
+        audit::attributed(actor, change).await
+    }
+}
+
+async fn me(caller: Caller) -> Json<Session> {
+    Json(caller.0)
+}
+
+async fn list_customers(
+    State(app): State<AppHandle>,
+    caller: Caller,
+    params: Result<Query<ListParams>, QueryRejection>,
+) -> ApiResult<PaginatedResponse<Customer>> {
+    caller.allow(&app, "get_customers")?;
+    let Query(params) = params?;
+    Ok(Json(repositories(&app)?.list_customers(&params.query()?).await?))
+}
+
+async fn get_customer(
+    State(app): State<AppHandle>,
+    caller: Caller,
+    id: Result<Path<i64>, PathRejection>,
+) -> ApiResult<Customer> {
+    caller.allow(&app, "get_customers")?;
+    let Path(id) = id?;
+    let customer = repositories(&app)?.get_customer(id).await?;
+    customer.map(Json).ok_or_else(|| ApiError::not_found("Customer not found"))
+}
+
+async fn create_customer(
+    State(app): State<AppHandle>,
+    caller: Caller,
+    input: Result<Json<CustomerInput>, JsonRejection>,
+) -> Result<(StatusCode, Json<Customer>), ApiError> {
+    let Json(input) = input?;
+    let repo = repositories(&app)?;
+    let customer = caller
+        .change(&app, "create_customer", async { Ok(repo.create_customer(&input).await?) })
+        .await?;
+    Ok((StatusCode::CREATED, Json(customer)))
+}
+
+async fn update_customer(
+    State(app): State<AppHandle>,
+    caller: Caller,
+    id: Result<Path<i64>, PathRejection>,
+    input: Result<Json<CustomerInput>, JsonRejection>,
+) -> ApiResult<Customer> {
+    let (Path(id), Json(input)) = (id?, input?);
+    let repo = repositories(&app)?;
+    caller
+        .change(&app, "update_customer", async {
+            if repo.get_customer(id).await?.is_none() {
+                return Err(ApiError::not_found("Customer not found"));
+            }
+            Ok(Json(repo.update_customer(id, &input).await?))
+        })
+        .await
+}
+
+async fn list_products(
+    State(app): State<AppHandle>,
+    caller: Caller,
+    params: Result<Query<ListParams>, QueryRejection>,
+) -> ApiResult<PaginatedResponse<Product>> {
+    caller.allow(&app, "get_products")?;
+    let Query(params) = params?;
+    Ok(Json(repositories(&app)?.list_products(&params.query()?).await?))
+}
+
+async fn get_product(
+    State(app): State<AppHandle>,
+    caller: Caller,
+    id: Result<Path<i64>, PathRejection>,
+) -> ApiResult<Product> {
+    caller.allow(&app, "get_products")?;
+    let Path(id) = id?;
+    let product = repositories(&app)?.get_product(id).await?;
+    product.map(Json).ok_or_else(|| ApiError::not_found("Product not found"))
+}
+
+async fn create_product(
+    State(app): State<AppHandle>,
+    caller: Caller,
+    input: Result<Json<ProductInput>, JsonRejection>,
+) -> Result<(StatusCode, Json<Product>), ApiError> {
+    let Json(input) = input?;
+    let repo = repositories(&app)?;
+    let product = caller
+        .change(&app, "create_product", async { Ok(repo.create_product(&input).await?) })
+        .await?;
+    Ok((StatusCode::CREATED, Json(product)))
+}
+
+async fn update_product(
+    State(app): State<AppHandle>,
+    caller: Caller,
+    id: Result<Path<i64>, PathRejection>,
+    input: Result<Json<ProductInput>, JsonRejection>,
+) -> ApiResult<Product> {
+    let (Path(id), Json(input)) = (id?, input?);
+    let repo = repositories(&app)?;
+    caller
+        .change(&app, "update_product", async {
+            if repo.get_product(id).await?.is_none() {
+                return Err(ApiError::not_found("Product not found"));
+            }
+            Ok(Json(repo.update_product(id, &input).await?))
+        })
+        .await
+}
+
+async fn list_sales(
+    State(app): State<AppHandle>,
+    caller: Caller,
+    params: Result<Query<ListParams>, QueryRejection>,
+) -> ApiResult<PaginatedResponse<Sale>> {
+    caller.allow(&app, "get_sales")?;
+    let Query(params) = params?;
+    Ok(Json(repositories(&app)?.list_sales(&params.query()?).await?))
+}
+
+async fn get_sale(
+    State(app): State<AppHandle>,
+    caller: Caller,
+    id: Result<Path<i64>, PathRejection>,
+) -> ApiResult<WithItems<Sale, SaleItem>> {
+    caller.allow(&app, "get_sale")?;
+    let Path(id) = id?;
+    let (record, items) = repositories(&app)?
+        .get_sale(id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Sale not found"))?;
+    Ok(Json(WithItems { record, items }))
+}
+
+async fn create_sale(
+    State(app): State<AppHandle>,
+    caller: Caller,
+    input: Result<Json<SaleInput>, JsonRejection>,
+) -> Result<(StatusCode, Json<Sale>), ApiError> {
+    let Json(input) = input?;
+    if input.items.is_empty() {
+        return Err(ApiError::invalid("A sale needs at least one item"));
+    }
+    let repo = repositories(&app)?;
+    let sale = caller
+        .change(&app, "create_sale", async {
+            repo.create_sale(&input).await.map_err(|e| ApiError::invalid(e.to_string()))
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(sale)))
+}
+
+async fn list_purchases(
+    State(app): State<AppHandle>,
+    caller: Caller,
+    params: Result<Query<ListParams>, QueryRejection>,
+) -> ApiResult<PaginatedResponse<Purchase>> {
+    caller.allow(&app, "get_purchases")?;
+    let Query(params) = params?;
+    Ok(Json(repositories(&app)?.list_purchases(&params.query()?).await?))
+}
+
+async fn get_purchase(
+    State(app): State<AppHandle>,
+    caller: Caller,
+    id: Result<Path<i64>, PathRejection>,
+) -> ApiResult<WithItems<Purchase, PurchaseItem>> {
+    caller.allow(&app, "get_purchase")?;
+    let Path(id) = id?;
+    let (record, items) = repositories(&app)?
+        .get_purchase(id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Purchase not found"))?;
+    Ok(Json(WithItems { record, items }))
+}
+
+async fn create_purchase(
+    State(app): State<AppHandle>,
+    caller: Caller,
+    input: Result<Json<PurchaseInput>, JsonRejection>,
+) -> Result<(StatusCode, Json<Purchase>), ApiError> {
+    let Json(input) = input?;
+    if input.items.is_empty() {
+        return Err(ApiError::invalid("A purchase needs at least one item"));
+    }
+    let repo = repositories(&app)?;
+    let purchase = caller
+        .change(&app, "create_purchase", async {
+            repo.create_purchase(&input).await.map_err(|e| ApiError::invalid(e.to_string()))
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(purchase)))
+}
+
+async fn list_accounts(State(app): State<AppHandle>, caller: Caller) -> ApiResult<Vec<Account>> {
+    caller.allow(&app, "get_accounts")?;
+    Ok(Json(repositories(&app)?.list_accounts().await?))
+}
+
+async fn get_account(
+    State(app): State<AppHandle>,
+    caller: Caller,
+    id: Result<Path<i64>, PathRejection>,
+) -> ApiResult<Account> {
+    caller.allow(&app, "get_account")?;
+    let Path(id) = id?;
+    let account = repositories(&app)?.get_account(id).await?;
+    account.map(Json).ok_or_else(|| ApiError::not_found("Account not found"))
+}
+
+async fn report_summary(
+    State(app): State<AppHandle>,
+    caller: Caller,
+    params: Result<Query<PeriodParams>, QueryRejection>,
+) -> ApiResult<Summary> {
+    caller.allow(&app, "get_sales")?;
+    caller.allow(&app, "get_purchases")?;
+    let Query(PeriodParams { from, to }) = params?;
+    for date in [&from, &to].into_iter().flatten() {
+        if NaiveDate::parse_from_str(date, "%Y-%m-%d").is_err() {
+            return Err(ApiError::invalid(format!("{} is not a YYYY-MM-DD date", date)));
+        }
+    }
+    let repo = repositories(&app)?;
+    let sales = repo.sales_totals(from.as_deref(), to.as_deref()).await?;
+    let purchases = repo.purchases_totals(from.as_deref(), to.as_deref()).await?;
+    Ok(Json(Summary { from, to, sales, purchases }))
+}
@@ -0,0 +1,184 @@
+use crate::permissions::{self, Session};
+use crate::repository::{ApiToken, ApiTokenInput, Repositories};
+use crate::session::{random_hex, token_key};
+use crate::two_factor;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Start of every API token, so a leaked one is easy to recognise
+pub const TOKEN_PREFIX: &str = "shf_";
+/// Characters after `TOKEN_PREFIX` that are stored in the clear to tell tokens apart
+const SHOWN_CHARS: usize = 8;
+/// Longest lifetime a token can be given, in days
+pub const MAX_LIFETIME_DAYS: i64 = 3650;
+
+/// A newly created token; the token itself is only ever shown here
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssuedApiToken {
+    pub token: String,
+    #[serde(flatten)]
+    pub info: ApiToken,
+}
+
+/// Create a token for `user_id`, valid for `expires_in_days` or until revoked
+pub async fn issue(
+    repo: &dyn Repositories,
+    user_id: i64,
+    name: &str,
+    expires_in_days: Option<i64>,
+    now: DateTime<Utc>,
+) -> Result<IssuedApiToken> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > 100 {
+        return Err(anyhow!("Token name must be 1 to 100 characters"));
+    }
+    if expires_in_days.is_some_and(|days| !(1..=MAX_LIFETIME_DAYS).contains(&days)) {
+        return Err(anyhow!("Tokens can be valid for 1 to {} days", MAX_LIFETIME_DAYS));
+    }
+
+    let token = format!("{}{}", TOKEN_PREFIX, random_hex(32));
+    let input = ApiTokenInput {
+        user_id,
+        name: name.to_string(),
+        prefix: token[..TOKEN_PREFIX.len() + SHOWN_CHARS].to_string(),
+        token_hash: token_key(&token),
+        expires_at: expires_in_days.map(|days| now + TimeDelta::days(days)),
+    };
+    let info = repo.create_api_token(&input).await?;
+    Ok(IssuedApiToken { token, info })
+}
+
+/// Who a request presenting `token` acts as: the token's owner with their role's current
+/// capabilities, and whether they still have to change their password or set up two-factor
+/// authentication. None for unknown, expired or revoked tokens and for disabled users.
+pub async fn authenticate(repo: &dyn Repositories, token: &str, now: DateTime<Utc>) -> Result<Option<Session>> {
+    if !token.starts_with(TOKEN_PREFIX) {
+        return Ok(None);
+    }
+    let Some(found) = repo.use_api_token(&token_key(token), now).await? else {
+        return Ok(None);
+    };
+    let Some(user) = repo.get_user(found.user_id).await?.filter(|user| user.is_active != 0) else {
+        return Ok(None);
+    };
+    let capabilities = permissions::role_capabilities(repo, &user.role).await?;
+    let must_change_password = repo.find_login(&user.username).await?.is_some_and(|login| login.must_change_password);
+    let must_enroll_two_factor =
+        !repo.get_two_factor(user.id).await?.enabled && two_factor::two_factor_policy(repo).await?.requires(&user.role);
+    Ok(Some(Session {
+        user_id: user.id,
+        username: user.username,
+        role: user.role,
+        capabilities,
+        must_change_password,
+        must_enroll_two_factor,
+    }))
+}
+
+/// The token of an `Authorization: Bearer <token>` header value
+pub fn bearer_token(header: &str) -> Option<&str> {
+    let (scheme, token) = header.trim().split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then_some(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+    use crate::repository::{select, UserInput};
+    use std::path::PathBuf;
+
+    fn repo() -> Box<dyn Repositories> {
+        let db = Database::new(PathBuf::from(":memory:"));
+        db.open().unwrap();
+        crate::migrations::run_pending(&db).unwrap();
+        select(None, Some(db), None).unwrap()
+    }
+
+    async fn user(repo: &dyn Repositories, role: &str) -> i64 {
+        let input = UserInput {
+            username: "clerk".to_string(),
+            email: "clerk@example.com".to_string(),
+            password_hash: "$2b$12$hash".to_string(),
+            full_name: None,
+            phone: None,
+            role: role.to_string(),
+            must_change_password: false,
+        };
+        repo.create_user(&input).await.unwrap().id
+    }
+
+    #[tokio::test]
+    async fn test_issue_and_authenticate() {
+        let repo = repo();
+        let user_id = user(repo.as_ref(), "cashier").await;
+        let now = Utc::now();
+        let issued = issue(repo.as_ref(), user_id, " Stock sync ", Some(30), now).await.unwrap();
+        assert!(issued.token.starts_with(TOKEN_PREFIX));
+        assert!(issued.token.starts_with(&issued.info.prefix));
+        assert_eq!(issued.info.name, "Stock sync");
+
+        let session = authenticate(repo.as_ref(), &issued.token, now).await.unwrap().unwrap();
+        assert_eq!((session.user_id, session.role.as_str()), (user_id, "cashier"));
+        assert!(session.capabilities.contains(&permissions::Capability::SalesWrite));
+
+        let last = if issued.token.ends_with('0') { '1' } else { '0' };
+        let tampered = format!("{}{}", &issued.token[..issued.token.len() - 1], last);
+        assert!(authenticate(repo.as_ref(), &tampered, now).await.unwrap().is_none());
+        assert!(authenticate(repo.as_ref(), "not-a-token", now).await.unwrap().is_none());
+        let later = now + TimeDelta::days(31);
+        assert!(authenticate(repo.as_ref(), &issued.token, later).await.unwrap().is_none());
+
+        // Disabled users' tokens stop working
+        repo.set_user_active(user_id, false).await.unwrap();
+        assert!(authenticate(repo.as_ref(), &issued.token, now).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_issue_validates() {
+        let repo = repo();
+        let user_id = user(repo.as_ref(), "cashier").await;
+        let now = Utc::now();
+        assert!(issue(repo.as_ref(), user_id, "  ", None, now).await.is_err());
+        assert!(issue(repo.as_ref(), user_id, "sync", Some(0), now).await.is_err());
+        assert!(issue(repo.as_ref(), user_id, "sync", Some(MAX_LIFETIME_DAYS + 1), now).await.is_err());
+        assert!(issue(repo.as_ref(), user_id, "sync", None, now).await.unwrap().info.expires_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_requires_account_setup() {
+        let repo = repo();
+        let user_id = user(repo.as_ref(), "cashier").await;
+        let now = Utc::now();
+        let issued = issue(repo.as_ref(), user_id, "sync", None, now).await.unwrap();
+
+        repo.set_password(user_id, "$2b$12$reset", true).await.unwrap();
+        let session = authenticate(repo.as_ref(), &issued.token, now).await.unwrap().unwrap();
+        assert!(session.must_change_password);
+        assert!(matches!(
+            permissions::check_account_setup("get_sales", &session),
+            Err(permissions::AccessError::PasswordChangeRequired { .. })
+        ));
+
+        repo.set_password(user_id, "$2b$12$chosen", false).await.unwrap();
+        let policy = two_factor::TwoFactorPolicy { required_roles: ["cashier".to_string()].into() };
+        two_factor::save_two_factor_policy(repo.as_ref(), &policy).await.unwrap();
+        let session = authenticate(repo.as_ref(), &issued.token, now).await.unwrap().unwrap();
+        assert!(!session.must_change_password && session.must_enroll_two_factor);
+        assert!(matches!(
+            permissions::check_account_setup("get_sales", &session),
+            Err(permissions::AccessError::TwoFactorSetupRequired { .. })
+        ));
+    }
+
+    #[test]
+    fn test_bearer_token() {
+        assert_eq!(bearer_token("Bearer shf_abc"), Some("shf_abc"));
+        assert_eq!(bearer_token("bearer  shf_abc "), Some("shf_abc"));
+        assert_eq!(bearer_token("Basic dXNlcjpwYXNz"), None);
+        assert_eq!(bearer_token("Bearer "), None);
+        assert_eq!(bearer_token("shf_abc"), None);
+    }
+}
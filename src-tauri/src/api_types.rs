@@ -0,0 +1,255 @@
+use crate::permissions::AccessError;
+use crate::repository::{ListQuery, PeriodTotals};
+use axum::{
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Where the REST API is mounted
+pub const API_PREFIX: &str = "/api/v1";
+/// Largest page a list request can ask for
+pub const MAX_PER_PAGE: i64 = 200;
+
+/// What went wrong, for clients to act on; each code always comes with the same HTTP status
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// 400: malformed JSON, path or query, or values that fail validation
+    InvalidRequest,
+    /// 401: no credentials, or unknown, expired or revoked ones
+    Unauthenticated,
+    /// 403: the user's role doesn't allow it
+    Forbidden,
+    /// 403: the license is missing or expired, or lacks the module
+    LicenseRestricted,
+    /// 404: no such record or endpoint
+    NotFound,
+    /// 500: anything unexpected
+    Internal,
+    /// 503: no database is open
+    DatabaseUnavailable,
+}
+
+impl ErrorCode {
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::InvalidRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthenticated => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden | ErrorCode::LicenseRestricted => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::DatabaseUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+/// An error response of the HTTP server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    /// Human-readable explanation
+    pub message: String,
+    /// Extra facts about the error, depending on `code` (e.g. the missing capability)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+}
+
+/// Body of every error response: `{ "error": { "code", "message", "details" } }`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ErrorBody {
+    pub error: ApiError,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        ApiError { code, message: message.into(), details: None }
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    pub fn invalid(message: impl Into<String>) -> Self {
+        ApiError::new(ErrorCode::InvalidRequest, message)
+    }
+
+    pub fn unauthenticated(message: impl Into<String>) -> Self {
+        ApiError::new(ErrorCode::Unauthenticated, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        ApiError::new(ErrorCode::NotFound, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        ApiError::new(ErrorCode::Internal, message)
+    }
+
+    pub fn database_unavailable(message: impl Into<String>) -> Self {
+        ApiError::new(ErrorCode::DatabaseUnavailable, message)
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.code.status()
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status(), Json(ErrorBody { error: self })).into_response()
+    }
+}
+
+impl From<AccessError> for ApiError {
+    fn from(error: AccessError) -> Self {
+        match error {
+            AccessError::Unauthenticated { message, .. } => ApiError::unauthenticated(message),
+            AccessError::LicenseRestricted { command, mode, message } => {
+                ApiError::new(ErrorCode::LicenseRestricted, message).with_details(json!({ "command": command, "mode": mode }))
+            }
+            AccessError::Forbidden { command, capability, message } => {
+                ApiError::new(ErrorCode::Forbidden, message).with_details(json!({ "command": command, "capability": capability }))
+            }
+            AccessError::ReauthenticationRequired { command, message }
+            | AccessError::PasswordChangeRequired { command, message }
+            | AccessError::TwoFactorSetupRequired { command, message } => {
+                ApiError::new(ErrorCode::Forbidden, message).with_details(json!({ "command": command }))
+            }
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(error: anyhow::Error) -> Self {
+        ApiError::internal(error.to_string())
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        ApiError::invalid(rejection.body_text()).with_details(json!({ "part": "body" }))
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        ApiError::invalid(rejection.body_text()).with_details(json!({ "part": "path" }))
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError::invalid(rejection.body_text()).with_details(json!({ "part": "query" }))
+    }
+}
+
+/// Puter credentials `ai.html` hands to the app
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PuterCredentials {
+    pub app_id: String,
+    pub auth_token: String,
+}
+
+/// `?page=&per_page=&search=&sort_by=&sort_order=` of list endpoints
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct ListParams {
+    /// Page number, from 1 (default 1)
+    pub page: Option<i64>,
+    /// Records per page, 1 to 200 (default 50)
+    pub per_page: Option<i64>,
+    pub search: Option<String>,
+    pub sort_by: Option<String>,
+    /// `asc` or `desc`
+    pub sort_order: Option<String>,
+}
+
+impl ListParams {
+    pub fn query(self) -> Result<ListQuery, ApiError> {
+        let page = self.page.unwrap_or(1);
+        let per_page = self.per_page.unwrap_or(50);
+        if page < 1 || !(1..=MAX_PER_PAGE).contains(&per_page) {
+            return Err(ApiError::invalid(format!("page must be 1 or more and per_page 1 to {}", MAX_PER_PAGE))
+                .with_details(json!({ "page": page, "per_page": per_page, "max_per_page": MAX_PER_PAGE })));
+        }
+        Ok(ListQuery { page, per_page, search: self.search, sort_by: self.sort_by, sort_order: self.sort_order })
+    }
+}
+
+/// A sale or purchase with its items
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct WithItems<T, I> {
+    #[serde(flatten)]
+    pub record: T,
+    pub items: Vec<I>,
+}
+
+/// `?from=YYYY-MM-DD&to=YYYY-MM-DD`, both optional and inclusive
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PeriodParams {
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// Body of `POST /api/rpc` in LAN mode: a Tauri command and its arguments
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct RpcCall {
+    /// Name of the command, e.g. `get_products`
+    pub command: String,
+    /// Its arguments, named as the frontend passes them to `invoke` (camelCase)
+    #[serde(default)]
+    pub args: serde_json::Map<String, Value>,
+}
+
+/// Sales and purchase totals per currency within a period
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Summary {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub sales: Vec<PeriodTotals>,
+    pub purchases: Vec<PeriodTotals>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::permissions::Capability;
+
+    async fn body(error: ApiError) -> (StatusCode, Value) {
+        let response = error.into_response();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_error_response() {
+        let (status, json) = body(ApiError::invalid(r#"bad "quoted" value"#)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(json, json!({ "error": { "code": "invalid_request", "message": "bad \"quoted\" value" } }));
+
+        let forbidden = AccessError::Forbidden {
+            command: "create_sale".to_string(),
+            capability: Some(Capability::SalesWrite),
+            message: "Not allowed".to_string(),
+        };
+        let (status, json) = body(forbidden.into()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(json["error"]["code"], "forbidden");
+        assert_eq!(json["error"]["details"], json!({ "command": "create_sale", "capability": "sales.write" }));
+    }
+
+    #[test]
+    fn test_list_params() {
+        let query = ListParams::default().query().unwrap();
+        assert_eq!((query.page, query.per_page), (1, 50));
+        let error = ListParams { per_page: Some(MAX_PER_PAGE + 1), ..ListParams::default() }.query().unwrap_err();
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+        assert!(ListParams { page: Some(0), ..ListParams::default() }.query().is_err());
+    }
+}
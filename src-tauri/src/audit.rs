@@ -0,0 +1,457 @@
+use crate::db::Database;
+use crate::repository::{next_id, Repositories};
+use crate::surrealdb::DatabaseConnection;
+use crate::sync::SYNC_TABLES;
+use anyhow::Result;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::Value;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use std::time::Duration;
+
+/// `prev_hash` of the first entry
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+/// Columns never copied into the log: credentials, and login bookkeeping that changes on every attempt
+const HIDDEN_COLUMNS: &[&str] = &["password_hash", "totp_secret", "totp_last_step", "recovery_codes", "failed_logins", "locked_until"];
+/// Columns whose change alone doesn't make an entry
+const IGNORED_COLUMNS: &[&str] = &["updated_at"];
+/// Entries hashed or checked per round trip
+const BATCH: i64 = 500;
+/// How often new entries are sealed into the chain
+pub const SEAL_INTERVAL: Duration = Duration::from_secs(5);
+
+tokio::task_local! {
+    /// Author of the writes made by the running command
+    static ACTOR: AuditActor;
+}
+
+/// Tables whose creates, updates and deletes are logged
+fn audited_tables() -> impl Iterator<Item = &'static str> {
+    SYNC_TABLES.iter().copied().chain(["app_settings"])
+}
+
+/// Who a change is attributed to. Not `Deserialize`: Tauri commands take it as an argument
+/// filled from the calling window's session, never from the caller's arguments.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AuditActor {
+    pub user_id: Option<i64>,
+    pub username: Option<String>,
+    pub command: String,
+}
+
+/// One logged change
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub created_at: String,
+    pub user_id: Option<i64>,
+    pub username: Option<String>,
+    /// Tauri command that made the change
+    pub command: Option<String>,
+    /// Table of the changed record
+    pub entity: String,
+    pub entity_id: Option<String>,
+    /// `create`, `update` or `delete`
+    pub action: String,
+    /// The record before the change (None when created)
+    pub before: Option<serde_json::Value>,
+    /// The record after the change (None when deleted)
+    pub after: Option<serde_json::Value>,
+    pub prev_hash: Option<String>,
+    /// None until the entry is sealed into the chain
+    pub hash: Option<String>,
+}
+
+/// Filters of `get_audit_log`; dates are `YYYY-MM-DD` and inclusive
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditFilter {
+    pub entity: Option<String>,
+    pub entity_id: Option<String>,
+    pub user_id: Option<i64>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// Result of `verify_audit_chain`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditVerification {
+    pub valid: bool,
+    /// Entries checked
+    pub entries: usize,
+    /// First entry that was changed, removed or left out of the chain
+    pub broken_at: Option<i64>,
+    pub message: String,
+}
+
+/// Run `change` with every write it makes attributed to `actor`. The author travels with the
+/// task, so changes running at the same time each keep their own.
+pub async fn attributed<F: Future>(actor: AuditActor, change: F) -> F::Output {
+    ACTOR.scope(actor, change).await
+}
+
+/// `attributed` for a change that runs synchronously
+pub fn attributed_sync<T>(actor: AuditActor, change: impl FnOnce() -> T) -> T {
+    ACTOR.sync_scope(actor, change)
+}
+
+/// Author of the write being made right now, if it is attributed
+pub fn current_actor() -> Option<AuditActor> {
+    ACTOR.try_with(AuditActor::clone).ok()
+}
+
+/// Hash of `entry` chained to the entry before it
+pub fn entry_hash(entry: &AuditEntry, prev_hash: &str) -> String {
+    let content = json!([
+        prev_hash,
+        entry.id,
+        entry.created_at,
+        entry.user_id,
+        entry.username,
+        entry.command,
+        entry.entity,
+        entry.entity_id,
+        entry.action,
+        entry.before,
+        entry.after,
+    ]);
+    hex::encode(Sha256::digest(content.to_string().as_bytes()))
+}
+
+/// Hash every entry written since the last seal. Returns how many were sealed.
+pub async fn seal(repo: &dyn Repositories) -> Result<usize> {
+    let (mut after_id, mut prev_hash) = match repo.last_sealed_audit_entry().await? {
+        Some(entry) => (entry.id, entry.hash.unwrap_or_default()),
+        None => (0, GENESIS_HASH.to_string()),
+    };
+    let mut sealed = 0;
+    loop {
+        let entries = repo.audit_entries(after_id, BATCH).await?;
+        if entries.is_empty() {
+            return Ok(sealed);
+        }
+        let mut hashes = Vec::with_capacity(entries.len());
+        for entry in &entries {
+            let hash = entry_hash(entry, &prev_hash);
+            hashes.push((entry.id, std::mem::replace(&mut prev_hash, hash.clone()), hash));
+            after_id = entry.id;
+        }
+        if !repo.seal_audit_entries(&hashes).await? {
+            // The store is in the middle of a transaction; the next seal picks these up
+            return Ok(sealed);
+        }
+        sealed += hashes.len();
+    }
+}
+
+/// Seal pending entries, then recompute the whole chain
+pub async fn verify(repo: &dyn Repositories) -> Result<AuditVerification> {
+    seal(repo).await?;
+    let mut prev_hash = GENESIS_HASH.to_string();
+    let mut after_id = 0;
+    let mut entries = 0;
+    // Entries not sealed yet are fine at the end of the log, not before sealed ones
+    let mut unsealed: Option<i64> = None;
+    loop {
+        let batch = repo.audit_entries(after_id, BATCH).await?;
+        if batch.is_empty() {
+            break;
+        }
+        for entry in batch {
+            after_id = entry.id;
+            let Some(hash) = &entry.hash else {
+                unsealed.get_or_insert(entry.id);
+                continue;
+            };
+            let problem = if let Some(id) = unsealed {
+                Some((id, format!("Entry {} was taken out of the chain", id)))
+            } else if entry.prev_hash.as_deref() != Some(prev_hash.as_str()) {
+                Some((entry.id, format!("Entry {} doesn't follow the entry before it; entries were removed or reordered", entry.id)))
+            } else if *hash != entry_hash(&entry, &prev_hash) {
+                Some((entry.id, format!("Entry {} was changed after it was recorded", entry.id)))
+            } else {
+                None
+            };
+            if let Some((id, message)) = problem {
+                return Ok(AuditVerification { valid: false, entries, broken_at: Some(id), message });
+            }
+            prev_hash = hash.clone();
+            entries += 1;
+        }
+    }
+    Ok(AuditVerification {
+        valid: true,
+        entries,
+        broken_at: None,
+        message: format!("All {} audit log entries are intact", entries),
+    })
+}
+
+// ---------------------------------------------------------------------------
+// SQLite: TEMP triggers, so they are installed per connection and can call the
+// connection's own `audit_actor()` function
+// ---------------------------------------------------------------------------
+
+/// `audit_actor('user_id' | 'username' | 'command')`, read from the author of the statement
+/// while it runs
+fn register_actor_function(conn: &Connection) -> rusqlite::Result<()> {
+    conn.create_scalar_function("audit_actor", 1, FunctionFlags::SQLITE_UTF8, |ctx| {
+        let field: String = ctx.get(0)?;
+        let Some(actor) = current_actor() else {
+            return Ok(Value::Null);
+        };
+        Ok(match field.as_str() {
+            "user_id" => actor.user_id.map_or(Value::Null, Value::Integer),
+            "username" => actor.username.map_or(Value::Null, Value::Text),
+            _ => Value::Text(actor.command),
+        })
+    })
+}
+
+/// Columns of `table` and whether each is part of the primary key; empty if the table doesn't exist
+fn table_columns(conn: &Connection, table: &str) -> Result<Vec<(String, bool)>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA main.table_info({})", table))?;
+    let columns = stmt.query_map([], |row| Ok((row.get::<_, String>(1)?, row.get::<_, i64>(5)? > 0)))?;
+    Ok(columns.collect::<rusqlite::Result<_>>()?)
+}
+
+fn sqlite_triggers(table: &str, columns: &[(String, bool)]) -> String {
+    let key = columns.iter().find(|(_, pk)| *pk).map_or("rowid", |(name, _)| name.as_str());
+    let visible: Vec<&str> = columns
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .filter(|name| !HIDDEN_COLUMNS.contains(name))
+        .collect();
+    let record = |row: &str| {
+        let pairs: Vec<String> = visible.iter().map(|c| format!("'{c}', {row}.\"{c}\"")).collect();
+        format!("json_object({})", pairs.join(", "))
+    };
+    let changed: Vec<String> = visible
+        .iter()
+        .filter(|c| !IGNORED_COLUMNS.contains(c))
+        .map(|c| format!("NEW.\"{c}\" IS NOT OLD.\"{c}\""))
+        .collect();
+    let log = |event: &str, condition: &str, action: &str, id: &str, before: &str, after: &str| {
+        let trigger = event.to_lowercase();
+        format!(
+            "DROP TRIGGER IF EXISTS temp.audit_{table}_{trigger};
+            CREATE TEMP TRIGGER audit_{table}_{trigger} AFTER {event} ON main.{table} {condition} BEGIN
+                INSERT INTO audit_log (user_id, username, command, entity, entity_id, action, old_data, new_data)
+                VALUES (audit_actor('user_id'), audit_actor('username'), audit_actor('command'), '{table}', {id}, '{action}', {before}, {after});
+            END;"
+        )
+    };
+    [
+        log("INSERT", "", "create", &format!("NEW.{key}"), "NULL", &record("NEW")),
+        log("UPDATE", &format!("WHEN {}", changed.join(" OR ")), "update", &format!("NEW.{key}"), &record("OLD"), &record("NEW")),
+        log("DELETE", "", "delete", &format!("OLD.{key}"), &record("OLD"), "NULL"),
+    ]
+    .join("\n")
+}
+
+/// Install the change triggers on the open connection; run again after migrations change the tables
+pub fn install_audit_triggers(db: &Database) -> Result<()> {
+    db.with_connection(|conn| {
+        register_actor_function(conn)?;
+        for table in audited_tables() {
+            let columns = table_columns(conn, table)?;
+            if !columns.is_empty() {
+                conn.execute_batch(&sqlite_triggers(table, &columns))?;
+            }
+        }
+        Ok(())
+    })
+}
+
+// ---------------------------------------------------------------------------
+// SurrealDB: table events, attributed through the `$audit_actor` parameter that
+// `DatabaseConnection::query` binds to every query
+// ---------------------------------------------------------------------------
+
+/// SurrealQL for `record` without the hidden fields, with record links and datetimes
+/// written the way the SQLite rows have them
+fn surreal_plain_record(record: &str) -> String {
+    format!(
+        "object::from_entries(object::entries({record}).filter(|$e| $e[0] NOTINSIDE $hidden).map(|$e| [$e[0],
+            IF type::is::record($e[1]) THEN record::id($e[1])
+            ELSE IF type::is::datetime($e[1]) THEN time::format($e[1], '%Y-%m-%d %H:%M:%S')
+            ELSE $e[1] END]))"
+    )
+}
+
+fn surreal_event_query(table: &str) -> String {
+    let quoted = |columns: &[&str]| columns.iter().map(|c| format!("'{}'", c)).collect::<Vec<_>>().join(", ");
+    format!(
+        "DEFINE EVENT OVERWRITE audit ON TABLE {table} THEN {{
+            LET $hidden = [{hidden}];
+            LET $old = IF $event != 'CREATE' THEN {before} END;
+            LET $new = IF $event != 'DELETE' THEN {after} END;
+            LET $ignored = [{ignored}];
+            IF $event != 'UPDATE'
+                OR object::entries($old).filter(|$e| $e[0] NOTINSIDE $ignored) != object::entries($new).filter(|$e| $e[0] NOTINSIDE $ignored) {{
+                CREATE type::thing('audit_log', {next}) SET
+                    user_id = $audit_actor.user_id ?? NONE,
+                    username = $audit_actor.username ?? NONE,
+                    command = $audit_actor.command ?? NONE,
+                    entity = '{table}',
+                    entity_id = <string> record::id($value.id),
+                    action = string::lowercase($event),
+                    old_data = $old,
+                    new_data = $new;
+            }};
+        }};",
+        table = table,
+        hidden = quoted(HIDDEN_COLUMNS),
+        before = surreal_plain_record("$before"),
+        after = surreal_plain_record("$after"),
+        ignored = quoted(IGNORED_COLUMNS),
+        next = next_id("'audit_log'"),
+    )
+}
+
+/// Install the audit event on every audited table
+pub async fn install_audit_events(conn: &DatabaseConnection) -> Result<()> {
+    for table in audited_tables() {
+        conn.query(&surreal_event_query(table), json!({})).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::select;
+    use std::path::PathBuf;
+
+    fn sqlite() -> Database {
+        let db = Database::new(PathBuf::from(":memory:"));
+        db.open().unwrap();
+        crate::migrations::run_pending(&db).unwrap();
+        install_audit_triggers(&db).unwrap();
+        db
+    }
+
+    fn actor(command: &str) -> AuditActor {
+        AuditActor { user_id: Some(1), username: Some("sara".to_string()), command: command.to_string() }
+    }
+
+    #[tokio::test]
+    async fn test_changes_are_logged_with_their_author() {
+        let db = sqlite();
+        let repo = select(None, Some(db.clone()), None).unwrap();
+        attributed_sync(actor("create_currency"), || {
+            db.execute("INSERT INTO currencies (name, base, rate) VALUES ('AFN', 1, 1)", &[]).unwrap();
+        });
+        attributed_sync(actor("update_currency"), || {
+            db.execute("UPDATE currencies SET rate = 2 WHERE name = 'AFN'", &[]).unwrap();
+            // Only the timestamp changed: nothing to log
+            db.execute("UPDATE currencies SET updated_at = '2030-01-01 00:00:00'", &[]).unwrap();
+            db.execute("INSERT INTO users (username, email, password_hash) VALUES ('sara', 'sara@example.com', 'secret-hash')", &[]).unwrap();
+            db.execute("UPDATE users SET failed_logins = 3", &[]).unwrap();
+        });
+        attributed_sync(actor("delete_currency"), || db.execute("DELETE FROM currencies", &[]).unwrap());
+        // Outside any command, nobody is named
+        db.execute("INSERT INTO currencies (name, base, rate) VALUES ('USD', 0, 70)", &[]).unwrap();
+
+        let log = repo.audit_entries(0, 100).await.unwrap();
+        let actions: Vec<(&str, &str, Option<&str>)> =
+            log.iter().map(|e| (e.entity.as_str(), e.action.as_str(), e.command.as_deref())).collect();
+        assert_eq!(
+            actions,
+            [
+                ("currencies", "create", Some("create_currency")),
+                ("currencies", "update", Some("update_currency")),
+                ("users", "create", Some("update_currency")),
+                ("currencies", "delete", Some("delete_currency")),
+                ("currencies", "create", None),
+            ]
+        );
+        assert_eq!(log[1].before.as_ref().unwrap()["rate"], json!(1.0));
+        assert_eq!(log[1].after.as_ref().unwrap()["rate"], json!(2.0));
+        assert_eq!(log[3].after, None);
+        assert!(log[..4].iter().all(|e| e.user_id == Some(1) && e.username.as_deref() == Some("sara")));
+        assert_eq!((log[4].user_id, log[4].username.as_deref()), (None, None));
+        assert!(log.iter().all(|e| e.hash.is_none()));
+        // Secrets never reach the log
+        assert!(log[2].after.as_ref().unwrap().get("password_hash").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_changes_keep_their_authors() {
+        let db = sqlite();
+        let repo = select(None, Some(db.clone()), None).unwrap();
+        let add = |name: &'static str| {
+            let db = db.clone();
+            async move {
+                for phone in ["0700", "0711"] {
+                    db.execute("INSERT INTO customers (full_name, phone, address) VALUES (?, ?, 'Kabul')", &[&name, &phone]).unwrap();
+                    tokio::task::yield_now().await;
+                }
+            }
+        };
+        let sara = AuditActor { user_id: Some(2), username: Some("sara".to_string()), command: "create_customer".to_string() };
+        let omid = AuditActor { user_id: Some(3), username: Some("omid".to_string()), command: "create_customer".to_string() };
+        tokio::join!(attributed(sara, add("Ahmad")), attributed(omid, add("Bashir")));
+
+        let log = repo.audit_entries(0, 10).await.unwrap();
+        let mut authors: Vec<(&str, Option<&str>)> =
+            log.iter().map(|e| (e.after.as_ref().unwrap()["full_name"].as_str().unwrap(), e.username.as_deref())).collect();
+        // The two commands took turns
+        assert_ne!(authors[0].0, authors[1].0);
+        authors.sort();
+        assert_eq!(authors, [("Ahmad", Some("sara")), ("Ahmad", Some("sara")), ("Bashir", Some("omid")), ("Bashir", Some("omid"))]);
+    }
+
+    #[tokio::test]
+    async fn test_chain_detects_tampering() {
+        let db = sqlite();
+        let repo = select(None, Some(db.clone()), None).unwrap();
+        attributed_sync(actor("create_customer"), || {
+            for name in ["Ahmad", "Bashir", "Karim"] {
+                db.execute("INSERT INTO customers (full_name, phone, address) VALUES (?, '0700', 'Kabul')", &[&name]).unwrap();
+            }
+        });
+        assert_eq!(seal(repo.as_ref()).await.unwrap(), 3);
+        assert_eq!(seal(repo.as_ref()).await.unwrap(), 0);
+        let report = verify(repo.as_ref()).await.unwrap();
+        assert!(report.valid);
+        assert_eq!(report.entries, 3);
+
+        // Sealed entries can't be edited or removed through SQL...
+        assert!(db.execute("UPDATE audit_log SET username = 'nobody' WHERE id = 2", &[]).is_err());
+        assert!(db.execute("DELETE FROM audit_log WHERE id = 2", &[]).is_err());
+
+        // ...and when someone gets around that, verification finds the entry
+        db.execute("DROP TRIGGER audit_log_no_update", &[]).unwrap();
+        db.execute("UPDATE audit_log SET new_data = json_set(new_data, '$.phone', '0799') WHERE id = 2", &[]).unwrap();
+        let report = verify(repo.as_ref()).await.unwrap();
+        assert_eq!((report.valid, report.broken_at, report.entries), (false, Some(2), 1));
+
+        db.execute("DROP TRIGGER audit_log_no_delete", &[]).unwrap();
+        db.execute("DELETE FROM audit_log WHERE id = 2", &[]).unwrap();
+        assert_eq!(verify(repo.as_ref()).await.unwrap().broken_at, Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_unsealed_tail_is_not_an_error() {
+        let db = sqlite();
+        let repo = select(None, Some(db.clone()), None).unwrap();
+        db.execute("INSERT INTO customers (full_name, phone, address) VALUES ('Ahmad', '0700', 'Kabul')", &[]).unwrap();
+        seal(repo.as_ref()).await.unwrap();
+
+        // Written inside a transaction that is still open: sealing waits for the commit
+        db.execute("SAVEPOINT tx", &[]).unwrap();
+        db.execute("INSERT INTO customers (full_name, phone, address) VALUES ('Bashir', '0711', 'Herat')", &[]).unwrap();
+        assert_eq!(seal(repo.as_ref()).await.unwrap(), 0);
+        db.execute("RELEASE tx", &[]).unwrap();
+        let log = repo.audit_entries(0, 10).await.unwrap();
+        assert_eq!(log[1].hash, None);
+        assert!(log[1].user_id.is_none());
+
+        assert!(verify(repo.as_ref()).await.unwrap().valid);
+        assert!(repo.audit_entries(0, 10).await.unwrap().iter().all(|e| e.hash.is_some()));
+    }
+}
@@ -0,0 +1,198 @@
+use crate::db::Database;
+use anyhow::Result;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use surrealdb::{Action, Connection, Notification, Surreal};
+use tokio::task::AbortHandle;
+
+/// Tauri event emitted after every committed change to a record
+pub const ENTITY_CHANGED: &str = "entity_changed";
+/// How often the SQLite audit log is checked for new entries
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Entries read per poll
+const BATCH: i64 = 500;
+
+/// Where a change was seen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeSource {
+    /// The database on this machine (SQLite or the local SurrealKV store)
+    Local,
+    /// The online SurrealDB server, which other clients write to as well
+    Server,
+}
+
+/// Payload of `entity_changed`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntityChanged {
+    /// Table of the record, e.g. `sales`
+    pub entity: String,
+    pub id: Option<String>,
+    /// `create`, `update` or `delete`
+    pub action: String,
+    pub source: ChangeSource,
+}
+
+/// Columns of an audit log entry that describe the change
+#[derive(Debug, Deserialize)]
+struct LoggedChange {
+    entity: String,
+    entity_id: Option<String>,
+    action: String,
+}
+
+/// Follows the SQLite audit log, which its triggers fill in for every write
+#[derive(Debug, Default)]
+pub struct SqliteFeed {
+    db_path: Option<PathBuf>,
+    last_id: Option<i64>,
+}
+
+impl SqliteFeed {
+    /// Changes committed since the last poll. The first poll of a database only notes where
+    /// its log ends, so opening one doesn't replay its history.
+    pub fn poll(&mut self, db: &Database) -> Result<Vec<EntityChanged>> {
+        if self.db_path.as_ref() != Some(db.get_path()) {
+            self.db_path = Some(db.get_path().clone());
+            self.last_id = None;
+        }
+        db.with_connection(|conn| {
+            // Entries of an open transaction could still be rolled back
+            if !conn.is_autocommit() {
+                return Ok(Vec::new());
+            }
+            let Some(last_id) = self.last_id else {
+                self.last_id = Some(conn.query_row("SELECT COALESCE(MAX(id), 0) FROM audit_log", [], |row| row.get(0))?);
+                return Ok(Vec::new());
+            };
+            let mut stmt = conn.prepare("SELECT id, entity, entity_id, action FROM audit_log WHERE id > ? ORDER BY id LIMIT ?")?;
+            let rows = stmt
+                .query_map(rusqlite::params![last_id, BATCH], |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        EntityChanged { entity: row.get(1)?, id: row.get(2)?, action: row.get(3)?, source: ChangeSource::Local },
+                    ))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            if let Some((id, _)) = rows.last() {
+                self.last_id = Some(*id);
+            }
+            Ok(rows.into_iter().map(|(_, change)| change).collect())
+        })
+    }
+}
+
+/// Pass every change SurrealDB logs to `audit_log` (see `audit::install_audit_events`) to `sink`,
+/// through a LIVE query, until the connection closes or the task is aborted
+pub async fn watch_surreal<C: Connection>(db: &Surreal<C>, source: ChangeSource, mut sink: impl FnMut(EntityChanged)) -> Result<()> {
+    let mut response = db.query("LIVE SELECT entity, entity_id, action FROM audit_log").await?;
+    let mut stream = response.stream::<Notification<LoggedChange>>(0)?;
+    while let Some(notification) = stream.next().await {
+        let notification = notification?;
+        // Sealing updates entries later; only new ones are changes
+        if notification.action != Action::Create {
+            continue;
+        }
+        let LoggedChange { entity, entity_id, action } = notification.data;
+        sink(EntityChanged { entity, id: entity_id, action, source });
+    }
+    Ok(())
+}
+
+/// The LIVE query tasks of the open SurrealDB connection
+#[derive(Debug, Default)]
+pub struct LiveQueries(Mutex<Vec<AbortHandle>>);
+
+impl LiveQueries {
+    /// Stop the running tasks and keep track of `tasks` instead
+    pub fn replace(&self, tasks: Vec<AbortHandle>) {
+        let mut running = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        for task in running.drain(..) {
+            task.abort();
+        }
+        *running = tasks;
+    }
+
+    pub fn stop(&self) {
+        self.replace(Vec::new());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{install_audit_events, install_audit_triggers};
+    use crate::surrealdb::DatabaseConnection;
+    use std::sync::Arc;
+    use surrealdb::engine::local::SurrealKv;
+
+    fn changes(list: &[(&str, &str, &str)], source: ChangeSource) -> Vec<EntityChanged> {
+        list.iter()
+            .map(|(entity, id, action)| EntityChanged { entity: entity.to_string(), id: Some(id.to_string()), action: action.to_string(), source })
+            .collect()
+    }
+
+    #[test]
+    fn test_sqlite_feed_reports_committed_changes() {
+        let db = Database::new(PathBuf::from(":memory:"));
+        db.open().unwrap();
+        crate::migrations::run_pending(&db).unwrap();
+        install_audit_triggers(&db).unwrap();
+        let insert = "INSERT INTO customers (full_name, phone, address) VALUES ('Ahmad', '0700', 'Kabul')";
+        db.execute(insert, &[]).unwrap();
+
+        let mut feed = SqliteFeed::default();
+        assert!(feed.poll(&db).unwrap().is_empty(), "history isn't replayed");
+
+        db.execute(insert, &[]).unwrap();
+        db.execute("UPDATE customers SET phone = '0799' WHERE id = 1", &[]).unwrap();
+        let result: std::result::Result<(), String> = db.transaction(|db| {
+            db.execute("DELETE FROM customers WHERE id = 2", &[]).map_err(|e| e.to_string())?;
+            // Mid-transaction nothing is reported
+            assert!(feed.poll(db).unwrap().is_empty());
+            Err("rolled back".to_string())
+        });
+        assert!(result.is_err());
+
+        assert_eq!(feed.poll(&db).unwrap(), changes(&[("customers", "2", "create"), ("customers", "1", "update")], ChangeSource::Local));
+        assert!(feed.poll(&db).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_surreal_live_query_reports_changes() {
+        let dir = std::env::temp_dir().join(format!("shafaf-changes-{}", crate::session::random_hex(8)));
+        let db = Surreal::new::<SurrealKv>(dir.to_string_lossy().to_string()).await.unwrap();
+        db.use_ns("shafaf").use_db("shafaf").await.unwrap();
+        let db = Arc::new(db);
+        install_audit_events(&DatabaseConnection::Offline(db.clone())).await.unwrap();
+
+        let (sender, mut received) = tokio::sync::mpsc::unbounded_channel();
+        let watched = db.clone();
+        let task = tokio::spawn(async move {
+            watch_surreal(&watched, ChangeSource::Server, |change| sender.send(change).unwrap()).await.unwrap();
+        });
+        // Let the LIVE query start before writing
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        db.query("CREATE customers:1 SET full_name = 'Ahmad', phone = '0700'; UPDATE customers:1 SET phone = '0799'; DELETE customers:1;")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+        let mut seen = Vec::new();
+        while seen.len() < 3 {
+            let change = tokio::time::timeout(Duration::from_secs(5), received.recv()).await.unwrap().unwrap();
+            seen.push(change);
+        }
+        assert_eq!(seen, changes(&[("customers", "1", "create"), ("customers", "1", "update"), ("customers", "1", "delete")], ChangeSource::Server));
+
+        let queries = LiveQueries::default();
+        queries.replace(vec![task.abort_handle()]);
+        queries.stop();
+        assert!(task.await.unwrap_err().is_cancelled());
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
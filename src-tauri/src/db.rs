@@ -1,21 +1,46 @@
-    use rusqlite::{Connection, Result as SqliteResult, OpenFlags};
+use rusqlite::{Connection, Result as SqliteResult, OpenFlags};
+use parking_lot::{ReentrantMutex, ReentrantMutexGuard};
+use std::cell::{RefCell, RefMut};
 use std::path::PathBuf;
-use std::sync::Mutex;
+use std::sync::Arc;
 use anyhow::Result;
 
+/// Handle to the SQLite database. Clones share the same connection.
+///
+/// The lock is reentrant, so a transaction can hold it from start to end while the code
+/// inside keeps calling `execute`, `query`, etc. on the same thread; other threads wait.
+#[derive(Clone)]
 pub struct Database {
-    conn: Mutex<Option<Connection>>,
+    conn: Arc<ReentrantMutex<RefCell<Option<Connection>>>>,
     db_path: PathBuf,
 }
 
+/// The locked connection of a `Database`
+struct ConnectionGuard<'a>(ReentrantMutexGuard<'a, RefCell<Option<Connection>>>);
+
+impl ConnectionGuard<'_> {
+    fn slot(&self) -> RefMut<'_, Option<Connection>> {
+        self.0.borrow_mut()
+    }
+
+    fn open(&self) -> Result<RefMut<'_, Connection>> {
+        RefMut::filter_map(self.slot(), Option::as_mut)
+            .map_err(|_| anyhow::anyhow!("Database is not open. Please open it first."))
+    }
+}
+
 impl Database {
     pub fn new(db_path: PathBuf) -> Self {
         Database {
-            conn: Mutex::new(None),
+            conn: Arc::new(ReentrantMutex::new(RefCell::new(None))),
             db_path,
         }
     }
 
+    fn lock(&self) -> ConnectionGuard<'_> {
+        ConnectionGuard(self.conn.lock())
+    }
+
     /// Create a new database file (does not open it)
     #[allow(dead_code)]
     pub fn create_database(&self) -> Result<()> {
@@ -34,7 +59,8 @@ impl Database {
     /// Open the database connection with explicit read-write access
     /// Creates the database file if it doesn't exist
     pub fn open(&self) -> Result<()> {
-        let mut conn_guard = self.conn.lock().unwrap();
+        let guard = self.lock();
+        let mut conn_guard = guard.slot();
         if conn_guard.is_some() {
             return Ok(()); // Already open
         }
@@ -49,8 +75,9 @@ impl Database {
 
     /// Close the database connection
     pub fn close(&self) -> Result<()> {
-        let mut conn_guard = self.conn.lock().unwrap();
-        if let Some(conn) = conn_guard.take() {
+        let guard = self.lock();
+        let conn = guard.slot().take();
+        if let Some(conn) = conn {
             conn.close().map_err(|(_, e)| anyhow::anyhow!("Failed to close connection: {}", e))?;
         }
         Ok(())
@@ -58,24 +85,31 @@ impl Database {
 
     /// Check if database is open
     pub fn is_open(&self) -> bool {
-        let conn_guard = self.conn.lock().unwrap();
-        conn_guard.is_some()
+        self.lock().slot().is_some()
     }
 
     /// Execute a SQL query that doesn't return results
     pub fn execute(&self, sql: &str, params: &[&dyn rusqlite::ToSql]) -> Result<usize> {
-        let mut conn_guard = self.conn.lock().unwrap();
-        let conn = conn_guard.as_mut().ok_or_else(|| anyhow::anyhow!("Database is not open. Please open it first."))?;
+        let guard = self.lock();
+        let conn = guard.open()?;
         Ok(conn.execute(sql, params)?)
     }
 
+    /// Execute an INSERT and return the rowid of the row it created
+    pub fn insert(&self, sql: &str, params: &[&dyn rusqlite::ToSql]) -> Result<i64> {
+        let guard = self.lock();
+        let conn = guard.open()?;
+        conn.execute(sql, params)?;
+        Ok(conn.last_insert_rowid())
+    }
+
     /// Execute a SQL query and return results
     pub fn query<T, F>(&self, sql: &str, params: &[&dyn rusqlite::ToSql], f: F) -> Result<Vec<T>>
     where
         F: FnMut(&rusqlite::Row<'_>) -> SqliteResult<T>,
     {
-        let mut conn_guard = self.conn.lock().unwrap();
-        let conn = conn_guard.as_mut().ok_or_else(|| anyhow::anyhow!("Database is not open. Please open it first."))?;
+        let guard = self.lock();
+        let conn = guard.open()?;
         let mut stmt = conn.prepare(sql)?;
         let rows = stmt.query_map(params, f)?;
         let mut results = Vec::new();
@@ -88,8 +122,8 @@ impl Database {
     /// Get column names from a prepared statement
     #[allow(dead_code)]
     pub fn get_columns(&self, sql: &str) -> Result<Vec<String>> {
-        let mut conn_guard = self.conn.lock().unwrap();
-        let conn = conn_guard.as_mut().ok_or_else(|| anyhow::anyhow!("Database is not open. Please open it first."))?;
+        let guard = self.lock();
+        let conn = guard.open()?;
         let stmt = conn.prepare(sql)?;
         let column_count = stmt.column_count();
         let columns: Vec<String> = (0..column_count)
@@ -98,18 +132,45 @@ impl Database {
         Ok(columns)
     }
 
-    /// Get connection for advanced operations (internal use)
+    /// Get connection for advanced operations (internal use). `f` must not call back into
+    /// this `Database`, as the connection is borrowed while it runs.
     pub fn with_connection<F, R>(&self, f: F) -> Result<R>
     where
         F: FnOnce(&mut Connection) -> Result<R>,
     {
-        let mut conn_guard = self.conn.lock().unwrap();
-        let conn = conn_guard.as_mut().ok_or_else(|| anyhow::anyhow!("Database is not open. Please open it first."))?;
-        f(conn)
+        let guard = self.lock();
+        let mut conn = guard.open()?;
+        f(&mut conn)
+    }
+
+    /// Run `f` inside a transaction: commit if it returns Ok, roll back if it returns Err.
+    /// Uses savepoints, so calls can be nested (an inner failure only undoes the inner part).
+    /// The connection stays locked until the transaction ends, so other threads can't
+    /// interleave their statements with it.
+    pub fn transaction<T, E, F>(&self, f: F) -> std::result::Result<T, E>
+    where
+        F: FnOnce(&Database) -> std::result::Result<T, E>,
+        E: From<String>,
+    {
+        let _held = self.lock();
+        self.with_connection(|conn| Ok(conn.execute_batch("SAVEPOINT tx")?))
+            .map_err(|e| E::from(format!("Failed to begin transaction: {}", e)))?;
+
+        match f(self) {
+            Ok(value) => {
+                self.with_connection(|conn| Ok(conn.execute_batch("RELEASE tx")?))
+                    .map_err(|e| E::from(format!("Failed to commit transaction: {}", e)))?;
+                Ok(value)
+            }
+            Err(err) => {
+                // Keep the original error; a failed rollback would only hide it
+                let _ = self.with_connection(|conn| Ok(conn.execute_batch("ROLLBACK TO tx; RELEASE tx")?));
+                Err(err)
+            }
+        }
     }
 
     /// Get the database path
-    #[allow(dead_code)]
     pub fn get_path(&self) -> &PathBuf {
         &self.db_path
     }
@@ -120,3 +181,239 @@ impl Database {
         self.db_path.exists()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory_db() -> Database {
+        let db = Database::new(PathBuf::from(":memory:"));
+        db.open().unwrap();
+        db.execute("CREATE TABLE sales (id INTEGER PRIMARY KEY AUTOINCREMENT, customer_id INTEGER NOT NULL)", &[]).unwrap();
+        db.execute("CREATE TABLE sale_items (id INTEGER PRIMARY KEY AUTOINCREMENT, sale_id INTEGER NOT NULL, amount REAL NOT NULL)", &[]).unwrap();
+        db
+    }
+
+    fn count(db: &Database, table: &str) -> i64 {
+        db.query(&format!("SELECT COUNT(*) FROM {}", table), &[], |row| row.get(0)).unwrap()[0]
+    }
+
+    /// A database with the app's schema, a customer, a product, a purchase, a base currency and
+    /// a receivable, revenue and cash account, the cash account holding 500
+    fn business_db() -> Database {
+        let db = Database::new(PathBuf::from(":memory:"));
+        db.open().unwrap();
+        crate::migrations::run_pending(&db).unwrap();
+        for sql in [
+            "INSERT INTO currencies (id, name, base, rate) VALUES (1, 'AFN', 1, 1)",
+            "INSERT INTO customers (id, full_name, phone, address) VALUES (1, 'Ahmad', '0701', 'Herat')",
+            "INSERT INTO suppliers (id, full_name, phone, address) VALUES (1, 'Karim', '0700', 'Kabul')",
+            "INSERT INTO units (id, name) VALUES (1, 'piece')",
+            "INSERT INTO products (id, name) VALUES (1, 'Tea')",
+            "INSERT INTO purchases (id, supplier_id, date) VALUES (1, 1, '2026-01-01')",
+            "INSERT INTO accounts (id, name, account_type, currency_id) VALUES (1, 'Accounts Receivable', 'Asset', 1)",
+            "INSERT INTO accounts (id, name, account_type, currency_id) VALUES (2, 'Sales Revenue', 'Revenue', 1)",
+            "INSERT INTO accounts (id, name, account_type, currency_id, initial_balance, current_balance) VALUES (3, 'Cash', 'Asset', 1, 500, 500)",
+            "INSERT INTO account_currency_balances (account_id, currency_id, balance) VALUES (3, 1, 500)",
+        ] {
+            db.execute(sql, &[]).unwrap();
+        }
+        db
+    }
+
+    /// Row counts, `(account_id, currency_id, balance)` rows and the accounts' current balances
+    type State = (Vec<i64>, Vec<(i64, i64, f64)>, Vec<f64>);
+
+    /// Row counts of `tables`, the account balances and the accounts' current balances
+    fn state(db: &Database, tables: &[&str]) -> State {
+        let counts = tables.iter().map(|table| count(db, table)).collect();
+        let balances = db
+            .query("SELECT account_id, currency_id, balance FROM account_currency_balances ORDER BY account_id, currency_id", &[], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        let current = db.query("SELECT current_balance FROM accounts ORDER BY id", &[], |row| row.get(0)).unwrap();
+        (counts, balances, current)
+    }
+
+    #[test]
+    fn test_insert_returns_own_rowid() {
+        let db = memory_db();
+        let first = db.insert("INSERT INTO sales (customer_id) VALUES (?)", &[&7i64 as &dyn rusqlite::ToSql]).unwrap();
+        let second = db.insert("INSERT INTO sales (customer_id) VALUES (?)", &[&7i64 as &dyn rusqlite::ToSql]).unwrap();
+        assert_ne!(first, second);
+        let ids: Vec<i64> = db.query("SELECT id FROM sales ORDER BY id", &[], |row| row.get(0)).unwrap();
+        assert_eq!(ids, vec![first, second]);
+    }
+
+    #[test]
+    fn test_transaction_commits_on_success() {
+        let db = memory_db();
+        let result: std::result::Result<(), String> = db.transaction(|db| {
+            db.execute("INSERT INTO sales (customer_id) VALUES (1)", &[]).map_err(|e| e.to_string())?;
+            db.execute("INSERT INTO sale_items (sale_id, amount) VALUES (1, 2.0)", &[]).map_err(|e| e.to_string())?;
+            Ok(())
+        });
+        assert!(result.is_ok());
+        assert_eq!(count(&db, "sales"), 1);
+        assert_eq!(count(&db, "sale_items"), 1);
+    }
+
+    #[test]
+    fn test_failure_midway_leaves_no_partial_rows() {
+        let db = memory_db();
+        let result: std::result::Result<(), String> = db.transaction(|db| {
+            db.execute("INSERT INTO sales (customer_id) VALUES (1)", &[]).map_err(|e| e.to_string())?;
+            db.execute("INSERT INTO sale_items (sale_id, amount) VALUES (1, 2.0)", &[]).map_err(|e| e.to_string())?;
+            // NOT NULL violation on the second item
+            db.execute("INSERT INTO sale_items (sale_id, amount) VALUES (1, NULL)", &[]).map_err(|e| e.to_string())?;
+            Ok(())
+        });
+        assert!(result.is_err());
+        assert_eq!(count(&db, "sales"), 0);
+        assert_eq!(count(&db, "sale_items"), 0);
+    }
+
+    #[test]
+    fn test_failed_sale_leaves_no_rows() {
+        let db = business_db();
+        let tables = ["sales", "sale_items", "sale_payments", "journal_entries", "journal_entry_lines", "event_outbox"];
+        let item = |product_id| crate::SaleItemInput { product_id, unit_id: 1, per_price: 10.0, amount: 2.0, purchase_item_id: None, sale_type: None };
+        let mut sale = crate::SaleInput {
+            customer_id: 1,
+            date: "2026-01-02".to_string(),
+            notes: None,
+            currency_id: Some(1),
+            exchange_rate: 1.0,
+            paid_amount: 5.0,
+            additional_costs: Vec::new(),
+            // The header, its journal entry and the payment are written before the missing product
+            items: vec![item(1), item(99)],
+        };
+        let before = state(&db, &tables);
+
+        let error = crate::create_sale_internal(&db, &sale).unwrap_err();
+        assert!(error.contains("Failed to insert sale item"), "{}", error);
+        assert_eq!(state(&db, &tables), before);
+
+        sale.items.pop();
+        crate::create_sale_internal(&db, &sale).unwrap();
+        let (counts, balances, _) = state(&db, &tables);
+        assert_eq!(counts[..5], [1, 1, 1, 1, 2]);
+        assert_eq!(balances.len(), 3);
+    }
+
+    #[test]
+    fn test_failed_purchase_payment_leaves_no_rows() {
+        let db = business_db();
+        let tables = ["purchase_payments", "account_transactions", "journal_entries", "event_outbox"];
+        let payment = |amount| crate::PurchasePaymentInput {
+            account_id: Some(3),
+            amount,
+            currency: "AFN".to_string(),
+            rate: 1.0,
+            date: "2026-01-02".to_string(),
+            notes: None,
+        };
+        let before = state(&db, &tables);
+
+        // The balance is checked after the payment is inserted
+        let error = crate::create_purchase_payment_internal(&db, 1, &payment(600.0)).unwrap_err();
+        assert!(error.contains("Insufficient balance"), "{}", error);
+        assert_eq!(state(&db, &tables), before);
+
+        // The event is the last row written, after the withdrawal and both balances
+        db.execute(
+            "CREATE TRIGGER reject_event BEFORE INSERT ON event_outbox BEGIN SELECT RAISE(ABORT, 'outbox full'); END",
+            &[],
+        )
+        .unwrap();
+        let error = crate::create_purchase_payment_internal(&db, 1, &payment(100.0)).unwrap_err();
+        assert!(error.contains("outbox full"), "{}", error);
+        assert_eq!(state(&db, &tables), before);
+
+        db.execute("DROP TRIGGER reject_event", &[]).unwrap();
+        crate::create_purchase_payment_internal(&db, 1, &payment(100.0)).unwrap();
+        let (counts, balances, current) = state(&db, &tables);
+        assert_eq!(counts, [1, 1, 0, 1]);
+        assert_eq!(balances, [(3, 1, 400.0)]);
+        assert_eq!(current[2], 400.0);
+    }
+
+    #[test]
+    fn test_failed_journal_update_leaves_entry_unchanged() {
+        let db = business_db();
+        let line = |account_id, debit_amount, credit_amount| crate::JournalLineInput {
+            account_id,
+            currency_id: 1,
+            debit_amount,
+            credit_amount,
+            exchange_rate: 1.0,
+            description: None,
+        };
+        let entry = crate::JournalEntryInput {
+            entry_date: "2026-01-02".to_string(),
+            description: None,
+            reference_type: None,
+            reference_id: None,
+            lines: vec![line(3, 40.0, 0.0), line(2, 0.0, 40.0)],
+        };
+        let entry = crate::post_journal_entry_internal(&db, &entry).unwrap();
+        let tables = ["journal_entries", "journal_entry_lines", "account_transactions"];
+        let lines = |db: &Database| -> Vec<(i64, f64, f64)> {
+            db.query("SELECT account_id, debit_amount, credit_amount FROM journal_entry_lines ORDER BY id", &[], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap()
+        };
+        let before = (state(&db, &tables), lines(&db));
+
+        // The old lines are reversed and the first new one posted before the missing account
+        let error = crate::update_journal_entry_internal(&db, entry.id, &[line(3, 50.0, 0.0), line(99, 0.0, 50.0)]).unwrap_err();
+        assert!(error.contains("Failed to insert journal entry line"), "{}", error);
+        assert_eq!((state(&db, &tables), lines(&db)), before);
+
+        crate::update_journal_entry_internal(&db, entry.id, &[line(3, 50.0, 0.0), line(2, 0.0, 50.0)]).unwrap();
+        assert_eq!(lines(&db), [(3, 50.0, 0.0), (2, 0.0, 50.0)]);
+    }
+
+    #[test]
+    fn test_nested_transaction_rolls_back_inner_only() {
+        let db = memory_db();
+        let result: std::result::Result<(), String> = db.transaction(|db| {
+            db.execute("INSERT INTO sales (customer_id) VALUES (1)", &[]).map_err(|e| e.to_string())?;
+            let inner: std::result::Result<(), String> = db.transaction(|db| {
+                db.execute("INSERT INTO sale_items (sale_id, amount) VALUES (1, 2.0)", &[]).map_err(|e| e.to_string())?;
+                Err("forced failure".to_string())
+            });
+            assert!(inner.is_err());
+            Ok(())
+        });
+        assert!(result.is_ok());
+        assert_eq!(count(&db, "sales"), 1);
+        assert_eq!(count(&db, "sale_items"), 0);
+    }
+
+    #[test]
+    fn test_other_threads_wait_for_transaction() {
+        let db = memory_db();
+        let (started, wait_started) = std::sync::mpsc::channel();
+        let mut other = None;
+        let result: std::result::Result<(), String> = db.transaction(|db| {
+            db.execute("INSERT INTO sales (customer_id) VALUES (1)", &[]).map_err(|e| e.to_string())?;
+            let shared = db.clone();
+            other = Some(std::thread::spawn(move || {
+                started.send(()).unwrap();
+                shared.execute("INSERT INTO sales (customer_id) VALUES (2)", &[]).unwrap();
+            }));
+            wait_started.recv().unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            Err("forced failure".to_string())
+        });
+        assert!(result.is_err());
+        other.unwrap().join().unwrap();
+        // The other thread's insert ran after the rollback instead of inside the transaction
+        let customers: Vec<i64> = db.query("SELECT customer_id FROM sales", &[], |row| row.get(0)).unwrap();
+        assert_eq!(customers, vec![2]);
+    }
+}
@@ -0,0 +1,153 @@
+use crate::db::Database;
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+pub const SALE_CREATED: &str = "sale_created";
+/// A customer paid for a sale, at the sale or later
+pub const PAYMENT_RECEIVED: &str = "payment_received";
+pub const PURCHASE_CREATED: &str = "purchase_created";
+/// A supplier was paid for a purchase
+pub const PURCHASE_PAYMENT_MADE: &str = "purchase_payment_made";
+pub const EXPENSE_RECORDED: &str = "expense_recorded";
+pub const SALARY_PAID: &str = "salary_paid";
+/// A sale took a product's remaining stock down to `LOW_STOCK_LEVEL` or below
+pub const STOCK_LOW: &str = "stock_low";
+
+/// Every event the backend emits
+pub const ALL: &[&str] = &[
+    SALE_CREATED,
+    PAYMENT_RECEIVED,
+    PURCHASE_CREATED,
+    PURCHASE_PAYMENT_MADE,
+    EXPENSE_RECORDED,
+    SALARY_PAID,
+    STOCK_LOW,
+];
+
+/// Remaining quantity at which a product counts as running low
+pub const LOW_STOCK_LEVEL: f64 = 5.0;
+
+/// Queue an event in the outbox. Call it inside the transaction that made the change,
+/// so the event is stored if and only if the change is.
+pub fn record<T: Serialize>(db: &Database, event: &str, payload: &T) -> Result<i64> {
+    let payload = serde_json::to_string(payload)?;
+    db.insert(
+        "INSERT INTO event_outbox (event, payload) VALUES (?, ?)",
+        &[&event as &dyn rusqlite::ToSql, &payload],
+    )
+}
+
+/// Purchased minus sold quantity of a product, over purchases and sales not in the trash
+pub fn remaining_stock(db: &Database, product_id: i64) -> Result<f64> {
+    let sql = "SELECT
+            (SELECT COALESCE(SUM(pi.amount), 0) FROM purchase_items pi
+                INNER JOIN purchases p ON pi.purchase_id = p.id
+                WHERE pi.product_id = ?1 AND p.deleted_at IS NULL)
+            - (SELECT COALESCE(SUM(si.amount), 0) FROM sale_items si
+                INNER JOIN sales s ON si.sale_id = s.id
+                WHERE si.product_id = ?1 AND s.deleted_at IS NULL)";
+    let rows = db.query(sql, &[&product_id as &dyn rusqlite::ToSql], |row| row.get::<_, f64>(0))?;
+    Ok(rows.first().copied().unwrap_or(0.0))
+}
+
+/// Record `stock_low` for every product whose stock this sale took across `LOW_STOCK_LEVEL`.
+/// `sold` is (product_id, amount) of the sale's items, which must already be inserted.
+pub fn record_low_stock(db: &Database, sale_id: i64, sold: &[(i64, f64)]) -> Result<()> {
+    let mut per_product: BTreeMap<i64, f64> = BTreeMap::new();
+    for (product_id, amount) in sold {
+        *per_product.entry(*product_id).or_default() += amount;
+    }
+    for (product_id, amount) in per_product {
+        let remaining = remaining_stock(db, product_id)?;
+        if remaining <= LOW_STOCK_LEVEL && remaining + amount > LOW_STOCK_LEVEL {
+            let name = db
+                .query("SELECT name FROM products WHERE id = ?", &[&product_id as &dyn rusqlite::ToSql], |row| row.get::<_, String>(0))?
+                .into_iter()
+                .next();
+            let payload = json!({
+                "product_id": product_id,
+                "name": name,
+                "remaining": remaining,
+                "threshold": LOW_STOCK_LEVEL,
+                "sale_id": sale_id,
+            });
+            record(db, STOCK_LOW, &payload)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations;
+    use std::path::PathBuf;
+
+    fn memory_db() -> Database {
+        let db = Database::new(PathBuf::from(":memory:"));
+        db.open().unwrap();
+        migrations::run_pending(&db).unwrap();
+        db
+    }
+
+    fn outbox(db: &Database) -> Vec<(String, String)> {
+        db.query("SELECT event, payload FROM event_outbox ORDER BY id", &[], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_rolled_back_changes_leave_no_event() {
+        let db = memory_db();
+        let result: Result<(), String> = db.transaction(|db| {
+            record(db, SALE_CREATED, &json!({ "id": 1 })).map_err(|e| e.to_string())?;
+            Err("insert failed".to_string())
+        });
+        assert!(result.is_err());
+        assert!(outbox(&db).is_empty());
+
+        db.transaction(|db| record(db, SALE_CREATED, &json!({ "id": 2 })).map_err(|e| e.to_string())).unwrap();
+        assert_eq!(outbox(&db), vec![(SALE_CREATED.to_string(), r#"{"id":2}"#.to_string())]);
+    }
+
+    #[test]
+    fn test_stock_low_fires_once_when_crossing() {
+        let db = memory_db();
+        for sql in [
+            "INSERT INTO suppliers (id, full_name, phone, address) VALUES (1, 'Karim', '0700', 'Kabul')",
+            "INSERT INTO customers (id, full_name, phone, address) VALUES (1, 'Ahmad', '0701', 'Herat')",
+            "INSERT INTO units (id, name) VALUES (1, 'piece')",
+            "INSERT INTO products (id, name) VALUES (1, 'Tea')",
+        ] {
+            db.execute(sql, &[]).unwrap();
+        }
+        db.execute("INSERT INTO purchases (id, supplier_id, date) VALUES (1, 1, '2026-01-01')", &[]).unwrap();
+        db.execute("INSERT INTO purchase_items (purchase_id, product_id, unit_id, per_price, amount, total) VALUES (1, 1, 1, 1, 10, 10)", &[]).unwrap();
+
+        let sell = |sale_id: i64, amount: f64| {
+            db.execute(
+                "INSERT INTO sales (id, customer_id, date) VALUES (?, 1, '2026-01-02')",
+                &[&sale_id as &dyn rusqlite::ToSql],
+            )
+            .unwrap();
+            db.execute(
+                "INSERT INTO sale_items (sale_id, product_id, unit_id, per_price, amount, total) VALUES (?, 1, 1, 1, ?, 0)",
+                &[&sale_id as &dyn rusqlite::ToSql, &amount],
+            )
+            .unwrap();
+            record_low_stock(&db, sale_id, &[(1, amount)]).unwrap();
+        };
+
+        sell(1, 3.0); // 7 left
+        assert!(outbox(&db).is_empty());
+        sell(2, 4.0); // 3 left
+        sell(3, 1.0); // 2 left, already low
+        let events = outbox(&db);
+        assert_eq!(events.len(), 1);
+        let payload: serde_json::Value = serde_json::from_str(&events[0].1).unwrap();
+        assert_eq!(payload["remaining"], 3.0);
+        assert_eq!(payload["sale_id"], 2);
+        assert_eq!(payload["name"], "Tea");
+    }
+}
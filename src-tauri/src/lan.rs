@@ -0,0 +1,392 @@
+use crate::api_types::{ApiError, RpcCall};
+use crate::audit::AuditActor;
+use crate::license::LicenseGate;
+use crate::permissions::{self, Access};
+use crate::server_config::SESSION_COOKIE;
+use crate::session::{random_hex, SessionStore, SESSION_LIFETIME};
+use axum::{
+    extract::rejection::JsonRejection,
+    extract::{ConnectInfo, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use chrono::Utc;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::net::SocketAddr;
+use tauri::{AppHandle, Manager};
+
+/// Tauri commands a browser may call through `/api/rpc`: what a cashier terminal needs
+pub const COMMANDS: &[&str] = &[
+    "login_user",
+    "verify_two_factor",
+    "logout_user",
+    "get_current_user",
+    "get_company_settings",
+    "get_currencies",
+    "get_units",
+    "get_accounts",
+    "get_products",
+    "get_customers",
+    "create_customer",
+    "get_sales",
+    "get_sale",
+    "get_sale_items",
+    "get_sale_payments",
+    "create_sale",
+    "create_sale_payment",
+];
+
+#[derive(Clone)]
+struct Lan {
+    app: AppHandle,
+    /// Served over HTTPS, so the session cookie is marked `Secure`
+    secure: bool,
+}
+
+/// Routes of LAN mode: the RPC bridge, and the built frontend for every other path
+pub fn router<S: Clone + Send + Sync + 'static>(app: AppHandle, secure: bool) -> Router<S> {
+    Router::new()
+        .route("/api/rpc", post(rpc))
+        .fallback(frontend)
+        .with_state(Lan { app, secure })
+}
+
+/// Where a browser's login attempts come from, for the login throttle and two-factor challenges
+fn device(peer: SocketAddr) -> String {
+    format!("lan:{}", peer.ip().to_canonical())
+}
+
+/// A browser's session, from its cookie `<login id>.<token>`. The login id is random for every
+/// login, so browsers sharing an address don't share a session.
+#[derive(Debug, PartialEq)]
+struct LanSession<'a> {
+    login_id: &'a str,
+    token: &'a str,
+}
+
+impl<'a> LanSession<'a> {
+    fn parse(cookie: &'a str) -> Option<Self> {
+        let (login_id, token) = cookie.split_once('.')?;
+        (!login_id.is_empty() && !token.is_empty()).then_some(LanSession { login_id, token })
+    }
+
+    /// Session client the login is kept under, like a window label
+    fn client(&self) -> String {
+        client(self.login_id)
+    }
+}
+
+fn client(login_id: &str) -> String {
+    format!("lan:{}", login_id)
+}
+
+/// Run a Tauri command for a browser, checked the way `guarded` checks a window's calls.
+/// Logging in sets the session cookie, which every later call has to bring.
+async fn rpc(
+    State(lan): State<Lan>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    call: Result<Json<RpcCall>, JsonRejection>,
+) -> Result<Response, ApiError> {
+    let Json(RpcCall { command, args }) = call?;
+    if !COMMANDS.contains(&command.as_str()) {
+        return Err(ApiError::not_found(format!("`{}` isn't available in LAN mode", command)));
+    }
+    let app = &lan.app;
+    let sessions = app.state::<SessionStore>();
+    let session = session_cookie(&headers)
+        .and_then(LanSession::parse)
+        .filter(|session| sessions.holds(&session.client(), session.token));
+    let now = Utc::now();
+    match &session {
+        Some(session) => sessions.authorize(&session.client(), &command, now)?,
+        None if permissions::command_access(&command) != Some(Access::Public) => {
+            return Err(ApiError::unauthenticated("Please log in"))
+        }
+        None => {}
+    }
+    app.state::<LicenseGate>().state(now.date_naive()).authorize(&command)?;
+
+    match (command.as_str(), session) {
+        ("login_user" | "verify_two_factor", session) => {
+            // Logging in again replaces the browser's session; a new login gets a new id
+            let login_id = session.map_or_else(|| random_hex(16), |session| session.login_id.to_string());
+            let (device, client) = (device(peer), client(&login_id));
+            let mut result = if command == "login_user" {
+                crate::login_user_internal(app, &device, &client, arg(&args, "username")?, arg(&args, "password")?).await
+            } else {
+                crate::verify_two_factor_internal(app, &device, &client, arg(&args, "challenge")?, arg(&args, "code")?).await
+            }
+            .map_err(ApiError::invalid)?;
+            // The token goes into the cookie only, out of reach of the page's scripts
+            let token = result.token.take();
+            let mut response = Json(result).into_response();
+            if let Some(token) = token {
+                let cookie = format!("{}.{}", login_id, token);
+                response.headers_mut().insert(header::SET_COOKIE, set_cookie(Some(&cookie), lan.secure));
+            }
+            Ok(response)
+        }
+        ("logout_user", session) => {
+            let ended = session.is_some_and(|session| sessions.end(&session.client()));
+            Ok(([(header::SET_COOKIE, set_cookie(None, lan.secure))], Json(ended)).into_response())
+        }
+        ("get_current_user", session) => {
+            let current = session.and_then(|session| sessions.current(&session.client(), now));
+            Ok(Json(current).into_response())
+        }
+        (_, session) => {
+            let actor = browser_actor(app, session.as_ref(), &command);
+            Ok(Json(run(app, actor, &command, &args).await?).into_response())
+        }
+    }
+}
+
+/// The browser's user, as the author of the changes `command` makes
+fn browser_actor(app: &AppHandle, session: Option<&LanSession>, command: &str) -> AuditActor {
+    let sessions = app.state::<SessionStore>();
+    let user = session.and_then(|session| sessions.current(&session.client(), Utc::now())).map(|current| current.session);
+    AuditActor {
+        user_id: user.as_ref().map(|user| user.user_id),
+        username: user.map(|user| user.username),
+        command: command.to_string(),
+    }
+}
+
+/// Call the Tauri command `command` with `args`, as `invoke` would, with its writes attributed to `actor`
+async fn run(app: &AppHandle, actor: AuditActor, command: &str, args: &Map<String, Value>) -> Result<Value, ApiError> {
+    match command {
+        "get_company_settings" => reply(crate::get_company_settings(app.state())),
+        "get_currencies" => reply(crate::get_currencies(app.state())),
+        "get_units" => reply(crate::get_units(app.state())),
+        "get_accounts" => reply(crate::get_accounts(app.state(), app.state()).await),
+        "get_products" => reply(
+            crate::get_products(
+                app.state(),
+                app.state(),
+                arg(args, "page")?,
+                arg(args, "perPage")?,
+                arg(args, "search")?,
+                arg(args, "sortBy")?,
+                arg(args, "sortOrder")?,
+            )
+            .await,
+        ),
+        "get_customers" => reply(
+            crate::get_customers(
+                app.state(),
+                app.state(),
+                arg(args, "page")?,
+                arg(args, "perPage")?,
+                arg(args, "search")?,
+                arg(args, "sortBy")?,
+                arg(args, "sortOrder")?,
+            )
+            .await,
+        ),
+        "create_customer" => reply(
+            crate::create_customer(
+                app.state(),
+                app.state(),
+                actor,
+                arg(args, "fullName")?,
+                arg(args, "phone")?,
+                arg(args, "address")?,
+                arg(args, "email")?,
+                arg(args, "notes")?,
+            )
+            .await,
+        ),
+        "get_sales" => reply(
+            crate::get_sales(
+                app.state(),
+                app.state(),
+                arg(args, "page")?,
+                arg(args, "perPage")?,
+                arg(args, "search")?,
+                arg(args, "sortBy")?,
+                arg(args, "sortOrder")?,
+            )
+            .await,
+        ),
+        "get_sale" => reply(crate::get_sale(app.state(), app.state(), arg(args, "id")?).await),
+        "get_sale_items" => reply(crate::get_sale_items(app.state(), app.state(), arg(args, "saleId")?).await),
+        "get_sale_payments" => reply(crate::get_sale_payments(app.state(), app.state(), arg(args, "saleId")?).await),
+        "create_sale" => reply(
+            crate::create_sale(
+                app.state(),
+                app.state(),
+                actor,
+                arg(args, "customerId")?,
+                arg(args, "date")?,
+                arg(args, "notes")?,
+                arg(args, "currencyId")?,
+                arg(args, "exchangeRate")?,
+                arg(args, "paidAmount")?,
+                arg(args, "additionalCosts")?,
+                arg(args, "items")?,
+            )
+            .await,
+        ),
+        "create_sale_payment" => reply(
+            crate::create_sale_payment(
+                app.state(),
+                app.state(),
+                actor,
+                arg(args, "saleId")?,
+                arg(args, "accountId")?,
+                arg(args, "currencyId")?,
+                arg(args, "exchangeRate")?,
+                arg(args, "amount")?,
+                arg(args, "date")?,
+            )
+            .await,
+        ),
+        _ => Err(ApiError::not_found(format!("`{}` isn't available in LAN mode", command))),
+    }
+}
+
+/// Argument `name` of a call; a missing one reads as `null`, which suits `Option` parameters
+fn arg<T: DeserializeOwned>(args: &Map<String, Value>, name: &str) -> Result<T, ApiError> {
+    let value = args.get(name).cloned().unwrap_or(Value::Null);
+    serde_json::from_value(value)
+        .map_err(|e| ApiError::invalid(format!("Invalid `{}`: {}", name, e)).with_details(json!({ "part": "body", "argument": name })))
+}
+
+/// A command's result as JSON; its error message becomes a 400, as `invoke` would reject with it
+fn reply<T: Serialize>(result: Result<T, String>) -> Result<Value, ApiError> {
+    let value = result.map_err(ApiError::invalid)?;
+    serde_json::to_value(value).map_err(|e| ApiError::internal(e.to_string()))
+}
+
+/// The session a request carries in its cookie
+fn session_cookie(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, token)| token)
+}
+
+/// `Set-Cookie` that keeps `value` for `/api/rpc` as long as a session can last, or clears it
+fn set_cookie(value: Option<&str>, secure: bool) -> HeaderValue {
+    let (value, max_age) = match value {
+        Some(value) => (value, SESSION_LIFETIME.num_seconds()),
+        None => ("", 0),
+    };
+    let mut cookie = format!("{}={}; Path=/api/rpc; Max-Age={}; HttpOnly; SameSite=Strict", SESSION_COOKIE, value, max_age);
+    if secure {
+        cookie.push_str("; Secure");
+    }
+    HeaderValue::from_str(&cookie).expect("login ids and session tokens are hex")
+}
+
+/// Serve the built frontend (`frontendDist`, i.e. `../dist`). Paths that aren't files get
+/// `index.html`, so the app's own routes work when opened or reloaded directly.
+async fn frontend(State(lan): State<Lan>, uri: Uri) -> Response {
+    if uri.path().starts_with("/api/") {
+        return ApiError::not_found("No such endpoint").into_response();
+    }
+    let Some(path) = asset_path(uri.path()) else {
+        return ApiError::not_found("No such file").into_response();
+    };
+    let assets = lan.app.asset_resolver();
+    let Some(asset) = assets.get(path).or_else(|| assets.get("index.html".to_string())) else {
+        return (StatusCode::NOT_FOUND, "The frontend isn't built; run `npm run build` first").into_response();
+    };
+    let mut response = ([(header::CONTENT_TYPE, asset.mime_type.clone())], asset.bytes).into_response();
+    if let Some(csp) = asset.csp_header.and_then(|csp| HeaderValue::from_str(&csp).ok()) {
+        response.headers_mut().insert(header::CONTENT_SECURITY_POLICY, csp);
+    }
+    response
+}
+
+/// Asset a request path asks for, refusing paths that climb out of the frontend folder
+fn asset_path(path: &str) -> Option<String> {
+    let escapes = path.contains('\\') || path.split('/').any(|part| part == ".." || part.contains(':'));
+    if escapes {
+        return None;
+    }
+    match path.trim_start_matches('/') {
+        "" => Some("index.html".to_string()),
+        path => Some(path.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `create_sale`'s item: (product_id, unit_id, per_price, amount, purchase_item_id, sale_type)
+    type SaleItemArgs = (i64, i64, f64, f64, Option<i64>, Option<String>);
+
+    #[test]
+    fn test_commands_are_plain_commands() {
+        for command in COMMANDS {
+            assert!(permissions::command_access(command).is_some(), "{} has no access policy", command);
+            // Password confirmation is a desktop step
+            assert!(!permissions::requires_reauthentication(command), "{} is sensitive", command);
+        }
+    }
+
+    #[test]
+    fn test_session_cookie() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(session_cookie(&headers), None);
+        headers.append(header::COOKIE, HeaderValue::from_static("theme=dark"));
+        headers.append(header::COOKIE, HeaderValue::from_static("a=1; shafaf_session=abc123; b=2"));
+        assert_eq!(session_cookie(&headers), Some("abc123"));
+
+        let set = set_cookie(Some("abc123"), true);
+        assert_eq!(set, "shafaf_session=abc123; Path=/api/rpc; Max-Age=43200; HttpOnly; SameSite=Strict; Secure");
+        assert_eq!(set_cookie(None, false), "shafaf_session=; Path=/api/rpc; Max-Age=0; HttpOnly; SameSite=Strict");
+    }
+
+    #[test]
+    fn test_arguments() {
+        let args = json!({ "page": 2, "perPage": 20, "search": null, "items": [[1, 2, 10.0, 3.0, null, "retail"]] });
+        let args = args.as_object().unwrap();
+        assert_eq!(arg::<i64>(args, "perPage").unwrap(), 20);
+        assert_eq!(arg::<Option<String>>(args, "search").unwrap(), None);
+        assert_eq!(arg::<Option<String>>(args, "sortBy").unwrap(), None, "missing reads as null");
+        let items: Vec<SaleItemArgs> = arg(args, "items").unwrap();
+        assert_eq!(items[0].5.as_deref(), Some("retail"));
+
+        let error = arg::<i64>(args, "per_page").unwrap_err();
+        assert!(error.message.contains("per_page"));
+        assert_eq!(error.details.unwrap()["argument"], "per_page");
+    }
+
+    #[test]
+    fn test_asset_path() {
+        assert_eq!(asset_path("/").as_deref(), Some("index.html"));
+        assert_eq!(asset_path("/assets/index-3f2a.js").as_deref(), Some("assets/index-3f2a.js"));
+        assert_eq!(asset_path("/sales/12").as_deref(), Some("sales/12"));
+        for path in ["/../secret.txt", "/assets/../../etc/passwd", "/..\\windows", "/C:/windows"] {
+            assert_eq!(asset_path(path), None, "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_device() {
+        assert_eq!(device("192.168.1.30:51234".parse().unwrap()), "lan:192.168.1.30");
+        assert_eq!(device("[::ffff:192.168.1.30]:51234".parse().unwrap()), "lan:192.168.1.30");
+    }
+
+    #[test]
+    fn test_lan_session() {
+        let session = LanSession::parse("9f1c2b.abc123").unwrap();
+        assert_eq!(session, LanSession { login_id: "9f1c2b", token: "abc123" });
+        assert_eq!(session.client(), "lan:9f1c2b");
+        for cookie in ["abc123", ".abc123", "9f1c2b.", ""] {
+            assert_eq!(LanSession::parse(cookie), None, "{}", cookie);
+        }
+    }
+}
@@ -1,14 +1,51 @@
+mod api;
+mod api_tokens;
+mod api_types;
+mod audit;
+mod changes;
 mod db;
+mod events;
 mod surrealdb;
+mod lan;
 mod license;
 mod server;
-
+mod server_config;
+mod session;
+mod migrations;
+mod openapi;
+mod permissions;
+mod repository;
+mod security;
+mod two_factor;
+mod sync;
+mod transfer;
+mod trash;
+mod tls;
+mod webhooks;
+
+use audit::{AuditActor, AuditEntry, AuditFilter, AuditVerification};
+use changes::{ChangeSource, EntityChanged, LiveQueries, SqliteFeed};
 use db::Database;
+use license::{LicenseGate, LicenseInfo, LicenseState, MachineDiagnosis};
+use migrations::{MigrationReport, SchemaVersion};
+use permissions::{Capability, Role, Session};
+use security::{LoginThrottle, PasswordPolicy};
+use server::ServerAccess;
+use server_config::ServerConfig;
+use session::{CurrentUser, SessionInfo, SessionStore};
+use two_factor::{LoginChallenges, TwoFactorPolicy};
+use webhooks::{DeliveryFilter, Webhook, WebhookDelivery, WebhookInput};
+use api_tokens::IssuedApiToken;
+use repository::{ApiToken, CustomerInput, ListQuery, ProductInput, Repositories, UserInput, UserProfileInput};
 use surrealdb::{SurrealDatabase, DatabaseConfig, ConnectionMode, init_schema};
+use sync::{ConflictSide, SyncConflict, SyncEngine, SyncReport, SyncStatus};
+use transfer::TransferReport;
+use trash::{PurgeReport, RetentionPolicy, TrashBin, TrashedRecord};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use std::sync::Mutex;
-use tauri::{AppHandle, Manager, State};
+use tauri::{AppHandle, Emitter, Manager, State};
 
 // Load environment variables at startup
 fn load_env() {
@@ -26,7 +63,7 @@ pub struct ExecuteResult {
     pub rows_affected: usize,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct PaginatedResponse<T> {
     pub items: Vec<T>,
     pub total: i64,
@@ -79,6 +116,60 @@ fn get_db_path(app: &AppHandle, _db_name: &str) -> Result<PathBuf, String> {
     Ok(db_path)
 }
 
+/// Local SurrealKV store path: the configured offline path, else `db.surreal` next to the SQLite file
+fn get_surreal_path(app: &AppHandle, config: &DatabaseConfig) -> Result<PathBuf, String> {
+    if let Some(path) = config.offline_path.as_ref().filter(|p| !p.is_empty()) {
+        return Ok(PathBuf::from(path));
+    }
+    let sqlite_path = get_db_path(app, "")?;
+    // Older versions kept the SurrealKV store at the SQLite path
+    if sqlite_path.is_dir() {
+        return Ok(sqlite_path);
+    }
+    Ok(sqlite_path.with_file_name("db.surreal"))
+}
+
+/// Repositories for the open backend: SurrealDB while a SurrealDB connection is open, SQLite otherwise
+fn repositories(
+    db_state: &State<'_, Mutex<Option<Database>>>,
+    surreal_state: &State<'_, Mutex<Option<SurrealDatabase>>>,
+) -> Result<Box<dyn Repositories>, String> {
+    let sqlite = db_state.lock().map_err(|e| format!("Lock error: {}", e))?.clone();
+    let surreal = surreal_state.lock().map_err(|e| format!("Lock error: {}", e))?.clone();
+    let mode = surreal.as_ref().map(|db| db.config.mode.clone());
+    repository::select(mode.as_ref(), sqlite, surreal).map_err(|e| e.to_string())
+}
+
+/// Choosing the database is setup, open to anyone while the open store has no users. Once it has
+/// one it takes `DatabaseManage`: anyone could otherwise open an empty store, register as its
+/// first user (an admin) and take that session back to the real one.
+async fn authorize_database_switch(
+    webview: &tauri::Webview,
+    db_state: &State<'_, Mutex<Option<Database>>>,
+    surreal_state: &State<'_, Mutex<Option<SurrealDatabase>>>,
+) -> Result<(), String> {
+    let Ok(repo) = repositories(db_state, surreal_state) else {
+        return Ok(());
+    };
+    let users = repo.count_users(None).await
+        .map_err(|e| format!("Database query error: {}", e))?;
+    if users == 0 {
+        return Ok(());
+    }
+    let current = webview.state::<SessionStore>().current(webview.label(), chrono::Utc::now())
+        .ok_or("Please log in first")?;
+    if !current.capabilities.contains(&Capability::DatabaseManage) {
+        return Err(format!("Role {} lacks the {} capability", current.session.role, Capability::DatabaseManage.name()));
+    }
+    Ok(())
+}
+
+/// Whether `config` is the one saved by `db_configure`, which the login screen opens
+fn is_saved_db_config(config: &DatabaseConfig) -> bool {
+    let saved = get_db_config().ok().flatten();
+    saved.is_some_and(|saved| serde_json::to_value(saved).ok() == serde_json::to_value(config).ok())
+}
+
 /// Get the current database path
 #[tauri::command]
 fn get_database_path(app: AppHandle) -> Result<String, String> {
@@ -101,10 +192,15 @@ fn backup_database(app: AppHandle) -> Result<String, String> {
 
 /// Configure SurrealDB database
 #[tauri::command]
-fn db_configure(
+async fn db_configure(
+    webview: tauri::Webview,
     config: DatabaseConfig,
     config_state: State<'_, Mutex<Option<DatabaseConfig>>>,
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
 ) -> Result<String, String> {
+    authorize_database_switch(&webview, &db_state, &surreal_state).await?;
+
     let mut config_guard = config_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     *config_guard = Some(config.clone());
     drop(config_guard);
@@ -144,21 +240,32 @@ fn get_db_config() -> Result<Option<DatabaseConfig>, String> {
 #[tauri::command]
 async fn db_open_surreal(
     app: AppHandle,
+    webview: tauri::Webview,
     config: DatabaseConfig,
     db_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sqlite_state: State<'_, Mutex<Option<Database>>>,
     config_state: State<'_, Mutex<Option<DatabaseConfig>>>,
+    sessions: State<'_, SessionStore>,
 ) -> Result<String, String> {
+    // The login screen connects to the saved configuration before anyone is logged in
+    if !is_saved_db_config(&config) {
+        authorize_database_switch(&webview, &sqlite_state, &db_state).await?;
+    }
+
     {
         let mut config_guard = config_state.lock().map_err(|e| format!("Lock error: {}", e))?;
         *config_guard = Some(config.clone());
     } // Drop guard before await
     
-    let db_path = get_db_path(&app, "")?;
+    let db_path = get_surreal_path(&app, &config)?;
     let db_path_str = db_path.to_string_lossy().to_string();
     
     let mut db = SurrealDatabase::new(config.clone());
     
     match config.mode {
+        ConnectionMode::Sqlite => {
+            return Err("SQLite mode does not use a SurrealDB connection".to_string());
+        }
         ConnectionMode::Offline => {
             db.connect_offline(db_path).await
                 .map_err(|e| format!("Failed to connect offline: {}", e))?;
@@ -198,20 +305,57 @@ async fn db_open_surreal(
     // Initialize schema
     init_schema(&db).await
         .map_err(|e| format!("Failed to initialize schema: {}", e))?;
+    watch_surreal_changes(&app, &db);
     
     {
         let mut db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
         *db_guard = Some(db);
     } // Drop guard
+    // The sessions belong to the users of the previous store
+    sessions.end_all();
     
     Ok(format!("SurrealDB opened successfully: {}", db_path_str))
 }
 
+/// Emit `entity_changed` for the changes the LIVE queries of `db` report, instead of those of
+/// the connection opened before it
+fn watch_surreal_changes(app: &AppHandle, db: &SurrealDatabase) {
+    let mut tasks = Vec::new();
+    if let Some(offline) = db.get_offline() {
+        let app = app.clone();
+        let task = tauri::async_runtime::spawn(async move {
+            if let Err(e) = changes::watch_surreal(&offline, ChangeSource::Local, |change| emit_change(&app, &change)).await {
+                eprintln!("⚠️ Local SurrealDB changes are no longer watched: {}", e);
+            }
+        });
+        tasks.push(task.inner().abort_handle());
+    }
+    if let Some(online) = db.get_online() {
+        let app = app.clone();
+        let task = tauri::async_runtime::spawn(async move {
+            if let Err(e) = changes::watch_surreal(&online, ChangeSource::Server, |change| emit_change(&app, &change)).await {
+                eprintln!("⚠️ Server changes are no longer watched: {}", e);
+            }
+        });
+        tasks.push(task.inner().abort_handle());
+    }
+    app.state::<LiveQueries>().replace(tasks);
+}
+
+fn emit_change(app: &AppHandle, change: &EntityChanged) {
+    if let Err(e) = app.emit(changes::ENTITY_CHANGED, change) {
+        eprintln!("⚠️ Failed to emit {}: {}", changes::ENTITY_CHANGED, e);
+    }
+}
+
 /// Close SurrealDB database
 #[tauri::command]
 async fn db_close_surreal(
     db_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    live_queries: State<'_, LiveQueries>,
+    sessions: State<'_, SessionStore>,
 ) -> Result<String, String> {
+    live_queries.stop();
     let db = {
         let mut db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
         db_guard.take()
@@ -220,6 +364,8 @@ async fn db_close_surreal(
     if let Some(mut db) = db {
         db.close().await
             .map_err(|e| format!("Failed to close database: {}", e))?;
+        // Commands now reach the SQLite database, which has its own users
+        sessions.end_all();
         Ok("SurrealDB closed successfully".to_string())
     } else {
         Err("No SurrealDB connection is currently open".to_string())
@@ -235,11 +381,12 @@ fn db_is_open_surreal(
     Ok(db_guard.as_ref().map(|db| db.is_offline_connected() || db.is_online_connected()).unwrap_or(false))
 }
 
-/// Execute a SurrealQL query
+/// Execute a SurrealQL query; values are passed in `bindings` and referenced as `$name`
 #[tauri::command]
 async fn db_query_surreal(
     db_state: State<'_, Mutex<Option<SurrealDatabase>>>,
     query: String,
+    bindings: Option<serde_json::Map<String, serde_json::Value>>,
 ) -> Result<QueryResult, String> {
     let db = {
         let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
@@ -247,7 +394,7 @@ async fn db_query_surreal(
     }; // Clone and drop guard before await
     
     // Execute query and get results as JSON values
-    let results: Vec<serde_json::Value> = db.query_json(&query).await
+    let results: Vec<serde_json::Value> = db.query_with(&query, bindings.unwrap_or_default()).await
         .map_err(|e| format!("Query error: {}", e))?;
     
     if results.is_empty() {
@@ -277,18 +424,19 @@ async fn db_query_surreal(
     Ok(QueryResult { columns, rows })
 }
 
-/// Execute a SurrealQL command (CREATE, UPDATE, DELETE)
+/// Execute a SurrealQL command (CREATE, UPDATE, DELETE); values are passed in `bindings` and referenced as `$name`
 #[tauri::command]
 async fn db_execute_surreal(
     db_state: State<'_, Mutex<Option<SurrealDatabase>>>,
     query: String,
+    bindings: Option<serde_json::Map<String, serde_json::Value>>,
 ) -> Result<ExecuteResult, String> {
     let db = {
         let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
         db_guard.as_ref().ok_or("No database is currently open")?.clone()
     }; // Clone and drop guard before await
     
-    db.execute(&query).await
+    db.execute_with(&query, bindings.unwrap_or_default()).await
         .map_err(|e| format!("Execute error: {}", e))?;
     
     // SurrealDB doesn't return rows_affected directly, so we return 1 as a placeholder
@@ -296,23 +444,152 @@ async fn db_execute_surreal(
     Ok(ExecuteResult { rows_affected: 1 })
 }
 
-/// Sync data between offline and online
+/// Sync engine for the open SurrealDB database
+fn sync_engine(db_state: &State<'_, Mutex<Option<SurrealDatabase>>>) -> Result<SyncEngine, String> {
+    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
+    let db = db_guard.as_ref().ok_or("No database is currently open")?;
+    SyncEngine::for_database(db).map_err(|e| e.to_string())
+}
+
+/// Sync data between offline and online: push local changes and pull server changes since the last sync
 #[tauri::command]
 async fn db_sync(
     db_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+) -> Result<SyncReport, String> {
+    let engine = sync_engine(&db_state)?;
+    audit::attributed(actor, engine.sync()).await
+        .map_err(|e| format!("Sync error: {}", e))
+}
+
+/// Pending local and server changes and the number of unresolved conflicts
+#[tauri::command]
+async fn db_sync_status(
+    db_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+) -> Result<SyncStatus, String> {
+    let engine = sync_engine(&db_state)?;
+    engine.status().await
+        .map_err(|e| format!("Failed to get sync status: {}", e))
+}
+
+/// Conflicts waiting for a manual decision
+#[tauri::command]
+async fn db_sync_conflicts(
+    db_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+) -> Result<Vec<SyncConflict>, String> {
+    let engine = sync_engine(&db_state)?;
+    engine.conflicts().await
+        .map_err(|e| format!("Failed to get sync conflicts: {}", e))
+}
+
+/// Resolve a queued conflict by keeping the local or the server version
+#[tauri::command]
+async fn db_sync_resolve_conflict(
+    db_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    table: String,
+    key: serde_json::Value,
+    keep: ConflictSide,
 ) -> Result<String, String> {
-    let db = {
-        let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-        db_guard.as_ref().ok_or("No database is currently open")?.clone()
-    }; // Clone and drop guard before await
-    
-    if db.is_offline_connected() && db.is_online_connected() {
-        // Sync offline to online
-        db.sync_offline_to_online().await
-            .map_err(|e| format!("Sync error: {}", e))?;
-        Ok("Sync completed successfully".to_string())
+    let engine = sync_engine(&db_state)?;
+    audit::attributed(actor, engine.resolve_conflict(&table, &key, keep)).await
+        .map_err(|e| format!("Failed to resolve conflict: {}", e))?;
+    Ok("Conflict resolved".to_string())
+}
+
+/// Open SQLite and SurrealDB databases for a data transfer
+fn transfer_databases(
+    db_state: &State<'_, Mutex<Option<Database>>>,
+    surreal_state: &State<'_, Mutex<Option<SurrealDatabase>>>,
+) -> Result<(Database, SurrealDatabase), String> {
+    let sqlite = db_state.lock().map_err(|e| format!("Lock error: {}", e))?.clone()
+        .ok_or("SQLite database is not open")?;
+    let surreal = surreal_state.lock().map_err(|e| format!("Lock error: {}", e))?.clone()
+        .ok_or("No database is currently open")?;
+    Ok((sqlite, surreal))
+}
+
+/// Copy every table from the SQLite file into SurrealDB.
+/// Resumes after the last copied batch unless `restart` is set.
+#[tauri::command]
+async fn migrate_sqlite_to_surreal(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    restart: Option<bool>,
+) -> Result<TransferReport, String> {
+    let (sqlite, surreal) = transfer_databases(&db_state, &surreal_state)?;
+    audit::attributed(actor, transfer::sqlite_to_surreal(&sqlite, &surreal, restart.unwrap_or(false))).await
+        .map_err(|e| format!("Migration error: {:#}", e))
+}
+
+/// Copy every table from SurrealDB back into the SQLite file.
+/// Resumes after the last copied batch unless `restart` is set.
+#[tauri::command]
+async fn export_surreal_to_sqlite(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    restart: Option<bool>,
+) -> Result<TransferReport, String> {
+    let (sqlite, surreal) = transfer_databases(&db_state, &surreal_state)?;
+    audit::attributed(actor, transfer::surreal_to_sqlite(&surreal, &sqlite, restart.unwrap_or(false))).await
+        .map_err(|e| format!("Export error: {:#}", e))
+}
+
+/// Open the SQLite database at `db_path` and bring its schema up to date
+fn open_sqlite_database(db_path: PathBuf) -> Result<Database, String> {
+    let db = Database::new(db_path);
+    db.open()
+        .map_err(|e| format!("Failed to open database: {}", e))?;
+    ensure_schema(&db)?;
+    Ok(db)
+}
+
+/// Open the default SQLite database at startup so the SQLite commands work without an explicit db_open
+fn open_default_database(app: &AppHandle) -> Result<(), String> {
+    let db_path = get_db_path(app, "")?;
+    let db = open_sqlite_database(db_path)?;
+
+    let db_state: State<'_, Mutex<Option<Database>>> = app.state();
+    let mut db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
+    *db_guard = Some(db);
+    Ok(())
+}
+
+/// Verify that every piece of state a command can ask for has been registered.
+/// A missing `.manage()` otherwise only shows up as a panic when that command is first invoked.
+fn check_managed_state(app: &AppHandle) -> Result<(), String> {
+    let mut missing = Vec::new();
+    if app.try_state::<Mutex<Option<Database>>>().is_none() {
+        missing.push("Mutex<Option<Database>>");
+    }
+    if app.try_state::<Mutex<Option<SurrealDatabase>>>().is_none() {
+        missing.push("Mutex<Option<SurrealDatabase>>");
+    }
+    if app.try_state::<Mutex<Option<DatabaseConfig>>>().is_none() {
+        missing.push("Mutex<Option<DatabaseConfig>>");
+    }
+    if app.try_state::<SessionStore>().is_none() {
+        missing.push("SessionStore");
+    }
+    if app.try_state::<LoginThrottle>().is_none() {
+        missing.push("LoginThrottle");
+    }
+    if app.try_state::<LoginChallenges>().is_none() {
+        missing.push("LoginChallenges");
+    }
+    if app.try_state::<LicenseGate>().is_none() {
+        missing.push("LicenseGate");
+    }
+    if app.try_state::<LiveQueries>().is_none() {
+        missing.push("LiveQueries");
+    }
+
+    if missing.is_empty() {
+        Ok(())
     } else {
-        Err("Both offline and online connections are required for sync".to_string())
+        Err(format!("Missing managed state: {}", missing.join(", ")))
     }
 }
 
@@ -328,19 +605,32 @@ fn db_create(app: AppHandle, _db_name: String) -> Result<String, String> {
 
 /// Open database (creates it automatically if it doesn't exist)
 #[tauri::command]
-fn db_open(app: AppHandle, _db_name: String) -> Result<String, String> {
+async fn db_open(
+    app: AppHandle,
+    webview: tauri::Webview,
+    _db_name: String,
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+) -> Result<String, String> {
+    authorize_database_switch(&webview, &db_state, &surreal_state).await?;
+
     let db_path = get_db_path(&app, "")?;
+    let existed = db_path.exists();
 
-    let db = Database::new(db_path.clone());
-    db.open()
-        .map_err(|e| format!("Failed to open database: {}", e))?;
+    let db = open_sqlite_database(db_path.clone())?;
 
     // Update existing database state
-    let db_state: State<'_, Mutex<Option<Database>>> = app.state();
-    let mut db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    *db_guard = Some(db);
+    {
+        let mut db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
+        if let Some(old_db) = db_guard.take() {
+            let _ = old_db.close();
+        }
+        *db_guard = Some(db);
+    }
+    sessions.end_all();
 
-    if db_path.exists() {
+    if existed {
         Ok(format!("Database opened: {:?}", db_path))
     } else {
         Ok(format!("Database created and opened: {:?}", db_path))
@@ -504,6 +794,44 @@ fn db_query(
     })
 }
 
+/// Bring the SQLite schema up to date (used by the per-table init commands)
+fn ensure_schema(db: &Database) -> Result<(), String> {
+    migrations::run_pending(db)
+        .map_err(|e| format!("Schema migration failed: {:#}", e))?;
+    audit::install_audit_triggers(db)
+        .map_err(|e| format!("Failed to install audit triggers: {:#}", e))
+}
+
+/// Fails unless a database is open. Its schema was brought up to date when it was opened, so the
+/// `init_*_table` commands the frontend still calls have nothing else to do.
+fn ensure_database_open(db_state: &State<'_, Mutex<Option<Database>>>) -> Result<(), String> {
+    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
+    db_guard.as_ref().ok_or("No database is currently open")?;
+    Ok(())
+}
+
+/// Run all pending schema migrations in a single transaction
+#[tauri::command]
+fn db_migrate(db_state: State<'_, Mutex<Option<Database>>>) -> Result<MigrationReport, String> {
+    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
+    let db = db_guard.as_ref().ok_or("No database is currently open")?;
+
+    let report = migrations::run_pending(db).map_err(|e| format!("Schema migration failed: {:#}", e))?;
+    // New columns have to be picked up by the audit triggers
+    audit::install_audit_triggers(db)
+        .map_err(|e| format!("Failed to install audit triggers: {:#}", e))?;
+    Ok(report)
+}
+
+/// Get the current schema version and pending migrations
+#[tauri::command]
+fn db_schema_version(db_state: State<'_, Mutex<Option<Database>>>) -> Result<SchemaVersion, String> {
+    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
+    let db = db_guard.as_ref().ok_or("No database is currently open")?;
+
+    migrations::schema_version(db).map_err(|e| format!("Failed to read schema version: {}", e))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: i64, // Numeric ID extracted from SurrealDB record ID
@@ -517,300 +845,1191 @@ pub struct User {
     pub updated_at: String,
 }
 
-// Helper to convert SurrealDB record to User
-fn record_to_user(record: &serde_json::Value) -> Result<User, String> {
-    // Extract numeric ID from record ID (e.g., "users:123" -> 123)
-    // SurrealDB returns id as a record identifier string
-    let id = if let Some(id_val) = record.get("id") {
-        if let Some(id_str) = id_val.as_str() {
-            // Extract numeric part from "users:123" format
-            id_str.split(':').last()
-                .and_then(|n| n.parse::<i64>().ok())
-                .unwrap_or(0)
-        } else if let Some(id_num) = id_val.as_i64() {
-            id_num
-        } else {
-            0
-        }
-    } else {
-        0
-    };
-    
-    Ok(User {
-        id,
-        username: record.get("username")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string())
-            .ok_or("Missing username")?,
-        email: record.get("email")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string())
-            .ok_or("Missing email")?,
-        full_name: record.get("full_name")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string()),
-        phone: record.get("phone")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string()),
-        role: record.get("role")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| "user".to_string()),
-        is_active: record.get("is_active")
-            .and_then(|v| v.as_i64())
-            .unwrap_or(1),
-        created_at: record.get("created_at")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string()),
-        updated_at: record.get("updated_at")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string()),
-    })
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LoginResult {
     pub success: bool,
     pub user: Option<User>,
     pub message: String,
+    /// What the logged-in user's role may do (empty unless `success`)
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+    /// Opaque session token, only returned by a successful login
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub session: Option<SessionInfo>,
+    /// The user has to choose a new password before doing anything else
+    #[serde(default)]
+    pub must_change_password: bool,
+    /// Seconds to wait before the next attempt, after too many failed logins
+    #[serde(default)]
+    pub retry_after_seconds: Option<i64>,
+    /// Set when the password was right but a two-factor code is needed; pass it to `verify_two_factor`
+    #[serde(default)]
+    pub two_factor_challenge: Option<String>,
+    /// The user's role requires two-factor authentication, which they have to set up first
+    #[serde(default)]
+    pub must_enroll_two_factor: bool,
+}
+
+impl LoginResult {
+    fn failure(message: &str) -> Self {
+        LoginResult {
+            success: false,
+            user: None,
+            message: message.to_string(),
+            capabilities: Vec::new(),
+            token: None,
+            session: None,
+            must_change_password: false,
+            retry_after_seconds: None,
+            two_factor_challenge: None,
+            must_enroll_two_factor: false,
+        }
+    }
+
+    fn retry_after(message: &str, wait: chrono::TimeDelta) -> Self {
+        LoginResult {
+            // Round up so waiting the full time always works
+            retry_after_seconds: Some((wait.num_milliseconds() + 999) / 1000),
+            ..LoginResult::failure(message)
+        }
+    }
 }
 
-/// Initialize users table schema (SurrealDB - schema is already defined in surreal_schema.surql)
+/// Initialize users table schema. Both backends create it when they are opened, so this is public
+/// (the login screen calls it) and touches no database; it is kept for backward compatibility.
 #[tauri::command]
-async fn init_users_table(db_state: State<'_, Mutex<Option<SurrealDatabase>>>) -> Result<String, String> {
-    let db = {
-        let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-        db_guard.as_ref().ok_or("No database is currently open")?.clone()
-    }; // Clone and drop guard before await
-    
-    // Schema is already initialized in init_schema, so we just verify it exists
-    // This function is kept for backward compatibility
+fn init_users_table() -> Result<String, String> {
     Ok("Users table schema already initialized".to_string())
 }
 
-/// Register a new user (SurrealDB)
+/// Register a new user
 #[tauri::command]
 async fn register_user(
-    db_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    gate: State<'_, LicenseGate>,
+    actor: AuditActor,
     username: String,
     email: String,
     password: String,
 ) -> Result<LoginResult, String> {
-    let db = {
-        let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-        db_guard.as_ref().ok_or("No database is currently open")?.clone()
-    }; // Clone and drop guard before await
+    let repo = repositories(&db_state, &surreal_state)?;
+
+    let policy = security::password_policy(repo.as_ref()).await
+        .map_err(|e| format!("Failed to load password policy: {}", e))?;
+    if let Err(e) = policy.check(&password, &username) {
+        return Ok(LoginResult::failure(&e.to_string()));
+    }
 
     // Hash the password
     let password_hash = bcrypt::hash(&password, bcrypt::DEFAULT_COST)
         .map_err(|e| format!("Failed to hash password: {}", e))?;
 
     // Check if username or email already exists
-    let check_query = format!("SELECT id FROM users WHERE username = '{}' OR email = '{}'", username, email);
-    let existing: Vec<serde_json::Value> = db.query_json(&check_query).await
+    let exists = repo.user_exists(&username, &email).await
         .map_err(|e| format!("Database query error: {}", e))?;
 
-    if !existing.is_empty() {
-        return Ok(LoginResult {
-            success: false,
-            user: None,
-            message: "Username or email already exists".to_string(),
-        });
+    if exists {
+        return Ok(LoginResult::failure("Username or email already exists"));
+    }
+    if let Err(e) = check_seat_available(repo.as_ref(), &gate).await {
+        return Ok(LoginResult::failure(&e));
     }
 
-    // Create new user with SurrealQL
-    // Use parameterized query to avoid SQL injection
-    let create_query = format!(
-        "CREATE users SET username = '{}', email = '{}', password_hash = '{}', role = 'user', is_active = 1, created_at = time::now(), updated_at = time::now()",
-        username.replace("'", "''"),
-        email.replace("'", "''"),
-        password_hash.replace("'", "''")
-    );
-    db.execute(&create_query).await
+    // The first user administers the app; later sign-ups can only read until an admin promotes them
+    let existing = repo.count_users(None).await
+        .map_err(|e| format!("Database query error: {}", e))?;
+    let role = if existing == 0 { permissions::ADMIN_ROLE } else { permissions::DEFAULT_ROLE };
+
+    let input = UserInput {
+        username,
+        email,
+        password_hash,
+        full_name: None,
+        phone: None,
+        role: role.to_string(),
+        must_change_password: false,
+    };
+    let user = audit::attributed(actor, repo.create_user(&input)).await
         .map_err(|e| format!("Failed to create user: {}", e))?;
 
-    // Get the created user
-    let user_query = format!("SELECT * FROM users WHERE username = '{}'", username.replace("'", "''"));
-    let user_records: Vec<serde_json::Value> = db.query_json(&user_query).await
-        .map_err(|e| format!("Failed to fetch user: {}", e))?;
-
-    if let Some(record) = user_records.first() {
-        let user = record_to_user(record)?;
-        Ok(LoginResult {
-            success: true,
-            user: Some(user),
-            message: "User registered successfully".to_string(),
-        })
-    } else {
-        Err("Failed to retrieve created user".to_string())
-    }
+    Ok(LoginResult {
+        success: true,
+        user: Some(user),
+        message: "User registered successfully".to_string(),
+        ..LoginResult::failure("")
+    })
 }
 
-/// Login a user (SurrealDB)
+/// Login a user by username or email, opening a session for the calling window
 #[tauri::command]
-async fn login_user(
-    db_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+async fn login_user(webview: tauri::Webview, username: String, password: String) -> Result<LoginResult, String> {
+    login_user_internal(&webview, webview.label(), webview.label(), username, password).await
+}
+
+/// Login from `device`, which failed attempts are throttled by, opening the session for `client`:
+/// both a window's label, or a LAN browser's address and login id (see `lan`)
+pub(crate) async fn login_user_internal(
+    manager: &impl Manager<tauri::Wry>,
+    device: &str,
+    client: &str,
     username: String,
     password: String,
 ) -> Result<LoginResult, String> {
-    let db = {
-        let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-        db_guard.as_ref().ok_or("No database is currently open")?.clone()
-    }; // Clone and drop guard before await
+    let db_state = manager.state::<Mutex<Option<Database>>>();
+    let surreal_state = manager.state::<Mutex<Option<SurrealDatabase>>>();
+    let sessions = manager.state::<SessionStore>();
+    let throttle = manager.state::<LoginThrottle>();
+    let challenges = manager.state::<LoginChallenges>();
+    let now = chrono::Utc::now();
+    if let Some(wait) = throttle.wait(device, now) {
+        let message = format!("Too many failed attempts, try again in {} seconds", wait.num_seconds().max(1));
+        return Ok(LoginResult::retry_after(&message, wait));
+    }
 
-    // Get user by username or email using SurrealQL
-    let escaped_username = username.replace("'", "''");
-    let user_query = format!(
-        "SELECT * FROM users WHERE username = '{}' OR email = '{}'",
-        escaped_username, escaped_username
-    );
-    
-    let user_records: Vec<serde_json::Value> = db.query_json(&user_query).await
+    let repo = repositories(&db_state, &surreal_state)?;
+    let found = repo.find_login(&username).await
         .map_err(|e| format!("Database query error: {}", e))?;
 
-    if user_records.is_empty() {
-        return Ok(LoginResult {
-            success: false,
-            user: None,
-            message: "Invalid username or password".to_string(),
-        });
-    }
+    let Some(record) = found else {
+        throttle.failed(device, now);
+        return Ok(LoginResult::failure("Invalid username or password"));
+    };
 
-    let record = &user_records[0];
-    
-    // Get password hash from the record
-    let password_hash = record.get("password_hash")
-        .and_then(|v| v.as_str())
-        .ok_or("Failed to get password hash")?;
+    // A locked account is refused before the password is even checked
+    if let Some(locked_until) = record.locked_until.filter(|until| *until > now) {
+        return Ok(LoginResult::retry_after(&locked_message(locked_until), locked_until - now));
+    }
 
     // Verify password
-    let password_valid = bcrypt::verify(&password, password_hash)
+    let password_valid = bcrypt::verify(&password, &record.password_hash)
         .map_err(|e| format!("Password verification error: {}", e))?;
 
     if !password_valid {
+        throttle.failed(device, now);
+        let failed_logins = record.failed_logins + 1;
+        let locked_until = security::lockout_until(failed_logins, now);
+        repo.set_login_failures(record.user.id, failed_logins, locked_until).await
+            .map_err(|e| format!("Failed to record login attempt: {}", e))?;
+        return Ok(match locked_until {
+            Some(until) => LoginResult::retry_after(&locked_message(until), until - now),
+            None => LoginResult::failure("Invalid username or password"),
+        });
+    }
+
+    throttle.succeeded(device);
+    if record.user.is_active == 0 {
+        return Ok(LoginResult::failure("This account has been disabled, please contact an administrator"));
+    }
+    if record.failed_logins > 0 || record.locked_until.is_some() {
+        repo.set_login_failures(record.user.id, 0, None).await
+            .map_err(|e| format!("Failed to record login attempt: {}", e))?;
+    }
+
+    let two_factor = repo.get_two_factor(record.user.id).await
+        .map_err(|e| format!("Database query error: {}", e))?;
+    if two_factor.enabled {
         return Ok(LoginResult {
-            success: false,
-            user: None,
-            message: "Invalid username or password".to_string(),
+            two_factor_challenge: Some(challenges.start(record.user.id, device, now)),
+            ..LoginResult::failure("Enter the code from your authenticator app or a recovery code")
         });
     }
 
-    // Convert record to User
-    let user = record_to_user(record)?;
+    open_session(repo.as_ref(), &sessions, record.user, record.must_change_password, false, client, now).await
+}
+
+/// Start a session for a user who passed every login step
+async fn open_session(
+    repo: &dyn Repositories,
+    sessions: &SessionStore,
+    user: User,
+    must_change_password: bool,
+    two_factor_enabled: bool,
+    client: &str,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Result<LoginResult, String> {
+    let capabilities = permissions::role_capabilities(repo, &user.role).await
+        .map_err(|e| format!("Failed to load role: {}", e))?;
+    let policy = two_factor::two_factor_policy(repo).await
+        .map_err(|e| format!("Failed to load two-factor policy: {}", e))?;
+    let must_enroll_two_factor = !two_factor_enabled && policy.requires(&user.role);
+    let principal = Session {
+        user_id: user.id,
+        username: user.username.clone(),
+        role: user.role.clone(),
+        capabilities: capabilities.clone(),
+        must_change_password,
+        must_enroll_two_factor,
+    };
+    let (token, session) = sessions.start(principal, client, now);
 
+    let message = if must_change_password {
+        "Login successful, please choose a new password"
+    } else if must_enroll_two_factor {
+        "Login successful, please set up two-factor authentication"
+    } else {
+        "Login successful"
+    };
     Ok(LoginResult {
         success: true,
         user: Some(user),
-        message: "Login successful".to_string(),
+        capabilities: capabilities.into_iter().collect(),
+        token: Some(token),
+        session: Some(session),
+        must_change_password,
+        must_enroll_two_factor,
+        ..LoginResult::failure(message)
     })
 }
 
-/// Get all users with pagination
+/// Second login step: check a TOTP or recovery code against the challenge `login_user` returned
 #[tauri::command]
-fn get_users(
-    db_state: State<'_, Mutex<Option<Database>>>,
-    page: i64,
-    per_page: i64,
-    search: Option<String>,
-    sort_by: Option<String>,
-    sort_order: Option<String>,
-) -> Result<PaginatedResponse<User>, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
+async fn verify_two_factor(webview: tauri::Webview, challenge: String, code: String) -> Result<LoginResult, String> {
+    verify_two_factor_internal(&webview, webview.label(), webview.label(), challenge, code).await
+}
 
-    let offset = (page - 1) * per_page;
-    
-    // Build WHERE clause
-    let mut where_clause = String::new();
-    let mut params: Vec<serde_json::Value> = Vec::new();
+/// Second login step from `device`, which must be the one that started the login, opening
+/// the session for `client`
+pub(crate) async fn verify_two_factor_internal(
+    manager: &impl Manager<tauri::Wry>,
+    device: &str,
+    client: &str,
+    challenge: String,
+    code: String,
+) -> Result<LoginResult, String> {
+    let db_state = manager.state::<Mutex<Option<Database>>>();
+    let surreal_state = manager.state::<Mutex<Option<SurrealDatabase>>>();
+    let sessions = manager.state::<SessionStore>();
+    let throttle = manager.state::<LoginThrottle>();
+    let challenges = manager.state::<LoginChallenges>();
+    let now = chrono::Utc::now();
+    if let Some(wait) = throttle.wait(device, now) {
+        let message = format!("Too many failed attempts, try again in {} seconds", wait.num_seconds().max(1));
+        return Ok(LoginResult::retry_after(&message, wait));
+    }
+    let Some(user_id) = challenges.user(&challenge, device, now) else {
+        return Ok(LoginResult::failure("The login has expired, please log in again"));
+    };
 
-    if let Some(s) = search {
-        if !s.trim().is_empty() {
-            let search_term = format!("%{}%", s);
-            where_clause = "WHERE (username LIKE ? OR email LIKE ? OR full_name LIKE ? OR phone LIKE ?)".to_string();
-            params.push(serde_json::Value::String(search_term.clone()));
-            params.push(serde_json::Value::String(search_term.clone()));
-            params.push(serde_json::Value::String(search_term.clone()));
-            params.push(serde_json::Value::String(search_term));
-        }
+    let repo = repositories(&db_state, &surreal_state)?;
+    let mut two_factor = repo.get_two_factor(user_id).await
+        .map_err(|e| format!("Database query error: {}", e))?;
+    let secret = two_factor.secret.clone().unwrap_or_default();
+    let step = two_factor::verify_code(&secret, &code, now, two_factor.last_step)
+        .map_err(|e| e.to_string())?;
+    let used_recovery_code = step.is_none() && two_factor::use_recovery_code(&mut two_factor, &code);
+    if step.is_none() && !used_recovery_code {
+        throttle.failed(device, now);
+        return Ok(if challenges.failed(&challenge) {
+            LoginResult { two_factor_challenge: Some(challenge), ..LoginResult::failure("Invalid code") }
+        } else {
+            LoginResult::failure("Too many invalid codes, please log in again")
+        });
+    }
+    if step.is_some() {
+        two_factor.last_step = step;
     }
+    repo.save_two_factor(user_id, &two_factor).await
+        .map_err(|e| format!("Failed to save two-factor state: {}", e))?;
+    challenges.finish(&challenge);
+    throttle.succeeded(device);
+
+    let user = repo.get_user(user_id).await
+        .map_err(|e| format!("Database query error: {}", e))?
+        .ok_or("User not found")?;
+    let record = repo.find_login(&user.username).await
+        .map_err(|e| format!("Database query error: {}", e))?
+        .ok_or("User not found")?;
+    let mut result = open_session(repo.as_ref(), &sessions, user, record.must_change_password, true, client, now).await?;
+    if used_recovery_code {
+        result.message = format!("Login successful, {} recovery codes left", two_factor.recovery_codes.len());
+    }
+    Ok(result)
+}
 
-    // Get total count
-    let count_sql = format!("SELECT COUNT(*) FROM users {}", where_clause);
-    let total: i64 = db.with_connection(|conn| {
-        let mut stmt = conn.prepare(&count_sql).map_err(|e| anyhow::anyhow!("{}", e))?;
-        let rusqlite_params: Vec<rusqlite::types::Value> = params.iter().map(|v| {
-            match v {
-                serde_json::Value::String(s) => rusqlite::types::Value::Text(s.clone()),
-                _ => rusqlite::types::Value::Null,
-            }
-        }).collect();
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    /// The user's role requires two-factor authentication
+    pub required: bool,
+    pub recovery_codes_left: usize,
+}
 
-        let count: i64 = stmt.query_row(rusqlite::params_from_iter(rusqlite_params.iter()), |row| row.get(0))
-             .map_err(|e| anyhow::anyhow!("{}", e))?;
-        Ok(count)
-    }).map_err(|e| format!("Failed to count users: {}", e))?;
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorEnrollment {
+    /// Base32 secret, for typing into an authenticator app by hand
+    pub secret: String,
+    /// `otpauth://` URI to show as a QR code
+    pub uri: String,
+}
 
-    // Build Order By
-    let order_clause = if let Some(sort) = sort_by {
-        let order = sort_order.unwrap_or_else(|| "ASC".to_string());
-        let allowed_cols = ["username", "email", "full_name", "phone", "role", "is_active", "created_at"];
-        if allowed_cols.contains(&sort.as_str()) {
-             format!("ORDER BY {} {}", sort, if order.to_uppercase() == "DESC" { "DESC" } else { "ASC" })
-        } else {
-            "ORDER BY created_at DESC".to_string()
-        }
-    } else {
-        "ORDER BY created_at DESC".to_string()
-    };
+/// Whether the logged-in user has two-factor authentication
+#[tauri::command]
+async fn get_two_factor_status(
+    webview: tauri::Webview,
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+) -> Result<TwoFactorStatus, String> {
+    let current = sessions.current(webview.label(), chrono::Utc::now())
+        .ok_or("Your session has expired, please log in again")?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    let two_factor = repo.get_two_factor(current.session.user_id).await
+        .map_err(|e| format!("Database query error: {}", e))?;
+    let policy = two_factor::two_factor_policy(repo.as_ref()).await
+        .map_err(|e| format!("Failed to load two-factor policy: {}", e))?;
+    Ok(TwoFactorStatus {
+        enabled: two_factor.enabled,
+        required: policy.requires(&current.session.role),
+        recovery_codes_left: two_factor.recovery_codes.len(),
+    })
+}
 
-    let sql = format!("SELECT id, username, email, full_name, phone, role, is_active, created_at, updated_at FROM users {} {} LIMIT ? OFFSET ?", where_clause, order_clause);
-    
-    params.push(serde_json::Value::Number(serde_json::Number::from(per_page)));
-    params.push(serde_json::Value::Number(serde_json::Number::from(offset)));
+/// Generate a new secret for the logged-in user; it is only enabled once a code from it is confirmed
+#[tauri::command]
+async fn begin_two_factor_enrollment(
+    webview: tauri::Webview,
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+) -> Result<TwoFactorEnrollment, String> {
+    let current = sessions.current(webview.label(), chrono::Utc::now())
+        .ok_or("Your session has expired, please log in again")?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    let two_factor = repo.get_two_factor(current.session.user_id).await
+        .map_err(|e| format!("Database query error: {}", e))?;
+    if two_factor.enabled {
+        return Err("Two-factor authentication is already enabled".to_string());
+    }
 
-    let users = db.with_connection(|conn| {
-        let mut stmt = conn.prepare(&sql).map_err(|e| anyhow::anyhow!("{}", e))?;
-        let rusqlite_params: Vec<rusqlite::types::Value> = params.iter().map(|v| {
-            match v {
-                serde_json::Value::String(s) => rusqlite::types::Value::Text(s.clone()),
-                serde_json::Value::Number(n) => rusqlite::types::Value::Integer(n.as_i64().unwrap_or(0)),
-                _ => rusqlite::types::Value::Null,
-            }
-        }).collect();
+    let secret = two_factor::new_secret();
+    let pending = repository::TwoFactorRecord { secret: Some(secret.clone()), ..Default::default() };
+    repo.save_two_factor(current.session.user_id, &pending).await
+        .map_err(|e| format!("Failed to save two-factor state: {}", e))?;
+    Ok(TwoFactorEnrollment {
+        uri: two_factor::otpauth_uri(&secret, &current.session.username),
+        secret,
+    })
+}
 
-        let rows = stmt.query_map(rusqlite::params_from_iter(rusqlite_params.iter()), |row| {
-             Ok(User {
-                id: row.get(0)?,
-                username: row.get(1)?,
-                email: row.get(2)?,
-                full_name: row.get(3)?,
-                phone: row.get(4)?,
-                role: row.get(5)?,
-                is_active: row.get(6)?,
-                created_at: row.get(7)?,
-                updated_at: row.get(8)?,
-            })
-        }).map_err(|e| anyhow::anyhow!("{}", e))?;
+/// Enable two-factor authentication with a code from the new secret; returns the recovery codes, shown only once
+#[tauri::command]
+async fn confirm_two_factor_enrollment(
+    webview: tauri::Webview,
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    actor: AuditActor,
+    code: String,
+) -> Result<Vec<String>, String> {
+    let now = chrono::Utc::now();
+    let current = sessions.current(webview.label(), now)
+        .ok_or("Your session has expired, please log in again")?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    let mut two_factor = repo.get_two_factor(current.session.user_id).await
+        .map_err(|e| format!("Database query error: {}", e))?;
+    let Some(secret) = two_factor.secret.clone().filter(|_| !two_factor.enabled) else {
+        return Err("Start two-factor setup first".to_string());
+    };
+    let step = two_factor::verify_code(&secret, &code, now, None)
+        .map_err(|e| e.to_string())?
+        .ok_or("Invalid code, check the time on this computer and your phone")?;
 
-        let mut results = Vec::new();
-        for row in rows {
-            results.push(row.map_err(|e| anyhow::anyhow!("{}", e))?);
+    let (codes, hashes) = two_factor::new_recovery_codes();
+    two_factor.enabled = true;
+    two_factor.last_step = Some(step);
+    two_factor.recovery_codes = hashes;
+    audit::attributed(actor, repo.save_two_factor(current.session.user_id, &two_factor)).await
+        .map_err(|e| format!("Failed to save two-factor state: {}", e))?;
+    sessions.two_factor_enrolled(webview.label());
+    Ok(codes)
+}
+
+/// Turn off two-factor authentication for the logged-in user, unless their role requires it
+#[tauri::command]
+async fn disable_two_factor(
+    webview: tauri::Webview,
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    actor: AuditActor,
+) -> Result<(), String> {
+    let current = sessions.current(webview.label(), chrono::Utc::now())
+        .ok_or("Your session has expired, please log in again")?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    let policy = two_factor::two_factor_policy(repo.as_ref()).await
+        .map_err(|e| format!("Failed to load two-factor policy: {}", e))?;
+    if policy.requires(&current.session.role) {
+        return Err(format!("Two-factor authentication is required for the {} role", current.session.role));
+    }
+    audit::attributed(actor, repo.save_two_factor(current.session.user_id, &Default::default())).await
+        .map_err(|e| format!("Failed to save two-factor state: {}", e))
+}
+
+/// Replace the logged-in user's recovery codes; the old ones stop working
+#[tauri::command]
+async fn regenerate_recovery_codes(
+    webview: tauri::Webview,
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    actor: AuditActor,
+) -> Result<Vec<String>, String> {
+    let current = sessions.current(webview.label(), chrono::Utc::now())
+        .ok_or("Your session has expired, please log in again")?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    let mut two_factor = repo.get_two_factor(current.session.user_id).await
+        .map_err(|e| format!("Database query error: {}", e))?;
+    if !two_factor.enabled {
+        return Err("Two-factor authentication isn't enabled".to_string());
+    }
+    let (codes, hashes) = two_factor::new_recovery_codes();
+    two_factor.recovery_codes = hashes;
+    audit::attributed(actor, repo.save_two_factor(current.session.user_id, &two_factor)).await
+        .map_err(|e| format!("Failed to save two-factor state: {}", e))?;
+    Ok(codes)
+}
+
+/// Remove a user's two-factor authentication, e.g. after a lost phone; they set it up again at their next login if required
+#[tauri::command]
+async fn reset_two_factor(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    user_id: i64,
+) -> Result<(), String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    existing_user(repo.as_ref(), user_id).await?;
+    audit::attributed(actor, repo.save_two_factor(user_id, &Default::default())).await
+        .map_err(|e| format!("Failed to reset two-factor authentication: {}", e))
+}
+
+/// Get the roles that must use two-factor authentication
+#[tauri::command]
+async fn get_two_factor_policy(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+) -> Result<TwoFactorPolicy, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    two_factor::two_factor_policy(repo.as_ref()).await
+        .map_err(|e| format!("Failed to load two-factor policy: {}", e))
+}
+
+/// Change the roles that must use two-factor authentication; their users set it up at their next login
+#[tauri::command]
+async fn update_two_factor_policy(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    policy: TwoFactorPolicy,
+) -> Result<TwoFactorPolicy, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    for role in &policy.required_roles {
+        let exists = permissions::role_exists(repo.as_ref(), role).await
+            .map_err(|e| format!("Failed to load role: {}", e))?;
+        if !exists {
+            return Err(format!("Role {} doesn't exist", role));
         }
-        Ok(results)
-    }).map_err(|e| format!("Failed to fetch users: {}", e))?;
+    }
+    audit::attributed(actor, two_factor::save_two_factor_policy(repo.as_ref(), &policy)).await
+        .map_err(|e| format!("Failed to save two-factor policy: {}", e))?;
+    Ok(policy)
+}
 
-    let total_pages = (total as f64 / per_page as f64).ceil() as i64;
+fn locked_message(until: chrono::DateTime<chrono::Utc>) -> String {
+    format!(
+        "Account is locked after too many failed attempts, try again after {}",
+        until.with_timezone(&chrono::Local).format("%H:%M")
+    )
+}
 
-    Ok(PaginatedResponse {
-        items: users,
-        total,
-        page,
-        per_page,
-        total_pages,
+/// Change the current user's password; this also completes a forced password change
+#[tauri::command]
+async fn change_password(
+    webview: tauri::Webview,
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    actor: AuditActor,
+    current_password: String,
+    new_password: String,
+) -> Result<(), String> {
+    let current = sessions.current(webview.label(), chrono::Utc::now())
+        .ok_or("Your session has expired, please log in again")?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    let record = repo.find_login(&current.session.username).await
+        .map_err(|e| format!("Database query error: {}", e))?
+        .ok_or("User not found")?;
+
+    let password_valid = bcrypt::verify(&current_password, &record.password_hash)
+        .map_err(|e| format!("Password verification error: {}", e))?;
+    if !password_valid {
+        return Err("Current password is incorrect".to_string());
+    }
+
+    let policy = security::password_policy(repo.as_ref()).await
+        .map_err(|e| format!("Failed to load password policy: {}", e))?;
+    security::check_new_password(
+        repo.as_ref(),
+        &policy,
+        record.user.id,
+        &record.user.username,
+        &record.password_hash,
+        &new_password,
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let password_hash = bcrypt::hash(&new_password, bcrypt::DEFAULT_COST)
+        .map_err(|e| format!("Failed to hash password: {}", e))?;
+    audit::attributed(actor, repo.set_password(record.user.id, &password_hash, false)).await
+        .map_err(|e| format!("Failed to change password: {}", e))?;
+
+    sessions.password_changed(webview.label(), chrono::Utc::now());
+    Ok(())
+}
+
+/// Clear a user's failed logins and lift any lockout
+#[tauri::command]
+async fn unlock_user(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    user_id: i64,
+) -> Result<(), String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    audit::attributed(actor, repo.set_login_failures(user_id, 0, None)).await
+        .map_err(|e| format!("Failed to unlock user: {}", e))
+}
+
+/// Get the rules new passwords must follow
+#[tauri::command]
+async fn get_password_policy(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+) -> Result<PasswordPolicy, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    security::password_policy(repo.as_ref()).await
+        .map_err(|e| format!("Failed to load password policy: {}", e))
+}
+
+/// Change the rules new passwords must follow; existing passwords are kept
+#[tauri::command]
+async fn update_password_policy(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    policy: PasswordPolicy,
+) -> Result<PasswordPolicy, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    audit::attributed(actor, security::save_password_policy(repo.as_ref(), &policy)).await
+        .map_err(|e| format!("Failed to save password policy: {}", e))?;
+    Ok(policy)
+}
+
+/// End the calling window's session
+#[tauri::command]
+fn logout_user(webview: tauri::Webview, sessions: State<'_, SessionStore>) -> bool {
+    sessions.end(webview.label())
+}
+
+/// Get the user logged in to the calling window, if any
+#[tauri::command]
+fn get_current_user(webview: tauri::Webview, sessions: State<'_, SessionStore>) -> Option<CurrentUser> {
+    sessions.current(webview.label(), chrono::Utc::now())
+}
+
+/// Confirm the current user's password, unlocking sensitive commands for a few minutes
+#[tauri::command]
+async fn reauthenticate(
+    webview: tauri::Webview,
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    password: String,
+) -> Result<bool, String> {
+    let current = sessions.current(webview.label(), chrono::Utc::now())
+        .ok_or("Your session has expired, please log in again")?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    let found = repo.find_login(&current.session.username).await
+        .map_err(|e| format!("Database query error: {}", e))?;
+    let Some(record) = found else {
+        return Ok(false);
+    };
+
+    let password_valid = bcrypt::verify(&password, &record.password_hash)
+        .map_err(|e| format!("Password verification error: {}", e))?;
+    Ok(password_valid && sessions.reauthenticated(webview.label(), chrono::Utc::now()))
+}
+
+/// List every open session
+#[tauri::command]
+fn list_sessions(webview: tauri::Webview, sessions: State<'_, SessionStore>) -> Vec<SessionInfo> {
+    sessions.list(webview.label(), chrono::Utc::now())
+}
+
+/// End another session by its id
+#[tauri::command]
+fn revoke_session(sessions: State<'_, SessionStore>, id: String) -> Result<(), String> {
+    if sessions.revoke(&id) {
+        Ok(())
+    } else {
+        Err("Session not found".to_string())
+    }
+}
+
+/// Create a REST API token acting as the logged-in user; the token is only returned this once
+#[tauri::command]
+async fn create_api_token(
+    webview: tauri::Webview,
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    name: String,
+    expires_in_days: Option<i64>,
+) -> Result<IssuedApiToken, String> {
+    let user_id = current_user_id(&sessions, &webview).ok_or("Your session has expired, please log in again")?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    api_tokens::issue(repo.as_ref(), user_id, &name, expires_in_days, chrono::Utc::now()).await
+        .map_err(|e| format!("Failed to create API token: {}", e))
+}
+
+/// Whether the logged-in user may see and revoke `user_id`'s API tokens: their own, or anyone's with users.manage
+fn manages_api_tokens_of(current: &CurrentUser, user_id: i64) -> bool {
+    current.session.user_id == user_id || current.capabilities.contains(&Capability::UsersManage)
+}
+
+/// List the API tokens of the logged-in user, or of `user_id`
+#[tauri::command]
+async fn get_api_tokens(
+    webview: tauri::Webview,
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    user_id: Option<i64>,
+) -> Result<Vec<ApiToken>, String> {
+    let current = sessions.current(webview.label(), chrono::Utc::now())
+        .ok_or("Your session has expired, please log in again")?;
+    let user_id = user_id.unwrap_or(current.session.user_id);
+    if !manages_api_tokens_of(&current, user_id) {
+        return Err("You can only list your own API tokens".to_string());
+    }
+    let repo = repositories(&db_state, &surreal_state)?;
+    repo.list_api_tokens(Some(user_id)).await
+        .map_err(|e| format!("Failed to fetch API tokens: {}", e))
+}
+
+/// Revoke an API token; requests using it are refused from now on
+#[tauri::command]
+async fn revoke_api_token(
+    webview: tauri::Webview,
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    id: i64,
+) -> Result<(), String> {
+    let current = sessions.current(webview.label(), chrono::Utc::now())
+        .ok_or("Your session has expired, please log in again")?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    let token = repo.get_api_token(id).await
+        .map_err(|e| format!("Failed to fetch API token: {}", e))?
+        .filter(|token| manages_api_tokens_of(&current, token.user_id))
+        .ok_or("API token not found")?;
+    repo.delete_api_token(token.id).await
+        .map_err(|e| format!("Failed to revoke API token: {}", e))
+}
+
+/// Get all users with pagination
+#[tauri::command]
+async fn get_users(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    page: i64,
+    per_page: i64,
+    search: Option<String>,
+    sort_by: Option<String>,
+    sort_order: Option<String>,
+) -> Result<PaginatedResponse<User>, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    let query = ListQuery { page, per_page, search, sort_by, sort_order };
+    repo.list_users(&query).await
+        .map_err(|e| format!("Failed to fetch users: {}", e))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserStats {
+    pub total: i64,
+    pub active: i64,
+    pub inactive: i64,
+    pub admins: i64,
+}
+
+/// Trim a profile and make sure its username and email are valid and not used by another user
+async fn check_profile(repo: &dyn Repositories, user_id: Option<i64>, profile: UserProfileInput) -> Result<UserProfileInput, String> {
+    let blank_to_none = |value: Option<String>| value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+    let profile = UserProfileInput {
+        username: profile.username.trim().to_string(),
+        email: profile.email.trim().to_string(),
+        full_name: blank_to_none(profile.full_name),
+        phone: blank_to_none(profile.phone),
+    };
+    if profile.username.is_empty() {
+        return Err("Username is required".to_string());
+    }
+    if !profile.email.contains('@') {
+        return Err("A valid email is required".to_string());
+    }
+    for login in [&profile.username, &profile.email] {
+        let existing = repo.find_login(login).await
+            .map_err(|e| format!("Database query error: {}", e))?;
+        if existing.is_some_and(|record| Some(record.user.id) != user_id) {
+            return Err("Username or email already exists".to_string());
+        }
+    }
+    Ok(profile)
+}
+
+async fn existing_user(repo: &dyn Repositories, id: i64) -> Result<User, String> {
+    repo.get_user(id).await
+        .map_err(|e| format!("Failed to fetch user: {}", e))?
+        .ok_or_else(|| "User not found".to_string())
+}
+
+/// Get a single user by ID
+#[tauri::command]
+async fn get_user(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    id: i64,
+) -> Result<User, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    existing_user(repo.as_ref(), id).await
+}
+
+/// Count users by status, and how many are admins
+#[tauri::command]
+async fn get_user_stats(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+) -> Result<UserStats, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    let count = |result: anyhow::Result<i64>| result.map_err(|e| format!("Failed to count users: {}", e));
+    let total = count(repo.count_users(None).await)?;
+    let active = count(repo.count_active_users(None).await)?;
+    let admins = count(repo.count_users(Some(permissions::ADMIN_ROLE)).await)?;
+    Ok(UserStats { total, active, inactive: total - active, admins })
+}
+
+/// Create a user on behalf of an admin; they have to choose their own password at first login
+#[tauri::command]
+async fn create_user(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    gate: State<'_, LicenseGate>,
+    actor: AuditActor,
+    profile: UserProfileInput,
+    password: String,
+    role: String,
+) -> Result<User, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    let profile = check_profile(repo.as_ref(), None, profile).await?;
+    let role_exists = permissions::role_exists(repo.as_ref(), &role).await
+        .map_err(|e| format!("Failed to load role: {}", e))?;
+    if !role_exists {
+        return Err(format!("Role {} doesn't exist", role));
+    }
+    check_seat_available(repo.as_ref(), &gate).await?;
+
+    let policy = security::password_policy(repo.as_ref()).await
+        .map_err(|e| format!("Failed to load password policy: {}", e))?;
+    policy.check(&password, &profile.username).map_err(|e| e.to_string())?;
+    let password_hash = bcrypt::hash(&password, bcrypt::DEFAULT_COST)
+        .map_err(|e| format!("Failed to hash password: {}", e))?;
+
+    let input = UserInput {
+        username: profile.username,
+        email: profile.email,
+        password_hash,
+        full_name: profile.full_name,
+        phone: profile.phone,
+        role,
+        must_change_password: true,
+    };
+    audit::attributed(actor, repo.create_user(&input)).await
+        .map_err(|e| format!("Failed to create user: {}", e))
+}
+
+/// Change a user's username, email, name or phone
+#[tauri::command]
+async fn update_user(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    actor: AuditActor,
+    id: i64,
+    profile: UserProfileInput,
+) -> Result<User, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    existing_user(repo.as_ref(), id).await?;
+    let profile = check_profile(repo.as_ref(), Some(id), profile).await?;
+    let user = audit::attributed(actor, repo.update_user(id, &profile)).await
+        .map_err(|e| format!("Failed to update user: {}", e))?;
+    refresh_sessions(repo.as_ref(), &sessions, &user).await?;
+    Ok(user)
+}
+
+/// Get the logged-in user's own account
+#[tauri::command]
+async fn get_profile(
+    webview: tauri::Webview,
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+) -> Result<User, String> {
+    let current = sessions.current(webview.label(), chrono::Utc::now())
+        .ok_or("Your session has expired, please log in again")?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    existing_user(repo.as_ref(), current.session.user_id).await
+}
+
+/// Change the logged-in user's own username, email, name or phone
+#[tauri::command]
+async fn update_profile(
+    webview: tauri::Webview,
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    actor: AuditActor,
+    profile: UserProfileInput,
+) -> Result<User, String> {
+    let current = sessions.current(webview.label(), chrono::Utc::now())
+        .ok_or("Your session has expired, please log in again")?;
+    let id = current.session.user_id;
+    let repo = repositories(&db_state, &surreal_state)?;
+    let profile = check_profile(repo.as_ref(), Some(id), profile).await?;
+    let user = audit::attributed(actor, repo.update_user(id, &profile)).await
+        .map_err(|e| format!("Failed to update profile: {}", e))?;
+    refresh_sessions(repo.as_ref(), &sessions, &user).await?;
+    Ok(user)
+}
+
+/// Refuse one more active user when the license's seats are all taken
+async fn check_seat_available(repo: &dyn Repositories, gate: &LicenseGate) -> Result<(), String> {
+    let active = repo.count_active_users(None).await
+        .map_err(|e| format!("Failed to count users: {}", e))?;
+    gate.state(chrono::Utc::now().date_naive()).check_seat(active)
+}
+
+async fn refresh_sessions(repo: &dyn Repositories, sessions: &SessionStore, user: &User) -> Result<(), String> {
+    let capabilities = permissions::role_capabilities(repo, &user.role).await
+        .map_err(|e| format!("Failed to load role: {}", e))?;
+    sessions.refresh_user(user.id, &user.username, &user.role, &capabilities);
+    Ok(())
+}
+
+/// Enable or disable a user; disabled users can't log in and are logged out right away
+#[tauri::command]
+async fn set_user_active(
+    webview: tauri::Webview,
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    gate: State<'_, LicenseGate>,
+    actor: AuditActor,
+    id: i64,
+    active: bool,
+) -> Result<User, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    let user = existing_user(repo.as_ref(), id).await?;
+    if !active && is_current_user(&sessions, &webview, id) {
+        return Err("You can't disable your own account".to_string());
+    }
+    if active && user.is_active == 0 {
+        check_seat_available(repo.as_ref(), &gate).await?;
+    }
+    permissions::check_admin_remains(repo.as_ref(), &user, &user.role, active).await
+        .map_err(|e| e.to_string())?;
+
+    audit::attributed(actor, repo.set_user_active(id, active)).await
+        .map_err(|e| format!("Failed to update user: {}", e))?;
+    if !active {
+        sessions.end_user(id);
+    }
+    existing_user(repo.as_ref(), id).await
+}
+
+/// Give a user another role; their open sessions get its capabilities right away
+#[tauri::command]
+async fn set_user_role(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    actor: AuditActor,
+    id: i64,
+    role: String,
+) -> Result<User, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    let user = existing_user(repo.as_ref(), id).await?;
+    let role_exists = permissions::role_exists(repo.as_ref(), &role).await
+        .map_err(|e| format!("Failed to load role: {}", e))?;
+    if !role_exists {
+        return Err(format!("Role {} doesn't exist", role));
+    }
+    permissions::check_admin_remains(repo.as_ref(), &user, &role, user.is_active != 0).await
+        .map_err(|e| e.to_string())?;
+
+    audit::attributed(actor, repo.set_user_role(id, &role)).await
+        .map_err(|e| format!("Failed to update user: {}", e))?;
+    let user = existing_user(repo.as_ref(), id).await?;
+    refresh_sessions(repo.as_ref(), &sessions, &user).await?;
+    Ok(user)
+}
+
+/// Give a user a temporary password they must change at their next login; also lifts any lockout
+#[tauri::command]
+async fn reset_password(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    actor: AuditActor,
+    id: i64,
+    new_password: String,
+) -> Result<(), String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    let user = existing_user(repo.as_ref(), id).await?;
+    let policy = security::password_policy(repo.as_ref()).await
+        .map_err(|e| format!("Failed to load password policy: {}", e))?;
+    policy.check(&new_password, &user.username).map_err(|e| e.to_string())?;
+
+    let password_hash = bcrypt::hash(&new_password, bcrypt::DEFAULT_COST)
+        .map_err(|e| format!("Failed to hash password: {}", e))?;
+    audit::attributed(actor, async {
+        repo.set_password(id, &password_hash, true).await
+            .map_err(|e| format!("Failed to reset password: {}", e))?;
+        repo.set_login_failures(id, 0, None).await
+            .map_err(|e| format!("Failed to unlock user: {}", e))
     })
+    .await?;
+    sessions.end_user(id);
+    Ok(())
+}
+
+/// Delete a user; admins can't delete themselves or the last active admin
+#[tauri::command]
+async fn delete_user(
+    webview: tauri::Webview,
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    actor: AuditActor,
+    id: i64,
+) -> Result<(), String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    let user = existing_user(repo.as_ref(), id).await?;
+    if is_current_user(&sessions, &webview, id) {
+        return Err("You can't delete your own account".to_string());
+    }
+    permissions::check_admin_remains(repo.as_ref(), &user, &user.role, false).await
+        .map_err(|e| e.to_string())?;
+
+    audit::attributed(actor, repo.delete_user(id)).await
+        .map_err(|e| format!("Failed to delete user: {}", e))?;
+    sessions.end_user(id);
+    Ok(())
+}
+
+fn is_current_user(sessions: &SessionStore, webview: &tauri::Webview, user_id: i64) -> bool {
+    current_user_id(sessions, webview) == Some(user_id)
+}
+
+/// Id of the user logged in to the calling window
+fn current_user_id(sessions: &SessionStore, webview: &tauri::Webview) -> Option<i64> {
+    sessions
+        .current(webview.label(), chrono::Utc::now())
+        .map(|current| current.session.user_id)
+}
+
+/// List every capability a role can be granted
+#[tauri::command]
+fn list_capabilities() -> Vec<Capability> {
+    Capability::ALL.to_vec()
+}
+
+/// Get all roles: the built-in ones followed by custom roles
+#[tauri::command]
+async fn get_roles(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+) -> Result<Vec<Role>, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    permissions::list_roles(repo.as_ref()).await
+        .map_err(|e| format!("Failed to fetch roles: {}", e))
+}
+
+/// Create a custom role or change the capabilities of an existing one
+#[tauri::command]
+async fn save_role(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    actor: AuditActor,
+    name: String,
+    description: Option<String>,
+    capabilities: Vec<Capability>,
+) -> Result<Role, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    let capabilities = capabilities.into_iter().collect();
+    let role = audit::attributed(actor, permissions::save_role(repo.as_ref(), &name, description, &capabilities)).await
+        .map_err(|e| format!("Failed to save role: {}", e))?;
+
+    // Users logged in with this role get its new capabilities right away
+    sessions.refresh_role(&role.name, &role.capabilities);
+    Ok(role)
+}
+
+/// Delete a custom role that no user is assigned to
+#[tauri::command]
+async fn delete_role(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    name: String,
+) -> Result<(), String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    audit::attributed(actor, permissions::delete_role(repo.as_ref(), &name)).await
+        .map_err(|e| format!("Failed to delete role: {}", e))
+}
+
+/// Get audit log entries, newest first
+#[tauri::command]
+async fn get_audit_log(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    filter: Option<AuditFilter>,
+    page: i64,
+    per_page: i64,
+) -> Result<PaginatedResponse<AuditEntry>, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    audit::seal(repo.as_ref()).await
+        .map_err(|e| format!("Failed to seal audit log: {}", e))?;
+    repo.list_audit_log(&filter.unwrap_or_default(), &ListQuery::new(page, per_page)).await
+        .map_err(|e| format!("Failed to fetch audit log: {}", e))
+}
+
+/// Recompute the audit log hash chain and report the first entry that was tampered with
+#[tauri::command]
+async fn verify_audit_chain(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+) -> Result<AuditVerification, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    audit::verify(repo.as_ref()).await
+        .map_err(|e| format!("Failed to verify audit log: {}", e))
+}
+
+/// Repositories holding a bin's records: SQLite for the entities whose other commands only use
+/// SQLite, the open backend otherwise
+fn trash_repositories(
+    db_state: &State<'_, Mutex<Option<Database>>>,
+    surreal_state: &State<'_, Mutex<Option<SurrealDatabase>>>,
+    bin: &TrashBin,
+) -> Result<Box<dyn Repositories>, String> {
+    if !bin.sqlite_only {
+        return repositories(db_state, surreal_state);
+    }
+    let sqlite = db_state.lock().map_err(|e| format!("Lock error: {}", e))?.clone();
+    repository::select(None, sqlite, None).map_err(|e| e.to_string())
+}
+
+async fn move_to_trash(repo: &dyn Repositories, bin: &TrashBin, id: i64, deleted_by: Option<i64>) -> Result<(), String> {
+    let deleted = repo.soft_delete(bin.table, id, deleted_by).await
+        .map_err(|e| format!("Failed to delete {}: {}", bin.noun, e))?;
+    if !deleted {
+        return Err(format!("No {} with id {} to delete", bin.noun, id));
+    }
+    Ok(())
+}
+
+async fn restore_from_trash(repo: &dyn Repositories, bin: &TrashBin, id: i64) -> Result<(), String> {
+    let restored = repo.restore(bin.table, id).await
+        .map_err(|e| format!("Failed to restore {}: {}", bin.noun, e))?;
+    if !restored {
+        return Err(format!("No {} with id {} in the trash", bin.noun, id));
+    }
+    Ok(())
+}
+
+async fn list_trash(
+    repo: &dyn Repositories,
+    bin: &TrashBin,
+    page: i64,
+    per_page: i64,
+    search: Option<String>,
+) -> Result<PaginatedResponse<TrashedRecord>, String> {
+    let query = ListQuery { search, ..ListQuery::new(page, per_page) };
+    repo.list_deleted(bin, &query).await
+        .map_err(|e| format!("Failed to fetch deleted {}: {}", bin.entity, e))
+}
+
+/// Permanently delete trashed records past the retention period, of one entity or of all of them.
+/// Records other records still link to are kept.
+#[tauri::command]
+async fn purge_trash(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    entity: Option<String>,
+) -> Result<PurgeReport, String> {
+    let bins = match entity.as_deref() {
+        Some(entity) => vec![trash::bin(entity).ok_or_else(|| format!("Unknown trash: {}", entity))?],
+        None => trash::BINS.to_vec(),
+    };
+    let repo = repositories(&db_state, &surreal_state)?;
+    let policy = trash::retention_policy(repo.as_ref()).await
+        .map_err(|e| format!("Failed to load retention policy: {}", e))?;
+    // Each backend's bins are purged in their own pass, in BINS order
+    let (sqlite_bins, bins): (Vec<_>, Vec<_>) = bins.into_iter().partition(|bin| bin.sqlite_only);
+    let sqlite_repo = match sqlite_bins.first() {
+        Some(bin) => Some(trash_repositories(&db_state, &surreal_state, bin)?),
+        None => None,
+    };
+    let now = chrono::Utc::now();
+    let purge = async {
+        let mut report = trash::purge(repo.as_ref(), &policy, &bins, now).await?;
+        if let Some(sqlite_repo) = &sqlite_repo {
+            report.merge(trash::purge(sqlite_repo.as_ref(), &policy, &sqlite_bins, now).await?);
+        }
+        Ok::<_, anyhow::Error>(report)
+    };
+    audit::attributed(actor, purge).await
+        .map_err(|e| format!("Failed to purge trash: {}", e))
+}
+
+/// Get how long deleted records and documents are kept
+#[tauri::command]
+async fn get_retention_policy(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+) -> Result<RetentionPolicy, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    trash::retention_policy(repo.as_ref()).await
+        .map_err(|e| format!("Failed to load retention policy: {}", e))
+}
+
+/// Change how long deleted records and documents are kept
+#[tauri::command]
+async fn update_retention_policy(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    policy: RetentionPolicy,
+) -> Result<RetentionPolicy, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    audit::attributed(actor, trash::save_retention_policy(repo.as_ref(), &policy)).await
+        .map_err(|e| format!("Failed to save retention policy: {}", e))?;
+    Ok(policy)
 }
 
 /// Get machine ID for license generation
@@ -819,38 +2038,40 @@ fn get_machine_id() -> Result<String, String> {
     Ok(license::generate_machine_id())
 }
 
-/// Store license key in secure storage
+/// Show which hardware components still match the machine a license was issued for;
+/// checks the stored license unless a key is given
 #[tauri::command]
-fn store_license_key(key: String) -> Result<(), String> {
-    use keyring::Entry;
-    
-    let entry = Entry::new("finance_app", "license_key")
-        .map_err(|e| format!("Failed to create keyring entry: {}", e))?;
-    
-    entry.set_password(&key)
-        .map_err(|e| format!("Failed to store license key: {}", e))?;
-    
+fn diagnose_machine_id(license_key: Option<String>) -> Result<MachineDiagnosis, String> {
+    let key = match license_key {
+        Some(key) => Some(key),
+        None => license::stored_license_key()?,
+    };
+    Ok(license::diagnose_machine(key.as_deref(), &license::public_key()?, &license::Machine::current()))
+}
+
+/// Store license key in secure storage; commands are allowed by it right away
+#[tauri::command]
+fn store_license_key(gate: State<'_, LicenseGate>, key: String) -> Result<(), String> {
+    license::store_license_key(&key)?;
+    gate.set(Some(license::validate_license_key(&key)?));
     Ok(())
 }
 
 /// Get license key from secure storage
 #[tauri::command]
 fn get_license_key() -> Result<Option<String>, String> {
-    use keyring::Entry;
-    
-    let entry = Entry::new("finance_app", "license_key")
-        .map_err(|e| format!("Failed to create keyring entry: {}", e))?;
-    
-    match entry.get_password() {
-        Ok(key) => Ok(Some(key)),
-        Err(keyring::Error::NoEntry) => Ok(None),
-        Err(e) => Err(format!("Failed to get license key: {}", e)),
-    }
+    license::stored_license_key()
 }
 
-/// Validate license key
+/// What the stored license allows today: mode, modules, days left and any warning to show
 #[tauri::command]
-fn validate_license_key(entered_key: String) -> Result<bool, String> {
+fn get_license_status(gate: State<'_, LicenseGate>) -> LicenseState {
+    gate.state(chrono::Utc::now().date_naive())
+}
+
+/// Check a signed license key against this machine, with the reason when it is rejected
+#[tauri::command]
+fn validate_license_key(entered_key: String) -> Result<LicenseInfo, String> {
     license::validate_license_key(&entered_key)
 }
 
@@ -893,448 +2114,270 @@ fn get_puter_credentials() -> Result<Option<(String, String)>, String> {
     }
 }
 
-/// Hash a password using bcrypt
+/// Settings of the built-in server (bind address, port, allowed origins, TLS)
 #[tauri::command]
-fn hash_password(password: String) -> Result<String, String> {
-    bcrypt::hash(&password, bcrypt::DEFAULT_COST)
-        .map_err(|e| format!("Failed to hash password: {}", e))
+fn get_server_config() -> Result<ServerConfig, String> {
+    server::load_config()
 }
 
-/// Verify a password against a hash using bcrypt
+/// Change the built-in server's settings; they apply the next time the app starts
 #[tauri::command]
-fn verify_password(password: String, hash: String) -> Result<bool, String> {
-    bcrypt::verify(&password, &hash)
-        .map_err(|e| format!("Password verification error: {}", e))
+fn update_server_config(config: ServerConfig) -> Result<ServerConfig, String> {
+    server::save_config(config)
 }
 
-// Currency Model
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Currency {
-    pub id: i64,
-    pub name: String,
-    pub base: bool,
-    pub rate: f64,
-    pub created_at: String,
-    pub updated_at: String,
-}
-
-/// Initialize currencies table schema
+/// URL and install secret of the running server, for the app's own requests to it
 #[tauri::command]
-fn init_currencies_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS currencies (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT NOT NULL UNIQUE,
-            base INTEGER NOT NULL DEFAULT 0,
-            rate REAL NOT NULL DEFAULT 1.0,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
-        )
-    ";
-
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create currencies table: {}", e))?;
-
-    // Add rate column if it doesn't exist (for existing databases)
-    let alter_sql = "ALTER TABLE currencies ADD COLUMN rate REAL NOT NULL DEFAULT 1.0";
-    let _ = db.execute(alter_sql, &[]);
-
-    Ok("Currencies table initialized successfully".to_string())
+fn get_server_access() -> Option<ServerAccess> {
+    server::access()
 }
 
-/// Create a new currency
+/// Get the webhooks events are sent to
 #[tauri::command]
-fn create_currency(
-    db_state: State<'_, Mutex<Option<Database>>>,
-    name: String,
-    base: bool,
-    rate: f64,
-) -> Result<Currency, String> {
+fn get_webhooks(db_state: State<'_, Mutex<Option<Database>>>) -> Result<Vec<Webhook>, String> {
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    // If this is set as base, unset all other base currencies
-    if base {
-        let update_sql = "UPDATE currencies SET base = 0";
-        db.execute(update_sql, &[])
-            .map_err(|e| format!("Failed to update base currencies: {}", e))?;
-    }
-
-    // Insert new currency
-    let insert_sql = "INSERT INTO currencies (name, base, rate) VALUES (?, ?, ?)";
-    let base_int = if base { 1 } else { 0 };
-    db.execute(insert_sql, &[&name as &dyn rusqlite::ToSql, &base_int as &dyn rusqlite::ToSql, &rate as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to insert currency: {}", e))?;
-
-    // Get the created currency
-    let currency_sql = "SELECT id, name, base, rate, created_at, updated_at FROM currencies WHERE name = ?";
-    let currencies = db
-        .query(currency_sql, &[&name as &dyn rusqlite::ToSql], |row| {
-            Ok(Currency {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                base: row.get::<_, i64>(2)? != 0,
-                rate: row.get(3)?,
-                created_at: row.get(4)?,
-                updated_at: row.get(5)?,
-            })
-        })
-        .map_err(|e| format!("Failed to fetch currency: {}", e))?;
-
-    if let Some(currency) = currencies.first() {
-        Ok(currency.clone())
-    } else {
-        Err("Failed to retrieve created currency".to_string())
-    }
+    webhooks::list(db).map_err(|e| format!("Failed to fetch webhooks: {}", e))
 }
 
-/// Get all currencies
-#[tauri::command]
-fn get_currencies(db_state: State<'_, Mutex<Option<Database>>>) -> Result<Vec<Currency>, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let sql = "SELECT id, name, base, rate, created_at, updated_at FROM currencies ORDER BY base DESC, name ASC";
-    let currencies = db
-        .query(sql, &[], |row| {
-            Ok(Currency {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                base: row.get::<_, i64>(2)? != 0,
-                rate: row.get(3)?,
-                created_at: row.get(4)?,
-                updated_at: row.get(5)?,
-            })
-        })
-        .map_err(|e| format!("Failed to fetch currencies: {}", e))?;
-
-    Ok(currencies)
+/// Events are only queued by the SQLite writes, so a webhook would never hear of the sales,
+/// purchases and payments stored while a SurrealDB connection is open
+fn ensure_webhooks_receive_events(surreal_state: &State<'_, Mutex<Option<SurrealDatabase>>>) -> Result<(), String> {
+    if surreal_state.lock().map_err(|e| format!("Lock error: {}", e))?.is_some() {
+        return Err("Webhooks are only supported in SQLite mode: events are not sent while SurrealDB is in use".to_string());
+    }
+    Ok(())
 }
 
-/// Update a currency
+/// Add a webhook; its signing secret is generated. Refused while a SurrealDB connection is open,
+/// as events are only sent for changes stored in SQLite.
 #[tauri::command]
-fn update_currency(
+fn create_webhook(
     db_state: State<'_, Mutex<Option<Database>>>,
-    id: i64,
-    name: String,
-    base: bool,
-    rate: f64,
-) -> Result<Currency, String> {
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    input: WebhookInput,
+) -> Result<Webhook, String> {
+    ensure_webhooks_receive_events(&surreal_state)?;
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    // If this is set as base, unset all other base currencies
-    if base {
-        let update_sql = "UPDATE currencies SET base = 0 WHERE id != ?";
-        db.execute(update_sql, &[&id as &dyn rusqlite::ToSql])
-            .map_err(|e| format!("Failed to update base currencies: {}", e))?;
-    }
-
-    // Update currency
-    let base_int = if base { 1 } else { 0 };
-    let update_sql = "UPDATE currencies SET name = ?, base = ?, rate = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?";
-    db.execute(update_sql, &[&name as &dyn rusqlite::ToSql, &base_int as &dyn rusqlite::ToSql, &rate as &dyn rusqlite::ToSql, &id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to update currency: {}", e))?;
-
-    // Get the updated currency
-    let currency_sql = "SELECT id, name, base, rate, created_at, updated_at FROM currencies WHERE id = ?";
-    let currencies = db
-        .query(currency_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(Currency {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                base: row.get::<_, i64>(2)? != 0,
-                rate: row.get(3)?,
-                created_at: row.get(4)?,
-                updated_at: row.get(5)?,
-            })
-        })
-        .map_err(|e| format!("Failed to fetch currency: {}", e))?;
-
-    if let Some(currency) = currencies.first() {
-        Ok(currency.clone())
-    } else {
-        Err("Failed to retrieve updated currency".to_string())
-    }
+    webhooks::create(db, &input).map_err(|e| format!("Failed to create webhook: {}", e))
 }
 
-/// Delete a currency
+/// Change a webhook's URL, events or active flag. Only deactivating is allowed while a SurrealDB
+/// connection is open, as for `create_webhook`.
 #[tauri::command]
-fn delete_currency(
+fn update_webhook(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
     id: i64,
-) -> Result<String, String> {
+    input: WebhookInput,
+) -> Result<Webhook, String> {
+    if input.is_active {
+        ensure_webhooks_receive_events(&surreal_state)?;
+    }
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let delete_sql = "DELETE FROM currencies WHERE id = ?";
-    db.execute(delete_sql, &[&id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to delete currency: {}", e))?;
-
-    Ok("Currency deleted successfully".to_string())
-}
-
-// Supplier Model
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Supplier {
-    pub id: i64,
-    pub full_name: String,
-    pub phone: String,
-    pub address: String,
-    pub email: Option<String>,
-    pub notes: Option<String>,
-    pub created_at: String,
-    pub updated_at: String,
+    webhooks::update(db, id, &input).map_err(|e| format!("Failed to update webhook: {}", e))
 }
 
-/// Initialize suppliers table schema
+/// Remove a webhook with its delivery log
 #[tauri::command]
-fn init_suppliers_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
+fn delete_webhook(db_state: State<'_, Mutex<Option<Database>>>, id: i64) -> Result<(), String> {
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS suppliers (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            full_name TEXT NOT NULL,
-            phone TEXT NOT NULL,
-            address TEXT NOT NULL,
-            email TEXT,
-            notes TEXT,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
-        )
-    ";
-
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create suppliers table: {}", e))?;
-
-    Ok("Suppliers table initialized successfully".to_string())
+    match webhooks::delete(db, id) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(format!("No webhook with id {} to delete", id)),
+        Err(e) => Err(format!("Failed to delete webhook: {}", e)),
+    }
 }
 
-/// Create a new supplier
+/// Get the webhook delivery log, newest first
 #[tauri::command]
-fn create_supplier(
+fn get_webhook_deliveries(
     db_state: State<'_, Mutex<Option<Database>>>,
-    full_name: String,
-    phone: String,
-    address: String,
-    email: Option<String>,
-    notes: Option<String>,
-) -> Result<Supplier, String> {
+    filter: Option<DeliveryFilter>,
+    page: i64,
+    per_page: i64,
+) -> Result<PaginatedResponse<WebhookDelivery>, String> {
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
+    webhooks::deliveries(db, &filter.unwrap_or_default(), &ListQuery::new(page, per_page))
+        .map_err(|e| format!("Failed to fetch webhook deliveries: {}", e))
+}
 
-    // Insert new supplier
-    let insert_sql = "INSERT INTO suppliers (full_name, phone, address, email, notes) VALUES (?, ?, ?, ?, ?)";
-    let email_str: Option<&str> = email.as_ref().map(|s| s.as_str());
-    let notes_str: Option<&str> = notes.as_ref().map(|s| s.as_str());
-    db.execute(insert_sql, &[
-        &full_name as &dyn rusqlite::ToSql,
-        &phone as &dyn rusqlite::ToSql,
-        &address as &dyn rusqlite::ToSql,
-        &email_str as &dyn rusqlite::ToSql,
-        &notes_str as &dyn rusqlite::ToSql,
-    ])
-        .map_err(|e| format!("Failed to insert supplier: {}", e))?;
+/// Hash a password using bcrypt
+#[tauri::command]
+fn hash_password(password: String) -> Result<String, String> {
+    bcrypt::hash(&password, bcrypt::DEFAULT_COST)
+        .map_err(|e| format!("Failed to hash password: {}", e))
+}
 
-    // Get the created supplier
-    let supplier_sql = "SELECT id, full_name, phone, address, email, notes, created_at, updated_at FROM suppliers WHERE full_name = ? AND phone = ? ORDER BY id DESC LIMIT 1";
-    let suppliers = db
-        .query(supplier_sql, &[&full_name as &dyn rusqlite::ToSql, &phone as &dyn rusqlite::ToSql], |row| {
-            Ok(Supplier {
-                id: row.get(0)?,
-                full_name: row.get(1)?,
-                phone: row.get(2)?,
-                address: row.get(3)?,
-                email: row.get::<_, Option<String>>(4)?,
-                notes: row.get::<_, Option<String>>(5)?,
-                created_at: row.get(6)?,
-                updated_at: row.get(7)?,
-            })
-        })
-        .map_err(|e| format!("Failed to fetch supplier: {}", e))?;
+/// Verify a password against a hash using bcrypt
+#[tauri::command]
+fn verify_password(password: String, hash: String) -> Result<bool, String> {
+    bcrypt::verify(&password, &hash)
+        .map_err(|e| format!("Password verification error: {}", e))
+}
 
-    if let Some(supplier) = suppliers.first() {
-        Ok(supplier.clone())
-    } else {
-        Err("Failed to retrieve created supplier".to_string())
-    }
+// Currency Model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Currency {
+    pub id: i64,
+    pub name: String,
+    pub base: bool,
+    pub rate: f64,
+    pub created_at: String,
+    pub updated_at: String,
 }
 
-/// Get all suppliers
+/// Initialize currencies table schema
 #[tauri::command]
-fn get_suppliers(
+fn init_currencies_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
+    ensure_database_open(&db_state)?;
+
+    Ok("Currencies table initialized successfully".to_string())
+}
+
+/// Create a new currency
+#[tauri::command]
+fn create_currency(
     db_state: State<'_, Mutex<Option<Database>>>,
-    page: i64,
-    per_page: i64,
-    search: Option<String>,
-    sort_by: Option<String>,
-    sort_order: Option<String>,
-) -> Result<PaginatedResponse<Supplier>, String> {
+    name: String,
+    base: bool,
+    rate: f64,
+) -> Result<Currency, String> {
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
 
-    let offset = (page - 1) * per_page;
-    let mut where_clause = String::new();
-    let mut params: Vec<serde_json::Value> = Vec::new();
-
-    if let Some(s) = search {
-        if !s.trim().is_empty() {
-            let search_term = format!("%{}%", s);
-            where_clause = "WHERE (full_name LIKE ? OR phone LIKE ? OR email LIKE ?)".to_string();
-            params.push(serde_json::Value::String(search_term.clone()));
-            params.push(serde_json::Value::String(search_term.clone()));
-            params.push(serde_json::Value::String(search_term));
+    db.transaction(|db| {
+        // If this is set as base, unset all other base currencies
+        if base {
+            let update_sql = "UPDATE currencies SET base = 0";
+            db.execute(update_sql, &[])
+                .map_err(|e| format!("Failed to update base currencies: {}", e))?;
         }
-    }
 
-    let count_sql = format!("SELECT COUNT(*) FROM suppliers {}", where_clause);
-    let total: i64 = db.with_connection(|conn| {
-        let mut stmt = conn.prepare(&count_sql).map_err(|e| anyhow::anyhow!("{}", e))?;
-        let rusqlite_params: Vec<rusqlite::types::Value> = params.iter().map(|v| {
-            match v {
-                serde_json::Value::String(s) => rusqlite::types::Value::Text(s.clone()),
-                _ => rusqlite::types::Value::Null,
-            }
-        }).collect();
-        let count: i64 = stmt.query_row(rusqlite::params_from_iter(rusqlite_params.iter()), |row| row.get(0))
-            .map_err(|e| anyhow::anyhow!("{}", e))?;
-        Ok(count)
-    }).map_err(|e| format!("Failed to count suppliers: {}", e))?;
+        // Insert new currency
+        let insert_sql = "INSERT INTO currencies (name, base, rate) VALUES (?, ?, ?)";
+        let base_int = if base { 1 } else { 0 };
+        let currency_id = db.insert(insert_sql, &[&name as &dyn rusqlite::ToSql, &base_int as &dyn rusqlite::ToSql, &rate as &dyn rusqlite::ToSql])
+            .map_err(|e| format!("Failed to insert currency: {}", e))?;
+
+        // Get the created currency
+        let currency_sql = "SELECT id, name, base, rate, created_at, updated_at FROM currencies WHERE id = ?";
+        let currencies = db
+            .query(currency_sql, &[&currency_id as &dyn rusqlite::ToSql], |row| {
+                Ok(Currency {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    base: row.get::<_, i64>(2)? != 0,
+                    rate: row.get(3)?,
+                    created_at: row.get(4)?,
+                    updated_at: row.get(5)?,
+                })
+            })
+            .map_err(|e| format!("Failed to fetch currency: {}", e))?;
 
-    let order_clause = if let Some(sort) = sort_by {
-        let order = sort_order.unwrap_or_else(|| "ASC".to_string());
-        let allowed_cols = ["full_name", "created_at"];
-        if allowed_cols.contains(&sort.as_str()) {
-            format!("ORDER BY {} {}", sort, if order.to_uppercase() == "DESC" { "DESC" } else { "ASC" })
+        if let Some(currency) = currencies.first() {
+            Ok(currency.clone())
         } else {
-            "ORDER BY created_at DESC".to_string()
+            Err("Failed to retrieve created currency".to_string())
         }
-    } else {
-        "ORDER BY created_at DESC".to_string()
-    };
-
-    let sql = format!("SELECT id, full_name, phone, address, email, notes, created_at, updated_at FROM suppliers {} {} LIMIT ? OFFSET ?", where_clause, order_clause);
-    
-    params.push(serde_json::Value::Number(serde_json::Number::from(per_page)));
-    params.push(serde_json::Value::Number(serde_json::Number::from(offset)));
+    })
+}
 
-    let suppliers = db.with_connection(|conn| {
-        let mut stmt = conn.prepare(&sql).map_err(|e| anyhow::anyhow!("{}", e))?;
-        let rusqlite_params: Vec<rusqlite::types::Value> = params.iter().map(|v| {
-            match v {
-                serde_json::Value::String(s) => rusqlite::types::Value::Text(s.clone()),
-                serde_json::Value::Number(n) => rusqlite::types::Value::Integer(n.as_i64().unwrap_or(0)),
-                _ => rusqlite::types::Value::Null,
-            }
-        }).collect();
+/// Get all currencies
+#[tauri::command]
+fn get_currencies(db_state: State<'_, Mutex<Option<Database>>>) -> Result<Vec<Currency>, String> {
+    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
+    let db = db_guard.as_ref().ok_or("No database is currently open")?;
 
-        let rows = stmt.query_map(rusqlite::params_from_iter(rusqlite_params.iter()), |row| {
-             Ok(Supplier {
+    let sql = "SELECT id, name, base, rate, created_at, updated_at FROM currencies ORDER BY base DESC, name ASC";
+    let currencies = db
+        .query(sql, &[], |row| {
+            Ok(Currency {
                 id: row.get(0)?,
-                full_name: row.get(1)?,
-                phone: row.get(2)?,
-                address: row.get(3)?,
-                email: row.get::<_, Option<String>>(4)?,
-                notes: row.get::<_, Option<String>>(5)?,
-                created_at: row.get(6)?,
-                updated_at: row.get(7)?,
+                name: row.get(1)?,
+                base: row.get::<_, i64>(2)? != 0,
+                rate: row.get(3)?,
+                created_at: row.get(4)?,
+                updated_at: row.get(5)?,
             })
-        }).map_err(|e| anyhow::anyhow!("{}", e))?;
-
-        let mut result = Vec::new();
-        for row in rows {
-            result.push(row.map_err(|e| anyhow::anyhow!("{}", e))?);
-        }
-        Ok(result)
-    }).map_err(|e| format!("Failed to fetch suppliers: {}", e))?;
+        })
+        .map_err(|e| format!("Failed to fetch currencies: {}", e))?;
 
-    let total_pages = (total as f64 / per_page as f64).ceil() as i64;
-    
-    Ok(PaginatedResponse {
-        items: suppliers,
-        total,
-        page,
-        per_page,
-        total_pages,
-    })
+    Ok(currencies)
 }
 
-/// Update a supplier
+/// Update a currency
 #[tauri::command]
-fn update_supplier(
+fn update_currency(
     db_state: State<'_, Mutex<Option<Database>>>,
     id: i64,
-    full_name: String,
-    phone: String,
-    address: String,
-    email: Option<String>,
-    notes: Option<String>,
-) -> Result<Supplier, String> {
+    name: String,
+    base: bool,
+    rate: f64,
+) -> Result<Currency, String> {
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
 
-    // Update supplier
-    let update_sql = "UPDATE suppliers SET full_name = ?, phone = ?, address = ?, email = ?, notes = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?";
-    let email_str: Option<&str> = email.as_ref().map(|s| s.as_str());
-    let notes_str: Option<&str> = notes.as_ref().map(|s| s.as_str());
-    db.execute(update_sql, &[
-        &full_name as &dyn rusqlite::ToSql,
-        &phone as &dyn rusqlite::ToSql,
-        &address as &dyn rusqlite::ToSql,
-        &email_str as &dyn rusqlite::ToSql,
-        &notes_str as &dyn rusqlite::ToSql,
-        &id as &dyn rusqlite::ToSql,
-    ])
-        .map_err(|e| format!("Failed to update supplier: {}", e))?;
+    db.transaction(|db| {
+        // If this is set as base, unset all other base currencies
+        if base {
+            let update_sql = "UPDATE currencies SET base = 0 WHERE id != ?";
+            db.execute(update_sql, &[&id as &dyn rusqlite::ToSql])
+                .map_err(|e| format!("Failed to update base currencies: {}", e))?;
+        }
 
-    // Get the updated supplier
-    let supplier_sql = "SELECT id, full_name, phone, address, email, notes, created_at, updated_at FROM suppliers WHERE id = ?";
-    let suppliers = db
-        .query(supplier_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(Supplier {
-                id: row.get(0)?,
-                full_name: row.get(1)?,
-                phone: row.get(2)?,
-                address: row.get(3)?,
-                email: row.get::<_, Option<String>>(4)?,
-                notes: row.get::<_, Option<String>>(5)?,
-                created_at: row.get(6)?,
-                updated_at: row.get(7)?,
+        // Update currency
+        let base_int = if base { 1 } else { 0 };
+        let update_sql = "UPDATE currencies SET name = ?, base = ?, rate = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?";
+        db.execute(update_sql, &[&name as &dyn rusqlite::ToSql, &base_int as &dyn rusqlite::ToSql, &rate as &dyn rusqlite::ToSql, &id as &dyn rusqlite::ToSql])
+            .map_err(|e| format!("Failed to update currency: {}", e))?;
+
+        // Get the updated currency
+        let currency_sql = "SELECT id, name, base, rate, created_at, updated_at FROM currencies WHERE id = ?";
+        let currencies = db
+            .query(currency_sql, &[&id as &dyn rusqlite::ToSql], |row| {
+                Ok(Currency {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    base: row.get::<_, i64>(2)? != 0,
+                    rate: row.get(3)?,
+                    created_at: row.get(4)?,
+                    updated_at: row.get(5)?,
+                })
             })
-        })
-        .map_err(|e| format!("Failed to fetch supplier: {}", e))?;
+            .map_err(|e| format!("Failed to fetch currency: {}", e))?;
 
-    if let Some(supplier) = suppliers.first() {
-        Ok(supplier.clone())
-    } else {
-        Err("Failed to retrieve updated supplier".to_string())
-    }
+        if let Some(currency) = currencies.first() {
+            Ok(currency.clone())
+        } else {
+            Err("Failed to retrieve updated currency".to_string())
+        }
+    })
 }
 
-/// Delete a supplier
+/// Delete a currency
 #[tauri::command]
-fn delete_supplier(
+fn delete_currency(
     db_state: State<'_, Mutex<Option<Database>>>,
     id: i64,
 ) -> Result<String, String> {
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
 
-    let delete_sql = "DELETE FROM suppliers WHERE id = ?";
+    let delete_sql = "DELETE FROM currencies WHERE id = ?";
     db.execute(delete_sql, &[&id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to delete supplier: {}", e))?;
+        .map_err(|e| format!("Failed to delete currency: {}", e))?;
 
-    Ok("Supplier deleted successfully".to_string())
+    Ok("Currency deleted successfully".to_string())
 }
 
-// Customer Model
+// Supplier Model
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Customer {
+pub struct Supplier {
     pub id: i64,
     pub full_name: String,
     pub phone: String,
@@ -1345,62 +2388,45 @@ pub struct Customer {
     pub updated_at: String,
 }
 
-/// Initialize customers table schema
-#[tauri::command]
-fn init_customers_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS customers (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            full_name TEXT NOT NULL,
-            phone TEXT NOT NULL,
-            address TEXT NOT NULL,
-            email TEXT,
-            notes TEXT,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
-        )
-    ";
-
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create customers table: {}", e))?;
+/// Initialize suppliers table schema
+#[tauri::command]
+fn init_suppliers_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
+    ensure_database_open(&db_state)?;
 
-    Ok("Customers table initialized successfully".to_string())
+    Ok("Suppliers table initialized successfully".to_string())
 }
 
-/// Create a new customer
+/// Create a new supplier
 #[tauri::command]
-fn create_customer(
+fn create_supplier(
     db_state: State<'_, Mutex<Option<Database>>>,
     full_name: String,
     phone: String,
     address: String,
     email: Option<String>,
     notes: Option<String>,
-) -> Result<Customer, String> {
+) -> Result<Supplier, String> {
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
 
-    // Insert new customer
-    let insert_sql = "INSERT INTO customers (full_name, phone, address, email, notes) VALUES (?, ?, ?, ?, ?)";
+    // Insert new supplier
+    let insert_sql = "INSERT INTO suppliers (full_name, phone, address, email, notes) VALUES (?, ?, ?, ?, ?)";
     let email_str: Option<&str> = email.as_ref().map(|s| s.as_str());
     let notes_str: Option<&str> = notes.as_ref().map(|s| s.as_str());
-    db.execute(insert_sql, &[
+    let supplier_id = db.insert(insert_sql, &[
         &full_name as &dyn rusqlite::ToSql,
         &phone as &dyn rusqlite::ToSql,
         &address as &dyn rusqlite::ToSql,
         &email_str as &dyn rusqlite::ToSql,
         &notes_str as &dyn rusqlite::ToSql,
     ])
-        .map_err(|e| format!("Failed to insert customer: {}", e))?;
+        .map_err(|e| format!("Failed to insert supplier: {}", e))?;
 
-    // Get the created customer
-    let customer_sql = "SELECT id, full_name, phone, address, email, notes, created_at, updated_at FROM customers WHERE full_name = ? AND phone = ? ORDER BY id DESC LIMIT 1";
-    let customers = db
-        .query(customer_sql, &[&full_name as &dyn rusqlite::ToSql, &phone as &dyn rusqlite::ToSql], |row| {
-            Ok(Customer {
+    // Get the created supplier
+    let supplier_sql = "SELECT id, full_name, phone, address, email, notes, created_at, updated_at FROM suppliers WHERE id = ?";
+    let suppliers = db
+        .query(supplier_sql, &[&supplier_id as &dyn rusqlite::ToSql], |row| {
+            Ok(Supplier {
                 id: row.get(0)?,
                 full_name: row.get(1)?,
                 phone: row.get(2)?,
@@ -1411,43 +2437,43 @@ fn create_customer(
                 updated_at: row.get(7)?,
             })
         })
-        .map_err(|e| format!("Failed to fetch customer: {}", e))?;
+        .map_err(|e| format!("Failed to fetch supplier: {}", e))?;
 
-    if let Some(customer) = customers.first() {
-        Ok(customer.clone())
+    if let Some(supplier) = suppliers.first() {
+        Ok(supplier.clone())
     } else {
-        Err("Failed to retrieve created customer".to_string())
+        Err("Failed to retrieve created supplier".to_string())
     }
 }
 
-/// Get all customers
+/// Get all suppliers
 #[tauri::command]
-fn get_customers(
+fn get_suppliers(
     db_state: State<'_, Mutex<Option<Database>>>,
     page: i64,
     per_page: i64,
     search: Option<String>,
     sort_by: Option<String>,
     sort_order: Option<String>,
-) -> Result<PaginatedResponse<Customer>, String> {
+) -> Result<PaginatedResponse<Supplier>, String> {
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
 
     let offset = (page - 1) * per_page;
-    let mut where_clause = String::new();
+    let mut where_clause = "WHERE deleted_at IS NULL".to_string();
     let mut params: Vec<serde_json::Value> = Vec::new();
 
     if let Some(s) = search {
         if !s.trim().is_empty() {
             let search_term = format!("%{}%", s);
-            where_clause = "WHERE (full_name LIKE ? OR phone LIKE ? OR email LIKE ?)".to_string();
+            where_clause.push_str(" AND (full_name LIKE ? OR phone LIKE ? OR email LIKE ?)");
             params.push(serde_json::Value::String(search_term.clone()));
             params.push(serde_json::Value::String(search_term.clone()));
             params.push(serde_json::Value::String(search_term));
         }
     }
 
-    let count_sql = format!("SELECT COUNT(*) FROM customers {}", where_clause);
+    let count_sql = format!("SELECT COUNT(*) FROM suppliers {}", where_clause);
     let total: i64 = db.with_connection(|conn| {
         let mut stmt = conn.prepare(&count_sql).map_err(|e| anyhow::anyhow!("{}", e))?;
         let rusqlite_params: Vec<rusqlite::types::Value> = params.iter().map(|v| {
@@ -1459,7 +2485,7 @@ fn get_customers(
         let count: i64 = stmt.query_row(rusqlite::params_from_iter(rusqlite_params.iter()), |row| row.get(0))
             .map_err(|e| anyhow::anyhow!("{}", e))?;
         Ok(count)
-    }).map_err(|e| format!("Failed to count customers: {}", e))?;
+    }).map_err(|e| format!("Failed to count suppliers: {}", e))?;
 
     let order_clause = if let Some(sort) = sort_by {
         let order = sort_order.unwrap_or_else(|| "ASC".to_string());
@@ -1473,12 +2499,12 @@ fn get_customers(
         "ORDER BY created_at DESC".to_string()
     };
 
-    let sql = format!("SELECT id, full_name, phone, address, email, notes, created_at, updated_at FROM customers {} {} LIMIT ? OFFSET ?", where_clause, order_clause);
+    let sql = format!("SELECT id, full_name, phone, address, email, notes, created_at, updated_at FROM suppliers {} {} LIMIT ? OFFSET ?", where_clause, order_clause);
     
     params.push(serde_json::Value::Number(serde_json::Number::from(per_page)));
     params.push(serde_json::Value::Number(serde_json::Number::from(offset)));
 
-    let customers = db.with_connection(|conn| {
+    let suppliers = db.with_connection(|conn| {
         let mut stmt = conn.prepare(&sql).map_err(|e| anyhow::anyhow!("{}", e))?;
         let rusqlite_params: Vec<rusqlite::types::Value> = params.iter().map(|v| {
             match v {
@@ -1489,7 +2515,7 @@ fn get_customers(
         }).collect();
 
         let rows = stmt.query_map(rusqlite::params_from_iter(rusqlite_params.iter()), |row| {
-             Ok(Customer {
+             Ok(Supplier {
                 id: row.get(0)?,
                 full_name: row.get(1)?,
                 phone: row.get(2)?,
@@ -1506,12 +2532,12 @@ fn get_customers(
             result.push(row.map_err(|e| anyhow::anyhow!("{}", e))?);
         }
         Ok(result)
-    }).map_err(|e| format!("Failed to fetch customers: {}", e))?;
+    }).map_err(|e| format!("Failed to fetch suppliers: {}", e))?;
 
     let total_pages = (total as f64 / per_page as f64).ceil() as i64;
     
     Ok(PaginatedResponse {
-        items: customers,
+        items: suppliers,
         total,
         page,
         per_page,
@@ -1519,9 +2545,9 @@ fn get_customers(
     })
 }
 
-/// Update a customer
+/// Update a supplier
 #[tauri::command]
-fn update_customer(
+fn update_supplier(
     db_state: State<'_, Mutex<Option<Database>>>,
     id: i64,
     full_name: String,
@@ -1529,12 +2555,12 @@ fn update_customer(
     address: String,
     email: Option<String>,
     notes: Option<String>,
-) -> Result<Customer, String> {
+) -> Result<Supplier, String> {
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
 
-    // Update customer
-    let update_sql = "UPDATE customers SET full_name = ?, phone = ?, address = ?, email = ?, notes = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?";
+    // Update supplier
+    let update_sql = "UPDATE suppliers SET full_name = ?, phone = ?, address = ?, email = ?, notes = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?";
     let email_str: Option<&str> = email.as_ref().map(|s| s.as_str());
     let notes_str: Option<&str> = notes.as_ref().map(|s| s.as_str());
     db.execute(update_sql, &[
@@ -1545,13 +2571,13 @@ fn update_customer(
         &notes_str as &dyn rusqlite::ToSql,
         &id as &dyn rusqlite::ToSql,
     ])
-        .map_err(|e| format!("Failed to update customer: {}", e))?;
+        .map_err(|e| format!("Failed to update supplier: {}", e))?;
 
-    // Get the updated customer
-    let customer_sql = "SELECT id, full_name, phone, address, email, notes, created_at, updated_at FROM customers WHERE id = ?";
-    let customers = db
-        .query(customer_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(Customer {
+    // Get the updated supplier
+    let supplier_sql = "SELECT id, full_name, phone, address, email, notes, created_at, updated_at FROM suppliers WHERE id = ?";
+    let suppliers = db
+        .query(supplier_sql, &[&id as &dyn rusqlite::ToSql], |row| {
+            Ok(Supplier {
                 id: row.get(0)?,
                 full_name: row.get(1)?,
                 phone: row.get(2)?,
@@ -1562,31 +2588,176 @@ fn update_customer(
                 updated_at: row.get(7)?,
             })
         })
-        .map_err(|e| format!("Failed to fetch customer: {}", e))?;
+        .map_err(|e| format!("Failed to fetch supplier: {}", e))?;
 
-    if let Some(customer) = customers.first() {
-        Ok(customer.clone())
+    if let Some(supplier) = suppliers.first() {
+        Ok(supplier.clone())
     } else {
-        Err("Failed to retrieve updated customer".to_string())
+        Err("Failed to retrieve updated supplier".to_string())
     }
 }
 
-/// Delete a customer
+/// Move a supplier to the trash
 #[tauri::command]
-fn delete_customer(
+async fn delete_supplier(
+    webview: tauri::Webview,
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    actor: AuditActor,
     id: i64,
 ) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
+    let repo = trash_repositories(&db_state, &surreal_state, &trash::SUPPLIERS)?;
+    audit::attributed(actor, move_to_trash(repo.as_ref(), &trash::SUPPLIERS, id, current_user_id(&sessions, &webview))).await?;
 
-    let delete_sql = "DELETE FROM customers WHERE id = ?";
-    db.execute(delete_sql, &[&id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to delete customer: {}", e))?;
+    Ok("Supplier deleted successfully".to_string())
+}
+
+/// Take a supplier out of the trash
+#[tauri::command]
+async fn restore_supplier(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    id: i64,
+) -> Result<String, String> {
+    let repo = trash_repositories(&db_state, &surreal_state, &trash::SUPPLIERS)?;
+    audit::attributed(actor, restore_from_trash(repo.as_ref(), &trash::SUPPLIERS, id)).await?;
+
+    Ok("Supplier restored successfully".to_string())
+}
+
+/// Get the suppliers in the trash, most recently deleted first
+#[tauri::command]
+async fn get_deleted_suppliers(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    page: i64,
+    per_page: i64,
+    search: Option<String>,
+) -> Result<PaginatedResponse<TrashedRecord>, String> {
+    let repo = trash_repositories(&db_state, &surreal_state, &trash::SUPPLIERS)?;
+    list_trash(repo.as_ref(), &trash::SUPPLIERS, page, per_page, search).await
+}
+
+// Customer Model
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Customer {
+    pub id: i64,
+    pub full_name: String,
+    pub phone: String,
+    pub address: String,
+    pub email: Option<String>,
+    pub notes: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Initialize customers table schema
+#[tauri::command]
+fn init_customers_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
+    ensure_database_open(&db_state)?;
+
+    Ok("Customers table initialized successfully".to_string())
+}
+
+/// Create a new customer
+#[tauri::command]
+async fn create_customer(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    full_name: String,
+    phone: String,
+    address: String,
+    email: Option<String>,
+    notes: Option<String>,
+) -> Result<Customer, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    let input = CustomerInput { full_name, phone, address, email, notes };
+    audit::attributed(actor, repo.create_customer(&input)).await
+        .map_err(|e| format!("Failed to insert customer: {}", e))
+}
+
+/// Get all customers
+#[tauri::command]
+async fn get_customers(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    page: i64,
+    per_page: i64,
+    search: Option<String>,
+    sort_by: Option<String>,
+    sort_order: Option<String>,
+) -> Result<PaginatedResponse<Customer>, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    let query = ListQuery { page, per_page, search, sort_by, sort_order };
+    repo.list_customers(&query).await
+        .map_err(|e| format!("Failed to fetch customers: {}", e))
+}
+
+/// Update a customer
+#[tauri::command]
+async fn update_customer(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    id: i64,
+    full_name: String,
+    phone: String,
+    address: String,
+    email: Option<String>,
+    notes: Option<String>,
+) -> Result<Customer, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    let input = CustomerInput { full_name, phone, address, email, notes };
+    audit::attributed(actor, repo.update_customer(id, &input)).await
+        .map_err(|e| format!("Failed to update customer: {}", e))
+}
+
+/// Move a customer to the trash
+#[tauri::command]
+async fn delete_customer(
+    webview: tauri::Webview,
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    actor: AuditActor,
+    id: i64,
+) -> Result<String, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    audit::attributed(actor, move_to_trash(repo.as_ref(), &trash::CUSTOMERS, id, current_user_id(&sessions, &webview))).await?;
 
     Ok("Customer deleted successfully".to_string())
 }
 
+/// Take a customer out of the trash
+#[tauri::command]
+async fn restore_customer(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    id: i64,
+) -> Result<String, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    audit::attributed(actor, restore_from_trash(repo.as_ref(), &trash::CUSTOMERS, id)).await?;
+
+    Ok("Customer restored successfully".to_string())
+}
+
+/// Get the customers in the trash, most recently deleted first
+#[tauri::command]
+async fn get_deleted_customers(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    page: i64,
+    per_page: i64,
+    search: Option<String>,
+) -> Result<PaginatedResponse<TrashedRecord>, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    list_trash(repo.as_ref(), &trash::CUSTOMERS, page, per_page, search).await
+}
+
 // UnitGroup Model
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UnitGroup {
@@ -1599,20 +2770,7 @@ pub struct UnitGroup {
 /// Initialize unit_groups table schema
 #[tauri::command]
 fn init_unit_groups_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS unit_groups (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT NOT NULL UNIQUE,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
-        )
-    ";
-
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create unit_groups table: {}", e))?;
+    ensure_database_open(&db_state)?;
 
     Ok("Unit groups table initialized successfully".to_string())
 }
@@ -1648,12 +2806,12 @@ fn create_unit_group(
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
 
     let insert_sql = "INSERT INTO unit_groups (name) VALUES (?)";
-    db.execute(insert_sql, &[&name as &dyn rusqlite::ToSql])
+    let group_id = db.insert(insert_sql, &[&name as &dyn rusqlite::ToSql])
         .map_err(|e| format!("Failed to insert unit group: {}", e))?;
 
-    let group_sql = "SELECT id, name, created_at, updated_at FROM unit_groups WHERE name = ?";
+    let group_sql = "SELECT id, name, created_at, updated_at FROM unit_groups WHERE id = ?";
     let groups = db
-        .query(group_sql, &[&name as &dyn rusqlite::ToSql], |row| {
+        .query(group_sql, &[&group_id as &dyn rusqlite::ToSql], |row| {
             Ok(UnitGroup {
                 id: row.get(0)?,
                 name: row.get(1)?,
@@ -1686,33 +2844,7 @@ pub struct Unit {
 /// Initialize units table schema
 #[tauri::command]
 fn init_units_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS units (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT NOT NULL UNIQUE,
-            group_id INTEGER REFERENCES unit_groups(id),
-            ratio REAL NOT NULL DEFAULT 1.0,
-            is_base INTEGER NOT NULL DEFAULT 0,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
-        )
-    ";
-
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create units table: {}", e))?;
-
-    // Add new columns for existing databases
-    let alter_sqls = vec![
-        "ALTER TABLE units ADD COLUMN group_id INTEGER",
-        "ALTER TABLE units ADD COLUMN ratio REAL NOT NULL DEFAULT 1.0",
-        "ALTER TABLE units ADD COLUMN is_base INTEGER NOT NULL DEFAULT 0",
-    ];
-    for alter_sql in alter_sqls {
-        let _ = db.execute(alter_sql, &[]);
-    }
+    ensure_database_open(&db_state)?;
 
     Ok("Units table initialized successfully".to_string())
 }
@@ -1731,7 +2863,7 @@ fn create_unit(
 
     let is_base_int: i32 = if is_base { 1 } else { 0 };
     let insert_sql = "INSERT INTO units (name, group_id, ratio, is_base) VALUES (?, ?, ?, ?)";
-    db.execute(
+    let unit_id = db.insert(
         insert_sql,
         &[
             &name as &dyn rusqlite::ToSql,
@@ -1742,9 +2874,9 @@ fn create_unit(
     )
     .map_err(|e| format!("Failed to insert unit: {}", e))?;
 
-    let unit_sql = "SELECT u.id, u.name, u.created_at, u.updated_at, u.group_id, u.ratio, u.is_base, g.name FROM units u LEFT JOIN unit_groups g ON u.group_id = g.id WHERE u.name = ? ORDER BY u.id DESC LIMIT 1";
+    let unit_sql = "SELECT u.id, u.name, u.created_at, u.updated_at, u.group_id, u.ratio, u.is_base, g.name FROM units u LEFT JOIN unit_groups g ON u.group_id = g.id WHERE u.id = ?";
     let units = db
-        .query(unit_sql, &[&name as &dyn rusqlite::ToSql], |row| {
+        .query(unit_sql, &[&unit_id as &dyn rusqlite::ToSql], |row| {
             Ok(Unit {
                 id: row.get(0)?,
                 name: row.get(1)?,
@@ -1857,7 +2989,7 @@ fn delete_unit(
 }
 
 // Product Model
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Product {
     pub id: i64,
     pub name: String,
@@ -1876,48 +3008,17 @@ pub struct Product {
 /// Initialize products table schema
 #[tauri::command]
 fn init_products_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS products (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT NOT NULL,
-            description TEXT,
-            price REAL,
-            currency_id INTEGER,
-            supplier_id INTEGER,
-            stock_quantity REAL,
-            unit TEXT,
-            image_path TEXT,
-            bar_code TEXT,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (currency_id) REFERENCES currencies(id),
-            FOREIGN KEY (supplier_id) REFERENCES suppliers(id)
-        )
-    ";
-
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create products table: {}", e))?;
-
-    // Add new columns if they don't exist (for existing databases)
-    let alter_sqls = vec![
-        "ALTER TABLE products ADD COLUMN image_path TEXT",
-        "ALTER TABLE products ADD COLUMN bar_code TEXT",
-    ];
-    
-    for alter_sql in alter_sqls {
-        let _ = db.execute(alter_sql, &[]);
-    }
+    ensure_database_open(&db_state)?;
 
     Ok("Products table initialized successfully".to_string())
 }
 
 /// Create a new product
 #[tauri::command]
-fn create_product(
+async fn create_product(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
     name: String,
     description: Option<String>,
     price: Option<f64>,
@@ -1925,164 +3026,38 @@ fn create_product(
     supplier_id: Option<i64>,
     stock_quantity: Option<f64>,
     unit: Option<String>,
-    image_path: Option<String>,
-    bar_code: Option<String>,
-) -> Result<Product, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    // Insert new product
-    let insert_sql = "INSERT INTO products (name, description, price, currency_id, supplier_id, stock_quantity, unit, image_path, bar_code) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)";
-    let description_str: Option<&str> = description.as_ref().map(|s| s.as_str());
-    let unit_str: Option<&str> = unit.as_ref().map(|s| s.as_str());
-    let image_path_str: Option<&str> = image_path.as_ref().map(|s| s.as_str());
-    let bar_code_str: Option<&str> = bar_code.as_ref().map(|s| s.as_str());
-    db.execute(insert_sql, &[
-        &name as &dyn rusqlite::ToSql,
-        &description_str as &dyn rusqlite::ToSql,
-        &price as &dyn rusqlite::ToSql,
-        &currency_id as &dyn rusqlite::ToSql,
-        &supplier_id as &dyn rusqlite::ToSql,
-        &stock_quantity as &dyn rusqlite::ToSql,
-        &unit_str as &dyn rusqlite::ToSql,
-        &image_path_str as &dyn rusqlite::ToSql,
-        &bar_code_str as &dyn rusqlite::ToSql,
-    ])
-        .map_err(|e| format!("Failed to insert product: {}", e))?;
-
-    // Get the created product
-    let product_sql = "SELECT id, name, description, price, currency_id, supplier_id, stock_quantity, unit, image_path, bar_code, created_at, updated_at FROM products WHERE name = ? ORDER BY id DESC LIMIT 1";
-    let products = db
-        .query(product_sql, &[&name as &dyn rusqlite::ToSql], |row| {
-            Ok(Product {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                description: row.get::<_, Option<String>>(2)?,
-                price: row.get::<_, Option<f64>>(3)?,
-                currency_id: row.get::<_, Option<i64>>(4)?,
-                supplier_id: row.get::<_, Option<i64>>(5)?,
-                stock_quantity: row.get::<_, Option<f64>>(6)?,
-                unit: row.get::<_, Option<String>>(7)?,
-                image_path: row.get::<_, Option<String>>(8)?,
-                bar_code: row.get::<_, Option<String>>(9)?,
-                created_at: row.get(10)?,
-                updated_at: row.get(11)?,
-            })
-        })
-        .map_err(|e| format!("Failed to fetch product: {}", e))?;
-
-    if let Some(product) = products.first() {
-        Ok(product.clone())
-    } else {
-        Err("Failed to retrieve created product".to_string())
-    }
-}
-
-/// Get all products
-#[tauri::command]
-fn get_products(
-    db_state: State<'_, Mutex<Option<Database>>>,
-    page: i64,
-    per_page: i64,
-    search: Option<String>,
-    sort_by: Option<String>,
-    sort_order: Option<String>,
-) -> Result<PaginatedResponse<Product>, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let offset = (page - 1) * per_page;
-    let mut where_clause = String::new();
-    let mut params: Vec<serde_json::Value> = Vec::new();
-
-    if let Some(s) = search {
-        if !s.trim().is_empty() {
-            let search_term = format!("%{}%", s);
-            where_clause = "WHERE (name LIKE ?)".to_string();
-            params.push(serde_json::Value::String(search_term.clone()));
-        }
-    }
-
-    let count_sql = format!("SELECT COUNT(*) FROM products {}", where_clause);
-    let total: i64 = db.with_connection(|conn| {
-        let mut stmt = conn.prepare(&count_sql).map_err(|e| anyhow::anyhow!("{}", e))?;
-        let rusqlite_params: Vec<rusqlite::types::Value> = params.iter().map(|v| {
-            match v {
-                serde_json::Value::String(s) => rusqlite::types::Value::Text(s.clone()),
-                _ => rusqlite::types::Value::Null,
-            }
-        }).collect();
-        let count: i64 = stmt.query_row(rusqlite::params_from_iter(rusqlite_params.iter()), |row| row.get(0))
-            .map_err(|e| anyhow::anyhow!("{}", e))?;
-        Ok(count)
-    }).map_err(|e| format!("Failed to count products: {}", e))?;
-
-    let order_clause = if let Some(sort) = sort_by {
-        let order = sort_order.unwrap_or_else(|| "ASC".to_string());
-        let allowed_cols = ["name", "price", "stock_quantity", "created_at"];
-        if allowed_cols.contains(&sort.as_str()) {
-            format!("ORDER BY {} {}", sort, if order.to_uppercase() == "DESC" { "DESC" } else { "ASC" })
-        } else {
-            "ORDER BY created_at DESC".to_string()
-        }
-    } else {
-        "ORDER BY created_at DESC".to_string()
-    };
-
-    let sql = format!("SELECT id, name, description, price, currency_id, supplier_id, stock_quantity, unit, image_path, bar_code, created_at, updated_at FROM products {} {} LIMIT ? OFFSET ?", where_clause, order_clause);
-    
-    params.push(serde_json::Value::Number(serde_json::Number::from(per_page)));
-    params.push(serde_json::Value::Number(serde_json::Number::from(offset)));
-
-    let products = db.with_connection(|conn| {
-        let mut stmt = conn.prepare(&sql).map_err(|e| anyhow::anyhow!("{}", e))?;
-        let rusqlite_params: Vec<rusqlite::types::Value> = params.iter().map(|v| {
-             match v {
-                serde_json::Value::String(s) => rusqlite::types::Value::Text(s.clone()),
-                serde_json::Value::Number(n) => rusqlite::types::Value::Integer(n.as_i64().unwrap_or(0)),
-                _ => rusqlite::types::Value::Null,
-            }
-        }).collect();
-
-        let rows = stmt.query_map(rusqlite::params_from_iter(rusqlite_params.iter()), |row| {
-             Ok(Product {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                description: row.get::<_, Option<String>>(2)?,
-                price: row.get::<_, Option<f64>>(3)?,
-                currency_id: row.get::<_, Option<i64>>(4)?,
-                supplier_id: row.get::<_, Option<i64>>(5)?,
-                stock_quantity: row.get::<_, Option<f64>>(6)?,
-                unit: row.get::<_, Option<String>>(7)?,
-                image_path: row.get::<_, Option<String>>(8)?,
-                bar_code: row.get::<_, Option<String>>(9)?,
-                created_at: row.get(10)?,
-                updated_at: row.get(11)?,
-            })
-        }).map_err(|e| anyhow::anyhow!("{}", e))?;
-
-        let mut result = Vec::new();
-        for row in rows {
-            result.push(row.map_err(|e| anyhow::anyhow!("{}", e))?);
-        }
-        Ok(result)
-    }).map_err(|e| format!("Failed to fetch products: {}", e))?;
+    image_path: Option<String>,
+    bar_code: Option<String>,
+) -> Result<Product, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    let input = ProductInput { name, description, price, currency_id, supplier_id, stock_quantity, unit, image_path, bar_code };
+    audit::attributed(actor, repo.create_product(&input)).await
+        .map_err(|e| format!("Failed to insert product: {}", e))
+}
 
-    let total_pages = (total as f64 / per_page as f64).ceil() as i64;
-    
-    Ok(PaginatedResponse {
-        items: products,
-        total,
-        page,
-        per_page,
-        total_pages,
-    })
+/// Get all products
+#[tauri::command]
+async fn get_products(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    page: i64,
+    per_page: i64,
+    search: Option<String>,
+    sort_by: Option<String>,
+    sort_order: Option<String>,
+) -> Result<PaginatedResponse<Product>, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    let query = ListQuery { page, per_page, search, sort_by, sort_order };
+    repo.list_products(&query).await
+        .map_err(|e| format!("Failed to fetch products: {}", e))
 }
 
 /// Update a product
 #[tauri::command]
-fn update_product(
+async fn update_product(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
     id: i64,
     name: String,
     description: Option<String>,
@@ -2094,108 +3069,57 @@ fn update_product(
     image_path: Option<String>,
     bar_code: Option<String>,
 ) -> Result<Product, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    // Update product
-    let update_sql = "UPDATE products SET name = ?, description = ?, price = ?, currency_id = ?, supplier_id = ?, stock_quantity = ?, unit = ?, image_path = ?, bar_code = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?";
-    let description_str: Option<&str> = description.as_ref().map(|s| s.as_str());
-    let unit_str: Option<&str> = unit.as_ref().map(|s| s.as_str());
-    let image_path_str: Option<&str> = image_path.as_ref().map(|s| s.as_str());
-    let bar_code_str: Option<&str> = bar_code.as_ref().map(|s| s.as_str());
-    db.execute(update_sql, &[
-        &name as &dyn rusqlite::ToSql,
-        &description_str as &dyn rusqlite::ToSql,
-        &price as &dyn rusqlite::ToSql,
-        &currency_id as &dyn rusqlite::ToSql,
-        &supplier_id as &dyn rusqlite::ToSql,
-        &stock_quantity as &dyn rusqlite::ToSql,
-        &unit_str as &dyn rusqlite::ToSql,
-        &image_path_str as &dyn rusqlite::ToSql,
-        &bar_code_str as &dyn rusqlite::ToSql,
-        &id as &dyn rusqlite::ToSql,
-    ])
-        .map_err(|e| format!("Failed to update product: {}", e))?;
-
-    // Get the updated product
-    let product_sql = "SELECT id, name, description, price, currency_id, supplier_id, stock_quantity, unit, image_path, bar_code, created_at, updated_at FROM products WHERE id = ?";
-    let products = db
-        .query(product_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(Product {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                description: row.get::<_, Option<String>>(2)?,
-                price: row.get::<_, Option<f64>>(3)?,
-                currency_id: row.get::<_, Option<i64>>(4)?,
-                supplier_id: row.get::<_, Option<i64>>(5)?,
-                stock_quantity: row.get::<_, Option<f64>>(6)?,
-                unit: row.get::<_, Option<String>>(7)?,
-                image_path: row.get::<_, Option<String>>(8)?,
-                bar_code: row.get::<_, Option<String>>(9)?,
-                created_at: row.get(10)?,
-                updated_at: row.get(11)?,
-            })
-        })
-        .map_err(|e| format!("Failed to fetch product: {}", e))?;
-
-    if let Some(product) = products.first() {
-        Ok(product.clone())
-    } else {
-        Err("Failed to retrieve updated product".to_string())
-    }
+    let repo = repositories(&db_state, &surreal_state)?;
+    let input = ProductInput { name, description, price, currency_id, supplier_id, stock_quantity, unit, image_path, bar_code };
+    audit::attributed(actor, repo.update_product(id, &input)).await
+        .map_err(|e| format!("Failed to update product: {}", e))
 }
 
-/// Delete a product
+/// Move a product to the trash
 #[tauri::command]
-fn delete_product(
+async fn delete_product(
+    webview: tauri::Webview,
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    actor: AuditActor,
     id: i64,
 ) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    // Check if product is used in purchase_items
-    let purchase_check_sql = "SELECT COUNT(*) FROM purchase_items WHERE product_id = ?";
-    let purchase_count: i64 = db
-        .query(purchase_check_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(row.get(0)?)
-        })
-        .map_err(|e| format!("Failed to check purchase items: {}", e))?
-        .first()
-        .cloned()
-        .unwrap_or(0);
+    let repo = repositories(&db_state, &surreal_state)?;
+    audit::attributed(actor, move_to_trash(repo.as_ref(), &trash::PRODUCTS, id, current_user_id(&sessions, &webview))).await?;
 
-    // Check if product is used in sale_items
-    let sale_check_sql = "SELECT COUNT(*) FROM sale_items WHERE product_id = ?";
-    let sale_count: i64 = db
-        .query(sale_check_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(row.get(0)?)
-        })
-        .map_err(|e| format!("Failed to check sale items: {}", e))?
-        .first()
-        .cloned()
-        .unwrap_or(0);
+    Ok("Product deleted successfully".to_string())
+}
 
-    if purchase_count > 0 || sale_count > 0 {
-        let mut reasons = Vec::new();
-        if purchase_count > 0 {
-            reasons.push(format!("used in {} purchase(s)", purchase_count));
-        }
-        if sale_count > 0 {
-            reasons.push(format!("used in {} sale(s)", sale_count));
-        }
-        return Err(format!("Cannot delete product: it is {}", reasons.join(" and ")));
-    }
+/// Take a product out of the trash
+#[tauri::command]
+async fn restore_product(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    id: i64,
+) -> Result<String, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    audit::attributed(actor, restore_from_trash(repo.as_ref(), &trash::PRODUCTS, id)).await?;
 
-    let delete_sql = "DELETE FROM products WHERE id = ?";
-    db.execute(delete_sql, &[&id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to delete product: {}", e))?;
+    Ok("Product restored successfully".to_string())
+}
 
-    Ok("Product deleted successfully".to_string())
+/// Get the products in the trash, most recently deleted first
+#[tauri::command]
+async fn get_deleted_products(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    page: i64,
+    per_page: i64,
+    search: Option<String>,
+) -> Result<PaginatedResponse<TrashedRecord>, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    list_trash(repo.as_ref(), &trash::PRODUCTS, page, per_page, search).await
 }
 
 // Purchase Model
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Purchase {
     pub id: i64,
     pub supplier_id: i64,
@@ -2210,7 +3134,7 @@ pub struct Purchase {
 }
 
 // PurchaseItem Model
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct PurchaseItem {
     pub id: i64,
     pub purchase_id: i64,
@@ -2237,108 +3161,55 @@ pub struct PurchaseAdditionalCost {
     pub created_at: String,
 }
 
-/// Initialize purchases table schema
-#[tauri::command]
-fn init_purchases_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS purchases (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            supplier_id INTEGER NOT NULL,
-            date TEXT NOT NULL,
-            notes TEXT,
-            currency_id INTEGER,
-            total_amount REAL NOT NULL DEFAULT 0,
-            additional_cost REAL NOT NULL DEFAULT 0,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (supplier_id) REFERENCES suppliers(id),
-            FOREIGN KEY (currency_id) REFERENCES currencies(id)
-        )
-    ";
-
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create purchases table: {}", e))?;
+/// A new purchase: `create_purchase`'s arguments, or the body of `POST /api/v1/purchases`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PurchaseInput {
+    pub supplier_id: i64,
+    pub date: String,
+    pub notes: Option<String>,
+    pub currency_id: Option<i64>,
+    #[serde(default)]
+    pub additional_costs: Vec<AdditionalCostInput>,
+    pub items: Vec<PurchaseItemInput>,
+}
 
-    // Add additional_cost column if it doesn't exist (for existing databases)
-    let alter_sql = "ALTER TABLE purchases ADD COLUMN additional_cost REAL NOT NULL DEFAULT 0";
-    let _ = db.execute(alter_sql, &[]);
-    
-    // Add currency_id column if it doesn't exist (for existing databases)
-    let alter_currency_sql = "ALTER TABLE purchases ADD COLUMN currency_id INTEGER";
-    let _ = db.execute(alter_currency_sql, &[]);
-    
-    // Add batch_number column if it doesn't exist (for existing databases)
-    let alter_batch_sql = "ALTER TABLE purchases ADD COLUMN batch_number TEXT";
-    let _ = db.execute(alter_batch_sql, &[]);
-
-    let create_items_table_sql = "
-        CREATE TABLE IF NOT EXISTS purchase_items (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            purchase_id INTEGER NOT NULL,
-            product_id INTEGER NOT NULL,
-            unit_id INTEGER NOT NULL,
-            per_price REAL NOT NULL,
-            amount REAL NOT NULL,
-            total REAL NOT NULL,
-            per_unit REAL,
-            cost_price REAL,
-            wholesale_price REAL,
-            retail_price REAL,
-            expiry_date TEXT,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (purchase_id) REFERENCES purchases(id) ON DELETE CASCADE,
-            FOREIGN KEY (product_id) REFERENCES products(id),
-            FOREIGN KEY (unit_id) REFERENCES units(id)
-        )
-    ";
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PurchaseItemInput {
+    pub product_id: i64,
+    pub unit_id: i64,
+    pub per_price: f64,
+    pub amount: f64,
+    pub per_unit: Option<f64>,
+    pub cost_price: Option<f64>,
+    pub wholesale_price: Option<f64>,
+    pub retail_price: Option<f64>,
+    pub expiry_date: Option<String>,
+}
 
-    db.execute(create_items_table_sql, &[])
-        .map_err(|e| format!("Failed to create purchase_items table: {}", e))?;
-    
-    // Add new columns to purchase_items if they don't exist (for existing databases)
-    let alter_per_unit_sql = "ALTER TABLE purchase_items ADD COLUMN per_unit REAL";
-    let _ = db.execute(alter_per_unit_sql, &[]);
-    
-    let alter_cost_price_sql = "ALTER TABLE purchase_items ADD COLUMN cost_price REAL";
-    let _ = db.execute(alter_cost_price_sql, &[]);
-    
-    let alter_wholesale_price_sql = "ALTER TABLE purchase_items ADD COLUMN wholesale_price REAL";
-    let _ = db.execute(alter_wholesale_price_sql, &[]);
-    
-    let alter_retail_price_sql = "ALTER TABLE purchase_items ADD COLUMN retail_price REAL";
-    let _ = db.execute(alter_retail_price_sql, &[]);
-    
-    // Note: selling_price column will remain in old databases but won't be used
-    // SQLite doesn't support DROP COLUMN, so we'll just ignore it
-    
-    let alter_expiry_date_sql = "ALTER TABLE purchase_items ADD COLUMN expiry_date TEXT";
-    let _ = db.execute(alter_expiry_date_sql, &[]);
-
-    // Create purchase_additional_costs table
-    let create_additional_costs_table_sql = "
-        CREATE TABLE IF NOT EXISTS purchase_additional_costs (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            purchase_id INTEGER NOT NULL,
-            name TEXT NOT NULL,
-            amount REAL NOT NULL,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (purchase_id) REFERENCES purchases(id) ON DELETE CASCADE
-        )
-    ";
+impl From<(i64, i64, f64, f64, Option<f64>, Option<f64>, Option<f64>, Option<f64>, Option<String>)> for PurchaseItemInput {
+    /// An item as `create_purchase` and `update_purchase` take it:
+    /// (product_id, unit_id, per_price, amount, per_unit, cost_price, wholesale_price, retail_price, expiry_date)
+    fn from(
+        (product_id, unit_id, per_price, amount, per_unit, cost_price, wholesale_price, retail_price, expiry_date): (i64, i64, f64, f64, Option<f64>, Option<f64>, Option<f64>, Option<f64>, Option<String>),
+    ) -> Self {
+        PurchaseItemInput { product_id, unit_id, per_price, amount, per_unit, cost_price, wholesale_price, retail_price, expiry_date }
+    }
+}
 
-    db.execute(create_additional_costs_table_sql, &[])
-        .map_err(|e| format!("Failed to create purchase_additional_costs table: {}", e))?;
+/// Initialize purchases table schema
+#[tauri::command]
+fn init_purchases_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
+    ensure_database_open(&db_state)?;
 
     Ok("Purchases and purchase_items tables initialized successfully".to_string())
 }
 
 /// Create a new purchase with items
 #[tauri::command]
-fn create_purchase(
+async fn create_purchase(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
     supplier_id: i64,
     date: String,
     notes: Option<String>,
@@ -2346,281 +3217,144 @@ fn create_purchase(
     additional_costs: Vec<(String, f64)>, // (name, amount)
     items: Vec<(i64, i64, f64, f64, Option<f64>, Option<f64>, Option<f64>, Option<f64>, Option<String>)>, // (product_id, unit_id, per_price, amount, per_unit, cost_price, wholesale_price, retail_price, expiry_date)
 ) -> Result<Purchase, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    // Generate batch number
-    let batch_number_sql = "SELECT COALESCE(MAX(CAST(SUBSTR(batch_number, 7) AS INTEGER)), 0) + 1 FROM purchases WHERE batch_number LIKE 'BATCH-%'";
-    let batch_numbers = db
-        .query(batch_number_sql, &[], |row| {
-            Ok(row.get::<_, i64>(0)?)
-        })
-        .map_err(|e| format!("Failed to generate batch number: {}", e))?;
-    let batch_number = format!("BATCH-{:06}", batch_numbers.first().copied().unwrap_or(1));
-
-    // Calculate total amount from items + additional costs
-    let items_total: f64 = items.iter().map(|(_, _, per_price, amount, _, _, _, _, _)| per_price * amount).sum();
-    let additional_costs_total: f64 = additional_costs.iter().map(|(_, amount)| amount).sum();
-    let total_amount = items_total + additional_costs_total;
-
-    // Insert purchase (without additional_cost column since we're using the table now)
-    let notes_str: Option<&str> = notes.as_ref().map(|s| s.as_str());
-    let insert_sql = "INSERT INTO purchases (supplier_id, date, notes, currency_id, total_amount, batch_number) VALUES (?, ?, ?, ?, ?, ?)";
-    db.execute(insert_sql, &[
-        &supplier_id as &dyn rusqlite::ToSql,
-        &date as &dyn rusqlite::ToSql,
-        &notes_str as &dyn rusqlite::ToSql,
-        &currency_id as &dyn rusqlite::ToSql,
-        &total_amount as &dyn rusqlite::ToSql,
-        &batch_number as &dyn rusqlite::ToSql,
-    ])
-        .map_err(|e| format!("Failed to insert purchase: {}", e))?;
-
-    // Get the created purchase ID
-    let purchase_id_sql = "SELECT id FROM purchases WHERE supplier_id = ? AND date = ? ORDER BY id DESC LIMIT 1";
-    let purchase_ids = db
-        .query(purchase_id_sql, &[&supplier_id as &dyn rusqlite::ToSql, &date as &dyn rusqlite::ToSql], |row| {
-            Ok(row.get::<_, i64>(0)?)
-        })
-        .map_err(|e| format!("Failed to fetch purchase ID: {}", e))?;
-
-    let purchase_id = purchase_ids.first().ok_or("Failed to retrieve purchase ID")?;
-
-    // Insert purchase items
-    for (product_id, unit_id, per_price, amount, per_unit, cost_price, wholesale_price, retail_price, expiry_date) in items {
-        let total = per_price * amount;
-        let insert_item_sql = "INSERT INTO purchase_items (purchase_id, product_id, unit_id, per_price, amount, total, per_unit, cost_price, wholesale_price, retail_price, expiry_date) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
-        db.execute(insert_item_sql, &[
-            purchase_id as &dyn rusqlite::ToSql,
-            &product_id as &dyn rusqlite::ToSql,
-            &unit_id as &dyn rusqlite::ToSql,
-            &per_price as &dyn rusqlite::ToSql,
-            &amount as &dyn rusqlite::ToSql,
-            &total as &dyn rusqlite::ToSql,
-            &per_unit as &dyn rusqlite::ToSql,
-            &cost_price as &dyn rusqlite::ToSql,
-            &wholesale_price as &dyn rusqlite::ToSql,
-            &retail_price as &dyn rusqlite::ToSql,
-            &expiry_date as &dyn rusqlite::ToSql,
-        ])
-            .map_err(|e| format!("Failed to insert purchase item: {}", e))?;
-    }
-
-    // Insert additional costs
-    for (name, amount) in additional_costs {
-        let insert_cost_sql = "INSERT INTO purchase_additional_costs (purchase_id, name, amount) VALUES (?, ?, ?)";
-        db.execute(insert_cost_sql, &[
-            purchase_id as &dyn rusqlite::ToSql,
-            &name as &dyn rusqlite::ToSql,
-            &amount as &dyn rusqlite::ToSql,
-        ])
-            .map_err(|e| format!("Failed to insert purchase additional cost: {}", e))?;
-    }
-
-    // Get the created purchase (calculate additional_cost from the table for backward compatibility)
-    let purchase_sql = "SELECT id, supplier_id, date, notes, currency_id, total_amount, batch_number, created_at, updated_at FROM purchases WHERE id = ?";
-    let purchases = db
-        .query(purchase_sql, &[purchase_id as &dyn rusqlite::ToSql], |row| {
-            Ok(Purchase {
-                id: row.get(0)?,
-                supplier_id: row.get(1)?,
-                date: row.get(2)?,
-                notes: row.get(3)?,
-                currency_id: row.get(4)?,
-                total_amount: row.get(5)?,
-                additional_cost: additional_costs_total, // Sum of all additional costs
-                batch_number: row.get(6)?,
-                created_at: row.get(7)?,
-                updated_at: row.get(8)?,
-            })
-        })
-        .map_err(|e| format!("Failed to fetch purchase: {}", e))?;
-
-    if let Some(purchase) = purchases.first() {
-        Ok(purchase.clone())
-    } else {
-        Err("Failed to retrieve created purchase".to_string())
-    }
-}
-
-/// Get all purchases with pagination
-#[tauri::command]
-fn get_purchases(
-    db_state: State<'_, Mutex<Option<Database>>>,
-    page: i64,
-    per_page: i64,
-    search: Option<String>,
-    sort_by: Option<String>,
-    sort_order: Option<String>,
-) -> Result<PaginatedResponse<Purchase>, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let offset = (page - 1) * per_page;
-
-    // Build WHERE clause
-    let mut where_clause = String::new();
-    let mut params: Vec<serde_json::Value> = Vec::new();
-
-    if let Some(s) = search {
-        if !s.trim().is_empty() {
-            let search_term = format!("%{}%", s);
-            where_clause = "WHERE (CAST(p.date AS TEXT) LIKE ? OR p.notes LIKE ? OR p.supplier_id IN (SELECT id FROM suppliers WHERE full_name LIKE ?))".to_string();
-            params.push(serde_json::Value::String(search_term.clone()));
-            params.push(serde_json::Value::String(search_term.clone()));
-            params.push(serde_json::Value::String(search_term));
-        }
-    }
-
-    // Get total count
-    let count_sql = format!("SELECT COUNT(*) FROM purchases p {}", where_clause);
-    let total: i64 = db.with_connection(|conn| {
-        let mut stmt = conn.prepare(&count_sql).map_err(|e| anyhow::anyhow!("{}", e))?;
-        let rusqlite_params: Vec<rusqlite::types::Value> = params.iter().map(|v| {
-            match v {
-                serde_json::Value::String(s) => rusqlite::types::Value::Text(s.clone()),
-                _ => rusqlite::types::Value::Null,
-            }
-        }).collect();
-        let count: i64 = stmt.query_row(rusqlite::params_from_iter(rusqlite_params.iter()), |row| row.get(0))
-            .map_err(|e| anyhow::anyhow!("{}", e))?;
-        Ok(count)
-    }).map_err(|e| format!("Failed to count purchases: {}", e))?;
-
-    // Build Order By
-    let order_clause = if let Some(sort) = sort_by {
-        let order = sort_order.unwrap_or_else(|| "DESC".to_string());
-        let allowed_cols = ["date", "total_amount", "created_at"];
-        if allowed_cols.contains(&sort.as_str()) {
-            format!("ORDER BY p.{} {}", sort, if order.to_uppercase() == "DESC" { "DESC" } else { "ASC" })
-        } else {
-            "ORDER BY p.date DESC, p.created_at DESC".to_string()
-        }
-    } else {
-        "ORDER BY p.date DESC, p.created_at DESC".to_string()
+    let repo = repositories(&db_state, &surreal_state)?;
+    let purchase = PurchaseInput {
+        supplier_id,
+        date,
+        notes,
+        currency_id,
+        additional_costs: additional_costs.into_iter().map(AdditionalCostInput::from).collect(),
+        items: items.into_iter().map(PurchaseItemInput::from).collect(),
     };
-
-    let sql = format!("SELECT p.id, p.supplier_id, p.date, p.notes, p.currency_id, p.total_amount, p.batch_number, p.created_at, p.updated_at FROM purchases p {} {} LIMIT ? OFFSET ?", where_clause, order_clause);
-    
-    params.push(serde_json::Value::Number(serde_json::Number::from(per_page)));
-    params.push(serde_json::Value::Number(serde_json::Number::from(offset)));
-
-    let purchases = db.with_connection(|conn| {
-        let mut stmt = conn.prepare(&sql).map_err(|e| anyhow::anyhow!("{}", e))?;
-        let rusqlite_params: Vec<rusqlite::types::Value> = params.iter().map(|v| {
-            match v {
-                serde_json::Value::String(s) => rusqlite::types::Value::Text(s.clone()),
-                serde_json::Value::Number(n) => rusqlite::types::Value::Integer(n.as_i64().unwrap_or(0)),
-                _ => rusqlite::types::Value::Null,
-            }
-        }).collect();
-
-        let rows = stmt.query_map(rusqlite::params_from_iter(rusqlite_params.iter()), |row| {
-            Ok(Purchase {
-                id: row.get(0)?,
-                supplier_id: row.get(1)?,
-                date: row.get(2)?,
-                notes: row.get(3)?,
-                currency_id: row.get(4)?,
-                total_amount: row.get(5)?,
-                additional_cost: 0.0, // Will be calculated from purchase_additional_costs table
-                batch_number: row.get(6)?,
-                created_at: row.get(7)?,
-                updated_at: row.get(8)?,
-            })
-        }).map_err(|e| anyhow::anyhow!("{}", e))?;
-
-        let mut result = Vec::new();
-        for row in rows {
-            let mut purchase = row.map_err(|e| anyhow::anyhow!("{}", e))?;
-            // Calculate additional_cost from purchase_additional_costs table
-            let additional_costs_sql = "SELECT COALESCE(SUM(amount), 0) FROM purchase_additional_costs WHERE purchase_id = ?";
-            let additional_cost: f64 = conn.query_row(additional_costs_sql, &[&purchase.id as &dyn rusqlite::ToSql], |row| {
-                Ok(row.get::<_, f64>(0)?)
-            }).unwrap_or(0.0);
-            purchase.additional_cost = additional_cost;
-            result.push(purchase);
-        }
-        Ok(result)
-    }).map_err(|e| format!("Failed to fetch purchases: {}", e))?;
-
-    let total_pages = (total as f64 / per_page as f64).ceil() as i64;
-    
-    Ok(PaginatedResponse {
-        items: purchases,
-        total,
-        page,
-        per_page,
-        total_pages,
-    })
+    audit::attributed(actor, repo.create_purchase(&purchase)).await
+        .map_err(|e| format!("Failed to create purchase: {}", e))
 }
 
-/// Get a single purchase with its items
-#[tauri::command]
-fn get_purchase(db_state: State<'_, Mutex<Option<Database>>>, id: i64) -> Result<(Purchase, Vec<PurchaseItem>), String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
+/// Insert a purchase with its items and additional costs under the next batch number
+pub(crate) fn create_purchase_internal(db: &Database, purchase: &PurchaseInput) -> Result<Purchase, String> {
+    let PurchaseInput { supplier_id, ref date, ref notes, currency_id, ref additional_costs, ref items } = *purchase;
 
-    // Get purchase
-    let purchase_sql = "SELECT id, supplier_id, date, notes, currency_id, total_amount, batch_number, created_at, updated_at FROM purchases WHERE id = ?";
-    let purchases = db
-        .query(purchase_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(Purchase {
-                id: row.get(0)?,
-                supplier_id: row.get(1)?,
-                date: row.get(2)?,
-                notes: row.get(3)?,
-                currency_id: row.get(4)?,
-                total_amount: row.get(5)?,
-                additional_cost: 0.0, // Will be calculated from purchase_additional_costs table
-                batch_number: row.get(6)?,
-                created_at: row.get(7)?,
-                updated_at: row.get(8)?,
+    db.transaction(|db| {
+        // Generate batch number
+        let batch_number_sql = "SELECT COALESCE(MAX(CAST(SUBSTR(batch_number, 7) AS INTEGER)), 0) + 1 FROM purchases WHERE batch_number LIKE 'BATCH-%'";
+        let batch_numbers = db
+            .query(batch_number_sql, &[], |row| {
+                Ok(row.get::<_, i64>(0)?)
             })
-        })
-        .map_err(|e| format!("Failed to fetch purchase: {}", e))?;
-
-    let mut purchase = purchases.first().ok_or("Purchase not found")?.clone();
-
-    // Calculate additional_cost from purchase_additional_costs table
-    let additional_costs_sql = "SELECT COALESCE(SUM(amount), 0) FROM purchase_additional_costs WHERE purchase_id = ?";
-    let additional_cost_results: Vec<f64> = db
-        .query(additional_costs_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(row.get::<_, f64>(0)?)
-        })
-        .map_err(|e| format!("Failed to calculate additional cost: {}", e))?;
-    let additional_cost = additional_cost_results.first().copied().unwrap_or(0.0);
-    purchase.additional_cost = additional_cost;
-
-    // Get purchase items
-    let items_sql = "SELECT id, purchase_id, product_id, unit_id, per_price, amount, total, per_unit, cost_price, wholesale_price, retail_price, expiry_date, created_at FROM purchase_items WHERE purchase_id = ?";
-    let items = db
-        .query(items_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(PurchaseItem {
-                id: row.get(0)?,
-                purchase_id: row.get(1)?,
-                product_id: row.get(2)?,
-                unit_id: row.get(3)?,
-                per_price: row.get(4)?,
-                amount: row.get(5)?,
-                total: row.get(6)?,
-                per_unit: row.get(7)?,
-                cost_price: row.get(8)?,
-                wholesale_price: row.get(9)?,
-                retail_price: row.get(10)?,
-                expiry_date: row.get(11)?,
-                created_at: row.get(12)?,
+            .map_err(|e| format!("Failed to generate batch number: {}", e))?;
+        let batch_number = format!("BATCH-{:06}", batch_numbers.first().copied().unwrap_or(1));
+
+        // Calculate total amount from items + additional costs
+        let items_total: f64 = items.iter().map(|item| item.per_price * item.amount).sum();
+        let additional_costs_total: f64 = additional_costs.iter().map(|cost| cost.amount).sum();
+        let total_amount = items_total + additional_costs_total;
+
+        // Insert purchase (without additional_cost column since we're using the table now)
+        let notes_str: Option<&str> = notes.as_ref().map(|s| s.as_str());
+        let insert_sql = "INSERT INTO purchases (supplier_id, date, notes, currency_id, total_amount, batch_number) VALUES (?, ?, ?, ?, ?, ?)";
+        let purchase_id = db.insert(insert_sql, &[
+            &supplier_id as &dyn rusqlite::ToSql,
+            date as &dyn rusqlite::ToSql,
+            &notes_str as &dyn rusqlite::ToSql,
+            &currency_id as &dyn rusqlite::ToSql,
+            &total_amount as &dyn rusqlite::ToSql,
+            &batch_number as &dyn rusqlite::ToSql,
+        ])
+            .map_err(|e| format!("Failed to insert purchase: {}", e))?;
+
+        // Insert purchase items
+        for item in items {
+            let total = item.per_price * item.amount;
+            let insert_item_sql = "INSERT INTO purchase_items (purchase_id, product_id, unit_id, per_price, amount, total, per_unit, cost_price, wholesale_price, retail_price, expiry_date) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+            db.execute(insert_item_sql, &[
+                &purchase_id as &dyn rusqlite::ToSql,
+                &item.product_id as &dyn rusqlite::ToSql,
+                &item.unit_id as &dyn rusqlite::ToSql,
+                &item.per_price as &dyn rusqlite::ToSql,
+                &item.amount as &dyn rusqlite::ToSql,
+                &total as &dyn rusqlite::ToSql,
+                &item.per_unit as &dyn rusqlite::ToSql,
+                &item.cost_price as &dyn rusqlite::ToSql,
+                &item.wholesale_price as &dyn rusqlite::ToSql,
+                &item.retail_price as &dyn rusqlite::ToSql,
+                &item.expiry_date as &dyn rusqlite::ToSql,
+            ])
+                .map_err(|e| format!("Failed to insert purchase item: {}", e))?;
+        }
+
+        // Insert additional costs
+        for cost in additional_costs {
+            let insert_cost_sql = "INSERT INTO purchase_additional_costs (purchase_id, name, amount) VALUES (?, ?, ?)";
+            db.execute(insert_cost_sql, &[
+                &purchase_id as &dyn rusqlite::ToSql,
+                &cost.name as &dyn rusqlite::ToSql,
+                &cost.amount as &dyn rusqlite::ToSql,
+            ])
+                .map_err(|e| format!("Failed to insert purchase additional cost: {}", e))?;
+        }
+
+        // Get the created purchase (calculate additional_cost from the table for backward compatibility)
+        let purchase_sql = "SELECT id, supplier_id, date, notes, currency_id, total_amount, batch_number, created_at, updated_at FROM purchases WHERE id = ?";
+        let purchases = db
+            .query(purchase_sql, &[&purchase_id as &dyn rusqlite::ToSql], |row| {
+                Ok(Purchase {
+                    id: row.get(0)?,
+                    supplier_id: row.get(1)?,
+                    date: row.get(2)?,
+                    notes: row.get(3)?,
+                    currency_id: row.get(4)?,
+                    total_amount: row.get(5)?,
+                    additional_cost: additional_costs_total, // Sum of all additional costs
+                    batch_number: row.get(6)?,
+                    created_at: row.get(7)?,
+                    updated_at: row.get(8)?,
+                })
             })
-        })
-        .map_err(|e| format!("Failed to fetch purchase items: {}", e))?;
+            .map_err(|e| format!("Failed to fetch purchase: {}", e))?;
+
+        let purchase = purchases.first().cloned().ok_or("Failed to retrieve created purchase")?;
+        events::record(db, events::PURCHASE_CREATED, &serde_json::json!({ "purchase": purchase, "items": items }))
+            .map_err(|e| format!("Failed to record purchase event: {}", e))?;
+        Ok(purchase)
+    })
+}
+
+/// Get all purchases with pagination
+#[tauri::command]
+async fn get_purchases(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    page: i64,
+    per_page: i64,
+    search: Option<String>,
+    sort_by: Option<String>,
+    sort_order: Option<String>,
+) -> Result<PaginatedResponse<Purchase>, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    let query = ListQuery { page, per_page, search, sort_by, sort_order };
+    repo.list_purchases(&query).await
+        .map_err(|e| format!("Failed to fetch purchases: {}", e))
+}
 
-    Ok((purchase, items))
+/// Get a single purchase with its items
+#[tauri::command]
+async fn get_purchase(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    id: i64,
+) -> Result<(Purchase, Vec<PurchaseItem>), String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    repo.get_purchase(id).await
+        .map_err(|e| format!("Failed to fetch purchase: {}", e))?
+        .ok_or_else(|| "Purchase not found".to_string())
 }
 
 /// Update a purchase
 #[tauri::command]
-fn update_purchase(
+async fn update_purchase(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
     id: i64,
     supplier_id: i64,
     date: String,
@@ -2629,110 +3363,153 @@ fn update_purchase(
     additional_costs: Vec<(String, f64)>, // (name, amount)
     items: Vec<(i64, i64, f64, f64, Option<f64>, Option<f64>, Option<f64>, Option<f64>, Option<String>)>, // (product_id, unit_id, per_price, amount, per_unit, cost_price, wholesale_price, retail_price, expiry_date)
 ) -> Result<Purchase, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    // Calculate total amount from items + additional costs
-    let items_total: f64 = items.iter().map(|(_, _, per_price, amount, _, _, _, _, _)| per_price * amount).sum();
-    let additional_costs_total: f64 = additional_costs.iter().map(|(_, amount)| amount).sum();
-    let total_amount = items_total + additional_costs_total;
-
-    // Update purchase
-    let notes_str: Option<&str> = notes.as_ref().map(|s| s.as_str());
-    let update_sql = "UPDATE purchases SET supplier_id = ?, date = ?, notes = ?, currency_id = ?, total_amount = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?";
-    db.execute(update_sql, &[
-        &supplier_id as &dyn rusqlite::ToSql,
-        &date as &dyn rusqlite::ToSql,
-        &notes_str as &dyn rusqlite::ToSql,
-        &currency_id as &dyn rusqlite::ToSql,
-        &total_amount as &dyn rusqlite::ToSql,
-        &id as &dyn rusqlite::ToSql,
-    ])
-        .map_err(|e| format!("Failed to update purchase: {}", e))?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    let purchase = PurchaseInput {
+        supplier_id,
+        date,
+        notes,
+        currency_id,
+        additional_costs: additional_costs.into_iter().map(AdditionalCostInput::from).collect(),
+        items: items.into_iter().map(PurchaseItemInput::from).collect(),
+    };
+    audit::attributed(actor, repo.update_purchase(id, &purchase)).await
+        .map_err(|e| format!("Failed to update purchase: {}", e))
+}
 
-    // Delete existing items
-    let delete_items_sql = "DELETE FROM purchase_items WHERE purchase_id = ?";
-    db.execute(delete_items_sql, &[&id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to delete purchase items: {}", e))?;
+/// Replace a purchase's details, items and additional costs
+pub(crate) fn update_purchase_internal(db: &Database, id: i64, purchase: &PurchaseInput) -> Result<Purchase, String> {
+    let PurchaseInput { supplier_id, ref date, ref notes, currency_id, ref additional_costs, ref items } = *purchase;
 
-    // Delete existing additional costs
-    let delete_costs_sql = "DELETE FROM purchase_additional_costs WHERE purchase_id = ?";
-    db.execute(delete_costs_sql, &[&id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to delete purchase additional costs: {}", e))?;
+    db.transaction(|db| {
+        // Calculate total amount from items + additional costs
+        let items_total: f64 = items.iter().map(|item| item.per_price * item.amount).sum();
+        let additional_costs_total: f64 = additional_costs.iter().map(|cost| cost.amount).sum();
+        let total_amount = items_total + additional_costs_total;
 
-    // Insert new items
-    for (product_id, unit_id, per_price, amount, per_unit, cost_price, wholesale_price, retail_price, expiry_date) in items {
-        let total = per_price * amount;
-        let insert_item_sql = "INSERT INTO purchase_items (purchase_id, product_id, unit_id, per_price, amount, total, per_unit, cost_price, wholesale_price, retail_price, expiry_date) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
-        db.execute(insert_item_sql, &[
+        // Update purchase
+        let notes_str: Option<&str> = notes.as_ref().map(|s| s.as_str());
+        let update_sql = "UPDATE purchases SET supplier_id = ?, date = ?, notes = ?, currency_id = ?, total_amount = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?";
+        db.execute(update_sql, &[
+            &supplier_id as &dyn rusqlite::ToSql,
+            date as &dyn rusqlite::ToSql,
+            &notes_str as &dyn rusqlite::ToSql,
+            &currency_id as &dyn rusqlite::ToSql,
+            &total_amount as &dyn rusqlite::ToSql,
             &id as &dyn rusqlite::ToSql,
-            &product_id as &dyn rusqlite::ToSql,
-            &unit_id as &dyn rusqlite::ToSql,
-            &per_price as &dyn rusqlite::ToSql,
-            &amount as &dyn rusqlite::ToSql,
-            &total as &dyn rusqlite::ToSql,
-            &per_unit as &dyn rusqlite::ToSql,
-            &cost_price as &dyn rusqlite::ToSql,
-            &wholesale_price as &dyn rusqlite::ToSql,
-            &retail_price as &dyn rusqlite::ToSql,
-            &expiry_date as &dyn rusqlite::ToSql,
         ])
-            .map_err(|e| format!("Failed to insert purchase item: {}", e))?;
-    }
+            .map_err(|e| format!("Failed to update purchase: {}", e))?;
+
+        // Delete existing items
+        let delete_items_sql = "DELETE FROM purchase_items WHERE purchase_id = ?";
+        db.execute(delete_items_sql, &[&id as &dyn rusqlite::ToSql])
+            .map_err(|e| format!("Failed to delete purchase items: {}", e))?;
+
+        // Delete existing additional costs
+        let delete_costs_sql = "DELETE FROM purchase_additional_costs WHERE purchase_id = ?";
+        db.execute(delete_costs_sql, &[&id as &dyn rusqlite::ToSql])
+            .map_err(|e| format!("Failed to delete purchase additional costs: {}", e))?;
+
+        // Insert new items
+        for item in items {
+            let total = item.per_price * item.amount;
+            let insert_item_sql = "INSERT INTO purchase_items (purchase_id, product_id, unit_id, per_price, amount, total, per_unit, cost_price, wholesale_price, retail_price, expiry_date) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+            db.execute(insert_item_sql, &[
+                &id as &dyn rusqlite::ToSql,
+                &item.product_id as &dyn rusqlite::ToSql,
+                &item.unit_id as &dyn rusqlite::ToSql,
+                &item.per_price as &dyn rusqlite::ToSql,
+                &item.amount as &dyn rusqlite::ToSql,
+                &total as &dyn rusqlite::ToSql,
+                &item.per_unit as &dyn rusqlite::ToSql,
+                &item.cost_price as &dyn rusqlite::ToSql,
+                &item.wholesale_price as &dyn rusqlite::ToSql,
+                &item.retail_price as &dyn rusqlite::ToSql,
+                &item.expiry_date as &dyn rusqlite::ToSql,
+            ])
+                .map_err(|e| format!("Failed to insert purchase item: {}", e))?;
+        }
 
-    // Insert additional costs
-    for (name, amount) in additional_costs {
-        let insert_cost_sql = "INSERT INTO purchase_additional_costs (purchase_id, name, amount) VALUES (?, ?, ?)";
-        db.execute(insert_cost_sql, &[
-            &id as &dyn rusqlite::ToSql,
-            &name as &dyn rusqlite::ToSql,
-            &amount as &dyn rusqlite::ToSql,
-        ])
-            .map_err(|e| format!("Failed to insert purchase additional cost: {}", e))?;
-    }
+        // Insert additional costs
+        for cost in additional_costs {
+            let insert_cost_sql = "INSERT INTO purchase_additional_costs (purchase_id, name, amount) VALUES (?, ?, ?)";
+            db.execute(insert_cost_sql, &[
+                &id as &dyn rusqlite::ToSql,
+                &cost.name as &dyn rusqlite::ToSql,
+                &cost.amount as &dyn rusqlite::ToSql,
+            ])
+                .map_err(|e| format!("Failed to insert purchase additional cost: {}", e))?;
+        }
 
-    // Get the updated purchase (calculate additional_cost from the table for backward compatibility)
-    let purchase_sql = "SELECT id, supplier_id, date, notes, currency_id, total_amount, batch_number, created_at, updated_at FROM purchases WHERE id = ?";
-    let purchases = db
-        .query(purchase_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(Purchase {
-                id: row.get(0)?,
-                supplier_id: row.get(1)?,
-                date: row.get(2)?,
-                notes: row.get(3)?,
-                currency_id: row.get(4)?,
-                total_amount: row.get(5)?,
-                additional_cost: additional_costs_total, // Sum of all additional costs
-                batch_number: row.get(6)?,
-                created_at: row.get(7)?,
-                updated_at: row.get(8)?,
+        // Get the updated purchase (calculate additional_cost from the table for backward compatibility)
+        let purchase_sql = "SELECT id, supplier_id, date, notes, currency_id, total_amount, batch_number, created_at, updated_at FROM purchases WHERE id = ?";
+        let purchases = db
+            .query(purchase_sql, &[&id as &dyn rusqlite::ToSql], |row| {
+                Ok(Purchase {
+                    id: row.get(0)?,
+                    supplier_id: row.get(1)?,
+                    date: row.get(2)?,
+                    notes: row.get(3)?,
+                    currency_id: row.get(4)?,
+                    total_amount: row.get(5)?,
+                    additional_cost: additional_costs_total, // Sum of all additional costs
+                    batch_number: row.get(6)?,
+                    created_at: row.get(7)?,
+                    updated_at: row.get(8)?,
+                })
             })
-        })
-        .map_err(|e| format!("Failed to fetch purchase: {}", e))?;
+            .map_err(|e| format!("Failed to fetch purchase: {}", e))?;
 
-    if let Some(purchase) = purchases.first() {
-        Ok(purchase.clone())
-    } else {
-        Err("Failed to retrieve updated purchase".to_string())
-    }
+        if let Some(purchase) = purchases.first() {
+            Ok(purchase.clone())
+        } else {
+            Err("Failed to retrieve updated purchase".to_string())
+        }
+    })
 }
 
-/// Delete a purchase (items will be deleted automatically due to CASCADE)
+/// Move a purchase to the trash
 #[tauri::command]
-fn delete_purchase(
+async fn delete_purchase(
+    webview: tauri::Webview,
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    actor: AuditActor,
     id: i64,
 ) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let delete_sql = "DELETE FROM purchases WHERE id = ?";
-    db.execute(delete_sql, &[&id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to delete purchase: {}", e))?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    audit::attributed(actor, move_to_trash(repo.as_ref(), &trash::PURCHASES, id, current_user_id(&sessions, &webview))).await?;
 
     Ok("Purchase deleted successfully".to_string())
 }
 
+/// Take a purchase out of the trash
+#[tauri::command]
+async fn restore_purchase(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    id: i64,
+) -> Result<String, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    audit::attributed(actor, restore_from_trash(repo.as_ref(), &trash::PURCHASES, id)).await?;
+
+    Ok("Purchase restored successfully".to_string())
+}
+
+/// Get the purchases in the trash, most recently deleted first
+#[tauri::command]
+async fn get_deleted_purchases(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    page: i64,
+    per_page: i64,
+    search: Option<String>,
+) -> Result<PaginatedResponse<TrashedRecord>, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    list_trash(repo.as_ref(), &trash::PURCHASES, page, per_page, search).await
+}
+
 /// Create a purchase item (standalone, for adding items to existing purchase)
 #[tauri::command]
 fn create_purchase_item(
@@ -2746,56 +3523,58 @@ fn create_purchase_item(
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
 
-    let total = per_price * amount;
+    db.transaction(|db| {
+        let total = per_price * amount;
 
-    let insert_sql = "INSERT INTO purchase_items (purchase_id, product_id, unit_id, per_price, amount, total, per_unit, cost_price, wholesale_price, retail_price, expiry_date) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
-    db.execute(insert_sql, &[
-        &purchase_id as &dyn rusqlite::ToSql,
-        &product_id as &dyn rusqlite::ToSql,
-        &unit_id as &dyn rusqlite::ToSql,
-        &per_price as &dyn rusqlite::ToSql,
-        &amount as &dyn rusqlite::ToSql,
-        &total as &dyn rusqlite::ToSql,
-        &None::<f64> as &dyn rusqlite::ToSql,
-        &None::<f64> as &dyn rusqlite::ToSql,
-        &None::<f64> as &dyn rusqlite::ToSql,
-        &None::<f64> as &dyn rusqlite::ToSql,
-        &None::<String> as &dyn rusqlite::ToSql,
-    ])
-        .map_err(|e| format!("Failed to insert purchase item: {}", e))?;
+        let insert_sql = "INSERT INTO purchase_items (purchase_id, product_id, unit_id, per_price, amount, total, per_unit, cost_price, wholesale_price, retail_price, expiry_date) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+        let item_id = db.insert(insert_sql, &[
+            &purchase_id as &dyn rusqlite::ToSql,
+            &product_id as &dyn rusqlite::ToSql,
+            &unit_id as &dyn rusqlite::ToSql,
+            &per_price as &dyn rusqlite::ToSql,
+            &amount as &dyn rusqlite::ToSql,
+            &total as &dyn rusqlite::ToSql,
+            &None::<f64> as &dyn rusqlite::ToSql,
+            &None::<f64> as &dyn rusqlite::ToSql,
+            &None::<f64> as &dyn rusqlite::ToSql,
+            &None::<f64> as &dyn rusqlite::ToSql,
+            &None::<String> as &dyn rusqlite::ToSql,
+        ])
+            .map_err(|e| format!("Failed to insert purchase item: {}", e))?;
 
-    // Update purchase total (items total + additional_cost)
-    let update_purchase_sql = "UPDATE purchases SET total_amount = (SELECT COALESCE(SUM(total), 0) FROM purchase_items WHERE purchase_id = ?) + COALESCE((SELECT additional_cost FROM purchases WHERE id = ?), 0), updated_at = CURRENT_TIMESTAMP WHERE id = ?";
-    db.execute(update_purchase_sql, &[&purchase_id as &dyn rusqlite::ToSql, &purchase_id as &dyn rusqlite::ToSql, &purchase_id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to update purchase total: {}", e))?;
+        // Update purchase total (items total + additional_cost)
+        let update_purchase_sql = "UPDATE purchases SET total_amount = (SELECT COALESCE(SUM(total), 0) FROM purchase_items WHERE purchase_id = ?) + COALESCE((SELECT additional_cost FROM purchases WHERE id = ?), 0), updated_at = CURRENT_TIMESTAMP WHERE id = ?";
+        db.execute(update_purchase_sql, &[&purchase_id as &dyn rusqlite::ToSql, &purchase_id as &dyn rusqlite::ToSql, &purchase_id as &dyn rusqlite::ToSql])
+            .map_err(|e| format!("Failed to update purchase total: {}", e))?;
 
-    // Get the created item
-    let item_sql = "SELECT id, purchase_id, product_id, unit_id, per_price, amount, total, per_unit, cost_price, wholesale_price, retail_price, expiry_date, created_at FROM purchase_items WHERE purchase_id = ? AND product_id = ? ORDER BY id DESC LIMIT 1";
-    let items = db
-        .query(item_sql, &[&purchase_id as &dyn rusqlite::ToSql, &product_id as &dyn rusqlite::ToSql], |row| {
-            Ok(PurchaseItem {
-                id: row.get(0)?,
-                purchase_id: row.get(1)?,
-                product_id: row.get(2)?,
-                unit_id: row.get(3)?,
-                per_price: row.get(4)?,
-                amount: row.get(5)?,
-                total: row.get(6)?,
-                per_unit: row.get(7)?,
-                cost_price: row.get(8)?,
-                wholesale_price: row.get(9)?,
-                retail_price: row.get(10)?,
-                expiry_date: row.get(11)?,
-                created_at: row.get(12)?,
+        // Get the created item
+        let item_sql = "SELECT id, purchase_id, product_id, unit_id, per_price, amount, total, per_unit, cost_price, wholesale_price, retail_price, expiry_date, created_at FROM purchase_items WHERE id = ?";
+        let items = db
+            .query(item_sql, &[&item_id as &dyn rusqlite::ToSql], |row| {
+                Ok(PurchaseItem {
+                    id: row.get(0)?,
+                    purchase_id: row.get(1)?,
+                    product_id: row.get(2)?,
+                    unit_id: row.get(3)?,
+                    per_price: row.get(4)?,
+                    amount: row.get(5)?,
+                    total: row.get(6)?,
+                    per_unit: row.get(7)?,
+                    cost_price: row.get(8)?,
+                    wholesale_price: row.get(9)?,
+                    retail_price: row.get(10)?,
+                    expiry_date: row.get(11)?,
+                    created_at: row.get(12)?,
+                })
             })
-        })
-        .map_err(|e| format!("Failed to fetch purchase item: {}", e))?;
+            .map_err(|e| format!("Failed to fetch purchase item: {}", e))?;
 
-    if let Some(item) = items.first() {
-        Ok(item.clone())
-    } else {
-        Err("Failed to retrieve created purchase item".to_string())
-    }
+        if let Some(item) = items.first() {
+            Ok(item.clone())
+        } else {
+            Err("Failed to retrieve created purchase item".to_string())
+        }
+    })
 }
 
 /// Get purchase items for a purchase
@@ -2863,66 +3642,68 @@ fn update_purchase_item(
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
 
-    let total = per_price * amount;
+    db.transaction(|db| {
+        let total = per_price * amount;
 
-    let update_sql = "UPDATE purchase_items SET product_id = ?, unit_id = ?, per_price = ?, amount = ?, total = ?, per_unit = ?, cost_price = ?, wholesale_price = ?, retail_price = ?, expiry_date = ? WHERE id = ?";
-    db.execute(update_sql, &[
-        &product_id as &dyn rusqlite::ToSql,
-        &unit_id as &dyn rusqlite::ToSql,
-        &per_price as &dyn rusqlite::ToSql,
-        &amount as &dyn rusqlite::ToSql,
-        &total as &dyn rusqlite::ToSql,
-        &None::<f64> as &dyn rusqlite::ToSql,
-        &None::<f64> as &dyn rusqlite::ToSql,
-        &None::<f64> as &dyn rusqlite::ToSql,
-        &None::<f64> as &dyn rusqlite::ToSql,
-        &None::<String> as &dyn rusqlite::ToSql,
-        &id as &dyn rusqlite::ToSql,
-    ])
-        .map_err(|e| format!("Failed to update purchase item: {}", e))?;
+        let update_sql = "UPDATE purchase_items SET product_id = ?, unit_id = ?, per_price = ?, amount = ?, total = ?, per_unit = ?, cost_price = ?, wholesale_price = ?, retail_price = ?, expiry_date = ? WHERE id = ?";
+        db.execute(update_sql, &[
+            &product_id as &dyn rusqlite::ToSql,
+            &unit_id as &dyn rusqlite::ToSql,
+            &per_price as &dyn rusqlite::ToSql,
+            &amount as &dyn rusqlite::ToSql,
+            &total as &dyn rusqlite::ToSql,
+            &None::<f64> as &dyn rusqlite::ToSql,
+            &None::<f64> as &dyn rusqlite::ToSql,
+            &None::<f64> as &dyn rusqlite::ToSql,
+            &None::<f64> as &dyn rusqlite::ToSql,
+            &None::<String> as &dyn rusqlite::ToSql,
+            &id as &dyn rusqlite::ToSql,
+        ])
+            .map_err(|e| format!("Failed to update purchase item: {}", e))?;
 
-    // Get purchase_id to update purchase total
-    let purchase_id_sql = "SELECT purchase_id FROM purchase_items WHERE id = ?";
-    let purchase_ids = db
-        .query(purchase_id_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(row.get::<_, i64>(0)?)
-        })
-        .map_err(|e| format!("Failed to fetch purchase_id: {}", e))?;
+        // Get purchase_id to update purchase total
+        let purchase_id_sql = "SELECT purchase_id FROM purchase_items WHERE id = ?";
+        let purchase_ids = db
+            .query(purchase_id_sql, &[&id as &dyn rusqlite::ToSql], |row| {
+                Ok(row.get::<_, i64>(0)?)
+            })
+            .map_err(|e| format!("Failed to fetch purchase_id: {}", e))?;
 
-    if let Some(purchase_id) = purchase_ids.first() {
-        // Update purchase total (items total + additional_cost)
-        let update_purchase_sql = "UPDATE purchases SET total_amount = (SELECT COALESCE(SUM(total), 0) FROM purchase_items WHERE purchase_id = ?) + COALESCE((SELECT additional_cost FROM purchases WHERE id = ?), 0), updated_at = CURRENT_TIMESTAMP WHERE id = ?";
-        db.execute(update_purchase_sql, &[purchase_id as &dyn rusqlite::ToSql, purchase_id as &dyn rusqlite::ToSql, purchase_id as &dyn rusqlite::ToSql])
-            .map_err(|e| format!("Failed to update purchase total: {}", e))?;
-    }
+        if let Some(purchase_id) = purchase_ids.first() {
+            // Update purchase total (items total + additional_cost)
+            let update_purchase_sql = "UPDATE purchases SET total_amount = (SELECT COALESCE(SUM(total), 0) FROM purchase_items WHERE purchase_id = ?) + COALESCE((SELECT additional_cost FROM purchases WHERE id = ?), 0), updated_at = CURRENT_TIMESTAMP WHERE id = ?";
+            db.execute(update_purchase_sql, &[purchase_id as &dyn rusqlite::ToSql, purchase_id as &dyn rusqlite::ToSql, purchase_id as &dyn rusqlite::ToSql])
+                .map_err(|e| format!("Failed to update purchase total: {}", e))?;
+        }
 
-    // Get the updated item
-    let item_sql = "SELECT id, purchase_id, product_id, unit_id, per_price, amount, total, per_unit, cost_price, wholesale_price, retail_price, expiry_date, created_at FROM purchase_items WHERE id = ?";
-    let items = db
-        .query(item_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(PurchaseItem {
-                id: row.get(0)?,
-                purchase_id: row.get(1)?,
-                product_id: row.get(2)?,
-                unit_id: row.get(3)?,
-                per_price: row.get(4)?,
-                amount: row.get(5)?,
-                total: row.get(6)?,
-                per_unit: row.get(7)?,
-                cost_price: row.get(8)?,
-                wholesale_price: row.get(9)?,
-                retail_price: row.get(10)?,
-                expiry_date: row.get(11)?,
-                created_at: row.get(12)?,
+        // Get the updated item
+        let item_sql = "SELECT id, purchase_id, product_id, unit_id, per_price, amount, total, per_unit, cost_price, wholesale_price, retail_price, expiry_date, created_at FROM purchase_items WHERE id = ?";
+        let items = db
+            .query(item_sql, &[&id as &dyn rusqlite::ToSql], |row| {
+                Ok(PurchaseItem {
+                    id: row.get(0)?,
+                    purchase_id: row.get(1)?,
+                    product_id: row.get(2)?,
+                    unit_id: row.get(3)?,
+                    per_price: row.get(4)?,
+                    amount: row.get(5)?,
+                    total: row.get(6)?,
+                    per_unit: row.get(7)?,
+                    cost_price: row.get(8)?,
+                    wholesale_price: row.get(9)?,
+                    retail_price: row.get(10)?,
+                    expiry_date: row.get(11)?,
+                    created_at: row.get(12)?,
+                })
             })
-        })
-        .map_err(|e| format!("Failed to fetch purchase item: {}", e))?;
+            .map_err(|e| format!("Failed to fetch purchase item: {}", e))?;
 
-    if let Some(item) = items.first() {
-        Ok(item.clone())
-    } else {
-        Err("Failed to retrieve updated purchase item".to_string())
-    }
+        if let Some(item) = items.first() {
+            Ok(item.clone())
+        } else {
+            Err("Failed to retrieve updated purchase item".to_string())
+        }
+    })
 }
 
 /// Delete a purchase item
@@ -2934,26 +3715,28 @@ fn delete_purchase_item(
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
 
-    // Get purchase_id before deleting
-    let purchase_id_sql = "SELECT purchase_id FROM purchase_items WHERE id = ?";
-    let purchase_ids = db
-        .query(purchase_id_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(row.get::<_, i64>(0)?)
-        })
-        .map_err(|e| format!("Failed to fetch purchase_id: {}", e))?;
+    db.transaction(|db| {
+        // Get purchase_id before deleting
+        let purchase_id_sql = "SELECT purchase_id FROM purchase_items WHERE id = ?";
+        let purchase_ids = db
+            .query(purchase_id_sql, &[&id as &dyn rusqlite::ToSql], |row| {
+                Ok(row.get::<_, i64>(0)?)
+            })
+            .map_err(|e| format!("Failed to fetch purchase_id: {}", e))?;
 
-    let purchase_id = purchase_ids.first().ok_or("Purchase item not found")?;
+        let purchase_id = purchase_ids.first().ok_or("Purchase item not found")?;
 
-    let delete_sql = "DELETE FROM purchase_items WHERE id = ?";
-    db.execute(delete_sql, &[&id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to delete purchase item: {}", e))?;
+        let delete_sql = "DELETE FROM purchase_items WHERE id = ?";
+        db.execute(delete_sql, &[&id as &dyn rusqlite::ToSql])
+            .map_err(|e| format!("Failed to delete purchase item: {}", e))?;
 
-    // Update purchase total (items total + additional_cost)
-    let update_purchase_sql = "UPDATE purchases SET total_amount = (SELECT COALESCE(SUM(total), 0) FROM purchase_items WHERE purchase_id = ?) + COALESCE((SELECT additional_cost FROM purchases WHERE id = ?), 0), updated_at = CURRENT_TIMESTAMP WHERE id = ?";
-    db.execute(update_purchase_sql, &[purchase_id as &dyn rusqlite::ToSql, purchase_id as &dyn rusqlite::ToSql, purchase_id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to update purchase total: {}", e))?;
+        // Update purchase total (items total + additional_cost)
+        let update_purchase_sql = "UPDATE purchases SET total_amount = (SELECT COALESCE(SUM(total), 0) FROM purchase_items WHERE purchase_id = ?) + COALESCE((SELECT additional_cost FROM purchases WHERE id = ?), 0), updated_at = CURRENT_TIMESTAMP WHERE id = ?";
+        db.execute(update_purchase_sql, &[purchase_id as &dyn rusqlite::ToSql, purchase_id as &dyn rusqlite::ToSql, purchase_id as &dyn rusqlite::ToSql])
+            .map_err(|e| format!("Failed to update purchase total: {}", e))?;
 
-    Ok("Purchase item deleted successfully".to_string())
+        Ok("Purchase item deleted successfully".to_string())
+    })
 }
 
 // Purchase Payment Model
@@ -2971,42 +3754,33 @@ pub struct PurchasePayment {
     pub created_at: String,
 }
 
+/// A payment towards a purchase: `create_purchase_payment`'s arguments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PurchasePaymentInput {
+    /// Account the money is withdrawn from
+    pub account_id: Option<i64>,
+    pub amount: f64,
+    /// Name of the currency
+    pub currency: String,
+    pub rate: f64,
+    pub date: String,
+    pub notes: Option<String>,
+}
+
 /// Initialize purchase payments table schema
 #[tauri::command]
 fn init_purchase_payments_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS purchase_payments (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            purchase_id INTEGER NOT NULL,
-            account_id INTEGER,
-            amount REAL NOT NULL,
-            currency TEXT NOT NULL,
-            rate REAL NOT NULL,
-            total REAL NOT NULL,
-            date TEXT NOT NULL,
-            notes TEXT,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (purchase_id) REFERENCES purchases(id) ON DELETE CASCADE,
-            FOREIGN KEY (account_id) REFERENCES accounts(id) ON DELETE SET NULL
-        )
-    ";
-
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create purchase_payments table: {}", e))?;
-
-    // Add account_id column if it doesn't exist (for existing databases)
-    let _ = db.execute("ALTER TABLE purchase_payments ADD COLUMN account_id INTEGER", &[]);
+    ensure_database_open(&db_state)?;
 
     Ok("Purchase payments table initialized successfully".to_string())
 }
 
 /// Create a purchase payment
 #[tauri::command]
-fn create_purchase_payment(
+async fn create_purchase_payment(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
     purchase_id: i64,
     account_id: Option<i64>,
     amount: f64,
@@ -3015,103 +3789,111 @@ fn create_purchase_payment(
     date: String,
     notes: Option<String>,
 ) -> Result<PurchasePayment, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    let payment = PurchasePaymentInput { account_id, amount, currency, rate, date, notes };
+    audit::attributed(actor, repo.create_purchase_payment(purchase_id, &payment)).await
+        .map_err(|e| format!("Failed to create purchase payment: {}", e))
+}
 
-    let total = amount * rate;
-    let notes_str: Option<&str> = notes.as_ref().map(|s| s.as_str());
+/// Record a payment towards a purchase and withdraw it from its account
+pub(crate) fn create_purchase_payment_internal(db: &Database, purchase_id: i64, payment: &PurchasePaymentInput) -> Result<PurchasePayment, String> {
+    let PurchasePaymentInput { account_id, amount, ref currency, rate, ref date, ref notes } = *payment;
 
-    let insert_sql = "INSERT INTO purchase_payments (purchase_id, account_id, amount, currency, rate, total, date, notes) VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
-    db.execute(insert_sql, &[
-        &purchase_id as &dyn rusqlite::ToSql,
-        &account_id as &dyn rusqlite::ToSql,
-        &amount as &dyn rusqlite::ToSql,
-        &currency as &dyn rusqlite::ToSql,
-        &rate as &dyn rusqlite::ToSql,
-        &total as &dyn rusqlite::ToSql,
-        &date as &dyn rusqlite::ToSql,
-        &notes_str as &dyn rusqlite::ToSql,
-    ])
-        .map_err(|e| format!("Failed to insert purchase payment: {}", e))?;
+    db.transaction(|db| {
+        let total = amount * rate;
+        let notes_str: Option<&str> = notes.as_ref().map(|s| s.as_str());
 
-    // If account_id is provided, withdraw the payment amount from the account
-    if let Some(aid) = account_id {
-        // Get currency_id from currency name
-        let currency_sql = "SELECT id FROM currencies WHERE name = ? LIMIT 1";
-        let currency_ids = db
-            .query(currency_sql, &[&currency as &dyn rusqlite::ToSql], |row| {
-                Ok(row.get::<_, i64>(0)?)
-            })
-            .map_err(|e| format!("Failed to find currency: {}", e))?;
-        
-        if let Some(currency_id) = currency_ids.first() {
-            // Check if account has sufficient balance
-            let current_balance = get_account_balance_by_currency_internal(db, aid, *currency_id)
-                .unwrap_or(0.0);
-            
-            if current_balance < amount {
-                return Err(format!("Insufficient balance in account. Available: {}, Required: {}", current_balance, amount));
-            }
-            
-            // Create account transaction record for this payment (withdrawal)
-            let payment_notes = notes.as_ref().map(|_s| format!("Payment for Purchase #{}", purchase_id));
-            let payment_notes_str: Option<&str> = payment_notes.as_ref().map(|s| s.as_str());
-            let is_full_int = 0i64;
-            
-            let insert_transaction_sql = "INSERT INTO account_transactions (account_id, transaction_type, amount, currency, rate, total, transaction_date, is_full, notes) VALUES (?, 'withdraw', ?, ?, ?, ?, ?, ?, ?)";
-            db.execute(insert_transaction_sql, &[
-                &aid as &dyn rusqlite::ToSql,
-                &amount as &dyn rusqlite::ToSql,
-                &currency as &dyn rusqlite::ToSql,
-                &rate as &dyn rusqlite::ToSql,
-                &total as &dyn rusqlite::ToSql,
-                &date as &dyn rusqlite::ToSql,
-                &is_full_int as &dyn rusqlite::ToSql,
-                &payment_notes_str as &dyn rusqlite::ToSql,
-            ])
-            .map_err(|e| format!("Failed to create account transaction: {}", e))?;
-            
-            // Subtract the payment amount from the balance
-            let new_balance = current_balance - amount;
-            
-            // Update account currency balance
-            update_account_currency_balance_internal(db, aid, *currency_id, new_balance)?;
-            
-            // Update account's current_balance
-            let new_account_balance = calculate_account_balance_internal(db, aid)?;
-            let update_balance_sql = "UPDATE accounts SET current_balance = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?";
-            db.execute(update_balance_sql, &[
-                &new_account_balance as &dyn rusqlite::ToSql,
-                &aid as &dyn rusqlite::ToSql,
-            ])
-            .map_err(|e| format!("Failed to update account balance: {}", e))?;
-        }
-    }
+        let insert_sql = "INSERT INTO purchase_payments (purchase_id, account_id, amount, currency, rate, total, date, notes) VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
+        let payment_id = db.insert(insert_sql, &[
+            &purchase_id as &dyn rusqlite::ToSql,
+            &account_id as &dyn rusqlite::ToSql,
+            &amount as &dyn rusqlite::ToSql,
+            currency as &dyn rusqlite::ToSql,
+            &rate as &dyn rusqlite::ToSql,
+            &total as &dyn rusqlite::ToSql,
+            date as &dyn rusqlite::ToSql,
+            &notes_str as &dyn rusqlite::ToSql,
+        ])
+            .map_err(|e| format!("Failed to insert purchase payment: {}", e))?;
+
+        // If account_id is provided, withdraw the payment amount from the account
+        if let Some(aid) = account_id {
+            // Get currency_id from currency name
+            let currency_sql = "SELECT id FROM currencies WHERE name = ? LIMIT 1";
+            let currency_ids = db
+                .query(currency_sql, &[currency as &dyn rusqlite::ToSql], |row| {
+                    Ok(row.get::<_, i64>(0)?)
+                })
+                .map_err(|e| format!("Failed to find currency: {}", e))?;
 
-    // Get the created payment
-    let payment_sql = "SELECT id, purchase_id, account_id, amount, currency, rate, total, date, notes, created_at FROM purchase_payments WHERE purchase_id = ? ORDER BY id DESC LIMIT 1";
-    let payments = db
-        .query(payment_sql, &[&purchase_id as &dyn rusqlite::ToSql], |row| {
-            Ok(PurchasePayment {
-                id: row.get(0)?,
-                purchase_id: row.get(1)?,
-                account_id: row.get(2)?,
-                amount: row.get(3)?,
-                currency: row.get(4)?,
-                rate: row.get(5)?,
-                total: row.get(6)?,
-                date: row.get(7)?,
-                notes: row.get(8)?,
-                created_at: row.get(9)?,
+            if let Some(currency_id) = currency_ids.first() {
+                // Check if account has sufficient balance
+                let current_balance = get_account_balance_by_currency_internal(db, aid, *currency_id)
+                    .unwrap_or(0.0);
+
+                if current_balance < amount {
+                    return Err(format!("Insufficient balance in account. Available: {}, Required: {}", current_balance, amount));
+                }
+
+                // Create account transaction record for this payment (withdrawal)
+                let payment_notes = notes.as_ref().map(|_s| format!("Payment for Purchase #{}", purchase_id));
+                let payment_notes_str: Option<&str> = payment_notes.as_ref().map(|s| s.as_str());
+                let is_full_int = 0i64;
+
+                let insert_transaction_sql = "INSERT INTO account_transactions (account_id, transaction_type, amount, currency, rate, total, transaction_date, is_full, notes) VALUES (?, 'withdraw', ?, ?, ?, ?, ?, ?, ?)";
+                db.execute(insert_transaction_sql, &[
+                    &aid as &dyn rusqlite::ToSql,
+                    &amount as &dyn rusqlite::ToSql,
+                    currency as &dyn rusqlite::ToSql,
+                    &rate as &dyn rusqlite::ToSql,
+                    &total as &dyn rusqlite::ToSql,
+                    date as &dyn rusqlite::ToSql,
+                    &is_full_int as &dyn rusqlite::ToSql,
+                    &payment_notes_str as &dyn rusqlite::ToSql,
+                ])
+                .map_err(|e| format!("Failed to create account transaction: {}", e))?;
+
+                // Subtract the payment amount from the balance
+                let new_balance = current_balance - amount;
+
+                // Update account currency balance
+                update_account_currency_balance_internal(db, aid, *currency_id, new_balance)?;
+
+                // Update account's current_balance
+                let new_account_balance = calculate_account_balance_internal(db, aid)?;
+                let update_balance_sql = "UPDATE accounts SET current_balance = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?";
+                db.execute(update_balance_sql, &[
+                    &new_account_balance as &dyn rusqlite::ToSql,
+                    &aid as &dyn rusqlite::ToSql,
+                ])
+                .map_err(|e| format!("Failed to update account balance: {}", e))?;
+            }
+        }
+
+        // Get the created payment
+        let payment_sql = "SELECT id, purchase_id, account_id, amount, currency, rate, total, date, notes, created_at FROM purchase_payments WHERE id = ?";
+        let payments = db
+            .query(payment_sql, &[&payment_id as &dyn rusqlite::ToSql], |row| {
+                Ok(PurchasePayment {
+                    id: row.get(0)?,
+                    purchase_id: row.get(1)?,
+                    account_id: row.get(2)?,
+                    amount: row.get(3)?,
+                    currency: row.get(4)?,
+                    rate: row.get(5)?,
+                    total: row.get(6)?,
+                    date: row.get(7)?,
+                    notes: row.get(8)?,
+                    created_at: row.get(9)?,
+                })
             })
-        })
-        .map_err(|e| format!("Failed to fetch purchase payment: {}", e))?;
+            .map_err(|e| format!("Failed to fetch purchase payment: {}", e))?;
 
-    if let Some(payment) = payments.first() {
-        Ok(payment.clone())
-    } else {
-        Err("Failed to retrieve created purchase payment".to_string())
-    }
+        let payment = payments.first().cloned().ok_or("Failed to retrieve created purchase payment")?;
+        events::record(db, events::PURCHASE_PAYMENT_MADE, &payment)
+            .map_err(|e| format!("Failed to record payment event: {}", e))?;
+        Ok(payment)
+    })
 }
 
 /// Get all purchase payments with pagination
@@ -3315,7 +4097,7 @@ fn delete_purchase_payment(
 }
 
 // Sale Model
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Sale {
     pub id: i64,
     pub customer_id: i64,
@@ -3332,7 +4114,7 @@ pub struct Sale {
 }
 
 // SaleItem Model
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct SaleItem {
     pub id: i64,
     pub sale_id: i64,
@@ -3386,131 +4168,86 @@ pub struct SaleAdditionalCost {
     pub created_at: String,
 }
 
-/// Initialize sales table schema
-#[tauri::command]
-fn init_sales_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS sales (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            customer_id INTEGER NOT NULL,
-            date TEXT NOT NULL,
-            notes TEXT,
-            currency_id INTEGER,
-            exchange_rate REAL NOT NULL DEFAULT 1,
-            total_amount REAL NOT NULL DEFAULT 0,
-            base_amount REAL NOT NULL DEFAULT 0,
-            paid_amount REAL NOT NULL DEFAULT 0,
-            additional_cost REAL NOT NULL DEFAULT 0,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (customer_id) REFERENCES customers(id),
-            FOREIGN KEY (currency_id) REFERENCES currencies(id)
-        )
-    ";
+/// A new sale: `create_sale`'s arguments, or the body of `POST /api/v1/sales`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SaleInput {
+    pub customer_id: i64,
+    pub date: String,
+    pub notes: Option<String>,
+    pub currency_id: Option<i64>,
+    #[serde(default = "default_exchange_rate")]
+    pub exchange_rate: f64,
+    #[serde(default)]
+    pub paid_amount: f64,
+    #[serde(default)]
+    pub additional_costs: Vec<AdditionalCostInput>,
+    pub items: Vec<SaleItemInput>,
+}
 
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create sales table: {}", e))?;
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SaleItemInput {
+    pub product_id: i64,
+    pub unit_id: i64,
+    pub per_price: f64,
+    pub amount: f64,
+    /// Purchase batch the goods come from
+    pub purchase_item_id: Option<i64>,
+    pub sale_type: Option<String>,
+}
 
-    // Add new columns if they don't exist (for existing databases)
-    let alter_queries = vec![
-        "ALTER TABLE sales ADD COLUMN additional_cost REAL NOT NULL DEFAULT 0",
-        "ALTER TABLE sales ADD COLUMN currency_id INTEGER",
-        "ALTER TABLE sales ADD COLUMN exchange_rate REAL NOT NULL DEFAULT 1",
-        "ALTER TABLE sales ADD COLUMN base_amount REAL NOT NULL DEFAULT 0",
-    ];
+/// A named extra cost of a sale or purchase, such as transport
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AdditionalCostInput {
+    pub name: String,
+    pub amount: f64,
+}
 
-    for alter_sql in alter_queries {
-        let _ = db.execute(alter_sql, &[]);
+impl From<(String, f64)> for AdditionalCostInput {
+    /// A cost as the sale and purchase commands take it: (name, amount)
+    fn from((name, amount): (String, f64)) -> Self {
+        AdditionalCostInput { name, amount }
     }
+}
 
-    let create_items_table_sql = "
-        CREATE TABLE IF NOT EXISTS sale_items (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            sale_id INTEGER NOT NULL,
-            product_id INTEGER NOT NULL,
-            unit_id INTEGER NOT NULL,
-            per_price REAL NOT NULL,
-            amount REAL NOT NULL,
-            total REAL NOT NULL,
-            purchase_item_id INTEGER,
-            sale_type TEXT,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (sale_id) REFERENCES sales(id) ON DELETE CASCADE,
-            FOREIGN KEY (product_id) REFERENCES products(id),
-            FOREIGN KEY (unit_id) REFERENCES units(id),
-            FOREIGN KEY (purchase_item_id) REFERENCES purchase_items(id)
-        )
-    ";
-
-    db.execute(create_items_table_sql, &[])
-        .map_err(|e| format!("Failed to create sale_items table: {}", e))?;
-
-    // Add new columns if they don't exist (for existing databases)
-    let alter_sale_items_queries = vec![
-        "ALTER TABLE sale_items ADD COLUMN purchase_item_id INTEGER",
-        "ALTER TABLE sale_items ADD COLUMN sale_type TEXT",
-    ];
-
-    for alter_sql in alter_sale_items_queries {
-        let _ = db.execute(alter_sql, &[]);
+impl From<(i64, i64, f64, f64, Option<i64>, Option<String>)> for SaleItemInput {
+    /// An item as `create_sale` and `update_sale` take it:
+    /// (product_id, unit_id, per_price, amount, purchase_item_id, sale_type)
+    fn from((product_id, unit_id, per_price, amount, purchase_item_id, sale_type): (i64, i64, f64, f64, Option<i64>, Option<String>)) -> Self {
+        SaleItemInput { product_id, unit_id, per_price, amount, purchase_item_id, sale_type }
     }
+}
 
-    let create_payments_table_sql = "
-        CREATE TABLE IF NOT EXISTS sale_payments (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            sale_id INTEGER NOT NULL,
-            account_id INTEGER,
-            currency_id INTEGER,
-            exchange_rate REAL NOT NULL DEFAULT 1,
-            amount REAL NOT NULL,
-            base_amount REAL NOT NULL DEFAULT 0,
-            date TEXT NOT NULL,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (sale_id) REFERENCES sales(id) ON DELETE CASCADE,
-            FOREIGN KEY (account_id) REFERENCES accounts(id),
-            FOREIGN KEY (currency_id) REFERENCES currencies(id)
-        )
-    ";
-
-    db.execute(create_payments_table_sql, &[])
-        .map_err(|e| format!("Failed to create sale_payments table: {}", e))?;
-
-    // Add new columns if they don't exist (for existing databases)
-    let alter_payment_queries = vec![
-        "ALTER TABLE sale_payments ADD COLUMN account_id INTEGER",
-        "ALTER TABLE sale_payments ADD COLUMN currency_id INTEGER",
-        "ALTER TABLE sale_payments ADD COLUMN exchange_rate REAL NOT NULL DEFAULT 1",
-        "ALTER TABLE sale_payments ADD COLUMN base_amount REAL NOT NULL DEFAULT 0",
-    ];
-
-    for alter_sql in alter_payment_queries {
-        let _ = db.execute(alter_sql, &[]);
-    }
+/// A payment towards a sale: `create_sale_payment`'s arguments
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SalePaymentInput {
+    /// Account the money is deposited to
+    pub account_id: Option<i64>,
+    /// Defaults to the sale's currency, then the base currency
+    pub currency_id: Option<i64>,
+    #[serde(default = "default_exchange_rate")]
+    pub exchange_rate: f64,
+    pub amount: f64,
+    pub date: String,
+}
 
-    let create_additional_costs_table_sql = "
-        CREATE TABLE IF NOT EXISTS sale_additional_costs (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            sale_id INTEGER NOT NULL,
-            name TEXT NOT NULL,
-            amount REAL NOT NULL,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (sale_id) REFERENCES sales(id) ON DELETE CASCADE
-        )
-    ";
+fn default_exchange_rate() -> f64 {
+    1.0
+}
 
-    db.execute(create_additional_costs_table_sql, &[])
-        .map_err(|e| format!("Failed to create sale_additional_costs table: {}", e))?;
+/// Initialize sales table schema
+#[tauri::command]
+fn init_sales_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
+    ensure_database_open(&db_state)?;
 
     Ok("Sales, sale_items, sale_payments, and sale_additional_costs tables initialized successfully".to_string())
 }
 
 /// Create a new sale with items
 #[tauri::command]
-fn create_sale(
+async fn create_sale(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
     customer_id: i64,
     date: String,
     notes: Option<String>,
@@ -3520,304 +4257,198 @@ fn create_sale(
     additional_costs: Vec<(String, f64)>, // (name, amount)
     items: Vec<(i64, i64, f64, f64, Option<i64>, Option<String>)>, // (product_id, unit_id, per_price, amount, purchase_item_id, sale_type)
 ) -> Result<Sale, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    // Calculate total amount from items + additional costs
-    let items_total: f64 = items.iter().map(|(_, _, per_price, amount, _, _)| per_price * amount).sum();
-    let additional_costs_total: f64 = additional_costs.iter().map(|(_, amount)| amount).sum();
-    let total_amount = items_total + additional_costs_total;
-    let base_amount = total_amount * exchange_rate;
-
-    // Insert sale (keep additional_cost column for backward compatibility - sum of all additional costs)
-    let notes_str: Option<&str> = notes.as_ref().map(|s| s.as_str());
-    let insert_sql = "INSERT INTO sales (customer_id, date, notes, currency_id, exchange_rate, total_amount, base_amount, paid_amount, additional_cost) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)";
-    db.execute(insert_sql, &[
-        &customer_id as &dyn rusqlite::ToSql,
-        &date as &dyn rusqlite::ToSql,
-        &notes_str as &dyn rusqlite::ToSql,
-        &currency_id as &dyn rusqlite::ToSql,
-        &exchange_rate as &dyn rusqlite::ToSql,
-        &total_amount as &dyn rusqlite::ToSql,
-        &base_amount as &dyn rusqlite::ToSql,
-        &paid_amount as &dyn rusqlite::ToSql,
-        &additional_costs_total as &dyn rusqlite::ToSql,
-    ])
-        .map_err(|e| format!("Failed to insert sale: {}", e))?;
-
-    // Get the created sale ID
-    let sale_id_sql = "SELECT id FROM sales WHERE customer_id = ? AND date = ? ORDER BY id DESC LIMIT 1";
-    let sale_ids = db
-        .query(sale_id_sql, &[&customer_id as &dyn rusqlite::ToSql, &date as &dyn rusqlite::ToSql], |row| {
-            Ok(row.get::<_, i64>(0)?)
-        })
-        .map_err(|e| format!("Failed to fetch sale ID: {}", e))?;
-
-    let sale_id = sale_ids.first().ok_or("Failed to retrieve sale ID")?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    let sale = SaleInput {
+        customer_id,
+        date,
+        notes,
+        currency_id,
+        exchange_rate,
+        paid_amount,
+        additional_costs: additional_costs.into_iter().map(AdditionalCostInput::from).collect(),
+        items: items.into_iter().map(SaleItemInput::from).collect(),
+    };
+    audit::attributed(actor, repo.create_sale(&sale)).await
+        .map_err(|e| format!("Failed to create sale: {}", e))
+}
+
+/// Insert a sale with its items, additional costs, initial payment and journal entry
+pub(crate) fn create_sale_internal(db: &Database, sale: &SaleInput) -> Result<Sale, String> {
+    let SaleInput { customer_id, ref date, ref notes, currency_id, exchange_rate, paid_amount, ref additional_costs, ref items } = *sale;
+
+    db.transaction(|db| {
+        // Calculate total amount from items + additional costs
+        let items_total: f64 = items.iter().map(|item| item.per_price * item.amount).sum();
+        let additional_costs_total: f64 = additional_costs.iter().map(|cost| cost.amount).sum();
+        let total_amount = items_total + additional_costs_total;
+        let base_amount = total_amount * exchange_rate;
+
+        // Insert sale (keep additional_cost column for backward compatibility - sum of all additional costs)
+        let notes_str: Option<&str> = notes.as_ref().map(|s| s.as_str());
+        let insert_sql = "INSERT INTO sales (customer_id, date, notes, currency_id, exchange_rate, total_amount, base_amount, paid_amount, additional_cost) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)";
+        let sale_id = db.insert(insert_sql, &[
+            &customer_id as &dyn rusqlite::ToSql,
+            date as &dyn rusqlite::ToSql,
+            &notes_str as &dyn rusqlite::ToSql,
+            &currency_id as &dyn rusqlite::ToSql,
+            &exchange_rate as &dyn rusqlite::ToSql,
+            &total_amount as &dyn rusqlite::ToSql,
+            &base_amount as &dyn rusqlite::ToSql,
+            &paid_amount as &dyn rusqlite::ToSql,
+            &additional_costs_total as &dyn rusqlite::ToSql,
+        ])
+            .map_err(|e| format!("Failed to insert sale: {}", e))?;
+
+        // Get base currency ID (first currency marked as base, or first currency)
+        let base_currency_sql = "SELECT id FROM currencies WHERE base = 1 LIMIT 1";
+        let base_currencies = db.query(base_currency_sql, &[], |row| Ok(row.get::<_, i64>(0)?))
+            .map_err(|e| format!("Failed to get base currency: {}", e))?;
+        let base_currency_id = base_currencies.first().copied().unwrap_or_else(|| {
+            // Fallback to first currency if no base currency set
+            db.query("SELECT id FROM currencies LIMIT 1", &[], |row| Ok(row.get::<_, i64>(0)?))
+                .ok()
+                .and_then(|v| v.first().copied())
+                .unwrap_or(1)
+        });
 
-    // Get base currency ID (first currency marked as base, or first currency)
-    let base_currency_sql = "SELECT id FROM currencies WHERE base = 1 LIMIT 1";
-    let base_currencies = db.query(base_currency_sql, &[], |row| Ok(row.get::<_, i64>(0)?))
-        .map_err(|e| format!("Failed to get base currency: {}", e))?;
-    let base_currency_id = base_currencies.first().copied().unwrap_or_else(|| {
-        // Fallback to first currency if no base currency set
-        db.query("SELECT id FROM currencies LIMIT 1", &[], |row| Ok(row.get::<_, i64>(0)?))
+        // Create journal entry for sale: Debit Accounts Receivable, Credit Sales Revenue
+        // Note: This assumes accounts exist for AR and Sales Revenue - in production, these should be configurable
+        let ar_account_sql = "SELECT id FROM accounts WHERE account_type = 'Asset' AND name LIKE '%Receivable%' AND deleted_at IS NULL LIMIT 1";
+        let ar_accounts = db.query(ar_account_sql, &[], |row| Ok(row.get::<_, i64>(0)?))
             .ok()
-            .and_then(|v| v.first().copied())
-            .unwrap_or(1)
-    });
+            .and_then(|v| v.first().copied());
 
-    // Create journal entry for sale: Debit Accounts Receivable, Credit Sales Revenue
-    // Note: This assumes accounts exist for AR and Sales Revenue - in production, these should be configurable
-    let ar_account_sql = "SELECT id FROM accounts WHERE account_type = 'Asset' AND name LIKE '%Receivable%' LIMIT 1";
-    let ar_accounts = db.query(ar_account_sql, &[], |row| Ok(row.get::<_, i64>(0)?))
-        .ok()
-        .and_then(|v| v.first().copied());
-    
-    let revenue_account_sql = "SELECT id FROM accounts WHERE account_type = 'Revenue' LIMIT 1";
-    let revenue_accounts = db.query(revenue_account_sql, &[], |row| Ok(row.get::<_, i64>(0)?))
-        .ok()
-        .and_then(|v| v.first().copied());
-
-    // Only create journal entry if accounts exist
-    if let (Some(ar_account), Some(revenue_account)) = (ar_accounts, revenue_accounts) {
-        let sale_currency_id = currency_id.unwrap_or(base_currency_id);
-        let journal_lines = vec![
-            (ar_account, sale_currency_id, base_amount, 0.0, exchange_rate, Some(format!("Sale #{}", sale_id))),
-            (revenue_account, sale_currency_id, 0.0, base_amount, exchange_rate, Some(format!("Sale #{}", sale_id))),
-        ];
-        let _ = create_journal_entry_internal(db, &date, notes.clone(), Some("sale".to_string()), Some(*sale_id), journal_lines);
-    }
+        let revenue_account_sql = "SELECT id FROM accounts WHERE account_type = 'Revenue' AND deleted_at IS NULL LIMIT 1";
+        let revenue_accounts = db.query(revenue_account_sql, &[], |row| Ok(row.get::<_, i64>(0)?))
+            .ok()
+            .and_then(|v| v.first().copied());
+
+        // Only create journal entry if accounts exist
+        if let (Some(ar_account), Some(revenue_account)) = (ar_accounts, revenue_accounts) {
+            let sale_currency_id = currency_id.unwrap_or(base_currency_id);
+            let journal_lines = vec![
+                (ar_account, sale_currency_id, base_amount, 0.0, exchange_rate, Some(format!("Sale #{}", sale_id))),
+                (revenue_account, sale_currency_id, 0.0, base_amount, exchange_rate, Some(format!("Sale #{}", sale_id))),
+            ];
+            create_journal_entry_internal(db, date, notes.clone(), Some("sale".to_string()), Some(sale_id), journal_lines)?;
+        }
 
-    // Insert initial payment if paid_amount > 0
-    if paid_amount > 0.0 {
-        let payment_currency_id = currency_id.unwrap_or(base_currency_id);
-        let payment_base_amount = paid_amount * exchange_rate;
-        let insert_payment_sql = "INSERT INTO sale_payments (sale_id, currency_id, exchange_rate, amount, base_amount, date) VALUES (?, ?, ?, ?, ?, ?)";
-        db.execute(insert_payment_sql, &[
-            sale_id as &dyn rusqlite::ToSql,
-            &payment_currency_id as &dyn rusqlite::ToSql,
-            &exchange_rate as &dyn rusqlite::ToSql,
-            &paid_amount as &dyn rusqlite::ToSql,
-            &payment_base_amount as &dyn rusqlite::ToSql,
-            &date as &dyn rusqlite::ToSql,
-        ])
-            .map_err(|e| format!("Failed to insert initial payment: {}", e))?;
-    }
+        // Insert initial payment if paid_amount > 0
+        if paid_amount > 0.0 {
+            let payment_currency_id = currency_id.unwrap_or(base_currency_id);
+            let payment_base_amount = paid_amount * exchange_rate;
+            let insert_payment_sql = "INSERT INTO sale_payments (sale_id, currency_id, exchange_rate, amount, base_amount, date) VALUES (?, ?, ?, ?, ?, ?)";
+            let payment_id = db.insert(insert_payment_sql, &[
+                &sale_id as &dyn rusqlite::ToSql,
+                &payment_currency_id as &dyn rusqlite::ToSql,
+                &exchange_rate as &dyn rusqlite::ToSql,
+                &paid_amount as &dyn rusqlite::ToSql,
+                &payment_base_amount as &dyn rusqlite::ToSql,
+                date as &dyn rusqlite::ToSql,
+            ])
+                .map_err(|e| format!("Failed to insert initial payment: {}", e))?;
+
+            let payment = serde_json::json!({
+                "id": payment_id,
+                "sale_id": sale_id,
+                "currency_id": payment_currency_id,
+                "exchange_rate": exchange_rate,
+                "amount": paid_amount,
+                "base_amount": payment_base_amount,
+                "date": date,
+            });
+            events::record(db, events::PAYMENT_RECEIVED, &payment)
+                .map_err(|e| format!("Failed to record payment event: {}", e))?;
+        }
 
-    // Insert sale items
-    for (product_id, unit_id, per_price, amount, purchase_item_id, sale_type) in items {
-        let total = per_price * amount;
-        let insert_item_sql = "INSERT INTO sale_items (sale_id, product_id, unit_id, per_price, amount, total, purchase_item_id, sale_type) VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
-        db.execute(insert_item_sql, &[
-            sale_id as &dyn rusqlite::ToSql,
-            &product_id as &dyn rusqlite::ToSql,
-            &unit_id as &dyn rusqlite::ToSql,
-            &per_price as &dyn rusqlite::ToSql,
-            &amount as &dyn rusqlite::ToSql,
-            &total as &dyn rusqlite::ToSql,
-            &purchase_item_id as &dyn rusqlite::ToSql,
-            &sale_type as &dyn rusqlite::ToSql,
-        ])
-            .map_err(|e| format!("Failed to insert sale item: {}", e))?;
-    }
+        // Insert sale items
+        for item in items {
+            let total = item.per_price * item.amount;
+            let insert_item_sql = "INSERT INTO sale_items (sale_id, product_id, unit_id, per_price, amount, total, purchase_item_id, sale_type) VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
+            db.execute(insert_item_sql, &[
+                &sale_id as &dyn rusqlite::ToSql,
+                &item.product_id as &dyn rusqlite::ToSql,
+                &item.unit_id as &dyn rusqlite::ToSql,
+                &item.per_price as &dyn rusqlite::ToSql,
+                &item.amount as &dyn rusqlite::ToSql,
+                &total as &dyn rusqlite::ToSql,
+                &item.purchase_item_id as &dyn rusqlite::ToSql,
+                &item.sale_type as &dyn rusqlite::ToSql,
+            ])
+                .map_err(|e| format!("Failed to insert sale item: {}", e))?;
+        }
 
-    // Insert additional costs
-    for (name, amount) in additional_costs {
-        let insert_cost_sql = "INSERT INTO sale_additional_costs (sale_id, name, amount) VALUES (?, ?, ?)";
-        db.execute(insert_cost_sql, &[
-            sale_id as &dyn rusqlite::ToSql,
-            &name as &dyn rusqlite::ToSql,
-            &amount as &dyn rusqlite::ToSql,
-        ])
-            .map_err(|e| format!("Failed to insert sale additional cost: {}", e))?;
-    }
+        // Insert additional costs
+        for cost in additional_costs {
+            let insert_cost_sql = "INSERT INTO sale_additional_costs (sale_id, name, amount) VALUES (?, ?, ?)";
+            db.execute(insert_cost_sql, &[
+                &sale_id as &dyn rusqlite::ToSql,
+                &cost.name as &dyn rusqlite::ToSql,
+                &cost.amount as &dyn rusqlite::ToSql,
+            ])
+                .map_err(|e| format!("Failed to insert sale additional cost: {}", e))?;
+        }
 
-    // Get the created sale
-    let sale_sql = "SELECT id, customer_id, date, notes, currency_id, exchange_rate, total_amount, base_amount, paid_amount, additional_cost, created_at, updated_at FROM sales WHERE id = ?";
-    let sales = db
-        .query(sale_sql, &[sale_id as &dyn rusqlite::ToSql], |row| {
-            Ok(Sale {
-                id: row.get(0)?,
-                customer_id: row.get(1)?,
-                date: row.get(2)?,
-                notes: row.get(3)?,
-                currency_id: row.get(4)?,
-                exchange_rate: row.get(5)?,
-                total_amount: row.get(6)?,
-                base_amount: row.get(7)?,
-                paid_amount: row.get(8)?,
-                additional_cost: row.get(9)?,
-                created_at: row.get(10)?,
-                updated_at: row.get(11)?,
+        let sold: Vec<(i64, f64)> = items.iter().map(|item| (item.product_id, item.amount)).collect();
+        events::record_low_stock(db, sale_id, &sold)
+            .map_err(|e| format!("Failed to check stock levels: {}", e))?;
+
+        // Get the created sale
+        let sale_sql = "SELECT id, customer_id, date, notes, currency_id, exchange_rate, total_amount, base_amount, paid_amount, additional_cost, created_at, updated_at FROM sales WHERE id = ?";
+        let sales = db
+            .query(sale_sql, &[&sale_id as &dyn rusqlite::ToSql], |row| {
+                Ok(Sale {
+                    id: row.get(0)?,
+                    customer_id: row.get(1)?,
+                    date: row.get(2)?,
+                    notes: row.get(3)?,
+                    currency_id: row.get(4)?,
+                    exchange_rate: row.get(5)?,
+                    total_amount: row.get(6)?,
+                    base_amount: row.get(7)?,
+                    paid_amount: row.get(8)?,
+                    additional_cost: row.get(9)?,
+                    created_at: row.get(10)?,
+                    updated_at: row.get(11)?,
+                })
             })
-        })
-        .map_err(|e| format!("Failed to fetch sale: {}", e))?;
+            .map_err(|e| format!("Failed to fetch sale: {}", e))?;
 
-    if let Some(sale) = sales.first() {
-        Ok(sale.clone())
-    } else {
-        Err("Failed to retrieve created sale".to_string())
-    }
+        let sale = sales.first().cloned().ok_or("Failed to retrieve created sale")?;
+        events::record(db, events::SALE_CREATED, &serde_json::json!({ "sale": sale, "items": items }))
+            .map_err(|e| format!("Failed to record sale event: {}", e))?;
+        Ok(sale)
+    })
 }
 
 /// Get all sales with pagination
 #[tauri::command]
-fn get_sales(
+async fn get_sales(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
     page: i64,
     per_page: i64,
     search: Option<String>,
     sort_by: Option<String>,
     sort_order: Option<String>,
 ) -> Result<PaginatedResponse<Sale>, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let offset = (page - 1) * per_page;
-
-    // Build WHERE clause
-    let mut where_clause = String::new();
-    let mut params: Vec<serde_json::Value> = Vec::new();
-
-    if let Some(s) = search {
-        if !s.trim().is_empty() {
-            let search_term = format!("%{}%", s);
-            where_clause = "WHERE (CAST(s.date AS TEXT) LIKE ? OR s.notes LIKE ? OR s.customer_id IN (SELECT id FROM customers WHERE full_name LIKE ? OR phone LIKE ?))".to_string();
-            params.push(serde_json::Value::String(search_term.clone()));
-            params.push(serde_json::Value::String(search_term.clone()));
-            params.push(serde_json::Value::String(search_term.clone()));
-            params.push(serde_json::Value::String(search_term));
-        }
-    }
-
-    // Get total count
-    let count_sql = format!("SELECT COUNT(*) FROM sales s {}", where_clause);
-    let total: i64 = db.with_connection(|conn| {
-        let mut stmt = conn.prepare(&count_sql).map_err(|e| anyhow::anyhow!("{}", e))?;
-        let rusqlite_params: Vec<rusqlite::types::Value> = params.iter().map(|v| {
-            match v {
-                serde_json::Value::String(s) => rusqlite::types::Value::Text(s.clone()),
-                _ => rusqlite::types::Value::Null,
-            }
-        }).collect();
-        let count: i64 = stmt.query_row(rusqlite::params_from_iter(rusqlite_params.iter()), |row| row.get(0))
-            .map_err(|e| anyhow::anyhow!("{}", e))?;
-        Ok(count)
-    }).map_err(|e| format!("Failed to count sales: {}", e))?;
-
-    // Build Order By
-    let order_clause = if let Some(sort) = sort_by {
-        let order = sort_order.unwrap_or_else(|| "DESC".to_string());
-        let allowed_cols = ["date", "total_amount", "paid_amount", "created_at"];
-        if allowed_cols.contains(&sort.as_str()) {
-            format!("ORDER BY s.{} {}", sort, if order.to_uppercase() == "DESC" { "DESC" } else { "ASC" })
-        } else {
-            "ORDER BY s.date DESC, s.created_at DESC".to_string()
-        }
-    } else {
-        "ORDER BY s.date DESC, s.created_at DESC".to_string()
-    };
-
-    let sql = format!("SELECT s.id, s.customer_id, s.date, s.notes, s.currency_id, s.exchange_rate, s.total_amount, s.base_amount, s.paid_amount, s.additional_cost, s.created_at, s.updated_at FROM sales s {} {} LIMIT ? OFFSET ?", where_clause, order_clause);
-    
-    params.push(serde_json::Value::Number(serde_json::Number::from(per_page)));
-    params.push(serde_json::Value::Number(serde_json::Number::from(offset)));
-
-    let sales = db.with_connection(|conn| {
-        let mut stmt = conn.prepare(&sql).map_err(|e| anyhow::anyhow!("{}", e))?;
-        let rusqlite_params: Vec<rusqlite::types::Value> = params.iter().map(|v| {
-            match v {
-                serde_json::Value::String(s) => rusqlite::types::Value::Text(s.clone()),
-                serde_json::Value::Number(n) => rusqlite::types::Value::Integer(n.as_i64().unwrap_or(0)),
-                _ => rusqlite::types::Value::Null,
-            }
-        }).collect();
-
-        let rows = stmt.query_map(rusqlite::params_from_iter(rusqlite_params.iter()), |row| {
-            Ok(Sale {
-                id: row.get(0)?,
-                customer_id: row.get(1)?,
-                date: row.get(2)?,
-                notes: row.get::<_, Option<String>>(3)?,
-                currency_id: row.get(4)?,
-                exchange_rate: row.get(5)?,
-                total_amount: row.get(6)?,
-                base_amount: row.get(7)?,
-                paid_amount: row.get(8)?,
-                additional_cost: row.get(9)?,
-                created_at: row.get(10)?,
-                updated_at: row.get(11)?,
-            })
-        }).map_err(|e| anyhow::anyhow!("{}", e))?;
-
-        let mut result = Vec::new();
-        for row in rows {
-            result.push(row.map_err(|e| anyhow::anyhow!("{}", e))?);
-        }
-        Ok(result)
-    }).map_err(|e| format!("Failed to fetch sales: {}", e))?;
-
-    let total_pages = (total as f64 / per_page as f64).ceil() as i64;
-    
-    Ok(PaginatedResponse {
-        items: sales,
-        total,
-        page,
-        per_page,
-        total_pages,
-    })
+    let repo = repositories(&db_state, &surreal_state)?;
+    let query = ListQuery { page, per_page, search, sort_by, sort_order };
+    repo.list_sales(&query).await
+        .map_err(|e| format!("Failed to fetch sales: {}", e))
 }
 
 /// Get a single sale with its items
 #[tauri::command]
-fn get_sale(db_state: State<'_, Mutex<Option<Database>>>, id: i64) -> Result<(Sale, Vec<SaleItem>), String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    // Get sale
-    let sale_sql = "SELECT id, customer_id, date, notes, currency_id, exchange_rate, total_amount, base_amount, paid_amount, additional_cost, created_at, updated_at FROM sales WHERE id = ?";
-    let sales = db
-        .query(sale_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(Sale {
-                id: row.get(0)?,
-                customer_id: row.get(1)?,
-                date: row.get(2)?,
-                notes: row.get(3)?,
-                currency_id: row.get(4)?,
-                exchange_rate: row.get(5)?,
-                total_amount: row.get(6)?,
-                base_amount: row.get(7)?,
-                paid_amount: row.get(8)?,
-                additional_cost: row.get(9)?,
-                created_at: row.get(10)?,
-                updated_at: row.get(11)?,
-            })
-        })
-        .map_err(|e| format!("Failed to fetch sale: {}", e))?;
-
-    let sale = sales.first().ok_or("Sale not found")?;
-
-    // Get sale items
-    let items_sql = "SELECT id, sale_id, product_id, unit_id, per_price, amount, total, purchase_item_id, sale_type, created_at FROM sale_items WHERE sale_id = ?";
-    let items = db
-        .query(items_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(SaleItem {
-                id: row.get(0)?,
-                sale_id: row.get(1)?,
-                product_id: row.get(2)?,
-                unit_id: row.get(3)?,
-                per_price: row.get(4)?,
-                amount: row.get(5)?,
-                total: row.get(6)?,
-                purchase_item_id: row.get(7)?,
-                sale_type: row.get(8)?,
-                created_at: row.get(9)?,
-            })
-        })
-        .map_err(|e| format!("Failed to fetch sale items: {}", e))?;
-
-    Ok((sale.clone(), items))
+async fn get_sale(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    id: i64,
+) -> Result<(Sale, Vec<SaleItem>), String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    repo.get_sale(id).await
+        .map_err(|e| format!("Failed to fetch sale: {}", e))?
+        .ok_or_else(|| "Sale not found".to_string())
 }
 
 /// Get sale additional costs
@@ -3844,129 +4475,179 @@ fn get_sale_additional_costs(db_state: State<'_, Mutex<Option<Database>>>, sale_
 
 /// Update a sale
 #[tauri::command]
-fn update_sale(
+async fn update_sale(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
     id: i64,
     customer_id: i64,
     date: String,
     notes: Option<String>,
     currency_id: Option<i64>,
     exchange_rate: f64,
-    _paid_amount: f64, // Ignored, handled by payments table
+    paid_amount: f64, // Ignored, handled by payments table
     additional_costs: Vec<(String, f64)>, // (name, amount)
     items: Vec<(i64, i64, f64, f64, Option<i64>, Option<String>)>, // (product_id, unit_id, per_price, amount, purchase_item_id, sale_type)
 ) -> Result<Sale, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    // Calculate total amount from items + additional costs
-    let items_total: f64 = items.iter().map(|(_, _, per_price, amount, _, _)| per_price * amount).sum();
-    let additional_costs_total: f64 = additional_costs.iter().map(|(_, amount)| amount).sum();
-    let total_amount = items_total + additional_costs_total;
-    let base_amount = total_amount * exchange_rate;
+    let repo = repositories(&db_state, &surreal_state)?;
+    let sale = SaleInput {
+        customer_id,
+        date,
+        notes,
+        currency_id,
+        exchange_rate,
+        paid_amount,
+        additional_costs: additional_costs.into_iter().map(AdditionalCostInput::from).collect(),
+        items: items.into_iter().map(SaleItemInput::from).collect(),
+    };
+    audit::attributed(actor, repo.update_sale(id, &sale)).await
+        .map_err(|e| format!("Failed to update sale: {}", e))
+}
 
-    // Update sale (excluding paid_amount, keep additional_cost column for backward compatibility)
-    let notes_str: Option<&str> = notes.as_ref().map(|s| s.as_str());
-    let update_sql = "UPDATE sales SET customer_id = ?, date = ?, notes = ?, currency_id = ?, exchange_rate = ?, total_amount = ?, base_amount = ?, additional_cost = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?";
-    db.execute(update_sql, &[
-        &customer_id as &dyn rusqlite::ToSql,
-        &date as &dyn rusqlite::ToSql,
-        &notes_str as &dyn rusqlite::ToSql,
-        &currency_id as &dyn rusqlite::ToSql,
-        &exchange_rate as &dyn rusqlite::ToSql,
-        &total_amount as &dyn rusqlite::ToSql,
-        &base_amount as &dyn rusqlite::ToSql,
-        &additional_costs_total as &dyn rusqlite::ToSql,
-        &id as &dyn rusqlite::ToSql,
-    ])
-        .map_err(|e| format!("Failed to update sale: {}", e))?;
+/// Replace a sale's details, items and additional costs. The paid amount isn't touched:
+/// it follows the sale's payments.
+pub(crate) fn update_sale_internal(db: &Database, id: i64, sale: &SaleInput) -> Result<Sale, String> {
+    let SaleInput { customer_id, ref date, ref notes, currency_id, exchange_rate, ref additional_costs, ref items, .. } = *sale;
 
-    // Delete existing items
-    let delete_items_sql = "DELETE FROM sale_items WHERE sale_id = ?";
-    db.execute(delete_items_sql, &[&id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to delete sale items: {}", e))?;
+    db.transaction(|db| {
+        // Calculate total amount from items + additional costs
+        let items_total: f64 = items.iter().map(|item| item.per_price * item.amount).sum();
+        let additional_costs_total: f64 = additional_costs.iter().map(|cost| cost.amount).sum();
+        let total_amount = items_total + additional_costs_total;
+        let base_amount = total_amount * exchange_rate;
 
-    // Insert new items
-    for (product_id, unit_id, per_price, amount, purchase_item_id, sale_type) in items {
-        let total = per_price * amount;
-        let insert_item_sql = "INSERT INTO sale_items (sale_id, product_id, unit_id, per_price, amount, total, purchase_item_id, sale_type) VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
-        db.execute(insert_item_sql, &[
+        // Update sale (excluding paid_amount, keep additional_cost column for backward compatibility)
+        let notes_str: Option<&str> = notes.as_ref().map(|s| s.as_str());
+        let update_sql = "UPDATE sales SET customer_id = ?, date = ?, notes = ?, currency_id = ?, exchange_rate = ?, total_amount = ?, base_amount = ?, additional_cost = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?";
+        db.execute(update_sql, &[
+            &customer_id as &dyn rusqlite::ToSql,
+            date as &dyn rusqlite::ToSql,
+            &notes_str as &dyn rusqlite::ToSql,
+            &currency_id as &dyn rusqlite::ToSql,
+            &exchange_rate as &dyn rusqlite::ToSql,
+            &total_amount as &dyn rusqlite::ToSql,
+            &base_amount as &dyn rusqlite::ToSql,
+            &additional_costs_total as &dyn rusqlite::ToSql,
             &id as &dyn rusqlite::ToSql,
-            &product_id as &dyn rusqlite::ToSql,
-            &unit_id as &dyn rusqlite::ToSql,
-            &per_price as &dyn rusqlite::ToSql,
-            &amount as &dyn rusqlite::ToSql,
-            &total as &dyn rusqlite::ToSql,
-            &purchase_item_id as &dyn rusqlite::ToSql,
-            &sale_type as &dyn rusqlite::ToSql,
         ])
-            .map_err(|e| format!("Failed to insert sale item: {}", e))?;
-    }
-
-    // Delete existing additional costs
-    let delete_costs_sql = "DELETE FROM sale_additional_costs WHERE sale_id = ?";
-    db.execute(delete_costs_sql, &[&id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to delete sale additional costs: {}", e))?;
+            .map_err(|e| format!("Failed to update sale: {}", e))?;
+
+        // Delete existing items
+        let delete_items_sql = "DELETE FROM sale_items WHERE sale_id = ?";
+        db.execute(delete_items_sql, &[&id as &dyn rusqlite::ToSql])
+            .map_err(|e| format!("Failed to delete sale items: {}", e))?;
+
+        // Insert new items
+        for item in items {
+            let total = item.per_price * item.amount;
+            let insert_item_sql = "INSERT INTO sale_items (sale_id, product_id, unit_id, per_price, amount, total, purchase_item_id, sale_type) VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
+            db.execute(insert_item_sql, &[
+                &id as &dyn rusqlite::ToSql,
+                &item.product_id as &dyn rusqlite::ToSql,
+                &item.unit_id as &dyn rusqlite::ToSql,
+                &item.per_price as &dyn rusqlite::ToSql,
+                &item.amount as &dyn rusqlite::ToSql,
+                &total as &dyn rusqlite::ToSql,
+                &item.purchase_item_id as &dyn rusqlite::ToSql,
+                &item.sale_type as &dyn rusqlite::ToSql,
+            ])
+                .map_err(|e| format!("Failed to insert sale item: {}", e))?;
+        }
 
-    // Insert new additional costs
-    for (name, amount) in additional_costs {
-        let insert_cost_sql = "INSERT INTO sale_additional_costs (sale_id, name, amount) VALUES (?, ?, ?)";
-        db.execute(insert_cost_sql, &[
-            &id as &dyn rusqlite::ToSql,
-            &name as &dyn rusqlite::ToSql,
-            &amount as &dyn rusqlite::ToSql,
-        ])
-            .map_err(|e| format!("Failed to insert sale additional cost: {}", e))?;
-    }
+        // Delete existing additional costs
+        let delete_costs_sql = "DELETE FROM sale_additional_costs WHERE sale_id = ?";
+        db.execute(delete_costs_sql, &[&id as &dyn rusqlite::ToSql])
+            .map_err(|e| format!("Failed to delete sale additional costs: {}", e))?;
+
+        // Insert new additional costs
+        for cost in additional_costs {
+            let insert_cost_sql = "INSERT INTO sale_additional_costs (sale_id, name, amount) VALUES (?, ?, ?)";
+            db.execute(insert_cost_sql, &[
+                &id as &dyn rusqlite::ToSql,
+                &cost.name as &dyn rusqlite::ToSql,
+                &cost.amount as &dyn rusqlite::ToSql,
+            ])
+                .map_err(|e| format!("Failed to insert sale additional cost: {}", e))?;
+        }
 
-    // Get the updated sale
-    let sale_sql = "SELECT id, customer_id, date, notes, currency_id, exchange_rate, total_amount, base_amount, paid_amount, additional_cost, created_at, updated_at FROM sales WHERE id = ?";
-    let sales = db
-        .query(sale_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(Sale {
-                id: row.get(0)?,
-                customer_id: row.get(1)?,
-                date: row.get(2)?,
-                notes: row.get(3)?,
-                currency_id: row.get(4)?,
-                exchange_rate: row.get(5)?,
-                total_amount: row.get(6)?,
-                base_amount: row.get(7)?,
-                paid_amount: row.get(8)?,
-                additional_cost: row.get(9)?,
-                created_at: row.get(10)?,
-                updated_at: row.get(11)?,
+        // Get the updated sale
+        let sale_sql = "SELECT id, customer_id, date, notes, currency_id, exchange_rate, total_amount, base_amount, paid_amount, additional_cost, created_at, updated_at FROM sales WHERE id = ?";
+        let sales = db
+            .query(sale_sql, &[&id as &dyn rusqlite::ToSql], |row| {
+                Ok(Sale {
+                    id: row.get(0)?,
+                    customer_id: row.get(1)?,
+                    date: row.get(2)?,
+                    notes: row.get(3)?,
+                    currency_id: row.get(4)?,
+                    exchange_rate: row.get(5)?,
+                    total_amount: row.get(6)?,
+                    base_amount: row.get(7)?,
+                    paid_amount: row.get(8)?,
+                    additional_cost: row.get(9)?,
+                    created_at: row.get(10)?,
+                    updated_at: row.get(11)?,
+                })
             })
-        })
-        .map_err(|e| format!("Failed to fetch sale: {}", e))?;
+            .map_err(|e| format!("Failed to fetch sale: {}", e))?;
 
-    if let Some(sale) = sales.first() {
-        Ok(sale.clone())
-    } else {
-        Err("Failed to retrieve updated sale".to_string())
-    }
+        if let Some(sale) = sales.first() {
+            Ok(sale.clone())
+        } else {
+            Err("Failed to retrieve updated sale".to_string())
+        }
+    })
 }
 
-/// Delete a sale (items will be deleted automatically due to CASCADE)
+/// Move a sale to the trash
 #[tauri::command]
-fn delete_sale(
+async fn delete_sale(
+    webview: tauri::Webview,
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    actor: AuditActor,
     id: i64,
 ) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let delete_sql = "DELETE FROM sales WHERE id = ?";
-    db.execute(delete_sql, &[&id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to delete sale: {}", e))?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    audit::attributed(actor, move_to_trash(repo.as_ref(), &trash::SALES, id, current_user_id(&sessions, &webview))).await?;
 
     Ok("Sale deleted successfully".to_string())
 }
 
+/// Take a sale out of the trash
+#[tauri::command]
+async fn restore_sale(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    id: i64,
+) -> Result<String, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    audit::attributed(actor, restore_from_trash(repo.as_ref(), &trash::SALES, id)).await?;
+
+    Ok("Sale restored successfully".to_string())
+}
+
+/// Get the sales in the trash, most recently deleted first
+#[tauri::command]
+async fn get_deleted_sales(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    page: i64,
+    per_page: i64,
+    search: Option<String>,
+) -> Result<PaginatedResponse<TrashedRecord>, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    list_trash(repo.as_ref(), &trash::SALES, page, per_page, search).await
+}
+
 /// Create a sale item (standalone, for adding items to existing sale)
 #[tauri::command]
-fn create_sale_item(
+async fn create_sale_item(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
     sale_id: i64,
     product_id: i64,
     unit_id: i64,
@@ -3975,80 +4656,75 @@ fn create_sale_item(
     purchase_item_id: Option<i64>,
     sale_type: Option<String>,
 ) -> Result<SaleItem, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    let item = SaleItemInput { product_id, unit_id, per_price, amount, purchase_item_id, sale_type };
+    audit::attributed(actor, repo.create_sale_item(sale_id, &item)).await
+        .map_err(|e| format!("Failed to create sale item: {}", e))
+}
 
-    let total = per_price * amount;
+/// Add an item to an existing sale and bring the sale's total up to date
+pub(crate) fn create_sale_item_internal(db: &Database, sale_id: i64, item: &SaleItemInput) -> Result<SaleItem, String> {
+    let SaleItemInput { product_id, unit_id, per_price, amount, purchase_item_id, ref sale_type } = *item;
 
-    let insert_sql = "INSERT INTO sale_items (sale_id, product_id, unit_id, per_price, amount, total, purchase_item_id, sale_type) VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
-    db.execute(insert_sql, &[
-        &sale_id as &dyn rusqlite::ToSql,
-        &product_id as &dyn rusqlite::ToSql,
-        &unit_id as &dyn rusqlite::ToSql,
-        &per_price as &dyn rusqlite::ToSql,
-        &amount as &dyn rusqlite::ToSql,
-        &total as &dyn rusqlite::ToSql,
-        &purchase_item_id as &dyn rusqlite::ToSql,
-        &sale_type as &dyn rusqlite::ToSql,
-    ])
-        .map_err(|e| format!("Failed to insert sale item: {}", e))?;
+    db.transaction(|db| {
+        let total = per_price * amount;
 
-    // Update sale total (items total + additional_cost)
-    let update_sale_sql = "UPDATE sales SET total_amount = (SELECT COALESCE(SUM(total), 0) FROM sale_items WHERE sale_id = ?) + COALESCE((SELECT additional_cost FROM sales WHERE id = ?), 0), updated_at = CURRENT_TIMESTAMP WHERE id = ?";
-    db.execute(update_sale_sql, &[&sale_id as &dyn rusqlite::ToSql, &sale_id as &dyn rusqlite::ToSql, &sale_id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to update sale total: {}", e))?;
+        let insert_sql = "INSERT INTO sale_items (sale_id, product_id, unit_id, per_price, amount, total, purchase_item_id, sale_type) VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
+        let item_id = db.insert(insert_sql, &[
+            &sale_id as &dyn rusqlite::ToSql,
+            &product_id as &dyn rusqlite::ToSql,
+            &unit_id as &dyn rusqlite::ToSql,
+            &per_price as &dyn rusqlite::ToSql,
+            &amount as &dyn rusqlite::ToSql,
+            &total as &dyn rusqlite::ToSql,
+            &purchase_item_id as &dyn rusqlite::ToSql,
+            sale_type as &dyn rusqlite::ToSql,
+        ])
+            .map_err(|e| format!("Failed to insert sale item: {}", e))?;
 
-    // Get the created item
-    let item_sql = "SELECT id, sale_id, product_id, unit_id, per_price, amount, total, purchase_item_id, sale_type, created_at FROM sale_items WHERE sale_id = ? AND product_id = ? ORDER BY id DESC LIMIT 1";
-    let items = db
-        .query(item_sql, &[&sale_id as &dyn rusqlite::ToSql, &product_id as &dyn rusqlite::ToSql], |row| {
-            Ok(SaleItem {
-                id: row.get(0)?,
-                sale_id: row.get(1)?,
-                product_id: row.get(2)?,
-                unit_id: row.get(3)?,
-                per_price: row.get(4)?,
-                amount: row.get(5)?,
-                total: row.get(6)?,
-                purchase_item_id: row.get(7)?,
-                sale_type: row.get(8)?,
-                created_at: row.get(9)?,
+        // Update sale total (items total + additional_cost)
+        let update_sale_sql = "UPDATE sales SET total_amount = (SELECT COALESCE(SUM(total), 0) FROM sale_items WHERE sale_id = ?) + COALESCE((SELECT additional_cost FROM sales WHERE id = ?), 0), updated_at = CURRENT_TIMESTAMP WHERE id = ?";
+        db.execute(update_sale_sql, &[&sale_id as &dyn rusqlite::ToSql, &sale_id as &dyn rusqlite::ToSql, &sale_id as &dyn rusqlite::ToSql])
+            .map_err(|e| format!("Failed to update sale total: {}", e))?;
+
+        // Get the created item
+        let item_sql = "SELECT id, sale_id, product_id, unit_id, per_price, amount, total, purchase_item_id, sale_type, created_at FROM sale_items WHERE id = ?";
+        let items = db
+            .query(item_sql, &[&item_id as &dyn rusqlite::ToSql], |row| {
+                Ok(SaleItem {
+                    id: row.get(0)?,
+                    sale_id: row.get(1)?,
+                    product_id: row.get(2)?,
+                    unit_id: row.get(3)?,
+                    per_price: row.get(4)?,
+                    amount: row.get(5)?,
+                    total: row.get(6)?,
+                    purchase_item_id: row.get(7)?,
+                    sale_type: row.get(8)?,
+                    created_at: row.get(9)?,
+                })
             })
-        })
-        .map_err(|e| format!("Failed to fetch sale item: {}", e))?;
+            .map_err(|e| format!("Failed to fetch sale item: {}", e))?;
 
-    if let Some(item) = items.first() {
-        Ok(item.clone())
-    } else {
-        Err("Failed to retrieve created sale item".to_string())
-    }
+        if let Some(item) = items.first() {
+            Ok(item.clone())
+        } else {
+            Err("Failed to retrieve created sale item".to_string())
+        }
+    })
 }
 
 /// Get sale items for a sale
 #[tauri::command]
-fn get_sale_items(db_state: State<'_, Mutex<Option<Database>>>, sale_id: i64) -> Result<Vec<SaleItem>, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let sql = "SELECT id, sale_id, product_id, unit_id, per_price, amount, total, purchase_item_id, sale_type, created_at FROM sale_items WHERE sale_id = ? ORDER BY id";
-    let items = db
-        .query(sql, &[&sale_id as &dyn rusqlite::ToSql], |row| {
-            Ok(SaleItem {
-                id: row.get(0)?,
-                sale_id: row.get(1)?,
-                product_id: row.get(2)?,
-                unit_id: row.get(3)?,
-                per_price: row.get(4)?,
-                amount: row.get(5)?,
-                total: row.get(6)?,
-                purchase_item_id: row.get(7)?,
-                sale_type: row.get(8)?,
-                created_at: row.get(9)?,
-            })
-        })
+async fn get_sale_items(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sale_id: i64,
+) -> Result<Vec<SaleItem>, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    let sale = repo.get_sale(sale_id).await
         .map_err(|e| format!("Failed to fetch sale items: {}", e))?;
-
-    Ok(items)
+    Ok(sale.map(|(_, items)| items).unwrap_or_default())
 }
 
 /// Get all batches for a product (from purchase_items)
@@ -4074,7 +4750,7 @@ fn get_product_batches(db_state: State<'_, Mutex<Option<Database>>>, product_id:
         FROM purchase_items pi
         INNER JOIN purchases p ON pi.purchase_id = p.id
         LEFT JOIN sale_items si ON si.purchase_item_id = pi.id
-        WHERE pi.product_id = ?
+        WHERE pi.product_id = ? AND p.deleted_at IS NULL
         GROUP BY pi.id, pi.purchase_id, p.batch_number, p.date, pi.expiry_date, pi.per_price, pi.per_unit, pi.wholesale_price, pi.retail_price, pi.amount
         HAVING remaining_quantity > 0
         ORDER BY p.date ASC, pi.id ASC
@@ -4103,8 +4779,10 @@ fn get_product_batches(db_state: State<'_, Mutex<Option<Database>>>, product_id:
 
 /// Update a sale item
 #[tauri::command]
-fn update_sale_item(
+async fn update_sale_item(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
     id: i64,
     product_id: i64,
     unit_id: i64,
@@ -4113,63 +4791,72 @@ fn update_sale_item(
     purchase_item_id: Option<i64>,
     sale_type: Option<String>,
 ) -> Result<SaleItem, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    let item = SaleItemInput { product_id, unit_id, per_price, amount, purchase_item_id, sale_type };
+    audit::attributed(actor, repo.update_sale_item(id, &item)).await
+        .map_err(|e| format!("Failed to update sale item: {}", e))
+}
 
-    let total = per_price * amount;
+/// Replace a sale item and bring its sale's total up to date
+pub(crate) fn update_sale_item_internal(db: &Database, id: i64, item: &SaleItemInput) -> Result<SaleItem, String> {
+    let SaleItemInput { product_id, unit_id, per_price, amount, purchase_item_id, ref sale_type } = *item;
 
-    let update_sql = "UPDATE sale_items SET product_id = ?, unit_id = ?, per_price = ?, amount = ?, total = ?, purchase_item_id = ?, sale_type = ? WHERE id = ?";
-    db.execute(update_sql, &[
-        &product_id as &dyn rusqlite::ToSql,
-        &unit_id as &dyn rusqlite::ToSql,
-        &per_price as &dyn rusqlite::ToSql,
-        &amount as &dyn rusqlite::ToSql,
-        &total as &dyn rusqlite::ToSql,
-        &purchase_item_id as &dyn rusqlite::ToSql,
-        &sale_type as &dyn rusqlite::ToSql,
-        &id as &dyn rusqlite::ToSql,
-    ])
-        .map_err(|e| format!("Failed to update sale item: {}", e))?;
+    db.transaction(|db| {
+        let total = per_price * amount;
 
-    // Get sale_id to update sale total
-    let sale_id_sql = "SELECT sale_id FROM sale_items WHERE id = ?";
-    let sale_ids = db
-        .query(sale_id_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(row.get::<_, i64>(0)?)
-        })
-        .map_err(|e| format!("Failed to fetch sale_id: {}", e))?;
+        let update_sql = "UPDATE sale_items SET product_id = ?, unit_id = ?, per_price = ?, amount = ?, total = ?, purchase_item_id = ?, sale_type = ? WHERE id = ?";
+        db.execute(update_sql, &[
+            &product_id as &dyn rusqlite::ToSql,
+            &unit_id as &dyn rusqlite::ToSql,
+            &per_price as &dyn rusqlite::ToSql,
+            &amount as &dyn rusqlite::ToSql,
+            &total as &dyn rusqlite::ToSql,
+            &purchase_item_id as &dyn rusqlite::ToSql,
+            sale_type as &dyn rusqlite::ToSql,
+            &id as &dyn rusqlite::ToSql,
+        ])
+            .map_err(|e| format!("Failed to update sale item: {}", e))?;
 
-    if let Some(sale_id) = sale_ids.first() {
-        // Update sale total (items total + additional_cost)
-        let update_sale_sql = "UPDATE sales SET total_amount = (SELECT COALESCE(SUM(total), 0) FROM sale_items WHERE sale_id = ?) + COALESCE((SELECT additional_cost FROM sales WHERE id = ?), 0), updated_at = CURRENT_TIMESTAMP WHERE id = ?";
-        db.execute(update_sale_sql, &[sale_id as &dyn rusqlite::ToSql, sale_id as &dyn rusqlite::ToSql, sale_id as &dyn rusqlite::ToSql])
-            .map_err(|e| format!("Failed to update sale total: {}", e))?;
-    }
+        // Get sale_id to update sale total
+        let sale_id_sql = "SELECT sale_id FROM sale_items WHERE id = ?";
+        let sale_ids = db
+            .query(sale_id_sql, &[&id as &dyn rusqlite::ToSql], |row| {
+                Ok(row.get::<_, i64>(0)?)
+            })
+            .map_err(|e| format!("Failed to fetch sale_id: {}", e))?;
 
-    // Get the updated item
-    let item_sql = "SELECT id, sale_id, product_id, unit_id, per_price, amount, total, purchase_item_id, sale_type, created_at FROM sale_items WHERE id = ?";
-    let items = db
-        .query(item_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(SaleItem {
-                id: row.get(0)?,
-                sale_id: row.get(1)?,
-                product_id: row.get(2)?,
-                unit_id: row.get(3)?,
-                per_price: row.get(4)?,
-                amount: row.get(5)?,
-                total: row.get(6)?,
-                purchase_item_id: row.get(7)?,
-                sale_type: row.get(8)?,
-                created_at: row.get(9)?,
+        if let Some(sale_id) = sale_ids.first() {
+            // Update sale total (items total + additional_cost)
+            let update_sale_sql = "UPDATE sales SET total_amount = (SELECT COALESCE(SUM(total), 0) FROM sale_items WHERE sale_id = ?) + COALESCE((SELECT additional_cost FROM sales WHERE id = ?), 0), updated_at = CURRENT_TIMESTAMP WHERE id = ?";
+            db.execute(update_sale_sql, &[sale_id as &dyn rusqlite::ToSql, sale_id as &dyn rusqlite::ToSql, sale_id as &dyn rusqlite::ToSql])
+                .map_err(|e| format!("Failed to update sale total: {}", e))?;
+        }
+
+        // Get the updated item
+        let item_sql = "SELECT id, sale_id, product_id, unit_id, per_price, amount, total, purchase_item_id, sale_type, created_at FROM sale_items WHERE id = ?";
+        let items = db
+            .query(item_sql, &[&id as &dyn rusqlite::ToSql], |row| {
+                Ok(SaleItem {
+                    id: row.get(0)?,
+                    sale_id: row.get(1)?,
+                    product_id: row.get(2)?,
+                    unit_id: row.get(3)?,
+                    per_price: row.get(4)?,
+                    amount: row.get(5)?,
+                    total: row.get(6)?,
+                    purchase_item_id: row.get(7)?,
+                    sale_type: row.get(8)?,
+                    created_at: row.get(9)?,
+                })
             })
-        })
-        .map_err(|e| format!("Failed to fetch sale item: {}", e))?;
+            .map_err(|e| format!("Failed to fetch sale item: {}", e))?;
 
-    if let Some(item) = items.first() {
-        Ok(item.clone())
-    } else {
-        Err("Failed to retrieve updated sale item".to_string())
-    }
+        if let Some(item) = items.first() {
+            Ok(item.clone())
+        } else {
+            Err("Failed to retrieve updated sale item".to_string())
+        }
+    })
 }
 
 /// Delete a sale item
@@ -4181,32 +4868,36 @@ fn delete_sale_item(
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
 
-    // Get sale_id before deleting
-    let sale_id_sql = "SELECT sale_id FROM sale_items WHERE id = ?";
-    let sale_ids = db
-        .query(sale_id_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(row.get::<_, i64>(0)?)
-        })
-        .map_err(|e| format!("Failed to fetch sale_id: {}", e))?;
+    db.transaction(|db| {
+        // Get sale_id before deleting
+        let sale_id_sql = "SELECT sale_id FROM sale_items WHERE id = ?";
+        let sale_ids = db
+            .query(sale_id_sql, &[&id as &dyn rusqlite::ToSql], |row| {
+                Ok(row.get::<_, i64>(0)?)
+            })
+            .map_err(|e| format!("Failed to fetch sale_id: {}", e))?;
 
-    let sale_id = sale_ids.first().ok_or("Sale item not found")?;
+        let sale_id = sale_ids.first().ok_or("Sale item not found")?;
 
-    let delete_sql = "DELETE FROM sale_items WHERE id = ?";
-    db.execute(delete_sql, &[&id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to delete sale item: {}", e))?;
+        let delete_sql = "DELETE FROM sale_items WHERE id = ?";
+        db.execute(delete_sql, &[&id as &dyn rusqlite::ToSql])
+            .map_err(|e| format!("Failed to delete sale item: {}", e))?;
 
-    // Update sale total (items total + additional_cost)
-    let update_sale_sql = "UPDATE sales SET total_amount = (SELECT COALESCE(SUM(total), 0) FROM sale_items WHERE sale_id = ?) + COALESCE((SELECT additional_cost FROM sales WHERE id = ?), 0), updated_at = CURRENT_TIMESTAMP WHERE id = ?";
-    db.execute(update_sale_sql, &[sale_id as &dyn rusqlite::ToSql, sale_id as &dyn rusqlite::ToSql, sale_id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to update sale total: {}", e))?;
+        // Update sale total (items total + additional_cost)
+        let update_sale_sql = "UPDATE sales SET total_amount = (SELECT COALESCE(SUM(total), 0) FROM sale_items WHERE sale_id = ?) + COALESCE((SELECT additional_cost FROM sales WHERE id = ?), 0), updated_at = CURRENT_TIMESTAMP WHERE id = ?";
+        db.execute(update_sale_sql, &[sale_id as &dyn rusqlite::ToSql, sale_id as &dyn rusqlite::ToSql, sale_id as &dyn rusqlite::ToSql])
+            .map_err(|e| format!("Failed to update sale total: {}", e))?;
 
-    Ok("Sale item deleted successfully".to_string())
+        Ok("Sale item deleted successfully".to_string())
+    })
 }
 
 /// Create a sale payment
 #[tauri::command]
-fn create_sale_payment(
+async fn create_sale_payment(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
     sale_id: i64,
     account_id: Option<i64>,
     currency_id: Option<i64>,
@@ -4214,191 +4905,196 @@ fn create_sale_payment(
     amount: f64,
     date: String,
 ) -> Result<SalePayment, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    let payment = SalePaymentInput { account_id, currency_id, exchange_rate, amount, date };
+    audit::attributed(actor, repo.create_sale_payment(sale_id, &payment)).await
+        .map_err(|e| format!("Failed to create sale payment: {}", e))
+}
+
+/// Record a payment towards a sale: deposit it to its account, bring the sale's paid
+/// amount up to date and post its journal entry
+pub(crate) fn create_sale_payment_internal(db: &Database, sale_id: i64, payment: &SalePaymentInput) -> Result<SalePayment, String> {
+    let SalePaymentInput { account_id, currency_id, exchange_rate, amount, ref date } = *payment;
+
+    db.transaction(|db| {
+        let base_amount = amount * exchange_rate;
+        let payment_currency_id = currency_id.unwrap_or_else(|| {
+            // Get sale currency or base currency
+            let sale_currency_sql = "SELECT currency_id FROM sales WHERE id = ?";
+            db.query(sale_currency_sql, &[&sale_id as &dyn rusqlite::ToSql], |row| Ok(row.get::<_, Option<i64>>(0)?))
+                .ok()
+                .and_then(|v| v.first().and_then(|c| *c))
+                .unwrap_or_else(|| {
+                    // Fallback to base currency
+                    db.query("SELECT id FROM currencies WHERE base = 1 LIMIT 1", &[], |row| Ok(row.get::<_, i64>(0)?))
+                        .ok()
+                        .and_then(|v| v.first().copied())
+                        .unwrap_or(1)
+                })
+        });
 
-    let base_amount = amount * exchange_rate;
-    let payment_currency_id = currency_id.unwrap_or_else(|| {
-        // Get sale currency or base currency
-        let sale_currency_sql = "SELECT currency_id FROM sales WHERE id = ?";
-        db.query(sale_currency_sql, &[&sale_id as &dyn rusqlite::ToSql], |row| Ok(row.get::<_, Option<i64>>(0)?))
-            .ok()
-            .and_then(|v| v.first().and_then(|c| *c))
-            .unwrap_or_else(|| {
-                // Fallback to base currency
-                db.query("SELECT id FROM currencies WHERE base = 1 LIMIT 1", &[], |row| Ok(row.get::<_, i64>(0)?))
-                    .ok()
-                    .and_then(|v| v.first().copied())
-                    .unwrap_or(1)
-            })
-    });
+        let insert_sql = "INSERT INTO sale_payments (sale_id, account_id, currency_id, exchange_rate, amount, base_amount, date) VALUES (?, ?, ?, ?, ?, ?, ?)";
+        let payment_id = db.insert(insert_sql, &[
+            &sale_id as &dyn rusqlite::ToSql,
+            &account_id as &dyn rusqlite::ToSql,
+            &payment_currency_id as &dyn rusqlite::ToSql,
+            &exchange_rate as &dyn rusqlite::ToSql,
+            &amount as &dyn rusqlite::ToSql,
+            &base_amount as &dyn rusqlite::ToSql,
+            date as &dyn rusqlite::ToSql,
+        ])
+            .map_err(|e| format!("Failed to insert sale payment: {}", e))?;
 
-    let insert_sql = "INSERT INTO sale_payments (sale_id, account_id, currency_id, exchange_rate, amount, base_amount, date) VALUES (?, ?, ?, ?, ?, ?, ?)";
-    db.execute(insert_sql, &[
-        &sale_id as &dyn rusqlite::ToSql,
-        &account_id as &dyn rusqlite::ToSql,
-        &payment_currency_id as &dyn rusqlite::ToSql,
-        &exchange_rate as &dyn rusqlite::ToSql,
-        &amount as &dyn rusqlite::ToSql,
-        &base_amount as &dyn rusqlite::ToSql,
-        &date as &dyn rusqlite::ToSql,
-    ])
-        .map_err(|e| format!("Failed to insert sale payment: {}", e))?;
+        // If account_id is provided, deposit the payment amount to the account
+        if let Some(aid) = account_id {
+            // Get current balance for the account's currency
+            let current_balance = get_account_balance_by_currency_internal(db, aid, payment_currency_id)
+                .unwrap_or(0.0);
 
-    // If account_id is provided, deposit the payment amount to the account
-    if let Some(aid) = account_id {
-        // Get current balance for the account's currency
-        let current_balance = get_account_balance_by_currency_internal(db, aid, payment_currency_id)
-            .unwrap_or(0.0);
-        
-        // Get currency name for transaction record
-        let currency_name_sql = "SELECT name FROM currencies WHERE id = ? LIMIT 1";
-        let currency_names = db
-            .query(currency_name_sql, &[&payment_currency_id as &dyn rusqlite::ToSql], |row| {
-                Ok(row.get::<_, String>(0)?)
-            })
-            .map_err(|e| format!("Failed to find currency name: {}", e))?;
-        
-        if let Some(currency_name) = currency_names.first() {
-            // Create account transaction record for this payment (deposit)
-            let payment_notes = Some(format!("Payment for Sale #{}", sale_id));
-            let payment_notes_str: Option<&str> = payment_notes.as_ref().map(|s| s.as_str());
-            let is_full_int = 0i64;
-            
-            let insert_transaction_sql = "INSERT INTO account_transactions (account_id, transaction_type, amount, currency, rate, total, transaction_date, is_full, notes) VALUES (?, 'deposit', ?, ?, ?, ?, ?, ?, ?)";
-            db.execute(insert_transaction_sql, &[
-                &aid as &dyn rusqlite::ToSql,
-                &amount as &dyn rusqlite::ToSql,
-                currency_name as &dyn rusqlite::ToSql,
-                &exchange_rate as &dyn rusqlite::ToSql,
-                &base_amount as &dyn rusqlite::ToSql,
-                &date as &dyn rusqlite::ToSql,
-                &is_full_int as &dyn rusqlite::ToSql,
-                &payment_notes_str as &dyn rusqlite::ToSql,
-            ])
-            .map_err(|e| format!("Failed to create account transaction: {}", e))?;
-            
-            // Add the payment amount to the balance (deposit)
-            let new_balance = current_balance + amount;
-            
-            // Update account currency balance
-            update_account_currency_balance_internal(db, aid, payment_currency_id, new_balance)?;
-            
-            // Update account's current_balance
-            let new_account_balance = calculate_account_balance_internal(db, aid)?;
-            let update_balance_sql = "UPDATE accounts SET current_balance = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?";
-            db.execute(update_balance_sql, &[
-                &new_account_balance as &dyn rusqlite::ToSql,
-                &aid as &dyn rusqlite::ToSql,
-            ])
-            .map_err(|e| format!("Failed to update account balance: {}", e))?;
+            // Get currency name for transaction record
+            let currency_name_sql = "SELECT name FROM currencies WHERE id = ? LIMIT 1";
+            let currency_names = db
+                .query(currency_name_sql, &[&payment_currency_id as &dyn rusqlite::ToSql], |row| {
+                    Ok(row.get::<_, String>(0)?)
+                })
+                .map_err(|e| format!("Failed to find currency name: {}", e))?;
+
+            if let Some(currency_name) = currency_names.first() {
+                // Create account transaction record for this payment (deposit)
+                let payment_notes = Some(format!("Payment for Sale #{}", sale_id));
+                let payment_notes_str: Option<&str> = payment_notes.as_ref().map(|s| s.as_str());
+                let is_full_int = 0i64;
+
+                let insert_transaction_sql = "INSERT INTO account_transactions (account_id, transaction_type, amount, currency, rate, total, transaction_date, is_full, notes) VALUES (?, 'deposit', ?, ?, ?, ?, ?, ?, ?)";
+                db.execute(insert_transaction_sql, &[
+                    &aid as &dyn rusqlite::ToSql,
+                    &amount as &dyn rusqlite::ToSql,
+                    currency_name as &dyn rusqlite::ToSql,
+                    &exchange_rate as &dyn rusqlite::ToSql,
+                    &base_amount as &dyn rusqlite::ToSql,
+                    date as &dyn rusqlite::ToSql,
+                    &is_full_int as &dyn rusqlite::ToSql,
+                    &payment_notes_str as &dyn rusqlite::ToSql,
+                ])
+                .map_err(|e| format!("Failed to create account transaction: {}", e))?;
+
+                // Add the payment amount to the balance (deposit)
+                let new_balance = current_balance + amount;
+
+                // Update account currency balance
+                update_account_currency_balance_internal(db, aid, payment_currency_id, new_balance)?;
+
+                // Update account's current_balance
+                let new_account_balance = calculate_account_balance_internal(db, aid)?;
+                let update_balance_sql = "UPDATE accounts SET current_balance = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?";
+                db.execute(update_balance_sql, &[
+                    &new_account_balance as &dyn rusqlite::ToSql,
+                    &aid as &dyn rusqlite::ToSql,
+                ])
+                .map_err(|e| format!("Failed to update account balance: {}", e))?;
+            }
         }
-    }
 
-    // Update sale paid_amount
-    let update_sale_sql = "UPDATE sales SET paid_amount = (SELECT COALESCE(SUM(base_amount), 0) FROM sale_payments WHERE sale_id = ?), updated_at = CURRENT_TIMESTAMP WHERE id = ?";
-    db.execute(update_sale_sql, &[&sale_id as &dyn rusqlite::ToSql, &sale_id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to update sale paid amount: {}", e))?;
+        // Update sale paid_amount
+        let update_sale_sql = "UPDATE sales SET paid_amount = (SELECT COALESCE(SUM(base_amount), 0) FROM sale_payments WHERE sale_id = ?), updated_at = CURRENT_TIMESTAMP WHERE id = ?";
+        db.execute(update_sale_sql, &[&sale_id as &dyn rusqlite::ToSql, &sale_id as &dyn rusqlite::ToSql])
+            .map_err(|e| format!("Failed to update sale paid amount: {}", e))?;
 
-    // Create journal entry for payment: Debit Cash/Bank, Credit Accounts Receivable
-    let cash_account_sql = "SELECT id FROM accounts WHERE account_type = 'Asset' AND (name LIKE '%Cash%' OR name LIKE '%Bank%') LIMIT 1";
-    let cash_accounts = db.query(cash_account_sql, &[], |row| Ok(row.get::<_, i64>(0)?))
-        .ok()
-        .and_then(|v| v.first().copied());
-    
-    let ar_account_sql = "SELECT id FROM accounts WHERE account_type = 'Asset' AND name LIKE '%Receivable%' LIMIT 1";
-    let ar_accounts = db.query(ar_account_sql, &[], |row| Ok(row.get::<_, i64>(0)?))
-        .ok()
-        .and_then(|v| v.first().copied());
-
-    if let (Some(cash_account), Some(ar_account)) = (cash_accounts, ar_accounts) {
-        let journal_lines = vec![
-            (cash_account, payment_currency_id, base_amount, 0.0, exchange_rate, Some(format!("Payment for Sale #{}", sale_id))),
-            (ar_account, payment_currency_id, 0.0, base_amount, exchange_rate, Some(format!("Payment for Sale #{}", sale_id))),
-        ];
-        let _ = create_journal_entry_internal(db, &date, Some(format!("Payment for Sale #{}", sale_id)), Some("sale_payment".to_string()), Some(sale_id), journal_lines);
-    }
+        // Create journal entry for payment: Debit Cash/Bank, Credit Accounts Receivable
+        let cash_account_sql = "SELECT id FROM accounts WHERE account_type = 'Asset' AND (name LIKE '%Cash%' OR name LIKE '%Bank%') AND deleted_at IS NULL LIMIT 1";
+        let cash_accounts = db.query(cash_account_sql, &[], |row| Ok(row.get::<_, i64>(0)?))
+            .ok()
+            .and_then(|v| v.first().copied());
 
-    // Get the created payment
-    let payment_sql = "SELECT id, sale_id, account_id, currency_id, exchange_rate, amount, base_amount, date, created_at FROM sale_payments WHERE sale_id = ? ORDER BY id DESC LIMIT 1";
-    let payments = db
-        .query(payment_sql, &[&sale_id as &dyn rusqlite::ToSql], |row| {
-            Ok(SalePayment {
-                id: row.get(0)?,
-                sale_id: row.get(1)?,
-                account_id: row.get(2)?,
-                currency_id: row.get(3)?,
-                exchange_rate: row.get(4)?,
-                amount: row.get(5)?,
-                base_amount: row.get(6)?,
-                date: row.get(7)?,
-                created_at: row.get(8)?,
+        let ar_account_sql = "SELECT id FROM accounts WHERE account_type = 'Asset' AND name LIKE '%Receivable%' AND deleted_at IS NULL LIMIT 1";
+        let ar_accounts = db.query(ar_account_sql, &[], |row| Ok(row.get::<_, i64>(0)?))
+            .ok()
+            .and_then(|v| v.first().copied());
+
+        if let (Some(cash_account), Some(ar_account)) = (cash_accounts, ar_accounts) {
+            let journal_lines = vec![
+                (cash_account, payment_currency_id, base_amount, 0.0, exchange_rate, Some(format!("Payment for Sale #{}", sale_id))),
+                (ar_account, payment_currency_id, 0.0, base_amount, exchange_rate, Some(format!("Payment for Sale #{}", sale_id))),
+            ];
+            create_journal_entry_internal(db, date, Some(format!("Payment for Sale #{}", sale_id)), Some("sale_payment".to_string()), Some(sale_id), journal_lines)?;
+        }
+
+        // Get the created payment
+        let payment_sql = "SELECT id, sale_id, account_id, currency_id, exchange_rate, amount, base_amount, date, created_at FROM sale_payments WHERE id = ?";
+        let payments = db
+            .query(payment_sql, &[&payment_id as &dyn rusqlite::ToSql], |row| {
+                Ok(SalePayment {
+                    id: row.get(0)?,
+                    sale_id: row.get(1)?,
+                    account_id: row.get(2)?,
+                    currency_id: row.get(3)?,
+                    exchange_rate: row.get(4)?,
+                    amount: row.get(5)?,
+                    base_amount: row.get(6)?,
+                    date: row.get(7)?,
+                    created_at: row.get(8)?,
+                })
             })
-        })
-        .map_err(|e| format!("Failed to fetch sale payment: {}", e))?;
+            .map_err(|e| format!("Failed to fetch sale payment: {}", e))?;
 
-    if let Some(payment) = payments.first() {
-        Ok(payment.clone())
-    } else {
-        Err("Failed to retrieve created sale payment".to_string())
-    }
+        let payment = payments.first().cloned().ok_or("Failed to retrieve created sale payment")?;
+        events::record(db, events::PAYMENT_RECEIVED, &payment)
+            .map_err(|e| format!("Failed to record payment event: {}", e))?;
+        Ok(payment)
+    })
 }
 
 /// Get payments for a sale
 #[tauri::command]
-fn get_sale_payments(db_state: State<'_, Mutex<Option<Database>>>, sale_id: i64) -> Result<Vec<SalePayment>, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let sql = "SELECT id, sale_id, account_id, currency_id, exchange_rate, amount, base_amount, date, created_at FROM sale_payments WHERE sale_id = ? ORDER BY date DESC, created_at DESC";
-    let payments = db
-        .query(sql, &[&sale_id as &dyn rusqlite::ToSql], |row| {
-            Ok(SalePayment {
-                id: row.get(0)?,
-                sale_id: row.get(1)?,
-                account_id: row.get(2)?,
-                currency_id: row.get(3)?,
-                exchange_rate: row.get(4)?,
-                amount: row.get(5)?,
-                base_amount: row.get(6)?,
-                date: row.get(7)?,
-                created_at: row.get(8)?,
-            })
-        })
-        .map_err(|e| format!("Failed to fetch sale payments: {}", e))?;
-
-    Ok(payments)
+async fn get_sale_payments(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sale_id: i64,
+) -> Result<Vec<SalePayment>, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    repo.list_sale_payments(sale_id).await
+        .map_err(|e| format!("Failed to fetch sale payments: {}", e))
 }
 
 /// Delete a sale payment
 #[tauri::command]
-fn delete_sale_payment(
+async fn delete_sale_payment(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
     id: i64,
 ) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    audit::attributed(actor, repo.delete_sale_payment(id)).await
+        .map_err(|e| format!("Failed to delete sale payment: {}", e))?;
 
-    // Get sale_id before deleting
-    let sale_id_sql = "SELECT sale_id FROM sale_payments WHERE id = ?";
-    let sale_ids = db
-        .query(sale_id_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(row.get::<_, i64>(0)?)
-        })
-        .map_err(|e| format!("Failed to fetch sale_id: {}", e))?;
+    Ok("Sale payment deleted successfully".to_string())
+}
 
-    let sale_id = sale_ids.first().ok_or("Sale payment not found")?;
+/// Delete a sale payment and bring its sale's paid amount up to date
+pub(crate) fn delete_sale_payment_internal(db: &Database, id: i64) -> Result<(), String> {
+    db.transaction(|db| {
+        // Get sale_id before deleting
+        let sale_id_sql = "SELECT sale_id FROM sale_payments WHERE id = ?";
+        let sale_ids = db
+            .query(sale_id_sql, &[&id as &dyn rusqlite::ToSql], |row| {
+                Ok(row.get::<_, i64>(0)?)
+            })
+            .map_err(|e| format!("Failed to fetch sale_id: {}", e))?;
 
-    let delete_sql = "DELETE FROM sale_payments WHERE id = ?";
-    db.execute(delete_sql, &[&id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to delete sale payment: {}", e))?;
+        let sale_id = sale_ids.first().ok_or("Sale payment not found")?;
 
-    // Update sale paid_amount
-    let update_sale_sql = "UPDATE sales SET paid_amount = (SELECT COALESCE(SUM(amount), 0) FROM sale_payments WHERE sale_id = ?), updated_at = CURRENT_TIMESTAMP WHERE id = ?";
-    db.execute(update_sale_sql, &[sale_id as &dyn rusqlite::ToSql, sale_id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to update sale paid amount: {}", e))?;
+        let delete_sql = "DELETE FROM sale_payments WHERE id = ?";
+        db.execute(delete_sql, &[&id as &dyn rusqlite::ToSql])
+            .map_err(|e| format!("Failed to delete sale payment: {}", e))?;
 
-    Ok("Sale payment deleted successfully".to_string())
+        // Update sale paid_amount
+        let update_sale_sql = "UPDATE sales SET paid_amount = (SELECT COALESCE(SUM(amount), 0) FROM sale_payments WHERE sale_id = ?), updated_at = CURRENT_TIMESTAMP WHERE id = ?";
+        db.execute(update_sale_sql, &[sale_id as &dyn rusqlite::ToSql, sale_id as &dyn rusqlite::ToSql])
+            .map_err(|e| format!("Failed to update sale paid amount: {}", e))?;
+
+        Ok(())
+    })
 }
 
 // ExpenseType Model
@@ -4413,20 +5109,7 @@ pub struct ExpenseType {
 /// Initialize expense_types table schema
 #[tauri::command]
 fn init_expense_types_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS expense_types (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT NOT NULL UNIQUE,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
-        )
-    ";
-
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create expense_types table: {}", e))?;
+    ensure_database_open(&db_state)?;
 
     Ok("Expense types table initialized successfully".to_string())
 }
@@ -4442,13 +5125,13 @@ fn create_expense_type(
 
     // Insert new expense type
     let insert_sql = "INSERT INTO expense_types (name) VALUES (?)";
-    db.execute(insert_sql, &[&name as &dyn rusqlite::ToSql])
+    let expense_type_id = db.insert(insert_sql, &[&name as &dyn rusqlite::ToSql])
         .map_err(|e| format!("Failed to insert expense type: {}", e))?;
 
     // Get the created expense type
-    let expense_type_sql = "SELECT id, name, created_at, updated_at FROM expense_types WHERE name = ?";
+    let expense_type_sql = "SELECT id, name, created_at, updated_at FROM expense_types WHERE id = ?";
     let expense_types = db
-        .query(expense_type_sql, &[&name as &dyn rusqlite::ToSql], |row| {
+        .query(expense_type_sql, &[&expense_type_id as &dyn rusqlite::ToSql], |row| {
             Ok(ExpenseType {
                 id: row.get(0)?,
                 name: row.get(1)?,
@@ -4548,79 +5231,15 @@ pub struct Expense {
     pub total: f64,
     pub date: String,
     pub bill_no: Option<String>,
-    pub description: Option<String>,
-    pub created_at: String,
-    pub updated_at: String,
-}
-
-/// Initialize expenses table schema
-#[tauri::command]
-fn init_expenses_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    // First ensure expense_types table exists
-    let create_expense_types_sql = "
-        CREATE TABLE IF NOT EXISTS expense_types (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT NOT NULL UNIQUE,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
-        )
-    ";
-    db.execute(create_expense_types_sql, &[])
-        .map_err(|e| format!("Failed to create expense_types table: {}", e))?;
-
-    // Create expenses table with expense_type_id
-    // If table already exists with old schema, we'll handle migration
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS expenses (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            expense_type_id INTEGER NOT NULL,
-            amount REAL NOT NULL,
-            currency TEXT NOT NULL,
-            rate REAL NOT NULL DEFAULT 1.0,
-            total REAL NOT NULL,
-            date TEXT NOT NULL,
-            bill_no TEXT,
-            description TEXT,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (expense_type_id) REFERENCES expense_types(id)
-        )
-    ";
-    
-    // Try to create the table (will fail silently if it exists)
-    let _ = db.execute(create_table_sql, &[]);
-    
-    // Check if columns exist, if not, try to add them
-    let check_column_sql = "PRAGMA table_info(expenses)";
-    if let Ok(columns) = db.query(check_column_sql, &[], |row| {
-        Ok(row.get::<_, String>(1)?)
-    }) {
-        let has_expense_type_id = columns.iter().any(|c| c == "expense_type_id");
-        let has_bill_no = columns.iter().any(|c| c == "bill_no");
-        let has_description = columns.iter().any(|c| c == "description");
-        let has_name = columns.iter().any(|c| c == "name");
-        
-        if !has_expense_type_id && has_name {
-            // Old schema detected - add expense_type_id column
-            // Note: SQLite doesn't support adding NOT NULL columns to existing tables easily
-            // So we'll add it as nullable first, then the app should handle migration
-            let add_column_sql = "ALTER TABLE expenses ADD COLUMN expense_type_id INTEGER";
-            let _ = db.execute(add_column_sql, &[]);
-        }
-        
-        if !has_bill_no {
-            let add_column_sql = "ALTER TABLE expenses ADD COLUMN bill_no TEXT";
-            let _ = db.execute(add_column_sql, &[]);
-        }
-        
-        if !has_description {
-            let add_column_sql = "ALTER TABLE expenses ADD COLUMN description TEXT";
-            let _ = db.execute(add_column_sql, &[]);
-        }
-    }
+    pub description: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Initialize expenses table schema
+#[tauri::command]
+fn init_expenses_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
+    ensure_database_open(&db_state)?;
 
     Ok("Expenses table initialized successfully".to_string())
 }
@@ -4641,45 +5260,46 @@ fn create_expense(
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
 
-    // Insert new expense
-    let insert_sql = "INSERT INTO expenses (expense_type_id, amount, currency, rate, total, date, bill_no, description) VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
-    db.execute(insert_sql, &[
-        &expense_type_id as &dyn rusqlite::ToSql,
-        &amount as &dyn rusqlite::ToSql,
-        &currency as &dyn rusqlite::ToSql,
-        &rate as &dyn rusqlite::ToSql,
-        &total as &dyn rusqlite::ToSql,
-        &date as &dyn rusqlite::ToSql,
-        &bill_no as &dyn rusqlite::ToSql,
-        &description as &dyn rusqlite::ToSql,
-    ])
-        .map_err(|e| format!("Failed to insert expense: {}", e))?;
-
-    // Get the created expense
-    let expense_sql = "SELECT id, expense_type_id, amount, currency, rate, total, date, bill_no, description, created_at, updated_at FROM expenses WHERE expense_type_id = ? AND date = ? ORDER BY id DESC LIMIT 1";
-    let expenses = db
-        .query(expense_sql, &[&expense_type_id as &dyn rusqlite::ToSql, &date as &dyn rusqlite::ToSql], |row| {
-            Ok(Expense {
-                id: row.get(0)?,
-                expense_type_id: row.get(1)?,
-                amount: row.get(2)?,
-                currency: row.get(3)?,
-                rate: row.get(4)?,
-                total: row.get(5)?,
-                date: row.get(6)?,
-                bill_no: row.get(7)?,
-                description: row.get(8)?,
-                created_at: row.get(9)?,
-                updated_at: row.get(10)?,
+    db.transaction(|db| {
+        // Insert new expense
+        let insert_sql = "INSERT INTO expenses (expense_type_id, amount, currency, rate, total, date, bill_no, description) VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
+        let expense_id = db.insert(insert_sql, &[
+            &expense_type_id as &dyn rusqlite::ToSql,
+            &amount as &dyn rusqlite::ToSql,
+            &currency as &dyn rusqlite::ToSql,
+            &rate as &dyn rusqlite::ToSql,
+            &total as &dyn rusqlite::ToSql,
+            &date as &dyn rusqlite::ToSql,
+            &bill_no as &dyn rusqlite::ToSql,
+            &description as &dyn rusqlite::ToSql,
+        ])
+            .map_err(|e| format!("Failed to insert expense: {}", e))?;
+
+        // Get the created expense
+        let expense_sql = "SELECT id, expense_type_id, amount, currency, rate, total, date, bill_no, description, created_at, updated_at FROM expenses WHERE id = ?";
+        let expenses = db
+            .query(expense_sql, &[&expense_id as &dyn rusqlite::ToSql], |row| {
+                Ok(Expense {
+                    id: row.get(0)?,
+                    expense_type_id: row.get(1)?,
+                    amount: row.get(2)?,
+                    currency: row.get(3)?,
+                    rate: row.get(4)?,
+                    total: row.get(5)?,
+                    date: row.get(6)?,
+                    bill_no: row.get(7)?,
+                    description: row.get(8)?,
+                    created_at: row.get(9)?,
+                    updated_at: row.get(10)?,
+                })
             })
-        })
-        .map_err(|e| format!("Failed to fetch expense: {}", e))?;
+            .map_err(|e| format!("Failed to fetch expense: {}", e))?;
 
-    if let Some(expense) = expenses.first() {
-        Ok(expense.clone())
-    } else {
-        Err("Failed to retrieve created expense".to_string())
-    }
+        let expense = expenses.first().cloned().ok_or("Failed to retrieve created expense")?;
+        events::record(db, events::EXPENSE_RECORDED, &expense)
+            .map_err(|e| format!("Failed to record expense event: {}", e))?;
+        Ok(expense)
+    })
 }
 
 #[tauri::command]
@@ -4697,13 +5317,13 @@ fn get_expenses(
     let offset = (page - 1) * per_page;
 
     // Build WHERE clause
-    let mut where_clause = String::new();
+    let mut where_clause = "WHERE deleted_at IS NULL".to_string();
     let mut params: Vec<serde_json::Value> = Vec::new();
 
     if let Some(s) = search {
         if !s.trim().is_empty() {
              let search_term = format!("%{}%", s);
-             where_clause = "WHERE (currency LIKE ? OR date LIKE ? OR bill_no LIKE ? OR description LIKE ?)".to_string();
+             where_clause.push_str(" AND (currency LIKE ? OR date LIKE ? OR bill_no LIKE ? OR description LIKE ?)");
              params.push(serde_json::Value::String(search_term.clone()));
              params.push(serde_json::Value::String(search_term.clone()));
              params.push(serde_json::Value::String(search_term.clone()));
@@ -4876,22 +5496,49 @@ fn update_expense(
     }
 }
 
-/// Delete an expense
+/// Move an expense to the trash
 #[tauri::command]
-fn delete_expense(
+async fn delete_expense(
+    webview: tauri::Webview,
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    actor: AuditActor,
     id: i64,
 ) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let delete_sql = "DELETE FROM expenses WHERE id = ?";
-    db.execute(delete_sql, &[&id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to delete expense: {}", e))?;
+    let repo = trash_repositories(&db_state, &surreal_state, &trash::EXPENSES)?;
+    audit::attributed(actor, move_to_trash(repo.as_ref(), &trash::EXPENSES, id, current_user_id(&sessions, &webview))).await?;
 
     Ok("Expense deleted successfully".to_string())
 }
 
+/// Take an expense out of the trash
+#[tauri::command]
+async fn restore_expense(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    id: i64,
+) -> Result<String, String> {
+    let repo = trash_repositories(&db_state, &surreal_state, &trash::EXPENSES)?;
+    audit::attributed(actor, restore_from_trash(repo.as_ref(), &trash::EXPENSES, id)).await?;
+
+    Ok("Expense restored successfully".to_string())
+}
+
+/// Get the expenses in the trash, most recently deleted first
+#[tauri::command]
+async fn get_deleted_expenses(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    page: i64,
+    per_page: i64,
+    search: Option<String>,
+) -> Result<PaginatedResponse<TrashedRecord>, String> {
+    let repo = trash_repositories(&db_state, &surreal_state, &trash::EXPENSES)?;
+    list_trash(repo.as_ref(), &trash::EXPENSES, page, per_page, search).await
+}
+
 // Employee Model
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Employee {
@@ -4912,28 +5559,7 @@ pub struct Employee {
 /// Initialize employees table schema
 #[tauri::command]
 fn init_employees_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS employees (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            full_name TEXT NOT NULL,
-            phone TEXT NOT NULL,
-            email TEXT,
-            address TEXT NOT NULL,
-            position TEXT,
-            hire_date TEXT,
-            base_salary REAL,
-            photo_path TEXT,
-            notes TEXT,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
-        )
-    ";
-
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create employees table: {}", e))?;
+    ensure_database_open(&db_state)?;
 
     Ok("Employees table initialized successfully".to_string())
 }
@@ -4963,7 +5589,7 @@ fn create_employee(
     let photo_path_str: Option<&str> = photo_path.as_ref().map(|s| s.as_str());
     let notes_str: Option<&str> = notes.as_ref().map(|s| s.as_str());
     
-    db.execute(insert_sql, &[
+    let employee_id = db.insert(insert_sql, &[
         &full_name as &dyn rusqlite::ToSql,
         &phone as &dyn rusqlite::ToSql,
         &email_str as &dyn rusqlite::ToSql,
@@ -4977,9 +5603,9 @@ fn create_employee(
         .map_err(|e| format!("Failed to insert employee: {}", e))?;
 
     // Get the created employee
-    let employee_sql = "SELECT id, full_name, phone, email, address, position, hire_date, base_salary, photo_path, notes, created_at, updated_at FROM employees WHERE full_name = ? AND phone = ? ORDER BY id DESC LIMIT 1";
+    let employee_sql = "SELECT id, full_name, phone, email, address, position, hire_date, base_salary, photo_path, notes, created_at, updated_at FROM employees WHERE id = ?";
     let employees = db
-        .query(employee_sql, &[&full_name as &dyn rusqlite::ToSql, &phone as &dyn rusqlite::ToSql], |row| {
+        .query(employee_sql, &[&employee_id as &dyn rusqlite::ToSql], |row| {
             Ok(Employee {
                 id: row.get(0)?,
                 full_name: row.get(1)?,
@@ -5020,13 +5646,13 @@ fn get_employees(
     let offset = (page - 1) * per_page;
     
     // Build WHERE clause
-    let mut where_clause = String::new();
+    let mut where_clause = "WHERE deleted_at IS NULL".to_string();
     let mut params: Vec<serde_json::Value> = Vec::new();
 
     if let Some(s) = search {
         if !s.trim().is_empty() {
             let search_term = format!("%{}%", s);
-            where_clause = "WHERE (full_name LIKE ? OR phone LIKE ? OR email LIKE ? OR position LIKE ?)".to_string();
+            where_clause.push_str(" AND (full_name LIKE ? OR phone LIKE ? OR email LIKE ? OR position LIKE ?)");
             params.push(serde_json::Value::String(search_term.clone())); // full_name
             params.push(serde_json::Value::String(search_term.clone())); // phone
             params.push(serde_json::Value::String(search_term.clone())); // email
@@ -5228,22 +5854,49 @@ fn update_employee(
     }
 }
 
-/// Delete an employee
+/// Move an employee to the trash
 #[tauri::command]
-fn delete_employee(
+async fn delete_employee(
+    webview: tauri::Webview,
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    actor: AuditActor,
     id: i64,
 ) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let delete_sql = "DELETE FROM employees WHERE id = ?";
-    db.execute(delete_sql, &[&id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to delete employee: {}", e))?;
+    let repo = trash_repositories(&db_state, &surreal_state, &trash::EMPLOYEES)?;
+    audit::attributed(actor, move_to_trash(repo.as_ref(), &trash::EMPLOYEES, id, current_user_id(&sessions, &webview))).await?;
 
     Ok("Employee deleted successfully".to_string())
 }
 
+/// Take an employee out of the trash
+#[tauri::command]
+async fn restore_employee(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    id: i64,
+) -> Result<String, String> {
+    let repo = trash_repositories(&db_state, &surreal_state, &trash::EMPLOYEES)?;
+    audit::attributed(actor, restore_from_trash(repo.as_ref(), &trash::EMPLOYEES, id)).await?;
+
+    Ok("Employee restored successfully".to_string())
+}
+
+/// Get the employees in the trash, most recently deleted first
+#[tauri::command]
+async fn get_deleted_employees(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    page: i64,
+    per_page: i64,
+    search: Option<String>,
+) -> Result<PaginatedResponse<TrashedRecord>, String> {
+    let repo = trash_repositories(&db_state, &surreal_state, &trash::EMPLOYEES)?;
+    list_trash(repo.as_ref(), &trash::EMPLOYEES, page, per_page, search).await
+}
+
 // Salary Model
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Salary {
@@ -5261,43 +5914,7 @@ pub struct Salary {
 /// Initialize salaries table schema
 #[tauri::command]
 fn init_salaries_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    // Create table if it doesn't exist
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS salaries (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            employee_id INTEGER NOT NULL,
-            year INTEGER NOT NULL,
-            month TEXT NOT NULL,
-            amount REAL NOT NULL,
-            deductions REAL NOT NULL DEFAULT 0,
-            notes TEXT,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (employee_id) REFERENCES employees(id) ON DELETE CASCADE,
-            UNIQUE(employee_id, year, month)
-        )
-    ";
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create salaries table: {}", e))?;
-
-    // Check if deductions column exists, if not add it
-    let check_column_sql = "PRAGMA table_info(salaries)";
-    if let Ok(columns) = db.query(check_column_sql, &[], |row| {
-        Ok(row.get::<_, String>(1)?)
-    }) {
-        let has_deductions = columns.iter().any(|c| c == "deductions");
-        if !has_deductions {
-            // Add deductions column
-            let add_column_sql = "ALTER TABLE salaries ADD COLUMN deductions REAL NOT NULL DEFAULT 0";
-            let _ = db.execute(add_column_sql, &[]);
-        }
-    }
-
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create salaries table: {}", e))?;
+    ensure_database_open(&db_state)?;
 
     Ok("Salaries table initialized successfully".to_string())
 }
@@ -5316,43 +5933,44 @@ fn create_salary(
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
 
-    // Insert new salary
-    let insert_sql = "INSERT INTO salaries (employee_id, year, month, amount, deductions, notes) VALUES (?, ?, ?, ?, ?, ?)";
-    let notes_str: Option<&str> = notes.as_ref().map(|s| s.as_str());
+    db.transaction(|db| {
+        // Insert new salary
+        let insert_sql = "INSERT INTO salaries (employee_id, year, month, amount, deductions, notes) VALUES (?, ?, ?, ?, ?, ?)";
+        let notes_str: Option<&str> = notes.as_deref();
     
-    db.execute(insert_sql, &[
-        &employee_id as &dyn rusqlite::ToSql,
-        &year as &dyn rusqlite::ToSql,
-        &month as &dyn rusqlite::ToSql,
-        &amount as &dyn rusqlite::ToSql,
-        &deductions as &dyn rusqlite::ToSql,
-        &notes_str as &dyn rusqlite::ToSql,
-    ])
-        .map_err(|e| format!("Failed to insert salary: {}", e))?;
-
-    // Get the created salary
-    let salary_sql = "SELECT id, employee_id, year, month, amount, deductions, notes, created_at, updated_at FROM salaries WHERE employee_id = ? AND year = ? AND month = ? ORDER BY id DESC LIMIT 1";
-    let salaries = db
-        .query(salary_sql, &[&employee_id as &dyn rusqlite::ToSql, &year as &dyn rusqlite::ToSql, &month as &dyn rusqlite::ToSql], |row| {
-            Ok(Salary {
-                id: row.get(0)?,
-                employee_id: row.get(1)?,
-                year: row.get(2)?,
-                month: row.get(3)?,
-                amount: row.get(4)?,
-                deductions: row.get(5)?,
-                notes: row.get::<_, Option<String>>(6)?,
-                created_at: row.get(7)?,
-                updated_at: row.get(8)?,
+        let salary_id = db.insert(insert_sql, &[
+            &employee_id as &dyn rusqlite::ToSql,
+            &year as &dyn rusqlite::ToSql,
+            &month as &dyn rusqlite::ToSql,
+            &amount as &dyn rusqlite::ToSql,
+            &deductions as &dyn rusqlite::ToSql,
+            &notes_str as &dyn rusqlite::ToSql,
+        ])
+            .map_err(|e| format!("Failed to insert salary: {}", e))?;
+
+        // Get the created salary
+        let salary_sql = "SELECT id, employee_id, year, month, amount, deductions, notes, created_at, updated_at FROM salaries WHERE id = ?";
+        let salaries = db
+            .query(salary_sql, &[&salary_id as &dyn rusqlite::ToSql], |row| {
+                Ok(Salary {
+                    id: row.get(0)?,
+                    employee_id: row.get(1)?,
+                    year: row.get(2)?,
+                    month: row.get(3)?,
+                    amount: row.get(4)?,
+                    deductions: row.get(5)?,
+                    notes: row.get::<_, Option<String>>(6)?,
+                    created_at: row.get(7)?,
+                    updated_at: row.get(8)?,
+                })
             })
-        })
-        .map_err(|e| format!("Failed to fetch salary: {}", e))?;
+            .map_err(|e| format!("Failed to fetch salary: {}", e))?;
 
-    if let Some(salary) = salaries.first() {
-        Ok(salary.clone())
-    } else {
-        Err("Failed to retrieve created salary".to_string())
-    }
+        let salary = salaries.first().cloned().ok_or("Failed to retrieve created salary")?;
+        events::record(db, events::SALARY_PAID, &salary)
+            .map_err(|e| format!("Failed to record salary event: {}", e))?;
+        Ok(salary)
+    })
 }
 
 /// Get all salaries
@@ -5371,13 +5989,13 @@ fn get_salaries(
     let offset = (page - 1) * per_page;
 
     // Build WHERE clause
-    let mut where_clause = String::new();
+    let mut where_clause = "WHERE s.deleted_at IS NULL".to_string();
     let mut params: Vec<serde_json::Value> = Vec::new();
 
     if let Some(s) = search {
         if !s.trim().is_empty() {
              let search_term = format!("%{}%", s);
-             where_clause = "WHERE (CAST(s.year AS TEXT) LIKE ? OR s.month LIKE ? OR s.employee_id IN (SELECT id FROM employees WHERE full_name LIKE ?))".to_string();
+             where_clause.push_str(" AND (CAST(s.year AS TEXT) LIKE ? OR s.month LIKE ? OR s.employee_id IN (SELECT id FROM employees WHERE full_name LIKE ?))");
              params.push(serde_json::Value::String(search_term.clone()));
              params.push(serde_json::Value::String(search_term.clone()));
              params.push(serde_json::Value::String(search_term));
@@ -5468,7 +6086,7 @@ fn get_salaries_by_employee(
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
 
-    let sql = "SELECT id, employee_id, year, month, amount, COALESCE(deductions, 0) as deductions, notes, created_at, updated_at FROM salaries WHERE employee_id = ? ORDER BY year DESC, month DESC";
+    let sql = "SELECT id, employee_id, year, month, amount, COALESCE(deductions, 0) as deductions, notes, created_at, updated_at FROM salaries WHERE employee_id = ? AND deleted_at IS NULL ORDER BY year DESC, month DESC";
     let salaries = db
         .query(sql, &[&employee_id as &dyn rusqlite::ToSql], |row| {
             Ok(Salary {
@@ -5576,22 +6194,49 @@ fn update_salary(
     }
 }
 
-/// Delete a salary
+/// Move a salary to the trash
 #[tauri::command]
-fn delete_salary(
+async fn delete_salary(
+    webview: tauri::Webview,
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    actor: AuditActor,
     id: i64,
 ) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let delete_sql = "DELETE FROM salaries WHERE id = ?";
-    db.execute(delete_sql, &[&id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to delete salary: {}", e))?;
+    let repo = trash_repositories(&db_state, &surreal_state, &trash::SALARIES)?;
+    audit::attributed(actor, move_to_trash(repo.as_ref(), &trash::SALARIES, id, current_user_id(&sessions, &webview))).await?;
 
     Ok("Salary deleted successfully".to_string())
 }
 
+/// Take a salary out of the trash
+#[tauri::command]
+async fn restore_salary(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    id: i64,
+) -> Result<String, String> {
+    let repo = trash_repositories(&db_state, &surreal_state, &trash::SALARIES)?;
+    audit::attributed(actor, restore_from_trash(repo.as_ref(), &trash::SALARIES, id)).await?;
+
+    Ok("Salary restored successfully".to_string())
+}
+
+/// Get the salaries in the trash, most recently deleted first
+#[tauri::command]
+async fn get_deleted_salaries(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    page: i64,
+    per_page: i64,
+    search: Option<String>,
+) -> Result<PaginatedResponse<TrashedRecord>, String> {
+    let repo = trash_repositories(&db_state, &surreal_state, &trash::SALARIES)?;
+    list_trash(repo.as_ref(), &trash::SALARIES, page, per_page, search).await
+}
+
 // Deduction Model
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Deduction {
@@ -5609,47 +6254,7 @@ pub struct Deduction {
 /// Initialize deductions table schema
 #[tauri::command]
 fn init_deductions_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    // Create table if it doesn't exist
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS deductions (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            employee_id INTEGER NOT NULL,
-            year INTEGER NOT NULL DEFAULT 1403,
-            month TEXT NOT NULL DEFAULT 'حمل',
-            currency TEXT NOT NULL,
-            rate REAL NOT NULL DEFAULT 1.0,
-            amount REAL NOT NULL,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (employee_id) REFERENCES employees(id) ON DELETE CASCADE
-        )
-    ";
-
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create deductions table: {}", e))?;
-
-    // Check if year column exists, if not add it
-    let check_column_sql = "PRAGMA table_info(deductions)";
-    if let Ok(columns) = db.query(check_column_sql, &[], |row| {
-        Ok(row.get::<_, String>(1)?)
-    }) {
-        let has_year = columns.iter().any(|c| c == "year");
-        if !has_year {
-            // Add year column
-            let add_year_sql = "ALTER TABLE deductions ADD COLUMN year INTEGER NOT NULL DEFAULT 1403";
-            let _ = db.execute(add_year_sql, &[]);
-        }
-        
-        let has_month = columns.iter().any(|c| c == "month");
-        if !has_month {
-            // Add month column
-            let add_month_sql = "ALTER TABLE deductions ADD COLUMN month TEXT NOT NULL DEFAULT 'حمل'";
-            let _ = db.execute(add_month_sql, &[]);
-        }
-    }
+    ensure_database_open(&db_state)?;
 
     Ok("Deductions table initialized successfully".to_string())
 }
@@ -5670,7 +6275,7 @@ fn create_deduction(
 
     // Insert new deduction
     let insert_sql = "INSERT INTO deductions (employee_id, year, month, currency, rate, amount) VALUES (?, ?, ?, ?, ?, ?)";
-    db.execute(insert_sql, &[
+    let deduction_id = db.insert(insert_sql, &[
         &employee_id as &dyn rusqlite::ToSql,
         &year as &dyn rusqlite::ToSql,
         &month as &dyn rusqlite::ToSql,
@@ -5681,16 +6286,9 @@ fn create_deduction(
         .map_err(|e| format!("Failed to insert deduction: {}", e))?;
 
     // Get the created deduction
-    let deduction_sql = "SELECT id, employee_id, year, month, currency, rate, amount, created_at, updated_at FROM deductions WHERE employee_id = ? AND year = ? AND month = ? AND currency = ? AND rate = ? AND amount = ? ORDER BY id DESC LIMIT 1";
+    let deduction_sql = "SELECT id, employee_id, year, month, currency, rate, amount, created_at, updated_at FROM deductions WHERE id = ?";
     let deductions = db
-        .query(deduction_sql, &[
-            &employee_id as &dyn rusqlite::ToSql,
-            &year as &dyn rusqlite::ToSql,
-            &month as &dyn rusqlite::ToSql,
-            &currency as &dyn rusqlite::ToSql,
-            &rate as &dyn rusqlite::ToSql,
-            &amount as &dyn rusqlite::ToSql,
-        ], |row| {
+        .query(deduction_sql, &[&deduction_id as &dyn rusqlite::ToSql], |row| {
             Ok(Deduction {
                 id: row.get(0)?,
                 employee_id: row.get(1)?,
@@ -5728,13 +6326,13 @@ fn get_deductions(
     let offset = (page - 1) * per_page;
 
     // Build WHERE clause
-    let mut where_clause = String::new();
+    let mut where_clause = "WHERE deleted_at IS NULL".to_string();
     let mut params: Vec<serde_json::Value> = Vec::new();
 
     if let Some(s) = search {
         if !s.trim().is_empty() {
              let search_term = format!("%{}%", s);
-             where_clause = "WHERE (currency LIKE ? OR month LIKE ? OR CAST(year AS TEXT) LIKE ?)".to_string();
+             where_clause.push_str(" AND (currency LIKE ? OR month LIKE ? OR CAST(year AS TEXT) LIKE ?)");
              params.push(serde_json::Value::String(search_term.clone()));
              params.push(serde_json::Value::String(search_term.clone()));
              params.push(serde_json::Value::String(search_term));
@@ -5825,7 +6423,7 @@ fn get_deductions_by_employee(
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
 
-    let sql = "SELECT id, employee_id, COALESCE(year, 1403) as year, COALESCE(month, 'حمل') as month, currency, rate, amount, created_at, updated_at FROM deductions WHERE employee_id = ? ORDER BY year DESC, month DESC, created_at DESC";
+    let sql = "SELECT id, employee_id, COALESCE(year, 1403) as year, COALESCE(month, 'حمل') as month, currency, rate, amount, created_at, updated_at FROM deductions WHERE employee_id = ? AND deleted_at IS NULL ORDER BY year DESC, month DESC, created_at DESC";
     let deductions = db
         .query(sql, &[&employee_id as &dyn rusqlite::ToSql], |row| {
             Ok(Deduction {
@@ -5856,7 +6454,7 @@ fn get_deductions_by_employee_year_month(
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
 
-    let sql = "SELECT id, employee_id, COALESCE(year, 1403) as year, COALESCE(month, 'حمل') as month, currency, rate, amount, created_at, updated_at FROM deductions WHERE employee_id = ? AND year = ? AND month = ? ORDER BY created_at DESC";
+    let sql = "SELECT id, employee_id, COALESCE(year, 1403) as year, COALESCE(month, 'حمل') as month, currency, rate, amount, created_at, updated_at FROM deductions WHERE employee_id = ? AND year = ? AND month = ? AND deleted_at IS NULL ORDER BY created_at DESC";
     let deductions = db
         .query(sql, &[
             &employee_id as &dyn rusqlite::ToSql,
@@ -5959,22 +6557,49 @@ fn update_deduction(
     }
 }
 
-/// Delete a deduction
+/// Move a deduction to the trash
 #[tauri::command]
-fn delete_deduction(
+async fn delete_deduction(
+    webview: tauri::Webview,
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    actor: AuditActor,
     id: i64,
 ) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let delete_sql = "DELETE FROM deductions WHERE id = ?";
-    db.execute(delete_sql, &[&id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to delete deduction: {}", e))?;
+    let repo = trash_repositories(&db_state, &surreal_state, &trash::DEDUCTIONS)?;
+    audit::attributed(actor, move_to_trash(repo.as_ref(), &trash::DEDUCTIONS, id, current_user_id(&sessions, &webview))).await?;
 
     Ok("Deduction deleted successfully".to_string())
 }
 
+/// Take a deduction out of the trash
+#[tauri::command]
+async fn restore_deduction(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    id: i64,
+) -> Result<String, String> {
+    let repo = trash_repositories(&db_state, &surreal_state, &trash::DEDUCTIONS)?;
+    audit::attributed(actor, restore_from_trash(repo.as_ref(), &trash::DEDUCTIONS, id)).await?;
+
+    Ok("Deduction restored successfully".to_string())
+}
+
+/// Get the deductions in the trash, most recently deleted first
+#[tauri::command]
+async fn get_deleted_deductions(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    page: i64,
+    per_page: i64,
+    search: Option<String>,
+) -> Result<PaginatedResponse<TrashedRecord>, String> {
+    let repo = trash_repositories(&db_state, &surreal_state, &trash::DEDUCTIONS)?;
+    list_trash(repo.as_ref(), &trash::DEDUCTIONS, page, per_page, search).await
+}
+
 // ========== Company Settings ==========
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -5992,56 +6617,7 @@ pub struct CompanySettings {
 /// Initialize company_settings table schema
 #[tauri::command]
 fn init_company_settings_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    // First, check if font column exists, if not add it
-    let check_column_sql = "PRAGMA table_info(company_settings)";
-    let columns = db.query(check_column_sql, &[], |row| {
-        Ok(row.get::<_, String>(1)?)
-    }).unwrap_or_else(|_| vec![]);
-    
-    let has_font_column = columns.iter().any(|col| col == "font");
-    
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS company_settings (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT NOT NULL,
-            logo TEXT,
-            phone TEXT,
-            address TEXT,
-            font TEXT,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
-        )
-    ";
-
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create company_settings table: {}", e))?;
-
-    // Add font column if it doesn't exist (for existing databases)
-    if !has_font_column {
-        db.execute("ALTER TABLE company_settings ADD COLUMN font TEXT", &[])
-            .map_err(|e| format!("Failed to add font column: {}", e))?;
-    }
-
-    // Insert default row if table is empty
-    let count_sql = "SELECT COUNT(*) FROM company_settings";
-    let counts = db.query(count_sql, &[], |row| Ok(row.get::<_, i64>(0)?))
-        .unwrap_or_else(|_| vec![]);
-    let count: i64 = counts.first().copied().unwrap_or(0);
-    
-    if count == 0 {
-        let insert_sql = "INSERT INTO company_settings (name, logo, phone, address, font) VALUES (?, ?, ?, ?, ?)";
-        db.execute(insert_sql, &[
-            &"شرکت" as &dyn rusqlite::ToSql,
-            &None::<String> as &dyn rusqlite::ToSql,
-            &None::<String> as &dyn rusqlite::ToSql,
-            &None::<String> as &dyn rusqlite::ToSql,
-            &None::<String> as &dyn rusqlite::ToSql,
-        ])
-        .map_err(|e| format!("Failed to insert default company settings: {}", e))?;
-    }
+    ensure_database_open(&db_state)?;
 
     Ok("Company settings table initialized successfully".to_string())
 }
@@ -6085,55 +6661,57 @@ fn update_company_settings(
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
 
-    // Check if settings exist
-    let count_sql = "SELECT COUNT(*) FROM company_settings";
-    let counts = db.query(count_sql, &[], |row| Ok(row.get::<_, i64>(0)?))
-        .unwrap_or_else(|_| vec![]);
-    let count: i64 = counts.first().copied().unwrap_or(0);
+    db.transaction(|db| {
+        // Check if settings exist
+        let count_sql = "SELECT COUNT(*) FROM company_settings";
+        let counts = db.query(count_sql, &[], |row| Ok(row.get::<_, i64>(0)?))
+            .unwrap_or_else(|_| vec![]);
+        let count: i64 = counts.first().copied().unwrap_or(0);
 
-    if count == 0 {
-        // Insert new settings
-        let insert_sql = "INSERT INTO company_settings (name, logo, phone, address, font) VALUES (?, ?, ?, ?, ?)";
-        db.execute(insert_sql, &[
-            &name as &dyn rusqlite::ToSql,
-            &logo as &dyn rusqlite::ToSql,
-            &phone as &dyn rusqlite::ToSql,
-            &address as &dyn rusqlite::ToSql,
-            &font as &dyn rusqlite::ToSql,
-        ])
-        .map_err(|e| format!("Failed to insert company settings: {}", e))?;
-    } else {
-        // Update existing settings (update first row)
-        let update_sql = "UPDATE company_settings SET name = ?, logo = ?, phone = ?, address = ?, font = ?, updated_at = CURRENT_TIMESTAMP WHERE id = (SELECT id FROM company_settings ORDER BY id LIMIT 1)";
-        db.execute(update_sql, &[
-            &name as &dyn rusqlite::ToSql,
-            &logo as &dyn rusqlite::ToSql,
-            &phone as &dyn rusqlite::ToSql,
-            &address as &dyn rusqlite::ToSql,
-            &font as &dyn rusqlite::ToSql,
-        ])
-        .map_err(|e| format!("Failed to update company settings: {}", e))?;
-    }
+        if count == 0 {
+            // Insert new settings
+            let insert_sql = "INSERT INTO company_settings (name, logo, phone, address, font) VALUES (?, ?, ?, ?, ?)";
+            db.execute(insert_sql, &[
+                &name as &dyn rusqlite::ToSql,
+                &logo as &dyn rusqlite::ToSql,
+                &phone as &dyn rusqlite::ToSql,
+                &address as &dyn rusqlite::ToSql,
+                &font as &dyn rusqlite::ToSql,
+            ])
+            .map_err(|e| format!("Failed to insert company settings: {}", e))?;
+        } else {
+            // Update existing settings (update first row)
+            let update_sql = "UPDATE company_settings SET name = ?, logo = ?, phone = ?, address = ?, font = ?, updated_at = CURRENT_TIMESTAMP WHERE id = (SELECT id FROM company_settings ORDER BY id LIMIT 1)";
+            db.execute(update_sql, &[
+                &name as &dyn rusqlite::ToSql,
+                &logo as &dyn rusqlite::ToSql,
+                &phone as &dyn rusqlite::ToSql,
+                &address as &dyn rusqlite::ToSql,
+                &font as &dyn rusqlite::ToSql,
+            ])
+            .map_err(|e| format!("Failed to update company settings: {}", e))?;
+        }
 
-    // Get the updated settings (reuse the same db reference)
-    let get_sql = "SELECT id, name, logo, phone, address, font, created_at, updated_at FROM company_settings ORDER BY id LIMIT 1";
-    let settings_list = db
-        .query(get_sql, &[], |row| {
-            Ok(CompanySettings {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                logo: row.get(2)?,
-                phone: row.get(3)?,
-                address: row.get(4)?,
-                font: row.get(5)?,
-                created_at: row.get(6)?,
-                updated_at: row.get(7)?,
+        // Get the updated settings (reuse the same db reference)
+        let get_sql = "SELECT id, name, logo, phone, address, font, created_at, updated_at FROM company_settings ORDER BY id LIMIT 1";
+        let settings_list = db
+            .query(get_sql, &[], |row| {
+                Ok(CompanySettings {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    logo: row.get(2)?,
+                    phone: row.get(3)?,
+                    address: row.get(4)?,
+                    font: row.get(5)?,
+                    created_at: row.get(6)?,
+                    updated_at: row.get(7)?,
+                })
             })
-        })
-        .map_err(|e| format!("Failed to fetch updated company settings: {}", e))?;
+            .map_err(|e| format!("Failed to fetch updated company settings: {}", e))?;
 
-    let settings = settings_list.first().ok_or("No company settings found")?;
-    Ok(settings.clone())
+        let settings = settings_list.first().ok_or("No company settings found")?;
+        Ok(settings.clone())
+    })
 }
 
 // COA Category Model
@@ -6187,6 +6765,35 @@ pub struct JournalEntryLine {
     pub created_at: String,
 }
 
+/// A new journal entry: `create_journal_entry`'s arguments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntryInput {
+    pub entry_date: String,
+    pub description: Option<String>,
+    pub reference_type: Option<String>,
+    pub reference_id: Option<i64>,
+    pub lines: Vec<JournalLineInput>,
+}
+
+/// One debit or credit of a journal entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalLineInput {
+    pub account_id: i64,
+    pub currency_id: i64,
+    pub debit_amount: f64,
+    pub credit_amount: f64,
+    pub exchange_rate: f64,
+    pub description: Option<String>,
+}
+
+impl From<(i64, i64, f64, f64, f64, Option<String>)> for JournalLineInput {
+    /// A line as `create_journal_entry` and `update_journal_entry` take it:
+    /// (account_id, currency_id, debit_amount, credit_amount, exchange_rate, description)
+    fn from((account_id, currency_id, debit_amount, credit_amount, exchange_rate, description): (i64, i64, f64, f64, f64, Option<String>)) -> Self {
+        JournalLineInput { account_id, currency_id, debit_amount, credit_amount, exchange_rate, description }
+    }
+}
+
 // Currency Exchange Rate Model
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CurrencyExchangeRate {
@@ -6201,50 +6808,15 @@ pub struct CurrencyExchangeRate {
 /// Initialize COA categories table schema
 #[tauri::command]
 fn init_coa_categories_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS coa_categories (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            parent_id INTEGER,
-            name TEXT NOT NULL,
-            code TEXT NOT NULL UNIQUE,
-            category_type TEXT NOT NULL,
-            level INTEGER NOT NULL DEFAULT 0,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (parent_id) REFERENCES coa_categories(id) ON DELETE SET NULL
-        )
-    ";
-
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create coa_categories table: {}", e))?;
+    ensure_database_open(&db_state)?;
 
     Ok("COA categories table initialized successfully".to_string())
 }
 
 /// Initialize account currency balances table schema
-#[tauri::command]
-fn init_account_currency_balances_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS account_currency_balances (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            account_id INTEGER NOT NULL,
-            currency_id INTEGER NOT NULL,
-            balance REAL NOT NULL DEFAULT 0,
-            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (account_id) REFERENCES accounts(id) ON DELETE CASCADE,
-            FOREIGN KEY (currency_id) REFERENCES currencies(id),
-            UNIQUE(account_id, currency_id)
-        )
-    ";
-
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create account_currency_balances table: {}", e))?;
+#[tauri::command]
+fn init_account_currency_balances_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
+    ensure_database_open(&db_state)?;
 
     Ok("Account currency balances table initialized successfully".to_string())
 }
@@ -6252,24 +6824,7 @@ fn init_account_currency_balances_table(db_state: State<'_, Mutex<Option<Databas
 /// Initialize journal entries table schema
 #[tauri::command]
 fn init_journal_entries_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS journal_entries (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            entry_number TEXT NOT NULL UNIQUE,
-            entry_date TEXT NOT NULL,
-            description TEXT,
-            reference_type TEXT,
-            reference_id INTEGER,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
-        )
-    ";
-
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create journal_entries table: {}", e))?;
+    ensure_database_open(&db_state)?;
 
     Ok("Journal entries table initialized successfully".to_string())
 }
@@ -6277,29 +6832,7 @@ fn init_journal_entries_table(db_state: State<'_, Mutex<Option<Database>>>) -> R
 /// Initialize journal entry lines table schema
 #[tauri::command]
 fn init_journal_entry_lines_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS journal_entry_lines (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            journal_entry_id INTEGER NOT NULL,
-            account_id INTEGER NOT NULL,
-            currency_id INTEGER NOT NULL,
-            debit_amount REAL NOT NULL DEFAULT 0,
-            credit_amount REAL NOT NULL DEFAULT 0,
-            exchange_rate REAL NOT NULL DEFAULT 1,
-            base_amount REAL NOT NULL DEFAULT 0,
-            description TEXT,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (journal_entry_id) REFERENCES journal_entries(id) ON DELETE CASCADE,
-            FOREIGN KEY (account_id) REFERENCES accounts(id),
-            FOREIGN KEY (currency_id) REFERENCES currencies(id)
-        )
-    ";
-
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create journal_entry_lines table: {}", e))?;
+    ensure_database_open(&db_state)?;
 
     Ok("Journal entry lines table initialized successfully".to_string())
 }
@@ -6307,24 +6840,7 @@ fn init_journal_entry_lines_table(db_state: State<'_, Mutex<Option<Database>>>)
 /// Initialize currency exchange rates table schema
 #[tauri::command]
 fn init_currency_exchange_rates_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS currency_exchange_rates (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            from_currency_id INTEGER NOT NULL,
-            to_currency_id INTEGER NOT NULL,
-            rate REAL NOT NULL,
-            date TEXT NOT NULL,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (from_currency_id) REFERENCES currencies(id),
-            FOREIGN KEY (to_currency_id) REFERENCES currencies(id)
-        )
-    ";
-
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create currency_exchange_rates table: {}", e))?;
+    ensure_database_open(&db_state)?;
 
     Ok("Currency exchange rates table initialized successfully".to_string())
 }
@@ -6355,7 +6871,7 @@ fn create_coa_category(
     };
 
     let insert_sql = "INSERT INTO coa_categories (parent_id, name, code, category_type, level) VALUES (?, ?, ?, ?, ?)";
-    db.execute(insert_sql, &[
+    let category_id = db.insert(insert_sql, &[
         &parent_id as &dyn rusqlite::ToSql,
         &name as &dyn rusqlite::ToSql,
         &code as &dyn rusqlite::ToSql,
@@ -6365,9 +6881,9 @@ fn create_coa_category(
         .map_err(|e| format!("Failed to insert COA category: {}", e))?;
 
     // Get the created category
-    let category_sql = "SELECT id, parent_id, name, code, category_type, level, created_at, updated_at FROM coa_categories WHERE code = ? ORDER BY id DESC LIMIT 1";
+    let category_sql = "SELECT id, parent_id, name, code, category_type, level, created_at, updated_at FROM coa_categories WHERE id = ?";
     let categories = db
-        .query(category_sql, &[&code as &dyn rusqlite::ToSql], |row| {
+        .query(category_sql, &[&category_id as &dyn rusqlite::ToSql], |row| {
             Ok(CoaCategory {
                 id: row.get(0)?,
                 parent_id: row.get(1)?,
@@ -6547,21 +7063,14 @@ fn init_standard_coa_categories(db_state: State<'_, Mutex<Option<Database>>>) ->
     // Helper function to insert category and return its ID
     let insert_category = |parent_id: Option<i64>, name: &str, code: &str, category_type: &str, level: i64| -> Result<i64, String> {
         let insert_sql = "INSERT INTO coa_categories (parent_id, name, code, category_type, level) VALUES (?, ?, ?, ?, ?)";
-        db.execute(insert_sql, &[
+        db.insert(insert_sql, &[
             &parent_id as &dyn rusqlite::ToSql,
             &name as &dyn rusqlite::ToSql,
             &code as &dyn rusqlite::ToSql,
             &category_type as &dyn rusqlite::ToSql,
             &level as &dyn rusqlite::ToSql,
         ])
-        .map_err(|e| format!("Failed to insert COA category {}: {}", code, e))?;
-
-        let get_id_sql = "SELECT id FROM coa_categories WHERE code = ? ORDER BY id DESC LIMIT 1";
-        let ids: Vec<i64> = db
-            .query(get_id_sql, &[&code as &dyn rusqlite::ToSql], |row| Ok(row.get::<_, i64>(0)?))
-            .map_err(|e| format!("Failed to get category ID: {}", e))?;
-        
-        ids.first().copied().ok_or_else(|| format!("Failed to retrieve category ID for {}", code))
+        .map_err(|e| format!("Failed to insert COA category {}: {}", code, e))
     };
 
     // Assets (دارایی‌ها) - Level 0
@@ -6663,7 +7172,7 @@ fn init_standard_coa_categories(db_state: State<'_, Mutex<Option<Database>>>) ->
 }
 
 // Account Model
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Account {
     pub id: i64,
     pub name: String,
@@ -6696,45 +7205,42 @@ pub struct AccountTransaction {
     pub updated_at: String,
 }
 
-/// Initialize accounts table schema
-#[tauri::command]
-fn init_accounts_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS accounts (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT NOT NULL,
-            currency_id INTEGER,
-            coa_category_id INTEGER,
-            account_code TEXT UNIQUE,
-            account_type TEXT,
-            initial_balance REAL NOT NULL DEFAULT 0,
-            current_balance REAL NOT NULL DEFAULT 0,
-            is_active INTEGER NOT NULL DEFAULT 1,
-            notes TEXT,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (currency_id) REFERENCES currencies(id),
-            FOREIGN KEY (coa_category_id) REFERENCES coa_categories(id)
-        )
-    ";
+/// A new account: `create_account`'s arguments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountInput {
+    pub name: String,
+    pub currency_id: Option<i64>,
+    pub coa_category_id: Option<i64>,
+    pub account_code: Option<String>,
+    pub account_type: Option<String>,
+    pub initial_balance: f64,
+    pub notes: Option<String>,
+}
 
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create accounts table: {}", e))?;
+impl AccountInput {
+    /// The account code, with a blank one as none so it can't clash in the unique index
+    pub fn code(&self) -> Option<&str> {
+        self.account_code.as_deref().filter(|code| !code.trim().is_empty())
+    }
+}
 
-    // Add new columns if they don't exist (for existing databases)
-    let alter_queries = vec![
-        "ALTER TABLE accounts ADD COLUMN coa_category_id INTEGER",
-        "ALTER TABLE accounts ADD COLUMN account_code TEXT UNIQUE",
-        "ALTER TABLE accounts ADD COLUMN account_type TEXT",
-        "ALTER TABLE accounts ADD COLUMN is_active INTEGER NOT NULL DEFAULT 1",
-    ];
+/// A deposit or withdrawal: the arguments of `deposit_account` and `withdraw_account`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountTransactionInput {
+    pub amount: f64,
+    /// Name of the currency
+    pub currency: String,
+    pub rate: f64,
+    pub transaction_date: String,
+    /// Move the account's whole balance instead of `amount`
+    pub is_full: bool,
+    pub notes: Option<String>,
+}
 
-    for alter_sql in alter_queries {
-        let _ = db.execute(alter_sql, &[]);
-    }
+/// Initialize accounts table schema
+#[tauri::command]
+fn init_accounts_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
+    ensure_database_open(&db_state)?;
 
     Ok("Accounts table initialized successfully".to_string())
 }
@@ -6742,37 +7248,17 @@ fn init_accounts_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<S
 /// Initialize account transactions table schema
 #[tauri::command]
 fn init_account_transactions_table(db_state: State<'_, Mutex<Option<Database>>>) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let create_table_sql = "
-        CREATE TABLE IF NOT EXISTS account_transactions (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            account_id INTEGER NOT NULL,
-            transaction_type TEXT NOT NULL,
-            amount REAL NOT NULL,
-            currency TEXT NOT NULL,
-            rate REAL NOT NULL,
-            total REAL NOT NULL,
-            transaction_date TEXT NOT NULL,
-            is_full INTEGER NOT NULL DEFAULT 0,
-            notes TEXT,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (account_id) REFERENCES accounts(id) ON DELETE CASCADE
-        )
-    ";
-
-    db.execute(create_table_sql, &[])
-        .map_err(|e| format!("Failed to create account_transactions table: {}", e))?;
+    ensure_database_open(&db_state)?;
 
     Ok("Account transactions table initialized successfully".to_string())
 }
 
 /// Create a new account
 #[tauri::command]
-fn create_account(
+async fn create_account(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
     name: String,
     currency_id: Option<i64>,
     coa_category_id: Option<i64>,
@@ -6781,138 +7267,100 @@ fn create_account(
     initial_balance: f64,
     notes: Option<String>,
 ) -> Result<Account, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    let account = AccountInput { name, currency_id, coa_category_id, account_code, account_type, initial_balance, notes };
+    audit::attributed(actor, repo.create_account(&account)).await
+        .map_err(|e| format!("Failed to create account: {}", e))
+}
 
-    let notes_str: Option<&str> = notes.as_ref().map(|s| s.as_str());
-    // Convert empty strings to None to avoid UNIQUE constraint violations
-    let code_str: Option<&str> = account_code.as_ref()
-        .and_then(|s| if s.trim().is_empty() { None } else { Some(s.as_str()) });
-    let type_str: Option<&str> = account_type.as_ref().map(|s| s.as_str());
-    let is_active_int = 1i64;
-
-    let insert_sql = "INSERT INTO accounts (name, currency_id, coa_category_id, account_code, account_type, initial_balance, current_balance, is_active, notes) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)";
-    db.execute(insert_sql, &[
-        &name as &dyn rusqlite::ToSql,
-        &currency_id as &dyn rusqlite::ToSql,
-        &coa_category_id as &dyn rusqlite::ToSql,
-        &code_str as &dyn rusqlite::ToSql,
-        &type_str as &dyn rusqlite::ToSql,
-        &initial_balance as &dyn rusqlite::ToSql,
-        &initial_balance as &dyn rusqlite::ToSql,
-        &is_active_int as &dyn rusqlite::ToSql,
-        &notes_str as &dyn rusqlite::ToSql,
-    ])
-        .map_err(|e| format!("Failed to insert account: {}", e))?;
+/// Insert an account and open its balance in its currency
+pub(crate) fn create_account_internal(db: &Database, account: &AccountInput) -> Result<Account, String> {
+    let AccountInput { ref name, currency_id, coa_category_id, ref account_type, initial_balance, ref notes, .. } = *account;
 
-    // Get the created account ID first
-    let account_id_sql = "SELECT id FROM accounts WHERE name = ? ORDER BY id DESC LIMIT 1";
-    let account_ids = db
-        .query(account_id_sql, &[&name as &dyn rusqlite::ToSql], |row| {
-            Ok(row.get::<_, i64>(0)?)
-        })
-        .map_err(|e| format!("Failed to get account ID: {}", e))?;
-    let account_id = account_ids.first().ok_or("Failed to get account ID")?;
+    db.transaction(|db| {
+        let notes_str: Option<&str> = notes.as_deref();
+        let code_str: Option<&str> = account.code();
+        let type_str: Option<&str> = account_type.as_deref();
+        let is_active_int = 1i64;
 
-    // Initialize currency balance if currency_id is provided
-    if let Some(cid) = currency_id {
-        update_account_currency_balance_internal(db, *account_id, cid, initial_balance)?;
-    }
+        let insert_sql = "INSERT INTO accounts (name, currency_id, coa_category_id, account_code, account_type, initial_balance, current_balance, is_active, notes) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)";
+        let account_id = db.insert(insert_sql, &[
+            name as &dyn rusqlite::ToSql,
+            &currency_id as &dyn rusqlite::ToSql,
+            &coa_category_id as &dyn rusqlite::ToSql,
+            &code_str as &dyn rusqlite::ToSql,
+            &type_str as &dyn rusqlite::ToSql,
+            &initial_balance as &dyn rusqlite::ToSql,
+            &initial_balance as &dyn rusqlite::ToSql,
+            &is_active_int as &dyn rusqlite::ToSql,
+            &notes_str as &dyn rusqlite::ToSql,
+        ])
+            .map_err(|e| format!("Failed to insert account: {}", e))?;
 
-    // Get the created account
-    let account_sql = "SELECT id, name, currency_id, coa_category_id, account_code, account_type, initial_balance, current_balance, is_active, notes, created_at, updated_at FROM accounts WHERE name = ? ORDER BY id DESC LIMIT 1";
-    let accounts = db
-        .query(account_sql, &[&name as &dyn rusqlite::ToSql], |row| {
-            Ok(Account {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                currency_id: row.get(2)?,
-                coa_category_id: row.get(3)?,
-                account_code: row.get(4)?,
-                account_type: row.get(5)?,
-                initial_balance: row.get(6)?,
-                current_balance: row.get(7)?,
-                is_active: row.get::<_, i64>(8)? != 0,
-                notes: row.get(9)?,
-                created_at: row.get(10)?,
-                updated_at: row.get(11)?,
+        // Initialize currency balance if currency_id is provided
+        if let Some(cid) = currency_id {
+            update_account_currency_balance_internal(db, account_id, cid, initial_balance)?;
+        }
+
+        // Get the created account
+        let account_sql = "SELECT id, name, currency_id, coa_category_id, account_code, account_type, initial_balance, current_balance, is_active, notes, created_at, updated_at FROM accounts WHERE id = ?";
+        let accounts = db
+            .query(account_sql, &[&account_id as &dyn rusqlite::ToSql], |row| {
+                Ok(Account {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    currency_id: row.get(2)?,
+                    coa_category_id: row.get(3)?,
+                    account_code: row.get(4)?,
+                    account_type: row.get(5)?,
+                    initial_balance: row.get(6)?,
+                    current_balance: row.get(7)?,
+                    is_active: row.get::<_, i64>(8)? != 0,
+                    notes: row.get(9)?,
+                    created_at: row.get(10)?,
+                    updated_at: row.get(11)?,
+                })
             })
-        })
-        .map_err(|e| format!("Failed to fetch account: {}", e))?;
+            .map_err(|e| format!("Failed to fetch account: {}", e))?;
 
-    if let Some(account) = accounts.first() {
-        Ok(account.clone())
-    } else {
-        Err("Failed to retrieve created account".to_string())
-    }
+        if let Some(account) = accounts.first() {
+            Ok(account.clone())
+        } else {
+            Err("Failed to retrieve created account".to_string())
+        }
+    })
 }
 
 /// Get all accounts
 #[tauri::command]
-fn get_accounts(db_state: State<'_, Mutex<Option<Database>>>) -> Result<Vec<Account>, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let sql = "SELECT id, name, currency_id, coa_category_id, account_code, account_type, initial_balance, current_balance, is_active, notes, created_at, updated_at FROM accounts ORDER BY name";
-    let accounts = db
-        .query(sql, &[], |row| {
-            Ok(Account {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                currency_id: row.get(2)?,
-                coa_category_id: row.get(3)?,
-                account_code: row.get(4)?,
-                account_type: row.get(5)?,
-                initial_balance: row.get(6)?,
-                current_balance: row.get(7)?,
-                is_active: row.get::<_, i64>(8)? != 0,
-                notes: row.get(9)?,
-                created_at: row.get(10)?,
-                updated_at: row.get(11)?,
-            })
-        })
-        .map_err(|e| format!("Failed to fetch accounts: {}", e))?;
-
-    Ok(accounts)
+async fn get_accounts(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+) -> Result<Vec<Account>, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    repo.list_accounts().await
+        .map_err(|e| format!("Failed to fetch accounts: {}", e))
 }
 
 /// Get a single account
 #[tauri::command]
-fn get_account(db_state: State<'_, Mutex<Option<Database>>>, id: i64) -> Result<Account, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let sql = "SELECT id, name, currency_id, coa_category_id, account_code, account_type, initial_balance, current_balance, is_active, notes, created_at, updated_at FROM accounts WHERE id = ?";
-    let accounts = db
-        .query(sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(Account {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                currency_id: row.get(2)?,
-                coa_category_id: row.get(3)?,
-                account_code: row.get(4)?,
-                account_type: row.get(5)?,
-                initial_balance: row.get(6)?,
-                current_balance: row.get(7)?,
-                is_active: row.get::<_, i64>(8)? != 0,
-                notes: row.get(9)?,
-                created_at: row.get(10)?,
-                updated_at: row.get(11)?,
-            })
-        })
-        .map_err(|e| format!("Failed to fetch account: {}", e))?;
-
-    if let Some(account) = accounts.first() {
-        Ok(account.clone())
-    } else {
-        Err("Account not found".to_string())
-    }
+async fn get_account(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    id: i64,
+) -> Result<Account, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    repo.get_account(id).await
+        .map_err(|e| format!("Failed to fetch account: {}", e))?
+        .ok_or_else(|| "Account not found".to_string())
 }
 
 /// Update an account
 #[tauri::command]
-fn update_account(
+async fn update_account(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
     id: i64,
     name: String,
     currency_id: Option<i64>,
@@ -6923,79 +7371,116 @@ fn update_account(
     is_active: bool,
     notes: Option<String>,
 ) -> Result<Account, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    let account = AccountInput { name, currency_id, coa_category_id, account_code, account_type, initial_balance, notes };
+    audit::attributed(actor, repo.update_account(id, &account, is_active)).await
+        .map_err(|e| format!("Failed to update account: {}", e))
+}
 
-    let notes_str: Option<&str> = notes.as_ref().map(|s| s.as_str());
-    // Convert empty strings to None to avoid UNIQUE constraint violations
-    let code_str: Option<&str> = account_code.as_ref()
-        .and_then(|s| if s.trim().is_empty() { None } else { Some(s.as_str()) });
-    let type_str: Option<&str> = account_type.as_ref().map(|s| s.as_str());
-    let is_active_int = if is_active { 1i64 } else { 0i64 };
+/// Update an account and recalculate its current balance
+pub(crate) fn update_account_internal(db: &Database, id: i64, account: &AccountInput, is_active: bool) -> Result<Account, String> {
+    let AccountInput { ref name, currency_id, coa_category_id, ref account_type, initial_balance, ref notes, .. } = *account;
 
-    let update_sql = "UPDATE accounts SET name = ?, currency_id = ?, coa_category_id = ?, account_code = ?, account_type = ?, initial_balance = ?, is_active = ?, notes = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?";
-    db.execute(update_sql, &[
-        &name as &dyn rusqlite::ToSql,
-        &currency_id as &dyn rusqlite::ToSql,
-        &coa_category_id as &dyn rusqlite::ToSql,
-        &code_str as &dyn rusqlite::ToSql,
-        &type_str as &dyn rusqlite::ToSql,
-        &initial_balance as &dyn rusqlite::ToSql,
-        &is_active_int as &dyn rusqlite::ToSql,
-        &notes_str as &dyn rusqlite::ToSql,
-        &id as &dyn rusqlite::ToSql,
-    ])
-        .map_err(|e| format!("Failed to update account: {}", e))?;
-
-    // Recalculate current balance
-    let balance = calculate_account_balance_internal(db, id)?;
-    let update_balance_sql = "UPDATE accounts SET current_balance = ? WHERE id = ?";
-    db.execute(update_balance_sql, &[&balance as &dyn rusqlite::ToSql, &id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to update account balance: {}", e))?;
-
-    // Get the updated account directly
-    let account_sql = "SELECT id, name, currency_id, coa_category_id, account_code, account_type, initial_balance, current_balance, is_active, notes, created_at, updated_at FROM accounts WHERE id = ?";
-    let accounts = db
-        .query(account_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(Account {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                currency_id: row.get(2)?,
-                coa_category_id: row.get(3)?,
-                account_code: row.get(4)?,
-                account_type: row.get(5)?,
-                initial_balance: row.get(6)?,
-                current_balance: row.get(7)?,
-                is_active: row.get::<_, i64>(8)? != 0,
-                notes: row.get(9)?,
-                created_at: row.get(10)?,
-                updated_at: row.get(11)?,
+    db.transaction(|db| {
+        let notes_str: Option<&str> = notes.as_deref();
+        // Convert empty strings to None to avoid UNIQUE constraint violations
+        let code_str: Option<&str> = account.code();
+        let type_str: Option<&str> = account_type.as_deref();
+        let is_active_int = if is_active { 1i64 } else { 0i64 };
+
+        let update_sql = "UPDATE accounts SET name = ?, currency_id = ?, coa_category_id = ?, account_code = ?, account_type = ?, initial_balance = ?, is_active = ?, notes = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?";
+        db.execute(update_sql, &[
+            name as &dyn rusqlite::ToSql,
+            &currency_id as &dyn rusqlite::ToSql,
+            &coa_category_id as &dyn rusqlite::ToSql,
+            &code_str as &dyn rusqlite::ToSql,
+            &type_str as &dyn rusqlite::ToSql,
+            &initial_balance as &dyn rusqlite::ToSql,
+            &is_active_int as &dyn rusqlite::ToSql,
+            &notes_str as &dyn rusqlite::ToSql,
+            &id as &dyn rusqlite::ToSql,
+        ])
+            .map_err(|e| format!("Failed to update account: {}", e))?;
+        // Recalculate current balance
+        let balance = calculate_account_balance_internal(db, id)?;
+        let update_balance_sql = "UPDATE accounts SET current_balance = ? WHERE id = ?";
+        db.execute(update_balance_sql, &[&balance as &dyn rusqlite::ToSql, &id as &dyn rusqlite::ToSql])
+            .map_err(|e| format!("Failed to update account balance: {}", e))?;
+
+        // Get the updated account directly
+        let account_sql = "SELECT id, name, currency_id, coa_category_id, account_code, account_type, initial_balance, current_balance, is_active, notes, created_at, updated_at FROM accounts WHERE id = ?";
+        let accounts = db
+            .query(account_sql, &[&id as &dyn rusqlite::ToSql], |row| {
+                Ok(Account {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    currency_id: row.get(2)?,
+                    coa_category_id: row.get(3)?,
+                    account_code: row.get(4)?,
+                    account_type: row.get(5)?,
+                    initial_balance: row.get(6)?,
+                    current_balance: row.get(7)?,
+                    is_active: row.get::<_, i64>(8)? != 0,
+                    notes: row.get(9)?,
+                    created_at: row.get(10)?,
+                    updated_at: row.get(11)?,
+                })
             })
-        })
-        .map_err(|e| format!("Failed to fetch account: {}", e))?;
+            .map_err(|e| format!("Failed to fetch account: {}", e))?;
 
-    if let Some(account) = accounts.first() {
-        Ok(account.clone())
-    } else {
-        Err("Account not found".to_string())
-    }
+        if let Some(account) = accounts.first() {
+            Ok(account.clone())
+        } else {
+            Err("Account not found".to_string())
+        }
+    })
 }
 
-/// Delete an account
+/// Move an account to the trash
 #[tauri::command]
-fn delete_account(db_state: State<'_, Mutex<Option<Database>>>, id: i64) -> Result<String, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let delete_sql = "DELETE FROM accounts WHERE id = ?";
-    db.execute(delete_sql, &[&id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to delete account: {}", e))?;
+async fn delete_account(
+    webview: tauri::Webview,
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    sessions: State<'_, SessionStore>,
+    actor: AuditActor,
+    id: i64,
+) -> Result<String, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    audit::attributed(actor, move_to_trash(repo.as_ref(), &trash::ACCOUNTS, id, current_user_id(&sessions, &webview))).await?;
 
     Ok("Account deleted successfully".to_string())
 }
 
+/// Take an account out of the trash
+#[tauri::command]
+async fn restore_account(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
+    id: i64,
+) -> Result<String, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    audit::attributed(actor, restore_from_trash(repo.as_ref(), &trash::ACCOUNTS, id)).await?;
+
+    Ok("Account restored successfully".to_string())
+}
+
+/// Get the accounts in the trash, most recently deleted first
+#[tauri::command]
+async fn get_deleted_accounts(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    page: i64,
+    per_page: i64,
+    search: Option<String>,
+) -> Result<PaginatedResponse<TrashedRecord>, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    list_trash(repo.as_ref(), &trash::ACCOUNTS, page, per_page, search).await
+}
+
 /// Calculate account balance (internal helper)
-fn calculate_account_balance_internal(db: &Database, account_id: i64) -> Result<f64, String> {
+pub(crate) fn calculate_account_balance_internal(db: &Database, account_id: i64) -> Result<f64, String> {
     // Get initial balance
     let initial_balance_sql = "SELECT initial_balance FROM accounts WHERE id = ?";
     let initial_balances = db
@@ -7032,17 +7517,22 @@ fn calculate_account_balance_internal(db: &Database, account_id: i64) -> Result<
 
 /// Get account balance
 #[tauri::command]
-fn get_account_balance(db_state: State<'_, Mutex<Option<Database>>>, account_id: i64) -> Result<f64, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    calculate_account_balance_internal(db, account_id)
+async fn get_account_balance(
+    db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    account_id: i64,
+) -> Result<f64, String> {
+    let repo = repositories(&db_state, &surreal_state)?;
+    repo.account_balance(account_id).await
+        .map_err(|e| format!("Failed to calculate account balance: {}", e))
 }
 
 /// Deposit to account
 #[tauri::command]
-fn deposit_account(
+async fn deposit_account(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
     account_id: i64,
     amount: f64,
     currency: String,
@@ -7051,107 +7541,118 @@ fn deposit_account(
     is_full: bool,
     notes: Option<String>,
 ) -> Result<AccountTransaction, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    let transaction = AccountTransactionInput { amount, currency, rate, transaction_date, is_full, notes };
+    audit::attributed(actor, repo.deposit_account(account_id, &transaction)).await
+        .map_err(|e| format!("Failed to deposit to account: {}", e))
+}
+
+/// Deposit to an account and post the deposit against cash
+pub(crate) fn deposit_account_internal(db: &Database, account_id: i64, transaction: &AccountTransactionInput) -> Result<AccountTransaction, String> {
+    let AccountTransactionInput { amount, ref currency, rate, ref transaction_date, is_full, ref notes } = *transaction;
+
+    db.transaction(|db| {
+        let final_amount = if is_full {
+            // Get current balance and deposit all of it
+            let current_balance = calculate_account_balance_internal(db, account_id)?;
+            if current_balance <= 0.0 {
+                return Err("Account has no balance to deposit".to_string());
+            }
+            current_balance
+        } else {
+            if amount <= 0.0 {
+                return Err("Deposit amount must be greater than 0".to_string());
+            }
+            amount
+        };
 
-    let final_amount = if is_full {
-        // Get current balance and deposit all of it
-        let current_balance = calculate_account_balance_internal(db, account_id)?;
-        if current_balance <= 0.0 {
-            return Err("Account has no balance to deposit".to_string());
-        }
-        current_balance
-    } else {
-        if amount <= 0.0 {
-            return Err("Deposit amount must be greater than 0".to_string());
-        }
-        amount
-    };
+        let total = final_amount * rate;
+        let notes_str: Option<&str> = notes.as_deref();
+        let is_full_int = if is_full { 1 } else { 0 };
 
-    let total = final_amount * rate;
-    let notes_str: Option<&str> = notes.as_ref().map(|s| s.as_str());
-    let is_full_int = if is_full { 1 } else { 0 };
+        // Get currency ID from currency name
+        let currency_id_sql = "SELECT id FROM currencies WHERE name = ? LIMIT 1";
+        let currency_ids = db
+            .query(currency_id_sql, &[currency as &dyn rusqlite::ToSql], |row| {
+                Ok(row.get::<_, i64>(0)?)
+            })
+            .map_err(|e| format!("Failed to get currency ID: {}", e))?;
+        let currency_id = currency_ids.first().ok_or("Currency not found")?;
 
-    // Get currency ID from currency name
-    let currency_id_sql = "SELECT id FROM currencies WHERE name = ? LIMIT 1";
-    let currency_ids = db
-        .query(currency_id_sql, &[&currency as &dyn rusqlite::ToSql], |row| {
-            Ok(row.get::<_, i64>(0)?)
-        })
-        .map_err(|e| format!("Failed to get currency ID: {}", e))?;
-    let currency_id = currency_ids.first().ok_or("Currency not found")?;
+        // Insert transaction
+        let insert_sql = "INSERT INTO account_transactions (account_id, transaction_type, amount, currency, rate, total, transaction_date, is_full, notes) VALUES (?, 'deposit', ?, ?, ?, ?, ?, ?, ?)";
+        let transaction_id = db.insert(insert_sql, &[
+            &account_id as &dyn rusqlite::ToSql,
+            &final_amount as &dyn rusqlite::ToSql,
+            currency as &dyn rusqlite::ToSql,
+            &rate as &dyn rusqlite::ToSql,
+            &total as &dyn rusqlite::ToSql,
+            transaction_date as &dyn rusqlite::ToSql,
+            &is_full_int as &dyn rusqlite::ToSql,
+            &notes_str as &dyn rusqlite::ToSql,
+        ])
+            .map_err(|e| format!("Failed to insert deposit transaction: {}", e))?;
 
-    // Insert transaction
-    let insert_sql = "INSERT INTO account_transactions (account_id, transaction_type, amount, currency, rate, total, transaction_date, is_full, notes) VALUES (?, 'deposit', ?, ?, ?, ?, ?, ?, ?)";
-    db.execute(insert_sql, &[
-        &account_id as &dyn rusqlite::ToSql,
-        &final_amount as &dyn rusqlite::ToSql,
-        &currency as &dyn rusqlite::ToSql,
-        &rate as &dyn rusqlite::ToSql,
-        &total as &dyn rusqlite::ToSql,
-        &transaction_date as &dyn rusqlite::ToSql,
-        &is_full_int as &dyn rusqlite::ToSql,
-        &notes_str as &dyn rusqlite::ToSql,
-    ])
-        .map_err(|e| format!("Failed to insert deposit transaction: {}", e))?;
-
-    // Update account currency balance
-    let current_currency_balance = get_account_balance_by_currency_internal(db, account_id, *currency_id)?;
-    let new_currency_balance = current_currency_balance + final_amount;
-    update_account_currency_balance_internal(db, account_id, *currency_id, new_currency_balance)?;
-
-    // Update account balance
-    let new_balance = calculate_account_balance_internal(db, account_id)?;
-    let update_balance_sql = "UPDATE accounts SET current_balance = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?";
-    db.execute(update_balance_sql, &[&new_balance as &dyn rusqlite::ToSql, &account_id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to update account balance: {}", e))?;
-
-    // Create journal entry: Debit Account, Credit Cash/Source
-    let cash_account_sql = "SELECT id FROM accounts WHERE account_type = 'Asset' AND (name LIKE '%Cash%' OR name LIKE '%Bank%') LIMIT 1";
-    let cash_accounts = db.query(cash_account_sql, &[], |row| Ok(row.get::<_, i64>(0)?))
-        .ok()
-        .and_then(|v| v.first().copied());
-
-    if let Some(cash_account) = cash_accounts {
-        let journal_lines = vec![
-            (account_id, *currency_id, total, 0.0, rate, notes.clone()),
-            (cash_account, *currency_id, 0.0, total, rate, notes.clone()),
-        ];
-        let _ = create_journal_entry_internal(db, &transaction_date, notes.clone(), Some("account_deposit".to_string()), None, journal_lines);
-    }
+        // Update account currency balance
+        let current_currency_balance = get_account_balance_by_currency_internal(db, account_id, *currency_id)?;
+        let new_currency_balance = current_currency_balance + final_amount;
+        update_account_currency_balance_internal(db, account_id, *currency_id, new_currency_balance)?;
+
+        // Update account balance
+        let new_balance = calculate_account_balance_internal(db, account_id)?;
+        let update_balance_sql = "UPDATE accounts SET current_balance = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?";
+        db.execute(update_balance_sql, &[&new_balance as &dyn rusqlite::ToSql, &account_id as &dyn rusqlite::ToSql])
+            .map_err(|e| format!("Failed to update account balance: {}", e))?;
 
-    // Get the created transaction
-    let transaction_sql = "SELECT id, account_id, transaction_type, amount, currency, rate, total, transaction_date, is_full, notes, created_at, updated_at FROM account_transactions WHERE account_id = ? AND transaction_type = 'deposit' ORDER BY id DESC LIMIT 1";
-    let transactions = db
-        .query(transaction_sql, &[&account_id as &dyn rusqlite::ToSql], |row| {
-            Ok(AccountTransaction {
-                id: row.get(0)?,
-                account_id: row.get(1)?,
-                transaction_type: row.get(2)?,
-                amount: row.get(3)?,
-                currency: row.get(4)?,
-                rate: row.get(5)?,
-                total: row.get(6)?,
-                transaction_date: row.get(7)?,
-                is_full: row.get::<_, i64>(8)? != 0,
-                notes: row.get(9)?,
-                created_at: row.get(10)?,
-                updated_at: row.get(11)?,
+        // Create journal entry: Debit Account, Credit Cash/Source
+        let cash_account_sql = "SELECT id FROM accounts WHERE account_type = 'Asset' AND (name LIKE '%Cash%' OR name LIKE '%Bank%') AND deleted_at IS NULL LIMIT 1";
+        let cash_accounts = db.query(cash_account_sql, &[], |row| Ok(row.get::<_, i64>(0)?))
+            .ok()
+            .and_then(|v| v.first().copied());
+
+        if let Some(cash_account) = cash_accounts {
+            let journal_lines = vec![
+                (account_id, *currency_id, total, 0.0, rate, notes.clone()),
+                (cash_account, *currency_id, 0.0, total, rate, notes.clone()),
+            ];
+            create_journal_entry_internal(db, transaction_date, notes.clone(), Some("account_deposit".to_string()), None, journal_lines)?;
+        }
+
+        // Get the created transaction
+        let transaction_sql = "SELECT id, account_id, transaction_type, amount, currency, rate, total, transaction_date, is_full, notes, created_at, updated_at FROM account_transactions WHERE id = ?";
+        let transactions = db
+            .query(transaction_sql, &[&transaction_id as &dyn rusqlite::ToSql], |row| {
+                Ok(AccountTransaction {
+                    id: row.get(0)?,
+                    account_id: row.get(1)?,
+                    transaction_type: row.get(2)?,
+                    amount: row.get(3)?,
+                    currency: row.get(4)?,
+                    rate: row.get(5)?,
+                    total: row.get(6)?,
+                    transaction_date: row.get(7)?,
+                    is_full: row.get::<_, i64>(8)? != 0,
+                    notes: row.get(9)?,
+                    created_at: row.get(10)?,
+                    updated_at: row.get(11)?,
+                })
             })
-        })
-        .map_err(|e| format!("Failed to fetch transaction: {}", e))?;
+            .map_err(|e| format!("Failed to fetch transaction: {}", e))?;
 
-    if let Some(transaction) = transactions.first() {
-        Ok(transaction.clone())
-    } else {
-        Err("Failed to retrieve created transaction".to_string())
-    }
+        if let Some(transaction) = transactions.first() {
+            Ok(transaction.clone())
+        } else {
+            Err("Failed to retrieve created transaction".to_string())
+        }
+    })
 }
 
 /// Withdraw from account
 #[tauri::command]
-fn withdraw_account(
+async fn withdraw_account(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
     account_id: i64,
     amount: f64,
     currency: String,
@@ -7160,184 +7661,155 @@ fn withdraw_account(
     is_full: bool,
     notes: Option<String>,
 ) -> Result<AccountTransaction, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    let transaction = AccountTransactionInput { amount, currency, rate, transaction_date, is_full, notes };
+    audit::attributed(actor, repo.withdraw_account(account_id, &transaction)).await
+        .map_err(|e| format!("Failed to withdraw from account: {}", e))
+}
 
-    let current_balance = calculate_account_balance_internal(db, account_id)?;
+/// Withdraw from an account and post the withdrawal against an expense account
+pub(crate) fn withdraw_account_internal(db: &Database, account_id: i64, transaction: &AccountTransactionInput) -> Result<AccountTransaction, String> {
+    let AccountTransactionInput { amount, ref currency, rate, ref transaction_date, is_full, ref notes } = *transaction;
 
-    let final_amount = if is_full {
-        // Withdraw all available balance
-        if current_balance <= 0.0 {
-            return Err("Account has no balance to withdraw".to_string());
-        }
-        current_balance
-    } else {
-        if amount <= 0.0 {
-            return Err("Withdrawal amount must be greater than 0".to_string());
-        }
-        // Check if sufficient balance
-        let withdrawal_total = amount * rate;
-        if withdrawal_total > current_balance {
-            return Err("Insufficient balance for withdrawal".to_string());
-        }
-        amount
-    };
+    db.transaction(|db| {
+        let current_balance = calculate_account_balance_internal(db, account_id)?;
 
-    let total = final_amount * rate;
-    let notes_str: Option<&str> = notes.as_ref().map(|s| s.as_str());
-    let is_full_int = if is_full { 1 } else { 0 };
+        let final_amount = if is_full {
+            // Withdraw all available balance
+            if current_balance <= 0.0 {
+                return Err("Account has no balance to withdraw".to_string());
+            }
+            current_balance
+        } else {
+            if amount <= 0.0 {
+                return Err("Withdrawal amount must be greater than 0".to_string());
+            }
+            // Check if sufficient balance
+            let withdrawal_total = amount * rate;
+            if withdrawal_total > current_balance {
+                return Err("Insufficient balance for withdrawal".to_string());
+            }
+            amount
+        };
 
-    // Get currency ID from currency name
-    let currency_id_sql = "SELECT id FROM currencies WHERE name = ? LIMIT 1";
-    let currency_ids = db
-        .query(currency_id_sql, &[&currency as &dyn rusqlite::ToSql], |row| {
-            Ok(row.get::<_, i64>(0)?)
-        })
-        .map_err(|e| format!("Failed to get currency ID: {}", e))?;
-    let currency_id = currency_ids.first().ok_or("Currency not found")?;
+        let total = final_amount * rate;
+        let notes_str: Option<&str> = notes.as_deref();
+        let is_full_int = if is_full { 1 } else { 0 };
 
-    // Insert transaction
-    let insert_sql = "INSERT INTO account_transactions (account_id, transaction_type, amount, currency, rate, total, transaction_date, is_full, notes) VALUES (?, 'withdraw', ?, ?, ?, ?, ?, ?, ?)";
-    db.execute(insert_sql, &[
-        &account_id as &dyn rusqlite::ToSql,
-        &final_amount as &dyn rusqlite::ToSql,
-        &currency as &dyn rusqlite::ToSql,
-        &rate as &dyn rusqlite::ToSql,
-        &total as &dyn rusqlite::ToSql,
-        &transaction_date as &dyn rusqlite::ToSql,
-        &is_full_int as &dyn rusqlite::ToSql,
-        &notes_str as &dyn rusqlite::ToSql,
-    ])
-        .map_err(|e| format!("Failed to insert withdrawal transaction: {}", e))?;
-
-    // Update account currency balance
-    let current_currency_balance = get_account_balance_by_currency_internal(db, account_id, *currency_id)?;
-    let new_currency_balance = current_currency_balance - final_amount;
-    update_account_currency_balance_internal(db, account_id, *currency_id, new_currency_balance)?;
-
-    // Update account balance
-    let new_balance = calculate_account_balance_internal(db, account_id)?;
-    let update_balance_sql = "UPDATE accounts SET current_balance = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?";
-    db.execute(update_balance_sql, &[&new_balance as &dyn rusqlite::ToSql, &account_id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to update account balance: {}", e))?;
-
-    // Create journal entry: Debit Expense/Cash, Credit Account
-    let expense_account_sql = "SELECT id FROM accounts WHERE account_type = 'Expense' LIMIT 1";
-    let expense_accounts = db.query(expense_account_sql, &[], |row| Ok(row.get::<_, i64>(0)?))
-        .ok()
-        .and_then(|v| v.first().copied());
-
-    if let Some(expense_account) = expense_accounts {
-        let journal_lines = vec![
-            (expense_account, *currency_id, total, 0.0, rate, notes.clone()),
-            (account_id, *currency_id, 0.0, total, rate, notes.clone()),
-        ];
-        let _ = create_journal_entry_internal(db, &transaction_date, notes.clone(), Some("account_withdraw".to_string()), None, journal_lines);
-    }
+        // Get currency ID from currency name
+        let currency_id_sql = "SELECT id FROM currencies WHERE name = ? LIMIT 1";
+        let currency_ids = db
+            .query(currency_id_sql, &[currency as &dyn rusqlite::ToSql], |row| {
+                Ok(row.get::<_, i64>(0)?)
+            })
+            .map_err(|e| format!("Failed to get currency ID: {}", e))?;
+        let currency_id = currency_ids.first().ok_or("Currency not found")?;
 
-    // Get the created transaction
-    let transaction_sql = "SELECT id, account_id, transaction_type, amount, currency, rate, total, transaction_date, is_full, notes, created_at, updated_at FROM account_transactions WHERE account_id = ? AND transaction_type = 'withdraw' ORDER BY id DESC LIMIT 1";
-    let transactions = db
-        .query(transaction_sql, &[&account_id as &dyn rusqlite::ToSql], |row| {
-            Ok(AccountTransaction {
-                id: row.get(0)?,
-                account_id: row.get(1)?,
-                transaction_type: row.get(2)?,
-                amount: row.get(3)?,
-                currency: row.get(4)?,
-                rate: row.get(5)?,
-                total: row.get(6)?,
-                transaction_date: row.get(7)?,
-                is_full: row.get::<_, i64>(8)? != 0,
-                notes: row.get(9)?,
-                created_at: row.get(10)?,
-                updated_at: row.get(11)?,
+        // Insert transaction
+        let insert_sql = "INSERT INTO account_transactions (account_id, transaction_type, amount, currency, rate, total, transaction_date, is_full, notes) VALUES (?, 'withdraw', ?, ?, ?, ?, ?, ?, ?)";
+        let transaction_id = db.insert(insert_sql, &[
+            &account_id as &dyn rusqlite::ToSql,
+            &final_amount as &dyn rusqlite::ToSql,
+            currency as &dyn rusqlite::ToSql,
+            &rate as &dyn rusqlite::ToSql,
+            &total as &dyn rusqlite::ToSql,
+            transaction_date as &dyn rusqlite::ToSql,
+            &is_full_int as &dyn rusqlite::ToSql,
+            &notes_str as &dyn rusqlite::ToSql,
+        ])
+            .map_err(|e| format!("Failed to insert withdrawal transaction: {}", e))?;
+
+        // Update account currency balance
+        let current_currency_balance = get_account_balance_by_currency_internal(db, account_id, *currency_id)?;
+        let new_currency_balance = current_currency_balance - final_amount;
+        update_account_currency_balance_internal(db, account_id, *currency_id, new_currency_balance)?;
+
+        // Update account balance
+        let new_balance = calculate_account_balance_internal(db, account_id)?;
+        let update_balance_sql = "UPDATE accounts SET current_balance = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?";
+        db.execute(update_balance_sql, &[&new_balance as &dyn rusqlite::ToSql, &account_id as &dyn rusqlite::ToSql])
+            .map_err(|e| format!("Failed to update account balance: {}", e))?;
+
+        // Create journal entry: Debit Expense/Cash, Credit Account
+        let expense_account_sql = "SELECT id FROM accounts WHERE account_type = 'Expense' AND deleted_at IS NULL LIMIT 1";
+        let expense_accounts = db.query(expense_account_sql, &[], |row| Ok(row.get::<_, i64>(0)?))
+            .ok()
+            .and_then(|v| v.first().copied());
+
+        if let Some(expense_account) = expense_accounts {
+            let journal_lines = vec![
+                (expense_account, *currency_id, total, 0.0, rate, notes.clone()),
+                (account_id, *currency_id, 0.0, total, rate, notes.clone()),
+            ];
+            create_journal_entry_internal(db, transaction_date, notes.clone(), Some("account_withdraw".to_string()), None, journal_lines)?;
+        }
+
+        // Get the created transaction
+        let transaction_sql = "SELECT id, account_id, transaction_type, amount, currency, rate, total, transaction_date, is_full, notes, created_at, updated_at FROM account_transactions WHERE id = ?";
+        let transactions = db
+            .query(transaction_sql, &[&transaction_id as &dyn rusqlite::ToSql], |row| {
+                Ok(AccountTransaction {
+                    id: row.get(0)?,
+                    account_id: row.get(1)?,
+                    transaction_type: row.get(2)?,
+                    amount: row.get(3)?,
+                    currency: row.get(4)?,
+                    rate: row.get(5)?,
+                    total: row.get(6)?,
+                    transaction_date: row.get(7)?,
+                    is_full: row.get::<_, i64>(8)? != 0,
+                    notes: row.get(9)?,
+                    created_at: row.get(10)?,
+                    updated_at: row.get(11)?,
+                })
             })
-        })
-        .map_err(|e| format!("Failed to fetch transaction: {}", e))?;
+            .map_err(|e| format!("Failed to fetch transaction: {}", e))?;
 
-    if let Some(transaction) = transactions.first() {
-        Ok(transaction.clone())
-    } else {
-        Err("Failed to retrieve created transaction".to_string())
-    }
+        if let Some(transaction) = transactions.first() {
+            Ok(transaction.clone())
+        } else {
+            Err("Failed to retrieve created transaction".to_string())
+        }
+    })
 }
 
 /// Get account transactions
 #[tauri::command]
-fn get_account_transactions(
+async fn get_account_transactions(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
     account_id: i64,
 ) -> Result<Vec<AccountTransaction>, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let sql = "SELECT id, account_id, transaction_type, amount, currency, rate, total, transaction_date, is_full, notes, created_at, updated_at FROM account_transactions WHERE account_id = ? ORDER BY transaction_date DESC, created_at DESC";
-    let transactions = db
-        .query(sql, &[&account_id as &dyn rusqlite::ToSql], |row| {
-            Ok(AccountTransaction {
-                id: row.get(0)?,
-                account_id: row.get(1)?,
-                transaction_type: row.get(2)?,
-                amount: row.get(3)?,
-                currency: row.get(4)?,
-                rate: row.get(5)?,
-                total: row.get(6)?,
-                transaction_date: row.get(7)?,
-                is_full: row.get::<_, i64>(8)? != 0,
-                notes: row.get(9)?,
-                created_at: row.get(10)?,
-                updated_at: row.get(11)?,
-            })
-        })
-        .map_err(|e| format!("Failed to fetch transactions: {}", e))?;
-
-    Ok(transactions)
+    let repo = repositories(&db_state, &surreal_state)?;
+    repo.list_account_transactions(account_id).await
+        .map_err(|e| format!("Failed to fetch transactions: {}", e))
 }
 
 /// Get account balance by currency
 #[tauri::command]
-fn get_account_balance_by_currency(
+async fn get_account_balance_by_currency(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
     account_id: i64,
     currency_id: i64,
 ) -> Result<f64, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let sql = "SELECT balance FROM account_currency_balances WHERE account_id = ? AND currency_id = ?";
-    let balances = db
-        .query(sql, &[&account_id as &dyn rusqlite::ToSql, &currency_id as &dyn rusqlite::ToSql], |row| {
-            Ok(row.get::<_, f64>(0)?)
-        })
+    let repo = repositories(&db_state, &surreal_state)?;
+    let balances = repo.list_currency_balances(account_id).await
         .map_err(|e| format!("Failed to fetch account balance: {}", e))?;
 
-    Ok(balances.first().copied().unwrap_or(0.0))
+    Ok(balances.iter().find(|b| b.currency_id == currency_id).map_or(0.0, |b| b.balance))
 }
 
 /// Get all currency balances for an account
 #[tauri::command]
-fn get_all_account_balances(
+async fn get_all_account_balances(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
     account_id: i64,
 ) -> Result<Vec<AccountCurrencyBalance>, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let sql = "SELECT id, account_id, currency_id, balance, updated_at FROM account_currency_balances WHERE account_id = ?";
-    let balances = db
-        .query(sql, &[&account_id as &dyn rusqlite::ToSql], |row| {
-            Ok(AccountCurrencyBalance {
-                id: row.get(0)?,
-                account_id: row.get(1)?,
-                currency_id: row.get(2)?,
-                balance: row.get(3)?,
-                updated_at: row.get(4)?,
-            })
-        })
-        .map_err(|e| format!("Failed to fetch account balances: {}", e))?;
-
-    Ok(balances)
+    let repo = repositories(&db_state, &surreal_state)?;
+    repo.list_currency_balances(account_id).await
+        .map_err(|e| format!("Failed to fetch account balances: {}", e))
 }
 
 /// Update account currency balance (internal function)
@@ -7388,7 +7860,7 @@ fn create_journal_entry_internal(
 
     // Insert journal entry
     let insert_sql = "INSERT INTO journal_entries (entry_number, entry_date, description, reference_type, reference_id) VALUES (?, ?, ?, ?, ?)";
-    db.execute(insert_sql, &[
+    let entry_id = db.insert(insert_sql, &[
         &entry_number as &dyn rusqlite::ToSql,
         &entry_date as &dyn rusqlite::ToSql,
         &desc_str as &dyn rusqlite::ToSql,
@@ -7397,15 +7869,6 @@ fn create_journal_entry_internal(
     ])
         .map_err(|e| format!("Failed to insert journal entry: {}", e))?;
 
-    // Get the created entry ID
-    let entry_id_sql = "SELECT id FROM journal_entries WHERE entry_number = ?";
-    let entry_ids = db
-        .query(entry_id_sql, &[&entry_number as &dyn rusqlite::ToSql], |row| {
-            Ok(row.get::<_, i64>(0)?)
-        })
-        .map_err(|e| format!("Failed to fetch entry ID: {}", e))?;
-    let entry_id = entry_ids.first().ok_or("Failed to retrieve entry ID")?;
-
     // Insert journal entry lines
     for (account_id, currency_id, debit_amount, credit_amount, exchange_rate, line_desc) in lines {
         let base_amount = if debit_amount > 0.0 {
@@ -7417,7 +7880,7 @@ fn create_journal_entry_internal(
 
         let insert_line_sql = "INSERT INTO journal_entry_lines (journal_entry_id, account_id, currency_id, debit_amount, credit_amount, exchange_rate, base_amount, description) VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
         db.execute(insert_line_sql, &[
-            entry_id as &dyn rusqlite::ToSql,
+            &entry_id as &dyn rusqlite::ToSql,
             &account_id as &dyn rusqlite::ToSql,
             &currency_id as &dyn rusqlite::ToSql,
             &debit_amount as &dyn rusqlite::ToSql,
@@ -7438,110 +7901,66 @@ fn create_journal_entry_internal(
         update_account_currency_balance_internal(db, account_id, currency_id, new_balance)?;
     }
 
-    Ok(*entry_id)
+    Ok(entry_id)
 }
 
 /// Create a journal entry with lines
 #[tauri::command]
-fn create_journal_entry(
+async fn create_journal_entry(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
     entry_date: String,
     description: Option<String>,
     reference_type: Option<String>,
     reference_id: Option<i64>,
     lines: Vec<(i64, i64, f64, f64, f64, Option<String>)>, // (account_id, currency_id, debit_amount, credit_amount, exchange_rate, description)
 ) -> Result<JournalEntry, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    // Balance validation removed - entries can be saved unbalanced and balanced later with updates
-
-    // Generate entry number
-    let entry_number_sql = "SELECT COALESCE(MAX(CAST(SUBSTR(entry_number, 2) AS INTEGER)), 0) + 1 FROM journal_entries WHERE entry_number LIKE 'J%'";
-    let entry_numbers = db
-        .query(entry_number_sql, &[], |row| {
-            Ok(row.get::<_, i64>(0)?)
-        })
-        .map_err(|e| format!("Failed to generate entry number: {}", e))?;
-    let entry_number = format!("J{:06}", entry_numbers.first().copied().unwrap_or(1));
-
-    let desc_str: Option<&str> = description.as_ref().map(|s| s.as_str());
-    let ref_type_str: Option<&str> = reference_type.as_ref().map(|s| s.as_str());
-
-    // Insert journal entry
-    let insert_sql = "INSERT INTO journal_entries (entry_number, entry_date, description, reference_type, reference_id) VALUES (?, ?, ?, ?, ?)";
-    db.execute(insert_sql, &[
-        &entry_number as &dyn rusqlite::ToSql,
-        &entry_date as &dyn rusqlite::ToSql,
-        &desc_str as &dyn rusqlite::ToSql,
-        &ref_type_str as &dyn rusqlite::ToSql,
-        &reference_id as &dyn rusqlite::ToSql,
-    ])
-        .map_err(|e| format!("Failed to insert journal entry: {}", e))?;
-
-    // Get the created entry ID
-    let entry_id_sql = "SELECT id FROM journal_entries WHERE entry_number = ?";
-    let entry_ids = db
-        .query(entry_id_sql, &[&entry_number as &dyn rusqlite::ToSql], |row| {
-            Ok(row.get::<_, i64>(0)?)
-        })
-        .map_err(|e| format!("Failed to fetch entry ID: {}", e))?;
-    let entry_id = entry_ids.first().ok_or("Failed to retrieve entry ID")?;
-
-    // Insert journal entry lines
-    for (account_id, currency_id, debit_amount, credit_amount, exchange_rate, line_desc) in lines {
-        let base_amount = if debit_amount > 0.0 {
-            debit_amount * exchange_rate
-        } else {
-            credit_amount * exchange_rate
-        };
-        let line_desc_str: Option<&str> = line_desc.as_ref().map(|s| s.as_str());
-
-        let insert_line_sql = "INSERT INTO journal_entry_lines (journal_entry_id, account_id, currency_id, debit_amount, credit_amount, exchange_rate, base_amount, description) VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
-        db.execute(insert_line_sql, &[
-            entry_id as &dyn rusqlite::ToSql,
-            &account_id as &dyn rusqlite::ToSql,
-            &currency_id as &dyn rusqlite::ToSql,
-            &debit_amount as &dyn rusqlite::ToSql,
-            &credit_amount as &dyn rusqlite::ToSql,
-            &exchange_rate as &dyn rusqlite::ToSql,
-            &base_amount as &dyn rusqlite::ToSql,
-            &line_desc_str as &dyn rusqlite::ToSql,
-        ])
-            .map_err(|e| format!("Failed to insert journal entry line: {}", e))?;
-
-        // Update account currency balance
-        let current_balance = get_account_balance_by_currency_internal(db, account_id, currency_id)?;
-        let new_balance = if debit_amount > 0.0 {
-            current_balance + debit_amount
-        } else {
-            current_balance - credit_amount
-        };
-        update_account_currency_balance_internal(db, account_id, currency_id, new_balance)?;
-    }
-
-    // Get the created entry
-    let entry_sql = "SELECT id, entry_number, entry_date, description, reference_type, reference_id, created_at, updated_at FROM journal_entries WHERE id = ?";
-    let entries = db
-        .query(entry_sql, &[entry_id as &dyn rusqlite::ToSql], |row| {
-            Ok(JournalEntry {
-                id: row.get(0)?,
-                entry_number: row.get(1)?,
-                entry_date: row.get(2)?,
-                description: row.get(3)?,
-                reference_type: row.get(4)?,
-                reference_id: row.get(5)?,
-                created_at: row.get(6)?,
-                updated_at: row.get(7)?,
+    let repo = repositories(&db_state, &surreal_state)?;
+    let lines = lines.into_iter().map(JournalLineInput::from).collect();
+    let entry = JournalEntryInput { entry_date, description, reference_type, reference_id, lines };
+    audit::attributed(actor, repo.create_journal_entry(&entry)).await
+        .map_err(|e| format!("Failed to create journal entry: {}", e))
+}
+
+/// Post a journal entry and move its accounts' balances
+pub(crate) fn post_journal_entry_internal(db: &Database, entry: &JournalEntryInput) -> Result<JournalEntry, String> {
+    db.transaction(|db| {
+        let lines = entry.lines.iter()
+            .map(|line| (line.account_id, line.currency_id, line.debit_amount, line.credit_amount, line.exchange_rate, line.description.clone()))
+            .collect();
+        let entry_id = create_journal_entry_internal(
+            db,
+            &entry.entry_date,
+            entry.description.clone(),
+            entry.reference_type.clone(),
+            entry.reference_id,
+            lines,
+        )?;
+
+        // Get the created entry
+        let entry_sql = "SELECT id, entry_number, entry_date, description, reference_type, reference_id, created_at, updated_at FROM journal_entries WHERE id = ?";
+        let entries = db
+            .query(entry_sql, &[&entry_id as &dyn rusqlite::ToSql], |row| {
+                Ok(JournalEntry {
+                    id: row.get(0)?,
+                    entry_number: row.get(1)?,
+                    entry_date: row.get(2)?,
+                    description: row.get(3)?,
+                    reference_type: row.get(4)?,
+                    reference_id: row.get(5)?,
+                    created_at: row.get(6)?,
+                    updated_at: row.get(7)?,
+                })
             })
-        })
-        .map_err(|e| format!("Failed to fetch journal entry: {}", e))?;
+            .map_err(|e| format!("Failed to fetch journal entry: {}", e))?;
 
-    if let Some(entry) = entries.first() {
-        Ok(entry.clone())
-    } else {
-        Err("Failed to retrieve created journal entry".to_string())
-    }
+        if let Some(entry) = entries.first() {
+            Ok(entry.clone())
+        } else {
+            Err("Failed to retrieve created journal entry".to_string())
+        }
+    })
 }
 
 /// Internal helper to get account balance by currency
@@ -7561,241 +7980,177 @@ fn get_account_balance_by_currency_internal(
 
 /// Get journal entries with pagination
 #[tauri::command]
-fn get_journal_entries(
+async fn get_journal_entries(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
     page: i64,
     per_page: i64,
 ) -> Result<PaginatedResponse<JournalEntry>, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    let offset = (page - 1) * per_page;
-
-    // Get total count
-    let count_sql = "SELECT COUNT(*) FROM journal_entries";
-    let total: i64 = db
-        .query(count_sql, &[], |row| {
-            Ok(row.get::<_, i64>(0)?)
-        })
-        .map_err(|e| format!("Failed to count journal entries: {}", e))?
-        .first()
-        .copied()
-        .unwrap_or(0);
-
-    // Get paginated entries
-    let sql = "SELECT id, entry_number, entry_date, description, reference_type, reference_id, created_at, updated_at FROM journal_entries ORDER BY entry_date DESC, id DESC LIMIT ? OFFSET ?";
-    let entries = db
-        .query(sql, &[&per_page as &dyn rusqlite::ToSql, &offset as &dyn rusqlite::ToSql], |row| {
-            Ok(JournalEntry {
-                id: row.get(0)?,
-                entry_number: row.get(1)?,
-                entry_date: row.get(2)?,
-                description: row.get(3)?,
-                reference_type: row.get(4)?,
-                reference_id: row.get(5)?,
-                created_at: row.get(6)?,
-                updated_at: row.get(7)?,
-            })
-        })
-        .map_err(|e| format!("Failed to fetch journal entries: {}", e))?;
-
-    let total_pages = (total as f64 / per_page as f64).ceil() as i64;
-
-    Ok(PaginatedResponse {
-        items: entries,
-        total,
-        page,
-        per_page,
-        total_pages,
-    })
+    let repo = repositories(&db_state, &surreal_state)?;
+    repo.list_journal_entries(&ListQuery::new(page, per_page)).await
+        .map_err(|e| format!("Failed to fetch journal entries: {}", e))
 }
 
 /// Get a single journal entry with lines
 #[tauri::command]
-fn get_journal_entry(
+async fn get_journal_entry(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
     id: i64,
 ) -> Result<(JournalEntry, Vec<JournalEntryLine>), String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    // Get entry
-    let entry_sql = "SELECT id, entry_number, entry_date, description, reference_type, reference_id, created_at, updated_at FROM journal_entries WHERE id = ?";
-    let entries = db
-        .query(entry_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(JournalEntry {
-                id: row.get(0)?,
-                entry_number: row.get(1)?,
-                entry_date: row.get(2)?,
-                description: row.get(3)?,
-                reference_type: row.get(4)?,
-                reference_id: row.get(5)?,
-                created_at: row.get(6)?,
-                updated_at: row.get(7)?,
-            })
-        })
-        .map_err(|e| format!("Failed to fetch journal entry: {}", e))?;
-
-    let entry = entries.first().ok_or("Journal entry not found")?;
-
-    // Get lines
-    let lines_sql = "SELECT id, journal_entry_id, account_id, currency_id, debit_amount, credit_amount, exchange_rate, base_amount, description, created_at FROM journal_entry_lines WHERE journal_entry_id = ?";
-    let lines = db
-        .query(lines_sql, &[&id as &dyn rusqlite::ToSql], |row| {
-            Ok(JournalEntryLine {
-                id: row.get(0)?,
-                journal_entry_id: row.get(1)?,
-                account_id: row.get(2)?,
-                currency_id: row.get(3)?,
-                debit_amount: row.get(4)?,
-                credit_amount: row.get(5)?,
-                exchange_rate: row.get(6)?,
-                base_amount: row.get(7)?,
-                description: row.get(8)?,
-                created_at: row.get(9)?,
-            })
-        })
-        .map_err(|e| format!("Failed to fetch journal entry lines: {}", e))?;
-
-    Ok((entry.clone(), lines))
+    let repo = repositories(&db_state, &surreal_state)?;
+    repo.get_journal_entry(id).await
+        .map_err(|e| format!("Failed to fetch journal entry: {}", e))?
+        .ok_or_else(|| "Journal entry not found".to_string())
 }
 
 /// Update a journal entry - add new lines to balance or modify existing lines
 #[tauri::command]
-fn update_journal_entry(
+async fn update_journal_entry(
     db_state: State<'_, Mutex<Option<Database>>>,
+    surreal_state: State<'_, Mutex<Option<SurrealDatabase>>>,
+    actor: AuditActor,
     entry_id: i64,
     new_lines: Vec<(i64, i64, f64, f64, f64, Option<String>)>, // (account_id, currency_id, debit_amount, credit_amount, exchange_rate, description)
 ) -> Result<JournalEntry, String> {
-    let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    let db = db_guard.as_ref().ok_or("No database is currently open")?;
-
-    // Get existing lines to reverse their account balance changes
-    let existing_lines_sql = "SELECT account_id, currency_id, debit_amount, credit_amount FROM journal_entry_lines WHERE journal_entry_id = ?";
-    let existing_lines = db
-        .query(existing_lines_sql, &[&entry_id as &dyn rusqlite::ToSql], |row| {
-            Ok((
-                row.get::<_, i64>(0)?, // account_id
-                row.get::<_, i64>(1)?, // currency_id
-                row.get::<_, f64>(2)?, // debit_amount
-                row.get::<_, f64>(3)?, // credit_amount
-            ))
-        })
-        .map_err(|e| format!("Failed to fetch existing lines: {}", e))?;
-
-    // Reverse account balance changes from existing lines
-    for (account_id, currency_id, old_debit, old_credit) in existing_lines.iter() {
-        let current_balance = get_account_balance_by_currency_internal(db, *account_id, *currency_id)?;
-        // Reverse: if it was a debit, subtract it; if it was a credit, add it back
-        let reversed_balance = if *old_debit > 0.0 {
-            current_balance - old_debit
-        } else {
-            current_balance + old_credit
-        };
-        update_account_currency_balance_internal(db, *account_id, *currency_id, reversed_balance)?;
-    }
-
-    // Delete existing lines
-    let delete_lines_sql = "DELETE FROM journal_entry_lines WHERE journal_entry_id = ?";
-    db.execute(delete_lines_sql, &[&entry_id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to delete existing lines: {}", e))?;
-
-    // Insert new lines and update account balances
-    for (account_id, currency_id, debit_amount, credit_amount, exchange_rate, line_desc) in new_lines.iter() {
-        let base_amount = if *debit_amount > 0.0 {
-            debit_amount * exchange_rate
-        } else {
-            credit_amount * exchange_rate
-        };
-        let line_desc_str: Option<&str> = line_desc.as_ref().map(|s| s.as_str());
-
-        // Insert new line
-        let insert_line_sql = "INSERT INTO journal_entry_lines (journal_entry_id, account_id, currency_id, debit_amount, credit_amount, exchange_rate, base_amount, description) VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
-        db.execute(insert_line_sql, &[
-            &entry_id as &dyn rusqlite::ToSql,
-            account_id as &dyn rusqlite::ToSql,
-            currency_id as &dyn rusqlite::ToSql,
-            debit_amount as &dyn rusqlite::ToSql,
-            credit_amount as &dyn rusqlite::ToSql,
-            exchange_rate as &dyn rusqlite::ToSql,
-            &base_amount as &dyn rusqlite::ToSql,
-            &line_desc_str as &dyn rusqlite::ToSql,
-        ])
-            .map_err(|e| format!("Failed to insert journal entry line: {}", e))?;
+    let repo = repositories(&db_state, &surreal_state)?;
+    let lines: Vec<JournalLineInput> = new_lines.into_iter().map(JournalLineInput::from).collect();
+    audit::attributed(actor, repo.update_journal_entry(entry_id, &lines)).await
+        .map_err(|e| format!("Failed to update journal entry: {}", e))
+}
+
+/// Replace a journal entry's lines, reversing the old lines' balances and recording each new
+/// line as a transaction of its account
+pub(crate) fn update_journal_entry_internal(db: &Database, entry_id: i64, new_lines: &[JournalLineInput]) -> Result<JournalEntry, String> {
+    db.transaction(|db| {
+        // Get existing lines to reverse their account balance changes
+        let existing_lines_sql = "SELECT account_id, currency_id, debit_amount, credit_amount FROM journal_entry_lines WHERE journal_entry_id = ?";
+        let existing_lines = db
+            .query(existing_lines_sql, &[&entry_id as &dyn rusqlite::ToSql], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?, // account_id
+                    row.get::<_, i64>(1)?, // currency_id
+                    row.get::<_, f64>(2)?, // debit_amount
+                    row.get::<_, f64>(3)?, // credit_amount
+                ))
+            })
+            .map_err(|e| format!("Failed to fetch existing lines: {}", e))?;
+
+        // Reverse account balance changes from existing lines
+        for (account_id, currency_id, old_debit, old_credit) in existing_lines.iter() {
+            let current_balance = get_account_balance_by_currency_internal(db, *account_id, *currency_id)?;
+            // Reverse: if it was a debit, subtract it; if it was a credit, add it back
+            let reversed_balance = if *old_debit > 0.0 {
+                current_balance - old_debit
+            } else {
+                current_balance + old_credit
+            };
+            update_account_currency_balance_internal(db, *account_id, *currency_id, reversed_balance)?;
+        }
 
-        // Update account currency balance
-        let current_balance = get_account_balance_by_currency_internal(db, *account_id, *currency_id)?;
-        let new_balance = if *debit_amount > 0.0 {
-            current_balance + debit_amount
-        } else {
-            current_balance - credit_amount
-        };
-        update_account_currency_balance_internal(db, *account_id, *currency_id, new_balance)?;
+        // Delete existing lines
+        let delete_lines_sql = "DELETE FROM journal_entry_lines WHERE journal_entry_id = ?";
+        db.execute(delete_lines_sql, &[&entry_id as &dyn rusqlite::ToSql])
+            .map_err(|e| format!("Failed to delete existing lines: {}", e))?;
+
+        // Insert new lines and update account balances
+        for JournalLineInput { account_id, currency_id, debit_amount, credit_amount, exchange_rate, description: line_desc } in new_lines {
+            let base_amount = if *debit_amount > 0.0 {
+                debit_amount * exchange_rate
+            } else {
+                credit_amount * exchange_rate
+            };
+            let line_desc_str: Option<&str> = line_desc.as_ref().map(|s| s.as_str());
+
+            // Insert new line
+            let insert_line_sql = "INSERT INTO journal_entry_lines (journal_entry_id, account_id, currency_id, debit_amount, credit_amount, exchange_rate, base_amount, description) VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
+            db.execute(insert_line_sql, &[
+                &entry_id as &dyn rusqlite::ToSql,
+                account_id as &dyn rusqlite::ToSql,
+                currency_id as &dyn rusqlite::ToSql,
+                debit_amount as &dyn rusqlite::ToSql,
+                credit_amount as &dyn rusqlite::ToSql,
+                exchange_rate as &dyn rusqlite::ToSql,
+                &base_amount as &dyn rusqlite::ToSql,
+                &line_desc_str as &dyn rusqlite::ToSql,
+            ])
+                .map_err(|e| format!("Failed to insert journal entry line: {}", e))?;
 
-        // Create account transaction for new/modified lines
-        let entry_sql = "SELECT entry_date FROM journal_entries WHERE id = ?";
-        let entry_dates = db
-            .query(entry_sql, &[&entry_id as &dyn rusqlite::ToSql], |row| {
-                Ok(row.get::<_, String>(0)?)
-            })
-            .map_err(|e| format!("Failed to fetch entry date: {}", e))?;
-        
-        if let Some(entry_date) = entry_dates.first() {
-            let transaction_type = if *debit_amount > 0.0 { "deposit" } else { "withdraw" };
-            let amount = if *debit_amount > 0.0 { *debit_amount } else { *credit_amount };
-            let currency_name_sql = "SELECT name FROM currencies WHERE id = ?";
-            let currency_names = db
-                .query(currency_name_sql, &[currency_id as &dyn rusqlite::ToSql], |row| {
+            // Update account currency balance
+            let current_balance = get_account_balance_by_currency_internal(db, *account_id, *currency_id)?;
+            let new_balance = if *debit_amount > 0.0 {
+                current_balance + debit_amount
+            } else {
+                current_balance - credit_amount
+            };
+            update_account_currency_balance_internal(db, *account_id, *currency_id, new_balance)?;
+
+            // Create account transaction for new/modified lines
+            let entry_sql = "SELECT entry_date FROM journal_entries WHERE id = ?";
+            let entry_dates = db
+                .query(entry_sql, &[&entry_id as &dyn rusqlite::ToSql], |row| {
                     Ok(row.get::<_, String>(0)?)
                 })
-                .ok()
-                .and_then(|v| v.first().cloned());
-            
-            if let Some(currency_name) = currency_names {
-                let total = base_amount;
-                let insert_transaction_sql = "INSERT INTO account_transactions (account_id, transaction_type, amount, currency, rate, total, transaction_date, is_full, notes) VALUES (?, ?, ?, ?, ?, ?, ?, 0, ?)";
-                let notes_str: Option<&str> = line_desc.as_ref().map(|s| s.as_str());
-                let _ = db.execute(insert_transaction_sql, &[
-                    account_id as &dyn rusqlite::ToSql,
-                    &transaction_type as &dyn rusqlite::ToSql,
-                    &amount as &dyn rusqlite::ToSql,
-                    &currency_name as &dyn rusqlite::ToSql,
-                    exchange_rate as &dyn rusqlite::ToSql,
-                    &total as &dyn rusqlite::ToSql,
-                    entry_date as &dyn rusqlite::ToSql,
-                    &notes_str as &dyn rusqlite::ToSql,
-                ]);
+                .map_err(|e| format!("Failed to fetch entry date: {}", e))?;
+
+            if let Some(entry_date) = entry_dates.first() {
+                let transaction_type = if *debit_amount > 0.0 { "deposit" } else { "withdraw" };
+                let amount = if *debit_amount > 0.0 { *debit_amount } else { *credit_amount };
+                let currency_name_sql = "SELECT name FROM currencies WHERE id = ?";
+                let currency_names = db
+                    .query(currency_name_sql, &[currency_id as &dyn rusqlite::ToSql], |row| {
+                        Ok(row.get::<_, String>(0)?)
+                    })
+                    .ok()
+                    .and_then(|v| v.first().cloned());
+
+                if let Some(currency_name) = currency_names {
+                    let total = base_amount;
+                    let insert_transaction_sql = "INSERT INTO account_transactions (account_id, transaction_type, amount, currency, rate, total, transaction_date, is_full, notes) VALUES (?, ?, ?, ?, ?, ?, ?, 0, ?)";
+                    let notes_str: Option<&str> = line_desc.as_ref().map(|s| s.as_str());
+                    db.execute(insert_transaction_sql, &[
+                        account_id as &dyn rusqlite::ToSql,
+                        &transaction_type as &dyn rusqlite::ToSql,
+                        &amount as &dyn rusqlite::ToSql,
+                        &currency_name as &dyn rusqlite::ToSql,
+                        exchange_rate as &dyn rusqlite::ToSql,
+                        &total as &dyn rusqlite::ToSql,
+                        entry_date as &dyn rusqlite::ToSql,
+                        &notes_str as &dyn rusqlite::ToSql,
+                    ])
+                        .map_err(|e| format!("Failed to insert account transaction: {}", e))?;
+                }
             }
         }
-    }
 
-    // Update entry timestamp
-    let update_entry_sql = "UPDATE journal_entries SET updated_at = CURRENT_TIMESTAMP WHERE id = ?";
-    db.execute(update_entry_sql, &[&entry_id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to update journal entry: {}", e))?;
+        // Update entry timestamp
+        let update_entry_sql = "UPDATE journal_entries SET updated_at = CURRENT_TIMESTAMP WHERE id = ?";
+        db.execute(update_entry_sql, &[&entry_id as &dyn rusqlite::ToSql])
+            .map_err(|e| format!("Failed to update journal entry: {}", e))?;
 
-    // Get the updated entry
-    let entry_sql = "SELECT id, entry_number, entry_date, description, reference_type, reference_id, created_at, updated_at FROM journal_entries WHERE id = ?";
-    let entries = db
-        .query(entry_sql, &[&entry_id as &dyn rusqlite::ToSql], |row| {
-            Ok(JournalEntry {
-                id: row.get(0)?,
-                entry_number: row.get(1)?,
-                entry_date: row.get(2)?,
-                description: row.get(3)?,
-                reference_type: row.get(4)?,
-                reference_id: row.get(5)?,
-                created_at: row.get(6)?,
-                updated_at: row.get(7)?,
+        // Get the updated entry
+        let entry_sql = "SELECT id, entry_number, entry_date, description, reference_type, reference_id, created_at, updated_at FROM journal_entries WHERE id = ?";
+        let entries = db
+            .query(entry_sql, &[&entry_id as &dyn rusqlite::ToSql], |row| {
+                Ok(JournalEntry {
+                    id: row.get(0)?,
+                    entry_number: row.get(1)?,
+                    entry_date: row.get(2)?,
+                    description: row.get(3)?,
+                    reference_type: row.get(4)?,
+                    reference_id: row.get(5)?,
+                    created_at: row.get(6)?,
+                    updated_at: row.get(7)?,
+                })
             })
-        })
-        .map_err(|e| format!("Failed to fetch updated journal entry: {}", e))?;
+            .map_err(|e| format!("Failed to fetch updated journal entry: {}", e))?;
 
-    if let Some(entry) = entries.first() {
-        Ok(entry.clone())
-    } else {
-        Err("Failed to retrieve updated journal entry".to_string())
-    }
+        if let Some(entry) = entries.first() {
+            Ok(entry.clone())
+        } else {
+            Err("Failed to retrieve updated journal entry".to_string())
+        }
+    })
 }
 
 /// Create exchange rate
@@ -7811,7 +8166,7 @@ fn create_exchange_rate(
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
 
     let insert_sql = "INSERT INTO currency_exchange_rates (from_currency_id, to_currency_id, rate, date) VALUES (?, ?, ?, ?)";
-    db.execute(insert_sql, &[
+    let rate_id = db.insert(insert_sql, &[
         &from_currency_id as &dyn rusqlite::ToSql,
         &to_currency_id as &dyn rusqlite::ToSql,
         &rate as &dyn rusqlite::ToSql,
@@ -7820,9 +8175,9 @@ fn create_exchange_rate(
         .map_err(|e| format!("Failed to insert exchange rate: {}", e))?;
 
     // Get the created rate
-    let rate_sql = "SELECT id, from_currency_id, to_currency_id, rate, date, created_at FROM currency_exchange_rates WHERE from_currency_id = ? AND to_currency_id = ? AND date = ? ORDER BY id DESC LIMIT 1";
+    let rate_sql = "SELECT id, from_currency_id, to_currency_id, rate, date, created_at FROM currency_exchange_rates WHERE id = ?";
     let rates = db
-        .query(rate_sql, &[&from_currency_id as &dyn rusqlite::ToSql, &to_currency_id as &dyn rusqlite::ToSql, &date as &dyn rusqlite::ToSql], |row| {
+        .query(rate_sql, &[&rate_id as &dyn rusqlite::ToSql], |row| {
             Ok(CurrencyExchangeRate {
                 id: row.get(0)?,
                 from_currency_id: row.get(1)?,
@@ -7951,40 +8306,141 @@ fn migrate_existing_data(db_state: State<'_, Mutex<Option<Database>>>) -> Result
     let db_guard = db_state.lock().map_err(|e| format!("Lock error: {}", e))?;
     let db = db_guard.as_ref().ok_or("No database is currently open")?;
 
-    // Get base currency
-    let base_currency_sql = "SELECT id FROM currencies WHERE base = 1 LIMIT 1";
-    let base_currencies = db.query(base_currency_sql, &[], |row| Ok(row.get::<_, i64>(0)?))
-        .map_err(|e| format!("Failed to get base currency: {}", e))?;
-    let base_currency_id = base_currencies.first().copied().unwrap_or_else(|| {
-        db.query("SELECT id FROM currencies LIMIT 1", &[], |row| Ok(row.get::<_, i64>(0)?))
-            .ok()
-            .and_then(|v| v.first().copied())
-            .unwrap_or(1)
-    });
+    db.transaction(|db| {
+        // Get base currency
+        let base_currency_sql = "SELECT id FROM currencies WHERE base = 1 LIMIT 1";
+        let base_currencies = db.query(base_currency_sql, &[], |row| Ok(row.get::<_, i64>(0)?))
+            .map_err(|e| format!("Failed to get base currency: {}", e))?;
+        let base_currency_id = base_currencies.first().copied().unwrap_or_else(|| {
+            db.query("SELECT id FROM currencies LIMIT 1", &[], |row| Ok(row.get::<_, i64>(0)?))
+                .ok()
+                .and_then(|v| v.first().copied())
+                .unwrap_or(1)
+        });
 
-    // Migrate existing account balances to account_currency_balances
-    let accounts_sql = "SELECT id, currency_id, current_balance FROM accounts";
-    let accounts = db
-        .query(accounts_sql, &[], |row| {
-            Ok((row.get::<_, i64>(0)?, row.get::<_, Option<i64>>(1)?, row.get::<_, f64>(2)?))
-        })
-        .map_err(|e| format!("Failed to fetch accounts: {}", e))?;
-
-    let mut migrated_count = 0;
-    for (account_id, currency_id, balance) in accounts {
-        let currency = currency_id.unwrap_or(base_currency_id);
-        if balance != 0.0 {
-            update_account_currency_balance_internal(db, account_id, currency, balance)?;
-            migrated_count += 1;
+        // Migrate existing account balances to account_currency_balances
+        let accounts_sql = "SELECT id, currency_id, current_balance FROM accounts";
+        let accounts = db
+            .query(accounts_sql, &[], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, Option<i64>>(1)?, row.get::<_, f64>(2)?))
+            })
+            .map_err(|e| format!("Failed to fetch accounts: {}", e))?;
+
+        let mut migrated_count = 0;
+        for (account_id, currency_id, balance) in accounts {
+            let currency = currency_id.unwrap_or(base_currency_id);
+            if balance != 0.0 {
+                update_account_currency_balance_internal(db, account_id, currency, balance)?;
+                migrated_count += 1;
+            }
+        }
+
+        // Migrate existing sales to have base currency
+        let update_sales_sql = "UPDATE sales SET currency_id = ?, exchange_rate = 1, base_amount = total_amount WHERE currency_id IS NULL";
+        db.execute(update_sales_sql, &[&base_currency_id as &dyn rusqlite::ToSql])
+            .map_err(|e| format!("Failed to migrate sales: {}", e))?;
+
+        Ok(format!("Migration completed. Migrated {} account balances.", migrated_count))
+    })
+}
+
+/// Check every command against the calling window's session before `handler` runs it,
+/// and attribute the data that synchronous commands change to the session's user in the
+/// audit log. Async commands run after `handler` returns, so they take an `AuditActor`
+/// argument and attribute their writes themselves.
+fn guarded<R: tauri::Runtime>(
+    handler: impl Fn(tauri::ipc::Invoke<R>) -> bool + Send + Sync + 'static,
+) -> impl Fn(tauri::ipc::Invoke<R>) -> bool + Send + Sync + 'static {
+    move |invoke| {
+        let webview = invoke.message.webview();
+        let command = invoke.message.command().to_string();
+        let sessions = webview.state::<SessionStore>();
+        let now = chrono::Utc::now();
+        if let Err(error) = sessions.authorize(webview.label(), &command, now) {
+            invoke.resolver.reject(error);
+            return true;
+        }
+        if let Err(error) = webview.state::<LicenseGate>().state(now.date_naive()).authorize(&command) {
+            invoke.resolver.reject(error);
+            return true;
         }
+        if !permissions::changes_data(&command) {
+            return handler(invoke);
+        }
+        let actor = window_actor(&webview, &command);
+        audit::attributed_sync(actor, || handler(invoke))
+    }
+}
+
+/// The user logged in to `webview`, as the author of `command`'s changes
+fn window_actor<R: tauri::Runtime>(webview: &tauri::Webview<R>, command: &str) -> AuditActor {
+    let user = webview.state::<SessionStore>().current(webview.label(), chrono::Utc::now()).map(|current| current.session);
+    AuditActor {
+        user_id: user.as_ref().map(|user| user.user_id),
+        username: user.map(|user| user.username),
+        command: command.to_string(),
+    }
+}
+
+/// Lets an async command take its caller as an argument, to attribute its writes
+impl<'de, R: tauri::Runtime> tauri::ipc::CommandArg<'de, R> for AuditActor {
+    fn from_command(command: tauri::ipc::CommandItem<'de, R>) -> Result<Self, tauri::ipc::InvokeError> {
+        Ok(window_actor(&command.message.webview(), command.message.command()))
     }
+}
+
+/// Seal new audit log entries into the chain, for as long as the app runs
+fn spawn_audit_sealer(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            if let Ok(repo) = repositories(&app.state(), &app.state()) {
+                if let Err(e) = audit::seal(repo.as_ref()).await {
+                    eprintln!("⚠️ Audit log: {}", e);
+                }
+            }
+            tokio::time::sleep(audit::SEAL_INTERVAL).await;
+        }
+    });
+}
 
-    // Migrate existing sales to have base currency
-    let update_sales_sql = "UPDATE sales SET currency_id = ?, exchange_rate = 1, base_amount = total_amount WHERE currency_id IS NULL";
-    db.execute(update_sales_sql, &[&base_currency_id as &dyn rusqlite::ToSql])
-        .map_err(|e| format!("Failed to migrate sales: {}", e))?;
+/// Emit `entity_changed` for every change committed to SQLite, for as long as the app runs
+fn spawn_change_feed(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut feed = SqliteFeed::default();
+        loop {
+            let db = app.state::<Mutex<Option<Database>>>().lock().ok().and_then(|guard| guard.clone());
+            if let Some(db) = db {
+                match feed.poll(&db) {
+                    Ok(changes) => changes.iter().for_each(|change| emit_change(&app, change)),
+                    Err(e) => eprintln!("⚠️ Change feed: {}", e),
+                }
+            }
+            tokio::time::sleep(changes::POLL_INTERVAL).await;
+        }
+    });
+}
 
-    Ok(format!("Migration completed. Migrated {} account balances.", migrated_count))
+/// Send queued events to webhooks for as long as the app runs
+fn spawn_webhook_worker(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let client = match webhooks::client() {
+            Ok(client) => client,
+            Err(e) => {
+                eprintln!("❌ Failed to create webhook client: {}", e);
+                return;
+            }
+        };
+        loop {
+            // Take the database that is open right now; it can be switched or closed at any time
+            let db = app.state::<Mutex<Option<Database>>>().lock().ok().and_then(|guard| guard.clone());
+            if let Some(db) = db {
+                if let Err(e) = webhooks::run_once(&db, &client, chrono::Utc::now()).await {
+                    eprintln!("⚠️ Webhook delivery failed: {}", e);
+                }
+            }
+            tokio::time::sleep(webhooks::POLL_INTERVAL).await;
+        }
+    });
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -7999,6 +8455,18 @@ pub fn run() {
         .plugin(tauri_plugin_keychain::init())
         .plugin(tauri_plugin_updater::Builder::new().build())
         .setup(|app| {
+            // Fail loudly if a command would ask for state that was never registered
+            check_managed_state(app.handle())?;
+
+            // Open the SQLite database; commands report "No database is currently open" if this fails
+            if let Err(e) = open_default_database(app.handle()) {
+                eprintln!("❌ Failed to open SQLite database: {}", e);
+            }
+
+            spawn_change_feed(app.handle().clone());
+            spawn_webhook_worker(app.handle().clone());
+            spawn_audit_sealer(app.handle().clone());
+
             // Start the AI server in a background thread with its own runtime
             let app_handle = app.handle().clone();
             std::thread::spawn(move || {
@@ -8024,9 +8492,15 @@ pub fn run() {
             });
             Ok(())
         })
+        .manage(Mutex::new(None::<Database>))
         .manage(Mutex::new(None::<SurrealDatabase>))
         .manage(Mutex::new(None::<DatabaseConfig>))
-        .invoke_handler(tauri::generate_handler![
+        .manage(SessionStore::default())
+        .manage(LoginThrottle::default())
+        .manage(LoginChallenges::default())
+        .manage(LicenseGate::load())
+        .manage(LiveQueries::default())
+        .invoke_handler(guarded(tauri::generate_handler![
             db_configure,
             get_db_config,
             db_open_surreal,
@@ -8035,12 +8509,65 @@ pub fn run() {
             db_query_surreal,
             db_execute_surreal,
             db_sync,
+            db_sync_status,
+            db_sync_conflicts,
+            db_sync_resolve_conflict,
+            migrate_sqlite_to_surreal,
+            export_surreal_to_sqlite,
+            db_create,
+            db_open,
+            db_close,
+            db_is_open,
+            db_execute,
+            db_query,
             get_database_path,
             backup_database,
+            db_migrate,
+            db_schema_version,
             init_users_table,
             register_user,
             login_user,
+            logout_user,
+            get_current_user,
+            reauthenticate,
+            list_sessions,
+            revoke_session,
+            create_api_token,
+            get_api_tokens,
+            revoke_api_token,
+            change_password,
+            get_profile,
+            update_profile,
+            get_user,
+            get_user_stats,
+            create_user,
+            update_user,
+            set_user_active,
+            set_user_role,
+            reset_password,
+            delete_user,
+            unlock_user,
+            get_password_policy,
+            update_password_policy,
+            verify_two_factor,
+            get_two_factor_status,
+            begin_two_factor_enrollment,
+            confirm_two_factor_enrollment,
+            disable_two_factor,
+            regenerate_recovery_codes,
+            reset_two_factor,
+            get_two_factor_policy,
+            update_two_factor_policy,
             get_users,
+            list_capabilities,
+            get_roles,
+            get_audit_log,
+            verify_audit_chain,
+            purge_trash,
+            get_retention_policy,
+            update_retention_policy,
+            save_role,
+            delete_role,
             init_currencies_table,
             create_currency,
             get_currencies,
@@ -8051,17 +8578,23 @@ pub fn run() {
             get_suppliers,
             update_supplier,
             delete_supplier,
+            restore_supplier,
+            get_deleted_suppliers,
             init_products_table,
             create_product,
             get_products,
             update_product,
             delete_product,
+            restore_product,
+            get_deleted_products,
             init_purchases_table,
             create_purchase,
             get_purchases,
             get_purchase,
             update_purchase,
             delete_purchase,
+            restore_purchase,
+            get_deleted_purchases,
             create_purchase_item,
             get_purchase_items,
             update_purchase_item,
@@ -8080,12 +8613,16 @@ pub fn run() {
             get_customers,
             update_customer,
             delete_customer,
+            restore_customer,
+            get_deleted_customers,
             init_sales_table,
             create_sale,
             get_sales,
             get_sale,
             update_sale,
             delete_sale,
+            restore_sale,
+            get_deleted_sales,
             create_sale_item,
             get_sale_items,
             get_product_batches,
@@ -8106,12 +8643,16 @@ pub fn run() {
             get_expense,
             update_expense,
             delete_expense,
+            restore_expense,
+            get_deleted_expenses,
             init_employees_table,
             create_employee,
             get_employees,
             get_employee,
             update_employee,
             delete_employee,
+            restore_employee,
+            get_deleted_employees,
             init_salaries_table,
             create_salary,
             get_salaries,
@@ -8119,6 +8660,8 @@ pub fn run() {
             get_salary,
             update_salary,
             delete_salary,
+            restore_salary,
+            get_deleted_salaries,
             init_deductions_table,
             create_deduction,
             get_deductions,
@@ -8127,6 +8670,8 @@ pub fn run() {
             get_deduction,
             update_deduction,
             delete_deduction,
+            restore_deduction,
+            get_deleted_deductions,
             init_company_settings_table,
             get_company_settings,
             update_company_settings,
@@ -8137,6 +8682,8 @@ pub fn run() {
             get_account,
             update_account,
             delete_account,
+            restore_account,
+            get_deleted_accounts,
             deposit_account,
             withdraw_account,
             get_account_transactions,
@@ -8170,14 +8717,24 @@ pub fn run() {
             update_purchase_payment,
             delete_purchase_payment,
             get_machine_id,
+            diagnose_machine_id,
             store_license_key,
             get_license_key,
             validate_license_key,
+            get_license_status,
             hash_password,
             verify_password,
             store_puter_credentials,
-            get_puter_credentials
-        ])
+            get_puter_credentials,
+            get_server_config,
+            update_server_config,
+            get_server_access,
+            get_webhooks,
+            create_webhook,
+            update_webhook,
+            delete_webhook,
+            get_webhook_deliveries
+        ]))
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
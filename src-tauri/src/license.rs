@@ -1,127 +1,286 @@
-use aes_gcm::{
-    aead::{Aead, KeyInit},
-    Aes256Gcm, Nonce,
+use crate::permissions::{self, Access, AccessError, Capability};
+use chrono::{NaiveDate, TimeDelta};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+pub use shafaf_license::{
+    diagnose_machine, generate_machine_id, public_key, validate_license_key, License, LicenseInfo, LicenseStatus, Machine,
+    MachineDiagnosis, Module,
 };
-use sha2::{Digest, Sha256};
-use sysinfo::System;
-
-// Secret key derived from app identifier
-// In production, this should be obfuscated or derived from app metadata
-const SECRET_KEY_BASE: &str = "com.sulaiman.financeapp.license.secret.2024";
-const SALT: &str = "finance-app-salt-2024";
-
-/// Derive encryption key from secret base
-fn derive_key() -> [u8; 32] {
-    let mut hasher = Sha256::new();
-    hasher.update(SECRET_KEY_BASE.as_bytes());
-    hasher.update(SALT.as_bytes());
-    let hash = hasher.finalize();
-    let mut key = [0u8; 32];
-    key.copy_from_slice(&hash[..32]);
-    key
-}
 
-/// Generate a unique machine ID based on hardware information
-pub fn generate_machine_id() -> String {
-    let mut system = System::new();
-    system.refresh_all();
+/// Days before expiry the app starts warning about it
+const EXPIRY_WARNING_DAYS: i64 = 30;
 
-    let mut components = Vec::new();
+/// Days after expiry everything keeps working, with a warning, before the app turns read-only
+const GRACE_DAYS: i64 = 14;
 
-    // Get CPU information
-    if let Some(cpu) = system.cpus().first() {
-        components.push(format!("cpu:{}", cpu.brand()));
-    }
+/// Commands of a module that other modules depend on, so every edition has them
+const SHARED_COMMANDS: &[&str] = &["get_accounts", "get_account"];
 
-    // Get system hostname (if available)
-    if let Some(hostname) = System::host_name() {
-        components.push(format!("host:{}", hostname));
+/// Module a command belongs to, if it isn't part of every edition
+fn module_of_command(command: &str) -> Option<Module> {
+    if command.contains("exchange_rate") || command == "get_account_balance_by_currency" {
+        return Some(Module::MultiCurrency);
     }
-
-    // Get system name and kernel version (if available)
-    if let Some(name) = System::name() {
-        components.push(format!("sys:{}", name));
+    if SHARED_COMMANDS.contains(&command) {
+        return None;
     }
-    
-    if let Some(kernel) = System::kernel_version() {
-        components.push(format!("kernel:{}", kernel));
+    match permissions::command_access(command)? {
+        Access::Requires(Capability::HrRead | Capability::HrWrite) => Some(Module::Payroll),
+        Access::Requires(Capability::AccountingRead | Capability::AccountingWrite) => Some(Module::Accounting),
+        _ => None,
     }
+}
 
-    // Get total memory (as a stable identifier)
-    components.push(format!("mem:{}", system.total_memory()));
+fn keyring_entry() -> Result<keyring::Entry, String> {
+    keyring::Entry::new("finance_app", "license_key").map_err(|e| format!("Failed to create keyring entry: {}", e))
+}
 
-    // Get number of CPUs (as additional identifier)
-    components.push(format!("cpu_count:{}", system.cpus().len()));
+/// The license key saved in secure storage
+pub fn stored_license_key() -> Result<Option<String>, String> {
+    match keyring_entry()?.get_password() {
+        Ok(key) => Ok(Some(key)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(format!("Failed to get license key: {}", e)),
+    }
+}
+
+pub fn store_license_key(key: &str) -> Result<(), String> {
+    keyring_entry()?
+        .set_password(key)
+        .map_err(|e| format!("Failed to store license key: {}", e))
+}
+
+/// How far the app may be used under the current license
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LicenseMode {
+    /// No usable license; only setup, login and license commands work
+    Unlicensed,
+    Active,
+    /// Still active, but expiring within the warning period
+    Expiring,
+    /// Expired, but still fully usable until the grace period ends
+    Grace,
+    /// Past the grace period: data can be viewed and exported but not changed
+    ReadOnly,
+}
+
+/// What the license allows today; polled by the frontend
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LicenseState {
+    pub mode: LicenseMode,
+    pub license: Option<License>,
+    pub modules: Vec<Module>,
+    /// Days until the license expires; negative once it has
+    pub days_left: Option<i64>,
+    /// Last day of the grace period, after which the app is read-only
+    pub grace_ends: Option<NaiveDate>,
+    /// Message to show the user, when there is something to warn about
+    pub warning: Option<String>,
+}
+
+impl LicenseState {
+    /// State of a checked license (or of none at all) on `today`
+    pub fn new(info: Option<&LicenseInfo>, today: NaiveDate) -> Self {
+        let license = info
+            .filter(|info| matches!(info.status, LicenseStatus::Valid | LicenseStatus::Expired))
+            .and_then(|info| info.license.clone());
+        let Some(license) = license else {
+            let reason = info.and_then(|info| info.reason.clone());
+            return LicenseState {
+                mode: LicenseMode::Unlicensed,
+                license: None,
+                modules: Vec::new(),
+                days_left: None,
+                grace_ends: None,
+                warning: Some(reason.unwrap_or_else(|| "No license has been entered".to_string())),
+            };
+        };
+
+        let days_left = license.expires_at.map(|expires_at| (expires_at - today).num_days());
+        let grace_ends = license.expires_at.map(|expires_at| expires_at + TimeDelta::days(GRACE_DAYS));
+        let (mode, warning) = match (days_left, grace_ends) {
+            (Some(days), Some(grace_ends)) if days < -GRACE_DAYS => (
+                LicenseMode::ReadOnly,
+                Some(format!("The license expired and the grace period ended on {}; renew it to make changes again", grace_ends)),
+            ),
+            (Some(days), Some(grace_ends)) if days < 0 => (
+                LicenseMode::Grace,
+                Some(format!("The license has expired; the app turns read-only after {}", grace_ends)),
+            ),
+            (Some(days), _) if days <= EXPIRY_WARNING_DAYS => {
+                (LicenseMode::Expiring, Some(format!("The license expires in {} day(s)", days)))
+            }
+            _ => (LicenseMode::Active, None),
+        };
+        LicenseState {
+            mode,
+            modules: license.modules().into_iter().collect(),
+            license: Some(license),
+            days_left,
+            grace_ends,
+            warning,
+        }
+    }
+
+    /// Whether the license lets anyone call `command`
+    pub fn authorize(&self, command: &str) -> Result<(), AccessError> {
+        let refuse = |message: String| {
+            Err(AccessError::LicenseRestricted { command: command.to_string(), mode: self.mode, message })
+        };
+        // Finishing a forced password change or 2FA setup stays possible, or nobody could sign in to read
+        if self.mode == LicenseMode::ReadOnly
+            && permissions::changes_data(command)
+            && command != "change_password"
+            && !permissions::allowed_before_two_factor_setup(command)
+        {
+            return refuse("The license has expired; the app is read-only until it is renewed".to_string());
+        }
+        if matches!(permissions::command_access(command), None | Some(Access::Public)) {
+            return Ok(());
+        }
+        if self.mode == LicenseMode::Unlicensed {
+            return refuse("A valid license is required".to_string());
+        }
+        match module_of_command(command) {
+            Some(module) if !self.modules.contains(&module) => {
+                refuse(format!("The {} module isn't included in this license", module.name()))
+            }
+            _ => Ok(()),
+        }
+    }
 
-    // Combine all components and hash
-    let combined = components.join("|");
-    let mut hasher = Sha256::new();
-    hasher.update(combined.as_bytes());
-    let hash = hasher.finalize();
-    
-    // Return first 32 characters of hex-encoded hash
-    hex::encode(&hash[..16])
+    /// Whether one more user may be made active when `active_users` already are
+    pub fn check_seat(&self, active_users: i64) -> Result<(), String> {
+        match self.license.as_ref().and_then(|license| license.seats) {
+            Some(seats) if active_users >= i64::from(seats) => Err(format!(
+                "The license allows {} active user(s); disable another user first",
+                seats
+            )),
+            _ => Ok(()),
+        }
+    }
 }
 
-/// Encrypt machine ID using AES-256-GCM with deterministic nonce
-/// The nonce is derived from the machine ID to ensure consistent encryption
-pub fn encrypt_machine_id(machine_id: &str) -> Result<String, String> {
-    let key = derive_key();
-    let cipher = Aes256Gcm::new(&key.into());
-    
-    // Derive nonce from machine ID for deterministic encryption
-    // Use first 12 bytes of SHA256 hash of machine_id as nonce
-    let mut hasher = Sha256::new();
-    hasher.update(machine_id.as_bytes());
-    hasher.update(SALT.as_bytes()); // Add salt for nonce derivation
-    let nonce_hash = hasher.finalize();
-    let nonce = Nonce::from_slice(&nonce_hash[..12]);
-    
-    // Encrypt the machine ID
-    let ciphertext = cipher
-        .encrypt(nonce, machine_id.as_bytes())
-        .map_err(|e| format!("Encryption error: {}", e))?;
-    
-    // Combine nonce and ciphertext, then encode as hex
-    let mut combined = nonce.to_vec();
-    combined.extend_from_slice(&ciphertext);
-    
-    Ok(hex::encode(combined))
+/// The license of this installation, as last checked
+#[derive(Default)]
+pub struct LicenseGate {
+    info: Mutex<Option<LicenseInfo>>,
 }
 
-/// Validate license key by encrypting current machine ID and comparing
-pub fn validate_license_key(entered_key: &str) -> Result<bool, String> {
-    // Get current machine ID
-    let machine_id = generate_machine_id();
-    
-    // Encrypt current machine ID
-    let encrypted = encrypt_machine_id(&machine_id)?;
-    
-    // Compare (case-insensitive)
-    Ok(encrypted.to_lowercase() == entered_key.to_lowercase())
+impl LicenseGate {
+    /// Check the license saved in secure storage
+    pub fn load() -> Self {
+        let gate = LicenseGate::default();
+        match stored_license_key().and_then(|key| key.map(|key| validate_license_key(&key)).transpose()) {
+            Ok(info) => gate.set(info),
+            Err(e) => eprintln!("⚠️ License: {}", e),
+        }
+        gate
+    }
+
+    pub fn set(&self, info: Option<LicenseInfo>) {
+        *self.info.lock().unwrap() = info;
+    }
+
+    pub fn state(&self, today: NaiveDate) -> LicenseState {
+        LicenseState::new(self.info.lock().unwrap().as_ref(), today)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    fn license(expires_at: Option<&str>) -> License {
+        License {
+            id: "lic-1".to_string(),
+            machine_id: "M2-u1a2b3c4d".to_string(),
+            customer: "Karimi Traders".to_string(),
+            edition: "full".to_string(),
+            features: vec!["accounting".to_string()],
+            issued_at: date("2026-01-01"),
+            expires_at: expires_at.map(date),
+            seats: None,
+        }
+    }
+
+    fn state(license: License, status: LicenseStatus, today: &str) -> LicenseState {
+        let info = LicenseInfo { valid: status == LicenseStatus::Valid, status, reason: None, license: Some(license) };
+        LicenseState::new(Some(&info), date(today))
+    }
+
     #[test]
-    fn test_machine_id_consistency() {
-        let id1 = generate_machine_id();
-        let id2 = generate_machine_id();
-        // Machine ID should be consistent within the same session
-        assert_eq!(id1, id2);
+    fn test_license_modes() {
+        let expiring = license(Some("2026-12-31"));
+        let status = LicenseStatus::Valid;
+        assert_eq!(state(expiring.clone(), status, "2026-06-01").mode, LicenseMode::Active);
+        let warned = state(expiring.clone(), status, "2026-12-01");
+        assert_eq!((warned.mode, warned.days_left), (LicenseMode::Expiring, Some(30)));
+        assert!(warned.warning.is_some());
+        let grace = state(expiring.clone(), LicenseStatus::Expired, "2027-01-14");
+        assert_eq!((grace.mode, grace.grace_ends), (LicenseMode::Grace, Some(date("2027-01-14"))));
+        assert_eq!(state(expiring, LicenseStatus::Expired, "2027-01-15").mode, LicenseMode::ReadOnly);
+        assert_eq!(state(license(None), status, "2040-01-01").mode, LicenseMode::Active);
+
+        let wrong_machine = LicenseInfo::rejected(LicenseStatus::WrongMachine, "elsewhere", Some(license(None)));
+        let unlicensed = LicenseState::new(Some(&wrong_machine), date("2026-06-01"));
+        assert_eq!((unlicensed.mode, unlicensed.warning.as_deref()), (LicenseMode::Unlicensed, Some("elsewhere")));
+        assert_eq!(LicenseState::new(None, date("2026-06-01")).mode, LicenseMode::Unlicensed);
+    }
+
+    #[test]
+    fn test_license_authorize() {
+        let restricted = |state: &LicenseState, command: &str| state.authorize(command).is_err();
+
+        let unlicensed = LicenseState::new(None, date("2026-06-01"));
+        assert!(!restricted(&unlicensed, "login_user"));
+        assert!(!restricted(&unlicensed, "store_license_key"));
+        assert!(restricted(&unlicensed, "get_customers"));
+
+        let read_only = state(license(Some("2026-01-31")), LicenseStatus::Expired, "2026-06-01");
+        assert!(!restricted(&read_only, "get_sales"));
+        assert!(!restricted(&read_only, "backup_database"));
+        assert!(restricted(&read_only, "create_sale"));
+        assert!(restricted(&read_only, "db_execute"));
+        for (command, _) in permissions::COMMANDS {
+            let required_to_sign_in =
+                *command == "change_password" || permissions::allowed_before_two_factor_setup(command);
+            if permissions::changes_data(command) && !required_to_sign_in {
+                assert!(restricted(&read_only, command), "{} is allowed while read-only", command);
+            } else {
+                assert!(!restricted(&read_only, command), "{} is refused while read-only", command);
+            }
+        }
+        assert!(restricted(&read_only, "update_company_settings"));
+        assert!(restricted(&read_only, "db_sync"));
+        assert!(!restricted(&read_only, "confirm_two_factor_enrollment"));
+        assert!(matches!(
+            read_only.authorize("create_sale"),
+            Err(AccessError::LicenseRestricted { mode: LicenseMode::ReadOnly, .. })
+        ));
+
+        let basic = License { edition: "basic".to_string(), features: vec!["payroll".to_string()], ..license(None) };
+        let basic = state(basic, LicenseStatus::Valid, "2026-06-01");
+        assert_eq!(basic.modules, vec![Module::Payroll]);
+        assert!(!restricted(&basic, "create_salary"));
+        assert!(!restricted(&basic, "get_accounts"));
+        assert!(restricted(&basic, "create_journal_entry"));
+        assert!(restricted(&basic, "create_exchange_rate"));
+        assert!(!restricted(&basic, "create_sale"));
     }
 
     #[test]
-    fn test_encryption_decryption() {
-        let machine_id = generate_machine_id();
-        let encrypted = encrypt_machine_id(&machine_id).unwrap();
-        
-        // Encrypt again and compare
-        let encrypted2 = encrypt_machine_id(&machine_id).unwrap();
-        // Note: Due to random nonce, encrypted values will differ
-        // But validation should work
-        assert!(validate_license_key(&encrypted).unwrap());
+    fn test_check_seat() {
+        let unlimited = state(license(None), LicenseStatus::Valid, "2026-06-01");
+        assert!(unlimited.check_seat(1000).is_ok());
+        let three = state(License { seats: Some(3), ..license(None) }, LicenseStatus::Valid, "2026-06-01");
+        assert!(three.check_seat(2).is_ok());
+        assert!(three.check_seat(3).is_err());
     }
 }
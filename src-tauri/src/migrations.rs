@@ -0,0 +1,812 @@
+use crate::db::Database;
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+/// A single schema change inside a migration
+pub enum Step {
+    /// Plain SQL statement(s), executed as a batch
+    Sql(&'static str),
+    /// Add a column only if the table doesn't have it yet (SQLite has no ADD COLUMN IF NOT EXISTS)
+    AddColumn {
+        table: &'static str,
+        column: &'static str,
+        definition: &'static str,
+    },
+}
+
+/// A numbered migration; versions must be strictly increasing
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub steps: &'static [Step],
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationReport {
+    pub from_version: i64,
+    pub to_version: i64,
+    pub applied: Vec<AppliedMigration>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchemaVersion {
+    pub current: i64,
+    pub latest: i64,
+    pub pending: Vec<AppliedMigration>,
+}
+
+/// All migrations, in order. Never edit a released migration - append a new one instead.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial_schema",
+        steps: &[Step::Sql(INITIAL_SCHEMA)],
+    },
+    Migration {
+        version: 2,
+        name: "backfill_legacy_columns",
+        steps: &[
+            Step::AddColumn { table: "currencies", column: "rate", definition: "REAL NOT NULL DEFAULT 1.0" },
+            Step::AddColumn { table: "units", column: "group_id", definition: "INTEGER REFERENCES unit_groups(id)" },
+            Step::AddColumn { table: "units", column: "ratio", definition: "REAL NOT NULL DEFAULT 1.0" },
+            Step::AddColumn { table: "units", column: "is_base", definition: "INTEGER NOT NULL DEFAULT 0" },
+            Step::AddColumn { table: "products", column: "image_path", definition: "TEXT" },
+            Step::AddColumn { table: "products", column: "bar_code", definition: "TEXT" },
+            Step::AddColumn { table: "purchases", column: "additional_cost", definition: "REAL NOT NULL DEFAULT 0" },
+            Step::AddColumn { table: "purchases", column: "currency_id", definition: "INTEGER" },
+            Step::AddColumn { table: "purchases", column: "batch_number", definition: "TEXT" },
+            Step::AddColumn { table: "purchase_items", column: "per_unit", definition: "REAL" },
+            Step::AddColumn { table: "purchase_items", column: "cost_price", definition: "REAL" },
+            Step::AddColumn { table: "purchase_items", column: "wholesale_price", definition: "REAL" },
+            Step::AddColumn { table: "purchase_items", column: "retail_price", definition: "REAL" },
+            Step::AddColumn { table: "purchase_items", column: "expiry_date", definition: "TEXT" },
+            Step::AddColumn { table: "purchase_payments", column: "account_id", definition: "INTEGER" },
+            Step::AddColumn { table: "sales", column: "additional_cost", definition: "REAL NOT NULL DEFAULT 0" },
+            Step::AddColumn { table: "sales", column: "currency_id", definition: "INTEGER" },
+            Step::AddColumn { table: "sales", column: "exchange_rate", definition: "REAL NOT NULL DEFAULT 1" },
+            Step::AddColumn { table: "sales", column: "base_amount", definition: "REAL NOT NULL DEFAULT 0" },
+            Step::AddColumn { table: "sale_items", column: "purchase_item_id", definition: "INTEGER" },
+            Step::AddColumn { table: "sale_items", column: "sale_type", definition: "TEXT" },
+            Step::AddColumn { table: "sale_payments", column: "account_id", definition: "INTEGER" },
+            Step::AddColumn { table: "sale_payments", column: "currency_id", definition: "INTEGER" },
+            Step::AddColumn { table: "sale_payments", column: "exchange_rate", definition: "REAL NOT NULL DEFAULT 1" },
+            Step::AddColumn { table: "sale_payments", column: "base_amount", definition: "REAL NOT NULL DEFAULT 0" },
+            // Old expenses tables used a free-text name; the type id stays nullable for those rows
+            Step::AddColumn { table: "expenses", column: "expense_type_id", definition: "INTEGER" },
+            Step::AddColumn { table: "expenses", column: "bill_no", definition: "TEXT" },
+            Step::AddColumn { table: "expenses", column: "description", definition: "TEXT" },
+            Step::AddColumn { table: "salaries", column: "deductions", definition: "REAL NOT NULL DEFAULT 0" },
+            Step::AddColumn { table: "deductions", column: "year", definition: "INTEGER NOT NULL DEFAULT 1403" },
+            Step::AddColumn { table: "deductions", column: "month", definition: "TEXT NOT NULL DEFAULT 'حمل'" },
+            Step::AddColumn { table: "company_settings", column: "font", definition: "TEXT" },
+            Step::AddColumn { table: "accounts", column: "coa_category_id", definition: "INTEGER" },
+            // SQLite cannot add a UNIQUE column, so uniqueness comes from an index below
+            Step::AddColumn { table: "accounts", column: "account_code", definition: "TEXT" },
+            Step::AddColumn { table: "accounts", column: "account_type", definition: "TEXT" },
+            Step::AddColumn { table: "accounts", column: "is_active", definition: "INTEGER NOT NULL DEFAULT 1" },
+            Step::Sql("CREATE UNIQUE INDEX IF NOT EXISTS idx_accounts_account_code ON accounts(account_code)"),
+        ],
+    },
+    Migration {
+        version: 3,
+        name: "default_company_settings",
+        steps: &[Step::Sql(
+            "INSERT INTO company_settings (name) SELECT 'شرکت' WHERE NOT EXISTS (SELECT 1 FROM company_settings)",
+        )],
+    },
+    Migration {
+        version: 4,
+        name: "users_table",
+        steps: &[Step::Sql(
+            "CREATE TABLE IF NOT EXISTS users (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                username TEXT NOT NULL UNIQUE,
+                email TEXT NOT NULL UNIQUE,
+                password_hash TEXT NOT NULL,
+                full_name TEXT,
+                phone TEXT,
+                role TEXT NOT NULL DEFAULT 'user',
+                is_active INTEGER NOT NULL DEFAULT 1,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+        )],
+    },
+    Migration {
+        version: 5,
+        name: "surreal_export_progress",
+        steps: &[Step::Sql(
+            "CREATE TABLE IF NOT EXISTS surreal_export (
+                table_name TEXT PRIMARY KEY,
+                last_id INTEGER NOT NULL DEFAULT 0,
+                transferred INTEGER NOT NULL DEFAULT 0,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+        )],
+    },
+    Migration {
+        version: 6,
+        name: "roles",
+        steps: &[Step::Sql(
+            "CREATE TABLE IF NOT EXISTS roles (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE,
+                description TEXT,
+                capabilities TEXT NOT NULL DEFAULT '[]',
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            );
+            -- Users from before roles were enforced: the first one administers, the rest only read
+            UPDATE users SET role = 'admin'
+                WHERE id = (SELECT MIN(id) FROM users)
+                AND NOT EXISTS (SELECT 1 FROM users WHERE role = 'admin');
+            UPDATE users SET role = 'read_only' WHERE role = 'user';",
+        )],
+    },
+    Migration {
+        version: 7,
+        name: "login_hardening",
+        steps: &[
+            Step::AddColumn { table: "users", column: "failed_logins", definition: "INTEGER NOT NULL DEFAULT 0" },
+            Step::AddColumn { table: "users", column: "locked_until", definition: "DATETIME" },
+            Step::AddColumn { table: "users", column: "must_change_password", definition: "INTEGER NOT NULL DEFAULT 0" },
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS password_history (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                    password_hash TEXT NOT NULL,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                );
+                CREATE INDEX IF NOT EXISTS idx_password_history_user ON password_history(user_id);
+                CREATE TABLE IF NOT EXISTS app_settings (
+                    key TEXT PRIMARY KEY,
+                    value TEXT NOT NULL,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                );",
+            ),
+        ],
+    },
+    Migration {
+        version: 8,
+        name: "two_factor",
+        steps: &[
+            Step::AddColumn { table: "users", column: "totp_secret", definition: "TEXT" },
+            Step::AddColumn { table: "users", column: "totp_enabled", definition: "INTEGER NOT NULL DEFAULT 0" },
+            Step::AddColumn { table: "users", column: "totp_last_step", definition: "INTEGER" },
+            Step::AddColumn { table: "users", column: "recovery_codes", definition: "TEXT NOT NULL DEFAULT '[]'" },
+        ],
+    },
+    Migration {
+        version: 9,
+        name: "audit_log",
+        steps: &[Step::Sql(
+            "CREATE TABLE IF NOT EXISTS audit_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                user_id INTEGER,
+                username TEXT,
+                command TEXT,
+                entity TEXT NOT NULL,
+                entity_id TEXT,
+                action TEXT NOT NULL,
+                old_data TEXT,
+                new_data TEXT,
+                prev_hash TEXT,
+                hash TEXT
+            );
+            CREATE INDEX IF NOT EXISTS idx_audit_log_entity ON audit_log(entity, entity_id);
+            CREATE INDEX IF NOT EXISTS idx_audit_log_user ON audit_log(user_id);
+            CREATE INDEX IF NOT EXISTS idx_audit_log_created_at ON audit_log(created_at);
+            -- Entries can only be hashed once, never changed or removed
+            CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log WHEN OLD.hash IS NOT NULL
+            BEGIN
+                SELECT RAISE(ABORT, 'Audit log entries can''t be changed');
+            END;
+            CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+            BEGIN
+                SELECT RAISE(ABORT, 'Audit log entries can''t be deleted');
+            END;",
+        )],
+    },
+    Migration {
+        version: 10,
+        name: "soft_delete",
+        steps: &[
+            Step::AddColumn { table: "customers", column: "deleted_at", definition: "DATETIME" },
+            Step::AddColumn { table: "customers", column: "deleted_by", definition: "INTEGER" },
+            Step::AddColumn { table: "suppliers", column: "deleted_at", definition: "DATETIME" },
+            Step::AddColumn { table: "suppliers", column: "deleted_by", definition: "INTEGER" },
+            Step::AddColumn { table: "products", column: "deleted_at", definition: "DATETIME" },
+            Step::AddColumn { table: "products", column: "deleted_by", definition: "INTEGER" },
+            Step::AddColumn { table: "employees", column: "deleted_at", definition: "DATETIME" },
+            Step::AddColumn { table: "employees", column: "deleted_by", definition: "INTEGER" },
+            Step::AddColumn { table: "accounts", column: "deleted_at", definition: "DATETIME" },
+            Step::AddColumn { table: "accounts", column: "deleted_by", definition: "INTEGER" },
+            Step::AddColumn { table: "sales", column: "deleted_at", definition: "DATETIME" },
+            Step::AddColumn { table: "sales", column: "deleted_by", definition: "INTEGER" },
+            Step::AddColumn { table: "purchases", column: "deleted_at", definition: "DATETIME" },
+            Step::AddColumn { table: "purchases", column: "deleted_by", definition: "INTEGER" },
+            Step::AddColumn { table: "expenses", column: "deleted_at", definition: "DATETIME" },
+            Step::AddColumn { table: "expenses", column: "deleted_by", definition: "INTEGER" },
+            Step::AddColumn { table: "salaries", column: "deleted_at", definition: "DATETIME" },
+            Step::AddColumn { table: "salaries", column: "deleted_by", definition: "INTEGER" },
+            Step::AddColumn { table: "deductions", column: "deleted_at", definition: "DATETIME" },
+            Step::AddColumn { table: "deductions", column: "deleted_by", definition: "INTEGER" },
+            Step::Sql(
+                "CREATE INDEX IF NOT EXISTS idx_customers_deleted_at ON customers(deleted_at);
+                CREATE INDEX IF NOT EXISTS idx_suppliers_deleted_at ON suppliers(deleted_at);
+                CREATE INDEX IF NOT EXISTS idx_products_deleted_at ON products(deleted_at);
+                CREATE INDEX IF NOT EXISTS idx_employees_deleted_at ON employees(deleted_at);
+                CREATE INDEX IF NOT EXISTS idx_accounts_deleted_at ON accounts(deleted_at);
+                CREATE INDEX IF NOT EXISTS idx_sales_deleted_at ON sales(deleted_at);
+                CREATE INDEX IF NOT EXISTS idx_purchases_deleted_at ON purchases(deleted_at);
+                CREATE INDEX IF NOT EXISTS idx_expenses_deleted_at ON expenses(deleted_at);
+                CREATE INDEX IF NOT EXISTS idx_salaries_deleted_at ON salaries(deleted_at);
+                CREATE INDEX IF NOT EXISTS idx_deductions_deleted_at ON deductions(deleted_at);",
+            ),
+        ],
+    },
+    Migration {
+        version: 11,
+        name: "api_tokens",
+        steps: &[Step::Sql(
+            "CREATE TABLE IF NOT EXISTS api_tokens (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                name TEXT NOT NULL,
+                prefix TEXT NOT NULL,
+                token_hash TEXT NOT NULL UNIQUE,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                last_used_at DATETIME,
+                expires_at DATETIME
+            );
+            CREATE INDEX IF NOT EXISTS idx_api_tokens_user ON api_tokens(user_id);",
+        )],
+    },
+    Migration {
+        version: 12,
+        name: "webhooks",
+        steps: &[Step::Sql(
+            "CREATE TABLE IF NOT EXISTS webhooks (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                url TEXT NOT NULL,
+                secret TEXT NOT NULL,
+                events TEXT NOT NULL DEFAULT '*',
+                is_active INTEGER NOT NULL DEFAULT 1,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            );
+            -- Events are written here in the same transaction as the change they describe
+            CREATE TABLE IF NOT EXISTS event_outbox (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                event TEXT NOT NULL,
+                payload TEXT NOT NULL,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                dispatched_at DATETIME
+            );
+            CREATE INDEX IF NOT EXISTS idx_event_outbox_dispatched_at ON event_outbox(dispatched_at);
+            CREATE TABLE IF NOT EXISTS webhook_deliveries (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                webhook_id INTEGER NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+                event_id INTEGER NOT NULL REFERENCES event_outbox(id),
+                status TEXT NOT NULL DEFAULT 'pending',
+                attempts INTEGER NOT NULL DEFAULT 0,
+                next_attempt_at DATETIME,
+                last_status INTEGER,
+                last_error TEXT,
+                delivered_at DATETIME,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            );
+            CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_due ON webhook_deliveries(status, next_attempt_at);
+            CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_webhook ON webhook_deliveries(webhook_id);",
+        )],
+    },
+];
+
+const INITIAL_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS currencies (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL UNIQUE,
+        base INTEGER NOT NULL DEFAULT 0,
+        rate REAL NOT NULL DEFAULT 1.0,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    );
+
+    CREATE TABLE IF NOT EXISTS suppliers (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        full_name TEXT NOT NULL,
+        phone TEXT NOT NULL,
+        address TEXT NOT NULL,
+        email TEXT,
+        notes TEXT,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    );
+
+    CREATE TABLE IF NOT EXISTS customers (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        full_name TEXT NOT NULL,
+        phone TEXT NOT NULL,
+        address TEXT NOT NULL,
+        email TEXT,
+        notes TEXT,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    );
+
+    CREATE TABLE IF NOT EXISTS unit_groups (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL UNIQUE,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    );
+
+    CREATE TABLE IF NOT EXISTS units (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL UNIQUE,
+        group_id INTEGER REFERENCES unit_groups(id),
+        ratio REAL NOT NULL DEFAULT 1.0,
+        is_base INTEGER NOT NULL DEFAULT 0,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    );
+
+    CREATE TABLE IF NOT EXISTS products (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        description TEXT,
+        price REAL,
+        currency_id INTEGER,
+        supplier_id INTEGER,
+        stock_quantity REAL,
+        unit TEXT,
+        image_path TEXT,
+        bar_code TEXT,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (currency_id) REFERENCES currencies(id),
+        FOREIGN KEY (supplier_id) REFERENCES suppliers(id)
+    );
+
+    CREATE TABLE IF NOT EXISTS purchases (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        supplier_id INTEGER NOT NULL,
+        date TEXT NOT NULL,
+        notes TEXT,
+        currency_id INTEGER,
+        total_amount REAL NOT NULL DEFAULT 0,
+        additional_cost REAL NOT NULL DEFAULT 0,
+        batch_number TEXT,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (supplier_id) REFERENCES suppliers(id),
+        FOREIGN KEY (currency_id) REFERENCES currencies(id)
+    );
+
+    CREATE TABLE IF NOT EXISTS purchase_items (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        purchase_id INTEGER NOT NULL,
+        product_id INTEGER NOT NULL,
+        unit_id INTEGER NOT NULL,
+        per_price REAL NOT NULL,
+        amount REAL NOT NULL,
+        total REAL NOT NULL,
+        per_unit REAL,
+        cost_price REAL,
+        wholesale_price REAL,
+        retail_price REAL,
+        expiry_date TEXT,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (purchase_id) REFERENCES purchases(id) ON DELETE CASCADE,
+        FOREIGN KEY (product_id) REFERENCES products(id),
+        FOREIGN KEY (unit_id) REFERENCES units(id)
+    );
+
+    CREATE TABLE IF NOT EXISTS purchase_additional_costs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        purchase_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        amount REAL NOT NULL,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (purchase_id) REFERENCES purchases(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS purchase_payments (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        purchase_id INTEGER NOT NULL,
+        account_id INTEGER,
+        amount REAL NOT NULL,
+        currency TEXT NOT NULL,
+        rate REAL NOT NULL,
+        total REAL NOT NULL,
+        date TEXT NOT NULL,
+        notes TEXT,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (purchase_id) REFERENCES purchases(id) ON DELETE CASCADE,
+        FOREIGN KEY (account_id) REFERENCES accounts(id) ON DELETE SET NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS sales (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        customer_id INTEGER NOT NULL,
+        date TEXT NOT NULL,
+        notes TEXT,
+        currency_id INTEGER,
+        exchange_rate REAL NOT NULL DEFAULT 1,
+        total_amount REAL NOT NULL DEFAULT 0,
+        base_amount REAL NOT NULL DEFAULT 0,
+        paid_amount REAL NOT NULL DEFAULT 0,
+        additional_cost REAL NOT NULL DEFAULT 0,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (customer_id) REFERENCES customers(id),
+        FOREIGN KEY (currency_id) REFERENCES currencies(id)
+    );
+
+    CREATE TABLE IF NOT EXISTS sale_items (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        sale_id INTEGER NOT NULL,
+        product_id INTEGER NOT NULL,
+        unit_id INTEGER NOT NULL,
+        per_price REAL NOT NULL,
+        amount REAL NOT NULL,
+        total REAL NOT NULL,
+        purchase_item_id INTEGER,
+        sale_type TEXT,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (sale_id) REFERENCES sales(id) ON DELETE CASCADE,
+        FOREIGN KEY (product_id) REFERENCES products(id),
+        FOREIGN KEY (unit_id) REFERENCES units(id),
+        FOREIGN KEY (purchase_item_id) REFERENCES purchase_items(id)
+    );
+
+    CREATE TABLE IF NOT EXISTS sale_payments (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        sale_id INTEGER NOT NULL,
+        account_id INTEGER,
+        currency_id INTEGER,
+        exchange_rate REAL NOT NULL DEFAULT 1,
+        amount REAL NOT NULL,
+        base_amount REAL NOT NULL DEFAULT 0,
+        date TEXT NOT NULL,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (sale_id) REFERENCES sales(id) ON DELETE CASCADE,
+        FOREIGN KEY (account_id) REFERENCES accounts(id),
+        FOREIGN KEY (currency_id) REFERENCES currencies(id)
+    );
+
+    CREATE TABLE IF NOT EXISTS sale_additional_costs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        sale_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        amount REAL NOT NULL,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (sale_id) REFERENCES sales(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS expense_types (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL UNIQUE,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    );
+
+    CREATE TABLE IF NOT EXISTS expenses (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        expense_type_id INTEGER NOT NULL,
+        amount REAL NOT NULL,
+        currency TEXT NOT NULL,
+        rate REAL NOT NULL DEFAULT 1.0,
+        total REAL NOT NULL,
+        date TEXT NOT NULL,
+        bill_no TEXT,
+        description TEXT,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (expense_type_id) REFERENCES expense_types(id)
+    );
+
+    CREATE TABLE IF NOT EXISTS employees (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        full_name TEXT NOT NULL,
+        phone TEXT NOT NULL,
+        email TEXT,
+        address TEXT NOT NULL,
+        position TEXT,
+        hire_date TEXT,
+        base_salary REAL,
+        photo_path TEXT,
+        notes TEXT,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    );
+
+    CREATE TABLE IF NOT EXISTS salaries (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        employee_id INTEGER NOT NULL,
+        year INTEGER NOT NULL,
+        month TEXT NOT NULL,
+        amount REAL NOT NULL,
+        deductions REAL NOT NULL DEFAULT 0,
+        notes TEXT,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (employee_id) REFERENCES employees(id) ON DELETE CASCADE,
+        UNIQUE(employee_id, year, month)
+    );
+
+    CREATE TABLE IF NOT EXISTS deductions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        employee_id INTEGER NOT NULL,
+        year INTEGER NOT NULL DEFAULT 1403,
+        month TEXT NOT NULL DEFAULT 'حمل',
+        currency TEXT NOT NULL,
+        rate REAL NOT NULL DEFAULT 1.0,
+        amount REAL NOT NULL,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (employee_id) REFERENCES employees(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS company_settings (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        logo TEXT,
+        phone TEXT,
+        address TEXT,
+        font TEXT,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    );
+
+    CREATE TABLE IF NOT EXISTS coa_categories (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        parent_id INTEGER,
+        name TEXT NOT NULL,
+        code TEXT NOT NULL UNIQUE,
+        category_type TEXT NOT NULL,
+        level INTEGER NOT NULL DEFAULT 0,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (parent_id) REFERENCES coa_categories(id) ON DELETE SET NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS accounts (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        currency_id INTEGER,
+        coa_category_id INTEGER,
+        account_code TEXT UNIQUE,
+        account_type TEXT,
+        initial_balance REAL NOT NULL DEFAULT 0,
+        current_balance REAL NOT NULL DEFAULT 0,
+        is_active INTEGER NOT NULL DEFAULT 1,
+        notes TEXT,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (currency_id) REFERENCES currencies(id),
+        FOREIGN KEY (coa_category_id) REFERENCES coa_categories(id)
+    );
+
+    CREATE TABLE IF NOT EXISTS account_transactions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        account_id INTEGER NOT NULL,
+        transaction_type TEXT NOT NULL,
+        amount REAL NOT NULL,
+        currency TEXT NOT NULL,
+        rate REAL NOT NULL,
+        total REAL NOT NULL,
+        transaction_date TEXT NOT NULL,
+        is_full INTEGER NOT NULL DEFAULT 0,
+        notes TEXT,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (account_id) REFERENCES accounts(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS account_currency_balances (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        account_id INTEGER NOT NULL,
+        currency_id INTEGER NOT NULL,
+        balance REAL NOT NULL DEFAULT 0,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (account_id) REFERENCES accounts(id) ON DELETE CASCADE,
+        FOREIGN KEY (currency_id) REFERENCES currencies(id),
+        UNIQUE(account_id, currency_id)
+    );
+
+    CREATE TABLE IF NOT EXISTS journal_entries (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        entry_number TEXT NOT NULL UNIQUE,
+        entry_date TEXT NOT NULL,
+        description TEXT,
+        reference_type TEXT,
+        reference_id INTEGER,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    );
+
+    CREATE TABLE IF NOT EXISTS journal_entry_lines (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        journal_entry_id INTEGER NOT NULL,
+        account_id INTEGER NOT NULL,
+        currency_id INTEGER NOT NULL,
+        debit_amount REAL NOT NULL DEFAULT 0,
+        credit_amount REAL NOT NULL DEFAULT 0,
+        exchange_rate REAL NOT NULL DEFAULT 1,
+        base_amount REAL NOT NULL DEFAULT 0,
+        description TEXT,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (journal_entry_id) REFERENCES journal_entries(id) ON DELETE CASCADE,
+        FOREIGN KEY (account_id) REFERENCES accounts(id),
+        FOREIGN KEY (currency_id) REFERENCES currencies(id)
+    );
+
+    CREATE TABLE IF NOT EXISTS currency_exchange_rates (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        from_currency_id INTEGER NOT NULL,
+        to_currency_id INTEGER NOT NULL,
+        rate REAL NOT NULL,
+        date TEXT NOT NULL,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (from_currency_id) REFERENCES currencies(id),
+        FOREIGN KEY (to_currency_id) REFERENCES currencies(id)
+    );
+";
+
+const CREATE_MIGRATIONS_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS schema_migrations (
+        version INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        applied_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    )
+";
+
+/// Latest schema version known to this build
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+fn current_version_conn(conn: &Connection) -> Result<i64> {
+    conn.execute(CREATE_MIGRATIONS_TABLE, [])?;
+    let version = conn.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_migrations", [], |row| row.get(0))?;
+    Ok(version)
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let columns = stmt.query_map([], |row| row.get::<_, String>(1))?;
+    for name in columns {
+        if name? == column {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn apply_step(conn: &Connection, step: &Step) -> Result<()> {
+    match step {
+        Step::Sql(sql) => conn.execute_batch(sql)?,
+        Step::AddColumn { table, column, definition } => {
+            if !has_column(conn, table, column)? {
+                conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])
+                    .with_context(|| format!("Failed to add column {}.{}", table, column))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Get the current schema version and the migrations still pending
+pub fn schema_version(db: &Database) -> Result<SchemaVersion> {
+    let current = db.with_connection(|conn| current_version_conn(conn))?;
+    let pending = MIGRATIONS
+        .iter()
+        .filter(|m| m.version > current)
+        .map(|m| AppliedMigration { version: m.version, name: m.name.to_string() })
+        .collect();
+
+    Ok(SchemaVersion {
+        current,
+        latest: latest_version(),
+        pending,
+    })
+}
+
+/// Run every pending migration inside a single transaction.
+/// If any step fails nothing is recorded and the schema is left untouched.
+pub fn run_pending(db: &Database) -> Result<MigrationReport> {
+    db.with_connection(|conn| {
+        let tx = conn.transaction()?;
+        let from_version = current_version_conn(&tx)?;
+        let mut applied = Vec::new();
+
+        for migration in MIGRATIONS.iter().filter(|m| m.version > from_version) {
+            for step in migration.steps {
+                apply_step(&tx, step)
+                    .with_context(|| format!("Migration {} ({}) failed", migration.version, migration.name))?;
+            }
+            tx.execute(
+                "INSERT INTO schema_migrations (version, name) VALUES (?, ?)",
+                rusqlite::params![migration.version, migration.name],
+            )?;
+            applied.push(AppliedMigration { version: migration.version, name: migration.name.to_string() });
+        }
+
+        tx.commit()?;
+
+        Ok(MigrationReport {
+            from_version,
+            to_version: from_version.max(latest_version()),
+            applied,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn memory_db() -> Database {
+        let db = Database::new(PathBuf::from(":memory:"));
+        db.open().unwrap();
+        db
+    }
+
+    #[test]
+    fn test_versions_are_increasing() {
+        for pair in MIGRATIONS.windows(2) {
+            assert!(pair[0].version < pair[1].version);
+        }
+    }
+
+    #[test]
+    fn test_fresh_database_migrates_to_latest() {
+        let db = memory_db();
+        let report = run_pending(&db).unwrap();
+        assert_eq!(report.from_version, 0);
+        assert_eq!(report.to_version, latest_version());
+        assert_eq!(report.applied.len(), MIGRATIONS.len());
+
+        // Running again is a no-op
+        let again = run_pending(&db).unwrap();
+        assert!(again.applied.is_empty());
+        assert!(schema_version(&db).unwrap().pending.is_empty());
+    }
+
+    #[test]
+    fn test_legacy_tables_get_missing_columns() {
+        let db = memory_db();
+        db.execute("CREATE TABLE currencies (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL UNIQUE, base INTEGER NOT NULL DEFAULT 0)", &[]).unwrap();
+        db.execute("CREATE TABLE accounts (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL, currency_id INTEGER, initial_balance REAL NOT NULL DEFAULT 0, current_balance REAL NOT NULL DEFAULT 0, notes TEXT)", &[]).unwrap();
+
+        run_pending(&db).unwrap();
+
+        assert!(db.with_connection(|conn| has_column(conn, "currencies", "rate")).unwrap());
+        assert!(db.with_connection(|conn| has_column(conn, "accounts", "account_code")).unwrap());
+    }
+
+    #[test]
+    fn test_failed_migration_is_rolled_back() {
+        let db = memory_db();
+        // A view can't be altered, so backfilling its columns fails after the tables were created
+        db.execute("CREATE VIEW expenses AS SELECT 1 AS id", &[]).unwrap();
+
+        assert!(run_pending(&db).is_err());
+        let version = schema_version(&db).unwrap();
+        assert_eq!(version.current, 0);
+        let tables = db
+            .query("SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'currencies'", &[], |row| row.get::<_, String>(0))
+            .unwrap();
+        assert!(tables.is_empty());
+    }
+}
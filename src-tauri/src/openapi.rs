@@ -0,0 +1,356 @@
+use crate::api_types::{ErrorBody, ListParams, PeriodParams, PuterCredentials, RpcCall, Summary, WithItems, API_PREFIX};
+use crate::permissions::Session;
+use crate::repository::{CustomerInput, ProductInput};
+use crate::{
+    Account, Customer, PaginatedResponse, Product, Purchase, PurchaseInput, PurchaseItem, Sale, SaleInput, SaleItem,
+};
+use axum::http::StatusCode;
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::JsonSchema;
+use serde_json::{json, Map, Value};
+
+/// Errors any REST API call can end in: bad token, role or license, unexpected failure, no database
+const API_ERRORS: &[u16] = &[401, 403, 500, 503];
+
+/// One operation, built up from its summary
+struct Operation(Map<String, Value>);
+
+impl Operation {
+    fn new(tag: &str, summary: &str) -> Self {
+        let mut operation = Map::new();
+        operation.insert("tags".into(), json!([tag]));
+        operation.insert("summary".into(), json!(summary));
+        operation.insert("responses".into(), json!({}));
+        Operation(operation)
+    }
+
+    /// A REST API operation allowed to whoever may run the Tauri command `command`
+    fn api(tag: &str, summary: &str, command: &str) -> Self {
+        Operation::new(tag, summary)
+            .describe(&format!("Needs the permissions of the `{}` command.", command))
+            .errors(API_ERRORS)
+    }
+
+    fn describe(mut self, description: &str) -> Self {
+        self.0.insert("description".into(), json!(description));
+        self
+    }
+
+    fn response(mut self, status: u16, response: Value) -> Self {
+        self.0["responses"][status.to_string()] = response;
+        self
+    }
+
+    fn ok(self, status: u16, schema: Value) -> Self {
+        let description = StatusCode::from_u16(status).ok().and_then(|s| s.canonical_reason()).unwrap_or("OK");
+        self.response(status, json!({ "description": description, "content": { "application/json": { "schema": schema } } }))
+    }
+
+    fn html(self) -> Self {
+        self.response(200, json!({ "description": "OK", "content": { "text/html": { "schema": { "type": "string" } } } }))
+    }
+
+    fn errors(mut self, statuses: &[u16]) -> Self {
+        for &status in statuses {
+            let description = StatusCode::from_u16(status).ok().and_then(|s| s.canonical_reason()).unwrap_or("Error");
+            let schema = json!({ "$ref": "#/components/schemas/ErrorBody" });
+            self = self.response(status, json!({ "description": description, "content": { "application/json": { "schema": schema } } }));
+        }
+        self
+    }
+
+    fn parameters(mut self, parameters: Vec<Value>) -> Self {
+        let list = self.0.entry("parameters").or_insert_with(|| json!([]));
+        list.as_array_mut().expect("parameters are a list").extend(parameters);
+        self.errors(&[400])
+    }
+
+    /// The `{id}` path parameter of a single record
+    fn id(self) -> Self {
+        let id = json!({ "name": "id", "in": "path", "required": true, "schema": { "type": "integer", "format": "int64" } });
+        self.parameters(vec![id]).errors(&[404])
+    }
+
+    fn body(mut self, schema: Value) -> Self {
+        let body = json!({ "required": true, "content": { "application/json": { "schema": schema } } });
+        self.0.insert("requestBody".into(), body);
+        self.errors(&[400])
+    }
+
+    fn security(mut self, security: Value) -> Self {
+        self.0.insert("security".into(), security);
+        self
+    }
+}
+
+/// Collects the operations and the schemas they refer to
+struct Document {
+    generator: SchemaGenerator,
+    paths: Map<String, Value>,
+}
+
+impl Document {
+    fn schema<T: JsonSchema>(&mut self) -> Value {
+        serde_json::to_value(self.generator.subschema_for::<T>()).unwrap_or_default()
+    }
+
+    /// Query parameters from the fields of `T`
+    fn query<T: JsonSchema>(&mut self) -> Vec<Value> {
+        let root = self.generator.root_schema_for::<T>();
+        let Some(object) = root.schema.object else {
+            return Vec::new();
+        };
+        object
+            .properties
+            .iter()
+            .map(|(name, schema)| {
+                json!({ "name": name, "in": "query", "required": object.required.contains(name), "schema": schema })
+            })
+            .collect()
+    }
+
+    /// Add `operation` under `path`, written the way axum routes are (`/sales/:id`)
+    fn add(&mut self, method: &str, path: &str, operation: Operation) {
+        let path = path.split('/').map(|part| match part.strip_prefix(':') {
+            Some(name) => format!("{{{}}}", name),
+            None => part.to_string(),
+        });
+        let path = path.collect::<Vec<_>>().join("/");
+        let item = self.paths.entry(path).or_insert_with(|| json!({}));
+        item[method] = Value::Object(operation.0);
+    }
+
+    fn api(&mut self, method: &str, path: &str, operation: Operation) {
+        self.add(method, &format!("{}{}", API_PREFIX, path), operation);
+    }
+}
+
+/// OpenAPI 3 document of every route the HTTP server serves, served at `/api/openapi.json`
+pub fn document() -> Value {
+    let mut doc = Document { generator: SchemaSettings::openapi3().into_generator(), paths: Map::new() };
+    doc.schema::<ErrorBody>();
+    let public = json!([]);
+    let secret = json!([{ "installSecret": [] }]);
+
+    // Pages and the app's own endpoints
+    doc.add("get", "/", Operation::new("app", "The ai.html page, or the app itself in LAN mode").html().security(public.clone()));
+    doc.add("get", "/ai.html", Operation::new("app", "The ai.html page").html().security(public.clone()));
+    let openapi = Operation::new("app", "This document").ok(200, json!({ "type": "object" })).security(public);
+    doc.add("get", "/api/openapi.json", openapi);
+    let credentials = doc.schema::<PuterCredentials>();
+    let stored = json!({ "type": "object", "properties": { "success": { "type": "boolean" } } });
+    let store = Operation::new("app", "Hand Puter credentials to the app")
+        .body(credentials.clone())
+        .ok(200, stored)
+        .errors(&[401, 500])
+        .security(secret.clone());
+    doc.add("post", "/api/store-credentials", store);
+    let get = Operation::new("app", "Puter credentials handed to the app").ok(200, credentials).errors(&[401, 404, 500]);
+    doc.add("get", "/api/get-credentials", get.security(secret));
+
+    // REST API
+    let session = doc.schema::<Session>();
+    let me = Operation::new("session", "The token's user and capabilities").ok(200, session).errors(&[401, 503]);
+    doc.api("get", "/me", me);
+
+    let list = doc.query::<ListParams>();
+    let customer = doc.schema::<Customer>();
+    let customer_input = doc.schema::<CustomerInput>();
+    let customers = doc.schema::<PaginatedResponse<Customer>>();
+    doc.api(
+        "get",
+        "/customers",
+        Operation::api("customers", "List customers", "get_customers").parameters(list.clone()).ok(200, customers),
+    );
+    doc.api(
+        "post",
+        "/customers",
+        Operation::api("customers", "Create a customer", "create_customer").body(customer_input.clone()).ok(201, customer.clone()),
+    );
+    doc.api("get", "/customers/:id", Operation::api("customers", "Get a customer", "get_customers").id().ok(200, customer.clone()));
+    doc.api(
+        "put",
+        "/customers/:id",
+        Operation::api("customers", "Update a customer", "update_customer").id().body(customer_input).ok(200, customer),
+    );
+
+    let product = doc.schema::<Product>();
+    let product_input = doc.schema::<ProductInput>();
+    let products = doc.schema::<PaginatedResponse<Product>>();
+    doc.api(
+        "get",
+        "/products",
+        Operation::api("products", "List products", "get_products").parameters(list.clone()).ok(200, products),
+    );
+    doc.api(
+        "post",
+        "/products",
+        Operation::api("products", "Create a product", "create_product").body(product_input.clone()).ok(201, product.clone()),
+    );
+    doc.api("get", "/products/:id", Operation::api("products", "Get a product", "get_products").id().ok(200, product.clone()));
+    doc.api(
+        "put",
+        "/products/:id",
+        Operation::api("products", "Update a product", "update_product").id().body(product_input).ok(200, product),
+    );
+
+    let sale = doc.schema::<Sale>();
+    let sales = doc.schema::<PaginatedResponse<Sale>>();
+    let sale_input = doc.schema::<SaleInput>();
+    let sale_with_items = doc.schema::<WithItems<Sale, SaleItem>>();
+    doc.api("get", "/sales", Operation::api("sales", "List sales", "get_sales").parameters(list.clone()).ok(200, sales));
+    doc.api("post", "/sales", Operation::api("sales", "Create a sale with its items", "create_sale").body(sale_input).ok(201, sale));
+    doc.api("get", "/sales/:id", Operation::api("sales", "Get a sale with its items", "get_sale").id().ok(200, sale_with_items));
+
+    let purchase = doc.schema::<Purchase>();
+    let purchases = doc.schema::<PaginatedResponse<Purchase>>();
+    let purchase_input = doc.schema::<PurchaseInput>();
+    let purchase_with_items = doc.schema::<WithItems<Purchase, PurchaseItem>>();
+    doc.api(
+        "get",
+        "/purchases",
+        Operation::api("purchases", "List purchases", "get_purchases").parameters(list).ok(200, purchases),
+    );
+    doc.api(
+        "post",
+        "/purchases",
+        Operation::api("purchases", "Create a purchase with its items", "create_purchase").body(purchase_input).ok(201, purchase),
+    );
+    doc.api(
+        "get",
+        "/purchases/:id",
+        Operation::api("purchases", "Get a purchase with its items", "get_purchase").id().ok(200, purchase_with_items),
+    );
+
+    let account = doc.schema::<Account>();
+    let accounts = doc.schema::<Vec<Account>>();
+    doc.api("get", "/accounts", Operation::api("accounts", "List accounts", "get_accounts").ok(200, accounts));
+    doc.api("get", "/accounts/:id", Operation::api("accounts", "Get an account", "get_account").id().ok(200, account));
+
+    let period = doc.query::<PeriodParams>();
+    let summary = doc.schema::<Summary>();
+    let report = Operation::api("reports", "Sales and purchase totals per currency", "get_sales")
+        .describe("Needs the permissions of both the `get_sales` and the `get_purchases` command.")
+        .parameters(period)
+        .ok(200, summary);
+    doc.api("get", "/reports/summary", report);
+
+    // LAN mode
+    let call = doc.schema::<RpcCall>();
+    let rpc = Operation::new("lan", "Run an app command from a browser on the network")
+        .describe(
+            "Only served in LAN mode, for the commands a cashier terminal needs. `login_user` and `verify_two_factor` \
+             set the session cookie, which every other command needs. \
+             The response is what the command returns.",
+        )
+        .body(call)
+        .ok(200, json!({}))
+        .errors(&[401, 403, 404, 500, 503])
+        .security(json!([{ "lanSession": [] }]));
+    doc.add("post", "/api/rpc", rpc);
+
+    let schemas = doc.generator.take_definitions();
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Shafaf HTTP API",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "REST API of the Shafaf desktop app. Errors always have the body `{ \"error\": { \"code\", \"message\", \"details\" } }`.",
+        },
+        "servers": [{ "url": "/" }],
+        "security": [{ "apiToken": [] }],
+        "paths": doc.paths,
+        "components": {
+            "schemas": schemas,
+            "securitySchemes": {
+                "apiToken": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "A personal API token (`shf_...`); it acts as its user, with that user's role",
+                },
+                "lanSession": {
+                    "type": "apiKey",
+                    "in": "cookie",
+                    "name": crate::server_config::SESSION_COOKIE,
+                    "description": "Session of a browser logged in through `/api/rpc` in LAN mode",
+                },
+                "installSecret": {
+                    "type": "apiKey",
+                    "in": "header",
+                    "name": crate::server_config::SECRET_HEADER,
+                    "description": "Per-install secret, only known to the app",
+                },
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    /// `(method, path)` of every `.route(...)` in `source`, paths prefixed with `prefix`
+    fn routes(source: &str, prefix: &str) -> BTreeSet<(String, String)> {
+        let mut routes = BTreeSet::new();
+        for line in source.lines().map(str::trim).filter(|line| line.starts_with(".route(\"")) {
+            let rest = &line[".route(\"".len()..];
+            let (path, handlers) = rest.split_once('"').unwrap();
+            for method in ["get", "post", "put", "delete", "patch"] {
+                let called = handlers.contains(&format!(" {}(", method)) || handlers.contains(&format!(".{}(", method));
+                if called {
+                    let path = format!("{}{}", prefix, path).replace(":id", "{id}");
+                    routes.insert((method.to_string(), path));
+                }
+            }
+        }
+        routes
+    }
+
+    fn documented(document: &Value) -> BTreeSet<(String, String)> {
+        let paths = document["paths"].as_object().unwrap();
+        paths
+            .iter()
+            .flat_map(|(path, item)| item.as_object().unwrap().keys().map(move |method| (method.clone(), path.clone())))
+            .collect()
+    }
+
+    fn refs<'a>(value: &'a Value, found: &mut Vec<&'a str>) {
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::String(target)) = map.get("$ref") {
+                    found.push(target);
+                }
+                map.values().for_each(|value| refs(value, found));
+            }
+            Value::Array(items) => items.iter().for_each(|value| refs(value, found)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_every_route_is_documented() {
+        let document = document();
+        let mut expected = routes(include_str!("server.rs"), "");
+        expected.extend(routes(include_str!("api.rs"), API_PREFIX));
+        expected.extend(routes(include_str!("lan.rs"), ""));
+        assert!(expected.contains(&("post".to_string(), "/api/rpc".to_string())));
+        assert!(expected.contains(&("post".to_string(), "/api/v1/sales".to_string())));
+        assert_eq!(documented(&document), expected);
+    }
+
+    #[test]
+    fn test_references_resolve() {
+        let document = document();
+        assert_eq!(document["openapi"], "3.0.3");
+        let mut found = Vec::new();
+        refs(&document, &mut found);
+        assert!(!found.is_empty());
+        for target in found {
+            let name = target.strip_prefix("#/components/schemas/").unwrap_or_else(|| panic!("{} is not a schema", target));
+            assert!(document["components"]["schemas"].get(name).is_some(), "{} is missing", target);
+        }
+        let codes = document["components"]["schemas"]["ErrorCode"].to_string();
+        assert!(codes.contains("\"license_restricted\""));
+    }
+}
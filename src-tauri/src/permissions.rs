@@ -0,0 +1,801 @@
+use crate::license::LicenseMode;
+use crate::repository::{Repositories, RoleRecord};
+use crate::User;
+use anyhow::{anyhow, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Something a role allows its users to do; serialized as a dotted name such as `sales.write`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(into = "&'static str", try_from = "String")]
+pub enum Capability {
+    UsersManage,
+    SettingsManage,
+    DatabaseManage,
+    DatabaseQuery,
+    DatabaseExecute,
+    CustomersRead,
+    CustomersWrite,
+    SuppliersRead,
+    SuppliersWrite,
+    InventoryRead,
+    InventoryWrite,
+    PurchasesRead,
+    PurchasesWrite,
+    SalesRead,
+    SalesWrite,
+    AccountingRead,
+    AccountingWrite,
+    ExpensesRead,
+    ExpensesWrite,
+    HrRead,
+    HrWrite,
+}
+
+use Capability::*;
+
+impl Capability {
+    pub const ALL: &'static [Capability] = &[
+        UsersManage, SettingsManage, DatabaseManage, DatabaseQuery, DatabaseExecute,
+        CustomersRead, CustomersWrite, SuppliersRead, SuppliersWrite, InventoryRead, InventoryWrite,
+        PurchasesRead, PurchasesWrite, SalesRead, SalesWrite, AccountingRead, AccountingWrite,
+        ExpensesRead, ExpensesWrite, HrRead, HrWrite,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            UsersManage => "users.manage",
+            SettingsManage => "settings.manage",
+            DatabaseManage => "database.manage",
+            DatabaseQuery => "database.query",
+            DatabaseExecute => "database.execute",
+            CustomersRead => "customers.read",
+            CustomersWrite => "customers.write",
+            SuppliersRead => "suppliers.read",
+            SuppliersWrite => "suppliers.write",
+            InventoryRead => "inventory.read",
+            InventoryWrite => "inventory.write",
+            PurchasesRead => "purchases.read",
+            PurchasesWrite => "purchases.write",
+            SalesRead => "sales.read",
+            SalesWrite => "sales.write",
+            AccountingRead => "accounting.read",
+            AccountingWrite => "accounting.write",
+            ExpensesRead => "expenses.read",
+            ExpensesWrite => "expenses.write",
+            HrRead => "hr.read",
+            HrWrite => "hr.write",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Capability> {
+        Capability::ALL.iter().copied().find(|capability| capability.name() == name)
+    }
+
+    fn is_read(self) -> bool {
+        self.name().ends_with(".read")
+    }
+
+    pub fn is_write(self) -> bool {
+        self.name().ends_with(".write")
+    }
+}
+
+impl From<Capability> for &'static str {
+    fn from(capability: Capability) -> Self {
+        capability.name()
+    }
+}
+
+impl TryFrom<String> for Capability {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        Capability::parse(&name).ok_or_else(|| format!("Unknown capability: {}", name))
+    }
+}
+
+impl JsonSchema for Capability {
+    fn schema_name() -> String {
+        "Capability".to_string()
+    }
+
+    fn json_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        schemars::schema::SchemaObject {
+            instance_type: Some(schemars::schema::InstanceType::String.into()),
+            enum_values: Some(Capability::ALL.iter().map(|capability| capability.name().into()).collect()),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+/// The administrator role: always holds every capability and can't be edited or deleted
+pub const ADMIN_ROLE: &str = "admin";
+/// Role given to users registered after the first one
+pub const DEFAULT_ROLE: &str = "read_only";
+
+struct BuiltInRole {
+    name: &'static str,
+    description: &'static str,
+    extra: &'static [Capability],
+    /// Also grant every `*.read` capability
+    reads: bool,
+}
+
+const BUILT_IN_ROLES: &[BuiltInRole] = &[
+    BuiltInRole { name: ADMIN_ROLE, description: "Full access, including users, roles and the database", extra: &[], reads: true },
+    BuiltInRole {
+        name: "accountant",
+        description: "Reads everything; records accounting, expenses and purchases",
+        extra: &[AccountingWrite, ExpensesWrite, PurchasesWrite, SuppliersWrite, DatabaseQuery],
+        reads: true,
+    },
+    BuiltInRole {
+        name: "cashier",
+        description: "Records sales and customers",
+        extra: &[SalesRead, SalesWrite, CustomersRead, CustomersWrite, InventoryRead, AccountingRead],
+        reads: false,
+    },
+    BuiltInRole { name: "hr", description: "Manages employees, salaries and deductions", extra: &[HrRead, HrWrite, ExpensesRead], reads: false },
+    BuiltInRole { name: DEFAULT_ROLE, description: "Reads everything, changes nothing", extra: &[], reads: true },
+];
+
+impl BuiltInRole {
+    fn capabilities(&self) -> BTreeSet<Capability> {
+        if self.name == ADMIN_ROLE {
+            return Capability::ALL.iter().copied().collect();
+        }
+        let reads = Capability::ALL.iter().copied().filter(|c| self.reads && c.is_read());
+        reads.chain(self.extra.iter().copied()).collect()
+    }
+}
+
+fn built_in(name: &str) -> Option<&'static BuiltInRole> {
+    BUILT_IN_ROLES.iter().find(|role| role.name == name)
+}
+
+/// A role as shown to administrators
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Role {
+    pub name: String,
+    pub description: Option<String>,
+    pub capabilities: BTreeSet<Capability>,
+    /// Shipped with the app; can be edited (except `admin`) but not deleted
+    pub built_in: bool,
+}
+
+impl Role {
+    fn from_record(record: RoleRecord) -> Role {
+        Role {
+            built_in: built_in(&record.name).is_some(),
+            // Names that are no longer capabilities (e.g. saved by a newer version) are ignored
+            capabilities: record.capabilities.iter().filter_map(|name| Capability::parse(name)).collect(),
+            name: record.name,
+            description: record.description,
+        }
+    }
+}
+
+/// Every role: the built-in ones (with any stored changes) followed by custom roles, by name
+pub async fn list_roles(repo: &dyn Repositories) -> Result<Vec<Role>> {
+    let mut stored: Vec<Role> = repo.list_roles().await?.into_iter().map(Role::from_record).collect();
+    let mut roles = Vec::new();
+    for default in BUILT_IN_ROLES {
+        let role = match stored.iter().position(|role| role.name == default.name) {
+            Some(index) if default.name != ADMIN_ROLE => stored.remove(index),
+            _ => Role {
+                name: default.name.to_string(),
+                description: Some(default.description.to_string()),
+                capabilities: default.capabilities(),
+                built_in: true,
+            },
+        };
+        roles.push(role);
+    }
+    stored.retain(|role| !role.built_in);
+    roles.extend(stored);
+    Ok(roles)
+}
+
+/// Capabilities currently granted to `role`; unknown roles get none
+pub async fn role_capabilities(repo: &dyn Repositories, role: &str) -> Result<BTreeSet<Capability>> {
+    if role == ADMIN_ROLE {
+        return Ok(Capability::ALL.iter().copied().collect());
+    }
+    if let Some(stored) = repo.get_role(role).await? {
+        return Ok(Role::from_record(stored).capabilities);
+    }
+    Ok(built_in(role).map(BuiltInRole::capabilities).unwrap_or_default())
+}
+
+/// Whether `role` is built in or has been saved
+pub async fn role_exists(repo: &dyn Repositories, role: &str) -> Result<bool> {
+    Ok(built_in(role).is_some() || repo.get_role(role).await?.is_some())
+}
+
+/// Fail if giving `user` this role and active state would leave nobody able to manage users
+pub async fn check_admin_remains(repo: &dyn Repositories, user: &User, role: &str, active: bool) -> Result<()> {
+    let was_admin = user.role == ADMIN_ROLE && user.is_active != 0;
+    if !was_admin || (role == ADMIN_ROLE && active) {
+        return Ok(());
+    }
+    if repo.count_active_users(Some(ADMIN_ROLE)).await? <= 1 {
+        return Err(anyhow!("{} is the only active admin; make another user admin first", user.username));
+    }
+    Ok(())
+}
+
+/// Create a custom role or change the capabilities of an existing one
+pub async fn save_role(
+    repo: &dyn Repositories,
+    name: &str,
+    description: Option<String>,
+    capabilities: &BTreeSet<Capability>,
+) -> Result<Role> {
+    let name = name.trim();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+        return Err(anyhow!("Role names may only contain lowercase letters, digits and underscores"));
+    }
+    if name == ADMIN_ROLE {
+        return Err(anyhow!("The admin role always has every capability and can't be changed"));
+    }
+    let record = RoleRecord {
+        name: name.to_string(),
+        description: description.filter(|d| !d.trim().is_empty()),
+        capabilities: capabilities.iter().map(|c| c.name().to_string()).collect(),
+    };
+    repo.save_role(&record).await?;
+    Ok(Role::from_record(record))
+}
+
+/// Delete a custom role; built-in roles and roles still assigned to users are kept
+pub async fn delete_role(repo: &dyn Repositories, name: &str) -> Result<()> {
+    if built_in(name).is_some() {
+        return Err(anyhow!("Built-in role {} can't be deleted", name));
+    }
+    let assigned = repo.count_users(Some(name)).await?;
+    if assigned > 0 {
+        return Err(anyhow!("Role {} is still assigned to {} user(s)", name, assigned));
+    }
+    repo.delete_role(name).await
+}
+
+/// Who a session belongs to and what they may do
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Session {
+    pub user_id: i64,
+    pub username: String,
+    pub role: String,
+    pub capabilities: BTreeSet<Capability>,
+    /// Only `change_password` is allowed until the user picks a new password
+    #[serde(default)]
+    pub must_change_password: bool,
+    /// The role requires two-factor authentication and the user hasn't set it up yet
+    #[serde(default)]
+    pub must_enroll_two_factor: bool,
+}
+
+/// Why a command was refused; sent to the frontend as `{ kind: "forbidden", ... }`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AccessError {
+    /// Nobody is logged in
+    Unauthenticated { command: String, message: String },
+    /// The logged-in role lacks `capability`, or the command has no access policy
+    Forbidden { command: String, capability: Option<Capability>, message: String },
+    /// A sensitive command needs the password confirmed again (see `reauthenticate`)
+    ReauthenticationRequired { command: String, message: String },
+    /// The user has to choose a new password (see `change_password`) before anything else
+    PasswordChangeRequired { command: String, message: String },
+    /// The role requires two-factor authentication, which the user has to set up first
+    TwoFactorSetupRequired { command: String, message: String },
+    /// The license is missing or expired, or doesn't include the command's module
+    LicenseRestricted { command: String, mode: LicenseMode, message: String },
+}
+
+/// Who may call a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Needed before login (setup, license, login itself)
+    Public,
+    /// Any logged-in user
+    Authenticated,
+    Requires(Capability),
+}
+
+use Access::*;
+
+/// Access policy of every Tauri command. A command missing here can't be called at all.
+pub const COMMANDS: &[(&str, Access)] = &[
+    // Setup, license and login
+    ("db_configure", Public),
+    ("get_db_config", Public),
+    ("db_open_surreal", Public),
+    ("db_is_open_surreal", Public),
+    ("db_open", Public),
+    ("db_is_open", Public),
+    ("db_schema_version", Public),
+    ("init_users_table", Public),
+    ("register_user", Public),
+    ("login_user", Public),
+    ("logout_user", Public),
+    ("get_current_user", Public),
+    ("reauthenticate", Authenticated),
+    ("change_password", Authenticated),
+    ("get_password_policy", Public),
+    ("update_password_policy", Requires(SettingsManage)),
+    ("verify_two_factor", Public),
+    ("get_two_factor_status", Authenticated),
+    ("begin_two_factor_enrollment", Authenticated),
+    ("confirm_two_factor_enrollment", Authenticated),
+    ("disable_two_factor", Authenticated),
+    ("regenerate_recovery_codes", Authenticated),
+    ("create_api_token", Authenticated),
+    ("get_api_tokens", Authenticated),
+    ("revoke_api_token", Authenticated),
+    ("get_two_factor_policy", Requires(SettingsManage)),
+    ("update_two_factor_policy", Requires(SettingsManage)),
+    ("get_machine_id", Public),
+    ("diagnose_machine_id", Public),
+    ("store_license_key", Public),
+    ("get_license_key", Public),
+    ("validate_license_key", Public),
+    ("get_license_status", Public),
+    ("hash_password", Public),
+    ("verify_password", Public),
+    ("store_puter_credentials", Public),
+    ("get_puter_credentials", Public),
+    ("get_server_access", Public),
+    // Database administration
+    ("db_close_surreal", Requires(DatabaseManage)),
+    ("db_create", Requires(DatabaseManage)),
+    ("db_close", Requires(DatabaseManage)),
+    ("get_database_path", Requires(DatabaseManage)),
+    ("backup_database", Requires(DatabaseManage)),
+    ("db_migrate", Requires(DatabaseManage)),
+    ("migrate_existing_data", Requires(DatabaseManage)),
+    ("migrate_sqlite_to_surreal", Requires(DatabaseManage)),
+    ("export_surreal_to_sqlite", Requires(DatabaseManage)),
+    ("db_sync", Authenticated),
+    ("db_sync_status", Authenticated),
+    ("db_sync_conflicts", Requires(DatabaseManage)),
+    ("db_sync_resolve_conflict", Requires(DatabaseManage)),
+    ("purge_trash", Requires(DatabaseManage)),
+    ("get_retention_policy", Requires(DatabaseManage)),
+    ("update_retention_policy", Requires(DatabaseManage)),
+    // Raw queries (reports and the AI assistant read; only trusted roles may write)
+    ("db_query", Requires(DatabaseQuery)),
+    ("db_query_surreal", Requires(DatabaseQuery)),
+    ("db_execute", Requires(DatabaseExecute)),
+    ("db_execute_surreal", Requires(DatabaseExecute)),
+    // Users and roles
+    ("get_profile", Authenticated),
+    ("update_profile", Authenticated),
+    ("get_users", Requires(UsersManage)),
+    ("get_user", Requires(UsersManage)),
+    ("get_user_stats", Requires(UsersManage)),
+    ("create_user", Requires(UsersManage)),
+    ("update_user", Requires(UsersManage)),
+    ("set_user_active", Requires(UsersManage)),
+    ("set_user_role", Requires(UsersManage)),
+    ("reset_password", Requires(UsersManage)),
+    ("delete_user", Requires(UsersManage)),
+    ("unlock_user", Requires(UsersManage)),
+    ("reset_two_factor", Requires(UsersManage)),
+    ("list_sessions", Requires(UsersManage)),
+    ("revoke_session", Requires(UsersManage)),
+    ("list_capabilities", Requires(UsersManage)),
+    ("get_audit_log", Requires(UsersManage)),
+    ("verify_audit_chain", Requires(UsersManage)),
+    ("get_roles", Requires(UsersManage)),
+    ("save_role", Requires(UsersManage)),
+    ("delete_role", Requires(UsersManage)),
+    // Settings and reference data every page needs
+    ("init_company_settings_table", Authenticated),
+    ("get_company_settings", Authenticated),
+    ("update_company_settings", Requires(SettingsManage)),
+    ("get_server_config", Requires(SettingsManage)),
+    ("update_server_config", Requires(SettingsManage)),
+    ("get_webhooks", Requires(SettingsManage)),
+    ("create_webhook", Requires(SettingsManage)),
+    ("update_webhook", Requires(SettingsManage)),
+    ("delete_webhook", Requires(SettingsManage)),
+    ("get_webhook_deliveries", Requires(SettingsManage)),
+    ("init_currencies_table", Authenticated),
+    ("get_currencies", Authenticated),
+    ("create_currency", Requires(SettingsManage)),
+    ("update_currency", Requires(SettingsManage)),
+    ("delete_currency", Requires(SettingsManage)),
+    ("init_unit_groups_table", Authenticated),
+    ("get_unit_groups", Authenticated),
+    ("create_unit_group", Requires(InventoryWrite)),
+    ("init_units_table", Authenticated),
+    ("get_units", Authenticated),
+    ("create_unit", Requires(InventoryWrite)),
+    ("update_unit", Requires(InventoryWrite)),
+    ("delete_unit", Requires(InventoryWrite)),
+    // Suppliers and customers
+    ("init_suppliers_table", Authenticated),
+    ("get_suppliers", Requires(SuppliersRead)),
+    ("create_supplier", Requires(SuppliersWrite)),
+    ("update_supplier", Requires(SuppliersWrite)),
+    ("delete_supplier", Requires(SuppliersWrite)),
+    ("restore_supplier", Requires(SuppliersWrite)),
+    ("get_deleted_suppliers", Requires(SuppliersRead)),
+    ("init_customers_table", Authenticated),
+    ("get_customers", Requires(CustomersRead)),
+    ("create_customer", Requires(CustomersWrite)),
+    ("update_customer", Requires(CustomersWrite)),
+    ("delete_customer", Requires(CustomersWrite)),
+    ("restore_customer", Requires(CustomersWrite)),
+    ("get_deleted_customers", Requires(CustomersRead)),
+    // Inventory
+    ("init_products_table", Authenticated),
+    ("get_products", Requires(InventoryRead)),
+    ("get_product_batches", Requires(InventoryRead)),
+    ("create_product", Requires(InventoryWrite)),
+    ("update_product", Requires(InventoryWrite)),
+    ("delete_product", Requires(InventoryWrite)),
+    ("restore_product", Requires(InventoryWrite)),
+    ("get_deleted_products", Requires(InventoryRead)),
+    // Purchases
+    ("init_purchases_table", Authenticated),
+    ("get_purchases", Requires(PurchasesRead)),
+    ("get_purchase", Requires(PurchasesRead)),
+    ("get_purchase_items", Requires(PurchasesRead)),
+    ("get_purchase_additional_costs", Requires(PurchasesRead)),
+    ("create_purchase", Requires(PurchasesWrite)),
+    ("update_purchase", Requires(PurchasesWrite)),
+    ("delete_purchase", Requires(PurchasesWrite)),
+    ("restore_purchase", Requires(PurchasesWrite)),
+    ("get_deleted_purchases", Requires(PurchasesRead)),
+    ("create_purchase_item", Requires(PurchasesWrite)),
+    ("update_purchase_item", Requires(PurchasesWrite)),
+    ("delete_purchase_item", Requires(PurchasesWrite)),
+    ("init_purchase_payments_table", Authenticated),
+    ("get_purchase_payments", Requires(PurchasesRead)),
+    ("get_purchase_payments_by_purchase", Requires(PurchasesRead)),
+    ("create_purchase_payment", Requires(PurchasesWrite)),
+    ("update_purchase_payment", Requires(PurchasesWrite)),
+    ("delete_purchase_payment", Requires(PurchasesWrite)),
+    // Sales
+    ("init_sales_table", Authenticated),
+    ("get_sales", Requires(SalesRead)),
+    ("get_sale", Requires(SalesRead)),
+    ("get_sale_items", Requires(SalesRead)),
+    ("get_sale_payments", Requires(SalesRead)),
+    ("get_sale_additional_costs", Requires(SalesRead)),
+    ("create_sale", Requires(SalesWrite)),
+    ("update_sale", Requires(SalesWrite)),
+    ("delete_sale", Requires(SalesWrite)),
+    ("restore_sale", Requires(SalesWrite)),
+    ("get_deleted_sales", Requires(SalesRead)),
+    ("create_sale_item", Requires(SalesWrite)),
+    ("update_sale_item", Requires(SalesWrite)),
+    ("delete_sale_item", Requires(SalesWrite)),
+    ("create_sale_payment", Requires(SalesWrite)),
+    ("delete_sale_payment", Requires(SalesWrite)),
+    // Expenses
+    ("init_expense_types_table", Authenticated),
+    ("get_expense_types", Requires(ExpensesRead)),
+    ("create_expense_type", Requires(ExpensesWrite)),
+    ("update_expense_type", Requires(ExpensesWrite)),
+    ("delete_expense_type", Requires(ExpensesWrite)),
+    ("init_expenses_table", Authenticated),
+    ("get_expenses", Requires(ExpensesRead)),
+    ("get_expense", Requires(ExpensesRead)),
+    ("create_expense", Requires(ExpensesWrite)),
+    ("update_expense", Requires(ExpensesWrite)),
+    ("delete_expense", Requires(ExpensesWrite)),
+    ("restore_expense", Requires(ExpensesWrite)),
+    ("get_deleted_expenses", Requires(ExpensesRead)),
+    // HR
+    ("init_employees_table", Authenticated),
+    ("get_employees", Requires(HrRead)),
+    ("get_employee", Requires(HrRead)),
+    ("create_employee", Requires(HrWrite)),
+    ("update_employee", Requires(HrWrite)),
+    ("delete_employee", Requires(HrWrite)),
+    ("restore_employee", Requires(HrWrite)),
+    ("get_deleted_employees", Requires(HrRead)),
+    ("init_salaries_table", Authenticated),
+    ("get_salaries", Requires(HrRead)),
+    ("get_salaries_by_employee", Requires(HrRead)),
+    ("get_salary", Requires(HrRead)),
+    ("create_salary", Requires(HrWrite)),
+    ("update_salary", Requires(HrWrite)),
+    ("delete_salary", Requires(HrWrite)),
+    ("restore_salary", Requires(HrWrite)),
+    ("get_deleted_salaries", Requires(HrRead)),
+    ("init_deductions_table", Authenticated),
+    ("get_deductions", Requires(HrRead)),
+    ("get_deductions_by_employee", Requires(HrRead)),
+    ("get_deductions_by_employee_year_month", Requires(HrRead)),
+    ("get_deduction", Requires(HrRead)),
+    ("create_deduction", Requires(HrWrite)),
+    ("update_deduction", Requires(HrWrite)),
+    ("delete_deduction", Requires(HrWrite)),
+    ("restore_deduction", Requires(HrWrite)),
+    ("get_deleted_deductions", Requires(HrRead)),
+    // Accounting
+    ("init_accounts_table", Authenticated),
+    ("init_account_transactions_table", Authenticated),
+    ("init_coa_categories_table", Authenticated),
+    ("init_account_currency_balances_table", Authenticated),
+    ("init_journal_entries_table", Authenticated),
+    ("init_journal_entry_lines_table", Authenticated),
+    ("init_currency_exchange_rates_table", Authenticated),
+    ("get_accounts", Requires(AccountingRead)),
+    ("get_account", Requires(AccountingRead)),
+    ("get_account_transactions", Requires(AccountingRead)),
+    ("get_account_balance", Requires(AccountingRead)),
+    ("get_account_balance_by_currency", Requires(AccountingRead)),
+    ("get_all_account_balances", Requires(AccountingRead)),
+    ("get_coa_categories", Requires(AccountingRead)),
+    ("get_coa_category_tree", Requires(AccountingRead)),
+    ("get_journal_entries", Requires(AccountingRead)),
+    ("get_journal_entry", Requires(AccountingRead)),
+    ("get_exchange_rate", Requires(AccountingRead)),
+    ("get_exchange_rate_history", Requires(AccountingRead)),
+    ("create_account", Requires(AccountingWrite)),
+    ("update_account", Requires(AccountingWrite)),
+    ("delete_account", Requires(AccountingWrite)),
+    ("restore_account", Requires(AccountingWrite)),
+    ("get_deleted_accounts", Requires(AccountingRead)),
+    ("deposit_account", Requires(AccountingWrite)),
+    ("withdraw_account", Requires(AccountingWrite)),
+    ("reconcile_account_balance", Requires(AccountingWrite)),
+    ("init_standard_coa_categories", Requires(AccountingWrite)),
+    ("create_coa_category", Requires(AccountingWrite)),
+    ("update_coa_category", Requires(AccountingWrite)),
+    ("delete_coa_category", Requires(AccountingWrite)),
+    ("create_journal_entry", Requires(AccountingWrite)),
+    ("update_journal_entry", Requires(AccountingWrite)),
+    ("create_exchange_rate", Requires(AccountingWrite)),
+];
+
+/// Commands that also need the password confirmed within the last few minutes
+const SENSITIVE_COMMANDS: &[&str] = &[
+    "create_api_token",
+    "db_execute",
+    "db_execute_surreal",
+    "db_close",
+    "db_close_surreal",
+    "backup_database",
+    "migrate_existing_data",
+    "migrate_sqlite_to_surreal",
+    "export_surreal_to_sqlite",
+    "save_role",
+    "delete_role",
+    "set_user_role",
+    "reset_password",
+    "delete_user",
+    "disable_two_factor",
+    "regenerate_recovery_codes",
+    "reset_two_factor",
+    "update_two_factor_policy",
+    "revoke_session",
+    "update_company_settings",
+    "update_server_config",
+    "create_webhook",
+    "update_webhook",
+    "update_password_policy",
+    "purge_trash",
+    "update_retention_policy",
+    "delete_sale",
+    "delete_sale_payment",
+    "delete_purchase",
+    "delete_purchase_payment",
+    "delete_account",
+    "withdraw_account",
+    "delete_expense",
+    "delete_salary",
+];
+
+pub fn requires_reauthentication(command: &str) -> bool {
+    SENSITIVE_COMMANDS.contains(&command)
+}
+
+/// Commands a user who still has to set up two-factor authentication may call
+const TWO_FACTOR_SETUP_COMMANDS: &[&str] = &[
+    "get_two_factor_status",
+    "begin_two_factor_enrollment",
+    "confirm_two_factor_enrollment",
+];
+
+pub fn allowed_before_two_factor_setup(command: &str) -> bool {
+    TWO_FACTOR_SETUP_COMMANDS.contains(&command)
+}
+
+/// Commands open to every user (or before login) that still change data
+const DATA_CHANGING_COMMANDS: &[&str] = &[
+    "register_user",
+    "change_password",
+    "update_profile",
+    "confirm_two_factor_enrollment",
+    "disable_two_factor",
+    "regenerate_recovery_codes",
+    "db_sync",
+];
+
+/// Commands behind a manage capability that only read data
+const READING_COMMANDS: &[&str] = &[
+    "get_two_factor_policy",
+    "db_close_surreal",
+    "db_close",
+    "get_database_path",
+    "backup_database",
+    "db_sync_conflicts",
+    "get_retention_policy",
+    "get_users",
+    "get_user",
+    "get_user_stats",
+    "list_sessions",
+    "list_capabilities",
+    "get_audit_log",
+    "verify_audit_chain",
+    "get_roles",
+    "get_server_config",
+    "get_webhooks",
+    "get_webhook_deliveries",
+];
+
+/// Whether `command` may change data, so the changes it makes are attributed to its caller in the audit log
+/// and an expired license refuses it
+pub fn changes_data(command: &str) -> bool {
+    match command_access(command) {
+        Some(Requires(capability)) => {
+            !capability.is_read() && capability != DatabaseQuery && !READING_COMMANDS.contains(&command)
+        }
+        Some(_) => DATA_CHANGING_COMMANDS.contains(&command),
+        None => false,
+    }
+}
+
+pub fn command_access(command: &str) -> Option<Access> {
+    COMMANDS.iter().find(|(name, _)| *name == command).map(|(_, access)| *access)
+}
+
+/// Decide whether `session` may call `command`
+pub fn authorize(command: &str, session: Option<&Session>) -> Result<(), AccessError> {
+    let Some(access) = command_access(command) else {
+        return Err(AccessError::Forbidden {
+            command: command.to_string(),
+            capability: None,
+            message: format!("Command {} has no access policy", command),
+        });
+    };
+    let session = match (access, session) {
+        (Public, _) => return Ok(()),
+        (_, Some(session)) => session,
+        (_, None) => {
+            return Err(AccessError::Unauthenticated {
+                command: command.to_string(),
+                message: "Please log in first".to_string(),
+            })
+        }
+    };
+    match access {
+        Requires(capability) if !session.capabilities.contains(&capability) => Err(AccessError::Forbidden {
+            command: command.to_string(),
+            capability: Some(capability),
+            message: format!("Role {} lacks the {} capability", session.role, capability.name()),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Refuse everything but the next setup step to a user who still has to choose a new password
+/// or set up two-factor authentication
+pub fn check_account_setup(command: &str, session: &Session) -> Result<(), AccessError> {
+    if session.must_change_password && command != "change_password" {
+        return Err(AccessError::PasswordChangeRequired {
+            command: command.to_string(),
+            message: "Please choose a new password to continue".to_string(),
+        });
+    }
+    if session.must_enroll_two_factor && !session.must_change_password && !allowed_before_two_factor_setup(command) {
+        return Err(AccessError::TwoFactorSetupRequired {
+            command: command.to_string(),
+            message: "Please set up two-factor authentication to continue".to_string(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(role: &str) -> Session {
+        Session {
+            user_id: 1,
+            username: "test".to_string(),
+            role: role.to_string(),
+            capabilities: built_in(role).unwrap().capabilities(),
+            must_change_password: false,
+            must_enroll_two_factor: false,
+        }
+    }
+
+    #[test]
+    fn test_every_registered_command_has_a_policy() {
+        let source = include_str!("lib.rs");
+        let start = source.find("generate_handler![").expect("generate_handler! call") + "generate_handler![".len();
+        let end = start + source[start..].find(']').unwrap();
+        let registered: Vec<&str> = source[start..end].split(',').map(str::trim).filter(|name| !name.is_empty()).collect();
+        assert!(registered.len() > 100);
+        for command in &registered {
+            assert!(command_access(command).is_some(), "{} has no access policy", command);
+        }
+        for (command, _) in COMMANDS {
+            assert!(registered.contains(command), "{} has a policy but isn't registered", command);
+        }
+        for command in SENSITIVE_COMMANDS {
+            assert!(!matches!(command_access(command), None | Some(Public)), "{} can't require a password", command);
+        }
+        for command in DATA_CHANGING_COMMANDS.iter().chain(READING_COMMANDS) {
+            assert!(registered.contains(command), "{} isn't registered", command);
+        }
+    }
+
+    #[test]
+    fn test_changes_data() {
+        for command in ["update_sale", "delete_purchase_payment", "update_journal_entry", "db_execute", "register_user", "db_sync"] {
+            assert!(changes_data(command), "{}", command);
+        }
+        for command in ["get_sales", "db_query", "login_user", "get_current_user", "get_audit_log", "backup_database", "no_such_command"] {
+            assert!(!changes_data(command), "{}", command);
+        }
+    }
+
+    #[test]
+    fn test_authorize_by_role() {
+        assert_eq!(authorize("login_user", None), Ok(()));
+        assert!(matches!(authorize("get_sales", None), Err(AccessError::Unauthenticated { .. })));
+        assert!(matches!(authorize("no_such_command", Some(&session(ADMIN_ROLE))), Err(AccessError::Forbidden { capability: None, .. })));
+
+        let cashier = session("cashier");
+        assert_eq!(authorize("create_sale", Some(&cashier)), Ok(()));
+        assert_eq!(authorize("get_currencies", Some(&cashier)), Ok(()));
+        assert_eq!(
+            authorize("create_salary", Some(&cashier)),
+            Err(AccessError::Forbidden {
+                command: "create_salary".to_string(),
+                capability: Some(HrWrite),
+                message: "Role cashier lacks the hr.write capability".to_string(),
+            })
+        );
+
+        let read_only = session(DEFAULT_ROLE);
+        assert_eq!(authorize("get_journal_entries", Some(&read_only)), Ok(()));
+        assert!(authorize("create_journal_entry", Some(&read_only)).is_err());
+        assert!(authorize("db_execute_surreal", Some(&session("accountant"))).is_err());
+        assert_eq!(authorize("db_execute_surreal", Some(&session(ADMIN_ROLE))), Ok(()));
+    }
+
+    #[test]
+    fn test_forbidden_error_shape() {
+        let error = authorize("delete_role", Some(&session("hr"))).unwrap_err();
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({
+                "kind": "forbidden",
+                "command": "delete_role",
+                "capability": "users.manage",
+                "message": "Role hr lacks the users.manage capability",
+            })
+        );
+    }
+
+    #[test]
+    fn test_capability_names_round_trip() {
+        for capability in Capability::ALL {
+            let name = serde_json::to_value(capability).unwrap();
+            assert_eq!(serde_json::from_value::<Capability>(name).unwrap(), *capability);
+        }
+        assert!(serde_json::from_value::<Capability>(serde_json::json!("sales.delete")).is_err());
+    }
+}
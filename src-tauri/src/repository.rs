@@ -0,0 +1,4072 @@
+use crate::audit::{AuditEntry, AuditFilter};
+use crate::db::Database;
+use crate::surrealdb::{ConnectionMode, SurrealDatabase};
+use crate::trash::{join_label, TrashBin, TrashedRecord};
+use crate::{
+    Account, AccountCurrencyBalance, AccountInput, AccountTransaction, AccountTransactionInput, Customer, JournalEntry,
+    JournalEntryInput, JournalEntryLine, JournalLineInput, PaginatedResponse, Product, Purchase,
+    PurchaseInput, PurchaseItem, PurchasePayment, PurchasePaymentInput, Sale, SaleInput, SaleItem,
+    SaleItemInput, SalePayment, SalePaymentInput, User,
+};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::de::DeserializeOwned;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Page, search and sort options of a list query
+#[derive(Debug, Clone)]
+pub struct ListQuery {
+    pub page: i64,
+    pub per_page: i64,
+    pub search: Option<String>,
+    pub sort_by: Option<String>,
+    pub sort_order: Option<String>,
+}
+
+impl ListQuery {
+    pub fn new(page: i64, per_page: i64) -> Self {
+        ListQuery {
+            page,
+            per_page,
+            search: None,
+            sort_by: None,
+            sort_order: None,
+        }
+    }
+
+    pub(crate) fn offset(&self) -> i64 {
+        (self.page - 1) * self.per_page
+    }
+
+    /// Search text, if any was entered
+    fn search_term(&self) -> Option<&str> {
+        self.search.as_deref().map(str::trim).filter(|s| !s.is_empty())
+    }
+
+    /// `column DIRECTION` if `sort_by` is one of the `allowed` columns
+    fn order_by(&self, allowed: &[&str], default_direction: &str) -> Option<String> {
+        let column = self.sort_by.as_deref().filter(|c| allowed.contains(c))?;
+        let direction = self.sort_order.as_deref().unwrap_or(default_direction);
+        let direction = if direction.eq_ignore_ascii_case("DESC") { "DESC" } else { "ASC" };
+        Some(format!("{} {}", column, direction))
+    }
+
+    pub(crate) fn page_of<T>(&self, items: Vec<T>, total: i64) -> PaginatedResponse<T> {
+        PaginatedResponse {
+            items,
+            total,
+            page: self.page,
+            per_page: self.per_page,
+            total_pages: (total as f64 / self.per_page as f64).ceil() as i64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CustomerInput {
+    pub full_name: String,
+    pub phone: String,
+    pub address: String,
+    pub email: Option<String>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ProductInput {
+    pub name: String,
+    pub description: Option<String>,
+    pub price: Option<f64>,
+    pub currency_id: Option<i64>,
+    pub supplier_id: Option<i64>,
+    pub stock_quantity: Option<f64>,
+    pub unit: Option<String>,
+    pub image_path: Option<String>,
+    pub bar_code: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserInput {
+    pub username: String,
+    pub email: String,
+    pub password_hash: String,
+    #[serde(default)]
+    pub full_name: Option<String>,
+    #[serde(default)]
+    pub phone: Option<String>,
+    pub role: String,
+    /// Make the user choose a new password at their first login
+    pub must_change_password: bool,
+}
+
+/// Fields a user (or an admin on their behalf) can edit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserProfileInput {
+    pub username: String,
+    pub email: String,
+    pub full_name: Option<String>,
+    pub phone: Option<String>,
+}
+
+/// A user's TOTP secret and recovery codes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TwoFactorRecord {
+    /// Base32 secret; set (but not enabled) while enrollment is being confirmed
+    pub secret: Option<String>,
+    pub enabled: bool,
+    /// Time step of the last accepted code, so a code can't be used twice
+    pub last_step: Option<i64>,
+    /// SHA-256 hashes of the unused recovery codes
+    pub recovery_codes: Vec<String>,
+}
+
+/// What logging in needs to know about a user
+#[derive(Debug, Clone)]
+pub struct LoginRecord {
+    pub user: User,
+    pub password_hash: String,
+    /// Failed logins since the last successful one
+    pub failed_logins: i64,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub must_change_password: bool,
+}
+
+/// A stored role: a custom role, or the edited capabilities of a built-in one
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoleRecord {
+    pub name: String,
+    pub description: Option<String>,
+    pub capabilities: Vec<String>,
+}
+
+/// A REST API token as shown to its owner (never includes the token itself)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    /// First characters of the token, to tell tokens apart
+    pub prefix: String,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+    /// None for tokens that don't expire
+    pub expires_at: Option<String>,
+}
+
+/// A new API token; only the SHA-256 of the token is stored
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiTokenInput {
+    pub user_id: i64,
+    pub name: String,
+    pub prefix: String,
+    pub token_hash: String,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Number and total of the sales or purchases in one currency within a period
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PeriodTotals {
+    pub currency_id: Option<i64>,
+    pub count: i64,
+    pub total_amount: f64,
+}
+
+#[async_trait]
+pub trait CustomerRepository: Send + Sync {
+    async fn create_customer(&self, input: &CustomerInput) -> Result<Customer>;
+    async fn get_customer(&self, id: i64) -> Result<Option<Customer>>;
+    async fn list_customers(&self, query: &ListQuery) -> Result<PaginatedResponse<Customer>>;
+    async fn update_customer(&self, id: i64, input: &CustomerInput) -> Result<Customer>;
+    async fn delete_customer(&self, id: i64) -> Result<()>;
+}
+
+#[async_trait]
+pub trait ProductRepository: Send + Sync {
+    async fn create_product(&self, input: &ProductInput) -> Result<Product>;
+    async fn get_product(&self, id: i64) -> Result<Option<Product>>;
+    async fn list_products(&self, query: &ListQuery) -> Result<PaginatedResponse<Product>>;
+    async fn update_product(&self, id: i64, input: &ProductInput) -> Result<Product>;
+    /// Fails if the product is still used by a purchase or sale
+    async fn delete_product(&self, id: i64) -> Result<()>;
+}
+
+#[async_trait]
+pub trait SaleRepository: Send + Sync {
+    /// Inserts the sale with its items, additional costs and initial payment, and posts its journal entry
+    async fn create_sale(&self, input: &SaleInput) -> Result<Sale>;
+    async fn get_sale(&self, id: i64) -> Result<Option<(Sale, Vec<SaleItem>)>>;
+    async fn list_sales(&self, query: &ListQuery) -> Result<PaginatedResponse<Sale>>;
+    /// Replaces the sale's items and additional costs; its paid amount follows its payments
+    async fn update_sale(&self, id: i64, input: &SaleInput) -> Result<Sale>;
+    /// Adds an item to an existing sale and brings the sale's total up to date
+    async fn create_sale_item(&self, sale_id: i64, input: &SaleItemInput) -> Result<SaleItem>;
+    /// Replaces the item and brings its sale's total up to date
+    async fn update_sale_item(&self, id: i64, input: &SaleItemInput) -> Result<SaleItem>;
+    /// Deposits the payment to its account, updates the sale's paid amount and posts its journal entry
+    async fn create_sale_payment(&self, sale_id: i64, input: &SalePaymentInput) -> Result<SalePayment>;
+    /// Deletes the payment and brings its sale's paid amount up to date
+    async fn delete_sale_payment(&self, id: i64) -> Result<()>;
+    /// The sale's payments, newest first
+    async fn list_sale_payments(&self, sale_id: i64) -> Result<Vec<SalePayment>>;
+    /// Deletes the sale together with its items, payments and additional costs
+    async fn delete_sale(&self, id: i64) -> Result<()>;
+}
+
+#[async_trait]
+pub trait PurchaseRepository: Send + Sync {
+    /// Inserts the purchase with its items and additional costs under the next batch number
+    async fn create_purchase(&self, input: &PurchaseInput) -> Result<Purchase>;
+    async fn get_purchase(&self, id: i64) -> Result<Option<(Purchase, Vec<PurchaseItem>)>>;
+    async fn list_purchases(&self, query: &ListQuery) -> Result<PaginatedResponse<Purchase>>;
+    /// Replaces the purchase's items and additional costs
+    async fn update_purchase(&self, id: i64, input: &PurchaseInput) -> Result<Purchase>;
+    /// Withdraws the payment from its account, which must hold enough in the payment's currency
+    async fn create_purchase_payment(&self, purchase_id: i64, input: &PurchasePaymentInput) -> Result<PurchasePayment>;
+    /// Deletes the purchase together with its items, payments and additional costs
+    async fn delete_purchase(&self, id: i64) -> Result<()>;
+}
+
+#[async_trait]
+pub trait AccountRepository: Send + Sync {
+    /// Inserts the account with its initial balance in its currency
+    async fn create_account(&self, input: &AccountInput) -> Result<Account>;
+    async fn get_account(&self, id: i64) -> Result<Option<Account>>;
+    /// All accounts outside the trash, ordered by name
+    async fn list_accounts(&self) -> Result<Vec<Account>>;
+    /// Updates the account and recalculates its current balance
+    async fn update_account(&self, id: i64, input: &AccountInput, is_active: bool) -> Result<Account>;
+    /// Initial balance plus deposits minus withdrawals
+    async fn account_balance(&self, account_id: i64) -> Result<f64>;
+    /// Newest transactions first
+    async fn list_account_transactions(&self, account_id: i64) -> Result<Vec<AccountTransaction>>;
+    /// The account's balance in each currency it has held
+    async fn list_currency_balances(&self, account_id: i64) -> Result<Vec<AccountCurrencyBalance>>;
+    /// Deletes the account together with its transactions and currency balances
+    async fn delete_account(&self, id: i64) -> Result<()>;
+    /// Records the deposit and posts it against a cash or bank account
+    async fn deposit_account(&self, account_id: i64, input: &AccountTransactionInput) -> Result<AccountTransaction>;
+    /// Records the withdrawal, which may not exceed the balance, and posts it against an expense account
+    async fn withdraw_account(&self, account_id: i64, input: &AccountTransactionInput) -> Result<AccountTransaction>;
+}
+
+#[async_trait]
+pub trait JournalRepository: Send + Sync {
+    /// Posts the entry under the next entry number and moves its accounts' balances
+    async fn create_journal_entry(&self, input: &JournalEntryInput) -> Result<JournalEntry>;
+    async fn get_journal_entry(&self, id: i64) -> Result<Option<(JournalEntry, Vec<JournalEntryLine>)>>;
+    /// Newest entries first
+    async fn list_journal_entries(&self, query: &ListQuery) -> Result<PaginatedResponse<JournalEntry>>;
+    /// Replaces the entry's lines, moving the balances of the old lines' accounts back and recording
+    /// each new line as a deposit to or withdrawal from its account
+    async fn update_journal_entry(&self, id: i64, lines: &[JournalLineInput]) -> Result<JournalEntry>;
+}
+
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn create_user(&self, input: &UserInput) -> Result<User>;
+    async fn get_user(&self, id: i64) -> Result<Option<User>>;
+    async fn update_user(&self, id: i64, input: &UserProfileInput) -> Result<User>;
+    async fn set_user_active(&self, id: i64, active: bool) -> Result<()>;
+    async fn set_user_role(&self, id: i64, role: &str) -> Result<()>;
+    /// Deletes the user together with their password history and API tokens
+    async fn delete_user(&self, id: i64) -> Result<()>;
+    async fn user_exists(&self, username: &str, email: &str) -> Result<bool>;
+    /// Login details of the user with this username or email
+    async fn find_login(&self, login: &str) -> Result<Option<LoginRecord>>;
+    async fn list_users(&self, query: &ListQuery) -> Result<PaginatedResponse<User>>;
+    /// Number of users, or of users with `role`
+    async fn count_users(&self, role: Option<&str>) -> Result<i64>;
+    /// Like `count_users`, but only users who can log in
+    async fn count_active_users(&self, role: Option<&str>) -> Result<i64>;
+    /// Save the failed login count and lockout (0 and `None` after a successful login or an unlock)
+    async fn set_login_failures(&self, user_id: i64, failed_logins: i64, locked_until: Option<DateTime<Utc>>) -> Result<()>;
+    /// Replace the password hash, keeping the old one in the password history
+    async fn set_password(&self, user_id: i64, password_hash: &str, must_change_password: bool) -> Result<()>;
+    /// Previous password hashes, newest first
+    async fn password_history(&self, user_id: i64, limit: i64) -> Result<Vec<String>>;
+    async fn get_two_factor(&self, user_id: i64) -> Result<TwoFactorRecord>;
+    async fn save_two_factor(&self, user_id: i64, record: &TwoFactorRecord) -> Result<()>;
+}
+
+#[async_trait]
+pub trait RoleRepository: Send + Sync {
+    /// Stored roles, ordered by name
+    async fn list_roles(&self) -> Result<Vec<RoleRecord>>;
+    async fn get_role(&self, name: &str) -> Result<Option<RoleRecord>>;
+    /// Create the role, or replace the description and capabilities of an existing one
+    async fn save_role(&self, role: &RoleRecord) -> Result<()>;
+    async fn delete_role(&self, name: &str) -> Result<()>;
+}
+
+#[async_trait]
+pub trait SettingsRepository: Send + Sync {
+    /// JSON value stored under `key`
+    async fn get_setting(&self, key: &str) -> Result<Option<serde_json::Value>>;
+    async fn set_setting(&self, key: &str, value: &serde_json::Value) -> Result<()>;
+}
+
+#[async_trait]
+pub trait AuditRepository: Send + Sync {
+    /// Entries with an id above `after_id`, oldest first
+    async fn audit_entries(&self, after_id: i64, limit: i64) -> Result<Vec<AuditEntry>>;
+    /// The newest entry that has been hashed into the chain
+    async fn last_sealed_audit_entry(&self) -> Result<Option<AuditEntry>>;
+    /// Store `(id, prev_hash, hash)` of unsealed entries. Returns false without storing anything
+    /// while the store is inside a transaction, whose entries could still be rolled back.
+    async fn seal_audit_entries(&self, hashes: &[(i64, String, String)]) -> Result<bool>;
+    /// Newest entries first
+    async fn list_audit_log(&self, filter: &AuditFilter, query: &ListQuery) -> Result<PaginatedResponse<AuditEntry>>;
+}
+
+#[async_trait]
+pub trait TrashRepository: Send + Sync {
+    /// Move the record to the trash. Returns false if there is no such record outside the trash.
+    async fn soft_delete(&self, table: &str, id: i64, deleted_by: Option<i64>) -> Result<bool>;
+    /// Take the record out of the trash. Returns false if it isn't in the trash.
+    async fn restore(&self, table: &str, id: i64) -> Result<bool>;
+    /// Records in the trash, most recently deleted first
+    async fn list_deleted(&self, bin: &TrashBin, query: &ListQuery) -> Result<PaginatedResponse<TrashedRecord>>;
+    /// Ids of trashed records deleted before `deleted_before` and, if given, created before `created_before`
+    async fn purgeable(&self, table: &str, deleted_before: DateTime<Utc>, created_before: Option<DateTime<Utc>>) -> Result<Vec<i64>>;
+    /// Records in the `(table, column)` references that link to record `id` of `table`
+    async fn count_references(&self, table: &str, id: i64, references: &[(&str, &str)]) -> Result<i64>;
+    /// Permanently delete a record that is in the trash
+    async fn purge_record(&self, table: &str, id: i64) -> Result<()>;
+}
+
+#[async_trait]
+pub trait ApiTokenRepository: Send + Sync {
+    async fn create_api_token(&self, input: &ApiTokenInput) -> Result<ApiToken>;
+    async fn get_api_token(&self, id: i64) -> Result<Option<ApiToken>>;
+    /// Tokens of `user_id`, or of every user; newest first
+    async fn list_api_tokens(&self, user_id: Option<i64>) -> Result<Vec<ApiToken>>;
+    /// The unexpired token with this hash, recording that it was used at `now`
+    async fn use_api_token(&self, token_hash: &str, now: DateTime<Utc>) -> Result<Option<ApiToken>>;
+    async fn delete_api_token(&self, id: i64) -> Result<()>;
+}
+
+#[async_trait]
+pub trait ReportRepository: Send + Sync {
+    /// Sales outside the trash dated from `from` to `to` (`YYYY-MM-DD`, inclusive), per currency
+    async fn sales_totals(&self, from: Option<&str>, to: Option<&str>) -> Result<Vec<PeriodTotals>>;
+    /// Purchases outside the trash dated from `from` to `to` (`YYYY-MM-DD`, inclusive), per currency
+    async fn purchases_totals(&self, from: Option<&str>, to: Option<&str>) -> Result<Vec<PeriodTotals>>;
+}
+
+/// Every repository, as provided by one storage backend
+pub trait Repositories:
+    CustomerRepository
+    + ProductRepository
+    + SaleRepository
+    + PurchaseRepository
+    + AccountRepository
+    + JournalRepository
+    + UserRepository
+    + RoleRepository
+    + SettingsRepository
+    + AuditRepository
+    + TrashRepository
+    + ApiTokenRepository
+    + ReportRepository
+{
+}
+
+impl<T> Repositories for T where
+    T: CustomerRepository
+        + ProductRepository
+        + SaleRepository
+        + PurchaseRepository
+        + AccountRepository
+        + JournalRepository
+        + UserRepository
+        + RoleRepository
+        + SettingsRepository
+        + AuditRepository
+        + TrashRepository
+        + ApiTokenRepository
+        + ReportRepository
+{
+}
+
+/// Pick the backend for the configured connection mode.
+/// SQLite is used until one of the SurrealDB modes is configured.
+pub fn select(
+    mode: Option<&ConnectionMode>,
+    sqlite: Option<Database>,
+    surreal: Option<SurrealDatabase>,
+) -> Result<Box<dyn Repositories>> {
+    match mode {
+        None | Some(ConnectionMode::Sqlite) => {
+            let db = sqlite.ok_or_else(|| anyhow!("No database is currently open"))?;
+            Ok(Box::new(SqliteRepository::new(db)))
+        }
+        Some(_) => {
+            let db = surreal.ok_or_else(|| anyhow!("No SurrealDB connection is currently open"))?;
+            Ok(Box::new(SurrealRepository::new(db)))
+        }
+    }
+}
+
+/// `WHERE` clause requiring every condition, empty without conditions
+fn where_all(conditions: &[String]) -> String {
+    if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", conditions.join(" AND "))
+    }
+}
+
+/// Error for deleting a product that is still referenced, if it is
+fn product_in_use(purchase_count: i64, sale_count: i64) -> Option<String> {
+    let mut reasons = Vec::new();
+    if purchase_count > 0 {
+        reasons.push(format!("used in {} purchase(s)", purchase_count));
+    }
+    if sale_count > 0 {
+        reasons.push(format!("used in {} sale(s)", sale_count));
+    }
+    if reasons.is_empty() {
+        None
+    } else {
+        Some(format!("Cannot delete product: it is {}", reasons.join(" and ")))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// SQLite
+// ---------------------------------------------------------------------------
+
+const CUSTOMER_COLUMNS: &str = "id, full_name, phone, address, email, notes, created_at, updated_at";
+const PRODUCT_COLUMNS: &str = "id, name, description, price, currency_id, supplier_id, stock_quantity, unit, image_path, bar_code, created_at, updated_at";
+const SALE_COLUMNS: &str = "id, customer_id, date, notes, currency_id, exchange_rate, total_amount, base_amount, paid_amount, additional_cost, created_at, updated_at";
+const SALE_ITEM_COLUMNS: &str = "id, sale_id, product_id, unit_id, per_price, amount, total, purchase_item_id, sale_type, created_at";
+const SALE_PAYMENT_COLUMNS: &str = "id, sale_id, account_id, currency_id, exchange_rate, amount, base_amount, date, created_at";
+// additional_cost is the sum of the purchase's additional cost rows, not the stored column
+const PURCHASE_COLUMNS: &str = "id, supplier_id, date, notes, currency_id, total_amount, (SELECT COALESCE(SUM(amount), 0) FROM purchase_additional_costs WHERE purchase_id = purchases.id), batch_number, created_at, updated_at";
+const PURCHASE_ITEM_COLUMNS: &str = "id, purchase_id, product_id, unit_id, per_price, amount, total, per_unit, cost_price, wholesale_price, retail_price, expiry_date, created_at";
+const ACCOUNT_COLUMNS: &str = "id, name, currency_id, coa_category_id, account_code, account_type, initial_balance, current_balance, is_active, notes, created_at, updated_at";
+const ACCOUNT_TRANSACTION_COLUMNS: &str = "id, account_id, transaction_type, amount, currency, rate, total, transaction_date, is_full, notes, created_at, updated_at";
+const CURRENCY_BALANCE_COLUMNS: &str = "id, account_id, currency_id, balance, updated_at";
+const JOURNAL_ENTRY_COLUMNS: &str = "id, entry_number, entry_date, description, reference_type, reference_id, created_at, updated_at";
+const JOURNAL_LINE_COLUMNS: &str = "id, journal_entry_id, account_id, currency_id, debit_amount, credit_amount, exchange_rate, base_amount, description, created_at";
+const USER_COLUMNS: &str = "id, username, email, full_name, phone, role, is_active, created_at, updated_at";
+
+fn customer_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Customer> {
+    Ok(Customer {
+        id: row.get(0)?,
+        full_name: row.get(1)?,
+        phone: row.get(2)?,
+        address: row.get(3)?,
+        email: row.get(4)?,
+        notes: row.get(5)?,
+        created_at: row.get(6)?,
+        updated_at: row.get(7)?,
+    })
+}
+
+fn product_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Product> {
+    Ok(Product {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        description: row.get(2)?,
+        price: row.get(3)?,
+        currency_id: row.get(4)?,
+        supplier_id: row.get(5)?,
+        stock_quantity: row.get(6)?,
+        unit: row.get(7)?,
+        image_path: row.get(8)?,
+        bar_code: row.get(9)?,
+        created_at: row.get(10)?,
+        updated_at: row.get(11)?,
+    })
+}
+
+fn sale_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Sale> {
+    Ok(Sale {
+        id: row.get(0)?,
+        customer_id: row.get(1)?,
+        date: row.get(2)?,
+        notes: row.get(3)?,
+        currency_id: row.get(4)?,
+        exchange_rate: row.get(5)?,
+        total_amount: row.get(6)?,
+        base_amount: row.get(7)?,
+        paid_amount: row.get(8)?,
+        additional_cost: row.get(9)?,
+        created_at: row.get(10)?,
+        updated_at: row.get(11)?,
+    })
+}
+
+fn sale_item_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<SaleItem> {
+    Ok(SaleItem {
+        id: row.get(0)?,
+        sale_id: row.get(1)?,
+        product_id: row.get(2)?,
+        unit_id: row.get(3)?,
+        per_price: row.get(4)?,
+        amount: row.get(5)?,
+        total: row.get(6)?,
+        purchase_item_id: row.get(7)?,
+        sale_type: row.get(8)?,
+        created_at: row.get(9)?,
+    })
+}
+
+fn sale_payment_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<SalePayment> {
+    Ok(SalePayment {
+        id: row.get(0)?,
+        sale_id: row.get(1)?,
+        account_id: row.get(2)?,
+        currency_id: row.get(3)?,
+        exchange_rate: row.get(4)?,
+        amount: row.get(5)?,
+        base_amount: row.get(6)?,
+        date: row.get(7)?,
+        created_at: row.get(8)?,
+    })
+}
+
+fn purchase_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Purchase> {
+    Ok(Purchase {
+        id: row.get(0)?,
+        supplier_id: row.get(1)?,
+        date: row.get(2)?,
+        notes: row.get(3)?,
+        currency_id: row.get(4)?,
+        total_amount: row.get(5)?,
+        additional_cost: row.get(6)?,
+        batch_number: row.get(7)?,
+        created_at: row.get(8)?,
+        updated_at: row.get(9)?,
+    })
+}
+
+fn purchase_item_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<PurchaseItem> {
+    Ok(PurchaseItem {
+        id: row.get(0)?,
+        purchase_id: row.get(1)?,
+        product_id: row.get(2)?,
+        unit_id: row.get(3)?,
+        per_price: row.get(4)?,
+        amount: row.get(5)?,
+        total: row.get(6)?,
+        per_unit: row.get(7)?,
+        cost_price: row.get(8)?,
+        wholesale_price: row.get(9)?,
+        retail_price: row.get(10)?,
+        expiry_date: row.get(11)?,
+        created_at: row.get(12)?,
+    })
+}
+
+fn account_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Account> {
+    Ok(Account {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        currency_id: row.get(2)?,
+        coa_category_id: row.get(3)?,
+        account_code: row.get(4)?,
+        account_type: row.get(5)?,
+        initial_balance: row.get(6)?,
+        current_balance: row.get(7)?,
+        is_active: row.get::<_, i64>(8)? != 0,
+        notes: row.get(9)?,
+        created_at: row.get(10)?,
+        updated_at: row.get(11)?,
+    })
+}
+
+fn account_transaction_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<AccountTransaction> {
+    Ok(AccountTransaction {
+        id: row.get(0)?,
+        account_id: row.get(1)?,
+        transaction_type: row.get(2)?,
+        amount: row.get(3)?,
+        currency: row.get(4)?,
+        rate: row.get(5)?,
+        total: row.get(6)?,
+        transaction_date: row.get(7)?,
+        is_full: row.get::<_, i64>(8)? != 0,
+        notes: row.get(9)?,
+        created_at: row.get(10)?,
+        updated_at: row.get(11)?,
+    })
+}
+
+fn currency_balance_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<AccountCurrencyBalance> {
+    Ok(AccountCurrencyBalance {
+        id: row.get(0)?,
+        account_id: row.get(1)?,
+        currency_id: row.get(2)?,
+        balance: row.get(3)?,
+        updated_at: row.get(4)?,
+    })
+}
+
+fn journal_entry_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<JournalEntry> {
+    Ok(JournalEntry {
+        id: row.get(0)?,
+        entry_number: row.get(1)?,
+        entry_date: row.get(2)?,
+        description: row.get(3)?,
+        reference_type: row.get(4)?,
+        reference_id: row.get(5)?,
+        created_at: row.get(6)?,
+        updated_at: row.get(7)?,
+    })
+}
+
+fn journal_line_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<JournalEntryLine> {
+    Ok(JournalEntryLine {
+        id: row.get(0)?,
+        journal_entry_id: row.get(1)?,
+        account_id: row.get(2)?,
+        currency_id: row.get(3)?,
+        debit_amount: row.get(4)?,
+        credit_amount: row.get(5)?,
+        exchange_rate: row.get(6)?,
+        base_amount: row.get(7)?,
+        description: row.get(8)?,
+        created_at: row.get(9)?,
+    })
+}
+
+pub(crate) const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// A UTC timestamp stored as `YYYY-MM-DD HH:MM:SS` text
+fn parse_timestamp(text: Option<String>) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(&text?, TIMESTAMP_FORMAT).ok().map(|t| t.and_utc())
+}
+
+fn user_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<User> {
+    Ok(User {
+        id: row.get(0)?,
+        username: row.get(1)?,
+        email: row.get(2)?,
+        full_name: row.get(3)?,
+        phone: row.get(4)?,
+        role: row.get(5)?,
+        is_active: row.get(6)?,
+        created_at: row.get(7)?,
+        updated_at: row.get(8)?,
+    })
+}
+
+/// Shape of a paginated list query against one SQLite table
+struct SqliteList<'a> {
+    table: &'a str,
+    columns: &'a str,
+    /// Conditions OR-ed together when searching; `?1` is the `%term%` pattern
+    search: &'a [&'a str],
+    sortable: &'a [&'a str],
+    default_direction: &'a str,
+    default_order: &'a str,
+    /// Leave out records that were moved to the trash
+    soft_delete: bool,
+}
+
+pub struct SqliteRepository {
+    db: Database,
+}
+
+impl SqliteRepository {
+    pub fn new(db: Database) -> Self {
+        SqliteRepository { db }
+    }
+
+    fn one<T, F>(&self, sql: &str, id: i64, f: F) -> Result<Option<T>>
+    where
+        F: FnMut(&rusqlite::Row<'_>) -> rusqlite::Result<T>,
+    {
+        Ok(self.db.query(sql, &[&id as &dyn rusqlite::ToSql], f)?.into_iter().next())
+    }
+
+    fn count(&self, sql: &str, id: i64) -> Result<i64> {
+        Ok(self.one(sql, id, |row| row.get(0))?.unwrap_or(0))
+    }
+
+    /// Totals per currency of `table` (sales or purchases) within the period
+    fn period_totals(&self, table: &str, from: Option<&str>, to: Option<&str>) -> Result<Vec<PeriodTotals>> {
+        let sql = format!(
+            "SELECT currency_id, COUNT(*), COALESCE(SUM(total_amount), 0) FROM {}
+             WHERE deleted_at IS NULL AND (?1 IS NULL OR substr(date, 1, 10) >= ?1) AND (?2 IS NULL OR substr(date, 1, 10) <= ?2)
+             GROUP BY currency_id ORDER BY currency_id",
+            table
+        );
+        self.db.query(&sql, &[&from as &dyn rusqlite::ToSql, &to], |row| {
+            Ok(PeriodTotals { currency_id: row.get(0)?, count: row.get(1)?, total_amount: row.get(2)? })
+        })
+    }
+
+    fn page<T, F>(&self, list: &SqliteList<'_>, query: &ListQuery, f: F) -> Result<PaginatedResponse<T>>
+    where
+        F: FnMut(&rusqlite::Row<'_>) -> rusqlite::Result<T>,
+    {
+        let pattern = query.search_term().map(|s| format!("%{}%", s));
+        let mut conditions = Vec::new();
+        if list.soft_delete {
+            conditions.push("deleted_at IS NULL".to_string());
+        }
+        if pattern.is_some() {
+            conditions.push(format!("({})", list.search.join(" OR ")));
+        }
+        let where_clause = where_all(&conditions);
+        let order_clause = query
+            .order_by(list.sortable, list.default_direction)
+            .unwrap_or_else(|| list.default_order.to_string());
+
+        let count_sql = format!("SELECT COUNT(*) FROM {} {}", list.table, where_clause);
+        let count_params: Vec<&dyn rusqlite::ToSql> = match &pattern {
+            Some(p) => vec![p],
+            None => vec![],
+        };
+        let total: i64 = self
+            .db
+            .query(&count_sql, &count_params, |row| row.get(0))?
+            .first()
+            .copied()
+            .unwrap_or(0);
+
+        // ?1 is always bound (possibly to NULL) so LIMIT/OFFSET keep their numbers
+        let sql = format!(
+            "SELECT {} FROM {} {} ORDER BY {} LIMIT ?2 OFFSET ?3",
+            list.columns, list.table, where_clause, order_clause
+        );
+        let offset = query.offset();
+        let items = self.db.query(
+            &sql,
+            &[&pattern as &dyn rusqlite::ToSql, &query.per_page, &offset],
+            f,
+        )?;
+
+        Ok(query.page_of(items, total))
+    }
+}
+
+#[async_trait]
+impl CustomerRepository for SqliteRepository {
+    async fn create_customer(&self, input: &CustomerInput) -> Result<Customer> {
+        let id = self.db.insert(
+            "INSERT INTO customers (full_name, phone, address, email, notes) VALUES (?, ?, ?, ?, ?)",
+            &[&input.full_name, &input.phone, &input.address, &input.email, &input.notes],
+        )?;
+        self.get_customer(id).await?.ok_or_else(|| anyhow!("Failed to retrieve created customer"))
+    }
+
+    async fn get_customer(&self, id: i64) -> Result<Option<Customer>> {
+        let sql = format!("SELECT {} FROM customers WHERE id = ?", CUSTOMER_COLUMNS);
+        self.one(&sql, id, customer_from_row)
+    }
+
+    async fn list_customers(&self, query: &ListQuery) -> Result<PaginatedResponse<Customer>> {
+        let list = SqliteList {
+            table: "customers",
+            columns: CUSTOMER_COLUMNS,
+            search: &["full_name LIKE ?1", "phone LIKE ?1", "email LIKE ?1"],
+            sortable: &["full_name", "created_at"],
+            default_direction: "ASC",
+            default_order: "created_at DESC",
+            soft_delete: true,
+        };
+        self.page(&list, query, customer_from_row)
+    }
+
+    async fn update_customer(&self, id: i64, input: &CustomerInput) -> Result<Customer> {
+        self.db.execute(
+            "UPDATE customers SET full_name = ?, phone = ?, address = ?, email = ?, notes = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+            &[&input.full_name, &input.phone, &input.address, &input.email, &input.notes, &id],
+        )?;
+        self.get_customer(id).await?.ok_or_else(|| anyhow!("Customer not found"))
+    }
+
+    async fn delete_customer(&self, id: i64) -> Result<()> {
+        self.db.execute("DELETE FROM customers WHERE id = ?", &[&id])?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ProductRepository for SqliteRepository {
+    async fn create_product(&self, input: &ProductInput) -> Result<Product> {
+        let id = self.db.insert(
+            "INSERT INTO products (name, description, price, currency_id, supplier_id, stock_quantity, unit, image_path, bar_code) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            &[
+                &input.name,
+                &input.description,
+                &input.price,
+                &input.currency_id,
+                &input.supplier_id,
+                &input.stock_quantity,
+                &input.unit,
+                &input.image_path,
+                &input.bar_code,
+            ],
+        )?;
+        self.get_product(id).await?.ok_or_else(|| anyhow!("Failed to retrieve created product"))
+    }
+
+    async fn get_product(&self, id: i64) -> Result<Option<Product>> {
+        let sql = format!("SELECT {} FROM products WHERE id = ?", PRODUCT_COLUMNS);
+        self.one(&sql, id, product_from_row)
+    }
+
+    async fn list_products(&self, query: &ListQuery) -> Result<PaginatedResponse<Product>> {
+        let list = SqliteList {
+            table: "products",
+            columns: PRODUCT_COLUMNS,
+            search: &["name LIKE ?1"],
+            sortable: &["name", "price", "stock_quantity", "created_at"],
+            default_direction: "ASC",
+            default_order: "created_at DESC",
+            soft_delete: true,
+        };
+        self.page(&list, query, product_from_row)
+    }
+
+    async fn update_product(&self, id: i64, input: &ProductInput) -> Result<Product> {
+        self.db.execute(
+            "UPDATE products SET name = ?, description = ?, price = ?, currency_id = ?, supplier_id = ?, stock_quantity = ?, unit = ?, image_path = ?, bar_code = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+            &[
+                &input.name,
+                &input.description,
+                &input.price,
+                &input.currency_id,
+                &input.supplier_id,
+                &input.stock_quantity,
+                &input.unit,
+                &input.image_path,
+                &input.bar_code,
+                &id,
+            ],
+        )?;
+        self.get_product(id).await?.ok_or_else(|| anyhow!("Product not found"))
+    }
+
+    async fn delete_product(&self, id: i64) -> Result<()> {
+        let purchase_count = self.count("SELECT COUNT(*) FROM purchase_items WHERE product_id = ?", id)?;
+        let sale_count = self.count("SELECT COUNT(*) FROM sale_items WHERE product_id = ?", id)?;
+        if let Some(message) = product_in_use(purchase_count, sale_count) {
+            return Err(anyhow!(message));
+        }
+        self.db.execute("DELETE FROM products WHERE id = ?", &[&id])?;
+        Ok(())
+    }
+}
+
+// The sale and purchase writes are the app's own, which also queue their webhook events
+#[async_trait]
+impl SaleRepository for SqliteRepository {
+    async fn create_sale(&self, input: &SaleInput) -> Result<Sale> {
+        crate::create_sale_internal(&self.db, input).map_err(|e| anyhow!(e))
+    }
+
+    async fn get_sale(&self, id: i64) -> Result<Option<(Sale, Vec<SaleItem>)>> {
+        let sql = format!("SELECT {} FROM sales WHERE id = ?", SALE_COLUMNS);
+        let Some(sale) = self.one(&sql, id, sale_from_row)? else {
+            return Ok(None);
+        };
+        let items_sql = format!("SELECT {} FROM sale_items WHERE sale_id = ? ORDER BY id", SALE_ITEM_COLUMNS);
+        let items = self.db.query(&items_sql, &[&id], sale_item_from_row)?;
+        Ok(Some((sale, items)))
+    }
+
+    async fn list_sales(&self, query: &ListQuery) -> Result<PaginatedResponse<Sale>> {
+        let list = SqliteList {
+            table: "sales",
+            columns: SALE_COLUMNS,
+            search: &[
+                "CAST(date AS TEXT) LIKE ?1",
+                "notes LIKE ?1",
+                "customer_id IN (SELECT id FROM customers WHERE full_name LIKE ?1 OR phone LIKE ?1)",
+            ],
+            sortable: &["date", "total_amount", "paid_amount", "created_at"],
+            default_direction: "DESC",
+            default_order: "date DESC, created_at DESC",
+            soft_delete: true,
+        };
+        self.page(&list, query, sale_from_row)
+    }
+
+    async fn update_sale(&self, id: i64, input: &SaleInput) -> Result<Sale> {
+        crate::update_sale_internal(&self.db, id, input).map_err(|e| anyhow!(e))
+    }
+
+    async fn create_sale_item(&self, sale_id: i64, input: &SaleItemInput) -> Result<SaleItem> {
+        crate::create_sale_item_internal(&self.db, sale_id, input).map_err(|e| anyhow!(e))
+    }
+
+    async fn update_sale_item(&self, id: i64, input: &SaleItemInput) -> Result<SaleItem> {
+        crate::update_sale_item_internal(&self.db, id, input).map_err(|e| anyhow!(e))
+    }
+
+    async fn create_sale_payment(&self, sale_id: i64, input: &SalePaymentInput) -> Result<SalePayment> {
+        crate::create_sale_payment_internal(&self.db, sale_id, input).map_err(|e| anyhow!(e))
+    }
+
+    async fn delete_sale_payment(&self, id: i64) -> Result<()> {
+        crate::delete_sale_payment_internal(&self.db, id).map_err(|e| anyhow!(e))
+    }
+
+    async fn list_sale_payments(&self, sale_id: i64) -> Result<Vec<SalePayment>> {
+        let sql = format!(
+            "SELECT {} FROM sale_payments WHERE sale_id = ? ORDER BY date DESC, created_at DESC",
+            SALE_PAYMENT_COLUMNS
+        );
+        Ok(self.db.query(&sql, &[&sale_id], sale_payment_from_row)?)
+    }
+
+    async fn delete_sale(&self, id: i64) -> Result<()> {
+        self.db.transaction(|db| {
+            for sql in [
+                "DELETE FROM sale_items WHERE sale_id = ?",
+                "DELETE FROM sale_payments WHERE sale_id = ?",
+                "DELETE FROM sale_additional_costs WHERE sale_id = ?",
+                "DELETE FROM sales WHERE id = ?",
+            ] {
+                db.execute(sql, &[&id]).map_err(|e| e.to_string())?;
+            }
+            Ok::<_, String>(())
+        }).map_err(|e| anyhow!(e))
+    }
+}
+
+#[async_trait]
+impl PurchaseRepository for SqliteRepository {
+    async fn create_purchase(&self, input: &PurchaseInput) -> Result<Purchase> {
+        crate::create_purchase_internal(&self.db, input).map_err(|e| anyhow!(e))
+    }
+
+    async fn get_purchase(&self, id: i64) -> Result<Option<(Purchase, Vec<PurchaseItem>)>> {
+        let sql = format!("SELECT {} FROM purchases WHERE id = ?", PURCHASE_COLUMNS);
+        let Some(purchase) = self.one(&sql, id, purchase_from_row)? else {
+            return Ok(None);
+        };
+        let items_sql = format!("SELECT {} FROM purchase_items WHERE purchase_id = ? ORDER BY id", PURCHASE_ITEM_COLUMNS);
+        let items = self.db.query(&items_sql, &[&id], purchase_item_from_row)?;
+        Ok(Some((purchase, items)))
+    }
+
+    async fn list_purchases(&self, query: &ListQuery) -> Result<PaginatedResponse<Purchase>> {
+        let list = SqliteList {
+            table: "purchases",
+            columns: PURCHASE_COLUMNS,
+            search: &[
+                "CAST(date AS TEXT) LIKE ?1",
+                "notes LIKE ?1",
+                "supplier_id IN (SELECT id FROM suppliers WHERE full_name LIKE ?1)",
+            ],
+            sortable: &["date", "total_amount", "created_at"],
+            default_direction: "DESC",
+            default_order: "date DESC, created_at DESC",
+            soft_delete: true,
+        };
+        self.page(&list, query, purchase_from_row)
+    }
+
+    async fn update_purchase(&self, id: i64, input: &PurchaseInput) -> Result<Purchase> {
+        crate::update_purchase_internal(&self.db, id, input).map_err(|e| anyhow!(e))
+    }
+
+    async fn create_purchase_payment(&self, purchase_id: i64, input: &PurchasePaymentInput) -> Result<PurchasePayment> {
+        crate::create_purchase_payment_internal(&self.db, purchase_id, input).map_err(|e| anyhow!(e))
+    }
+
+    async fn delete_purchase(&self, id: i64) -> Result<()> {
+        self.db.transaction(|db| {
+            for sql in [
+                "DELETE FROM purchase_items WHERE purchase_id = ?",
+                "DELETE FROM purchase_payments WHERE purchase_id = ?",
+                "DELETE FROM purchase_additional_costs WHERE purchase_id = ?",
+                "DELETE FROM purchases WHERE id = ?",
+            ] {
+                db.execute(sql, &[&id]).map_err(|e| e.to_string())?;
+            }
+            Ok::<_, String>(())
+        }).map_err(|e| anyhow!(e))
+    }
+}
+
+// Account and journal writes keep to the app's own functions, like the sale and purchase writes
+#[async_trait]
+impl AccountRepository for SqliteRepository {
+    async fn create_account(&self, input: &AccountInput) -> Result<Account> {
+        crate::create_account_internal(&self.db, input).map_err(|e| anyhow!(e))
+    }
+
+    async fn get_account(&self, id: i64) -> Result<Option<Account>> {
+        let sql = format!("SELECT {} FROM accounts WHERE id = ?", ACCOUNT_COLUMNS);
+        self.one(&sql, id, account_from_row)
+    }
+
+    async fn list_accounts(&self) -> Result<Vec<Account>> {
+        let sql = format!("SELECT {} FROM accounts WHERE deleted_at IS NULL ORDER BY name", ACCOUNT_COLUMNS);
+        self.db.query(&sql, &[], account_from_row)
+    }
+
+    async fn update_account(&self, id: i64, input: &AccountInput, is_active: bool) -> Result<Account> {
+        crate::update_account_internal(&self.db, id, input, is_active).map_err(|e| anyhow!(e))
+    }
+
+    async fn account_balance(&self, account_id: i64) -> Result<f64> {
+        crate::calculate_account_balance_internal(&self.db, account_id).map_err(|e| anyhow!(e))
+    }
+
+    async fn list_account_transactions(&self, account_id: i64) -> Result<Vec<AccountTransaction>> {
+        let sql = format!(
+            "SELECT {} FROM account_transactions WHERE account_id = ? ORDER BY transaction_date DESC, created_at DESC",
+            ACCOUNT_TRANSACTION_COLUMNS
+        );
+        self.db.query(&sql, &[&account_id], account_transaction_from_row)
+    }
+
+    async fn list_currency_balances(&self, account_id: i64) -> Result<Vec<AccountCurrencyBalance>> {
+        let sql = format!("SELECT {} FROM account_currency_balances WHERE account_id = ? ORDER BY currency_id", CURRENCY_BALANCE_COLUMNS);
+        self.db.query(&sql, &[&account_id], currency_balance_from_row)
+    }
+
+    async fn delete_account(&self, id: i64) -> Result<()> {
+        self.db.transaction(|db| {
+            for sql in [
+                "DELETE FROM account_transactions WHERE account_id = ?",
+                "DELETE FROM account_currency_balances WHERE account_id = ?",
+                "DELETE FROM accounts WHERE id = ?",
+            ] {
+                db.execute(sql, &[&id]).map_err(|e| e.to_string())?;
+            }
+            Ok::<_, String>(())
+        }).map_err(|e| anyhow!(e))
+    }
+
+    async fn deposit_account(&self, account_id: i64, input: &AccountTransactionInput) -> Result<AccountTransaction> {
+        crate::deposit_account_internal(&self.db, account_id, input).map_err(|e| anyhow!(e))
+    }
+
+    async fn withdraw_account(&self, account_id: i64, input: &AccountTransactionInput) -> Result<AccountTransaction> {
+        crate::withdraw_account_internal(&self.db, account_id, input).map_err(|e| anyhow!(e))
+    }
+}
+
+#[async_trait]
+impl JournalRepository for SqliteRepository {
+    async fn create_journal_entry(&self, input: &JournalEntryInput) -> Result<JournalEntry> {
+        crate::post_journal_entry_internal(&self.db, input).map_err(|e| anyhow!(e))
+    }
+
+    async fn get_journal_entry(&self, id: i64) -> Result<Option<(JournalEntry, Vec<JournalEntryLine>)>> {
+        let sql = format!("SELECT {} FROM journal_entries WHERE id = ?", JOURNAL_ENTRY_COLUMNS);
+        let Some(entry) = self.one(&sql, id, journal_entry_from_row)? else {
+            return Ok(None);
+        };
+        let lines_sql = format!("SELECT {} FROM journal_entry_lines WHERE journal_entry_id = ? ORDER BY id", JOURNAL_LINE_COLUMNS);
+        let lines = self.db.query(&lines_sql, &[&id], journal_line_from_row)?;
+        Ok(Some((entry, lines)))
+    }
+
+    async fn list_journal_entries(&self, query: &ListQuery) -> Result<PaginatedResponse<JournalEntry>> {
+        let list = SqliteList {
+            table: "journal_entries",
+            columns: JOURNAL_ENTRY_COLUMNS,
+            search: &["entry_number LIKE ?1", "description LIKE ?1"],
+            sortable: &[],
+            default_direction: "DESC",
+            default_order: "entry_date DESC, id DESC",
+            soft_delete: false,
+        };
+        self.page(&list, query, journal_entry_from_row)
+    }
+
+    async fn update_journal_entry(&self, id: i64, lines: &[JournalLineInput]) -> Result<JournalEntry> {
+        crate::update_journal_entry_internal(&self.db, id, lines).map_err(|e| anyhow!(e))
+    }
+}
+
+#[async_trait]
+impl UserRepository for SqliteRepository {
+    async fn create_user(&self, input: &UserInput) -> Result<User> {
+        let id = self.db.insert(
+            "INSERT INTO users (username, email, password_hash, full_name, phone, role, must_change_password) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            &[
+                &input.username,
+                &input.email,
+                &input.password_hash,
+                &input.full_name,
+                &input.phone,
+                &input.role,
+                &input.must_change_password,
+            ],
+        )?;
+        self.get_user(id).await?.ok_or_else(|| anyhow!("Failed to retrieve created user"))
+    }
+
+    async fn get_user(&self, id: i64) -> Result<Option<User>> {
+        let sql = format!("SELECT {} FROM users WHERE id = ?", USER_COLUMNS);
+        self.one(&sql, id, user_from_row)
+    }
+
+    async fn update_user(&self, id: i64, input: &UserProfileInput) -> Result<User> {
+        self.db.execute(
+            "UPDATE users SET username = ?, email = ?, full_name = ?, phone = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+            &[&input.username, &input.email, &input.full_name, &input.phone, &id],
+        )?;
+        self.get_user(id).await?.ok_or_else(|| anyhow!("User not found"))
+    }
+
+    async fn set_user_active(&self, id: i64, active: bool) -> Result<()> {
+        self.db.execute(
+            "UPDATE users SET is_active = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+            &[&active, &id],
+        )?;
+        Ok(())
+    }
+
+    async fn set_user_role(&self, id: i64, role: &str) -> Result<()> {
+        self.db.execute(
+            "UPDATE users SET role = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+            &[&role as &dyn rusqlite::ToSql, &id],
+        )?;
+        Ok(())
+    }
+
+    async fn delete_user(&self, id: i64) -> Result<()> {
+        self.db.transaction(|db| {
+            for sql in [
+                "DELETE FROM password_history WHERE user_id = ?",
+                "DELETE FROM api_tokens WHERE user_id = ?",
+                "DELETE FROM users WHERE id = ?",
+            ] {
+                db.execute(sql, &[&id]).map_err(|e| e.to_string())?;
+            }
+            Ok::<_, String>(())
+        }).map_err(|e| anyhow!(e))
+    }
+
+    async fn user_exists(&self, username: &str, email: &str) -> Result<bool> {
+        let rows = self.db.query(
+            "SELECT COUNT(*) FROM users WHERE username = ? OR email = ?",
+            &[&username, &email],
+            |row| row.get::<_, i64>(0),
+        )?;
+        Ok(rows.first().copied().unwrap_or(0) > 0)
+    }
+
+    async fn find_login(&self, login: &str) -> Result<Option<LoginRecord>> {
+        let sql = format!(
+            "SELECT {}, password_hash, failed_logins, locked_until, must_change_password FROM users WHERE username = ?1 OR email = ?1 LIMIT 1",
+            USER_COLUMNS
+        );
+        let rows = self.db.query(&sql, &[&login], |row| {
+            Ok(LoginRecord {
+                user: user_from_row(row)?,
+                password_hash: row.get(9)?,
+                failed_logins: row.get(10)?,
+                locked_until: parse_timestamp(row.get(11)?),
+                must_change_password: row.get::<_, i64>(12)? != 0,
+            })
+        })?;
+        Ok(rows.into_iter().next())
+    }
+
+    async fn list_users(&self, query: &ListQuery) -> Result<PaginatedResponse<User>> {
+        let list = SqliteList {
+            table: "users",
+            columns: USER_COLUMNS,
+            search: &["username LIKE ?1", "email LIKE ?1", "full_name LIKE ?1", "phone LIKE ?1"],
+            sortable: &["username", "email", "full_name", "phone", "role", "is_active", "created_at"],
+            default_direction: "ASC",
+            default_order: "created_at DESC",
+            soft_delete: false,
+        };
+        self.page(&list, query, user_from_row)
+    }
+
+    async fn count_users(&self, role: Option<&str>) -> Result<i64> {
+        let rows = self.db.query(
+            "SELECT COUNT(*) FROM users WHERE ?1 IS NULL OR role = ?1",
+            &[&role as &dyn rusqlite::ToSql],
+            |row| row.get::<_, i64>(0),
+        )?;
+        Ok(rows.first().copied().unwrap_or(0))
+    }
+
+    async fn count_active_users(&self, role: Option<&str>) -> Result<i64> {
+        let rows = self.db.query(
+            "SELECT COUNT(*) FROM users WHERE is_active = 1 AND (?1 IS NULL OR role = ?1)",
+            &[&role as &dyn rusqlite::ToSql],
+            |row| row.get::<_, i64>(0),
+        )?;
+        Ok(rows.first().copied().unwrap_or(0))
+    }
+
+    async fn set_login_failures(&self, user_id: i64, failed_logins: i64, locked_until: Option<DateTime<Utc>>) -> Result<()> {
+        let locked_until = locked_until.map(|t| t.format(TIMESTAMP_FORMAT).to_string());
+        self.db.execute(
+            "UPDATE users SET failed_logins = ?, locked_until = ? WHERE id = ?",
+            &[&failed_logins, &locked_until, &user_id],
+        )?;
+        Ok(())
+    }
+
+    async fn set_password(&self, user_id: i64, password_hash: &str, must_change_password: bool) -> Result<()> {
+        self.db.transaction(|db| {
+            db.execute(
+                "INSERT INTO password_history (user_id, password_hash) SELECT id, password_hash FROM users WHERE id = ?",
+                &[&user_id],
+            )
+            .map_err(|e| e.to_string())?;
+            db.execute(
+                "UPDATE users SET password_hash = ?, must_change_password = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+                &[&password_hash as &dyn rusqlite::ToSql, &must_change_password, &user_id],
+            )
+            .map_err(|e| e.to_string())?;
+            Ok::<_, String>(())
+        }).map_err(|e| anyhow!(e))
+    }
+
+    async fn password_history(&self, user_id: i64, limit: i64) -> Result<Vec<String>> {
+        Ok(self.db.query(
+            "SELECT password_hash FROM password_history WHERE user_id = ? ORDER BY id DESC LIMIT ?",
+            &[&user_id, &limit],
+            |row| row.get(0),
+        )?)
+    }
+
+    async fn get_two_factor(&self, user_id: i64) -> Result<TwoFactorRecord> {
+        let record = self.one(
+            "SELECT totp_secret, totp_enabled, totp_last_step, recovery_codes FROM users WHERE id = ?",
+            user_id,
+            |row| {
+                let recovery_codes: String = row.get(3)?;
+                Ok(TwoFactorRecord {
+                    secret: row.get(0)?,
+                    enabled: row.get::<_, i64>(1)? != 0,
+                    last_step: row.get(2)?,
+                    recovery_codes: serde_json::from_str(&recovery_codes).unwrap_or_default(),
+                })
+            },
+        )?;
+        record.ok_or_else(|| anyhow!("User not found"))
+    }
+
+    async fn save_two_factor(&self, user_id: i64, record: &TwoFactorRecord) -> Result<()> {
+        let recovery_codes = serde_json::to_string(&record.recovery_codes)?;
+        self.db.execute(
+            "UPDATE users SET totp_secret = ?, totp_enabled = ?, totp_last_step = ?, recovery_codes = ? WHERE id = ?",
+            &[&record.secret as &dyn rusqlite::ToSql, &record.enabled, &record.last_step, &recovery_codes, &user_id],
+        )?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SettingsRepository for SqliteRepository {
+    async fn get_setting(&self, key: &str) -> Result<Option<serde_json::Value>> {
+        let rows = self.db.query(
+            "SELECT value FROM app_settings WHERE key = ?",
+            &[&key as &dyn rusqlite::ToSql],
+            |row| row.get::<_, String>(0),
+        )?;
+        rows.first().map(|value| Ok(serde_json::from_str(value)?)).transpose()
+    }
+
+    async fn set_setting(&self, key: &str, value: &serde_json::Value) -> Result<()> {
+        self.db.execute(
+            "INSERT INTO app_settings (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = CURRENT_TIMESTAMP",
+            &[&key as &dyn rusqlite::ToSql, &value.to_string()],
+        )?;
+        Ok(())
+    }
+}
+
+fn role_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<RoleRecord> {
+    let capabilities: String = row.get(2)?;
+    Ok(RoleRecord {
+        name: row.get(0)?,
+        description: row.get(1)?,
+        capabilities: serde_json::from_str(&capabilities).unwrap_or_default(),
+    })
+}
+
+#[async_trait]
+impl RoleRepository for SqliteRepository {
+    async fn list_roles(&self) -> Result<Vec<RoleRecord>> {
+        Ok(self.db.query("SELECT name, description, capabilities FROM roles ORDER BY name", &[], role_from_row)?)
+    }
+
+    async fn get_role(&self, name: &str) -> Result<Option<RoleRecord>> {
+        let rows = self.db.query(
+            "SELECT name, description, capabilities FROM roles WHERE name = ?",
+            &[&name as &dyn rusqlite::ToSql],
+            role_from_row,
+        )?;
+        Ok(rows.into_iter().next())
+    }
+
+    async fn save_role(&self, role: &RoleRecord) -> Result<()> {
+        let capabilities = serde_json::to_string(&role.capabilities)?;
+        self.db.execute(
+            "INSERT INTO roles (name, description, capabilities) VALUES (?1, ?2, ?3)
+             ON CONFLICT(name) DO UPDATE SET description = excluded.description,
+                capabilities = excluded.capabilities, updated_at = CURRENT_TIMESTAMP",
+            &[&role.name, &role.description, &capabilities],
+        )?;
+        Ok(())
+    }
+
+    async fn delete_role(&self, name: &str) -> Result<()> {
+        self.db.execute("DELETE FROM roles WHERE name = ?", &[&name as &dyn rusqlite::ToSql])?;
+        Ok(())
+    }
+}
+
+const AUDIT_COLUMNS: &str = "id, created_at, user_id, username, command, entity, entity_id, action, old_data, new_data, prev_hash, hash";
+
+fn audit_entry_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<AuditEntry> {
+    // Data that isn't valid JSON any more has been tampered with; verification reports it
+    let data = |index: usize| -> rusqlite::Result<Option<serde_json::Value>> {
+        let text: Option<String> = row.get(index)?;
+        Ok(text.map(|text| serde_json::from_str(&text).unwrap_or(serde_json::Value::String(text))))
+    };
+    Ok(AuditEntry {
+        id: row.get(0)?,
+        created_at: row.get(1)?,
+        user_id: row.get(2)?,
+        username: row.get(3)?,
+        command: row.get(4)?,
+        entity: row.get(5)?,
+        entity_id: row.get(6)?,
+        action: row.get(7)?,
+        before: data(8)?,
+        after: data(9)?,
+        prev_hash: row.get(10)?,
+        hash: row.get(11)?,
+    })
+}
+
+#[async_trait]
+impl AuditRepository for SqliteRepository {
+    async fn audit_entries(&self, after_id: i64, limit: i64) -> Result<Vec<AuditEntry>> {
+        Ok(self.db.query(
+            &format!("SELECT {} FROM audit_log WHERE id > ? ORDER BY id LIMIT ?", AUDIT_COLUMNS),
+            &[&after_id as &dyn rusqlite::ToSql, &limit],
+            audit_entry_from_row,
+        )?)
+    }
+
+    async fn last_sealed_audit_entry(&self) -> Result<Option<AuditEntry>> {
+        let rows = self.db.query(
+            &format!("SELECT {} FROM audit_log WHERE hash IS NOT NULL ORDER BY id DESC LIMIT 1", AUDIT_COLUMNS),
+            &[],
+            audit_entry_from_row,
+        )?;
+        Ok(rows.into_iter().next())
+    }
+
+    async fn seal_audit_entries(&self, hashes: &[(i64, String, String)]) -> Result<bool> {
+        self.db.with_connection(|conn| {
+            if !conn.is_autocommit() {
+                return Ok(false);
+            }
+            let tx = conn.transaction()?;
+            {
+                let mut stmt = tx.prepare("UPDATE audit_log SET prev_hash = ?, hash = ? WHERE id = ? AND hash IS NULL")?;
+                for (id, prev_hash, hash) in hashes {
+                    stmt.execute(rusqlite::params![prev_hash, hash, id])?;
+                }
+            }
+            tx.commit()?;
+            Ok(true)
+        })
+    }
+
+    async fn list_audit_log(&self, filter: &AuditFilter, query: &ListQuery) -> Result<PaginatedResponse<AuditEntry>> {
+        let where_clause = "WHERE (?1 IS NULL OR entity = ?1) AND (?2 IS NULL OR entity_id = ?2) AND (?3 IS NULL OR user_id = ?3)
+            AND (?4 IS NULL OR date(created_at) >= ?4) AND (?5 IS NULL OR date(created_at) <= ?5)";
+        let params: [&dyn rusqlite::ToSql; 5] = [&filter.entity, &filter.entity_id, &filter.user_id, &filter.from, &filter.to];
+        let total: i64 = self
+            .db
+            .query(&format!("SELECT COUNT(*) FROM audit_log {}", where_clause), &params, |row| row.get(0))?
+            .first()
+            .copied()
+            .unwrap_or(0);
+        let offset = query.offset();
+        let mut page_params = params.to_vec();
+        page_params.extend([&query.per_page as &dyn rusqlite::ToSql, &offset]);
+        let items = self.db.query(
+            &format!("SELECT {} FROM audit_log {} ORDER BY id DESC LIMIT ?6 OFFSET ?7", AUDIT_COLUMNS, where_clause),
+            &page_params,
+            audit_entry_from_row,
+        )?;
+        Ok(query.page_of(items, total))
+    }
+}
+
+#[async_trait]
+impl TrashRepository for SqliteRepository {
+    async fn soft_delete(&self, table: &str, id: i64, deleted_by: Option<i64>) -> Result<bool> {
+        let sql = format!(
+            "UPDATE {} SET deleted_at = CURRENT_TIMESTAMP, deleted_by = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ? AND deleted_at IS NULL",
+            table
+        );
+        Ok(self.db.execute(&sql, &[&deleted_by as &dyn rusqlite::ToSql, &id])? > 0)
+    }
+
+    async fn restore(&self, table: &str, id: i64) -> Result<bool> {
+        let sql = format!(
+            "UPDATE {} SET deleted_at = NULL, deleted_by = NULL, updated_at = CURRENT_TIMESTAMP WHERE id = ? AND deleted_at IS NOT NULL",
+            table
+        );
+        Ok(self.db.execute(&sql, &[&id])? > 0)
+    }
+
+    async fn list_deleted(&self, bin: &TrashBin, query: &ListQuery) -> Result<PaginatedResponse<TrashedRecord>> {
+        let pattern = query.search_term().map(|s| format!("%{}%", s));
+        let mut where_clause = "WHERE t.deleted_at IS NOT NULL".to_string();
+        if pattern.is_some() {
+            let search: Vec<String> = bin.label.iter().map(|column| format!("CAST(t.{} AS TEXT) LIKE ?1", column)).collect();
+            where_clause.push_str(&format!(" AND ({})", search.join(" OR ")));
+        }
+        let count_params: Vec<&dyn rusqlite::ToSql> = match &pattern {
+            Some(p) => vec![p],
+            None => vec![],
+        };
+        let total: i64 = self
+            .db
+            .query(&format!("SELECT COUNT(*) FROM {} t {}", bin.table, where_clause), &count_params, |row| row.get(0))?
+            .first()
+            .copied()
+            .unwrap_or(0);
+
+        let label: Vec<String> = bin.label.iter().map(|column| format!("CAST(t.{} AS TEXT)", column)).collect();
+        let sql = format!(
+            "SELECT t.id, t.deleted_at, t.deleted_by, u.username, {}, {} FROM {} t LEFT JOIN users u ON u.id = t.deleted_by {} ORDER BY t.deleted_at DESC, t.id DESC LIMIT ?2 OFFSET ?3",
+            bin.amount.map(|column| format!("t.{}", column)).unwrap_or_else(|| "NULL".to_string()),
+            label.join(", "),
+            bin.table,
+            where_clause
+        );
+        let offset = query.offset();
+        let items = self.db.query(&sql, &[&pattern as &dyn rusqlite::ToSql, &query.per_page, &offset], |row| {
+            let parts = (0..bin.label.len()).map(|i| row.get::<_, Option<String>>(5 + i)).collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(TrashedRecord {
+                id: row.get(0)?,
+                deleted_at: row.get(1)?,
+                deleted_by: row.get(2)?,
+                deleted_by_username: row.get(3)?,
+                amount: row.get(4)?,
+                label: join_label(parts),
+            })
+        })?;
+        Ok(query.page_of(items, total))
+    }
+
+    async fn purgeable(&self, table: &str, deleted_before: DateTime<Utc>, created_before: Option<DateTime<Utc>>) -> Result<Vec<i64>> {
+        let sql = format!(
+            "SELECT id FROM {} WHERE deleted_at IS NOT NULL AND deleted_at <= ?1 AND (?2 IS NULL OR created_at <= ?2) ORDER BY id",
+            table
+        );
+        let deleted_before = deleted_before.format(TIMESTAMP_FORMAT).to_string();
+        let created_before = created_before.map(|time| time.format(TIMESTAMP_FORMAT).to_string());
+        self.db.query(&sql, &[&deleted_before as &dyn rusqlite::ToSql, &created_before], |row| row.get(0))
+    }
+
+    async fn count_references(&self, _table: &str, id: i64, references: &[(&str, &str)]) -> Result<i64> {
+        let mut total = 0;
+        for (table, column) in references {
+            total += self.count(&format!("SELECT COUNT(*) FROM {} WHERE {} = ?", table, column), id)?;
+        }
+        Ok(total)
+    }
+
+    async fn purge_record(&self, table: &str, id: i64) -> Result<()> {
+        self.db.execute(&format!("DELETE FROM {} WHERE id = ? AND deleted_at IS NOT NULL", table), &[&id])?;
+        Ok(())
+    }
+}
+
+const API_TOKEN_COLUMNS: &str = "id, user_id, name, prefix, created_at, last_used_at, expires_at";
+
+fn api_token_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<ApiToken> {
+    Ok(ApiToken {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        name: row.get(2)?,
+        prefix: row.get(3)?,
+        created_at: row.get(4)?,
+        last_used_at: row.get(5)?,
+        expires_at: row.get(6)?,
+    })
+}
+
+#[async_trait]
+impl ApiTokenRepository for SqliteRepository {
+    async fn create_api_token(&self, input: &ApiTokenInput) -> Result<ApiToken> {
+        let expires_at = input.expires_at.map(|t| t.format(TIMESTAMP_FORMAT).to_string());
+        let id = self.db.insert(
+            "INSERT INTO api_tokens (user_id, name, prefix, token_hash, expires_at) VALUES (?, ?, ?, ?, ?)",
+            &[&input.user_id as &dyn rusqlite::ToSql, &input.name, &input.prefix, &input.token_hash, &expires_at],
+        )?;
+        self.get_api_token(id).await?.ok_or_else(|| anyhow!("Failed to retrieve created API token"))
+    }
+
+    async fn get_api_token(&self, id: i64) -> Result<Option<ApiToken>> {
+        let sql = format!("SELECT {} FROM api_tokens WHERE id = ?", API_TOKEN_COLUMNS);
+        self.one(&sql, id, api_token_from_row)
+    }
+
+    async fn list_api_tokens(&self, user_id: Option<i64>) -> Result<Vec<ApiToken>> {
+        let sql = format!(
+            "SELECT {} FROM api_tokens WHERE ?1 IS NULL OR user_id = ?1 ORDER BY created_at DESC, id DESC",
+            API_TOKEN_COLUMNS
+        );
+        self.db.query(&sql, &[&user_id], api_token_from_row)
+    }
+
+    async fn use_api_token(&self, token_hash: &str, now: DateTime<Utc>) -> Result<Option<ApiToken>> {
+        let now = now.format(TIMESTAMP_FORMAT).to_string();
+        let sql = format!(
+            "SELECT {} FROM api_tokens WHERE token_hash = ? AND (expires_at IS NULL OR expires_at > ?)",
+            API_TOKEN_COLUMNS
+        );
+        let tokens = self.db.query(&sql, &[&token_hash as &dyn rusqlite::ToSql, &now], api_token_from_row)?;
+        let Some(mut token) = tokens.into_iter().next() else {
+            return Ok(None);
+        };
+        self.db.execute("UPDATE api_tokens SET last_used_at = ? WHERE id = ?", &[&now as &dyn rusqlite::ToSql, &token.id])?;
+        token.last_used_at = Some(now);
+        Ok(Some(token))
+    }
+
+    async fn delete_api_token(&self, id: i64) -> Result<()> {
+        self.db.execute("DELETE FROM api_tokens WHERE id = ?", &[&id])?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ReportRepository for SqliteRepository {
+    async fn sales_totals(&self, from: Option<&str>, to: Option<&str>) -> Result<Vec<PeriodTotals>> {
+        self.period_totals("sales", from, to)
+    }
+
+    async fn purchases_totals(&self, from: Option<&str>, to: Option<&str>) -> Result<Vec<PeriodTotals>> {
+        self.period_totals("purchases", from, to)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// SurrealDB
+//
+// Records use integer ids taken from the `sequences` table, so ids (and the
+// record links between tables) line up with the SQLite rows.
+// ---------------------------------------------------------------------------
+
+const SURREAL_CUSTOMER_FIELDS: &str = "record::id(id) AS id, full_name, phone, address, email, notes, time::format(created_at, '%Y-%m-%d %H:%M:%S') AS created_at, time::format(updated_at, '%Y-%m-%d %H:%M:%S') AS updated_at";
+const SURREAL_PRODUCT_FIELDS: &str = "record::id(id) AS id, name, description, price, IF currency_id THEN record::id(currency_id) END AS currency_id, IF supplier_id THEN record::id(supplier_id) END AS supplier_id, stock_quantity, unit, image_path, bar_code, time::format(created_at, '%Y-%m-%d %H:%M:%S') AS created_at, time::format(updated_at, '%Y-%m-%d %H:%M:%S') AS updated_at";
+const SURREAL_SALE_FIELDS: &str = "record::id(id) AS id, record::id(customer_id) AS customer_id, date, notes, IF currency_id THEN record::id(currency_id) END AS currency_id, exchange_rate, total_amount, base_amount, paid_amount, additional_cost, time::format(created_at, '%Y-%m-%d %H:%M:%S') AS created_at, time::format(updated_at, '%Y-%m-%d %H:%M:%S') AS updated_at";
+const SURREAL_SALE_ITEM_FIELDS: &str = "record::id(id) AS id, record::id(sale_id) AS sale_id, record::id(product_id) AS product_id, record::id(unit_id) AS unit_id, per_price, amount, total, IF purchase_item_id THEN record::id(purchase_item_id) END AS purchase_item_id, sale_type, time::format(created_at, '%Y-%m-%d %H:%M:%S') AS created_at";
+const SURREAL_PURCHASE_FIELDS: &str = "record::id(id) AS id, record::id(supplier_id) AS supplier_id, date, notes, IF currency_id THEN record::id(currency_id) END AS currency_id, total_amount, math::sum((SELECT VALUE amount FROM purchase_additional_costs WHERE purchase_id = $parent.id)) AS additional_cost, batch_number, time::format(created_at, '%Y-%m-%d %H:%M:%S') AS created_at, time::format(updated_at, '%Y-%m-%d %H:%M:%S') AS updated_at";
+const SURREAL_PURCHASE_ITEM_FIELDS: &str = "record::id(id) AS id, record::id(purchase_id) AS purchase_id, record::id(product_id) AS product_id, record::id(unit_id) AS unit_id, per_price, amount, total, per_unit, cost_price, wholesale_price, retail_price, expiry_date, time::format(created_at, '%Y-%m-%d %H:%M:%S') AS created_at";
+const SURREAL_SALE_PAYMENT_FIELDS: &str = "record::id(id) AS id, record::id(sale_id) AS sale_id, IF account_id THEN record::id(account_id) END AS account_id, IF currency_id THEN record::id(currency_id) END AS currency_id, exchange_rate, amount, base_amount, date, time::format(created_at, '%Y-%m-%d %H:%M:%S') AS created_at";
+const SURREAL_ACCOUNT_FIELDS: &str = "record::id(id) AS id, name, IF currency_id THEN record::id(currency_id) END AS currency_id, IF coa_category_id THEN record::id(coa_category_id) END AS coa_category_id, account_code, account_type, initial_balance, current_balance, is_active != 0 AS is_active, notes, time::format(created_at, '%Y-%m-%d %H:%M:%S') AS created_at, time::format(updated_at, '%Y-%m-%d %H:%M:%S') AS updated_at";
+const SURREAL_PURCHASE_PAYMENT_FIELDS: &str = "record::id(id) AS id, record::id(purchase_id) AS purchase_id, IF account_id THEN record::id(account_id) END AS account_id, amount, currency, rate, total, date, notes, time::format(created_at, '%Y-%m-%d %H:%M:%S') AS created_at";
+const SURREAL_ACCOUNT_TRANSACTION_FIELDS: &str = "record::id(id) AS id, record::id(account_id) AS account_id, transaction_type, amount, currency, rate, total, transaction_date, is_full != 0 AS is_full, notes, time::format(created_at, '%Y-%m-%d %H:%M:%S') AS created_at, time::format(updated_at, '%Y-%m-%d %H:%M:%S') AS updated_at";
+const SURREAL_CURRENCY_BALANCE_FIELDS: &str = "record::id(id) AS id, record::id(account_id) AS account_id, record::id(currency_id) AS currency_id, balance, time::format(updated_at, '%Y-%m-%d %H:%M:%S') AS updated_at";
+const SURREAL_JOURNAL_ENTRY_FIELDS: &str = "record::id(id) AS id, entry_number, entry_date, description, reference_type, reference_id, time::format(created_at, '%Y-%m-%d %H:%M:%S') AS created_at, time::format(updated_at, '%Y-%m-%d %H:%M:%S') AS updated_at";
+const SURREAL_JOURNAL_LINE_FIELDS: &str = "record::id(id) AS id, record::id(journal_entry_id) AS journal_entry_id, record::id(account_id) AS account_id, record::id(currency_id) AS currency_id, debit_amount, credit_amount, exchange_rate, base_amount, description, time::format(created_at, '%Y-%m-%d %H:%M:%S') AS created_at";
+// Users registered before integer ids were introduced have random ids; they report id 0
+const SURREAL_USER_FIELDS: &str = "IF type::is::int(record::id(id)) THEN record::id(id) ELSE 0 END AS id, username, email, full_name, phone, role, is_active, time::format(created_at, '%Y-%m-%d %H:%M:%S') AS created_at, time::format(updated_at, '%Y-%m-%d %H:%M:%S') AS updated_at";
+
+/// Shape of a paginated list query against one SurrealDB table
+struct SurrealList<'a> {
+    table: &'a str,
+    fields: &'a str,
+    /// Conditions OR-ed together when searching; `$search` is the lowercased term
+    search: &'a [&'a str],
+    sortable: &'a [&'a str],
+    default_direction: &'a str,
+    default_order: &'a str,
+    /// Leave out records that were moved to the trash
+    soft_delete: bool,
+}
+
+/// SurrealQL expression that allocates the next integer id for the table named by
+/// the SurrealQL expression `table` (a bound `$param` or a quoted literal)
+pub(crate) fn next_id(table: &str) -> String {
+    format!("(UPSERT type::thing('sequences', {}) SET value += 1 RETURN VALUE value)[0]", table)
+}
+
+/// SurrealQL expression for one more than the highest number after `prefix` in `table.field`,
+/// as SQLite's `MAX(CAST(SUBSTR(...) AS INTEGER)) + 1`
+fn next_number(table: &str, field: &str, prefix: &str) -> String {
+    format!(
+        "(math::max((SELECT VALUE <int> string::slice({field}, {len}) FROM {table} WHERE string::starts_with({field} ?? '', '{prefix}') AND string::is::numeric(string::slice({field}, {len})))) ?? 0) + 1",
+        len = prefix.len()
+    )
+}
+
+/// SurrealQL expression for `prefix` and the integer `number` with at least six digits, as `{:06}`
+fn numbered(prefix: &str, number: &str) -> String {
+    format!(
+        "'{prefix}' + (IF {number} < 1000000 THEN string::slice('00000' + <string> {number}, -6) ELSE <string> {number} END)"
+    )
+}
+
+/// SurrealQL expression for the float `number` as text without SurrealDB's `f` suffix, as `{}` prints it
+fn display_float(number: &str) -> String {
+    format!("string::replace(<string> {number}, 'f', '')")
+}
+
+/// SurrealQL that adds `delta` to the balance of `account` in `currency`, opening the balance
+/// under the next id if the account has none in that currency yet
+fn move_balance(account: &str, currency: &str, delta: &str) -> String {
+    format!(
+        "IF (SELECT VALUE id FROM account_currency_balances WHERE account_id = {account} AND currency_id = {currency} LIMIT 1)[0] {{
+            UPDATE account_currency_balances SET balance = balance + {delta}, updated_at = time::now() WHERE account_id = {account} AND currency_id = {currency};
+        }} ELSE {{
+            CREATE type::thing('account_currency_balances', {balance_id}) SET account_id = {account}, currency_id = {currency}, balance = {delta}, updated_at = time::now();
+        }};",
+        balance_id = next_id("'account_currency_balances'"),
+    )
+}
+
+/// SurrealQL expression for the balance of `account` from its initial balance and transactions,
+/// as `calculate_account_balance_internal` computes it
+fn account_balance(account: &str) -> String {
+    format!(
+        "(({account}.initial_balance ?? 0)
+            + math::sum((SELECT VALUE total FROM account_transactions WHERE account_id = {account} AND transaction_type = 'deposit'))
+            - math::sum((SELECT VALUE total FROM account_transactions WHERE account_id = {account} AND transaction_type = 'withdraw')))"
+    )
+}
+
+/// SurrealQL expression for the `JournalLineInput`s in `lines` with their ids as record links
+fn journal_lines(lines: &str) -> String {
+    format!(
+        "(SELECT type::thing('accounts', account_id) AS account_id, type::thing('currencies', currency_id) AS currency_id, debit_amount, credit_amount, exchange_rate, description FROM {lines})"
+    )
+}
+
+/// SurrealQL that adds `$line` to `$entry` and moves its account's balance
+fn create_journal_line() -> String {
+    format!(
+        "CREATE type::thing('journal_entry_lines', {line_id}) SET journal_entry_id = $entry, account_id = $line.account_id, currency_id = $line.currency_id, debit_amount = $line.debit_amount, credit_amount = $line.credit_amount, exchange_rate = $line.exchange_rate, base_amount = (IF $line.debit_amount > 0 THEN $line.debit_amount ELSE $line.credit_amount END) * $line.exchange_rate, description = $line.description;
+        {balance}",
+        line_id = next_id("'journal_entry_lines'"),
+        balance = move_balance(
+            "$line.account_id",
+            "$line.currency_id",
+            "(IF $line.debit_amount > 0 THEN $line.debit_amount ELSE -$line.credit_amount END)"
+        ),
+    )
+}
+
+/// SurrealQL that posts `$journal` ({ entry_date, description, reference_type, reference_id })
+/// with `$lines` and moves the accounts' balances, as `create_journal_entry_internal` does
+fn post_journal_entry() -> String {
+    format!(
+        "LET $entry_number = {number};
+        LET $entry = type::thing('journal_entries', {entry_id});
+        CREATE $entry SET entry_number = {entry_number}, entry_date = $journal.entry_date, description = $journal.description, reference_type = $journal.reference_type, reference_id = $journal.reference_id;
+        FOR $line IN $lines {{
+            {line}
+        }};",
+        number = next_number("journal_entries", "entry_number", "J"),
+        entry_id = next_id("'journal_entries'"),
+        entry_number = numbered("J", "$entry_number"),
+        line = create_journal_line(),
+    )
+}
+
+/// SurrealQL that creates `$items` and `$additional_costs` for `$sale` or `$purchase` (`owner`)
+fn create_lines(owner: &str, item_set: &str) -> String {
+    format!(
+        "FOR $item IN $items {{ CREATE type::thing('{owner}_items', {item_id}) SET {item_set}; }};
+        FOR $cost IN $additional_costs {{ CREATE type::thing('{owner}_additional_costs', {cost_id}) SET {owner}_id = ${owner}, name = $cost.name, amount = $cost.amount; }};",
+        item_id = next_id(&format!("'{}_items'", owner)),
+        cost_id = next_id(&format!("'{}_additional_costs'", owner)),
+    )
+}
+
+/// Bindings for a sale or purchase with the totals of its items and additional costs
+fn with_totals<T: Serialize>(input: &T, items_total: f64, additional_cost: f64) -> Result<serde_json::Value> {
+    let mut bindings = serde_json::to_value(input)?;
+    bindings["total_amount"] = json!(items_total + additional_cost);
+    bindings["additional_cost"] = json!(additional_cost);
+    Ok(bindings)
+}
+
+/// Rows of statement `index`, decoded through JSON so they use plain serde rules
+fn take_rows<T: DeserializeOwned>(response: &mut ::surrealdb::Response, index: usize) -> Result<Vec<T>> {
+    let rows: Vec<serde_json::Value> = response.take(index)?;
+    rows.into_iter()
+        .map(|row| serde_json::from_value(row).map_err(|e| anyhow!("Failed to deserialize: {}", e)))
+        .collect()
+}
+
+/// Bindings for `input` plus the record `id`
+fn with_id<T: Serialize>(input: &T, id: i64) -> Result<serde_json::Value> {
+    let mut bindings = serde_json::to_value(input)?;
+    bindings["id"] = json!(id);
+    Ok(bindings)
+}
+
+pub struct SurrealRepository {
+    db: SurrealDatabase,
+}
+
+impl SurrealRepository {
+    pub fn new(db: SurrealDatabase) -> Self {
+        SurrealRepository { db }
+    }
+
+    async fn rows<T: DeserializeOwned>(&self, query: &str, bindings: serde_json::Value) -> Result<Vec<T>> {
+        let mut response = self.db.query_response(query, bindings).await?;
+        take_rows(&mut response, 0)
+    }
+
+    async fn one<T: DeserializeOwned>(&self, query: &str, id: i64) -> Result<Option<T>> {
+        Ok(self.rows(query, json!({ "id": id })).await?.into_iter().next())
+    }
+
+    async fn count(&self, query: &str, bindings: serde_json::Value) -> Result<i64> {
+        let mut response = self.db.query_response(query, bindings).await?;
+        let count: Option<i64> = response.take(0)?;
+        Ok(count.unwrap_or(0))
+    }
+
+    /// Totals per currency of `table` (sales or purchases) within the period
+    async fn period_totals(&self, table: &str, from: Option<&str>, to: Option<&str>) -> Result<Vec<PeriodTotals>> {
+        self.rows(
+            "SELECT IF currency_id THEN record::id(currency_id) END AS currency_id, count() AS count, math::sum(total_amount) AS total_amount
+             FROM type::table($table)
+             WHERE deleted_at = NONE AND (!$from OR string::slice(date, 0, 10) >= $from) AND (!$to OR string::slice(date, 0, 10) <= $to)
+             GROUP BY currency_id ORDER BY currency_id",
+            json!({ "table": table, "from": from, "to": to }),
+        )
+        .await
+    }
+
+    /// Create a record with the next sequence id and return that id
+    async fn create(&self, table: &str, set: &str, mut bindings: serde_json::Value) -> Result<i64> {
+        let query = format!(
+            "BEGIN TRANSACTION; LET $id = {}; CREATE type::thing($table, $id) SET {} RETURN VALUE record::id(id); COMMIT TRANSACTION;",
+            next_id("$table"),
+            set
+        );
+        bindings["table"] = json!(table);
+        let mut response = self.db.query_response(&query, bindings).await?;
+        // Statement 0 is the LET; BEGIN/COMMIT produce no results
+        let ids: Vec<i64> = response.take(1)?;
+        ids.into_iter().next().ok_or_else(|| anyhow!("Failed to create {} record", table))
+    }
+
+    /// Run a `BEGIN ... RETURN <id>; COMMIT` transaction and give the id it returns
+    async fn transaction(&self, query: &str, bindings: serde_json::Value) -> Result<i64> {
+        let mut response = self.db.query_response(query, bindings).await?;
+        let id: Option<i64> = response.take(0)?;
+        id.ok_or_else(|| anyhow!("The transaction returned no id"))
+    }
+
+    async fn page<T: DeserializeOwned>(&self, list: &SurrealList<'_>, query: &ListQuery) -> Result<PaginatedResponse<T>> {
+        let search = query.search_term().map(|s| s.to_lowercase());
+        let mut conditions = Vec::new();
+        if list.soft_delete {
+            conditions.push("deleted_at = NONE".to_string());
+        }
+        if search.is_some() {
+            conditions.push(format!("({})", list.search.join(" OR ")));
+        }
+        let where_clause = where_all(&conditions);
+        let order_clause = query
+            .order_by(list.sortable, list.default_direction)
+            .unwrap_or_else(|| list.default_order.to_string());
+        let sql = format!(
+            "RETURN count((SELECT id FROM type::table($table) {where_clause})); SELECT {fields} FROM type::table($table) {where_clause} ORDER BY {order_clause} LIMIT $limit START $start;",
+            fields = list.fields,
+        );
+        let bindings = json!({ "table": list.table, "search": search, "limit": query.per_page, "start": query.offset() });
+        let mut response = self.db.query_response(&sql, bindings).await?;
+        let total: Option<i64> = response.take(0)?;
+        let items = take_rows(&mut response, 1)?;
+        Ok(query.page_of(items, total.unwrap_or(0)))
+    }
+}
+
+#[async_trait]
+impl CustomerRepository for SurrealRepository {
+    async fn create_customer(&self, input: &CustomerInput) -> Result<Customer> {
+        let set = "full_name = $full_name, phone = $phone, address = $address, email = $email, notes = $notes";
+        let id = self.create("customers", set, serde_json::to_value(input)?).await?;
+        self.get_customer(id).await?.ok_or_else(|| anyhow!("Failed to retrieve created customer"))
+    }
+
+    async fn get_customer(&self, id: i64) -> Result<Option<Customer>> {
+        let sql = format!("SELECT {} FROM type::thing('customers', $id)", SURREAL_CUSTOMER_FIELDS);
+        self.one(&sql, id).await
+    }
+
+    async fn list_customers(&self, query: &ListQuery) -> Result<PaginatedResponse<Customer>> {
+        let list = SurrealList {
+            table: "customers",
+            fields: SURREAL_CUSTOMER_FIELDS,
+            search: &[
+                "string::lowercase(full_name) CONTAINS $search",
+                "string::lowercase(phone) CONTAINS $search",
+                "string::lowercase(email ?? '') CONTAINS $search",
+            ],
+            sortable: &["full_name", "created_at"],
+            default_direction: "ASC",
+            default_order: "created_at DESC",
+            soft_delete: true,
+        };
+        self.page(&list, query).await
+    }
+
+    async fn update_customer(&self, id: i64, input: &CustomerInput) -> Result<Customer> {
+        self.db
+            .query_response(
+                "UPDATE type::thing('customers', $id) SET full_name = $full_name, phone = $phone, address = $address, email = $email, notes = $notes, updated_at = time::now()",
+                with_id(input, id)?,
+            )
+            .await?;
+        self.get_customer(id).await?.ok_or_else(|| anyhow!("Customer not found"))
+    }
+
+    async fn delete_customer(&self, id: i64) -> Result<()> {
+        self.db.query_response("DELETE type::thing('customers', $id)", json!({ "id": id })).await?;
+        Ok(())
+    }
+}
+
+const SURREAL_PRODUCT_SET: &str = "name = $name, description = $description, price = $price, currency_id = IF $currency_id THEN type::thing('currencies', $currency_id) END, supplier_id = IF $supplier_id THEN type::thing('suppliers', $supplier_id) END, stock_quantity = $stock_quantity, unit = $unit, image_path = $image_path, bar_code = $bar_code";
+
+#[async_trait]
+impl ProductRepository for SurrealRepository {
+    async fn create_product(&self, input: &ProductInput) -> Result<Product> {
+        let id = self.create("products", SURREAL_PRODUCT_SET, serde_json::to_value(input)?).await?;
+        self.get_product(id).await?.ok_or_else(|| anyhow!("Failed to retrieve created product"))
+    }
+
+    async fn get_product(&self, id: i64) -> Result<Option<Product>> {
+        let sql = format!("SELECT {} FROM type::thing('products', $id)", SURREAL_PRODUCT_FIELDS);
+        self.one(&sql, id).await
+    }
+
+    async fn list_products(&self, query: &ListQuery) -> Result<PaginatedResponse<Product>> {
+        let list = SurrealList {
+            table: "products",
+            fields: SURREAL_PRODUCT_FIELDS,
+            search: &["string::lowercase(name) CONTAINS $search"],
+            sortable: &["name", "price", "stock_quantity", "created_at"],
+            default_direction: "ASC",
+            default_order: "created_at DESC",
+            soft_delete: true,
+        };
+        self.page(&list, query).await
+    }
+
+    async fn update_product(&self, id: i64, input: &ProductInput) -> Result<Product> {
+        let sql = format!(
+            "UPDATE type::thing('products', $id) SET {}, updated_at = time::now()",
+            SURREAL_PRODUCT_SET
+        );
+        self.db.query_response(&sql, with_id(input, id)?).await?;
+        self.get_product(id).await?.ok_or_else(|| anyhow!("Product not found"))
+    }
+
+    async fn delete_product(&self, id: i64) -> Result<()> {
+        let bindings = json!({ "id": id });
+        let purchase_count = self
+            .count("RETURN count((SELECT id FROM purchase_items WHERE product_id = type::thing('products', $id)))", bindings.clone())
+            .await?;
+        let sale_count = self
+            .count("RETURN count((SELECT id FROM sale_items WHERE product_id = type::thing('products', $id)))", bindings.clone())
+            .await?;
+        if let Some(message) = product_in_use(purchase_count, sale_count) {
+            return Err(anyhow!(message));
+        }
+        self.db.query_response("DELETE type::thing('products', $id)", bindings).await?;
+        Ok(())
+    }
+}
+
+const SURREAL_SALE_SET: &str = "customer_id = type::thing('customers', $customer_id), date = $date, notes = $notes, currency_id = IF $currency_id THEN type::thing('currencies', $currency_id) END, exchange_rate = $exchange_rate, total_amount = $total_amount, base_amount = $total_amount * $exchange_rate, additional_cost = $additional_cost";
+const SURREAL_SALE_ITEM_SET: &str = "sale_id = $sale, product_id = type::thing('products', $item.product_id), unit_id = type::thing('units', $item.unit_id), per_price = $item.per_price, amount = $item.amount, total = $item.per_price * $item.amount, purchase_item_id = IF $item.purchase_item_id THEN type::thing('purchase_items', $item.purchase_item_id) END, sale_type = $item.sale_type";
+/// The base currency, else the first one, as the SQLite sale functions pick it
+const SURREAL_BASE_CURRENCY: &str = "((SELECT VALUE id FROM currencies WHERE base = 1 LIMIT 1)[0] ?? (SELECT VALUE id FROM currencies ORDER BY id LIMIT 1)[0] ?? currencies:1)";
+const SURREAL_RECEIVABLE_ACCOUNT: &str = "(SELECT VALUE id FROM accounts WHERE account_type = 'Asset' AND string::contains(string::lowercase(name), 'receivable') AND deleted_at = NONE LIMIT 1)[0]";
+const SURREAL_REVENUE_ACCOUNT: &str = "(SELECT VALUE id FROM accounts WHERE account_type = 'Revenue' AND deleted_at = NONE LIMIT 1)[0]";
+const SURREAL_CASH_ACCOUNT: &str = "(SELECT VALUE id FROM accounts WHERE account_type = 'Asset' AND (string::contains(string::lowercase(name), 'cash') OR string::contains(string::lowercase(name), 'bank')) AND deleted_at = NONE LIMIT 1)[0]";
+
+/// Bindings for a sale with its totals
+fn sale_bindings(input: &SaleInput) -> Result<serde_json::Value> {
+    let items_total = input.items.iter().map(|item| item.per_price * item.amount).sum();
+    let additional_cost = input.additional_costs.iter().map(|cost| cost.amount).sum();
+    with_totals(input, items_total, additional_cost)
+}
+
+#[async_trait]
+impl SaleRepository for SurrealRepository {
+    async fn create_sale(&self, input: &SaleInput) -> Result<Sale> {
+        let query = format!(
+            "BEGIN TRANSACTION;
+            LET $id = {sale_id};
+            LET $sale = type::thing('sales', $id);
+            LET $currency = IF $currency_id THEN type::thing('currencies', $currency_id) ELSE {base_currency} END;
+            LET $label = 'Sale #' + <string> $id;
+            CREATE $sale SET {sale_set}, paid_amount = $paid_amount;
+            LET $receivable = {receivable};
+            LET $revenue = {revenue};
+            IF $receivable AND $revenue {{
+                LET $journal = {{ entry_date: $date, description: $notes, reference_type: 'sale', reference_id: $id }};
+                LET $lines = [
+                    {{ account_id: $receivable, currency_id: $currency, debit_amount: $sale.base_amount, credit_amount: 0.0, exchange_rate: $exchange_rate, description: $label }},
+                    {{ account_id: $revenue, currency_id: $currency, debit_amount: 0.0, credit_amount: $sale.base_amount, exchange_rate: $exchange_rate, description: $label }}
+                ];
+                {journal}
+            }};
+            IF $paid_amount > 0 {{
+                CREATE type::thing('sale_payments', {payment_id}) SET sale_id = $sale, currency_id = $currency, exchange_rate = $exchange_rate, amount = $paid_amount, base_amount = $paid_amount * $exchange_rate, date = $date;
+            }};
+            {lines}
+            RETURN $id;
+            COMMIT TRANSACTION;",
+            sale_id = next_id("'sales'"),
+            base_currency = SURREAL_BASE_CURRENCY,
+            sale_set = SURREAL_SALE_SET,
+            receivable = SURREAL_RECEIVABLE_ACCOUNT,
+            revenue = SURREAL_REVENUE_ACCOUNT,
+            journal = post_journal_entry(),
+            payment_id = next_id("'sale_payments'"),
+            lines = create_lines("sale", SURREAL_SALE_ITEM_SET),
+        );
+        let id = self.transaction(&query, sale_bindings(input)?).await?;
+        let sale = self.get_sale(id).await?.map(|(sale, _)| sale);
+        sale.ok_or_else(|| anyhow!("Failed to retrieve created sale"))
+    }
+
+    async fn get_sale(&self, id: i64) -> Result<Option<(Sale, Vec<SaleItem>)>> {
+        let sql = format!("SELECT {} FROM type::thing('sales', $id)", SURREAL_SALE_FIELDS);
+        let Some(sale) = self.one(&sql, id).await? else {
+            return Ok(None);
+        };
+        let items_sql = format!(
+            "SELECT {} FROM sale_items WHERE sale_id = type::thing('sales', $id) ORDER BY id",
+            SURREAL_SALE_ITEM_FIELDS
+        );
+        let items = self.rows(&items_sql, json!({ "id": id })).await?;
+        Ok(Some((sale, items)))
+    }
+
+    async fn list_sales(&self, query: &ListQuery) -> Result<PaginatedResponse<Sale>> {
+        let list = SurrealList {
+            table: "sales",
+            fields: SURREAL_SALE_FIELDS,
+            search: &[
+                "string::lowercase(date) CONTAINS $search",
+                "string::lowercase(notes ?? '') CONTAINS $search",
+                "string::lowercase(customer_id.full_name ?? '') CONTAINS $search",
+                "string::lowercase(customer_id.phone ?? '') CONTAINS $search",
+            ],
+            sortable: &["date", "total_amount", "paid_amount", "created_at"],
+            default_direction: "DESC",
+            default_order: "date DESC, created_at DESC",
+            soft_delete: true,
+        };
+        self.page(&list, query).await
+    }
+
+    async fn update_sale(&self, id: i64, input: &SaleInput) -> Result<Sale> {
+        let query = format!(
+            "BEGIN TRANSACTION;
+            LET $sale = type::thing('sales', $id);
+            IF !record::exists($sale) {{ THROW 'Sale not found' }};
+            UPDATE $sale SET {sale_set}, updated_at = time::now();
+            DELETE sale_items WHERE sale_id = $sale;
+            DELETE sale_additional_costs WHERE sale_id = $sale;
+            {lines}
+            RETURN $id;
+            COMMIT TRANSACTION;",
+            sale_set = SURREAL_SALE_SET,
+            lines = create_lines("sale", SURREAL_SALE_ITEM_SET),
+        );
+        self.transaction(&query, with_id(&sale_bindings(input)?, id)?).await?;
+        let sale = self.get_sale(id).await?.map(|(sale, _)| sale);
+        sale.ok_or_else(|| anyhow!("Failed to retrieve updated sale"))
+    }
+
+    async fn create_sale_item(&self, sale_id: i64, input: &SaleItemInput) -> Result<SaleItem> {
+        let query = format!(
+            "BEGIN TRANSACTION;
+            LET $sale = type::thing('sales', $sale_id);
+            IF !record::exists($sale) {{ THROW 'Sale not found' }};
+            LET $id = {item_id};
+            CREATE type::thing('sale_items', $id) SET {item_set};
+            UPDATE $sale SET total_amount = math::sum((SELECT VALUE total FROM sale_items WHERE sale_id = $sale)) + (additional_cost ?? 0), updated_at = time::now();
+            RETURN $id;
+            COMMIT TRANSACTION;",
+            item_id = next_id("'sale_items'"),
+            item_set = SURREAL_SALE_ITEM_SET,
+        );
+        let id = self.transaction(&query, json!({ "sale_id": sale_id, "item": input })).await?;
+        let sql = format!("SELECT {} FROM type::thing('sale_items', $id)", SURREAL_SALE_ITEM_FIELDS);
+        self.one(&sql, id).await?.ok_or_else(|| anyhow!("Failed to retrieve created sale item"))
+    }
+
+    async fn update_sale_item(&self, id: i64, input: &SaleItemInput) -> Result<SaleItem> {
+        let query = format!(
+            "BEGIN TRANSACTION;
+            LET $record = type::thing('sale_items', $id);
+            IF !record::exists($record) {{ THROW 'Sale item not found' }};
+            LET $sale = $record.sale_id;
+            UPDATE $record SET {item_set};
+            UPDATE $sale SET total_amount = math::sum((SELECT VALUE total FROM sale_items WHERE sale_id = $sale)) + (additional_cost ?? 0), updated_at = time::now();
+            RETURN $id;
+            COMMIT TRANSACTION;",
+            item_set = SURREAL_SALE_ITEM_SET,
+        );
+        self.transaction(&query, json!({ "id": id, "item": input })).await?;
+        let sql = format!("SELECT {} FROM type::thing('sale_items', $id)", SURREAL_SALE_ITEM_FIELDS);
+        self.one(&sql, id).await?.ok_or_else(|| anyhow!("Failed to retrieve updated sale item"))
+    }
+
+    async fn create_sale_payment(&self, sale_id: i64, input: &SalePaymentInput) -> Result<SalePayment> {
+        let query = format!(
+            "BEGIN TRANSACTION;
+            LET $sale = type::thing('sales', $sale_id);
+            IF !record::exists($sale) {{ THROW 'Sale not found' }};
+            LET $currency = IF $currency_id THEN type::thing('currencies', $currency_id) ELSE $sale.currency_id ?? {base_currency} END;
+            LET $base_amount = $amount * $exchange_rate;
+            LET $label = 'Payment for Sale #' + <string> $sale_id;
+            LET $id = {payment_id};
+            CREATE type::thing('sale_payments', $id) SET sale_id = $sale, account_id = IF $account_id THEN type::thing('accounts', $account_id) END, currency_id = $currency, exchange_rate = $exchange_rate, amount = $amount, base_amount = $base_amount, date = $date;
+            IF $account_id AND $currency.name {{
+                LET $account = type::thing('accounts', $account_id);
+                CREATE type::thing('account_transactions', {transaction_id}) SET account_id = $account, transaction_type = 'deposit', amount = $amount, currency = $currency.name, rate = $exchange_rate, total = $base_amount, transaction_date = $date, is_full = 0, notes = $label;
+                {deposit}
+                UPDATE $account SET current_balance = {balance}, updated_at = time::now();
+            }};
+            UPDATE $sale SET paid_amount = math::sum((SELECT VALUE base_amount FROM sale_payments WHERE sale_id = $sale)), updated_at = time::now();
+            LET $cash = {cash};
+            LET $receivable = {receivable};
+            IF $cash AND $receivable {{
+                LET $journal = {{ entry_date: $date, description: $label, reference_type: 'sale_payment', reference_id: $sale_id }};
+                LET $lines = [
+                    {{ account_id: $cash, currency_id: $currency, debit_amount: $base_amount, credit_amount: 0.0, exchange_rate: $exchange_rate, description: $label }},
+                    {{ account_id: $receivable, currency_id: $currency, debit_amount: 0.0, credit_amount: $base_amount, exchange_rate: $exchange_rate, description: $label }}
+                ];
+                {journal}
+            }};
+            RETURN $id;
+            COMMIT TRANSACTION;",
+            base_currency = SURREAL_BASE_CURRENCY,
+            payment_id = next_id("'sale_payments'"),
+            transaction_id = next_id("'account_transactions'"),
+            deposit = move_balance("$account", "$currency", "$amount"),
+            balance = account_balance("$account"),
+            cash = SURREAL_CASH_ACCOUNT,
+            receivable = SURREAL_RECEIVABLE_ACCOUNT,
+            journal = post_journal_entry(),
+        );
+        let mut bindings = serde_json::to_value(input)?;
+        bindings["sale_id"] = json!(sale_id);
+        let id = self.transaction(&query, bindings).await?;
+        let sql = format!("SELECT {} FROM type::thing('sale_payments', $id)", SURREAL_SALE_PAYMENT_FIELDS);
+        self.one(&sql, id).await?.ok_or_else(|| anyhow!("Failed to retrieve created sale payment"))
+    }
+
+    async fn delete_sale_payment(&self, id: i64) -> Result<()> {
+        // The paid amount is summed over `amount` here, as `delete_sale_payment_internal` does
+        self.transaction(
+            "BEGIN TRANSACTION;
+            LET $payment = type::thing('sale_payments', $id);
+            IF !record::exists($payment) { THROW 'Sale payment not found' };
+            LET $sale = $payment.sale_id;
+            DELETE $payment;
+            UPDATE $sale SET paid_amount = math::sum((SELECT VALUE amount FROM sale_payments WHERE sale_id = $sale)), updated_at = time::now();
+            RETURN $id;
+            COMMIT TRANSACTION;",
+            json!({ "id": id }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn list_sale_payments(&self, sale_id: i64) -> Result<Vec<SalePayment>> {
+        let sql = format!(
+            "SELECT {} FROM sale_payments WHERE sale_id = type::thing('sales', $id) ORDER BY date DESC, created_at DESC",
+            SURREAL_SALE_PAYMENT_FIELDS
+        );
+        self.rows(&sql, json!({ "id": sale_id })).await
+    }
+
+    async fn delete_sale(&self, id: i64) -> Result<()> {
+        self.db
+            .query_response(
+                "BEGIN TRANSACTION;
+                LET $sale = type::thing('sales', $id);
+                DELETE sale_items WHERE sale_id = $sale;
+                DELETE sale_payments WHERE sale_id = $sale;
+                DELETE sale_additional_costs WHERE sale_id = $sale;
+                DELETE $sale;
+                COMMIT TRANSACTION;",
+                json!({ "id": id }),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+const SURREAL_PURCHASE_SET: &str = "supplier_id = type::thing('suppliers', $supplier_id), date = $date, notes = $notes, currency_id = IF $currency_id THEN type::thing('currencies', $currency_id) END, total_amount = $total_amount";
+const SURREAL_PURCHASE_ITEM_SET: &str = "purchase_id = $purchase, product_id = type::thing('products', $item.product_id), unit_id = type::thing('units', $item.unit_id), per_price = $item.per_price, amount = $item.amount, total = $item.per_price * $item.amount, per_unit = $item.per_unit, cost_price = $item.cost_price, wholesale_price = $item.wholesale_price, retail_price = $item.retail_price, expiry_date = $item.expiry_date";
+
+/// Bindings for a purchase with its totals
+fn purchase_bindings(input: &PurchaseInput) -> Result<serde_json::Value> {
+    let items_total = input.items.iter().map(|item| item.per_price * item.amount).sum();
+    let additional_cost = input.additional_costs.iter().map(|cost| cost.amount).sum();
+    with_totals(input, items_total, additional_cost)
+}
+
+#[async_trait]
+impl PurchaseRepository for SurrealRepository {
+    async fn create_purchase(&self, input: &PurchaseInput) -> Result<Purchase> {
+        let query = format!(
+            "BEGIN TRANSACTION;
+            LET $batch = {batch};
+            LET $id = {purchase_id};
+            LET $purchase = type::thing('purchases', $id);
+            CREATE $purchase SET {purchase_set}, batch_number = {batch_number};
+            {lines}
+            RETURN $id;
+            COMMIT TRANSACTION;",
+            batch = next_number("purchases", "batch_number", "BATCH-"),
+            purchase_id = next_id("'purchases'"),
+            purchase_set = SURREAL_PURCHASE_SET,
+            batch_number = numbered("BATCH-", "$batch"),
+            lines = create_lines("purchase", SURREAL_PURCHASE_ITEM_SET),
+        );
+        let id = self.transaction(&query, purchase_bindings(input)?).await?;
+        let purchase = self.get_purchase(id).await?.map(|(purchase, _)| purchase);
+        purchase.ok_or_else(|| anyhow!("Failed to retrieve created purchase"))
+    }
+
+    async fn get_purchase(&self, id: i64) -> Result<Option<(Purchase, Vec<PurchaseItem>)>> {
+        let sql = format!("SELECT {} FROM type::thing('purchases', $id)", SURREAL_PURCHASE_FIELDS);
+        let Some(purchase) = self.one(&sql, id).await? else {
+            return Ok(None);
+        };
+        let items_sql = format!(
+            "SELECT {} FROM purchase_items WHERE purchase_id = type::thing('purchases', $id) ORDER BY id",
+            SURREAL_PURCHASE_ITEM_FIELDS
+        );
+        let items = self.rows(&items_sql, json!({ "id": id })).await?;
+        Ok(Some((purchase, items)))
+    }
+
+    async fn list_purchases(&self, query: &ListQuery) -> Result<PaginatedResponse<Purchase>> {
+        let list = SurrealList {
+            table: "purchases",
+            fields: SURREAL_PURCHASE_FIELDS,
+            search: &[
+                "string::lowercase(date) CONTAINS $search",
+                "string::lowercase(notes ?? '') CONTAINS $search",
+                "string::lowercase(supplier_id.full_name ?? '') CONTAINS $search",
+            ],
+            sortable: &["date", "total_amount", "created_at"],
+            default_direction: "DESC",
+            default_order: "date DESC, created_at DESC",
+            soft_delete: true,
+        };
+        self.page(&list, query).await
+    }
+
+    async fn update_purchase(&self, id: i64, input: &PurchaseInput) -> Result<Purchase> {
+        let query = format!(
+            "BEGIN TRANSACTION;
+            LET $purchase = type::thing('purchases', $id);
+            IF !record::exists($purchase) {{ THROW 'Purchase not found' }};
+            UPDATE $purchase SET {purchase_set}, updated_at = time::now();
+            DELETE purchase_items WHERE purchase_id = $purchase;
+            DELETE purchase_additional_costs WHERE purchase_id = $purchase;
+            {lines}
+            RETURN $id;
+            COMMIT TRANSACTION;",
+            purchase_set = SURREAL_PURCHASE_SET,
+            lines = create_lines("purchase", SURREAL_PURCHASE_ITEM_SET),
+        );
+        self.transaction(&query, with_id(&purchase_bindings(input)?, id)?).await?;
+        let purchase = self.get_purchase(id).await?.map(|(purchase, _)| purchase);
+        purchase.ok_or_else(|| anyhow!("Failed to retrieve updated purchase"))
+    }
+
+    async fn create_purchase_payment(&self, purchase_id: i64, input: &PurchasePaymentInput) -> Result<PurchasePayment> {
+        let query = format!(
+            "BEGIN TRANSACTION;
+            LET $purchase = type::thing('purchases', $purchase_id);
+            IF !record::exists($purchase) {{ THROW 'Purchase not found' }};
+            LET $total = $amount * $rate;
+            LET $id = {payment_id};
+            CREATE type::thing('purchase_payments', $id) SET purchase_id = $purchase, account_id = IF $account_id THEN type::thing('accounts', $account_id) END, amount = $amount, currency = $currency, rate = $rate, total = $total, date = $date, notes = $notes;
+            LET $currency_id = (SELECT VALUE id FROM currencies WHERE name = $currency LIMIT 1)[0];
+            IF $account_id AND $currency_id {{
+                LET $account = type::thing('accounts', $account_id);
+                LET $available = (SELECT VALUE balance FROM account_currency_balances WHERE account_id = $account AND currency_id = $currency_id LIMIT 1)[0] ?? 0;
+                IF $available < $amount {{
+                    THROW 'Insufficient balance in account. Available: ' + {available} + ', Required: ' + {required};
+                }};
+                CREATE type::thing('account_transactions', {transaction_id}) SET account_id = $account, transaction_type = 'withdraw', amount = $amount, currency = $currency, rate = $rate, total = $total, transaction_date = $date, is_full = 0, notes = IF type::is::string($notes) THEN 'Payment for Purchase #' + <string> $purchase_id END;
+                {withdraw}
+                UPDATE $account SET current_balance = {balance}, updated_at = time::now();
+            }};
+            RETURN $id;
+            COMMIT TRANSACTION;",
+            payment_id = next_id("'purchase_payments'"),
+            transaction_id = next_id("'account_transactions'"),
+            available = display_float("$available"),
+            required = display_float("$amount"),
+            withdraw = move_balance("$account", "$currency_id", "-$amount"),
+            balance = account_balance("$account"),
+        );
+        let mut bindings = serde_json::to_value(input)?;
+        bindings["purchase_id"] = json!(purchase_id);
+        let id = self.transaction(&query, bindings).await?;
+        let sql = format!("SELECT {} FROM type::thing('purchase_payments', $id)", SURREAL_PURCHASE_PAYMENT_FIELDS);
+        self.one(&sql, id).await?.ok_or_else(|| anyhow!("Failed to retrieve created purchase payment"))
+    }
+
+    async fn delete_purchase(&self, id: i64) -> Result<()> {
+        self.db
+            .query_response(
+                "BEGIN TRANSACTION;
+                LET $purchase = type::thing('purchases', $id);
+                DELETE purchase_items WHERE purchase_id = $purchase;
+                DELETE purchase_payments WHERE purchase_id = $purchase;
+                DELETE purchase_additional_costs WHERE purchase_id = $purchase;
+                DELETE $purchase;
+                COMMIT TRANSACTION;",
+                json!({ "id": id }),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+const SURREAL_EXPENSE_ACCOUNT: &str = "(SELECT VALUE id FROM accounts WHERE account_type = 'Expense' AND deleted_at = NONE LIMIT 1)[0]";
+
+impl SurrealRepository {
+    /// Record a deposit to or withdrawal from an account once `checks` pass on its `$balance`,
+    /// and post it with the journal `$lines`, between `$account` and `$counterpart`
+    async fn account_transaction(
+        &self,
+        account_id: i64,
+        input: &AccountTransactionInput,
+        transaction_type: &str,
+        checks: &str,
+        counterpart: &str,
+        lines: &str,
+    ) -> Result<AccountTransaction> {
+        let query = format!(
+            "BEGIN TRANSACTION;
+            LET $account = type::thing('accounts', $account_id);
+            IF !record::exists($account) {{ THROW 'Account not found' }};
+            LET $balance = {balance};
+            {checks}
+            LET $final_amount = IF $is_full THEN $balance ELSE $amount END;
+            LET $total = $final_amount * $rate;
+            LET $currency_id = (SELECT VALUE id FROM currencies WHERE name = $currency LIMIT 1)[0];
+            IF !$currency_id {{ THROW 'Currency not found' }};
+            LET $id = {transaction_id};
+            CREATE type::thing('account_transactions', $id) SET account_id = $account, transaction_type = $transaction_type, amount = $final_amount, currency = $currency, rate = $rate, total = $total, transaction_date = $transaction_date, is_full = IF $is_full THEN 1 ELSE 0 END, notes = $notes;
+            {movement}
+            UPDATE $account SET current_balance = {balance}, updated_at = time::now();
+            LET $counterpart = {counterpart};
+            IF $counterpart {{
+                LET $journal = {{ entry_date: $transaction_date, description: $notes, reference_type: 'account_' + $transaction_type, reference_id: NONE }};
+                LET $lines = {lines};
+                {journal}
+            }};
+            RETURN $id;
+            COMMIT TRANSACTION;",
+            balance = account_balance("$account"),
+            transaction_id = next_id("'account_transactions'"),
+            movement = move_balance(
+                "$account",
+                "$currency_id",
+                "(IF $transaction_type = 'deposit' THEN $final_amount ELSE -$final_amount END)"
+            ),
+            journal = post_journal_entry(),
+        );
+        let mut bindings = serde_json::to_value(input)?;
+        bindings["account_id"] = json!(account_id);
+        bindings["transaction_type"] = json!(transaction_type);
+        let id = self.transaction(&query, bindings).await?;
+        let sql = format!("SELECT {} FROM type::thing('account_transactions', $id)", SURREAL_ACCOUNT_TRANSACTION_FIELDS);
+        self.one(&sql, id).await?.ok_or_else(|| anyhow!("Failed to retrieve created transaction"))
+    }
+}
+
+#[async_trait]
+impl AccountRepository for SurrealRepository {
+    async fn create_account(&self, input: &AccountInput) -> Result<Account> {
+        let query = format!(
+            "BEGIN TRANSACTION;
+            LET $id = {account_id};
+            LET $account = type::thing('accounts', $id);
+            LET $currency = IF $currency_id THEN type::thing('currencies', $currency_id) END;
+            CREATE $account SET name = $name, currency_id = $currency, coa_category_id = IF $coa_category_id THEN type::thing('coa_categories', $coa_category_id) END, account_code = $account_code, account_type = $account_type, initial_balance = $initial_balance, current_balance = $initial_balance, is_active = 1, notes = $notes;
+            IF $currency {{
+                {opening}
+            }};
+            RETURN $id;
+            COMMIT TRANSACTION;",
+            account_id = next_id("'accounts'"),
+            opening = move_balance("$account", "$currency", "$initial_balance"),
+        );
+        let mut bindings = serde_json::to_value(input)?;
+        bindings["account_code"] = json!(input.code());
+        let id = self.transaction(&query, bindings).await?;
+        self.get_account(id).await?.ok_or_else(|| anyhow!("Failed to retrieve created account"))
+    }
+
+    async fn get_account(&self, id: i64) -> Result<Option<Account>> {
+        let sql = format!("SELECT {} FROM type::thing('accounts', $id)", SURREAL_ACCOUNT_FIELDS);
+        self.one(&sql, id).await
+    }
+
+    async fn list_accounts(&self) -> Result<Vec<Account>> {
+        let sql = format!("SELECT {} FROM accounts WHERE deleted_at = NONE ORDER BY name", SURREAL_ACCOUNT_FIELDS);
+        self.rows(&sql, json!({})).await
+    }
+
+    async fn update_account(&self, id: i64, input: &AccountInput, is_active: bool) -> Result<Account> {
+        let query = format!(
+            "BEGIN TRANSACTION;
+            LET $account = type::thing('accounts', $id);
+            IF !record::exists($account) {{ THROW 'Account not found' }};
+            UPDATE $account SET name = $name, currency_id = IF $currency_id THEN type::thing('currencies', $currency_id) END, coa_category_id = IF $coa_category_id THEN type::thing('coa_categories', $coa_category_id) END, account_code = $account_code, account_type = $account_type, initial_balance = $initial_balance, is_active = IF $is_active THEN 1 ELSE 0 END, notes = $notes, updated_at = time::now();
+            UPDATE $account SET current_balance = {balance};
+            RETURN $id;
+            COMMIT TRANSACTION;",
+            balance = account_balance("$account"),
+        );
+        let mut bindings = serde_json::to_value(input)?;
+        bindings["id"] = json!(id);
+        bindings["account_code"] = json!(input.code());
+        bindings["is_active"] = json!(is_active);
+        self.transaction(&query, bindings).await?;
+        self.get_account(id).await?.ok_or_else(|| anyhow!("Account not found"))
+    }
+
+    async fn account_balance(&self, account_id: i64) -> Result<f64> {
+        let query = format!(
+            "LET $account = type::thing('accounts', $id);
+            RETURN {};",
+            account_balance("$account")
+        );
+        let mut response = self.db.query_response(&query, json!({ "id": account_id })).await?;
+        let balance: Option<f64> = response.take(1)?;
+        Ok(balance.unwrap_or(0.0))
+    }
+
+    async fn list_account_transactions(&self, account_id: i64) -> Result<Vec<AccountTransaction>> {
+        let sql = format!(
+            "SELECT {} FROM account_transactions WHERE account_id = type::thing('accounts', $id) ORDER BY transaction_date DESC, created_at DESC",
+            SURREAL_ACCOUNT_TRANSACTION_FIELDS
+        );
+        self.rows(&sql, json!({ "id": account_id })).await
+    }
+
+    async fn list_currency_balances(&self, account_id: i64) -> Result<Vec<AccountCurrencyBalance>> {
+        let sql = format!(
+            "SELECT {} FROM account_currency_balances WHERE account_id = type::thing('accounts', $id) ORDER BY currency_id",
+            SURREAL_CURRENCY_BALANCE_FIELDS
+        );
+        self.rows(&sql, json!({ "id": account_id })).await
+    }
+
+    async fn delete_account(&self, id: i64) -> Result<()> {
+        self.db
+            .query_response(
+                "BEGIN TRANSACTION;
+                LET $account = type::thing('accounts', $id);
+                DELETE account_transactions WHERE account_id = $account;
+                DELETE account_currency_balances WHERE account_id = $account;
+                DELETE $account;
+                COMMIT TRANSACTION;",
+                json!({ "id": id }),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn deposit_account(&self, account_id: i64, input: &AccountTransactionInput) -> Result<AccountTransaction> {
+        self.account_transaction(
+            account_id,
+            input,
+            "deposit",
+            "IF $is_full AND $balance <= 0 { THROW 'Account has no balance to deposit' };
+            IF !$is_full AND $amount <= 0 { THROW 'Deposit amount must be greater than 0' };",
+            SURREAL_CASH_ACCOUNT,
+            "[
+                { account_id: $account, currency_id: $currency_id, debit_amount: $total, credit_amount: 0.0, exchange_rate: $rate, description: $notes },
+                { account_id: $counterpart, currency_id: $currency_id, debit_amount: 0.0, credit_amount: $total, exchange_rate: $rate, description: $notes }
+            ]",
+        )
+        .await
+    }
+
+    async fn withdraw_account(&self, account_id: i64, input: &AccountTransactionInput) -> Result<AccountTransaction> {
+        self.account_transaction(
+            account_id,
+            input,
+            "withdraw",
+            "IF $is_full AND $balance <= 0 { THROW 'Account has no balance to withdraw' };
+            IF !$is_full AND $amount <= 0 { THROW 'Withdrawal amount must be greater than 0' };
+            IF !$is_full AND $amount * $rate > $balance { THROW 'Insufficient balance for withdrawal' };",
+            SURREAL_EXPENSE_ACCOUNT,
+            "[
+                { account_id: $counterpart, currency_id: $currency_id, debit_amount: $total, credit_amount: 0.0, exchange_rate: $rate, description: $notes },
+                { account_id: $account, currency_id: $currency_id, debit_amount: 0.0, credit_amount: $total, exchange_rate: $rate, description: $notes }
+            ]",
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl JournalRepository for SurrealRepository {
+    async fn create_journal_entry(&self, input: &JournalEntryInput) -> Result<JournalEntry> {
+        let query = format!(
+            "BEGIN TRANSACTION;
+            LET $lines = {lines};
+            {journal}
+            RETURN record::id($entry);
+            COMMIT TRANSACTION;",
+            lines = journal_lines("$journal.lines"),
+            journal = post_journal_entry(),
+        );
+        let id = self.transaction(&query, json!({ "journal": input })).await?;
+        let entry = self.get_journal_entry(id).await?.map(|(entry, _)| entry);
+        entry.ok_or_else(|| anyhow!("Failed to retrieve created journal entry"))
+    }
+
+    async fn get_journal_entry(&self, id: i64) -> Result<Option<(JournalEntry, Vec<JournalEntryLine>)>> {
+        let sql = format!("SELECT {} FROM type::thing('journal_entries', $id)", SURREAL_JOURNAL_ENTRY_FIELDS);
+        let Some(entry) = self.one(&sql, id).await? else {
+            return Ok(None);
+        };
+        let lines_sql = format!(
+            "SELECT {} FROM journal_entry_lines WHERE journal_entry_id = type::thing('journal_entries', $id) ORDER BY id",
+            SURREAL_JOURNAL_LINE_FIELDS
+        );
+        let lines = self.rows(&lines_sql, json!({ "id": id })).await?;
+        Ok(Some((entry, lines)))
+    }
+
+    async fn list_journal_entries(&self, query: &ListQuery) -> Result<PaginatedResponse<JournalEntry>> {
+        let list = SurrealList {
+            table: "journal_entries",
+            fields: SURREAL_JOURNAL_ENTRY_FIELDS,
+            search: &[
+                "string::lowercase(entry_number) CONTAINS $search",
+                "string::lowercase(description ?? '') CONTAINS $search",
+            ],
+            sortable: &[],
+            default_direction: "DESC",
+            default_order: "entry_date DESC, id DESC",
+            soft_delete: false,
+        };
+        self.page(&list, query).await
+    }
+
+    async fn update_journal_entry(&self, id: i64, lines: &[JournalLineInput]) -> Result<JournalEntry> {
+        let query = format!(
+            "BEGIN TRANSACTION;
+            LET $entry = type::thing('journal_entries', $id);
+            IF !record::exists($entry) {{ THROW 'Journal entry not found' }};
+            FOR $line IN (SELECT account_id, currency_id, debit_amount, credit_amount FROM journal_entry_lines WHERE journal_entry_id = $entry) {{
+                {reverse}
+            }};
+            DELETE journal_entry_lines WHERE journal_entry_id = $entry;
+            FOR $line IN {lines} {{
+                {line}
+                IF $line.currency_id.name {{
+                    CREATE type::thing('account_transactions', {transaction_id}) SET account_id = $line.account_id, transaction_type = IF $line.debit_amount > 0 THEN 'deposit' ELSE 'withdraw' END, amount = IF $line.debit_amount > 0 THEN $line.debit_amount ELSE $line.credit_amount END, currency = $line.currency_id.name, rate = $line.exchange_rate, total = (IF $line.debit_amount > 0 THEN $line.debit_amount ELSE $line.credit_amount END) * $line.exchange_rate, transaction_date = $entry.entry_date, is_full = 0, notes = $line.description;
+                }};
+            }};
+            UPDATE $entry SET updated_at = time::now();
+            RETURN $id;
+            COMMIT TRANSACTION;",
+            reverse = move_balance(
+                "$line.account_id",
+                "$line.currency_id",
+                "(IF $line.debit_amount > 0 THEN -$line.debit_amount ELSE $line.credit_amount END)"
+            ),
+            lines = journal_lines("$new_lines"),
+            line = create_journal_line(),
+            transaction_id = next_id("'account_transactions'"),
+        );
+        self.transaction(&query, json!({ "id": id, "new_lines": lines })).await?;
+        let entry = self.get_journal_entry(id).await?.map(|(entry, _)| entry);
+        entry.ok_or_else(|| anyhow!("Failed to retrieve updated journal entry"))
+    }
+}
+
+#[async_trait]
+impl UserRepository for SurrealRepository {
+    async fn create_user(&self, input: &UserInput) -> Result<User> {
+        let set = "username = $username, email = $email, password_hash = $password_hash,
+            full_name = $full_name, phone = $phone, role = $role, is_active = 1,
+            must_change_password = IF $must_change_password THEN 1 ELSE 0 END";
+        let id = self.create("users", set, serde_json::to_value(input)?).await?;
+        self.get_user(id).await?.ok_or_else(|| anyhow!("Failed to retrieve created user"))
+    }
+
+    async fn get_user(&self, id: i64) -> Result<Option<User>> {
+        let sql = format!("SELECT {} FROM type::thing('users', $id)", SURREAL_USER_FIELDS);
+        self.one(&sql, id).await
+    }
+
+    async fn update_user(&self, id: i64, input: &UserProfileInput) -> Result<User> {
+        self.db
+            .query_response(
+                "UPDATE type::thing('users', $id) SET username = $username, email = $email,
+                    full_name = $full_name, phone = $phone, updated_at = time::now()",
+                with_id(input, id)?,
+            )
+            .await?;
+        self.get_user(id).await?.ok_or_else(|| anyhow!("User not found"))
+    }
+
+    async fn set_user_active(&self, id: i64, active: bool) -> Result<()> {
+        self.db
+            .query_response(
+                "UPDATE type::thing('users', $id) SET is_active = IF $active THEN 1 ELSE 0 END, updated_at = time::now()",
+                json!({ "id": id, "active": active }),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn set_user_role(&self, id: i64, role: &str) -> Result<()> {
+        self.db
+            .query_response(
+                "UPDATE type::thing('users', $id) SET role = $role, updated_at = time::now()",
+                json!({ "id": id, "role": role }),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_user(&self, id: i64) -> Result<()> {
+        self.db
+            .query_response(
+                "BEGIN TRANSACTION;
+                LET $user = type::thing('users', $id);
+                DELETE password_history WHERE user_id = $user;
+                DELETE api_tokens WHERE user_id = $user;
+                DELETE $user;
+                COMMIT TRANSACTION;",
+                json!({ "id": id }),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn user_exists(&self, username: &str, email: &str) -> Result<bool> {
+        let count = self
+            .count(
+                "RETURN count((SELECT id FROM users WHERE username = $username OR email = $email))",
+                json!({ "username": username, "email": email }),
+            )
+            .await?;
+        Ok(count > 0)
+    }
+
+    async fn find_login(&self, login: &str) -> Result<Option<LoginRecord>> {
+        #[derive(Deserialize)]
+        struct Row {
+            #[serde(flatten)]
+            user: User,
+            password_hash: String,
+            failed_logins: i64,
+            locked_until: Option<String>,
+            must_change_password: i64,
+        }
+
+        let sql = format!(
+            "SELECT {}, password_hash, failed_logins ?? 0 AS failed_logins,
+                IF locked_until THEN time::format(locked_until, '%Y-%m-%d %H:%M:%S') END AS locked_until,
+                must_change_password ?? 0 AS must_change_password
+            FROM users WHERE username = $login OR email = $login LIMIT 1",
+            SURREAL_USER_FIELDS
+        );
+        let rows: Vec<Row> = self.rows(&sql, json!({ "login": login })).await?;
+        Ok(rows.into_iter().next().map(|row| LoginRecord {
+            user: row.user,
+            password_hash: row.password_hash,
+            failed_logins: row.failed_logins,
+            locked_until: parse_timestamp(row.locked_until),
+            must_change_password: row.must_change_password != 0,
+        }))
+    }
+
+    async fn list_users(&self, query: &ListQuery) -> Result<PaginatedResponse<User>> {
+        let list = SurrealList {
+            table: "users",
+            fields: SURREAL_USER_FIELDS,
+            search: &[
+                "string::lowercase(username) CONTAINS $search",
+                "string::lowercase(email) CONTAINS $search",
+                "string::lowercase(full_name ?? '') CONTAINS $search",
+                "string::lowercase(phone ?? '') CONTAINS $search",
+            ],
+            sortable: &["username", "email", "full_name", "phone", "role", "is_active", "created_at"],
+            default_direction: "ASC",
+            default_order: "created_at DESC",
+            soft_delete: false,
+        };
+        self.page(&list, query).await
+    }
+
+    async fn count_users(&self, role: Option<&str>) -> Result<i64> {
+        self.count(
+            "RETURN count((SELECT id FROM users WHERE !$role OR role = $role))",
+            json!({ "role": role }),
+        )
+        .await
+    }
+
+    async fn count_active_users(&self, role: Option<&str>) -> Result<i64> {
+        self.count(
+            "RETURN count((SELECT id FROM users WHERE is_active = 1 AND (!$role OR role = $role)))",
+            json!({ "role": role }),
+        )
+        .await
+    }
+
+    async fn set_login_failures(&self, user_id: i64, failed_logins: i64, locked_until: Option<DateTime<Utc>>) -> Result<()> {
+        self.db
+            .query_response(
+                "UPDATE type::thing('users', $id) SET failed_logins = $failed_logins,
+                    locked_until = IF $locked_until THEN <datetime> $locked_until END",
+                json!({ "id": user_id, "failed_logins": failed_logins, "locked_until": locked_until.map(|t| t.to_rfc3339()) }),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn set_password(&self, user_id: i64, password_hash: &str, must_change_password: bool) -> Result<()> {
+        self.db
+            .query_response(
+                "BEGIN TRANSACTION;
+                LET $user = type::thing('users', $id);
+                CREATE password_history SET user_id = $user, password_hash = $user.password_hash;
+                UPDATE $user SET password_hash = $password_hash,
+                    must_change_password = IF $must_change_password THEN 1 ELSE 0 END, updated_at = time::now();
+                COMMIT TRANSACTION;",
+                json!({ "id": user_id, "password_hash": password_hash, "must_change_password": must_change_password }),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn password_history(&self, user_id: i64, limit: i64) -> Result<Vec<String>> {
+        #[derive(Deserialize)]
+        struct Row {
+            password_hash: String,
+        }
+
+        let rows: Vec<Row> = self
+            .rows(
+                "SELECT password_hash, created_at FROM password_history
+                    WHERE user_id = type::thing('users', $id) ORDER BY created_at DESC LIMIT $limit",
+                json!({ "id": user_id, "limit": limit }),
+            )
+            .await?;
+        Ok(rows.into_iter().map(|row| row.password_hash).collect())
+    }
+
+    async fn get_two_factor(&self, user_id: i64) -> Result<TwoFactorRecord> {
+        #[derive(Deserialize)]
+        struct Row {
+            totp_secret: Option<String>,
+            totp_enabled: i64,
+            totp_last_step: Option<i64>,
+            recovery_codes: Vec<String>,
+        }
+
+        let row: Row = self
+            .one(
+                "SELECT totp_secret, totp_enabled ?? 0 AS totp_enabled, totp_last_step,
+                    recovery_codes ?? [] AS recovery_codes FROM type::thing('users', $id)",
+                user_id,
+            )
+            .await?
+            .ok_or_else(|| anyhow!("User not found"))?;
+        Ok(TwoFactorRecord {
+            secret: row.totp_secret,
+            enabled: row.totp_enabled != 0,
+            last_step: row.totp_last_step,
+            recovery_codes: row.recovery_codes,
+        })
+    }
+
+    async fn save_two_factor(&self, user_id: i64, record: &TwoFactorRecord) -> Result<()> {
+        self.db
+            .query_response(
+                "UPDATE type::thing('users', $id) SET totp_secret = $secret,
+                    totp_enabled = IF $enabled THEN 1 ELSE 0 END, totp_last_step = $last_step,
+                    recovery_codes = $recovery_codes",
+                json!({
+                    "id": user_id,
+                    "secret": record.secret,
+                    "enabled": record.enabled,
+                    "last_step": record.last_step,
+                    "recovery_codes": record.recovery_codes,
+                }),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SettingsRepository for SurrealRepository {
+    async fn get_setting(&self, key: &str) -> Result<Option<serde_json::Value>> {
+        let values: Vec<String> = self
+            .rows("SELECT VALUE value FROM type::thing('app_settings', $key)", json!({ "key": key }))
+            .await?;
+        values.first().map(|value| Ok(serde_json::from_str(value)?)).transpose()
+    }
+
+    async fn set_setting(&self, key: &str, value: &serde_json::Value) -> Result<()> {
+        self.db
+            .query_response(
+                "UPSERT type::thing('app_settings', $key) SET value = $value, updated_at = time::now()",
+                json!({ "key": key, "value": value.to_string() }),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RoleRepository for SurrealRepository {
+    async fn list_roles(&self) -> Result<Vec<RoleRecord>> {
+        self.rows("SELECT name, description, capabilities FROM roles ORDER BY name", json!({})).await
+    }
+
+    async fn get_role(&self, name: &str) -> Result<Option<RoleRecord>> {
+        let rows = self
+            .rows("SELECT name, description, capabilities FROM roles WHERE name = $name", json!({ "name": name }))
+            .await?;
+        Ok(rows.into_iter().next())
+    }
+
+    async fn save_role(&self, role: &RoleRecord) -> Result<()> {
+        let bindings = serde_json::to_value(role)?;
+        let updated: Vec<serde_json::Value> = self
+            .rows(
+                "UPDATE roles SET description = $description, capabilities = $capabilities, updated_at = time::now()
+                    WHERE name = $name RETURN VALUE name",
+                bindings.clone(),
+            )
+            .await?;
+        if updated.is_empty() {
+            self.create("roles", "name = $name, description = $description, capabilities = $capabilities", bindings)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn delete_role(&self, name: &str) -> Result<()> {
+        self.db.query_response("DELETE roles WHERE name = $name", json!({ "name": name })).await?;
+        Ok(())
+    }
+}
+
+const SURREAL_AUDIT_FIELDS: &str = "record::id(id) AS id, time::format(created_at, '%Y-%m-%d %H:%M:%S') AS created_at, user_id, username, command, entity, entity_id, action, old_data AS before, new_data AS after, prev_hash, hash";
+
+#[async_trait]
+impl AuditRepository for SurrealRepository {
+    async fn audit_entries(&self, after_id: i64, limit: i64) -> Result<Vec<AuditEntry>> {
+        self.rows(
+            &format!("SELECT {} FROM audit_log WHERE id > type::thing('audit_log', $after) ORDER BY id LIMIT $limit", SURREAL_AUDIT_FIELDS),
+            json!({ "after": after_id, "limit": limit }),
+        )
+        .await
+    }
+
+    async fn last_sealed_audit_entry(&self) -> Result<Option<AuditEntry>> {
+        let rows = self
+            .rows(
+                &format!("SELECT {} FROM audit_log WHERE hash != NONE ORDER BY id DESC LIMIT 1", SURREAL_AUDIT_FIELDS),
+                json!({}),
+            )
+            .await?;
+        Ok(rows.into_iter().next())
+    }
+
+    async fn seal_audit_entries(&self, hashes: &[(i64, String, String)]) -> Result<bool> {
+        let rows: Vec<serde_json::Value> =
+            hashes.iter().map(|(id, prev_hash, hash)| json!({ "id": id, "prev_hash": prev_hash, "hash": hash })).collect();
+        self.db
+            .query_response(
+                "BEGIN TRANSACTION;
+                FOR $row IN $rows {
+                    UPDATE type::thing('audit_log', $row.id) SET prev_hash = $row.prev_hash, hash = $row.hash WHERE hash = NONE;
+                };
+                COMMIT TRANSACTION;",
+                json!({ "rows": rows }),
+            )
+            .await?;
+        Ok(true)
+    }
+
+    async fn list_audit_log(&self, filter: &AuditFilter, query: &ListQuery) -> Result<PaginatedResponse<AuditEntry>> {
+        let where_clause = "WHERE (!$entity OR entity = $entity) AND (!$entity_id OR entity_id = $entity_id)
+            AND (!$user_id OR user_id = $user_id)
+            AND (!$from OR time::format(created_at, '%Y-%m-%d') >= $from) AND (!$to OR time::format(created_at, '%Y-%m-%d') <= $to)";
+        let sql = format!(
+            "RETURN count((SELECT id FROM audit_log {where_clause})); SELECT {fields} FROM audit_log {where_clause} ORDER BY id DESC LIMIT $limit START $start;",
+            fields = SURREAL_AUDIT_FIELDS,
+        );
+        let mut bindings = serde_json::to_value(filter)?;
+        bindings["limit"] = json!(query.per_page);
+        bindings["start"] = json!(query.offset());
+        let mut response = self.db.query_response(&sql, bindings).await?;
+        let total: Option<i64> = response.take(0)?;
+        let items = take_rows(&mut response, 1)?;
+        Ok(query.page_of(items, total.unwrap_or(0)))
+    }
+}
+
+#[async_trait]
+impl TrashRepository for SurrealRepository {
+    async fn soft_delete(&self, table: &str, id: i64, deleted_by: Option<i64>) -> Result<bool> {
+        let ids: Vec<i64> = self
+            .rows(
+                "UPDATE type::thing($table, $id) SET deleted_at = time::now(), deleted_by = $deleted_by ?? NONE, updated_at = time::now() WHERE deleted_at = NONE RETURN VALUE record::id(id)",
+                json!({ "table": table, "id": id, "deleted_by": deleted_by }),
+            )
+            .await?;
+        Ok(!ids.is_empty())
+    }
+
+    async fn restore(&self, table: &str, id: i64) -> Result<bool> {
+        let ids: Vec<i64> = self
+            .rows(
+                "UPDATE type::thing($table, $id) SET deleted_at = NONE, deleted_by = NONE, updated_at = time::now() WHERE deleted_at != NONE RETURN VALUE record::id(id)",
+                json!({ "table": table, "id": id }),
+            )
+            .await?;
+        Ok(!ids.is_empty())
+    }
+
+    async fn list_deleted(&self, bin: &TrashBin, query: &ListQuery) -> Result<PaginatedResponse<TrashedRecord>> {
+        let search = query.search_term().map(|s| s.to_lowercase());
+        let mut where_clause = "WHERE deleted_at != NONE".to_string();
+        if search.is_some() {
+            let conditions: Vec<String> = bin
+                .label
+                .iter()
+                .map(|field| format!("string::lowercase(<string> ({} ?? '')) CONTAINS $search", field))
+                .collect();
+            where_clause.push_str(&format!(" AND ({})", conditions.join(" OR ")));
+        }
+        let sql = format!(
+            "RETURN count((SELECT id FROM type::table($table) {where_clause}));
+            SELECT record::id(id) AS id, time::format(deleted_at, '%Y-%m-%d %H:%M:%S') AS deleted_at, deleted_by,
+                IF deleted_by THEN type::thing('users', deleted_by).username END AS deleted_by_username,
+                {amount} AS amount, [{label}] AS label
+            FROM type::table($table) {where_clause} ORDER BY deleted_at DESC LIMIT $limit START $start;",
+            amount = bin.amount.unwrap_or("NONE"),
+            label = bin.label.join(", "),
+        );
+        let bindings = json!({ "table": bin.table, "search": search, "limit": query.per_page, "start": query.offset() });
+        let mut response = self.db.query_response(&sql, bindings).await?;
+        let total: Option<i64> = response.take(0)?;
+        let rows: Vec<serde_json::Value> = response.take(1)?;
+        let items = rows
+            .into_iter()
+            .map(|row| {
+                let parts = row["label"].as_array().cloned().unwrap_or_default().into_iter().map(|part| match part {
+                    serde_json::Value::String(text) => Some(text),
+                    serde_json::Value::Null => None,
+                    other => Some(other.to_string()),
+                });
+                Ok(TrashedRecord {
+                    id: row["id"].as_i64().ok_or_else(|| anyhow!("Trashed record without an id"))?,
+                    label: join_label(parts),
+                    amount: row["amount"].as_f64(),
+                    deleted_at: row["deleted_at"].as_str().unwrap_or_default().to_string(),
+                    deleted_by: row["deleted_by"].as_i64(),
+                    deleted_by_username: row["deleted_by_username"].as_str().map(str::to_string),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(query.page_of(items, total.unwrap_or(0)))
+    }
+
+    async fn purgeable(&self, table: &str, deleted_before: DateTime<Utc>, created_before: Option<DateTime<Utc>>) -> Result<Vec<i64>> {
+        let mut sql = "SELECT VALUE record::id(id) FROM type::table($table) WHERE deleted_at != NONE AND deleted_at <= <datetime> $deleted_before".to_string();
+        if created_before.is_some() {
+            sql.push_str(" AND created_at <= <datetime> $created_before");
+        }
+        self.rows(
+            &sql,
+            json!({ "table": table, "deleted_before": deleted_before.to_rfc3339(), "created_before": created_before.map(|time| time.to_rfc3339()) }),
+        )
+        .await
+    }
+
+    async fn count_references(&self, table: &str, id: i64, references: &[(&str, &str)]) -> Result<i64> {
+        let mut total = 0;
+        for (referencing, column) in references {
+            let sql = format!("RETURN count((SELECT id FROM {} WHERE {} = type::thing($table, $id)))", referencing, column);
+            total += self.count(&sql, json!({ "table": table, "id": id })).await?;
+        }
+        Ok(total)
+    }
+
+    async fn purge_record(&self, table: &str, id: i64) -> Result<()> {
+        self.db
+            .query_response("DELETE type::thing($table, $id) WHERE deleted_at != NONE", json!({ "table": table, "id": id }))
+            .await?;
+        Ok(())
+    }
+}
+
+const SURREAL_API_TOKEN_FIELDS: &str = "record::id(id) AS id, record::id(user_id) AS user_id, name, prefix, time::format(created_at, '%Y-%m-%d %H:%M:%S') AS created_at, IF last_used_at THEN time::format(last_used_at, '%Y-%m-%d %H:%M:%S') END AS last_used_at, IF expires_at THEN time::format(expires_at, '%Y-%m-%d %H:%M:%S') END AS expires_at";
+
+#[async_trait]
+impl ApiTokenRepository for SurrealRepository {
+    async fn create_api_token(&self, input: &ApiTokenInput) -> Result<ApiToken> {
+        let set = "user_id = type::thing('users', $user_id), name = $name, prefix = $prefix, token_hash = $token_hash,
+            expires_at = IF $expires_at THEN <datetime> $expires_at END";
+        let id = self.create("api_tokens", set, serde_json::to_value(input)?).await?;
+        self.get_api_token(id).await?.ok_or_else(|| anyhow!("Failed to retrieve created API token"))
+    }
+
+    async fn get_api_token(&self, id: i64) -> Result<Option<ApiToken>> {
+        let sql = format!("SELECT {} FROM type::thing('api_tokens', $id)", SURREAL_API_TOKEN_FIELDS);
+        self.one(&sql, id).await
+    }
+
+    async fn list_api_tokens(&self, user_id: Option<i64>) -> Result<Vec<ApiToken>> {
+        let sql = format!(
+            "SELECT {} FROM api_tokens WHERE !$user_id OR user_id = type::thing('users', $user_id) ORDER BY created_at DESC, id DESC",
+            SURREAL_API_TOKEN_FIELDS
+        );
+        self.rows(&sql, json!({ "user_id": user_id })).await
+    }
+
+    async fn use_api_token(&self, token_hash: &str, now: DateTime<Utc>) -> Result<Option<ApiToken>> {
+        let sql = format!(
+            "UPDATE api_tokens SET last_used_at = <datetime> $now WHERE token_hash = $token_hash AND (expires_at = NONE OR expires_at > <datetime> $now) RETURN {}",
+            SURREAL_API_TOKEN_FIELDS
+        );
+        let tokens = self.rows(&sql, json!({ "token_hash": token_hash, "now": now.to_rfc3339() })).await?;
+        Ok(tokens.into_iter().next())
+    }
+
+    async fn delete_api_token(&self, id: i64) -> Result<()> {
+        self.db.query_response("DELETE type::thing('api_tokens', $id)", json!({ "id": id })).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ReportRepository for SurrealRepository {
+    async fn sales_totals(&self, from: Option<&str>, to: Option<&str>) -> Result<Vec<PeriodTotals>> {
+        self.period_totals("sales", from, to).await
+    }
+
+    async fn purchases_totals(&self, from: Option<&str>, to: Option<&str>) -> Result<Vec<PeriodTotals>> {
+        self.period_totals("purchases", from, to).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{attributed, AuditActor};
+    use crate::surrealdb::DatabaseConfig;
+    use crate::{AdditionalCostInput, PurchaseItemInput};
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Currency 1 and unit 1, referenced by the sale, purchase and journal fixtures
+    const SQLITE_REFERENCE_CURRENCY: &str = "INSERT INTO currencies (name, base, rate) VALUES ('AFN', 1, 1)";
+    const SQLITE_REFERENCE_UNIT: &str = "INSERT INTO units (name, ratio, is_base) VALUES ('kg', 1, 1)";
+    const SURREAL_REFERENCE_DATA: &str = "CREATE currencies:1 SET name = 'AFN', base = 1, rate = 1; CREATE units:1 SET name = 'kg', ratio = 1, is_base = 1;";
+
+    /// A backend under test, plus direct access for inserting fixtures the
+    /// repositories don't create themselves
+    enum Backend {
+        Sqlite(Database),
+        Surreal(SurrealDatabase, PathBuf),
+    }
+
+    impl Drop for Backend {
+        fn drop(&mut self) {
+            if let Backend::Surreal(_, dir) = self {
+                let _ = std::fs::remove_dir_all(dir);
+            }
+        }
+    }
+
+    impl Backend {
+        fn sqlite() -> Self {
+            let db = Database::new(PathBuf::from(":memory:"));
+            db.open().unwrap();
+            crate::migrations::run_pending(&db).unwrap();
+            crate::audit::install_audit_triggers(&db).unwrap();
+            for sql in [SQLITE_REFERENCE_CURRENCY, SQLITE_REFERENCE_UNIT] {
+                db.execute(sql, &[]).unwrap();
+            }
+            Backend::Sqlite(db)
+        }
+
+        async fn surreal() -> Self {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let dir = std::env::temp_dir().join(format!(
+                "shafaf-repository-test-{}-{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::SeqCst)
+            ));
+            let mut db = SurrealDatabase::new(DatabaseConfig {
+                mode: ConnectionMode::Offline,
+                offline_path: None,
+                online_url: None,
+                namespace: None,
+                database: None,
+                username: None,
+                password: None,
+                conflict_policy: Default::default(),
+            });
+            db.connect_offline(dir.clone()).await.unwrap();
+            crate::surrealdb::init_schema(&db).await.unwrap();
+            db.query_response(SURREAL_REFERENCE_DATA, json!({})).await.unwrap();
+            Backend::Surreal(db, dir)
+        }
+
+        fn repo(&self) -> Box<dyn Repositories> {
+            match self {
+                Backend::Sqlite(db) => select(None, Some(db.clone()), None).unwrap(),
+                Backend::Surreal(db, _) => select(Some(&ConnectionMode::Offline), None, Some(db.clone())).unwrap(),
+            }
+        }
+
+        /// Run one fixture statement; SQLite returns the new rowid, SurrealDB uses the id in the query
+        async fn insert(&self, sqlite: &str, surreal: &str, surreal_id: i64) -> i64 {
+            match self {
+                Backend::Sqlite(db) => db.insert(sqlite, &[]).unwrap(),
+                Backend::Surreal(db, _) => {
+                    db.query_response(surreal, json!({})).await.unwrap();
+                    surreal_id
+                }
+            }
+        }
+
+        async fn sale(&self, customer_id: i64, product_id: i64) -> i64 {
+            let id = self
+                .insert(
+                    &format!("INSERT INTO sales (customer_id, date, notes, exchange_rate, total_amount, base_amount, paid_amount, additional_cost) VALUES ({}, '2026-01-02', 'first sale', 1, 30, 30, 10, 0)", customer_id),
+                    &format!("CREATE sales:1 SET customer_id = customers:{}, date = '2026-01-02', notes = 'first sale', exchange_rate = 1, total_amount = 30, base_amount = 30, paid_amount = 10, additional_cost = 0", customer_id),
+                    1,
+                )
+                .await;
+            for (item, amount) in [(1, 1.0), (2, 2.0)] {
+                self.insert(
+                    &format!("INSERT INTO sale_items (sale_id, product_id, unit_id, per_price, amount, total) VALUES ({}, {}, 1, 10, {}, {})", id, product_id, amount, amount * 10.0),
+                    &format!("CREATE sale_items:{} SET sale_id = sales:{}, product_id = products:{}, unit_id = units:1, per_price = 10, amount = {}, total = {}", item, id, product_id, amount, amount * 10.0),
+                    item,
+                )
+                .await;
+            }
+            self.insert(
+                &format!("INSERT INTO sale_payments (sale_id, amount, date) VALUES ({}, 10, '2026-01-02')", id),
+                &format!("CREATE sale_payments:1 SET sale_id = sales:{}, exchange_rate = 1, amount = 10, base_amount = 10, date = '2026-01-02'", id),
+                1,
+            )
+            .await;
+            id
+        }
+
+        async fn purchase(&self, product_id: i64) -> i64 {
+            self.insert(
+                "INSERT INTO suppliers (full_name, phone, address) VALUES ('Karimi Traders', '0799', 'Herat')",
+                "CREATE suppliers:1 SET full_name = 'Karimi Traders', phone = '0799', address = 'Herat'",
+                1,
+            )
+            .await;
+            let id = self
+                .insert(
+                    "INSERT INTO purchases (supplier_id, date, notes, total_amount, batch_number) VALUES (1, '2026-02-03', 'restock', 50, 'B-7')",
+                    "CREATE purchases:1 SET supplier_id = suppliers:1, date = '2026-02-03', notes = 'restock', total_amount = 50, batch_number = 'B-7'",
+                    1,
+                )
+                .await;
+            self.insert(
+                &format!("INSERT INTO purchase_items (purchase_id, product_id, unit_id, per_price, amount, total, expiry_date) VALUES ({}, {}, 1, 5, 10, 50, '2027-01-01')", id, product_id),
+                &format!("CREATE purchase_items:1 SET purchase_id = purchases:{}, product_id = products:{}, unit_id = units:1, per_price = 5, amount = 10, total = 50, expiry_date = '2027-01-01'", id, product_id),
+                1,
+            )
+            .await;
+            for (cost, amount) in [(1, 2.5), (2, 1.5)] {
+                self.insert(
+                    &format!("INSERT INTO purchase_additional_costs (purchase_id, name, amount) VALUES ({}, 'transport', {})", id, amount),
+                    &format!("CREATE purchase_additional_costs:{} SET purchase_id = purchases:{}, name = 'transport', amount = {}", cost, id, amount),
+                    cost,
+                )
+                .await;
+            }
+            id
+        }
+
+        async fn account(&self, record: i64, name: &str) -> i64 {
+            self.insert(
+                &format!("INSERT INTO accounts (name, account_code, initial_balance, current_balance) VALUES ('{}', 'A-{}', 100, 100)", name, record),
+                &format!("CREATE accounts:{} SET name = '{}', account_code = 'A-{}', initial_balance = 100, current_balance = 100, is_active = 1", record, name, record),
+                record,
+            )
+            .await
+        }
+
+        /// An account of `account_type` that the sale writes find by its name and post to
+        async fn posting_account(&self, record: i64, name: &str, account_type: &str) -> i64 {
+            self.insert(
+                &format!("INSERT INTO accounts (name, account_type, initial_balance, current_balance) VALUES ('{}', '{}', 100, 100)", name, account_type),
+                &format!("CREATE accounts:{} SET name = '{}', account_type = '{}', initial_balance = 100, current_balance = 100, is_active = 1", record, name, account_type),
+                record,
+            )
+            .await
+        }
+
+        async fn journal_entry(&self, record: i64, date: &str, account_id: i64) -> i64 {
+            let id = self
+                .insert(
+                    &format!("INSERT INTO journal_entries (entry_number, entry_date, description) VALUES ('J{:06}', '{}', 'opening')", record, date),
+                    &format!("CREATE journal_entries:{} SET entry_number = 'J{:06}', entry_date = '{}', description = 'opening'", record, record, date),
+                    record,
+                )
+                .await;
+            for (line, debit, credit) in [(1, 25.0, 0.0), (2, 0.0, 25.0)] {
+                let line_id = record * 10 + line;
+                self.insert(
+                    &format!("INSERT INTO journal_entry_lines (journal_entry_id, account_id, currency_id, debit_amount, credit_amount, exchange_rate, base_amount) VALUES ({}, {}, 1, {}, {}, 1, 25)", id, account_id, debit, credit),
+                    &format!("CREATE journal_entry_lines:{} SET journal_entry_id = journal_entries:{}, account_id = accounts:{}, currency_id = currencies:1, debit_amount = {}, credit_amount = {}, exchange_rate = 1, base_amount = 25", line_id, id, account_id, debit, credit),
+                    line_id,
+                )
+                .await;
+            }
+            id
+        }
+
+        /// The account's balance in currency 1
+        async fn balance(&self, account_id: i64) -> f64 {
+            match self {
+                Backend::Sqlite(db) => db
+                    .query(
+                        "SELECT balance FROM account_currency_balances WHERE account_id = ? AND currency_id = 1",
+                        &[&account_id],
+                        |row| row.get(0),
+                    )
+                    .unwrap()
+                    .first()
+                    .copied()
+                    .unwrap_or(0.0),
+                Backend::Surreal(db, _) => {
+                    let mut response = db
+                        .query_response(
+                            "RETURN (SELECT VALUE balance FROM account_currency_balances WHERE account_id = type::thing('accounts', $id) AND currency_id = currencies:1)[0];",
+                            json!({ "id": account_id }),
+                        )
+                        .await
+                        .unwrap();
+                    let balance: Option<f64> = response.take(0).unwrap();
+                    balance.unwrap_or(0.0)
+                }
+            }
+        }
+    }
+
+    fn customer(full_name: &str, phone: &str) -> CustomerInput {
+        CustomerInput {
+            full_name: full_name.to_string(),
+            phone: phone.to_string(),
+            address: "Kabul".to_string(),
+            email: Some(format!("{}@example.com", full_name.to_lowercase())),
+            notes: None,
+        }
+    }
+
+    fn product(name: &str) -> ProductInput {
+        ProductInput {
+            name: name.to_string(),
+            description: Some("500g".to_string()),
+            price: Some(12.5),
+            currency_id: None,
+            supplier_id: None,
+            stock_quantity: Some(4.0),
+            unit: None,
+            image_path: None,
+            bar_code: Some("123".to_string()),
+        }
+    }
+
+    fn sale_item(product_id: i64, per_price: f64, amount: f64) -> SaleItemInput {
+        SaleItemInput { product_id, unit_id: 1, per_price, amount, purchase_item_id: None, sale_type: None }
+    }
+
+    fn purchase_item(product_id: i64, per_price: f64, amount: f64) -> PurchaseItemInput {
+        PurchaseItemInput {
+            product_id,
+            unit_id: 1,
+            per_price,
+            amount,
+            per_unit: None,
+            cost_price: None,
+            wholesale_price: None,
+            retail_price: Some(per_price * 1.2),
+            expiry_date: Some("2027-06-30".to_string()),
+        }
+    }
+
+    fn new_account(name: &str, account_code: &str) -> AccountInput {
+        AccountInput {
+            name: name.to_string(),
+            currency_id: Some(1),
+            coa_category_id: None,
+            account_code: Some(account_code.to_string()),
+            account_type: Some("Asset".to_string()),
+            initial_balance: 100.0,
+            notes: None,
+        }
+    }
+
+    fn account_transaction(amount: f64, currency: &str, is_full: bool) -> AccountTransactionInput {
+        AccountTransactionInput {
+            amount,
+            currency: currency.to_string(),
+            rate: 2.0,
+            transaction_date: "2026-05-01".to_string(),
+            is_full,
+            notes: Some("till".to_string()),
+        }
+    }
+
+    fn journal_line(account_id: i64, debit_amount: f64, credit_amount: f64) -> JournalLineInput {
+        JournalLineInput { account_id, currency_id: 1, debit_amount, credit_amount, exchange_rate: 2.0, description: None }
+    }
+
+    fn search(term: &str) -> ListQuery {
+        ListQuery { search: Some(term.to_string()), ..ListQuery::new(1, 10) }
+    }
+
+    async fn customer_contract(backend: Backend) {
+        let repo = backend.repo();
+        let ahmad = repo.create_customer(&customer("Ahmad", "0700")).await.unwrap();
+        let bashir = repo.create_customer(&customer("Bashir", "0711")).await.unwrap();
+        assert!(ahmad.id > 0 && bashir.id != ahmad.id);
+        assert_eq!(ahmad.email.as_deref(), Some("ahmad@example.com"));
+        assert_eq!(ahmad.notes, None);
+        assert_eq!(ahmad.created_at.len(), "2026-01-01 00:00:00".len());
+
+        let fetched = repo.get_customer(ahmad.id).await.unwrap().unwrap();
+        assert_eq!(fetched.full_name, "Ahmad");
+
+        let found = repo.list_customers(&search("AHM")).await.unwrap();
+        assert_eq!(found.total, 1);
+        assert_eq!(found.items[0].id, ahmad.id);
+
+        let sorted = ListQuery {
+            sort_by: Some("full_name".to_string()),
+            sort_order: Some("desc".to_string()),
+            ..ListQuery::new(1, 1)
+        };
+        let first_page = repo.list_customers(&sorted).await.unwrap();
+        assert_eq!((first_page.total, first_page.total_pages), (2, 2));
+        assert_eq!(first_page.items.len(), 1);
+        assert_eq!(first_page.items[0].full_name, "Bashir");
+
+        let mut changed = customer("Ahmad Zai", "0799");
+        changed.notes = Some("wholesale".to_string());
+        let updated = repo.update_customer(ahmad.id, &changed).await.unwrap();
+        assert_eq!(updated.phone, "0799");
+        assert_eq!(updated.notes.as_deref(), Some("wholesale"));
+        assert!(repo.update_customer(9999, &changed).await.is_err());
+
+        repo.delete_customer(bashir.id).await.unwrap();
+        assert!(repo.get_customer(bashir.id).await.unwrap().is_none());
+        assert_eq!(repo.list_customers(&ListQuery::new(1, 10)).await.unwrap().total, 1);
+    }
+
+    async fn product_contract(backend: Backend) {
+        let repo = backend.repo();
+        let rice = repo.create_product(&product("Rice")).await.unwrap();
+        assert_eq!(rice.price, Some(12.5));
+        assert_eq!(rice.currency_id, None);
+        assert_eq!(rice.bar_code.as_deref(), Some("123"));
+
+        let mut oil = product("Oil");
+        oil.price = None;
+        let oil = repo.create_product(&oil).await.unwrap();
+        assert_eq!(oil.price, None);
+
+        let found = repo.list_products(&search("ric")).await.unwrap();
+        assert_eq!(found.total, 1);
+        assert_eq!(found.items[0].name, "Rice");
+
+        let by_name = ListQuery { sort_by: Some("name".to_string()), ..ListQuery::new(1, 10) };
+        let names: Vec<String> = repo.list_products(&by_name).await.unwrap().items.into_iter().map(|p| p.name).collect();
+        assert_eq!(names, ["Oil", "Rice"]);
+
+        let mut changed = product("Rice (1kg)");
+        changed.stock_quantity = Some(10.0);
+        let updated = repo.update_product(rice.id, &changed).await.unwrap();
+        assert_eq!(updated.name, "Rice (1kg)");
+        assert_eq!(updated.stock_quantity, Some(10.0));
+        assert!(repo.update_product(9999, &changed).await.is_err());
+
+        // A product on a sale can't be deleted
+        let buyer = repo.create_customer(&customer("Ahmad", "0700")).await.unwrap();
+        backend.sale(buyer.id, rice.id).await;
+        let err = repo.delete_product(rice.id).await.unwrap_err();
+        assert!(err.to_string().contains("used in 2 sale(s)"), "{}", err);
+
+        repo.delete_product(oil.id).await.unwrap();
+        assert!(repo.get_product(oil.id).await.unwrap().is_none());
+    }
+
+    async fn sale_contract(backend: Backend) {
+        let repo = backend.repo();
+        let buyer = repo.create_customer(&customer("Ahmad", "0700")).await.unwrap();
+        let rice = repo.create_product(&product("Rice")).await.unwrap();
+        let sale_id = backend.sale(buyer.id, rice.id).await;
+
+        let (sale, items) = repo.get_sale(sale_id).await.unwrap().unwrap();
+        assert_eq!(sale.customer_id, buyer.id);
+        assert_eq!(sale.currency_id, None);
+        assert_eq!((sale.total_amount, sale.paid_amount), (30.0, 10.0));
+        assert_eq!(items.len(), 2);
+        assert!(items.iter().all(|i| i.sale_id == sale_id && i.product_id == rice.id));
+        assert_eq!(items[1].total, 20.0);
+        assert_eq!(items[0].purchase_item_id, None);
+
+        assert_eq!(repo.list_sales(&search("ahmad")).await.unwrap().total, 1);
+        assert_eq!(repo.list_sales(&search("2026-01")).await.unwrap().total, 1);
+        assert_eq!(repo.list_sales(&search("nobody")).await.unwrap().total, 0);
+
+        repo.delete_sale(sale_id).await.unwrap();
+        assert!(repo.get_sale(sale_id).await.unwrap().is_none());
+        // Items went with the sale, so the product is free to delete
+        repo.delete_product(rice.id).await.unwrap();
+    }
+
+    async fn sale_write_contract(backend: Backend) {
+        let repo = backend.repo();
+        let receivable = backend.posting_account(1, "Accounts Receivable", "Asset").await;
+        let revenue = backend.posting_account(2, "Sales Revenue", "Revenue").await;
+        let cash = backend.posting_account(3, "Cash Box", "Asset").await;
+        let buyer = repo.create_customer(&customer("Ahmad", "0700")).await.unwrap();
+        let rice = repo.create_product(&product("Rice")).await.unwrap();
+
+        let input = SaleInput {
+            customer_id: buyer.id,
+            date: "2026-03-04".to_string(),
+            notes: Some("counter".to_string()),
+            currency_id: None,
+            exchange_rate: 2.0,
+            paid_amount: 5.0,
+            additional_costs: vec![AdditionalCostInput { name: "delivery".to_string(), amount: 3.0 }],
+            items: vec![sale_item(rice.id, 10.0, 2.0), sale_item(rice.id, 4.0, 1.0)],
+        };
+        let sale = repo.create_sale(&input).await.unwrap();
+        assert_eq!(sale.customer_id, buyer.id);
+        assert_eq!(sale.notes.as_deref(), Some("counter"));
+        assert_eq!((sale.total_amount, sale.base_amount, sale.additional_cost, sale.paid_amount), (27.0, 54.0, 3.0, 5.0));
+        let (_, items) = repo.get_sale(sale.id).await.unwrap().unwrap();
+        assert_eq!(items.iter().map(|i| (i.sale_id, i.total)).collect::<Vec<_>>(), [(sale.id, 20.0), (sale.id, 4.0)]);
+
+        // The sale is posted from receivable to revenue, in the base currency
+        let entries = repo.list_journal_entries(&ListQuery::new(1, 10)).await.unwrap();
+        assert_eq!(entries.total, 1);
+        let entry = &entries.items[0];
+        assert_eq!(entry.entry_number, "J000001");
+        assert_eq!((entry.reference_type.as_deref(), entry.reference_id), (Some("sale"), Some(sale.id)));
+        let (_, lines) = repo.get_journal_entry(entry.id).await.unwrap().unwrap();
+        let posted: Vec<_> = lines.iter().map(|l| (l.account_id, l.currency_id, l.debit_amount, l.credit_amount)).collect();
+        assert_eq!(posted, [(receivable, 1, 54.0, 0.0), (revenue, 1, 0.0, 54.0)]);
+
+        let item = repo.create_sale_item(sale.id, &sale_item(rice.id, 5.0, 2.0)).await.unwrap();
+        assert_eq!((item.sale_id, item.product_id, item.total), (sale.id, rice.id, 10.0));
+        let (grown, items) = repo.get_sale(sale.id).await.unwrap().unwrap();
+        assert_eq!((grown.total_amount, items.len()), (37.0, 3));
+
+        let payment = SalePaymentInput {
+            account_id: Some(cash),
+            currency_id: None,
+            exchange_rate: 2.0,
+            amount: 10.0,
+            date: "2026-03-05".to_string(),
+        };
+        let payment = repo.create_sale_payment(sale.id, &payment).await.unwrap();
+        assert_eq!((payment.sale_id, payment.account_id, payment.currency_id), (sale.id, Some(cash), Some(1)));
+        assert_eq!((payment.amount, payment.base_amount), (10.0, 20.0));
+        // The opening payment counts too, at its base amount
+        let (paid, _) = repo.get_sale(sale.id).await.unwrap().unwrap();
+        assert_eq!(paid.paid_amount, 30.0);
+        let payments = repo.list_sale_payments(sale.id).await.unwrap();
+        assert_eq!(payments.iter().map(|p| (p.date.as_str(), p.amount)).collect::<Vec<_>>(), [("2026-03-05", 10.0), ("2026-03-04", 5.0)]);
+        assert!(repo.list_sale_payments(9999).await.unwrap().is_empty());
+        assert_eq!(repo.get_account(cash).await.unwrap().unwrap().current_balance, 120.0);
+        let entries = repo.list_journal_entries(&ListQuery::new(1, 10)).await.unwrap();
+        assert_eq!(entries.total, 2);
+        let entry = entries.items.iter().find(|e| e.reference_type.as_deref() == Some("sale_payment")).unwrap();
+        assert_eq!(entry.entry_number, "J000002");
+        let (_, lines) = repo.get_journal_entry(entry.id).await.unwrap().unwrap();
+        let posted: Vec<_> = lines.iter().map(|l| (l.account_id, l.debit_amount, l.credit_amount)).collect();
+        assert_eq!(posted, [(cash, 20.0, 0.0), (receivable, 0.0, 20.0)]);
+
+        let item = repo.update_sale_item(item.id, &sale_item(rice.id, 6.0, 2.0)).await.unwrap();
+        assert_eq!((item.sale_id, item.per_price, item.total), (sale.id, 6.0, 12.0));
+        let (regrown, _) = repo.get_sale(sale.id).await.unwrap().unwrap();
+        assert_eq!(regrown.total_amount, 39.0);
+        assert!(repo.update_sale_item(9999, &sale_item(rice.id, 6.0, 2.0)).await.is_err());
+
+        // What is left is the opening payment, summed at its amount
+        repo.delete_sale_payment(payment.id).await.unwrap();
+        let (unpaid, _) = repo.get_sale(sale.id).await.unwrap().unwrap();
+        assert_eq!(unpaid.paid_amount, 5.0);
+        assert_eq!(repo.list_sale_payments(sale.id).await.unwrap().len(), 1);
+        assert!(repo.delete_sale_payment(payment.id).await.is_err());
+
+        let changed = SaleInput {
+            notes: None,
+            paid_amount: 0.0,
+            additional_costs: Vec::new(),
+            items: vec![sale_item(rice.id, 7.0, 3.0)],
+            ..input
+        };
+        let updated = repo.update_sale(sale.id, &changed).await.unwrap();
+        assert_eq!(updated.notes, None);
+        assert_eq!((updated.total_amount, updated.base_amount, updated.additional_cost, updated.paid_amount), (21.0, 42.0, 0.0, 5.0));
+        let (_, items) = repo.get_sale(sale.id).await.unwrap().unwrap();
+        assert_eq!(items.iter().map(|i| i.total).collect::<Vec<_>>(), [21.0]);
+        assert!(repo.update_sale(9999, &changed).await.is_err());
+        assert!(repo.get_sale(9999).await.unwrap().is_none());
+    }
+
+    async fn purchase_write_contract(backend: Backend) {
+        let repo = backend.repo();
+        let supplier = backend
+            .insert(
+                "INSERT INTO suppliers (full_name, phone, address) VALUES ('Karimi Traders', '0799', 'Herat')",
+                "CREATE suppliers:1 SET full_name = 'Karimi Traders', phone = '0799', address = 'Herat'",
+                1,
+            )
+            .await;
+        let rice = repo.create_product(&product("Rice")).await.unwrap();
+
+        let input = PurchaseInput {
+            supplier_id: supplier,
+            date: "2026-02-10".to_string(),
+            notes: None,
+            currency_id: Some(1),
+            additional_costs: vec![AdditionalCostInput { name: "transport".to_string(), amount: 2.5 }],
+            items: vec![purchase_item(rice.id, 5.0, 10.0)],
+        };
+        let first = repo.create_purchase(&input).await.unwrap();
+        assert_eq!((first.supplier_id, first.currency_id), (supplier, Some(1)));
+        assert_eq!((first.total_amount, first.additional_cost), (52.5, 2.5));
+        assert_eq!(first.batch_number.as_deref(), Some("BATCH-000001"));
+        let second = repo.create_purchase(&input).await.unwrap();
+        assert_eq!(second.batch_number.as_deref(), Some("BATCH-000002"));
+
+        let (_, items) = repo.get_purchase(first.id).await.unwrap().unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!((items[0].purchase_id, items[0].total, items[0].retail_price), (first.id, 50.0, Some(6.0)));
+        assert_eq!(items[0].expiry_date.as_deref(), Some("2027-06-30"));
+
+        let changed = PurchaseInput {
+            notes: Some("recounted".to_string()),
+            additional_costs: Vec::new(),
+            items: vec![purchase_item(rice.id, 5.0, 8.0), purchase_item(rice.id, 1.0, 4.0)],
+            ..input
+        };
+        let updated = repo.update_purchase(first.id, &changed).await.unwrap();
+        assert_eq!(updated.notes.as_deref(), Some("recounted"));
+        assert_eq!((updated.total_amount, updated.additional_cost), (44.0, 0.0));
+        assert_eq!(updated.batch_number.as_deref(), Some("BATCH-000001"));
+        let (_, items) = repo.get_purchase(first.id).await.unwrap().unwrap();
+        assert_eq!(items.iter().map(|i| i.total).collect::<Vec<_>>(), [40.0, 4.0]);
+        assert!(repo.update_purchase(9999, &changed).await.is_err());
+
+        let till = repo.create_account(&new_account("Till", "1001")).await.unwrap();
+        let mut payment = PurchasePaymentInput {
+            account_id: Some(till.id),
+            amount: 30.0,
+            currency: "AFN".to_string(),
+            rate: 1.0,
+            date: "2026-02-11".to_string(),
+            notes: Some("first half".to_string()),
+        };
+        let paid = repo.create_purchase_payment(first.id, &payment).await.unwrap();
+        assert_eq!((paid.purchase_id, paid.account_id, paid.total), (first.id, Some(till.id), 30.0));
+        assert_eq!((paid.currency.as_str(), paid.notes.as_deref()), ("AFN", Some("first half")));
+        assert_eq!(backend.balance(till.id).await, 70.0);
+        assert_eq!(repo.get_account(till.id).await.unwrap().unwrap().current_balance, 70.0);
+
+        // The account can't go below zero in the payment's currency
+        payment.amount = 500.0;
+        let error = repo.create_purchase_payment(first.id, &payment).await.unwrap_err();
+        assert!(error.to_string().contains("Insufficient balance in account. Available: 70, Required: 500"), "{}", error);
+        assert_eq!(backend.balance(till.id).await, 70.0);
+
+        // Without an account nothing is withdrawn
+        payment.account_id = None;
+        let unbooked = repo.create_purchase_payment(first.id, &payment).await.unwrap();
+        assert_eq!((unbooked.account_id, unbooked.total), (None, 500.0));
+        assert_eq!(backend.balance(till.id).await, 70.0);
+    }
+
+    async fn purchase_contract(backend: Backend) {
+        let repo = backend.repo();
+        let rice = repo.create_product(&product("Rice")).await.unwrap();
+        let purchase_id = backend.purchase(rice.id).await;
+
+        let (purchase, items) = repo.get_purchase(purchase_id).await.unwrap().unwrap();
+        assert_eq!(purchase.batch_number.as_deref(), Some("B-7"));
+        assert_eq!(purchase.additional_cost, 4.0);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].product_id, rice.id);
+        assert_eq!(items[0].expiry_date.as_deref(), Some("2027-01-01"));
+        assert_eq!(items[0].retail_price, None);
+
+        let found = repo.list_purchases(&search("karimi")).await.unwrap();
+        assert_eq!(found.total, 1);
+        assert_eq!(found.items[0].additional_cost, 4.0);
+
+        assert!(repo.delete_product(rice.id).await.is_err());
+        repo.delete_purchase(purchase_id).await.unwrap();
+        assert!(repo.get_purchase(purchase_id).await.unwrap().is_none());
+        repo.delete_product(rice.id).await.unwrap();
+    }
+
+    async fn account_contract(backend: Backend) {
+        let repo = backend.repo();
+        let cash = backend.account(1, "Cash").await;
+        let bank = backend.account(2, "Bank").await;
+
+        let accounts = repo.list_accounts().await.unwrap();
+        let names: Vec<&str> = accounts.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["Bank", "Cash"]);
+
+        let account = repo.get_account(cash).await.unwrap().unwrap();
+        assert!(account.is_active);
+        assert_eq!(account.account_code.as_deref(), Some("A-1"));
+        assert_eq!(account.current_balance, 100.0);
+        assert!(repo.get_account(9999).await.unwrap().is_none());
+
+        repo.delete_account(bank).await.unwrap();
+        assert_eq!(repo.list_accounts().await.unwrap().len(), 1);
+    }
+
+    async fn account_write_contract(backend: Backend) {
+        let repo = backend.repo();
+        // Out of the way of the ids the repository gives the accounts it creates
+        let cash = backend.posting_account(101, "Cash Box", "Asset").await;
+        let rent = backend.posting_account(102, "Rent", "Expense").await;
+
+        let till = repo.create_account(&new_account("Till", "1001")).await.unwrap();
+        assert_eq!((till.name.as_str(), till.account_code.as_deref(), till.currency_id), ("Till", Some("1001"), Some(1)));
+        assert_eq!((till.initial_balance, till.current_balance, till.is_active), (100.0, 100.0, true));
+        assert_eq!(backend.balance(till.id).await, 100.0);
+        // Blank codes are left out, so they don't clash
+        let first = repo.create_account(&new_account("Drawer", " ")).await.unwrap();
+        let second = repo.create_account(&new_account("Safe", "")).await.unwrap();
+        assert_eq!((first.account_code, second.account_code), (None, None));
+        assert!(repo.create_account(&new_account("Copy", "1001")).await.is_err());
+
+        let deposit = repo.deposit_account(till.id, &account_transaction(50.0, "AFN", false)).await.unwrap();
+        assert_eq!((deposit.account_id, deposit.transaction_type.as_str()), (till.id, "deposit"));
+        assert_eq!((deposit.amount, deposit.total, deposit.is_full), (50.0, 100.0, false));
+        assert_eq!((deposit.currency.as_str(), deposit.notes.as_deref()), ("AFN", Some("till")));
+        assert_eq!(repo.get_account(till.id).await.unwrap().unwrap().current_balance, 200.0);
+        // The deposit and the debit of its journal entry both raise the currency balance
+        assert_eq!(backend.balance(till.id).await, 250.0);
+        let entries = repo.list_journal_entries(&ListQuery::new(1, 10)).await.unwrap();
+        assert_eq!(entries.total, 1);
+        assert_eq!(entries.items[0].reference_type.as_deref(), Some("account_deposit"));
+        let (_, lines) = repo.get_journal_entry(entries.items[0].id).await.unwrap().unwrap();
+        let posted: Vec<_> = lines.iter().map(|l| (l.account_id, l.debit_amount, l.credit_amount)).collect();
+        assert_eq!(posted, [(till.id, 100.0, 0.0), (cash, 0.0, 100.0)]);
+
+        let error = repo.deposit_account(till.id, &account_transaction(0.0, "AFN", false)).await.unwrap_err();
+        assert!(error.to_string().contains("Deposit amount must be greater than 0"), "{}", error);
+        let error = repo.deposit_account(till.id, &account_transaction(5.0, "USD", false)).await.unwrap_err();
+        assert!(error.to_string().contains("Currency not found"), "{}", error);
+        let error = repo.withdraw_account(till.id, &account_transaction(150.0, "AFN", false)).await.unwrap_err();
+        assert!(error.to_string().contains("Insufficient balance for withdrawal"), "{}", error);
+
+        let withdrawal = repo.withdraw_account(till.id, &account_transaction(0.0, "AFN", true)).await.unwrap();
+        assert_eq!((withdrawal.transaction_type.as_str(), withdrawal.is_full), ("withdraw", true));
+        assert_eq!((withdrawal.amount, withdrawal.total), (200.0, 400.0));
+        assert_eq!(repo.get_account(till.id).await.unwrap().unwrap().current_balance, -200.0);
+        let entries = repo.list_journal_entries(&ListQuery::new(1, 10)).await.unwrap();
+        let entry = entries.items.iter().find(|e| e.reference_type.as_deref() == Some("account_withdraw")).unwrap();
+        let (_, lines) = repo.get_journal_entry(entry.id).await.unwrap().unwrap();
+        let posted: Vec<_> = lines.iter().map(|l| (l.account_id, l.debit_amount, l.credit_amount)).collect();
+        assert_eq!(posted, [(rent, 400.0, 0.0), (till.id, 0.0, 400.0)]);
+        let error = repo.withdraw_account(second.id, &account_transaction(0.0, "AFN", false)).await.unwrap_err();
+        assert!(error.to_string().contains("Withdrawal amount must be greater than 0"), "{}", error);
+
+        assert_eq!(repo.account_balance(till.id).await.unwrap(), -200.0);
+        let mut types: Vec<_> = repo.list_account_transactions(till.id).await.unwrap().into_iter().map(|t| t.transaction_type).collect();
+        types.sort();
+        assert_eq!(types, ["deposit", "withdraw"]);
+        assert!(repo.list_account_transactions(second.id).await.unwrap().is_empty());
+        let balances = repo.list_currency_balances(till.id).await.unwrap();
+        let held: Vec<_> = balances.iter().map(|b| (b.account_id, b.currency_id, b.balance)).collect();
+        assert_eq!(held, [(till.id, 1, backend.balance(till.id).await)]);
+        assert!(balances[0].id > 0);
+
+        let renamed = AccountInput { initial_balance: 300.0, ..new_account("Main Till", "1002") };
+        let till = repo.update_account(till.id, &renamed, false).await.unwrap();
+        assert_eq!((till.name.as_str(), till.account_code.as_deref(), till.is_active), ("Main Till", Some("1002"), false));
+        assert_eq!((till.initial_balance, till.current_balance), (300.0, 0.0));
+        assert!(repo.update_account(second.id, &new_account("Safe", "1002"), true).await.is_err());
+        assert!(repo.update_account(9999, &new_account("Ghost", "9999"), true).await.is_err());
+    }
+
+    async fn journal_write_contract(backend: Backend) {
+        let repo = backend.repo();
+        let cash = backend.account(1, "Cash").await;
+        let bank = backend.account(2, "Bank").await;
+
+        let input = JournalEntryInput {
+            entry_date: "2026-04-01".to_string(),
+            description: Some("transfer".to_string()),
+            reference_type: None,
+            reference_id: None,
+            lines: vec![journal_line(cash, 40.0, 0.0), journal_line(bank, 0.0, 40.0)],
+        };
+        let entry = repo.create_journal_entry(&input).await.unwrap();
+        assert_eq!((entry.entry_number.as_str(), entry.entry_date.as_str()), ("J000001", "2026-04-01"));
+        assert_eq!(entry.description.as_deref(), Some("transfer"));
+        let (_, lines) = repo.get_journal_entry(entry.id).await.unwrap().unwrap();
+        let posted: Vec<_> = lines.iter().map(|l| (l.account_id, l.debit_amount, l.credit_amount, l.base_amount)).collect();
+        assert_eq!(posted, [(cash, 40.0, 0.0, 80.0), (bank, 0.0, 40.0, 80.0)]);
+        assert_eq!((backend.balance(cash).await, backend.balance(bank).await), (40.0, -40.0));
+        assert_eq!(repo.create_journal_entry(&input).await.unwrap().entry_number, "J000002");
+
+        let updated = repo.update_journal_entry(entry.id, &[journal_line(cash, 15.0, 0.0), journal_line(bank, 0.0, 15.0)]).await.unwrap();
+        assert_eq!((updated.id, updated.entry_number.as_str()), (entry.id, "J000001"));
+        let (_, lines) = repo.get_journal_entry(entry.id).await.unwrap().unwrap();
+        let posted: Vec<_> = lines.iter().map(|l| (l.account_id, l.debit_amount, l.credit_amount)).collect();
+        assert_eq!(posted, [(cash, 15.0, 0.0), (bank, 0.0, 15.0)]);
+        // The old lines' balances are moved back before the new ones are posted
+        assert_eq!((backend.balance(cash).await, backend.balance(bank).await), (55.0, -55.0));
+        assert!(repo.update_journal_entry(9999, &[journal_line(cash, 1.0, 0.0)]).await.is_err());
+    }
+
+    async fn journal_contract(backend: Backend) {
+        let repo = backend.repo();
+        let cash = backend.account(1, "Cash").await;
+        let older = backend.journal_entry(1, "2026-01-01", cash).await;
+        let newer = backend.journal_entry(2, "2026-03-01", cash).await;
+
+        let page = repo.list_journal_entries(&ListQuery::new(1, 1)).await.unwrap();
+        assert_eq!((page.total, page.total_pages), (2, 2));
+        assert_eq!(page.items[0].id, newer);
+
+        let (entry, lines) = repo.get_journal_entry(older).await.unwrap().unwrap();
+        assert_eq!(entry.entry_number, "J000001");
+        assert_eq!(entry.reference_id, None);
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|l| l.journal_entry_id == older && l.account_id == cash));
+        assert_eq!((lines[0].debit_amount, lines[1].credit_amount), (25.0, 25.0));
+        assert!(repo.get_journal_entry(9999).await.unwrap().is_none());
+    }
+
+    async fn user_contract(backend: Backend) {
+        let repo = backend.repo();
+        let user = repo
+            .create_user(&UserInput {
+                username: "admin".to_string(),
+                email: "admin@example.com".to_string(),
+                password_hash: "$2b$12$hash".to_string(),
+                full_name: None,
+                phone: Some("0700 000 000".to_string()),
+                role: "admin".to_string(),
+                must_change_password: true,
+            })
+            .await
+            .unwrap();
+        assert!(user.id > 0);
+        assert_eq!((user.role.as_str(), user.is_active), ("admin", 1));
+        assert_eq!(repo.count_users(None).await.unwrap(), 1);
+        assert_eq!(repo.count_users(Some("admin")).await.unwrap(), 1);
+        assert_eq!(repo.count_users(Some("cashier")).await.unwrap(), 0);
+
+        assert!(repo.user_exists("admin", "other@example.com").await.unwrap());
+        assert!(repo.user_exists("other", "admin@example.com").await.unwrap());
+        assert!(!repo.user_exists("other", "other@example.com").await.unwrap());
+
+        let login = repo.find_login("admin@example.com").await.unwrap().unwrap();
+        assert_eq!(login.user.id, user.id);
+        assert_eq!(login.password_hash, "$2b$12$hash");
+        assert_eq!((login.failed_logins, login.locked_until, login.must_change_password), (0, None, true));
+        assert!(repo.find_login("admin' OR '1'='1").await.unwrap().is_none());
+
+        assert_eq!(repo.list_users(&search("ADM")).await.unwrap().total, 1);
+
+        let until = DateTime::from_timestamp(1_900_000_000, 0).unwrap();
+        repo.set_login_failures(user.id, 5, Some(until)).await.unwrap();
+        let login = repo.find_login("admin").await.unwrap().unwrap();
+        assert_eq!((login.failed_logins, login.locked_until), (5, Some(until)));
+        repo.set_login_failures(user.id, 0, None).await.unwrap();
+        assert_eq!(repo.find_login("admin").await.unwrap().unwrap().locked_until, None);
+
+        // Changing the password keeps the old hashes, newest first
+        repo.set_password(user.id, "$2b$12$second", false).await.unwrap();
+        repo.set_password(user.id, "$2b$12$third", false).await.unwrap();
+        let login = repo.find_login("admin").await.unwrap().unwrap();
+        assert_eq!((login.password_hash.as_str(), login.must_change_password), ("$2b$12$third", false));
+        assert_eq!(repo.password_history(user.id, 5).await.unwrap(), ["$2b$12$second", "$2b$12$hash"]);
+        assert_eq!(repo.password_history(user.id, 1).await.unwrap(), ["$2b$12$second"]);
+
+        let profile = UserProfileInput {
+            username: "sara".to_string(),
+            email: "sara@example.com".to_string(),
+            full_name: Some("Sara Ahmadi".to_string()),
+            phone: None,
+        };
+        let updated = repo.update_user(user.id, &profile).await.unwrap();
+        assert_eq!((updated.username.as_str(), updated.full_name.as_deref(), updated.phone), ("sara", Some("Sara Ahmadi"), None));
+        assert!(repo.find_login("admin").await.unwrap().is_none());
+
+        repo.set_user_role(user.id, "cashier").await.unwrap();
+        repo.set_user_active(user.id, false).await.unwrap();
+        let stored = repo.get_user(user.id).await.unwrap().unwrap();
+        assert_eq!((stored.role.as_str(), stored.is_active), ("cashier", 0));
+        assert_eq!(repo.count_users(Some("cashier")).await.unwrap(), 1);
+        assert_eq!(repo.count_active_users(None).await.unwrap(), 0);
+        repo.set_user_active(user.id, true).await.unwrap();
+        assert_eq!(repo.count_active_users(Some("cashier")).await.unwrap(), 1);
+
+        assert_eq!(repo.get_two_factor(user.id).await.unwrap(), TwoFactorRecord::default());
+        let two_factor = TwoFactorRecord {
+            secret: Some("JBSWY3DPEHPK3PXP".to_string()),
+            enabled: true,
+            last_step: Some(56_666_666),
+            recovery_codes: vec!["aa".to_string(), "bb".to_string()],
+        };
+        repo.save_two_factor(user.id, &two_factor).await.unwrap();
+        assert_eq!(repo.get_two_factor(user.id).await.unwrap(), two_factor);
+        repo.save_two_factor(user.id, &TwoFactorRecord::default()).await.unwrap();
+        assert_eq!(repo.get_two_factor(user.id).await.unwrap(), TwoFactorRecord::default());
+
+        repo.delete_user(user.id).await.unwrap();
+        assert!(repo.get_user(user.id).await.unwrap().is_none());
+        assert!(repo.password_history(user.id, 5).await.unwrap().is_empty());
+        assert_eq!(repo.count_users(None).await.unwrap(), 0);
+    }
+
+    async fn settings_contract(backend: Backend) {
+        let repo = backend.repo();
+        assert_eq!(repo.get_setting("password_policy").await.unwrap(), None);
+        let value = serde_json::json!({ "min_length": 10, "require_digit": false });
+        repo.set_setting("password_policy", &value).await.unwrap();
+        repo.set_setting("password_policy", &value).await.unwrap();
+        assert_eq!(repo.get_setting("password_policy").await.unwrap(), Some(value));
+    }
+
+    async fn role_contract(backend: Backend) {
+        let repo = backend.repo();
+        assert!(repo.list_roles().await.unwrap().is_empty());
+        let mut role = RoleRecord {
+            name: "stock_keeper".to_string(),
+            description: Some("Counts stock".to_string()),
+            capabilities: vec!["inventory.read".to_string(), "inventory.write".to_string()],
+        };
+        repo.save_role(&role).await.unwrap();
+        assert_eq!(repo.get_role("stock_keeper").await.unwrap(), Some(role.clone()));
+
+        // Saving again replaces the capabilities instead of adding a second row
+        role.capabilities = vec!["inventory.read".to_string()];
+        role.description = None;
+        repo.save_role(&role).await.unwrap();
+        repo.save_role(&RoleRecord { name: "auditor".to_string(), description: None, capabilities: vec![] })
+            .await
+            .unwrap();
+        let roles = repo.list_roles().await.unwrap();
+        assert_eq!(roles.iter().map(|r| r.name.as_str()).collect::<Vec<_>>(), ["auditor", "stock_keeper"]);
+        assert_eq!(roles[1], role);
+
+        repo.delete_role("stock_keeper").await.unwrap();
+        assert!(repo.get_role("stock_keeper").await.unwrap().is_none());
+        assert_eq!(repo.list_roles().await.unwrap().len(), 1);
+    }
+
+    async fn audit_contract(backend: Backend) {
+        let repo = backend.repo();
+        let actor = |user_id: i64, command: &str| AuditActor {
+            user_id: Some(user_id),
+            username: Some(format!("user{}", user_id)),
+            command: command.to_string(),
+        };
+        let ahmad = attributed(actor(1, "create_customer"), repo.create_customer(&customer("Ahmad", "0700"))).await.unwrap();
+        attributed(actor(2, "update_customer"), async {
+            repo.update_customer(ahmad.id, &customer("Ahmad", "0799")).await.unwrap();
+            // Saving the same values again changes nothing worth logging
+            repo.update_customer(ahmad.id, &customer("Ahmad", "0799")).await.unwrap();
+        })
+        .await;
+        // Once the command is done, its author no longer applies
+        repo.delete_customer(ahmad.id).await.unwrap();
+
+        // The reference fixtures were logged without an author
+        let all = repo.list_audit_log(&AuditFilter::default(), &ListQuery::new(1, 50)).await.unwrap();
+        let filter = AuditFilter { entity: Some("customers".to_string()), ..AuditFilter::default() };
+        let page = repo.list_audit_log(&filter, &ListQuery::new(1, 10)).await.unwrap();
+        assert_eq!(page.total, 3);
+        let author = |id: i64, action: &str| {
+            let entry = page.items.iter().find(|e| e.entity_id == Some(id.to_string()) && e.action == action).unwrap();
+            (entry.command.as_deref(), entry.user_id)
+        };
+        assert_eq!(author(ahmad.id, "create"), (Some("create_customer"), Some(1)));
+        assert_eq!(author(ahmad.id, "update"), (Some("update_customer"), Some(2)));
+        assert_eq!(author(ahmad.id, "delete"), (None, None));
+        let update = &page.items[1];
+        assert_eq!(update.entity_id, Some(ahmad.id.to_string()));
+        assert_eq!(update.before.as_ref().unwrap()["phone"], json!("0700"));
+        assert_eq!(update.after.as_ref().unwrap()["phone"], json!("0799"));
+        assert_eq!(page.items[0].after, None);
+
+        let by_user = AuditFilter { user_id: Some(1), ..AuditFilter::default() };
+        assert_eq!(repo.list_audit_log(&by_user, &ListQuery::new(1, 10)).await.unwrap().total, 1);
+        let today = Utc::now().format("%Y-%m-%d").to_string();
+        let dated = AuditFilter { from: Some(today.clone()), to: Some(today), ..AuditFilter::default() };
+        assert_eq!(repo.list_audit_log(&dated, &ListQuery::new(1, 50)).await.unwrap().total, all.total);
+        let future = AuditFilter { from: Some("2999-01-01".to_string()), ..AuditFilter::default() };
+        assert_eq!(repo.list_audit_log(&future, &ListQuery::new(1, 10)).await.unwrap().total, 0);
+
+        // Sealing chains every entry, and the chain checks out
+        assert_eq!(crate::audit::seal(repo.as_ref()).await.unwrap() as i64, all.total);
+        let last = repo.last_sealed_audit_entry().await.unwrap().unwrap();
+        assert_eq!(last.id, page.items[0].id);
+        let report = crate::audit::verify(repo.as_ref()).await.unwrap();
+        assert!(report.valid, "{}", report.message);
+        assert_eq!(report.entries as i64, all.total);
+    }
+
+    async fn trash_contract(backend: Backend) {
+        use crate::trash::{purge, RetentionPolicy, CUSTOMERS, SALES};
+
+        let repo = backend.repo();
+        let clerk = repo
+            .create_user(&UserInput {
+                username: "clerk".to_string(),
+                email: "clerk@example.com".to_string(),
+                password_hash: "$2b$12$hash".to_string(),
+                full_name: None,
+                phone: None,
+                role: "sales".to_string(),
+                must_change_password: false,
+            })
+            .await
+            .unwrap();
+        let ahmad = repo.create_customer(&customer("Ahmad", "0700")).await.unwrap();
+        let bashir = repo.create_customer(&customer("Bashir", "0711")).await.unwrap();
+        let rice = repo.create_product(&product("Rice")).await.unwrap();
+        let sale = backend.sale(ahmad.id, rice.id).await;
+
+        // Trashed records leave the listings but stay restorable
+        assert!(repo.soft_delete("customers", bashir.id, Some(clerk.id)).await.unwrap());
+        assert!(!repo.soft_delete("customers", bashir.id, Some(clerk.id)).await.unwrap());
+        assert!(!repo.soft_delete("customers", 9999, None).await.unwrap());
+        assert_eq!(repo.list_customers(&ListQuery::new(1, 10)).await.unwrap().total, 1);
+        assert!(repo.get_customer(bashir.id).await.unwrap().is_some());
+        let trash = repo.list_deleted(&CUSTOMERS, &ListQuery::new(1, 10)).await.unwrap();
+        assert_eq!(trash.total, 1);
+        let trashed = &trash.items[0];
+        assert_eq!((trashed.id, trashed.label.as_str()), (bashir.id, "Bashir - 0711"));
+        assert_eq!((trashed.deleted_by, trashed.deleted_by_username.as_deref()), (Some(clerk.id), Some("clerk")));
+        assert_eq!(trashed.deleted_at.len(), "2026-01-01 00:00:00".len());
+        assert_eq!(repo.list_deleted(&CUSTOMERS, &search("071")).await.unwrap().total, 1);
+        assert_eq!(repo.list_deleted(&CUSTOMERS, &search("ahmad")).await.unwrap().total, 0);
+
+        assert!(repo.restore("customers", bashir.id).await.unwrap());
+        assert!(!repo.restore("customers", bashir.id).await.unwrap());
+        assert_eq!(repo.list_customers(&ListQuery::new(1, 10)).await.unwrap().total, 2);
+
+        assert!(repo.soft_delete("sales", sale, None).await.unwrap());
+        assert_eq!(repo.list_sales(&ListQuery::new(1, 10)).await.unwrap().total, 0);
+        let trashed_sale = repo.list_deleted(&SALES, &ListQuery::new(1, 10)).await.unwrap();
+        assert_eq!((trashed_sale.items[0].label.as_str(), trashed_sale.items[0].amount), ("2026-01-02 - first sale", Some(30.0)));
+        assert_eq!(trashed_sale.items[0].deleted_by_username, None);
+
+        // Nothing is purged before the retention period is over
+        assert!(repo.soft_delete("customers", ahmad.id, None).await.unwrap());
+        let policy = RetentionPolicy::default();
+        let report = purge(repo.as_ref(), &policy, crate::trash::BINS, Utc::now()).await.unwrap();
+        assert!(report.purged.values().all(|count| *count == 0));
+        assert_eq!(report.still_referenced, 0);
+
+        // Documents are kept longer than the trash period, and customers stay while their sales do
+        let later = Utc::now() + chrono::TimeDelta::days(31);
+        let report = purge(repo.as_ref(), &policy, crate::trash::BINS, later).await.unwrap();
+        assert_eq!((report.purged["sales"], report.purged["customers"], report.still_referenced), (0, 0, 1));
+
+        let short = RetentionPolicy { trash_days: 1, document_years: 0 };
+        let report = purge(repo.as_ref(), &short, crate::trash::BINS, later).await.unwrap();
+        assert_eq!((report.purged["sales"], report.purged["customers"], report.still_referenced), (1, 1, 0));
+        assert!(repo.get_sale(sale).await.unwrap().is_none());
+        assert!(repo.get_customer(ahmad.id).await.unwrap().is_none());
+        assert!(repo.get_customer(bashir.id).await.unwrap().is_some());
+        assert_eq!(repo.list_deleted(&CUSTOMERS, &ListQuery::new(1, 10)).await.unwrap().total, 0);
+    }
+
+    async fn api_token_contract(backend: Backend) {
+        let repo = backend.repo();
+        let user = repo
+            .create_user(&UserInput {
+                username: "clerk".to_string(),
+                email: "clerk@example.com".to_string(),
+                password_hash: "$2b$12$hash".to_string(),
+                full_name: None,
+                phone: None,
+                role: "sales".to_string(),
+                must_change_password: false,
+            })
+            .await
+            .unwrap();
+        let input = |name: &str, hash: &str, expires_at| ApiTokenInput {
+            user_id: user.id,
+            name: name.to_string(),
+            prefix: "shf_ab12".to_string(),
+            token_hash: hash.to_string(),
+            expires_at,
+        };
+        let now = Utc::now();
+        let token = repo.create_api_token(&input("reports", "hash-1", None)).await.unwrap();
+        assert_eq!((token.user_id, token.name.as_str(), token.last_used_at.as_deref()), (user.id, "reports", None));
+        assert_eq!(token.expires_at, None);
+        let expiring = repo
+            .create_api_token(&input("till", "hash-2", Some(now + chrono::TimeDelta::days(1))))
+            .await
+            .unwrap();
+        assert_eq!(expiring.expires_at.as_deref().map(str::len), Some("2026-01-01 00:00:00".len()));
+        assert_eq!(repo.list_api_tokens(Some(user.id)).await.unwrap().len(), 2);
+        assert_eq!(repo.list_api_tokens(Some(9999)).await.unwrap().len(), 0);
+        assert_eq!(repo.list_api_tokens(None).await.unwrap().len(), 2);
+
+        let used = repo.use_api_token("hash-1", now).await.unwrap().unwrap();
+        assert_eq!(used.id, token.id);
+        assert!(used.last_used_at.is_some());
+        assert_eq!(repo.get_api_token(token.id).await.unwrap().unwrap().last_used_at, used.last_used_at);
+        assert!(repo.use_api_token("no-such-hash", now).await.unwrap().is_none());
+        // Expired tokens no longer work
+        assert!(repo.use_api_token("hash-2", now).await.unwrap().is_some());
+        assert!(repo.use_api_token("hash-2", now + chrono::TimeDelta::days(2)).await.unwrap().is_none());
+
+        repo.delete_api_token(token.id).await.unwrap();
+        assert!(repo.get_api_token(token.id).await.unwrap().is_none());
+        assert!(repo.use_api_token("hash-1", now).await.unwrap().is_none());
+        // Deleting the user deletes their tokens
+        repo.delete_user(user.id).await.unwrap();
+        assert!(repo.list_api_tokens(None).await.unwrap().is_empty());
+    }
+
+    async fn report_contract(backend: Backend) {
+        let repo = backend.repo();
+        let buyer = repo.create_customer(&customer("Ahmad", "0700")).await.unwrap();
+        let rice = repo.create_product(&product("Rice")).await.unwrap();
+        let sale = backend.sale(buyer.id, rice.id).await;
+        backend.purchase(rice.id).await;
+
+        let totals = repo.sales_totals(None, None).await.unwrap();
+        assert_eq!(totals, [PeriodTotals { currency_id: None, count: 1, total_amount: 30.0 }]);
+        assert_eq!(repo.sales_totals(Some("2026-01-02"), Some("2026-01-02")).await.unwrap().len(), 1);
+        assert!(repo.sales_totals(Some("2026-01-03"), None).await.unwrap().is_empty());
+        assert!(repo.sales_totals(None, Some("2026-01-01")).await.unwrap().is_empty());
+
+        let totals = repo.purchases_totals(Some("2026-02-01"), Some("2026-02-28")).await.unwrap();
+        assert_eq!(totals, [PeriodTotals { currency_id: None, count: 1, total_amount: 50.0 }]);
+
+        // Trashed sales don't count
+        repo.soft_delete("sales", sale, None).await.unwrap();
+        assert!(repo.sales_totals(None, None).await.unwrap().is_empty());
+    }
+
+    /// Hostile input is stored and matched as a value, never run as a query
+    async fn injection_contract(backend: Backend) {
+        let repo = backend.repo();
+        let hostile = "x'); DELETE customers; --";
+        let created = repo.create_customer(&customer(hostile, "0700' OR '1'='1")).await.unwrap();
+        let other = repo.create_customer(&customer("Bashir", "0711")).await.unwrap();
+        assert_eq!(created.full_name, hostile);
+        assert_eq!(created.phone, "0700' OR '1'='1");
+        assert_eq!(repo.list_customers(&ListQuery::new(1, 10)).await.unwrap().total, 2);
+
+        assert_eq!(repo.list_customers(&search("' OR true OR '")).await.unwrap().total, 0);
+        assert_eq!(repo.list_customers(&search("delete customers")).await.unwrap().items[0].id, created.id);
+
+        let updated = repo.update_customer(other.id, &customer("Bashir\"; REMOVE TABLE customers; --", "0711")).await.unwrap();
+        assert_eq!(updated.full_name, "Bashir\"; REMOVE TABLE customers; --");
+        assert!(repo.get_customer(created.id).await.unwrap().is_some());
+
+        assert!(!repo.user_exists("' OR true OR '", "\" OR true OR \"").await.unwrap());
+    }
+
+    macro_rules! contract_tests {
+        ($($contract:ident),* $(,)?) => {
+            mod sqlite {
+                $(
+                    #[tokio::test]
+                    async fn $contract() {
+                        super::$contract(super::Backend::sqlite()).await;
+                    }
+                )*
+            }
+
+            mod surreal {
+                $(
+                    #[tokio::test]
+                    async fn $contract() {
+                        super::$contract(super::Backend::surreal().await).await;
+                    }
+                )*
+            }
+        };
+    }
+
+    contract_tests!(
+        customer_contract,
+        product_contract,
+        sale_contract,
+        sale_write_contract,
+        purchase_contract,
+        purchase_write_contract,
+        account_contract,
+        account_write_contract,
+        journal_contract,
+        journal_write_contract,
+        user_contract,
+        role_contract,
+        settings_contract,
+        audit_contract,
+        trash_contract,
+        api_token_contract,
+        report_contract,
+        injection_contract,
+    );
+}
@@ -0,0 +1,243 @@
+use crate::repository::Repositories;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Failed logins for one account before it is locked
+pub const MAX_FAILED_LOGINS: i64 = 5;
+/// First lockout; each further failure after it doubles the lockout, up to `MAX_LOCKOUT`
+pub const LOCKOUT: TimeDelta = TimeDelta::minutes(15);
+pub const MAX_LOCKOUT: TimeDelta = TimeDelta::hours(24);
+/// Failed logins from one device before it has to wait between attempts
+pub const FREE_DEVICE_ATTEMPTS: u32 = 3;
+pub const MAX_DEVICE_DELAY: TimeDelta = TimeDelta::minutes(5);
+/// A device that stopped failing for this long starts over
+pub const DEVICE_FAILURE_WINDOW: TimeDelta = TimeDelta::hours(1);
+
+const PASSWORD_POLICY_KEY: &str = "password_policy";
+
+/// Rules new passwords must follow; stored in the app settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub require_uppercase: bool,
+    pub require_lowercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    /// How many previous passwords can't be used again (0 allows reuse)
+    pub history: usize,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: 8,
+            require_uppercase: false,
+            require_lowercase: true,
+            require_digit: true,
+            require_symbol: false,
+            history: 5,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Everything wrong with `password`, empty if it is acceptable
+    pub fn problems(&self, password: &str, username: &str) -> Vec<String> {
+        let mut problems = Vec::new();
+        if password.chars().count() < self.min_length {
+            problems.push(format!("be at least {} characters", self.min_length));
+        }
+        let checks = [
+            (self.require_uppercase, "an uppercase letter", password.chars().any(char::is_uppercase)),
+            (self.require_lowercase, "a lowercase letter", password.chars().any(char::is_lowercase)),
+            (self.require_digit, "a digit", password.chars().any(|c| c.is_ascii_digit())),
+            (self.require_symbol, "a symbol", password.chars().any(|c| !c.is_alphanumeric() && !c.is_whitespace())),
+        ];
+        for (required, what, present) in checks {
+            if required && !present {
+                problems.push(format!("contain {}", what));
+            }
+        }
+        if !username.is_empty() && password.to_lowercase().contains(&username.to_lowercase()) {
+            problems.push("not contain the username".to_string());
+        }
+        problems
+    }
+
+    /// Fail with a readable message if `password` breaks the policy
+    pub fn check(&self, password: &str, username: &str) -> Result<()> {
+        let problems = self.problems(password, username);
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("Password must {}", problems.join(", ")))
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !(4..=128).contains(&self.min_length) {
+            return Err(anyhow!("Minimum password length must be between 4 and 128"));
+        }
+        if self.history > 24 {
+            return Err(anyhow!("Password history can't be longer than 24"));
+        }
+        Ok(())
+    }
+}
+
+pub async fn password_policy(repo: &dyn Repositories) -> Result<PasswordPolicy> {
+    Ok(match repo.get_setting(PASSWORD_POLICY_KEY).await? {
+        Some(value) => serde_json::from_value(value)?,
+        None => PasswordPolicy::default(),
+    })
+}
+
+pub async fn save_password_policy(repo: &dyn Repositories, policy: &PasswordPolicy) -> Result<()> {
+    policy.validate()?;
+    repo.set_setting(PASSWORD_POLICY_KEY, &serde_json::to_value(policy)?).await
+}
+
+/// Check `password` against the policy and, unless it allows reuse, the user's recent passwords
+pub async fn check_new_password(
+    repo: &dyn Repositories,
+    policy: &PasswordPolicy,
+    user_id: i64,
+    username: &str,
+    current_hash: &str,
+    password: &str,
+) -> Result<()> {
+    policy.check(password, username)?;
+    if policy.history == 0 {
+        return Ok(());
+    }
+    let previous = repo.password_history(user_id, policy.history as i64 - 1).await?;
+    for hash in std::iter::once(current_hash).chain(previous.iter().map(String::as_str)) {
+        if bcrypt::verify(password, hash).unwrap_or(false) {
+            return Err(anyhow!("Password was used recently; choose a different one"));
+        }
+    }
+    Ok(())
+}
+
+/// When an account with `failed_logins` failures is locked until, if it is locked at all
+pub fn lockout_until(failed_logins: i64, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    if failed_logins < MAX_FAILED_LOGINS {
+        return None;
+    }
+    let doublings = (failed_logins - MAX_FAILED_LOGINS).min(10) as u32;
+    Some(now + (LOCKOUT * 2i32.pow(doublings)).min(MAX_LOCKOUT))
+}
+
+struct DeviceFailures {
+    count: u32,
+    last_failure: DateTime<Utc>,
+}
+
+impl DeviceFailures {
+    fn retry_at(&self) -> DateTime<Utc> {
+        if self.count < FREE_DEVICE_ATTEMPTS {
+            return self.last_failure;
+        }
+        let seconds = 1i64 << (self.count - FREE_DEVICE_ATTEMPTS).min(16);
+        self.last_failure + TimeDelta::seconds(seconds).min(MAX_DEVICE_DELAY)
+    }
+}
+
+/// Failed logins per device (desktop window or network client), kept in memory.
+/// Each failure past the first few doubles the wait before the next attempt.
+#[derive(Default)]
+pub struct LoginThrottle {
+    devices: Mutex<HashMap<String, DeviceFailures>>,
+}
+
+impl LoginThrottle {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, DeviceFailures>> {
+        self.devices.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// How long `device` still has to wait before trying again, if at all
+    pub fn wait(&self, device: &str, now: DateTime<Utc>) -> Option<TimeDelta> {
+        let mut devices = self.lock();
+        devices.retain(|_, failures| now - failures.last_failure < DEVICE_FAILURE_WINDOW);
+        let retry_at = devices.get(device)?.retry_at();
+        (retry_at > now).then(|| retry_at - now)
+    }
+
+    pub fn failed(&self, device: &str, now: DateTime<Utc>) {
+        let mut devices = self.lock();
+        let failures = devices
+            .entry(device.to_string())
+            .or_insert(DeviceFailures { count: 0, last_failure: now });
+        failures.count += 1;
+        failures.last_failure = now;
+    }
+
+    pub fn succeeded(&self, device: &str) {
+        self.lock().remove(device);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + seconds, 0).unwrap()
+    }
+
+    #[test]
+    fn test_password_policy() {
+        let policy = PasswordPolicy::default();
+        assert!(policy.check("correct horse 7", "sara").is_ok());
+        assert_eq!(policy.problems("abc", "sara"), ["be at least 8 characters", "contain a digit"]);
+        assert_eq!(policy.problems("sara2024!", "Sara"), ["not contain the username"]);
+
+        let strict = PasswordPolicy { min_length: 12, require_uppercase: true, require_symbol: true, ..PasswordPolicy::default() };
+        let error = strict.check("lowercase", "sara").unwrap_err().to_string();
+        assert_eq!(error, "Password must be at least 12 characters, contain an uppercase letter, contain a digit, contain a symbol");
+        assert!(strict.check("Zebra-crossing-9", "sara").is_ok());
+
+        // Settings saved by an older version fill the missing rules from the defaults
+        let partial: PasswordPolicy = serde_json::from_value(serde_json::json!({ "min_length": 10 })).unwrap();
+        assert_eq!(partial, PasswordPolicy { min_length: 10, ..PasswordPolicy::default() });
+        assert!(PasswordPolicy { min_length: 2, ..PasswordPolicy::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_lockout_grows_with_failures() {
+        assert_eq!(lockout_until(MAX_FAILED_LOGINS - 1, at(0)), None);
+        assert_eq!(lockout_until(MAX_FAILED_LOGINS, at(0)), Some(at(15 * 60)));
+        assert_eq!(lockout_until(MAX_FAILED_LOGINS + 2, at(0)), Some(at(60 * 60)));
+        assert_eq!(lockout_until(MAX_FAILED_LOGINS + 40, at(0)), Some(at(24 * 60 * 60)));
+    }
+
+    #[test]
+    fn test_device_delay_is_progressive() {
+        let throttle = LoginThrottle::default();
+        for second in 0..FREE_DEVICE_ATTEMPTS as i64 {
+            assert_eq!(throttle.wait("main", at(second)), None);
+            throttle.failed("main", at(second));
+        }
+        // Third failure at second 2: wait 1s, then 2s, 4s...
+        assert_eq!(throttle.wait("main", at(2)), Some(TimeDelta::seconds(1)));
+        assert_eq!(throttle.wait("main", at(3)), None);
+        throttle.failed("main", at(3));
+        assert_eq!(throttle.wait("main", at(3)), Some(TimeDelta::seconds(2)));
+        // Other devices aren't affected
+        assert_eq!(throttle.wait("lan-1", at(3)), None);
+
+        for _ in 0..20 {
+            throttle.failed("main", at(10));
+        }
+        assert_eq!(throttle.wait("main", at(10)), Some(MAX_DEVICE_DELAY));
+        // A quiet hour or a successful login starts over
+        assert_eq!(throttle.wait("main", at(10 + 3600)), None);
+        throttle.failed("main", at(4000));
+        throttle.succeeded("main");
+        assert_eq!(throttle.wait("main", at(4000)), None);
+    }
+}
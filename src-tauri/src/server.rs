@@ -1,29 +1,116 @@
 use axum::{
     body::Body,
-    extract::State,
-    http::{Response, StatusCode},
+    extract::{rejection::JsonRejection, ConnectInfo, Request, State},
+    http::{header, HeaderName, HeaderValue, Method, Response, StatusCode},
+    middleware::{self, Next},
     response::IntoResponse,
     routing::{get, post},
+    Extension,
     Json,
     Router,
 };
+use hyper_util::rt::TokioIo;
+use hyper_util::service::TowerToHyperService;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use tauri::{AppHandle, Manager};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::api;
+use crate::api_types::{ApiError, PuterCredentials, API_PREFIX};
+use crate::lan;
+use crate::openapi;
+use crate::server_config::{self, ServerConfig, SECRET_HEADER};
+use crate::session::random_hex;
+use crate::tls;
 
 // Embed ai.html content at compile time for production
 // In development, try to read from file first, fallback to embedded
 const EMBEDDED_AI_HTML: &str = include_str!("../../ai.html");
 
-#[derive(Debug, Serialize, Deserialize)]
-struct PuterCredentials {
-    app_id: String,
-    auth_token: String,
+/// How the app reaches the running server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerAccess {
+    /// Base URL, e.g. `http://127.0.0.1:5021`
+    pub url: String,
+    /// Per-install secret the app sends in the `X-Shafaf-Secret` header on `/api/*`
+    pub secret: String,
+    /// SHA-256 fingerprint of the self-signed certificate when serving HTTPS
+    pub tls_fingerprint: Option<String>,
+}
+
+static SECRET: OnceLock<String> = OnceLock::new();
+static RUNNING: OnceLock<ServerAccess> = OnceLock::new();
+
+/// Server settings from the keyring, the defaults when none are stored
+pub fn load_config() -> Result<ServerConfig, String> {
+    use keyring::Entry;
+
+    let entry = Entry::new("finance_app", "server_config")
+        .map_err(|e| format!("Failed to create keyring entry: {}", e))?;
+    match entry.get_password() {
+        Ok(config_json) => serde_json::from_str(&config_json)
+            .map_err(|e| format!("Failed to deserialize server config: {}", e)),
+        Err(keyring::Error::NoEntry) => Ok(ServerConfig::default()),
+        Err(e) => Err(format!("Failed to get server config: {}", e)),
+    }
+}
+
+/// Check and store server settings; they apply the next time the app starts
+pub fn save_config(config: ServerConfig) -> Result<ServerConfig, String> {
+    use keyring::Entry;
+
+    let config = config.validated().map_err(|e| e.to_string())?;
+    let entry = Entry::new("finance_app", "server_config")
+        .map_err(|e| format!("Failed to create keyring entry: {}", e))?;
+    let config_json = serde_json::to_string(&config)
+        .map_err(|e| format!("Failed to serialize server config: {}", e))?;
+    entry.set_password(&config_json)
+        .map_err(|e| format!("Failed to store server config: {}", e))?;
+    Ok(config)
+}
+
+/// The per-install secret, created and stored in the keyring on first use.
+/// Without a keyring it only lasts until the app exits.
+fn install_secret() -> &'static str {
+    SECRET.get_or_init(|| {
+        use keyring::Entry;
+
+        let Ok(entry) = Entry::new("finance_app", "server_secret") else {
+            return random_hex(32);
+        };
+        match entry.get_password() {
+            Ok(secret) if !secret.is_empty() => secret,
+            _ => {
+                let secret = random_hex(32);
+                if let Err(e) = entry.set_password(&secret) {
+                    eprintln!("⚠️ Failed to store the server secret, using one for this run only: {}", e);
+                }
+                secret
+            }
+        }
+    })
+}
+
+/// URL, secret and certificate of the running server; None until it has started
+pub fn access() -> Option<ServerAccess> {
+    RUNNING.get().cloned()
 }
 
-/// Start the HTTP server on port 5021 to serve ai.html
+/// Start the HTTP server that serves ai.html, the REST API and, in LAN mode, the app itself,
+/// as configured in the server settings
 pub async fn start_server(app_handle: AppHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let config = load_config().unwrap_or_else(|e| {
+        eprintln!("⚠️ {}; using the default server settings", e);
+        ServerConfig::default()
+    });
+    let config = config.validated()?;
+
     // Try to find ai.html in multiple locations (for development)
     let resource_dir = app_handle
         .path()
@@ -79,19 +166,46 @@ pub async fn start_server(app_handle: AppHandle) -> Result<(), Box<dyn std::erro
     
     let credentials_path = Arc::new(app_data_dir.join("puter_credentials.json"));
 
-    // Create the router
-    let app = Router::new()
-        .route("/", get(serve_ai_html))
-        .route("/ai.html", get(serve_ai_html))
+    // The app's own endpoints need the install secret; /api/v1 has its own API tokens
+    let secret = Arc::new(install_secret().to_string());
+    let internal = Router::new()
         .route("/api/store-credentials", post(store_credentials))
         .route("/api/get-credentials", get(get_credentials))
+        .route_layer(middleware::from_fn_with_state(secret.clone(), require_secret));
+
+    // Create the router; in LAN mode the app takes `/` and every path nothing else serves
+    let pages = if config.lan_mode {
+        lan::router(app_handle.clone(), config.tls)
+    } else {
+        Router::new()
+            .route("/", get(serve_ai_html))
+    };
+    let app = Router::new()
+        .route("/ai.html", get(serve_ai_html))
+        .route("/api/openapi.json", get(serve_openapi))
+        .merge(internal)
+        .nest(API_PREFIX, api::router(app_handle.clone()))
+        .merge(pages)
+        .layer(cors(&config))
         .with_state((ai_html_content.clone(), credentials_path));
 
-    // Bind to all interfaces on port 5021
-    let bind_addr = "0.0.0.0:5021";
-    let listener = match tokio::net::TcpListener::bind(bind_addr).await {
+    let identity = if config.tls {
+        Some(tls::load_or_create(&app_data_dir.join("tls"), &config.certificate_hosts())?)
+    } else {
+        None
+    };
+
+    let bind_addr = config.socket_addr()?;
+    let listener = match TcpListener::bind(bind_addr).await {
         Ok(listener) => {
-            println!("🚀 AI server started at http://127.0.0.1:5021/ai.html (listening on all interfaces)");
+            println!("🚀 AI server started at {}/ai.html (listening on {})", config.local_url(), bind_addr);
+            println!("🔑 REST API at {}{} (needs an API token), described at /api/openapi.json", config.local_url(), API_PREFIX);
+            if config.lan_mode {
+                println!("📱 LAN mode: other devices can open the app at http{}://<this machine's address>:{}", if config.tls { "s" } else { "" }, config.port);
+            }
+            if !config.is_loopback() && !config.tls {
+                println!("⚠️ The server is reachable from the network without TLS");
+            }
             listener
         }
         Err(e) => {
@@ -103,9 +217,19 @@ pub async fn start_server(app_handle: AppHandle) -> Result<(), Box<dyn std::erro
             return Err(Box::new(e));
         }
     };
-    
+
+    let _ = RUNNING.set(ServerAccess {
+        url: config.local_url(),
+        secret: secret.to_string(),
+        tls_fingerprint: identity.as_ref().map(|identity| identity.fingerprint()),
+    });
+
     // Start serving
-    if let Err(e) = axum::serve(listener, app).await {
+    let served = match identity {
+        Some(identity) => serve_tls(listener, identity.acceptor()?, app).await,
+        None => axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await,
+    };
+    if let Err(e) = served {
         eprintln!("❌ Server error: {}", e);
         return Err(Box::new(e));
     }
@@ -113,6 +237,57 @@ pub async fn start_server(app_handle: AppHandle) -> Result<(), Box<dyn std::erro
     Ok(())
 }
 
+/// CORS policy: only the app's webview and the configured origins may call the server from a browser
+fn cors(config: &ServerConfig) -> CorsLayer {
+    let origins: Vec<HeaderValue> = config
+        .cors_origins()
+        .iter()
+        .filter_map(|origin| HeaderValue::from_str(origin).ok())
+        .collect();
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+        .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION, HeaderName::from_static(SECRET_HEADER)])
+}
+
+/// Refuse requests that don't carry the install secret
+async fn require_secret(State(secret): State<Arc<String>>, request: Request, next: Next) -> Response<Body> {
+    let presented = request
+        .headers()
+        .get(SECRET_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !server_config::secret_matches(&secret, presented) {
+        return ApiError::unauthenticated("Missing or wrong server secret").into_response();
+    }
+    next.run(request).await
+}
+
+/// Serve HTTPS: TLS handshake, then HTTP/1.1 on each accepted connection
+async fn serve_tls(listener: TcpListener, acceptor: TlsAcceptor, app: Router) -> std::io::Result<()> {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                eprintln!("⚠️ Failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let service = TowerToHyperService::new(app.clone().layer(Extension(ConnectInfo(peer))));
+        tokio::spawn(async move {
+            // Browsers that don't trust the self-signed certificate abort the handshake; nothing to report
+            let Ok(stream) = acceptor.accept(stream).await else {
+                return;
+            };
+            let _ = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .with_upgrades()
+                .await;
+        });
+    }
+}
+
 /// Handler to serve ai.html
 async fn serve_ai_html(
     State((content, _)): State<(String, Arc<PathBuf>)>,
@@ -120,7 +295,6 @@ async fn serve_ai_html(
     Response::builder()
         .status(StatusCode::OK)
         .header("Content-Type", "text/html; charset=utf-8")
-        .header("Access-Control-Allow-Origin", "*")
         .body(Body::from(content))
         .unwrap()
 }
@@ -128,84 +302,37 @@ async fn serve_ai_html(
 /// Handler to store Puter credentials
 async fn store_credentials(
     State((_, credentials_path)): State<(String, Arc<PathBuf>)>,
-    Json(credentials): Json<PuterCredentials>,
-) -> impl IntoResponse {
-    // Store credentials in JSON file
-    match serde_json::to_string_pretty(&credentials) {
-        Ok(json) => {
-            // Ensure parent directory exists
-            if let Some(parent) = credentials_path.parent() {
-                if let Err(e) = std::fs::create_dir_all(parent) {
-                    return Response::builder()
-                        .status(StatusCode::INTERNAL_SERVER_ERROR)
-                        .header("Content-Type", "application/json")
-                        .header("Access-Control-Allow-Origin", "*")
-                        .body(Body::from(format!(r#"{{"error": "Failed to create directory: {}"}}"#, e)))
-                        .unwrap();
-                }
-            }
-            
-            // Write credentials file
-            if let Err(e) = std::fs::write(&*credentials_path, json) {
-                return Response::builder()
-                    .status(StatusCode::INTERNAL_SERVER_ERROR)
-                    .header("Content-Type", "application/json")
-                    .header("Access-Control-Allow-Origin", "*")
-                    .body(Body::from(format!(r#"{{"error": "Failed to write credentials: {}"}}"#, e)))
-                    .unwrap();
-            }
-            
-            Response::builder()
-                .status(StatusCode::OK)
-                .header("Content-Type", "application/json")
-                .header("Access-Control-Allow-Origin", "*")
-                .body(Body::from(r#"{"success": true}"#))
-                .unwrap()
-        }
-        Err(e) => {
-            Response::builder()
-                .status(StatusCode::BAD_REQUEST)
-                .header("Content-Type", "application/json")
-                .header("Access-Control-Allow-Origin", "*")
-                .body(Body::from(format!(r#"{{"error": "Failed to serialize credentials: {}"}}"#, e)))
-                .unwrap()
-        }
+    credentials: Result<Json<PuterCredentials>, JsonRejection>,
+) -> Result<Json<Value>, ApiError> {
+    let Json(credentials) = credentials?;
+    let json = serde_json::to_string_pretty(&credentials)
+        .map_err(|e| ApiError::invalid(format!("Failed to serialize credentials: {}", e)))?;
+
+    // Ensure parent directory exists
+    if let Some(parent) = credentials_path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| ApiError::internal(format!("Failed to create directory: {}", e)))?;
     }
+
+    // Write credentials file
+    std::fs::write(&*credentials_path, json)
+        .map_err(|e| ApiError::internal(format!("Failed to write credentials: {}", e)))?;
+
+    Ok(Json(json!({ "success": true })))
 }
 
 /// Handler to get Puter credentials
 async fn get_credentials(
     State((_, credentials_path)): State<(String, Arc<PathBuf>)>,
-) -> impl IntoResponse {
-    match std::fs::read_to_string(&*credentials_path) {
-        Ok(content) => {
-            match serde_json::from_str::<PuterCredentials>(&content) {
-                Ok(credentials) => {
-                    Response::builder()
-                        .status(StatusCode::OK)
-                        .header("Content-Type", "application/json")
-                        .header("Access-Control-Allow-Origin", "*")
-                        .body(Body::from(serde_json::to_string(&credentials).unwrap()))
-                        .unwrap()
-                }
-                Err(e) => {
-                    Response::builder()
-                        .status(StatusCode::INTERNAL_SERVER_ERROR)
-                        .header("Content-Type", "application/json")
-                        .header("Access-Control-Allow-Origin", "*")
-                        .body(Body::from(format!(r#"{{"error": "Failed to parse credentials: {}"}}"#, e)))
-                        .unwrap()
-                }
-            }
-        }
-        Err(_) => {
-            // File doesn't exist, return empty response
-            Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .header("Content-Type", "application/json")
-                .header("Access-Control-Allow-Origin", "*")
-                .body(Body::from(r#"{"error": "No credentials found"}"#))
-                .unwrap()
-        }
-    }
+) -> Result<Json<PuterCredentials>, ApiError> {
+    let content = std::fs::read_to_string(&*credentials_path)
+        .map_err(|_| ApiError::not_found("No credentials found"))?;
+    let credentials = serde_json::from_str(&content)
+        .map_err(|e| ApiError::internal(format!("Failed to parse credentials: {}", e)))?;
+    Ok(Json(credentials))
+}
+
+/// Handler to serve the OpenAPI document of every route
+async fn serve_openapi() -> Json<Value> {
+    Json(openapi::document())
 }
@@ -0,0 +1,213 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// Origins of the app's own webview (bundled and `npm run dev`), always allowed by CORS
+pub const WEBVIEW_ORIGINS: &[&str] = &[
+    "tauri://localhost",
+    "http://tauri.localhost",
+    "https://tauri.localhost",
+    "http://localhost:1420",
+];
+
+/// Header that carries the per-install secret on the app's own `/api/*` requests
+pub const SECRET_HEADER: &str = "x-shafaf-secret";
+/// Cookie that carries a LAN browser's session token; scripts on the page can't read it
+pub const SESSION_COOKIE: &str = "shafaf_session";
+
+/// Settings of the built-in HTTP server; changes apply the next time the app starts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Address to listen on; loopback unless other machines should reach the server
+    pub bind_address: String,
+    pub port: u16,
+    /// Browser origins, besides the app's own webview, allowed to call the server
+    pub allowed_origins: Vec<String>,
+    /// Serve HTTPS with a self-signed certificate created on first run
+    pub tls: bool,
+    /// Serve the app itself and `/api/rpc`, so browsers on the network can log in and use it
+    pub lan_mode: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind_address: Ipv4Addr::LOCALHOST.to_string(),
+            port: 5021,
+            allowed_origins: Vec::new(),
+            tls: false,
+            lan_mode: false,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// The settings with origins normalised, or why they can't be used
+    pub fn validated(mut self) -> Result<Self> {
+        self.bind_address = self.bind_address.trim().to_string();
+        self.bind_ip()?;
+        if self.port == 0 {
+            return Err(anyhow!("Port must be between 1 and 65535"));
+        }
+        if self.lan_mode && self.is_loopback() {
+            return Err(anyhow!("LAN mode needs a bind address other devices can reach, such as 0.0.0.0"));
+        }
+        let mut origins = Vec::new();
+        for origin in &self.allowed_origins {
+            let origin = normalize_origin(origin)?;
+            if !origins.contains(&origin) {
+                origins.push(origin);
+            }
+        }
+        self.allowed_origins = origins;
+        Ok(self)
+    }
+
+    fn bind_ip(&self) -> Result<IpAddr> {
+        self.bind_address
+            .trim_matches(|c| c == '[' || c == ']')
+            .parse()
+            .map_err(|_| anyhow!("Bind address must be an IP address such as 127.0.0.1 or 0.0.0.0"))
+    }
+
+    pub fn socket_addr(&self) -> Result<SocketAddr> {
+        Ok(SocketAddr::new(self.bind_ip()?, self.port))
+    }
+
+    /// Whether only this machine can reach the server
+    pub fn is_loopback(&self) -> bool {
+        self.bind_ip().map(|ip| ip.is_loopback()).unwrap_or(false)
+    }
+
+    /// Address this machine reaches the server at
+    fn local_ip(&self) -> IpAddr {
+        match self.bind_ip() {
+            Ok(IpAddr::V4(ip)) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            Ok(IpAddr::V6(ip)) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            Ok(ip) => ip,
+            Err(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        }
+    }
+
+    /// Base URL the app itself uses, e.g. `http://127.0.0.1:5021`
+    pub fn local_url(&self) -> String {
+        let scheme = if self.tls { "https" } else { "http" };
+        format!("{}://{}", scheme, SocketAddr::new(self.local_ip(), self.port))
+    }
+
+    /// Host names and addresses the TLS certificate is made for
+    pub fn certificate_hosts(&self) -> Vec<String> {
+        let mut hosts = vec!["localhost".to_string(), "127.0.0.1".to_string(), "::1".to_string()];
+        let ip = self.local_ip().to_string();
+        if !hosts.contains(&ip) {
+            hosts.push(ip);
+        }
+        hosts
+    }
+
+    /// Every origin CORS lets through: the webview's and the configured ones
+    pub fn cors_origins(&self) -> Vec<String> {
+        WEBVIEW_ORIGINS
+            .iter()
+            .map(|origin| origin.to_string())
+            .chain(self.allowed_origins.iter().cloned())
+            .collect()
+    }
+}
+
+/// `scheme://host[:port]` of an origin, rejecting wildcards and URLs with a path
+fn normalize_origin(origin: &str) -> Result<String> {
+    let invalid = || anyhow!("\"{}\" is not an origin such as https://example.com", origin.trim());
+    let url = url::Url::parse(origin.trim()).map_err(|_| invalid())?;
+    let bare = url.path() == "/" && url.query().is_none() && url.fragment().is_none() && url.username().is_empty();
+    if !matches!(url.scheme(), "http" | "https") || url.host().is_none() || !bare {
+        return Err(invalid());
+    }
+    Ok(url.origin().ascii_serialization())
+}
+
+/// Whether `presented` is the install secret; the comparison takes the same time wherever they differ
+pub fn secret_matches(secret: &str, presented: &str) -> bool {
+    secret.len() == presented.len()
+        && secret.bytes().zip(presented.bytes()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_are_loopback() {
+        let config = ServerConfig::default();
+        assert!(config.is_loopback());
+        assert_eq!(config.local_url(), "http://127.0.0.1:5021");
+        assert_eq!(config.cors_origins(), WEBVIEW_ORIGINS.to_vec());
+
+        // Missing fields in stored settings fall back to the defaults
+        let stored: ServerConfig = serde_json::from_str(r#"{"tls": true}"#).unwrap();
+        assert_eq!(stored.local_url(), "https://127.0.0.1:5021");
+    }
+
+    #[test]
+    fn test_validated() {
+        let config = ServerConfig {
+            bind_address: " 0.0.0.0 ".to_string(),
+            port: 8443,
+            allowed_origins: vec![
+                "https://Shop.Example.com/".to_string(),
+                "https://shop.example.com".to_string(),
+                "http://192.168.1.20:3000".to_string(),
+            ],
+            tls: true,
+            lan_mode: true,
+        }
+        .validated()
+        .unwrap();
+        assert!(!config.is_loopback());
+        assert_eq!(config.allowed_origins, vec!["https://shop.example.com", "http://192.168.1.20:3000"]);
+        assert_eq!(config.local_url(), "https://127.0.0.1:8443");
+        assert_eq!(config.socket_addr().unwrap().to_string(), "0.0.0.0:8443");
+
+        let v6 = ServerConfig { bind_address: "[::1]".to_string(), ..ServerConfig::default() };
+        assert_eq!(v6.validated().unwrap().local_url(), "http://[::1]:5021");
+
+        for origin in ["*", "https://example.com/app", "file:///tmp", "example.com", "https://example.com?a=1"] {
+            let config = ServerConfig { allowed_origins: vec![origin.to_string()], ..ServerConfig::default() };
+            assert!(config.validated().is_err(), "{} accepted", origin);
+        }
+        let config = ServerConfig { bind_address: "localhost".to_string(), ..ServerConfig::default() };
+        assert!(config.validated().is_err());
+        let config = ServerConfig { port: 0, ..ServerConfig::default() };
+        assert!(config.validated().is_err());
+        let config = ServerConfig { lan_mode: true, ..ServerConfig::default() };
+        assert!(config.validated().is_err(), "LAN mode on loopback");
+    }
+
+    #[test]
+    fn test_certificate_hosts() {
+        let lan = ServerConfig { bind_address: "192.168.1.5".to_string(), ..ServerConfig::default() };
+        assert_eq!(lan.certificate_hosts(), vec!["localhost", "127.0.0.1", "::1", "192.168.1.5"]);
+        assert_eq!(ServerConfig::default().certificate_hosts().len(), 3);
+    }
+
+    #[test]
+    fn test_secret_matches() {
+        assert!(secret_matches("abc123", "abc123"));
+        assert!(!secret_matches("abc123", "abc124"));
+        assert!(!secret_matches("abc123", "abc12"));
+        assert!(!secret_matches("abc123", ""));
+    }
+
+    #[test]
+    fn test_ai_page_link_carries_secret() {
+        // The app opens ai.html with the secret in the fragment; the page sends it back in the header
+        let server_ts = include_str!("../../src/utils/server.ts");
+        assert!(server_ts.contains("/ai.html#secret=${encodeURIComponent(access.secret)}"));
+        let puter_ts = include_str!("../../src/utils/puter.ts");
+        assert!(puter_ts.contains("openUrl(aiPageUrl(access))"));
+        let page = include_str!("../../ai.html");
+        assert!(page.contains("new URLSearchParams(window.location.hash.slice(1)).get('secret')"));
+        assert!(page.to_ascii_lowercase().contains(&format!("'{}': serversecret", SECRET_HEADER)));
+    }
+}
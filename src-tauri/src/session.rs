@@ -0,0 +1,462 @@
+use crate::permissions::{self, Access, AccessError, Capability, Session};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+
+/// How long a session lasts after login, however active it is
+pub const SESSION_LIFETIME: TimeDelta = TimeDelta::hours(12);
+/// A session nobody used for this long is over
+pub const IDLE_TIMEOUT: TimeDelta = TimeDelta::minutes(30);
+/// How long a password confirmation unlocks sensitive commands
+pub const REAUTHENTICATION_WINDOW: TimeDelta = TimeDelta::minutes(5);
+
+/// A session as shown to its user and to administrators (never includes the token)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub user_id: i64,
+    pub username: String,
+    pub role: String,
+    /// Where the session was opened, e.g. the desktop window label
+    pub client: String,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    /// When the session ends unless it is used again before then
+    pub expires_at: DateTime<Utc>,
+    /// The session of the window asking
+    pub current: bool,
+}
+
+/// The logged-in user of a window, for `get_current_user`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CurrentUser {
+    #[serde(flatten)]
+    pub session: SessionInfo,
+    pub capabilities: BTreeSet<Capability>,
+    pub must_change_password: bool,
+    pub must_enroll_two_factor: bool,
+}
+
+struct Entry {
+    id: String,
+    principal: Session,
+    client: String,
+    created_at: DateTime<Utc>,
+    last_seen_at: DateTime<Utc>,
+    /// Login or the last password confirmation
+    authenticated_at: DateTime<Utc>,
+}
+
+impl Entry {
+    fn expires_at(&self) -> DateTime<Utc> {
+        (self.created_at + SESSION_LIFETIME).min(self.last_seen_at + IDLE_TIMEOUT)
+    }
+
+    fn info(&self, current: bool) -> SessionInfo {
+        SessionInfo {
+            id: self.id.clone(),
+            user_id: self.principal.user_id,
+            username: self.principal.username.clone(),
+            role: self.principal.role.clone(),
+            client: self.client.clone(),
+            created_at: self.created_at,
+            last_seen_at: self.last_seen_at,
+            expires_at: self.expires_at(),
+            current,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Sessions {
+    /// Keyed by the SHA-256 of the token, so the tokens themselves are never kept
+    entries: HashMap<String, Entry>,
+    /// Session key each desktop window is logged in with
+    clients: HashMap<String, String>,
+}
+
+impl Sessions {
+    fn prune(&mut self, now: DateTime<Utc>) {
+        self.entries.retain(|_, entry| entry.expires_at() > now);
+        let entries = &self.entries;
+        self.clients.retain(|_, key| entries.contains_key(key));
+    }
+}
+
+/// Server-side sessions, held in Tauri state
+#[derive(Default)]
+pub struct SessionStore {
+    sessions: Mutex<Sessions>,
+}
+
+pub(crate) fn random_hex(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub(crate) fn token_key(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+impl SessionStore {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Sessions> {
+        // A panic while holding the lock can't leave the maps half-updated
+        self.sessions.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Open a session for `principal` and log `client` into it, ending the client's previous session.
+    /// Returns the opaque token and the new session.
+    pub fn start(&self, principal: Session, client: &str, now: DateTime<Utc>) -> (String, SessionInfo) {
+        let token = random_hex(32);
+        let key = token_key(&token);
+        let entry = Entry {
+            id: random_hex(8),
+            principal,
+            client: client.to_string(),
+            created_at: now,
+            last_seen_at: now,
+            authenticated_at: now,
+        };
+        let info = entry.info(true);
+
+        let mut sessions = self.lock();
+        sessions.prune(now);
+        if let Some(previous) = sessions.clients.insert(client.to_string(), key.clone()) {
+            sessions.entries.remove(&previous);
+        }
+        sessions.entries.insert(key, entry);
+        (token, info)
+    }
+
+    /// End the session `client` is logged in with; false if it had none
+    pub fn end(&self, client: &str) -> bool {
+        let mut sessions = self.lock();
+        match sessions.clients.remove(client) {
+            Some(key) => sessions.entries.remove(&key).is_some(),
+            None => false,
+        }
+    }
+
+    /// The logged-in user of `client`, without counting as activity
+    pub fn current(&self, client: &str, now: DateTime<Utc>) -> Option<CurrentUser> {
+        let mut sessions = self.lock();
+        sessions.prune(now);
+        let entry = sessions.entries.get(sessions.clients.get(client)?)?;
+        Some(CurrentUser {
+            session: entry.info(true),
+            capabilities: entry.principal.capabilities.clone(),
+            must_change_password: entry.principal.must_change_password,
+            must_enroll_two_factor: entry.principal.must_enroll_two_factor,
+        })
+    }
+
+    /// Whether `token` is the token of the session `client` is logged in with
+    pub fn holds(&self, client: &str, token: &str) -> bool {
+        self.lock().clients.get(client) == Some(&token_key(token))
+    }
+
+    /// Decide whether `client` may call `command` now; a permitted call counts as activity
+    pub fn authorize(&self, client: &str, command: &str, now: DateTime<Utc>) -> Result<(), AccessError> {
+        if permissions::command_access(command) == Some(Access::Public) {
+            return Ok(());
+        }
+        let mut sessions = self.lock();
+        let key = sessions.clients.get(client).cloned();
+        let expired = key
+            .as_ref()
+            .and_then(|key| sessions.entries.get(key))
+            .is_some_and(|entry| entry.expires_at() <= now);
+        sessions.prune(now);
+        if expired {
+            return Err(AccessError::Unauthenticated {
+                command: command.to_string(),
+                message: "Your session has expired, please log in again".to_string(),
+            });
+        }
+
+        let entry = key.and_then(|key| sessions.entries.get_mut(&key));
+        permissions::authorize(command, entry.as_ref().map(|entry| &entry.principal))?;
+        let Some(entry) = entry else {
+            return Ok(());
+        };
+        permissions::check_account_setup(command, &entry.principal)?;
+        if permissions::requires_reauthentication(command) && now - entry.authenticated_at > REAUTHENTICATION_WINDOW {
+            return Err(AccessError::ReauthenticationRequired {
+                command: command.to_string(),
+                message: "Please confirm your password to continue".to_string(),
+            });
+        }
+        entry.last_seen_at = now;
+        Ok(())
+    }
+
+    /// Record that the user of `client` just confirmed their password
+    pub fn reauthenticated(&self, client: &str, now: DateTime<Utc>) -> bool {
+        let mut sessions = self.lock();
+        let Some(key) = sessions.clients.get(client).cloned() else {
+            return false;
+        };
+        match sessions.entries.get_mut(&key) {
+            Some(entry) => {
+                entry.authenticated_at = now;
+                entry.last_seen_at = now;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Record that the user of `client` chose a new password, which also counts as confirming it
+    pub fn password_changed(&self, client: &str, now: DateTime<Utc>) {
+        let mut sessions = self.lock();
+        let Some(key) = sessions.clients.get(client).cloned() else {
+            return;
+        };
+        if let Some(entry) = sessions.entries.get_mut(&key) {
+            entry.principal.must_change_password = false;
+            entry.authenticated_at = now;
+        }
+    }
+
+    /// Record that the user of `client` finished setting up two-factor authentication
+    pub fn two_factor_enrolled(&self, client: &str) {
+        let mut sessions = self.lock();
+        let Some(key) = sessions.clients.get(client).cloned() else {
+            return;
+        };
+        if let Some(entry) = sessions.entries.get_mut(&key) {
+            entry.principal.must_enroll_two_factor = false;
+        }
+    }
+
+    /// Every live session, oldest first; `current` marks the one `client` uses
+    pub fn list(&self, client: &str, now: DateTime<Utc>) -> Vec<SessionInfo> {
+        let mut sessions = self.lock();
+        sessions.prune(now);
+        let current = sessions.clients.get(client);
+        let mut list: Vec<SessionInfo> = sessions
+            .entries
+            .iter()
+            .map(|(key, entry)| entry.info(current == Some(key)))
+            .collect();
+        list.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+        list
+    }
+
+    /// End the session with public id `id`; false if there is none
+    pub fn revoke(&self, id: &str) -> bool {
+        let mut sessions = self.lock();
+        let Some(key) = sessions.entries.iter().find(|(_, entry)| entry.id == id).map(|(key, _)| key.clone()) else {
+            return false;
+        };
+        sessions.entries.remove(&key);
+        sessions.clients.retain(|_, bound| *bound != key);
+        true
+    }
+
+    /// Give sessions of users with `role` the role's new capabilities
+    pub fn refresh_role(&self, role: &str, capabilities: &BTreeSet<Capability>) {
+        for entry in self.lock().entries.values_mut().filter(|entry| entry.principal.role == role) {
+            entry.principal.capabilities = capabilities.clone();
+        }
+    }
+
+    /// Give the sessions of `user_id` their new username, role and capabilities
+    pub fn refresh_user(&self, user_id: i64, username: &str, role: &str, capabilities: &BTreeSet<Capability>) {
+        for entry in self.lock().entries.values_mut().filter(|entry| entry.principal.user_id == user_id) {
+            entry.principal.username = username.to_string();
+            entry.principal.role = role.to_string();
+            entry.principal.capabilities = capabilities.clone();
+        }
+    }
+
+    /// End every session of `user_id`, e.g. after the account is disabled; returns how many were open
+    pub fn end_user(&self, user_id: i64) -> usize {
+        let mut sessions = self.lock();
+        let Sessions { entries, clients } = &mut *sessions;
+        let before = entries.len();
+        entries.retain(|_, entry| entry.principal.user_id != user_id);
+        clients.retain(|_, key| entries.contains_key(key));
+        before - entries.len()
+    }
+
+    /// End every session, e.g. when another database is opened: they belong to the users of the
+    /// previous one; returns how many were open
+    pub fn end_all(&self) -> usize {
+        let mut sessions = self.lock();
+        sessions.clients.clear();
+        let count = sessions.entries.len();
+        sessions.entries.clear();
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn principal(role: &str, capabilities: &[Capability]) -> Session {
+        Session {
+            user_id: 7,
+            username: "sara".to_string(),
+            role: role.to_string(),
+            capabilities: capabilities.iter().copied().collect(),
+            must_change_password: false,
+            must_enroll_two_factor: false,
+        }
+    }
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000, 0).unwrap() + TimeDelta::minutes(minutes)
+    }
+
+    #[test]
+    fn test_login_logout() {
+        let store = SessionStore::default();
+        assert!(matches!(store.authorize("main", "get_sales", at(0)), Err(AccessError::Unauthenticated { .. })));
+        assert_eq!(store.authorize("main", "login_user", at(0)), Ok(()));
+
+        let (token, info) = store.start(principal("cashier", &[Capability::SalesRead]), "main", at(0));
+        assert_eq!(token.len(), 64);
+        assert_eq!(info.expires_at, at(30));
+        assert_eq!(store.authorize("main", "get_sales", at(1)), Ok(()));
+        // Other windows aren't logged in by it
+        assert!(store.authorize("other", "get_sales", at(1)).is_err());
+        assert!(matches!(store.authorize("main", "get_purchases", at(1)), Err(AccessError::Forbidden { .. })));
+        assert!(store.holds("main", &token));
+        assert!(!store.holds("other", &token));
+        assert!(!store.holds("main", "not-the-token"));
+
+        assert_eq!(store.current("main", at(2)).unwrap().session.username, "sara");
+        assert!(store.end("main"));
+        assert!(!store.holds("main", &token));
+        assert!(store.current("main", at(2)).is_none());
+        assert!(store.authorize("main", "get_sales", at(2)).is_err());
+    }
+
+    #[test]
+    fn test_idle_timeout_and_lifetime() {
+        let store = SessionStore::default();
+        store.start(principal("cashier", &[Capability::SalesRead]), "main", at(0));
+        // Activity keeps the session alive...
+        for minute in (20..=700).step_by(20) {
+            assert_eq!(store.authorize("main", "get_sales", at(minute)), Ok(()), "minute {}", minute);
+        }
+        // ...but not past its lifetime
+        let error = store.authorize("main", "get_sales", at(721)).unwrap_err();
+        assert!(matches!(error, AccessError::Unauthenticated { ref message, .. } if message.contains("expired")));
+
+        store.start(principal("cashier", &[Capability::SalesRead]), "main", at(800));
+        // Polling the current user isn't activity
+        assert!(store.current("main", at(829)).is_some());
+        assert!(store.authorize("main", "get_sales", at(831)).is_err());
+        assert!(store.list("main", at(831)).is_empty());
+    }
+
+    #[test]
+    fn test_sensitive_commands_need_a_recent_password() {
+        let store = SessionStore::default();
+        store.start(principal("admin", Capability::ALL), "main", at(0));
+        assert_eq!(store.authorize("main", "delete_sale", at(4)), Ok(()));
+        assert!(matches!(
+            store.authorize("main", "delete_sale", at(6)),
+            Err(AccessError::ReauthenticationRequired { .. })
+        ));
+        assert_eq!(store.authorize("main", "get_sales", at(6)), Ok(()));
+
+        assert!(store.reauthenticated("main", at(10)));
+        assert_eq!(store.authorize("main", "update_company_settings", at(12)), Ok(()));
+    }
+
+    #[test]
+    fn test_forced_password_change() {
+        let store = SessionStore::default();
+        let principal = Session { must_change_password: true, ..principal("cashier", &[Capability::SalesRead]) };
+        store.start(principal, "main", at(0));
+        assert!(matches!(
+            store.authorize("main", "get_sales", at(1)),
+            Err(AccessError::PasswordChangeRequired { .. })
+        ));
+        assert_eq!(store.authorize("main", "change_password", at(1)), Ok(()));
+        assert!(store.current("main", at(1)).unwrap().must_change_password);
+
+        store.password_changed("main", at(2));
+        assert_eq!(store.authorize("main", "get_sales", at(3)), Ok(()));
+        assert!(!store.current("main", at(3)).unwrap().must_change_password);
+    }
+
+    #[test]
+    fn test_required_two_factor_setup() {
+        let store = SessionStore::default();
+        let principal = Session {
+            must_change_password: true,
+            must_enroll_two_factor: true,
+            ..principal("accountant", &[Capability::AccountingRead])
+        };
+        store.start(principal, "main", at(0));
+        // The password comes first, then two-factor setup
+        assert!(matches!(
+            store.authorize("main", "begin_two_factor_enrollment", at(1)),
+            Err(AccessError::PasswordChangeRequired { .. })
+        ));
+        store.password_changed("main", at(1));
+        assert!(matches!(
+            store.authorize("main", "get_accounts", at(2)),
+            Err(AccessError::TwoFactorSetupRequired { .. })
+        ));
+        assert_eq!(store.authorize("main", "confirm_two_factor_enrollment", at(2)), Ok(()));
+
+        store.two_factor_enrolled("main");
+        assert_eq!(store.authorize("main", "get_accounts", at(3)), Ok(()));
+        assert!(!store.current("main", at(3)).unwrap().must_enroll_two_factor);
+    }
+
+    #[test]
+    fn test_admin_lists_and_revokes_sessions() {
+        let store = SessionStore::default();
+        let (_, first) = store.start(principal("admin", Capability::ALL), "main", at(0));
+        let (_, second) = store.start(principal("cashier", &[Capability::SalesRead]), "lan-1", at(1));
+        // Logging in again in the same window replaces its session
+        let (_, replaced) = store.start(principal("cashier", &[Capability::SalesRead]), "lan-1", at(2));
+
+        let sessions = store.list("main", at(3));
+        assert_eq!(sessions.iter().map(|s| s.id.as_str()).collect::<Vec<_>>(), [first.id.as_str(), replaced.id.as_str()]);
+        assert_eq!(sessions.iter().map(|s| s.current).collect::<Vec<_>>(), [true, false]);
+        assert!(!store.revoke(&second.id));
+
+        store.refresh_role("cashier", &[Capability::SalesRead, Capability::SalesWrite].into_iter().collect());
+        assert_eq!(store.authorize("lan-1", "create_sale", at(3)), Ok(()));
+
+        assert!(store.revoke(&replaced.id));
+        assert!(store.authorize("lan-1", "get_sales", at(4)).is_err());
+        assert_eq!(store.list("main", at(4)).len(), 1);
+    }
+
+    #[test]
+    fn test_user_changes_reach_open_sessions() {
+        let store = SessionStore::default();
+        store.start(principal("cashier", &[Capability::SalesRead]), "main", at(0));
+        store.start(principal("cashier", &[Capability::SalesRead]), "lan-1", at(0));
+        store.start(Session { user_id: 8, ..principal("hr", &[Capability::HrRead]) }, "lan-2", at(0));
+
+        store.refresh_user(7, "sara.a", "accountant", &[Capability::AccountingRead].into_iter().collect());
+        let current = store.current("main", at(1)).unwrap();
+        assert_eq!((current.session.username.as_str(), current.session.role.as_str()), ("sara.a", "accountant"));
+        assert_eq!(store.authorize("lan-1", "get_accounts", at(1)), Ok(()));
+        assert!(store.authorize("lan-1", "get_sales", at(1)).is_err());
+
+        assert_eq!(store.end_user(7), 2);
+        assert!(store.current("main", at(2)).is_none());
+        assert!(store.current("lan-1", at(2)).is_none());
+        assert!(store.current("lan-2", at(2)).is_some());
+
+        assert_eq!(store.end_all(), 1);
+        assert!(store.current("lan-2", at(2)).is_none());
+        assert!(store.authorize("lan-2", "get_salaries", at(2)).is_err());
+    }
+}
@@ -1,3 +1,5 @@
+use crate::audit::{current_actor, install_audit_events};
+use crate::sync::{install_change_tracking, ConflictPolicy};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
@@ -5,7 +7,7 @@ use std::sync::Arc;
 use surrealdb::engine::local::{Db, SurrealKv};
 use surrealdb::engine::remote::ws::{Client, Ws};
 use surrealdb::opt::auth::Root;
-use surrealdb::Surreal;
+use surrealdb::{Response, Surreal};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ConnectionMode {
@@ -15,6 +17,8 @@ pub enum ConnectionMode {
     Online,
     #[serde(rename = "both")]
     Both,
+    #[serde(rename = "sqlite")]
+    Sqlite,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,13 +30,14 @@ pub struct DatabaseConfig {
     pub database: Option<String>,
     pub username: Option<String>,
     pub password: Option<String>,
+    #[serde(default)]
+    pub conflict_policy: ConflictPolicy,
 }
 
 #[derive(Clone)]
 pub struct SurrealDatabase {
     pub offline: Option<Arc<Surreal<Db>>>,
     pub online: Option<Arc<Surreal<Client>>>,
-    #[allow(dead_code)]
     pub config: DatabaseConfig,
 }
 
@@ -56,10 +61,19 @@ impl SurrealDatabase {
         // Surreal::new::<SurrealKv> returns Surreal<Db>
         let db_path_str = db_path.to_string_lossy().to_string();
         let db: Surreal<Db> = Surreal::new::<SurrealKv>(db_path_str).await?;
+        self.use_local_namespace(&db).await?;
         self.offline = Some(Arc::new(db));
         Ok(())
     }
 
+    /// Select the configured namespace/database on the local store (queries fail without one)
+    async fn use_local_namespace(&self, db: &Surreal<Db>) -> Result<()> {
+        let namespace = self.config.namespace.as_deref().unwrap_or("shafaf");
+        let database = self.config.database.as_deref().unwrap_or("shafaf");
+        db.use_ns(namespace).use_db(database).await?;
+        Ok(())
+    }
+
     /// Connect in online mode (remote server)
     pub async fn connect_online(
         &mut self,
@@ -94,6 +108,7 @@ impl SurrealDatabase {
         // Surreal::new::<SurrealKv> returns Surreal<Db>
         let db_path_str = db_path.to_string_lossy().to_string();
         let offline_db: Surreal<Db> = Surreal::new::<SurrealKv>(db_path_str).await?;
+        self.use_local_namespace(&offline_db).await?;
         self.offline = Some(Arc::new(offline_db));
 
         // Connect online
@@ -134,138 +149,42 @@ impl SurrealDatabase {
         self.online.is_some()
     }
 
-    /// Sync data from offline to online
-    pub async fn sync_offline_to_online(&self) -> Result<()> {
-        let offline = self.offline.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Offline database not connected"))?;
-        let online = self.online.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Online database not connected"))?;
-
-        // Get all tables from schema
-        let tables = vec![
-            "users", "currencies", "suppliers", "customers", "unit_groups", "units",
-            "products", "purchases", "purchase_items", "purchase_additional_costs",
-            "purchase_payments", "sales", "sale_items", "sale_payments",
-            "sale_additional_costs", "expense_types", "expenses", "employees",
-            "salaries", "deductions", "company_settings", "coa_categories",
-            "accounts", "account_currency_balances", "journal_entries",
-            "journal_entry_lines", "currency_exchange_rates", "account_transactions",
-        ];
-
-        for table in tables {
-            // Get all records from offline
-            let query = format!("SELECT * FROM {}", table);
-            let mut response = offline.query(&query).await?;
-            
-            // Try to get results
-            if let Ok(records) = response.take::<Vec<serde_json::Value>>(0) {
-                for record in records {
-                    if let Some(id) = record.get("id") {
-                        // Create or update record in online
-                        let id_str = id.to_string().trim_matches('"').to_string();
-                        let update_query = format!("UPDATE {}:{} MERGE $data", table, id_str);
-                        let mut update_response = online.query(&update_query).await?;
-                        let _ = update_response.take::<Vec<serde_json::Value>>(0);
-                    }
-                }
-            }
-        }
-
-        Ok(())
-    }
-
-    /// Sync data from online to offline
-    #[allow(dead_code)]
-    pub async fn sync_online_to_offline(&self) -> Result<()> {
-        let offline = self.offline.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Offline database not connected"))?;
-        let online = self.online.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Online database not connected"))?;
-
-        // Get all tables from schema
-        let tables = vec![
-            "users", "currencies", "suppliers", "customers", "unit_groups", "units",
-            "products", "purchases", "purchase_items", "purchase_additional_costs",
-            "purchase_payments", "sales", "sale_items", "sale_payments",
-            "sale_additional_costs", "expense_types", "expenses", "employees",
-            "salaries", "deductions", "company_settings", "coa_categories",
-            "accounts", "account_currency_balances", "journal_entries",
-            "journal_entry_lines", "currency_exchange_rates", "account_transactions",
-        ];
-
-        for table in tables {
-            // Get all records from online
-            let query = format!("SELECT * FROM {}", table);
-            let mut response = online.query(&query).await?;
-            
-            // Try to get results
-            if let Ok(records) = response.take::<Vec<serde_json::Value>>(0) {
-                for record in records {
-                    if let Some(id) = record.get("id") {
-                        // Create or update record in offline
-                        let id_str = id.to_string().trim_matches('"').to_string();
-                        let update_query = format!("UPDATE {}:{} MERGE $data", table, id_str);
-                        let mut update_response = offline.query(&update_query).await?;
-                        let _ = update_response.take::<Vec<serde_json::Value>>(0);
-                    }
-                }
-            }
-        }
-
-        Ok(())
-    }
-
-    /// Execute a query on the active connection
-    pub async fn query<T>(&self, query: &str) -> Result<Vec<T>>
+    /// Execute a query with bound parameters (`$name` placeholders taken from the
+    /// keys of `bindings`) and return the rows of its first statement
+    pub async fn query_with<T, B>(&self, query: &str, bindings: B) -> Result<Vec<T>>
     where
         T: serde::de::DeserializeOwned,
+        B: Serialize + 'static,
     {
-        let conn = self.get_connection()?;
-        match conn {
-            DatabaseConnection::Offline(db) => {
-                let mut response = db.query(query).await?;
-                let result: Vec<T> = response.take(0)
-                    .map_err(|e| anyhow::anyhow!("Failed to deserialize: {}", e))?;
-                Ok(result)
-            }
-            DatabaseConnection::Online(db) => {
-                let mut response = db.query(query).await?;
-                let result: Vec<T> = response.take(0)
-                    .map_err(|e| anyhow::anyhow!("Failed to deserialize: {}", e))?;
-                Ok(result)
-            }
-        }
+        let mut response = self.get_connection()?.query(query, bindings).await?;
+        let result: Vec<T> = response.take(0)
+            .map_err(|e| anyhow::anyhow!("Failed to deserialize: {}", e))?;
+        Ok(result)
     }
 
-    /// Execute a query that returns a single result
+    /// Execute a query with bound parameters that returns a single result
     #[allow(dead_code)]
-    pub async fn query_one<T>(&self, query: &str) -> Result<Option<T>>
+    pub async fn query_one<T, B>(&self, query: &str, bindings: B) -> Result<Option<T>>
     where
         T: serde::de::DeserializeOwned,
+        B: Serialize + 'static,
     {
-        let results = self.query::<T>(query).await?;
+        let results = self.query_with::<T, B>(query, bindings).await?;
         Ok(results.into_iter().next())
     }
 
-    /// Execute a query and return raw JSON values
-    pub async fn query_json(&self, query: &str) -> Result<Vec<serde_json::Value>> {
-        // Use the generic query method with serde_json::Value
-        self.query::<serde_json::Value>(query).await
+    /// Execute a query with bound parameters and return the response, failing if any statement failed
+    pub async fn query_response(&self, query: &str, bindings: serde_json::Value) -> Result<Response> {
+        self.get_connection()?.query(query, bindings).await
     }
 
-    /// Execute a query that doesn't return results (CREATE, UPDATE, DELETE)
-    pub async fn execute(&self, query: &str) -> Result<()> {
-        let conn = self.get_connection()?;
-        match conn {
-            DatabaseConnection::Offline(db) => {
-                db.query(query).await?;
-                Ok(())
-            }
-            DatabaseConnection::Online(db) => {
-                db.query(query).await?;
-                Ok(())
-            }
-        }
+    /// Execute a query that doesn't return results (CREATE, UPDATE, DELETE), with bound parameters
+    pub async fn execute_with<B>(&self, query: &str, bindings: B) -> Result<()>
+    where
+        B: Serialize + 'static,
+    {
+        self.get_connection()?.query(query, bindings).await?;
+        Ok(())
     }
 
     /// Close all connections
@@ -282,6 +201,37 @@ pub enum DatabaseConnection {
     Online(Arc<Surreal<Client>>),
 }
 
+impl DatabaseConnection {
+    /// Execute a query with bound parameters, failing if any statement failed. The author of
+    /// the running command is bound as `$audit_actor`, for the audit events.
+    pub async fn query<B>(&self, query: &str, bindings: B) -> Result<Response>
+    where
+        B: Serialize + 'static,
+    {
+        let actor = ("audit_actor", current_actor());
+        let response = match self {
+            DatabaseConnection::Offline(db) => db.query(query).bind(bindings).bind(actor).await?,
+            DatabaseConnection::Online(db) => db.query(query).bind(bindings).bind(actor).await?,
+        };
+        check(response)
+    }
+}
+
+/// The response, or the error of the statement that failed. A failed transaction reports
+/// that statement rather than the ones it cancelled.
+fn check(mut response: Response) -> Result<Response> {
+    let mut errors: Vec<_> = response.take_errors().into_iter().collect();
+    if errors.is_empty() {
+        return Ok(response);
+    }
+    errors.sort_by_key(|(index, _)| *index);
+    let failed = errors
+        .iter()
+        .position(|(_, e)| !matches!(e, surrealdb::Error::Db(surrealdb::error::Db::QueryNotExecuted)))
+        .unwrap_or(0);
+    Err(errors.swap_remove(failed).1.into())
+}
+
 /// Initialize SurrealDB schema
 pub async fn init_schema(db: &SurrealDatabase) -> Result<()> {
     let schema = include_str!("../data/surreal_schema.surql");
@@ -289,12 +239,104 @@ pub async fn init_schema(db: &SurrealDatabase) -> Result<()> {
     // Execute schema on offline if available
     if let Some(offline) = db.get_offline() {
         let _ = offline.query(schema).await?;
+        let conn = DatabaseConnection::Offline(offline);
+        install_change_tracking(&conn).await?;
+        install_audit_events(&conn).await?;
     }
     
     // Execute schema on online if available
     if let Some(online) = db.get_online() {
         let _ = online.query(schema).await?;
+        let conn = DatabaseConnection::Online(online);
+        install_change_tracking(&conn).await?;
+        install_audit_events(&conn).await?;
     }
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    async fn open() -> (SurrealDatabase, PathBuf) {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "shafaf-surrealdb-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let mut db = SurrealDatabase::new(DatabaseConfig {
+            mode: ConnectionMode::Offline,
+            offline_path: None,
+            online_url: None,
+            namespace: None,
+            database: None,
+            username: None,
+            password: None,
+            conflict_policy: Default::default(),
+        });
+        db.connect_offline(dir.clone()).await.unwrap();
+        db.execute_with("CREATE notes:1 SET name = 'first'; CREATE notes:2 SET name = 'second'", json!({}))
+            .await
+            .unwrap();
+        (db, dir)
+    }
+
+    async fn names(db: &SurrealDatabase) -> Vec<String> {
+        db.query_with("SELECT VALUE name FROM notes ORDER BY name", json!({})).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_bound_values_are_not_parsed_as_surrealql() {
+        let (db, dir) = open().await;
+        for hostile in ["x'; DELETE notes; --", "x\"; REMOVE TABLE notes; --", "x` } ; DELETE notes; {"] {
+            db.execute_with("CREATE notes SET name = $name", json!({ "name": hostile }))
+                .await
+                .unwrap();
+            let found: Vec<String> = db
+                .query_with("SELECT VALUE name FROM notes WHERE name = $name", json!({ "name": hostile }))
+                .await
+                .unwrap();
+            assert_eq!(found, [hostile]);
+        }
+        assert_eq!(names(&db).await.len(), 5);
+
+        // A classic tautology only matches a row literally named that
+        let matched: Vec<String> = db
+            .query_with("SELECT VALUE name FROM notes WHERE name = $name", json!({ "name": "' OR true OR '" }))
+            .await
+            .unwrap();
+        assert!(matched.is_empty());
+        db.execute_with("DELETE notes WHERE name = $name", json!({ "name": "first' OR name != '" }))
+            .await
+            .unwrap();
+        assert_eq!(names(&db).await.len(), 5);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_bindings_map_from_frontend() {
+        let (db, dir) = open().await;
+        let bindings: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_value(json!({ "id": 2, "name": "renamed" })).unwrap();
+        db.execute_with("UPDATE type::thing('notes', $id) SET name = $name", bindings)
+            .await
+            .unwrap();
+        assert_eq!(names(&db).await, ["first", "renamed"]);
+
+        let one: Option<String> = db
+            .query_one("SELECT VALUE name FROM type::thing('notes', $id)", json!({ "id": 1 }))
+            .await
+            .unwrap();
+        assert_eq!(one.as_deref(), Some("first"));
+
+        // Failed statements surface as errors instead of being dropped
+        assert!(db.execute_with("THROW $message", json!({ "message": "boom" })).await.is_err());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,678 @@
+use crate::repository::next_id;
+use crate::surrealdb::{DatabaseConnection, SurrealDatabase};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+
+/// Tables copied between the local store and the server
+pub const SYNC_TABLES: &[&str] = &[
+    "users", "currencies", "suppliers", "customers", "unit_groups", "units",
+    "products", "purchases", "purchase_items", "purchase_additional_costs",
+    "purchase_payments", "sales", "sale_items", "sale_payments",
+    "sale_additional_costs", "expense_types", "expenses", "employees",
+    "salaries", "deductions", "company_settings", "coa_categories",
+    "accounts", "account_currency_balances", "journal_entries",
+    "journal_entry_lines", "currency_exchange_rates", "account_transactions",
+    "roles",
+];
+
+/// `origin` of local change rows written by a pull, so they are not pushed back
+const SERVER_ORIGIN: &str = "server";
+
+/// What to do when a record changed both locally and on the server since the last sync
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConflictPolicy {
+    /// The most recent change wins
+    #[default]
+    #[serde(rename = "last_writer_wins")]
+    LastWriterWins,
+    #[serde(rename = "server_wins")]
+    ServerWins,
+    /// Keep both versions in `sync_conflicts` until someone picks one
+    #[serde(rename = "manual")]
+    Manual,
+}
+
+/// Which version of a conflicting record to keep
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConflictSide {
+    #[serde(rename = "local")]
+    Local,
+    #[serde(rename = "remote")]
+    Remote,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncReport {
+    pub pushed: usize,
+    pub pulled: usize,
+    /// Conflicts settled by the conflict policy
+    pub conflicts_resolved: usize,
+    /// Conflicts added to the manual queue
+    pub conflicts_queued: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableChanges {
+    pub table: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncStatus {
+    pub conflict_policy: ConflictPolicy,
+    pub online: bool,
+    pub last_sync_at: Option<String>,
+    /// Local changes not pushed yet
+    pub pending_push: i64,
+    pub pending_push_by_table: Vec<TableChanges>,
+    /// Server changes not pulled yet; None while offline
+    pub pending_pull: Option<i64>,
+    pub conflicts: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncConflict {
+    pub table: String,
+    pub key: serde_json::Value,
+    pub local_deleted: bool,
+    pub local_changed_at: String,
+    pub local: Option<serde_json::Value>,
+    pub remote_deleted: bool,
+    pub remote_changed_at: String,
+    pub remote: Option<serde_json::Value>,
+    pub detected_at: String,
+}
+
+/// Event that records every write to `table` in `sync_changes`, plus a backfill
+/// for records written before change tracking was installed
+fn track_changes_query(table: &str) -> String {
+    format!(
+        "DEFINE EVENT OVERWRITE sync_track ON TABLE {table} THEN {{
+            UPSERT type::thing('sync_changes', ['{table}', record::id($value.id)]) SET
+                tbl = '{table}',
+                key = record::id($value.id),
+                deleted = $event = 'DELETE',
+                version += 1,
+                seq = {next},
+                changed_at = time::now(),
+                origin = NONE;
+        }};
+        LET $tracked = (SELECT VALUE key FROM sync_changes WHERE tbl = '{table}');
+        FOR $key IN (SELECT VALUE record::id(id) FROM {table}) {{
+            IF $key NOTINSIDE $tracked {{
+                UPSERT type::thing('sync_changes', ['{table}', $key]) SET
+                    tbl = '{table}', key = $key, deleted = false, version = 1, seq = {next}, changed_at = time::now();
+            }};
+        }};",
+        table = table,
+        next = next_id("'sync_changes'"),
+    )
+}
+
+/// Install change tracking on every synced table
+pub async fn install_change_tracking(conn: &DatabaseConnection) -> Result<()> {
+    for table in SYNC_TABLES {
+        conn.query(&track_changes_query(table), json!({})).await?;
+    }
+    Ok(())
+}
+
+/// Change rows (with the changed records) after `since`, skipping rows written by `origin`
+const CHANGES_QUERY: &str = "SELECT tbl, key, deleted, seq, changed_at,
+        IF deleted THEN NONE ELSE type::thing(tbl, key).* END AS data
+    FROM sync_changes WHERE seq > $since AND origin != $origin ORDER BY seq";
+
+/// The current state of one record, shaped like a change row
+const RECORD_QUERY: &str = "LET $data = type::thing($tbl, $key).*;
+    RETURN [{ tbl: $tbl, key: $key, deleted: $data = NONE, seq: 0, changed_at: time::now(), data: $data }];";
+
+/// Write the selected change rows to the target store and mark them as written by `$origin`
+const APPLY_QUERY: &str = "BEGIN TRANSACTION;
+    FOR $row IN $rows {
+        IF [$row.tbl, $row.key] INSIDE $keys {
+            LET $record = type::thing($row.tbl, $row.key);
+            IF $row.deleted { DELETE $record; } ELSE { UPSERT $record CONTENT $row.data; };
+            UPDATE type::thing('sync_changes', [$row.tbl, $row.key]) SET changed_at = $row.changed_at, origin = $origin;
+            -- Keep the id sequence ahead of synced ids so new local records don't reuse them
+            IF type::is::int($row.key) {
+                UPSERT type::thing('sequences', $row.tbl) SET value = math::max([value ?? 0, $row.key]);
+            };
+        };
+    };
+    COMMIT TRANSACTION;";
+
+/// Park the selected server rows in the manual conflict queue next to the local version
+const QUEUE_QUERY: &str = "FOR $row IN $rows {
+        IF [$row.tbl, $row.key] INSIDE $keys {
+            LET $mine = type::thing('sync_changes', [$row.tbl, $row.key]).*;
+            UPSERT type::thing('sync_conflicts', [$row.tbl, $row.key]) SET
+                tbl = $row.tbl,
+                key = $row.key,
+                local_deleted = $mine.deleted ?? false,
+                local_changed_at = $mine.changed_at ?? time::now(),
+                local = type::thing($row.tbl, $row.key).*,
+                remote_deleted = $row.deleted,
+                remote_changed_at = $row.changed_at,
+                remote = $row.data,
+                detected_at = time::now();
+        };
+    };";
+
+/// Record snapshot with its record links written out as `table:id` strings, so it converts to JSON
+fn snapshot_field(field: &str) -> String {
+    format!(
+        "IF {field} THEN object::from_entries(array::map(object::entries({field}), |$entry| [$entry[0], IF type::is::record($entry[1]) THEN <string> $entry[1] ELSE $entry[1] END])) END AS {field}",
+        field = field
+    )
+}
+
+fn conflict_fields() -> String {
+    format!(
+        "tbl AS table, key, local_deleted, time::format(local_changed_at, '%Y-%m-%d %H:%M:%S') AS local_changed_at, {}, remote_deleted, time::format(remote_changed_at, '%Y-%m-%d %H:%M:%S') AS remote_changed_at, {}, time::format(detected_at, '%Y-%m-%d %H:%M:%S') AS detected_at",
+        snapshot_field("local"),
+        snapshot_field("remote")
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct Change {
+    tbl: String,
+    key: serde_json::Value,
+    seq: i64,
+    changed_at: DateTime<Utc>,
+}
+
+impl Change {
+    fn ident(&self) -> (String, String) {
+        (self.tbl.clone(), self.key.to_string())
+    }
+
+    /// `[table, key]`, as matched by `$keys` in the apply and queue queries
+    fn binding(&self) -> serde_json::Value {
+        json!([self.tbl, self.key])
+    }
+}
+
+/// Change rows read from one store. `rows` keeps the SurrealDB values intact
+/// (record links, datetimes) so they can be written to the other store as-is.
+struct Batch {
+    rows: surrealdb::Value,
+    changes: Vec<Change>,
+}
+
+impl Batch {
+    async fn read(conn: &DatabaseConnection, query: &str, bindings: serde_json::Value, index: usize) -> Result<Batch> {
+        let mut response = conn.query(query, bindings).await?;
+        let rows: surrealdb::Value = response.take(index)?;
+        let changes = surrealdb::value::from_value(rows.clone())
+            .map_err(|e| anyhow!("Failed to read sync changes: {}", e))?;
+        Ok(Batch { rows, changes })
+    }
+
+    /// Changes after `since` that were not written by `origin`
+    async fn since(conn: &DatabaseConnection, since: i64, origin: &str) -> Result<Batch> {
+        Batch::read(conn, CHANGES_QUERY, json!({ "since": since, "origin": origin }), 0).await
+    }
+
+    /// The current version of a single record
+    async fn record(conn: &DatabaseConnection, table: &str, key: &serde_json::Value) -> Result<Batch> {
+        // Statement 0 is the LET
+        Batch::read(conn, RECORD_QUERY, json!({ "tbl": table, "key": key }), 1).await
+    }
+
+    fn max_seq(&self) -> Option<i64> {
+        self.changes.iter().map(|c| c.seq).max()
+    }
+
+    /// Write the changes in `keys` to `target`, marking them with `origin`
+    async fn apply(&self, target: &DatabaseConnection, keys: Vec<serde_json::Value>, origin: &str) -> Result<()> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+        target.query(APPLY_QUERY, self.bindings(keys, Some(origin))).await?;
+        Ok(())
+    }
+
+    fn bindings(&self, keys: Vec<serde_json::Value>, origin: Option<&str>) -> RowBindings {
+        RowBindings {
+            rows: self.rows.clone(),
+            keys,
+            origin: origin.map(str::to_string),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct RowBindings {
+    rows: surrealdb::Value,
+    keys: Vec<serde_json::Value>,
+    origin: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SyncState {
+    client_id: String,
+    pushed_seq: i64,
+    pulled_seq: i64,
+}
+
+/// Incremental two-way sync between the local store and the server.
+///
+/// Every write to a synced table bumps that record's row in `sync_changes` with
+/// a store-wide sequence number, so each side only has to send the rows after
+/// the last watermark. Deletes leave the row behind as a tombstone.
+pub struct SyncEngine {
+    local: DatabaseConnection,
+    remote: Option<DatabaseConnection>,
+    /// Identifies the server; watermarks are kept per server
+    peer: String,
+    policy: ConflictPolicy,
+}
+
+impl SyncEngine {
+    pub fn new(local: DatabaseConnection, remote: Option<DatabaseConnection>, peer: String, policy: ConflictPolicy) -> Self {
+        SyncEngine { local, remote, peer, policy }
+    }
+
+    /// Engine for an open SurrealDB database; needs the offline store
+    pub fn for_database(db: &SurrealDatabase) -> Result<Self> {
+        let local = db
+            .get_offline()
+            .map(DatabaseConnection::Offline)
+            .ok_or_else(|| anyhow!("Offline database not connected"))?;
+        let remote = db.get_online().map(DatabaseConnection::Online);
+        let config = &db.config;
+        let peer = format!(
+            "{}/{}/{}",
+            config.online_url.as_deref().unwrap_or(""),
+            config.namespace.as_deref().unwrap_or(""),
+            config.database.as_deref().unwrap_or("")
+        );
+        Ok(SyncEngine::new(local, remote, peer, config.conflict_policy))
+    }
+
+    fn remote(&self) -> Result<&DatabaseConnection> {
+        self.remote.as_ref().ok_or_else(|| anyhow!("Online database not connected"))
+    }
+
+    /// Watermarks for this server, creating them (and the client id) on first use
+    async fn state(&self) -> Result<SyncState> {
+        let mut response = self
+            .local
+            .query(
+                "UPSERT type::thing('sync_state', $peer) SET client_id = client_id ?? <string> rand::uuid::v4() RETURN client_id, pushed_seq, pulled_seq",
+                json!({ "peer": self.peer }),
+            )
+            .await?;
+        let state: Option<SyncState> = response.take(0)?;
+        state.ok_or_else(|| anyhow!("Failed to read sync state"))
+    }
+
+    /// Keys in the manual conflict queue
+    async fn queued(&self) -> Result<HashSet<(String, String)>> {
+        let mut response = self.local.query("SELECT tbl, key FROM sync_conflicts", json!({})).await?;
+        let rows: Vec<serde_json::Value> = response.take(0)?;
+        Ok(rows
+            .iter()
+            .map(|row| (row["tbl"].as_str().unwrap_or_default().to_string(), row["key"].to_string()))
+            .collect())
+    }
+
+    /// Push local changes and pull server changes made since the last sync
+    pub async fn sync(&self) -> Result<SyncReport> {
+        let remote = self.remote()?;
+        let state = self.state().await?;
+        let local_batch = Batch::since(&self.local, state.pushed_seq, SERVER_ORIGIN).await?;
+        let remote_batch = Batch::since(remote, state.pulled_seq, &state.client_id).await?;
+        let queued = self.queued().await?;
+
+        let remote_changes: HashMap<_, _> = remote_batch.changes.iter().map(|c| (c.ident(), c)).collect();
+        let local_idents: HashSet<_> = local_batch.changes.iter().map(Change::ident).collect();
+
+        let mut report = SyncReport::default();
+        let mut push = Vec::new();
+        let mut pull = Vec::new();
+        let mut queue = Vec::new();
+
+        for change in &local_batch.changes {
+            let ident = change.ident();
+            if queued.contains(&ident) {
+                // Settled by resolve_conflict, which sends whatever is current then
+                continue;
+            }
+            let Some(theirs) = remote_changes.get(&ident) else {
+                push.push(change.binding());
+                continue;
+            };
+            match self.policy {
+                ConflictPolicy::LastWriterWins if change.changed_at > theirs.changed_at => {
+                    push.push(change.binding());
+                    report.conflicts_resolved += 1;
+                }
+                ConflictPolicy::LastWriterWins | ConflictPolicy::ServerWins => {
+                    pull.push(theirs.binding());
+                    report.conflicts_resolved += 1;
+                }
+                ConflictPolicy::Manual => queue.push(theirs.binding()),
+            }
+        }
+        for change in &remote_batch.changes {
+            let ident = change.ident();
+            if queued.contains(&ident) {
+                // Refresh the server side of the queued conflict
+                queue.push(change.binding());
+            } else if !local_idents.contains(&ident) {
+                pull.push(change.binding());
+            }
+        }
+
+        report.pushed = push.len();
+        report.pulled = pull.len();
+        report.conflicts_queued = queue.len();
+
+        local_batch.apply(remote, push, &state.client_id).await?;
+        remote_batch.apply(&self.local, pull, SERVER_ORIGIN).await?;
+        if !queue.is_empty() {
+            self.local.query(QUEUE_QUERY, remote_batch.bindings(queue, None)).await?;
+        }
+
+        self.local
+            .query(
+                "UPDATE type::thing('sync_state', $peer) SET pushed_seq = $pushed, pulled_seq = $pulled, last_sync_at = time::now()",
+                json!({
+                    "peer": self.peer,
+                    "pushed": local_batch.max_seq().unwrap_or(state.pushed_seq),
+                    "pulled": remote_batch.max_seq().unwrap_or(state.pulled_seq),
+                }),
+            )
+            .await?;
+
+        Ok(report)
+    }
+
+    /// Pending changes on both sides and the size of the conflict queue
+    pub async fn status(&self) -> Result<SyncStatus> {
+        let mut response = self
+            .local
+            .query(
+                "LET $state = type::thing('sync_state', $peer).*;
+                RETURN IF $state.last_sync_at THEN time::format($state.last_sync_at, '%Y-%m-%d %H:%M:%S') END;
+                SELECT tbl AS table, count() AS count FROM sync_changes
+                    WHERE seq > ($state.pushed_seq ?? 0) AND origin != $origin GROUP BY tbl ORDER BY table;
+                RETURN count((SELECT id FROM sync_conflicts));
+                RETURN $state.client_id;
+                RETURN $state.pulled_seq ?? 0;",
+                json!({ "peer": self.peer, "origin": SERVER_ORIGIN }),
+            )
+            .await?;
+        // Statement 0 is the LET
+        let last_sync_at: Option<String> = response.take(1)?;
+        let by_table: Vec<TableChanges> = response.take(2)?;
+        let conflicts: Option<i64> = response.take(3)?;
+        let client_id: Option<String> = response.take(4)?;
+        let pulled_seq: Option<i64> = response.take(5)?;
+
+        let pending_pull = match &self.remote {
+            Some(remote) => {
+                let mut response = remote
+                    .query(
+                        "RETURN count((SELECT id FROM sync_changes WHERE seq > $since AND origin != $origin))",
+                        json!({ "since": pulled_seq.unwrap_or(0), "origin": client_id }),
+                    )
+                    .await?;
+                let count: Option<i64> = response.take(0)?;
+                Some(count.unwrap_or(0))
+            }
+            None => None,
+        };
+
+        Ok(SyncStatus {
+            conflict_policy: self.policy,
+            online: self.remote.is_some(),
+            last_sync_at,
+            pending_push: by_table.iter().map(|t| t.count).sum(),
+            pending_push_by_table: by_table,
+            pending_pull,
+            conflicts: conflicts.unwrap_or(0),
+        })
+    }
+
+    /// Conflicts waiting in the manual queue, oldest first
+    pub async fn conflicts(&self) -> Result<Vec<SyncConflict>> {
+        let sql = format!("SELECT {} FROM sync_conflicts ORDER BY detected_at", conflict_fields());
+        let mut response = self.local.query(&sql, json!({})).await?;
+        let rows: Vec<serde_json::Value> = response.take(0)?;
+        rows.into_iter()
+            .map(|row| serde_json::from_value(row).map_err(|e| anyhow!("Failed to read sync conflict: {}", e)))
+            .collect()
+    }
+
+    /// Settle a queued conflict by copying the current version from the kept side to the other
+    pub async fn resolve_conflict(&self, table: &str, key: &serde_json::Value, keep: ConflictSide) -> Result<()> {
+        let remote = self.remote()?;
+        let state = self.state().await?;
+        let keys = vec![json!([table, key])];
+        match keep {
+            ConflictSide::Local => {
+                Batch::record(&self.local, table, key).await?.apply(remote, keys, &state.client_id).await?
+            }
+            ConflictSide::Remote => {
+                Batch::record(remote, table, key).await?.apply(&self.local, keys, SERVER_ORIGIN).await?
+            }
+        }
+        self.local
+            .query("DELETE type::thing('sync_conflicts', [$tbl, $key])", json!({ "tbl": table, "key": key }))
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::surrealdb::{init_schema, ConnectionMode, DatabaseConfig};
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A SurrealKV store in a temporary directory, removed on drop
+    struct Store {
+        db: SurrealDatabase,
+        dir: PathBuf,
+    }
+
+    impl Drop for Store {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    impl Store {
+        async fn open() -> Self {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let dir = std::env::temp_dir().join(format!(
+                "shafaf-sync-test-{}-{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::SeqCst)
+            ));
+            let mut db = SurrealDatabase::new(DatabaseConfig {
+                mode: ConnectionMode::Offline,
+                offline_path: None,
+                online_url: None,
+                namespace: None,
+                database: None,
+                username: None,
+                password: None,
+                conflict_policy: ConflictPolicy::default(),
+            });
+            db.connect_offline(dir.clone()).await.unwrap();
+            init_schema(&db).await.unwrap();
+            Store { db, dir }
+        }
+
+        fn conn(&self) -> DatabaseConnection {
+            self.db.get_connection().unwrap()
+        }
+
+        async fn run(&self, query: &str) {
+            self.db.query_response(query, json!({})).await.unwrap();
+        }
+
+        /// Values of `field` for every record of `table`, ordered by id
+        async fn values(&self, table: &str, field: &str) -> Vec<serde_json::Value> {
+            let sql = format!("RETURN (SELECT id, {} AS value FROM {} ORDER BY id).value", field, table);
+            let mut response = self.db.query_response(&sql, json!({})).await.unwrap();
+            response.take(0).unwrap()
+        }
+    }
+
+    /// A client store syncing against a second local store standing in for the server
+    async fn pair(policy: ConflictPolicy) -> (Store, Store, SyncEngine) {
+        let client = Store::open().await;
+        let server = Store::open().await;
+        let engine = SyncEngine::new(client.conn(), Some(server.conn()), "test".to_string(), policy);
+        (client, server, engine)
+    }
+
+    #[tokio::test]
+    async fn test_push_is_incremental_and_keeps_types() {
+        let (client, server, engine) = pair(ConflictPolicy::default()).await;
+        client.run("CREATE currencies:1 SET name = 'AFN', base = 1, rate = 1").await;
+        client.run("CREATE products:1 SET name = 'Rice', price = 12.5, currency_id = currencies:1").await;
+
+        let status = engine.status().await.unwrap();
+        assert_eq!((status.pending_push, status.pending_pull), (2, Some(0)));
+
+        let report = engine.sync().await.unwrap();
+        assert_eq!((report.pushed, report.pulled), (2, 0));
+        assert_eq!(server.values("products", "record::id(currency_id)").await, [json!(1)]);
+        assert_eq!(server.values("products", "type::is::datetime(created_at)").await, [json!(true)]);
+
+        // Nothing new, nothing sent - and the server's copies are not echoed back
+        let again = engine.sync().await.unwrap();
+        assert_eq!((again.pushed, again.pulled), (0, 0));
+
+        client.run("UPDATE products:1 SET price = 14").await;
+        let report = engine.sync().await.unwrap();
+        assert_eq!((report.pushed, report.pulled), (1, 0));
+        assert_eq!(server.values("products", "price").await, [json!(14.0)]);
+        assert_eq!(engine.status().await.unwrap().pending_push, 0);
+    }
+
+    #[tokio::test]
+    async fn test_pull_applies_server_changes_and_tombstones() {
+        let (client, server, engine) = pair(ConflictPolicy::default()).await;
+        server.run("CREATE customers:1 SET full_name = 'Ahmad', phone = '0700', address = 'Kabul'").await;
+        server.run("CREATE customers:2 SET full_name = 'Bashir', phone = '0711', address = 'Herat'").await;
+
+        let report = engine.sync().await.unwrap();
+        assert_eq!((report.pushed, report.pulled), (0, 2));
+        assert_eq!(client.values("customers", "full_name").await, [json!("Ahmad"), json!("Bashir")]);
+
+        server.run("DELETE customers:1").await;
+        assert_eq!(engine.status().await.unwrap().pending_pull, Some(1));
+        engine.sync().await.unwrap();
+        assert_eq!(client.values("customers", "full_name").await, [json!("Bashir")]);
+
+        // New local records continue after the pulled ids
+        client.run("CREATE type::thing('customers', (UPSERT sequences:customers SET value += 1 RETURN VALUE value)[0]) SET full_name = 'Karim', phone = '0722', address = 'Balkh'").await;
+        assert_eq!(client.values("customers", "record::id(id)").await, [json!(2), json!(3)]);
+    }
+
+    #[tokio::test]
+    async fn test_local_delete_is_pushed() {
+        let (client, server, engine) = pair(ConflictPolicy::default()).await;
+        client.run("CREATE customers:1 SET full_name = 'Ahmad', phone = '0700', address = 'Kabul'").await;
+        engine.sync().await.unwrap();
+        assert_eq!(server.values("customers", "full_name").await.len(), 1);
+
+        client.run("DELETE customers:1").await;
+        assert_eq!(engine.sync().await.unwrap().pushed, 1);
+        assert!(server.values("customers", "full_name").await.is_empty());
+    }
+
+    /// Edit customer 1 on both sides after a sync: the client sets phone 0701, the server 0702
+    async fn conflicting_edit(client: &Store, server: &Store, engine: &SyncEngine, client_last: bool) {
+        client.run("CREATE customers:1 SET full_name = 'Ahmad', phone = '0700', address = 'Kabul'").await;
+        engine.sync().await.unwrap();
+        let client_edit = "UPDATE customers:1 SET phone = '0701'";
+        let server_edit = "UPDATE customers:1 SET phone = '0702'";
+        if client_last {
+            server.run(server_edit).await;
+            client.run(client_edit).await;
+        } else {
+            client.run(client_edit).await;
+            server.run(server_edit).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_last_writer_wins() {
+        let (client, server, engine) = pair(ConflictPolicy::LastWriterWins).await;
+        conflicting_edit(&client, &server, &engine, true).await;
+
+        // The client edited last, so its phone number wins on both sides
+        let report = engine.sync().await.unwrap();
+        assert_eq!((report.pushed, report.pulled, report.conflicts_resolved), (1, 0, 1));
+        assert_eq!(client.values("customers", "phone").await, [json!("0701")]);
+        assert_eq!(server.values("customers", "phone").await, [json!("0701")]);
+    }
+
+    #[tokio::test]
+    async fn test_server_wins() {
+        let (client, server, engine) = pair(ConflictPolicy::ServerWins).await;
+        conflicting_edit(&client, &server, &engine, true).await;
+
+        let report = engine.sync().await.unwrap();
+        assert_eq!((report.pushed, report.pulled, report.conflicts_resolved), (0, 1, 1));
+        assert_eq!(client.values("customers", "phone").await, [json!("0702")]);
+        assert_eq!(server.values("customers", "phone").await, [json!("0702")]);
+        assert_eq!(engine.status().await.unwrap().pending_push, 0);
+    }
+
+    #[tokio::test]
+    async fn test_manual_conflicts_are_queued_until_resolved() {
+        let (client, server, engine) = pair(ConflictPolicy::Manual).await;
+        conflicting_edit(&client, &server, &engine, false).await;
+
+        let report = engine.sync().await.unwrap();
+        assert_eq!((report.pushed, report.pulled, report.conflicts_queued), (0, 0, 1));
+        assert_eq!(client.values("customers", "phone").await, [json!("0701")]);
+        assert_eq!(server.values("customers", "phone").await, [json!("0702")]);
+
+        let conflicts = engine.conflicts().await.unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!((conflicts[0].table.as_str(), &conflicts[0].key), ("customers", &json!(1)));
+        assert_eq!(conflicts[0].local.as_ref().unwrap()["phone"], "0701");
+        assert_eq!(conflicts[0].remote.as_ref().unwrap()["phone"], "0702");
+        assert_eq!(conflicts[0].remote.as_ref().unwrap()["id"], "customers:1");
+        assert_eq!(engine.status().await.unwrap().conflicts, 1);
+
+        // Queued records are left alone by later syncs
+        client.run("UPDATE customers:1 SET notes = 'vip'").await;
+        assert_eq!(engine.sync().await.unwrap().pushed, 0);
+        assert_eq!(server.values("customers", "notes").await, [serde_json::Value::Null]);
+
+        engine.resolve_conflict("customers", &json!(1), ConflictSide::Local).await.unwrap();
+        assert_eq!(server.values("customers", "phone").await, [json!("0701")]);
+        assert_eq!(server.values("customers", "notes").await, [json!("vip")]);
+        assert!(engine.conflicts().await.unwrap().is_empty());
+        let report = engine.sync().await.unwrap();
+        assert_eq!((report.pushed, report.pulled), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_status_without_server() {
+        let client = Store::open().await;
+        client.run("CREATE customers:1 SET full_name = 'Ahmad', phone = '0700', address = 'Kabul'").await;
+        let engine = SyncEngine::new(client.conn(), None, "test".to_string(), ConflictPolicy::default());
+
+        let status = engine.status().await.unwrap();
+        assert!(!status.online);
+        assert_eq!((status.pending_push, status.pending_pull, status.last_sync_at), (1, None, None));
+        assert_eq!(status.pending_push_by_table[0].table, "customers");
+        assert!(engine.sync().await.is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, TimeDelta, Utc};
+use rcgen::{
+    date_time_ymd, CertificateParams, DistinguishedName, DnType, ExtendedKeyUsagePurpose, KeyPair, PKCS_ECDSA_P256_SHA256,
+};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::Arc;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use tokio_rustls::rustls::{self, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+/// How long a generated certificate is valid, in days
+const CERT_LIFETIME_DAYS: i64 = 3650;
+const CERT_FILE: &str = "server-cert.der";
+const KEY_FILE: &str = "server-key.der";
+
+/// A self-signed certificate (DER) and its PKCS#8 private key
+#[derive(Debug, Clone)]
+pub struct Identity {
+    pub cert: Vec<u8>,
+    pub key: Vec<u8>,
+}
+
+impl Identity {
+    /// SHA-256 fingerprint of the certificate, as browsers show it
+    pub fn fingerprint(&self) -> String {
+        Sha256::digest(&self.cert)
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(":")
+    }
+
+    /// TLS acceptor serving this certificate
+    pub fn acceptor(&self) -> Result<TlsAcceptor> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![CertificateDer::from(self.cert.clone())],
+                PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(self.key.clone())),
+            )?;
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+/// The certificate stored in `dir`, created on first use for `hosts` (names or IP addresses)
+pub fn load_or_create(dir: &Path, hosts: &[String]) -> Result<Identity> {
+    let (cert_path, key_path) = (dir.join(CERT_FILE), dir.join(KEY_FILE));
+    if cert_path.exists() && key_path.exists() {
+        return Ok(Identity { cert: std::fs::read(&cert_path)?, key: std::fs::read(&key_path)? });
+    }
+
+    let identity = self_signed(hosts, Utc::now())?;
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(&cert_path, &identity.cert)?;
+    std::fs::write(&key_path, &identity.key)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&key_path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(identity)
+}
+
+/// A new ECDSA P-256 certificate for `hosts`, valid from the start of `now`'s day for `CERT_LIFETIME_DAYS`
+pub fn self_signed(hosts: &[String], now: DateTime<Utc>) -> Result<Identity> {
+    if hosts.is_empty() {
+        return Err(anyhow!("A certificate needs at least one host name"));
+    }
+    // IPv6 hosts may come bracketed, as in URLs
+    let names: Vec<String> = hosts.iter().map(|host| host.trim_matches(|c| c == '[' || c == ']').to_string()).collect();
+    let key_pair = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256)?;
+
+    let mut params = CertificateParams::new(names)?;
+    params.distinguished_name = DistinguishedName::new();
+    params.distinguished_name.push(DnType::CommonName, "Shafaf");
+    let day = |at: DateTime<Utc>| date_time_ymd(at.year(), at.month() as u8, at.day() as u8);
+    params.not_before = day(now);
+    params.not_after = day(now + TimeDelta::days(CERT_LIFETIME_DAYS));
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+    let cert = params.self_signed(&key_pair)?;
+
+    Ok(Identity { cert: cert.der().to_vec(), key: key_pair.serialize_der() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::rustls::pki_types::ServerName;
+    use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+    use tokio_rustls::TlsConnector;
+
+    async fn handshake(identity: &Identity, host: &str) -> Result<()> {
+        let mut roots = RootCertStore::empty();
+        roots.add(CertificateDer::from(identity.cert.clone()))?;
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let client = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connector = TlsConnector::from(Arc::new(client));
+        let acceptor = identity.acceptor()?;
+
+        let (client_io, server_io) = tokio::io::duplex(16 * 1024);
+        let server = tokio::spawn(async move {
+            let mut stream = acceptor.accept(server_io).await?;
+            stream.write_all(b"ok").await?;
+            stream.shutdown().await?;
+            Ok::<_, std::io::Error>(())
+        });
+        let mut stream = connector.connect(ServerName::try_from(host.to_string())?, client_io).await?;
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await?;
+        server.await??;
+        assert_eq!(reply, b"ok");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_self_signed_certificate_handshakes() {
+        let hosts = vec!["localhost".to_string(), "127.0.0.1".to_string(), "::1".to_string()];
+        let identity = self_signed(&hosts, Utc::now()).unwrap();
+        // Browsers and webviews don't accept Ed25519 server certificates
+        assert_eq!(KeyPair::try_from(identity.key.as_slice()).unwrap().algorithm(), &PKCS_ECDSA_P256_SHA256);
+        handshake(&identity, "localhost").await.unwrap();
+        handshake(&identity, "127.0.0.1").await.unwrap();
+        handshake(&identity, "::1").await.unwrap();
+        // Names the certificate wasn't made for are refused
+        assert!(handshake(&identity, "example.com").await.is_err());
+        assert!(self_signed(&[], Utc::now()).is_err());
+    }
+
+    #[test]
+    fn test_load_or_create_reuses_certificate() {
+        let dir = std::env::temp_dir().join(format!("shafaf-tls-{}", crate::session::random_hex(8)));
+        let hosts = vec!["localhost".to_string()];
+        let first = load_or_create(&dir, &hosts).unwrap();
+        let second = load_or_create(&dir, &hosts).unwrap();
+        assert_eq!(first.fingerprint(), second.fingerprint());
+        assert_eq!(first.fingerprint().len(), 32 * 3 - 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,582 @@
+use crate::db::Database;
+use crate::surrealdb::SurrealDatabase;
+use crate::sync::SYNC_TABLES;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use rusqlite::types::Value as SqlValue;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use surrealdb::{Datetime, RecordId};
+
+/// Rows copied per transaction; progress is saved after every batch
+const BATCH_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableTransfer {
+    pub table: String,
+    /// Rows in the source table
+    pub source_rows: i64,
+    /// Rows copied by this run
+    pub transferred: i64,
+    /// Rows copied by this run and by earlier, interrupted runs
+    pub total_transferred: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferReport {
+    pub tables: Vec<TableTransfer>,
+    pub transferred: i64,
+}
+
+impl TransferReport {
+    fn new(tables: Vec<TableTransfer>) -> Self {
+        let transferred = tables.iter().map(|t| t.transferred).sum();
+        TransferReport { tables, transferred }
+    }
+}
+
+/// SurrealDB field type, reduced to what matters when converting SQLite values
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    /// `record<table>`: SQLite stores the integer id
+    Link(String),
+    Datetime,
+    Float,
+    Int,
+    String,
+    /// `array<..>` or `object`: SQLite stores JSON text
+    Json,
+    Other,
+}
+
+impl Kind {
+    fn parse(kind: &str) -> Kind {
+        let kind = kind
+            .strip_prefix("option<")
+            .and_then(|inner| inner.strip_suffix('>'))
+            .unwrap_or(kind);
+        if let Some(table) = kind.strip_prefix("record<").and_then(|inner| inner.strip_suffix('>')) {
+            return Kind::Link(table.to_string());
+        }
+        match kind {
+            "datetime" => Kind::Datetime,
+            "float" | "number" => Kind::Float,
+            "int" => Kind::Int,
+            "string" => Kind::String,
+            "object" => Kind::Json,
+            _ if kind.starts_with("array") => Kind::Json,
+            _ => Kind::Other,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct FieldInfo {
+    name: String,
+    kind: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TableInfo {
+    fields: Vec<FieldInfo>,
+}
+
+/// Field types of a SurrealDB table, keyed by field name (the `id` field is left out)
+async fn field_kinds(surreal: &SurrealDatabase, table: &str) -> Result<HashMap<String, Kind>> {
+    // INFO takes a table name rather than an expression; `table` always comes from SYNC_TABLES
+    let mut response = surreal
+        .query_response(&format!("INFO FOR TABLE {} STRUCTURE", table), json!({}))
+        .await?;
+    let info: surrealdb::Value = response.take(0)?;
+    let info: TableInfo = surrealdb::value::from_value(info)?;
+    Ok(info
+        .fields
+        .into_iter()
+        .filter(|field| field.name != "id")
+        .map(|field| {
+            let kind = field.kind.as_deref().map(Kind::parse).unwrap_or(Kind::Other);
+            (field.name, kind)
+        })
+        .collect())
+}
+
+/// Columns of a SQLite table, empty if the table doesn't exist
+fn sqlite_columns(sqlite: &Database, table: &str) -> Result<Vec<String>> {
+    sqlite.query(&format!("PRAGMA table_info(\"{}\")", table), &[], |row| row.get::<_, String>(1))
+}
+
+fn parse_timestamp(text: &str) -> Option<DateTime<Utc>> {
+    if let Ok(parsed) = DateTime::parse_from_rfc3339(text) {
+        return Some(parsed.with_timezone(&Utc));
+    }
+    ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0))
+        .map(|naive| naive.and_utc())
+}
+
+/// Convert a SQLite value to the SurrealDB type of its field; `None` for NULL
+fn to_surreal(kind: &Kind, value: SqlValue) -> Result<Option<surrealdb::Value>> {
+    let invalid = |value: &SqlValue| anyhow!("cannot convert {:?} to {:?}", value, kind);
+    let converted = match (kind, value) {
+        (_, SqlValue::Null) => return Ok(None),
+        (Kind::Link(table), SqlValue::Integer(id)) => surrealdb::value::to_value(RecordId::from_table_key(table.as_str(), id))?,
+        (Kind::Link(table), SqlValue::Text(text)) => {
+            let id: i64 = text.trim().parse().map_err(|_| invalid(&SqlValue::Text(text.clone())))?;
+            surrealdb::value::to_value(RecordId::from_table_key(table.as_str(), id))?
+        }
+        (Kind::Datetime, SqlValue::Text(text)) => {
+            let parsed = parse_timestamp(text.trim()).ok_or_else(|| invalid(&SqlValue::Text(text.clone())))?;
+            surrealdb::value::to_value(Datetime::from(parsed))?
+        }
+        (Kind::Datetime, SqlValue::Integer(seconds)) => {
+            let parsed = DateTime::from_timestamp(seconds, 0).ok_or_else(|| invalid(&SqlValue::Integer(seconds)))?;
+            surrealdb::value::to_value(Datetime::from(parsed))?
+        }
+        (Kind::Float, SqlValue::Integer(number)) => surrealdb::value::to_value(number as f64)?,
+        (Kind::Float, SqlValue::Real(number)) => surrealdb::value::to_value(number)?,
+        (Kind::Float, SqlValue::Text(text)) => {
+            let number: f64 = text.trim().parse().map_err(|_| invalid(&SqlValue::Text(text.clone())))?;
+            surrealdb::value::to_value(number)?
+        }
+        (Kind::Int, SqlValue::Integer(number)) => surrealdb::value::to_value(number)?,
+        (Kind::Int, SqlValue::Real(number)) if number.fract() == 0.0 => surrealdb::value::to_value(number as i64)?,
+        (Kind::Int, SqlValue::Text(text)) => {
+            let number: i64 = text.trim().parse().map_err(|_| invalid(&SqlValue::Text(text.clone())))?;
+            surrealdb::value::to_value(number)?
+        }
+        (Kind::String, SqlValue::Text(text)) => surrealdb::value::to_value(text)?,
+        (Kind::String, SqlValue::Integer(number)) => surrealdb::value::to_value(number.to_string())?,
+        (Kind::String, SqlValue::Real(number)) => surrealdb::value::to_value(number.to_string())?,
+        (Kind::Json, SqlValue::Text(text)) => {
+            let parsed: serde_json::Value =
+                serde_json::from_str(&text).map_err(|_| invalid(&SqlValue::Text(text.clone())))?;
+            surrealdb::value::to_value(parsed)?
+        }
+        (Kind::Other, SqlValue::Integer(number)) => surrealdb::value::to_value(number)?,
+        (Kind::Other, SqlValue::Real(number)) => surrealdb::value::to_value(number)?,
+        (Kind::Other, SqlValue::Text(text)) => surrealdb::value::to_value(text)?,
+        (_, value) => return Err(invalid(&value)),
+    };
+    Ok(Some(converted))
+}
+
+/// Convert an exported SurrealDB value (links and datetimes already flattened by EXPORT_QUERY) to a SQLite value
+fn to_sqlite(value: serde_json::Value) -> SqlValue {
+    match value {
+        serde_json::Value::Null => SqlValue::Null,
+        serde_json::Value::Bool(flag) => SqlValue::Integer(flag as i64),
+        serde_json::Value::Number(number) => match number.as_i64() {
+            Some(integer) => SqlValue::Integer(integer),
+            None => SqlValue::Real(number.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(text) => SqlValue::Text(text),
+        other => SqlValue::Text(other.to_string()),
+    }
+}
+
+#[derive(Serialize)]
+struct ImportRow {
+    id: i64,
+    data: BTreeMap<String, surrealdb::Value>,
+}
+
+#[derive(Serialize)]
+struct ImportBatch {
+    table: String,
+    rows: Vec<ImportRow>,
+    last_id: i64,
+    count: i64,
+}
+
+/// Write one batch, move the id sequence past it and save the checkpoint, all or nothing
+const IMPORT_QUERY: &str = "BEGIN TRANSACTION;
+    FOR $row IN $rows {
+        UPSERT type::thing($table, $row.id) CONTENT $row.data;
+    };
+    UPSERT type::thing('sequences', $table) SET value = math::max([value ?? 0, $last_id]);
+    UPSERT type::thing('sqlite_import', $table) SET last_id = $last_id, transferred += $count, updated_at = time::now();
+    COMMIT TRANSACTION;";
+
+/// Rows with an integer id after `$after`, with record links written as their integer
+/// id and datetimes in the SQLite timestamp format
+const EXPORT_QUERY: &str = "RETURN (SELECT id, object::from_entries(array::map(object::entries($this), |$entry| [
+        $entry[0],
+        IF type::is::record($entry[1]) THEN record::id($entry[1])
+        ELSE IF type::is::datetime($entry[1]) THEN time::format($entry[1], '%Y-%m-%d %H:%M:%S')
+        ELSE $entry[1] END
+    ])) AS row FROM type::table($table)
+    WHERE type::is::int(record::id(id)) AND record::id(id) > $after
+    ORDER BY id LIMIT $limit).row";
+
+/// Copy every table from the SQLite file into SurrealDB. Rows keep their ids, so running it
+/// again only copies rows added since; an interrupted run resumes after the last saved batch.
+pub async fn sqlite_to_surreal(sqlite: &Database, surreal: &SurrealDatabase, restart: bool) -> Result<TransferReport> {
+    import_in_batches(sqlite, surreal, restart, BATCH_SIZE).await
+}
+
+async fn import_in_batches(sqlite: &Database, surreal: &SurrealDatabase, restart: bool, batch_size: usize) -> Result<TransferReport> {
+    if restart {
+        surreal.execute_with("DELETE sqlite_import", json!({})).await?;
+    }
+    let mut tables = Vec::new();
+    for table in SYNC_TABLES {
+        let transfer = import_table(sqlite, surreal, table, batch_size)
+            .await
+            .with_context(|| format!("Failed to import {}", table))?;
+        tables.push(transfer);
+    }
+    Ok(TransferReport::new(tables))
+}
+
+async fn import_table(sqlite: &Database, surreal: &SurrealDatabase, table: &str, batch_size: usize) -> Result<TableTransfer> {
+    let mut transfer = TableTransfer {
+        table: table.to_string(),
+        source_rows: 0,
+        transferred: 0,
+        total_transferred: 0,
+    };
+    if sqlite_columns(sqlite, table)?.is_empty() {
+        return Ok(transfer);
+    }
+    transfer.source_rows = sqlite.query(&format!("SELECT COUNT(*) FROM \"{}\"", table), &[], |row| row.get(0))?[0];
+
+    let kinds = field_kinds(surreal, table).await?;
+    let progress: Vec<(i64, i64)> = surreal
+        .query_with(
+            "SELECT VALUE [last_id, transferred] FROM type::thing('sqlite_import', $table)",
+            json!({ "table": table }),
+        )
+        .await?;
+    let (mut last_id, total_transferred) = progress.into_iter().next().unwrap_or((0, 0));
+    transfer.total_transferred = total_transferred;
+
+    let select = format!("SELECT * FROM \"{}\" WHERE id > ?1 ORDER BY id LIMIT ?2", table);
+    loop {
+        let rows = read_batch(sqlite, &select, last_id, batch_size)?;
+        let Some(batch_last_id) = rows.last().map(|(id, _)| *id) else {
+            break;
+        };
+        let count = rows.len() as i64;
+        let mut import = Vec::with_capacity(rows.len());
+        for (id, columns) in rows {
+            let mut data = BTreeMap::new();
+            for (column, value) in columns {
+                let Some(kind) = kinds.get(&column) else {
+                    continue;
+                };
+                let converted = to_surreal(kind, value).with_context(|| format!("Row {} column {}", id, column))?;
+                if let Some(converted) = converted {
+                    data.insert(column, converted);
+                }
+            }
+            import.push(ImportRow { id, data });
+        }
+
+        surreal
+            .execute_with(
+                IMPORT_QUERY,
+                ImportBatch { table: table.to_string(), rows: import, last_id: batch_last_id, count },
+            )
+            .await?;
+
+        last_id = batch_last_id;
+        transfer.transferred += count;
+        transfer.total_transferred += count;
+    }
+    Ok(transfer)
+}
+
+/// A SQLite row as its id and (column, value) pairs
+type SqliteRow = (i64, Vec<(String, SqlValue)>);
+
+/// Up to `limit` rows after `after`
+fn read_batch(sqlite: &Database, select: &str, after: i64, limit: usize) -> Result<Vec<SqliteRow>> {
+    sqlite.with_connection(|conn| {
+        let mut stmt = conn.prepare(select)?;
+        let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+        let id_index = columns
+            .iter()
+            .position(|c| c == "id")
+            .ok_or_else(|| anyhow!("Table has no id column"))?;
+        let rows = stmt.query_map(rusqlite::params![after, limit as i64], |row| {
+            let id: i64 = row.get(id_index)?;
+            let values = columns
+                .iter()
+                .enumerate()
+                .map(|(index, column)| Ok((column.clone(), row.get::<_, SqlValue>(index)?)))
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok((id, values))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })
+}
+
+/// Copy every table from SurrealDB back into the SQLite file (rows with non-integer ids are
+/// skipped). Like the import, it is incremental and resumes after the last saved batch.
+pub async fn surreal_to_sqlite(surreal: &SurrealDatabase, sqlite: &Database, restart: bool) -> Result<TransferReport> {
+    export_in_batches(surreal, sqlite, restart, BATCH_SIZE).await
+}
+
+async fn export_in_batches(surreal: &SurrealDatabase, sqlite: &Database, restart: bool, batch_size: usize) -> Result<TransferReport> {
+    if restart {
+        sqlite.execute("DELETE FROM surreal_export", &[])?;
+    }
+    let mut tables = Vec::new();
+    for table in SYNC_TABLES {
+        let transfer = export_table(surreal, sqlite, table, batch_size)
+            .await
+            .with_context(|| format!("Failed to export {}", table))?;
+        tables.push(transfer);
+    }
+    Ok(TransferReport::new(tables))
+}
+
+async fn export_table(surreal: &SurrealDatabase, sqlite: &Database, table: &str, batch_size: usize) -> Result<TableTransfer> {
+    let kinds = field_kinds(surreal, table).await?;
+    // Copy the fields both sides know; the rest of the SQLite row keeps its defaults
+    let columns: Vec<String> = sqlite_columns(sqlite, table)?
+        .into_iter()
+        .filter(|column| column == "id" || kinds.contains_key(column))
+        .collect();
+
+    let mut response = surreal
+        .query_response("RETURN count((SELECT id FROM type::table($table)));", json!({ "table": table }))
+        .await?;
+    let source_rows: Option<i64> = response.take(0)?;
+    let mut transfer = TableTransfer {
+        table: table.to_string(),
+        source_rows: source_rows.unwrap_or(0),
+        transferred: 0,
+        total_transferred: 0,
+    };
+    if columns.is_empty() {
+        return Ok(transfer);
+    }
+
+    let progress = sqlite.query(
+        "SELECT last_id, transferred FROM surreal_export WHERE table_name = ?",
+        &[&table as &dyn rusqlite::ToSql],
+        |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+    )?;
+    let (mut last_id, total_transferred) = progress.into_iter().next().unwrap_or((0, 0));
+    transfer.total_transferred = total_transferred;
+
+    let quoted: Vec<String> = columns.iter().map(|c| format!("\"{}\"", c)).collect();
+    let insert = format!(
+        "INSERT INTO \"{}\" ({}) VALUES ({}) ON CONFLICT(id) DO UPDATE SET {}",
+        table,
+        quoted.join(", "),
+        vec!["?"; columns.len()].join(", "),
+        quoted.iter().map(|c| format!("{c} = excluded.{c}")).collect::<Vec<_>>().join(", ")
+    );
+
+    loop {
+        let rows: Vec<serde_json::Map<String, serde_json::Value>> = surreal
+            .query_with(EXPORT_QUERY, json!({ "table": table, "after": last_id, "limit": batch_size }))
+            .await?;
+        let Some(batch_last_id) = rows.last().and_then(|row| row.get("id")).and_then(|id| id.as_i64()) else {
+            break;
+        };
+        let count = rows.len() as i64;
+
+        sqlite.transaction(|db| -> std::result::Result<(), String> {
+            for mut row in rows {
+                let values: Vec<SqlValue> = columns
+                    .iter()
+                    .map(|column| to_sqlite(row.remove(column).unwrap_or(serde_json::Value::Null)))
+                    .collect();
+                let params: Vec<&dyn rusqlite::ToSql> = values.iter().map(|v| v as &dyn rusqlite::ToSql).collect();
+                db.execute(&insert, &params).map_err(|e| e.to_string())?;
+            }
+            db.execute(
+                "INSERT INTO surreal_export (table_name, last_id, transferred) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(table_name) DO UPDATE SET last_id = excluded.last_id,
+                    transferred = surreal_export.transferred + excluded.transferred, updated_at = CURRENT_TIMESTAMP",
+                &[&table as &dyn rusqlite::ToSql, &batch_last_id, &count],
+            )
+            .map_err(|e| e.to_string())?;
+            Ok(())
+        })
+        .map_err(|e| anyhow!(e))?;
+
+        last_id = batch_last_id;
+        transfer.transferred += count;
+        transfer.total_transferred += count;
+    }
+    Ok(transfer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::surrealdb::{ConnectionMode, DatabaseConfig};
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn sqlite() -> Database {
+        let db = Database::new(PathBuf::from(":memory:"));
+        db.open().unwrap();
+        crate::migrations::run_pending(&db).unwrap();
+        db
+    }
+
+    async fn surreal() -> (SurrealDatabase, PathBuf) {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "shafaf-transfer-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let mut db = SurrealDatabase::new(DatabaseConfig {
+            mode: ConnectionMode::Offline,
+            offline_path: None,
+            online_url: None,
+            namespace: None,
+            database: None,
+            username: None,
+            password: None,
+            conflict_policy: Default::default(),
+        });
+        db.connect_offline(dir.clone()).await.unwrap();
+        crate::surrealdb::init_schema(&db).await.unwrap();
+        (db, dir)
+    }
+
+    /// A customer with two sales and a custom role, written the way the SQLite build does
+    fn seed(db: &Database) {
+        for sql in [
+            "INSERT INTO currencies (id, name, base, rate) VALUES (1, 'AFN', 1, 1)",
+            "INSERT INTO customers (id, full_name, phone, address, created_at, updated_at) VALUES (4, 'Ahmad', '0700', 'Kabul', '2020-01-02 03:04:05', '2021-06-07 08:09:10')",
+            "INSERT INTO sales (id, customer_id, date, total_amount, paid_amount, currency_id) VALUES (1, 4, '2024-01-01', 30, 10, 1)",
+            "INSERT INTO sales (id, customer_id, date, total_amount, paid_amount, notes) VALUES (7, 4, '2024-02-01', 12.5, 0, 'credit')",
+            "INSERT INTO roles (id, name, capabilities) VALUES (1, 'stock_keeper', '[\"inventory.read\",\"inventory.write\"]')",
+        ] {
+            db.execute(sql, &[]).unwrap();
+        }
+    }
+
+    fn table<'a>(report: &'a TransferReport, name: &str) -> &'a TableTransfer {
+        report.tables.iter().find(|t| t.table == name).unwrap()
+    }
+
+    async fn value(db: &SurrealDatabase, query: &str) -> serde_json::Value {
+        let mut response = db.query_response(query, json!({})).await.unwrap();
+        let value: Option<serde_json::Value> = response.take(0).unwrap();
+        value.unwrap_or(serde_json::Value::Null)
+    }
+
+    #[tokio::test]
+    async fn test_import_links_records_and_keeps_timestamps() {
+        let sqlite = sqlite();
+        seed(&sqlite);
+        let (surreal, dir) = surreal().await;
+
+        let report = sqlite_to_surreal(&sqlite, &surreal, false).await.unwrap();
+        // The customer, the currency, both sales, the role and the default company settings row
+        assert_eq!(report.transferred, 6);
+        let sales = table(&report, "sales");
+        assert_eq!((sales.source_rows, sales.transferred, sales.total_transferred), (2, 2, 2));
+
+        let sale = value(
+            &surreal,
+            "RETURN { linked: type::is::record(sales:7.customer_id), customer: sales:7.customer_id.full_name, currency: <string> sales:1.currency_id, total: sales:7.total_amount }",
+        )
+        .await;
+        assert_eq!(sale, json!({ "linked": true, "customer": "Ahmad", "currency": "currencies:1", "total": 12.5 }));
+        let customer = value(&surreal, "RETURN { created: <string> customers:4.created_at, updated: <string> customers:4.updated_at }").await;
+        assert_eq!(customer, json!({ "created": "2020-01-02T03:04:05Z", "updated": "2021-06-07T08:09:10Z" }));
+        let role = value(&surreal, "RETURN { capabilities: roles:1.capabilities }").await;
+        assert_eq!(role, json!({ "capabilities": ["inventory.read", "inventory.write"] }));
+
+        // New records continue after the imported ids
+        let next = value(&surreal, &format!("RETURN {}", crate::repository::next_id("'sales'"))).await;
+        assert_eq!(next, json!(8));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_import_resumes_after_a_failed_batch() {
+        let sqlite = sqlite();
+        for id in 1..=5 {
+            let ratio = if id == 3 { "'not a number'".to_string() } else { id.to_string() };
+            sqlite
+                .execute(&format!("INSERT INTO units (id, name, ratio, is_base) VALUES ({id}, 'u{id}', {ratio}, 0)"), &[])
+                .unwrap();
+        }
+        let (surreal, dir) = surreal().await;
+
+        let err = import_in_batches(&sqlite, &surreal, false, 2).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("Row 3 column ratio"), "{:#}", err);
+        // The first batch was saved, the failing one left nothing behind
+        assert_eq!(value(&surreal, "RETURN count((SELECT id FROM units))").await, json!(2));
+
+        sqlite.execute("UPDATE units SET ratio = 3 WHERE id = 3", &[]).unwrap();
+        let report = import_in_batches(&sqlite, &surreal, false, 2).await.unwrap();
+        let units = table(&report, "units");
+        assert_eq!((units.source_rows, units.transferred, units.total_transferred), (5, 3, 5));
+        assert_eq!(value(&surreal, "RETURN units:3.ratio").await, json!(3.0));
+
+        // Nothing new to copy; a restart copies everything again
+        assert_eq!(sqlite_to_surreal(&sqlite, &surreal, false).await.unwrap().transferred, 0);
+        assert_eq!(table(&sqlite_to_surreal(&sqlite, &surreal, true).await.unwrap(), "units").transferred, 5);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_export_round_trips_into_sqlite() {
+        let source = sqlite();
+        seed(&source);
+        let (surreal, dir) = surreal().await;
+        sqlite_to_surreal(&source, &surreal, false).await.unwrap();
+        surreal
+            .execute_with(
+                "CREATE sales:9 SET customer_id = customers:4, date = '2024-03-01', exchange_rate = 1, total_amount = 5, base_amount = 5, paid_amount = 5;
+                CREATE customers:cash SET full_name = 'Walk-in', phone = '', address = ''",
+                json!({}),
+            )
+            .await
+            .unwrap();
+
+        let target = sqlite();
+        let report = export_in_batches(&surreal, &target, false, 2).await.unwrap();
+        let customers = table(&report, "customers");
+        // The record with a text id has no SQLite equivalent
+        assert_eq!((customers.source_rows, customers.transferred), (2, 1));
+        assert_eq!(table(&report, "sales").transferred, 3);
+
+        let customer = target
+            .query("SELECT full_name, created_at, updated_at FROM customers WHERE id = 4", &[], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+            })
+            .unwrap();
+        assert_eq!(customer, [("Ahmad".to_string(), "2020-01-02 03:04:05".to_string(), "2021-06-07 08:09:10".to_string())]);
+        let sales = target
+            .query("SELECT id, customer_id, currency_id, total_amount, notes FROM sales ORDER BY id", &[], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, Option<i64>>(2)?, row.get::<_, f64>(3)?, row.get::<_, Option<String>>(4)?))
+            })
+            .unwrap();
+        assert_eq!(
+            sales,
+            [
+                (1, 4, Some(1), 30.0, None),
+                (7, 4, None, 12.5, Some("credit".to_string())),
+                (9, 4, None, 5.0, None),
+            ]
+        );
+
+        let capabilities = target
+            .query("SELECT capabilities FROM roles WHERE name = 'stock_keeper'", &[], |row| row.get::<_, String>(0))
+            .unwrap();
+        assert_eq!(capabilities, [r#"["inventory.read","inventory.write"]"#]);
+
+        // A second run only picks up records added since
+        assert_eq!(surreal_to_sqlite(&surreal, &target, false).await.unwrap().transferred, 0);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,301 @@
+use crate::repository::Repositories;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Months, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const RETENTION_POLICY_KEY: &str = "retention_policy";
+
+/// A kind of record that is moved to the trash instead of being deleted
+pub struct TrashBin {
+    /// Name used by the trash commands, e.g. "customers"
+    pub entity: &'static str,
+    pub table: &'static str,
+    /// One record, as named in messages
+    pub noun: &'static str,
+    /// Columns joined into the label shown in the trash
+    pub label: &'static [&'static str],
+    pub amount: Option<&'static str>,
+    /// Documents are also kept for the document retention period after they were created
+    pub document: bool,
+    /// `(table, column)` of records that link to this one; a record is only purged once nothing does
+    pub references: &'static [(&'static str, &'static str)],
+    /// Kept in SQLite whichever backend is open, as the entity's other commands are
+    pub sqlite_only: bool,
+}
+
+pub const CUSTOMERS: TrashBin = TrashBin {
+    entity: "customers",
+    table: "customers",
+    noun: "customer",
+    label: &["full_name", "phone"],
+    amount: None,
+    document: false,
+    references: &[("sales", "customer_id")],
+    sqlite_only: false,
+};
+
+pub const SUPPLIERS: TrashBin = TrashBin {
+    entity: "suppliers",
+    table: "suppliers",
+    noun: "supplier",
+    label: &["full_name", "phone"],
+    amount: None,
+    document: false,
+    references: &[("purchases", "supplier_id"), ("products", "supplier_id")],
+    sqlite_only: true,
+};
+
+pub const PRODUCTS: TrashBin = TrashBin {
+    entity: "products",
+    table: "products",
+    noun: "product",
+    label: &["name", "bar_code"],
+    amount: None,
+    document: false,
+    references: &[("purchase_items", "product_id"), ("sale_items", "product_id")],
+    sqlite_only: false,
+};
+
+pub const EMPLOYEES: TrashBin = TrashBin {
+    entity: "employees",
+    table: "employees",
+    noun: "employee",
+    label: &["full_name", "position"],
+    amount: None,
+    document: false,
+    references: &[("salaries", "employee_id"), ("deductions", "employee_id")],
+    sqlite_only: true,
+};
+
+pub const ACCOUNTS: TrashBin = TrashBin {
+    entity: "accounts",
+    table: "accounts",
+    noun: "account",
+    label: &["name", "account_code"],
+    amount: Some("current_balance"),
+    document: false,
+    references: &[("journal_entry_lines", "account_id")],
+    sqlite_only: false,
+};
+
+pub const SALES: TrashBin = TrashBin {
+    entity: "sales",
+    table: "sales",
+    noun: "sale",
+    label: &["date", "notes"],
+    amount: Some("total_amount"),
+    document: true,
+    references: &[],
+    sqlite_only: false,
+};
+
+pub const PURCHASES: TrashBin = TrashBin {
+    entity: "purchases",
+    table: "purchases",
+    noun: "purchase",
+    label: &["date", "batch_number", "notes"],
+    amount: Some("total_amount"),
+    document: true,
+    references: &[],
+    sqlite_only: false,
+};
+
+pub const EXPENSES: TrashBin = TrashBin {
+    entity: "expenses",
+    table: "expenses",
+    noun: "expense",
+    label: &["date", "bill_no", "description"],
+    amount: Some("total"),
+    document: true,
+    references: &[],
+    sqlite_only: true,
+};
+
+pub const SALARIES: TrashBin = TrashBin {
+    entity: "salaries",
+    table: "salaries",
+    noun: "salary",
+    label: &["year", "month", "notes"],
+    amount: Some("amount"),
+    document: true,
+    references: &[],
+    sqlite_only: true,
+};
+
+pub const DEDUCTIONS: TrashBin = TrashBin {
+    entity: "deductions",
+    table: "deductions",
+    noun: "deduction",
+    label: &["year", "month", "currency"],
+    amount: Some("amount"),
+    document: true,
+    references: &[],
+    sqlite_only: true,
+};
+
+/// Every bin, documents first: purging them can free the master data they link to
+pub const BINS: &[&TrashBin] = &[
+    &SALES, &PURCHASES, &EXPENSES, &SALARIES, &DEDUCTIONS, &CUSTOMERS, &SUPPLIERS, &PRODUCTS, &EMPLOYEES, &ACCOUNTS,
+];
+
+pub fn bin(entity: &str) -> Option<&'static TrashBin> {
+    BINS.iter().copied().find(|bin| bin.entity == entity)
+}
+
+/// A record in the trash
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrashedRecord {
+    pub id: i64,
+    pub label: String,
+    pub amount: Option<f64>,
+    pub deleted_at: String,
+    pub deleted_by: Option<i64>,
+    pub deleted_by_username: Option<String>,
+}
+
+/// Label of a trashed record from its label column values
+pub fn join_label(parts: impl IntoIterator<Item = Option<String>>) -> String {
+    parts
+        .into_iter()
+        .flatten()
+        .map(|part| part.trim().to_string())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" - ")
+}
+
+/// How long deleted records stay restorable; stored in the app settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Days a record stays in the trash before it may be purged
+    pub trash_days: i64,
+    /// Years documents (sales, purchases, expenses, salaries, deductions) are kept after they were created
+    pub document_years: u32,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        RetentionPolicy { trash_days: 30, document_years: 7 }
+    }
+}
+
+impl RetentionPolicy {
+    fn validate(&self) -> Result<()> {
+        if !(1..=3650).contains(&self.trash_days) {
+            return Err(anyhow!("Records must stay in the trash between 1 and 3650 days"));
+        }
+        if self.document_years > 50 {
+            return Err(anyhow!("Documents can't be kept for more than 50 years"));
+        }
+        Ok(())
+    }
+
+    /// Records of `bin` may be purged if they were deleted before the first and,
+    /// if there is a second, created before it
+    pub fn cutoffs(&self, bin: &TrashBin, now: DateTime<Utc>) -> (DateTime<Utc>, Option<DateTime<Utc>>) {
+        let deleted_before = now - TimeDelta::days(self.trash_days);
+        let created_before = bin
+            .document
+            .then(|| now.checked_sub_months(Months::new(self.document_years * 12)).unwrap_or(DateTime::<Utc>::MIN_UTC));
+        (deleted_before, created_before)
+    }
+}
+
+pub async fn retention_policy(repo: &dyn Repositories) -> Result<RetentionPolicy> {
+    Ok(match repo.get_setting(RETENTION_POLICY_KEY).await? {
+        Some(value) => serde_json::from_value(value)?,
+        None => RetentionPolicy::default(),
+    })
+}
+
+pub async fn save_retention_policy(repo: &dyn Repositories, policy: &RetentionPolicy) -> Result<()> {
+    policy.validate()?;
+    repo.set_setting(RETENTION_POLICY_KEY, &serde_json::to_value(policy)?).await
+}
+
+/// What a purge removed, per entity, and how many records had to stay because others still link to them
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PurgeReport {
+    pub purged: BTreeMap<String, i64>,
+    pub still_referenced: i64,
+}
+
+impl PurgeReport {
+    /// Adds the counts of a purge of other bins
+    pub fn merge(&mut self, other: PurgeReport) {
+        self.purged.extend(other.purged);
+        self.still_referenced += other.still_referenced;
+    }
+}
+
+/// Permanently delete the trashed records of `bins` that are past the retention period
+pub async fn purge(repo: &dyn Repositories, policy: &RetentionPolicy, bins: &[&TrashBin], now: DateTime<Utc>) -> Result<PurgeReport> {
+    let mut report = PurgeReport::default();
+    for bin in bins {
+        let (deleted_before, created_before) = policy.cutoffs(bin, now);
+        let mut purged = 0;
+        for id in repo.purgeable(bin.table, deleted_before, created_before).await? {
+            if repo.count_references(bin.table, id, bin.references).await? > 0 {
+                report.still_referenced += 1;
+                continue;
+            }
+            // Sales, purchases and accounts go together with their items, payments and balances
+            match bin.table {
+                "customers" => repo.delete_customer(id).await?,
+                "products" => repo.delete_product(id).await?,
+                "sales" => repo.delete_sale(id).await?,
+                "purchases" => repo.delete_purchase(id).await?,
+                "accounts" => repo.delete_account(id).await?,
+                table => repo.purge_record(table, id).await?,
+            }
+            purged += 1;
+        }
+        report.purged.insert(bin.entity.to_string(), purged);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bins() {
+        assert!(BINS.iter().all(|b| bin(b.entity).is_some_and(|found| found.table == b.table)));
+        assert!(bin("users").is_none());
+        // Documents come before the master data they link to
+        let first_master = BINS.iter().position(|b| !b.document).unwrap();
+        assert!(BINS[first_master..].iter().all(|b| !b.document));
+    }
+
+    #[test]
+    fn test_merge_reports() {
+        let mut report = PurgeReport { purged: BTreeMap::from([("sales".to_string(), 2)]), still_referenced: 1 };
+        report.merge(PurgeReport { purged: BTreeMap::from([("expenses".to_string(), 3)]), still_referenced: 4 });
+        let purged: Vec<_> = report.purged.iter().map(|(entity, count)| (entity.as_str(), *count)).collect();
+        assert_eq!(purged, [("expenses", 3), ("sales", 2)]);
+        assert_eq!(report.still_referenced, 5);
+    }
+
+    #[test]
+    fn test_cutoffs() {
+        let now = DateTime::parse_from_rfc3339("2026-03-31T12:00:00Z").unwrap().with_timezone(&Utc);
+        let policy = RetentionPolicy::default();
+        let (deleted_before, created_before) = policy.cutoffs(&CUSTOMERS, now);
+        assert_eq!(deleted_before.to_rfc3339(), "2026-03-01T12:00:00+00:00");
+        assert_eq!(created_before, None);
+        let (_, created_before) = policy.cutoffs(&SALES, now);
+        assert_eq!(created_before.unwrap().to_rfc3339(), "2019-03-31T12:00:00+00:00");
+
+        assert!(RetentionPolicy { trash_days: 0, ..policy.clone() }.validate().is_err());
+        assert!(RetentionPolicy { document_years: 51, ..policy }.validate().is_err());
+    }
+
+    #[test]
+    fn test_join_label() {
+        assert_eq!(join_label([Some("Ahmad".into()), None, Some(" ".into()), Some("0799".into())]), "Ahmad - 0799");
+        assert_eq!(join_label([None]), "");
+    }
+}
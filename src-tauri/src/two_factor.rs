@@ -0,0 +1,287 @@
+use crate::repository::{Repositories, TwoFactorRecord};
+use crate::session::{random_hex, token_key};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+
+/// RFC 6238 parameters every authenticator app supports
+pub const TOTP_STEP: i64 = 30;
+pub const TOTP_DIGITS: u32 = 6;
+/// Codes one step before or after now are accepted, for clocks that drift
+const TOTP_SKEW: i64 = 1;
+const SECRET_BYTES: usize = 20;
+pub const RECOVERY_CODES: usize = 10;
+/// How long the second login step may take
+pub const CHALLENGE_LIFETIME: TimeDelta = TimeDelta::minutes(5);
+/// Wrong codes allowed before the password has to be entered again
+pub const CHALLENGE_ATTEMPTS: u32 = 5;
+const ISSUER: &str = "Shafaf";
+const POLICY_KEY: &str = "two_factor_policy";
+
+/// A new random secret, base32 encoded as authenticator apps expect
+pub fn new_secret() -> String {
+    let mut bytes = [0u8; SECRET_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    BASE32_NOPAD.encode(&bytes)
+}
+
+/// URI for the QR code authenticator apps scan
+pub fn otpauth_uri(secret: &str, username: &str) -> String {
+    let mut uri = url::Url::parse("otpauth://totp").expect("valid base URI");
+    uri.set_path(&format!("/{}:{}", ISSUER, username));
+    uri.query_pairs_mut()
+        .append_pair("secret", secret)
+        .append_pair("issuer", ISSUER)
+        .append_pair("algorithm", "SHA1")
+        .append_pair("digits", &TOTP_DIGITS.to_string())
+        .append_pair("period", &TOTP_STEP.to_string());
+    uri.to_string()
+}
+
+fn decode_secret(secret: &str) -> Result<Vec<u8>> {
+    BASE32_NOPAD
+        .decode(secret.trim_end_matches('=').to_ascii_uppercase().as_bytes())
+        .map_err(|e| anyhow!("Invalid two-factor secret: {}", e))
+}
+
+fn step_at(time: DateTime<Utc>) -> i64 {
+    time.timestamp().div_euclid(TOTP_STEP)
+}
+
+/// RFC 4226 HOTP code for `counter`, zero-padded
+fn hotp(key: &[u8], counter: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]]) & 0x7fff_ffff;
+    format!("{:0width$}", value % 10u32.pow(TOTP_DIGITS), width = TOTP_DIGITS as usize)
+}
+
+/// Check a code typed by the user. Returns the time step it belongs to, which has to be
+/// stored as `last_step`: codes of that step or earlier are refused, so none can be replayed.
+pub fn verify_code(secret: &str, code: &str, now: DateTime<Utc>, last_step: Option<i64>) -> Result<Option<i64>> {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    if code.len() != TOTP_DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return Ok(None);
+    }
+    let key = decode_secret(secret)?;
+    let current = step_at(now);
+    let matched = (current - TOTP_SKEW..=current + TOTP_SKEW)
+        .filter(|step| last_step.is_none_or(|last| *step > last))
+        .find(|step| hotp(&key, *step as u64) == code);
+    Ok(matched)
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_ascii_lowercase()
+}
+
+/// Recovery codes are random enough that a fast hash is as good as bcrypt, and checking
+/// all of them at login stays quick
+fn hash_recovery_code(code: &str) -> String {
+    hex::encode(Sha256::digest(normalize_recovery_code(code).as_bytes()))
+}
+
+/// Fresh recovery codes to show the user once, and the hashes to store
+pub fn new_recovery_codes() -> (Vec<String>, Vec<String>) {
+    let codes: Vec<String> = (0..RECOVERY_CODES)
+        .map(|_| {
+            let code = random_hex(5);
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect();
+    let hashes = codes.iter().map(|code| hash_recovery_code(code)).collect();
+    (codes, hashes)
+}
+
+/// Use up a recovery code; false if it isn't one of the user's unused codes
+pub fn use_recovery_code(record: &mut TwoFactorRecord, code: &str) -> bool {
+    if normalize_recovery_code(code).len() != 10 {
+        return false;
+    }
+    let hash = hash_recovery_code(code);
+    match record.recovery_codes.iter().position(|stored| *stored == hash) {
+        Some(index) => {
+            record.recovery_codes.remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Roles whose users must set up two-factor authentication; stored in the app settings
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TwoFactorPolicy {
+    pub required_roles: BTreeSet<String>,
+}
+
+impl TwoFactorPolicy {
+    pub fn requires(&self, role: &str) -> bool {
+        self.required_roles.contains(role)
+    }
+}
+
+pub async fn two_factor_policy(repo: &dyn Repositories) -> Result<TwoFactorPolicy> {
+    Ok(match repo.get_setting(POLICY_KEY).await? {
+        Some(value) => serde_json::from_value(value)?,
+        None => TwoFactorPolicy::default(),
+    })
+}
+
+pub async fn save_two_factor_policy(repo: &dyn Repositories, policy: &TwoFactorPolicy) -> Result<()> {
+    repo.set_setting(POLICY_KEY, &serde_json::to_value(policy)?).await
+}
+
+struct Challenge {
+    user_id: i64,
+    device: String,
+    expires_at: DateTime<Utc>,
+    failures: u32,
+}
+
+/// Logins waiting for their second step, kept in memory like the sessions.
+/// The password step hands out a challenge token that only works from the same device.
+#[derive(Default)]
+pub struct LoginChallenges {
+    challenges: Mutex<HashMap<String, Challenge>>,
+}
+
+impl LoginChallenges {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Challenge>> {
+        self.challenges.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Start the second step for `user_id`; returns the challenge token
+    pub fn start(&self, user_id: i64, device: &str, now: DateTime<Utc>) -> String {
+        let token = random_hex(32);
+        let mut challenges = self.lock();
+        challenges.retain(|_, challenge| challenge.expires_at > now && challenge.user_id != user_id);
+        challenges.insert(
+            token_key(&token),
+            Challenge { user_id, device: device.to_string(), expires_at: now + CHALLENGE_LIFETIME, failures: 0 },
+        );
+        token
+    }
+
+    /// User the challenge belongs to, if it is still open for `device`
+    pub fn user(&self, token: &str, device: &str, now: DateTime<Utc>) -> Option<i64> {
+        let mut challenges = self.lock();
+        challenges.retain(|_, challenge| challenge.expires_at > now);
+        challenges
+            .get(&token_key(token))
+            .filter(|challenge| challenge.device == device)
+            .map(|challenge| challenge.user_id)
+    }
+
+    /// Count a wrong code; after too many the challenge is dropped. Returns whether it is still open.
+    pub fn failed(&self, token: &str) -> bool {
+        let key = token_key(token);
+        let mut challenges = self.lock();
+        let Some(challenge) = challenges.get_mut(&key) else {
+            return false;
+        };
+        challenge.failures += 1;
+        if challenge.failures >= CHALLENGE_ATTEMPTS {
+            challenges.remove(&key);
+            return false;
+        }
+        true
+    }
+
+    pub fn finish(&self, token: &str) {
+        self.lock().remove(&token_key(token));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(seconds, 0).unwrap()
+    }
+
+    /// The code an authenticator app shows at `time`
+    fn code_at(secret: &str, time: DateTime<Utc>) -> Result<String> {
+        Ok(hotp(&decode_secret(secret)?, step_at(time) as u64))
+    }
+
+    /// RFC 6238 appendix B secret ("12345678901234567890"), SHA-1 rows truncated to 6 digits
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn test_codes_match_rfc_6238() {
+        for (time, code) in [(59, "287082"), (1_111_111_109, "081804"), (1_234_567_890, "005924"), (2_000_000_000, "279037")] {
+            assert_eq!(code_at(RFC_SECRET, at(time)).unwrap(), code, "time {}", time);
+        }
+        assert!(code_at("not base32!", at(0)).is_err());
+        assert_eq!(BASE32_NOPAD.decode(new_secret().as_bytes()).unwrap().len(), SECRET_BYTES);
+    }
+
+    #[test]
+    fn test_verify_allows_drift_but_not_replay() {
+        let now = at(1_234_567_890);
+        let step = step_at(now);
+        let code = code_at(RFC_SECRET, now).unwrap();
+        assert_eq!(verify_code(RFC_SECRET, "005 924", now, None).unwrap(), Some(step));
+        // The previous and next step are accepted, older codes aren't
+        assert_eq!(verify_code(RFC_SECRET, &code, now + TimeDelta::seconds(30), None).unwrap(), Some(step));
+        assert_eq!(verify_code(RFC_SECRET, &code, now + TimeDelta::seconds(90), None).unwrap(), None);
+        // A code already used (or an earlier one) is refused
+        assert_eq!(verify_code(RFC_SECRET, &code, now, Some(step)).unwrap(), None);
+        assert_eq!(verify_code(RFC_SECRET, "12345", now, None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_recovery_codes_work_once() {
+        let (codes, hashes) = new_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODES);
+        assert!(hashes.iter().all(|hash| !codes.contains(hash)));
+
+        let mut record = TwoFactorRecord { recovery_codes: hashes, ..TwoFactorRecord::default() };
+        // Typed without the dash and in capitals still works, but only once
+        let typed = codes[3].replace('-', "").to_uppercase();
+        assert!(use_recovery_code(&mut record, &typed));
+        assert!(!use_recovery_code(&mut record, &codes[3]));
+        assert_eq!(record.recovery_codes.len(), RECOVERY_CODES - 1);
+        assert!(!use_recovery_code(&mut record, "0000000000"));
+    }
+
+    #[test]
+    fn test_challenges() {
+        let challenges = LoginChallenges::default();
+        let token = challenges.start(7, "main", at(0));
+        assert_eq!(challenges.user(&token, "main", at(60)), Some(7));
+        assert_eq!(challenges.user(&token, "lan-1", at(60)), None);
+        assert_eq!(challenges.user(&token, "main", at(301)), None);
+
+        let token = challenges.start(7, "main", at(400));
+        for _ in 1..CHALLENGE_ATTEMPTS {
+            assert!(challenges.failed(&token));
+        }
+        assert!(!challenges.failed(&token));
+        assert_eq!(challenges.user(&token, "main", at(401)), None);
+
+        let token = challenges.start(7, "main", at(500));
+        challenges.finish(&token);
+        assert_eq!(challenges.user(&token, "main", at(501)), None);
+    }
+
+    #[test]
+    fn test_otpauth_uri() {
+        assert_eq!(
+            otpauth_uri("JBSWY3DPEHPK3PXP", "sara ahmadi"),
+            "otpauth://totp/Shafaf:sara%20ahmadi?secret=JBSWY3DPEHPK3PXP&issuer=Shafaf&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+}
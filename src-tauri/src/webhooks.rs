@@ -0,0 +1,481 @@
+use crate::db::Database;
+use crate::events;
+use crate::repository::{ListQuery, TIMESTAMP_FORMAT};
+use crate::session::random_hex;
+use crate::PaginatedResponse;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::time::Duration;
+
+/// Name of the event, e.g. `sale_created`
+pub const EVENT_HEADER: &str = "x-shafaf-event";
+/// Id of the delivery; the same on every retry, so receivers can drop duplicates
+pub const DELIVERY_HEADER: &str = "x-shafaf-delivery";
+/// Unix time the request was signed at
+pub const TIMESTAMP_HEADER: &str = "x-shafaf-timestamp";
+/// `sha256=<hex>` HMAC of `<timestamp>.<body>` with the webhook's secret
+pub const SIGNATURE_HEADER: &str = "x-shafaf-signature";
+
+/// How often the background worker looks for events to deliver
+pub const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Attempts after which a delivery is marked failed
+pub const MAX_ATTEMPTS: i64 = 8;
+/// Wait after the first failed attempt; it doubles with every further one
+const FIRST_RETRY_SECS: i64 = 30;
+const MAX_RETRY_SECS: i64 = 6 * 60 * 60;
+/// Deliveries attempted per run of the worker
+const BATCH_SIZE: i64 = 50;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest response text kept in the delivery log
+const MAX_ERROR_CHARS: usize = 500;
+
+/// A URL that is sent the events it subscribes to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: i64,
+    pub url: String,
+    /// Key of the `X-Shafaf-Signature` HMAC
+    pub secret: String,
+    /// Subscribed events; empty for all of them
+    pub events: Vec<String>,
+    pub is_active: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookInput {
+    pub url: String,
+    #[serde(default)]
+    pub events: Vec<String>,
+    pub is_active: bool,
+}
+
+/// One event sent (or to be sent) to one webhook
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub webhook_id: i64,
+    pub url: String,
+    pub event_id: i64,
+    pub event: String,
+    /// `pending`, `delivered` or `failed`
+    pub status: String,
+    pub attempts: i64,
+    pub next_attempt_at: Option<String>,
+    /// HTTP status of the last attempt, if the webhook answered
+    pub last_status: Option<i64>,
+    pub last_error: Option<String>,
+    pub delivered_at: Option<String>,
+    pub created_at: String,
+}
+
+/// Filters of `get_webhook_deliveries`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeliveryFilter {
+    pub webhook_id: Option<i64>,
+    pub status: Option<String>,
+    pub event: Option<String>,
+}
+
+const WEBHOOK_COLUMNS: &str = "id, url, secret, events, is_active, created_at, updated_at";
+
+fn webhook_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Webhook> {
+    let events: String = row.get(3)?;
+    Ok(Webhook {
+        id: row.get(0)?,
+        url: row.get(1)?,
+        secret: row.get(2)?,
+        events: if events == "*" { Vec::new() } else { events.split(',').map(str::to_string).collect() },
+        is_active: row.get::<_, i64>(4)? != 0,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+    })
+}
+
+/// Check the URL and event names; returns the `events` column value
+fn validate(input: &WebhookInput) -> Result<String> {
+    let url = url::Url::parse(input.url.trim()).map_err(|e| anyhow!("Invalid webhook URL: {}", e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(anyhow!("Webhook URLs must start with http:// or https://"));
+    }
+    if let Some(unknown) = input.events.iter().find(|event| !events::ALL.contains(&event.as_str())) {
+        return Err(anyhow!("Unknown event {}; expected one of {}", unknown, events::ALL.join(", ")));
+    }
+    Ok(if input.events.is_empty() { "*".to_string() } else { input.events.join(",") })
+}
+
+pub fn get(db: &Database, id: i64) -> Result<Option<Webhook>> {
+    let sql = format!("SELECT {} FROM webhooks WHERE id = ?", WEBHOOK_COLUMNS);
+    Ok(db.query(&sql, &[&id as &dyn rusqlite::ToSql], webhook_from_row)?.into_iter().next())
+}
+
+pub fn list(db: &Database) -> Result<Vec<Webhook>> {
+    let sql = format!("SELECT {} FROM webhooks ORDER BY id", WEBHOOK_COLUMNS);
+    db.query(&sql, &[], webhook_from_row)
+}
+
+/// Add a webhook with a new random secret
+pub fn create(db: &Database, input: &WebhookInput) -> Result<Webhook> {
+    let events = validate(input)?;
+    let secret = format!("whsec_{}", random_hex(32));
+    let is_active = input.is_active as i64;
+    let id = db.insert(
+        "INSERT INTO webhooks (url, secret, events, is_active) VALUES (?, ?, ?, ?)",
+        &[&input.url.trim() as &dyn rusqlite::ToSql, &secret, &events, &is_active],
+    )?;
+    get(db, id)?.ok_or_else(|| anyhow!("Failed to retrieve created webhook"))
+}
+
+/// Change a webhook's URL, events or active flag; the secret stays
+pub fn update(db: &Database, id: i64, input: &WebhookInput) -> Result<Webhook> {
+    let events = validate(input)?;
+    let is_active = input.is_active as i64;
+    let changed = db.execute(
+        "UPDATE webhooks SET url = ?, events = ?, is_active = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+        &[&input.url.trim() as &dyn rusqlite::ToSql, &events, &is_active, &id],
+    )?;
+    if changed == 0 {
+        return Err(anyhow!("No webhook with id {}", id));
+    }
+    get(db, id)?.ok_or_else(|| anyhow!("Failed to retrieve updated webhook"))
+}
+
+/// Remove a webhook and its delivery log
+pub fn delete(db: &Database, id: i64) -> Result<bool> {
+    db.transaction(|db| {
+        db.execute("DELETE FROM webhook_deliveries WHERE webhook_id = ?", &[&id]).map_err(|e| e.to_string())?;
+        db.execute("DELETE FROM webhooks WHERE id = ?", &[&id]).map_err(|e| e.to_string())
+    })
+    .map(|deleted| deleted > 0)
+    .map_err(|e| anyhow!(e))
+}
+
+/// The delivery log, newest first
+pub fn deliveries(db: &Database, filter: &DeliveryFilter, query: &ListQuery) -> Result<PaginatedResponse<WebhookDelivery>> {
+    let mut conditions = Vec::new();
+    let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+    if let Some(webhook_id) = filter.webhook_id {
+        conditions.push("d.webhook_id = ?");
+        params.push(Box::new(webhook_id));
+    }
+    if let Some(status) = filter.status.as_deref().filter(|s| !s.is_empty()) {
+        conditions.push("d.status = ?");
+        params.push(Box::new(status.to_string()));
+    }
+    if let Some(event) = filter.event.as_deref().filter(|s| !s.is_empty()) {
+        conditions.push("e.event = ?");
+        params.push(Box::new(event.to_string()));
+    }
+    let where_clause = if conditions.is_empty() { String::new() } else { format!("WHERE {}", conditions.join(" AND ")) };
+    let from = "FROM webhook_deliveries d
+        INNER JOIN webhooks w ON w.id = d.webhook_id
+        INNER JOIN event_outbox e ON e.id = d.event_id";
+    let refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+
+    let total = db
+        .query(&format!("SELECT COUNT(*) {} {}", from, where_clause), &refs, |row| row.get::<_, i64>(0))?
+        .first()
+        .copied()
+        .unwrap_or(0);
+
+    let sql = format!(
+        "SELECT d.id, d.webhook_id, w.url, d.event_id, e.event, d.status, d.attempts, d.next_attempt_at,
+            d.last_status, d.last_error, d.delivered_at, d.created_at
+        {} {} ORDER BY d.id DESC LIMIT {} OFFSET {}",
+        from, where_clause, query.per_page, query.offset()
+    );
+    let items = db.query(&sql, &refs, |row| {
+        Ok(WebhookDelivery {
+            id: row.get(0)?,
+            webhook_id: row.get(1)?,
+            url: row.get(2)?,
+            event_id: row.get(3)?,
+            event: row.get(4)?,
+            status: row.get(5)?,
+            attempts: row.get(6)?,
+            next_attempt_at: row.get(7)?,
+            last_status: row.get(8)?,
+            last_error: row.get(9)?,
+            delivered_at: row.get(10)?,
+            created_at: row.get(11)?,
+        })
+    })?;
+    Ok(query.page_of(items, total))
+}
+
+/// `sha256=<hex>` signature of a request body, as sent in `X-Shafaf-Signature`
+pub fn signature(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// How long to wait after the `attempts`-th failed attempt
+pub fn backoff(attempts: i64) -> TimeDelta {
+    let exponent = attempts.clamp(1, 20) - 1;
+    TimeDelta::seconds((FIRST_RETRY_SECS << exponent).min(MAX_RETRY_SECS))
+}
+
+/// HTTP client for deliveries
+pub fn client() -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?)
+}
+
+/// Queue a delivery of every new outbox event for each active webhook subscribed to it
+pub fn dispatch(db: &Database, now: DateTime<Utc>) -> Result<usize> {
+    let now_text = now.format(TIMESTAMP_FORMAT).to_string();
+    db.transaction(|db| {
+        let pending = db
+            .query("SELECT id, event FROM event_outbox WHERE dispatched_at IS NULL ORDER BY id", &[], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(|e| e.to_string())?;
+        let mut queued = 0;
+        for (event_id, event) in pending {
+            queued += db
+                .execute(
+                    "INSERT INTO webhook_deliveries (webhook_id, event_id, next_attempt_at)
+                    SELECT id, ?, ? FROM webhooks
+                    WHERE is_active = 1 AND (events = '*' OR ',' || events || ',' LIKE '%,' || ? || ',%')
+                    ORDER BY id",
+                    &[&event_id as &dyn rusqlite::ToSql, &now_text, &event],
+                )
+                .map_err(|e| e.to_string())?;
+            db.execute("UPDATE event_outbox SET dispatched_at = CURRENT_TIMESTAMP WHERE id = ?", &[&event_id])
+                .map_err(|e| e.to_string())?;
+        }
+        Ok::<_, String>(queued)
+    })
+    .map_err(|e| anyhow!(e))
+}
+
+struct DueDelivery {
+    id: i64,
+    attempts: i64,
+    url: String,
+    secret: String,
+    event_id: i64,
+    event: String,
+    payload: String,
+    created_at: String,
+}
+
+/// POST one delivery; Ok with the status for a 2xx answer, otherwise the status (if any) and what went wrong
+async fn send(client: &reqwest::Client, delivery: &DueDelivery, now: DateTime<Utc>) -> std::result::Result<u16, (Option<u16>, String)> {
+    let data: Value = serde_json::from_str(&delivery.payload).map_err(|e| (None, format!("Invalid event payload: {}", e)))?;
+    let body = json!({
+        "id": delivery.event_id,
+        "event": delivery.event,
+        "created_at": delivery.created_at,
+        "data": data,
+    })
+    .to_string();
+    let timestamp = now.timestamp();
+    let response = client
+        .post(&delivery.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, &delivery.event)
+        .header(DELIVERY_HEADER, delivery.id.to_string())
+        .header(TIMESTAMP_HEADER, timestamp.to_string())
+        .header(SIGNATURE_HEADER, signature(&delivery.secret, timestamp, &body))
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| (None, e.to_string()))?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(status.as_u16());
+    }
+    let text = response.text().await.unwrap_or_default();
+    Err((Some(status.as_u16()), text.chars().take(MAX_ERROR_CHARS).collect()))
+}
+
+/// Attempt the deliveries that are due at `now`; returns how many were attempted
+pub async fn deliver_due(db: &Database, client: &reqwest::Client, now: DateTime<Utc>) -> Result<usize> {
+    let now_text = now.format(TIMESTAMP_FORMAT).to_string();
+    let due = db.query(
+        "SELECT d.id, d.attempts, w.url, w.secret, e.id, e.event, e.payload, e.created_at
+        FROM webhook_deliveries d
+        INNER JOIN webhooks w ON w.id = d.webhook_id
+        INNER JOIN event_outbox e ON e.id = d.event_id
+        WHERE d.status = 'pending' AND w.is_active = 1 AND d.next_attempt_at <= ?
+        ORDER BY d.next_attempt_at, d.id LIMIT ?",
+        &[&now_text as &dyn rusqlite::ToSql, &BATCH_SIZE],
+        |row| {
+            Ok(DueDelivery {
+                id: row.get(0)?,
+                attempts: row.get(1)?,
+                url: row.get(2)?,
+                secret: row.get(3)?,
+                event_id: row.get(4)?,
+                event: row.get(5)?,
+                payload: row.get(6)?,
+                created_at: row.get(7)?,
+            })
+        },
+    )?;
+
+    for delivery in &due {
+        let attempts = delivery.attempts + 1;
+        match send(client, delivery, now).await {
+            Ok(status) => {
+                db.execute(
+                    "UPDATE webhook_deliveries SET status = 'delivered', attempts = ?, last_status = ?, last_error = NULL,
+                        delivered_at = ?, next_attempt_at = NULL WHERE id = ?",
+                    &[&attempts as &dyn rusqlite::ToSql, &status, &now_text, &delivery.id],
+                )?;
+            }
+            Err((status, error)) => {
+                let (state, next_attempt_at) = if attempts >= MAX_ATTEMPTS {
+                    ("failed", None)
+                } else {
+                    ("pending", Some((now + backoff(attempts)).format(TIMESTAMP_FORMAT).to_string()))
+                };
+                db.execute(
+                    "UPDATE webhook_deliveries SET status = ?, attempts = ?, last_status = ?, last_error = ?, next_attempt_at = ? WHERE id = ?",
+                    &[&state as &dyn rusqlite::ToSql, &attempts, &status, &error, &next_attempt_at, &delivery.id],
+                )?;
+            }
+        }
+    }
+    Ok(due.len())
+}
+
+/// One pass of the background worker: fan out new events, then send what is due
+pub async fn run_once(db: &Database, client: &reqwest::Client, now: DateTime<Utc>) -> Result<usize> {
+    dispatch(db, now)?;
+    deliver_due(db, client, now).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations;
+    use axum::{extract::State, http::HeaderMap, http::StatusCode, routing::post, Router};
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+
+    fn memory_db() -> Database {
+        let db = Database::new(PathBuf::from(":memory:"));
+        db.open().unwrap();
+        migrations::run_pending(&db).unwrap();
+        db
+    }
+
+    #[derive(Clone, Default)]
+    struct StandIn {
+        /// Statuses to answer with, in order; 200 once they run out
+        statuses: Arc<Mutex<Vec<u16>>>,
+        received: Arc<Mutex<Vec<(HeaderMap, String)>>>,
+    }
+
+    async fn receive(State(stand_in): State<StandIn>, headers: HeaderMap, body: String) -> StatusCode {
+        stand_in.received.lock().unwrap().push((headers, body));
+        let mut statuses = stand_in.statuses.lock().unwrap();
+        let status = if statuses.is_empty() { 200 } else { statuses.remove(0) };
+        StatusCode::from_u16(status).unwrap()
+    }
+
+    /// A local HTTP server standing in for a webhook receiver; returns its URL
+    async fn serve(stand_in: StandIn) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let app = Router::new().route("/hook", post(receive)).with_state(stand_in);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        url
+    }
+
+    fn delivery_log(db: &Database) -> Vec<WebhookDelivery> {
+        deliveries(db, &DeliveryFilter::default(), &ListQuery::new(1, 50)).unwrap().items
+    }
+
+    #[test]
+    fn test_validation_and_backoff() {
+        let db = memory_db();
+        let input = |url: &str, events: &[&str]| WebhookInput {
+            url: url.to_string(),
+            events: events.iter().map(|e| e.to_string()).collect(),
+            is_active: true,
+        };
+        assert!(create(&db, &input("ftp://example.com", &[])).is_err());
+        assert!(create(&db, &input("https://example.com/hook", &["sale_deleted"])).is_err());
+        let webhook = create(&db, &input("https://example.com/hook", &[events::SALE_CREATED, events::STOCK_LOW])).unwrap();
+        assert_eq!(webhook.events, vec!["sale_created", "stock_low"]);
+        assert!(webhook.secret.starts_with("whsec_"));
+
+        assert_eq!(backoff(1), TimeDelta::seconds(30));
+        assert_eq!(backoff(3), TimeDelta::seconds(120));
+        assert_eq!(backoff(MAX_ATTEMPTS + 10), TimeDelta::seconds(MAX_RETRY_SECS));
+    }
+
+    #[tokio::test]
+    async fn test_signed_delivery_is_retried_with_backoff() {
+        let db = memory_db();
+        let stand_in = StandIn { statuses: Arc::new(Mutex::new(vec![500])), ..StandIn::default() };
+        let url = serve(stand_in.clone()).await;
+        let webhook = create(&db, &WebhookInput { url, events: vec![events::SALE_CREATED.to_string()], is_active: true }).unwrap();
+        events::record(&db, events::SALE_CREATED, &json!({ "id": 7, "total_amount": 120.0 })).unwrap();
+        events::record(&db, events::EXPENSE_RECORDED, &json!({ "id": 3 })).unwrap();
+        let client = client().unwrap();
+        let now = Utc::now();
+
+        // The first attempt fails and is put off by the first backoff step
+        assert_eq!(run_once(&db, &client, now).await.unwrap(), 1);
+        let log = delivery_log(&db);
+        assert_eq!(log.len(), 1, "only the subscribed event is queued");
+        assert_eq!((log[0].status.as_str(), log[0].attempts, log[0].last_status), ("pending", 1, Some(500)));
+        assert_eq!(run_once(&db, &client, now + TimeDelta::seconds(10)).await.unwrap(), 0);
+
+        assert_eq!(run_once(&db, &client, now + backoff(1)).await.unwrap(), 1);
+        let log = delivery_log(&db);
+        assert_eq!((log[0].status.as_str(), log[0].attempts, log[0].last_status), ("delivered", 2, Some(200)));
+
+        let received = stand_in.received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        let (headers, body) = &received[1];
+        assert_eq!(headers[EVENT_HEADER], "sale_created");
+        assert_eq!(headers[DELIVERY_HEADER], log[0].id.to_string().as_str());
+        let timestamp: i64 = headers[TIMESTAMP_HEADER].to_str().unwrap().parse().unwrap();
+        assert_eq!(headers[SIGNATURE_HEADER], signature(&webhook.secret, timestamp, body).as_str());
+        assert_ne!(headers[SIGNATURE_HEADER], signature("whsec_other", timestamp, body).as_str());
+        let body: Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["event"], "sale_created");
+        assert_eq!(body["data"], json!({ "id": 7, "total_amount": 120.0 }));
+    }
+
+    #[tokio::test]
+    async fn test_outbox_survives_restart_and_gives_up() {
+        let dir = std::env::temp_dir().join(format!("shafaf-webhooks-{}", random_hex(8)));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.db");
+
+        // Nothing listens on a port that was just freed
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let db = Database::new(path.clone());
+        db.open().unwrap();
+        migrations::run_pending(&db).unwrap();
+        create(&db, &WebhookInput { url: format!("http://{}/hook", closed), events: Vec::new(), is_active: true }).unwrap();
+        events::record(&db, events::SALARY_PAID, &json!({ "id": 1 })).unwrap();
+        db.close().unwrap();
+
+        let db = Database::new(path);
+        db.open().unwrap();
+        let client = client().unwrap();
+        let mut now = Utc::now();
+        for _ in 0..MAX_ATTEMPTS {
+            assert_eq!(run_once(&db, &client, now).await.unwrap(), 1);
+            now += TimeDelta::seconds(MAX_RETRY_SECS);
+        }
+        assert_eq!(run_once(&db, &client, now).await.unwrap(), 0);
+        let log = delivery_log(&db);
+        assert_eq!((log[0].event.as_str(), log[0].status.as_str(), log[0].attempts), ("salary_paid", "failed", MAX_ATTEMPTS));
+        assert!(log[0].last_error.is_some());
+        db.close().unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -21,6 +21,7 @@ import { applyFont } from "./utils/fonts";
 import { isLicenseValid } from "./utils/license";
 import { checkForUpdatesOnStartup, checkForUpdates, installUpdate } from "./utils/updater";
 import { startCredentialSync } from "./utils/puter";
+import { logoutUser } from "./utils/auth";
 import Login from "./components/Login";
 import License from "./components/License";
 import CurrencyManagement from "./components/Currency";
@@ -281,6 +282,7 @@ function App() {
   }
 
   const handleLogout = () => {
+    logoutUser().catch((error) => console.error("Error ending session:", error));
     setUser(null);
     setCurrentPage("dashboard");
   };
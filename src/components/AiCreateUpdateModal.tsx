@@ -1,6 +1,6 @@
 import { useState, useEffect, useRef } from "react";
 import { motion } from "framer-motion";
-import { loadPuter, isPuterAvailable, LS_PUTER_APP_ID, LS_PUTER_TOKEN, LS_PUTER_MODEL } from "../utils/puter";
+import { loadPuter, isPuterAvailable, openAiPage, waitForPuterCredentials, LS_PUTER_APP_ID, LS_PUTER_TOKEN, LS_PUTER_MODEL } from "../utils/puter";
 import { generateCreateUpdateIntent, executeIntent } from "../utils/puterCreateUpdate";
 import { getCurrencies } from "../utils/currency";
 import { getSuppliers } from "../utils/supplier";
@@ -26,6 +26,7 @@ export default function AiCreateUpdateModal({ open, onClose, onSuccess }: AiCrea
   const [model, setModel] = useState("");
   const [puterLoaded, setPuterLoaded] = useState(false);
   const [applying, setApplying] = useState(false);
+  const [waitingForBrowser, setWaitingForBrowser] = useState(false);
   const [error, setError] = useState<string | null>(null);
   const [messages, setMessages] = useState<{ role: "user" | "assistant"; content: string }[]>([]);
   const [input, setInput] = useState("");
@@ -84,6 +85,29 @@ export default function AiCreateUpdateModal({ open, onClose, onSuccess }: AiCrea
     }
   };
 
+  const handleOpenAiPage = async () => {
+    setError(null);
+    setWaitingForBrowser(true);
+    try {
+      if (!(await openAiPage())) {
+        setError("سرور داخلی برنامه در حال اجرا نیست؛ شناسه و توکن را دستی وارد کنید.");
+        return;
+      }
+      const credentials = await waitForPuterCredentials();
+      if (!credentials) {
+        setError("اطلاعات Puter از مرورگر دریافت نشد. دوباره تلاش کنید.");
+        return;
+      }
+      setAppId(credentials.appId);
+      setAuthToken(credentials.authToken);
+      setPuterLoaded(await loadPuter(credentials.appId, credentials.authToken));
+    } catch (e) {
+      setError("باز کردن صفحه Puter ناموفق بود: " + String(e));
+    } finally {
+      setWaitingForBrowser(false);
+    }
+  };
+
   const handleSend = async (text: string) => {
     const prompt = text.trim();
     if (!prompt || loading) return;
@@ -178,6 +202,15 @@ export default function AiCreateUpdateModal({ open, onClose, onSuccess }: AiCrea
                 >
                   {applying ? "در حال اعمال…" : "اعمال"}
                 </motion.button>
+                <motion.button
+                  onClick={handleOpenAiPage}
+                  disabled={applying || waitingForBrowser}
+                  className="px-4 py-2.5 border border-blue-300 dark:border-blue-700 text-blue-700 dark:text-blue-300 hover:bg-blue-100 dark:hover:bg-blue-900/40 disabled:opacity-50 font-medium rounded-xl"
+                  whileHover={{ scale: 1.02 }}
+                  whileTap={{ scale: 0.98 }}
+                >
+                  {waitingForBrowser ? "در انتظار ورود در مرورگر…" : "ورود به Puter در مرورگر"}
+                </motion.button>
               </div>
             </div>
           )}
@@ -10,7 +10,7 @@ import moment from "moment-jalaali";
 import { generateReport, type ReportJson, type ReportSection } from "../utils/puterReport";
 import { formatPersianNumber } from "../utils/dashboard";
 import { sanitizeFilename, sanitizeSheetName, formatCellForExcel } from "../utils/exportHelpers";
-import { loadPuter, isPuterAvailable, LS_PUTER_APP_ID, LS_PUTER_TOKEN, LS_PUTER_MODEL } from "../utils/puter";
+import { loadPuter, isPuterAvailable, openAiPage, waitForPuterCredentials, LS_PUTER_APP_ID, LS_PUTER_TOKEN, LS_PUTER_MODEL } from "../utils/puter";
 
 interface AiReportProps {
   onBack: () => void;
@@ -158,6 +158,7 @@ export default function AiReport({ onBack }: AiReportProps) {
   const [model, setModel] = useState("");
   const [datePreset, setDatePreset] = useState<string | null>(null);
   const [applying, setApplying] = useState(false);
+  const [waitingForBrowser, setWaitingForBrowser] = useState(false);
   const [isExportingPdf, setIsExportingPdf] = useState(false);
   const [isExportingExcel, setIsExportingExcel] = useState(false);
   const [history, setHistory] = useState<HistoryItem[]>([]);
@@ -203,6 +204,29 @@ export default function AiReport({ onBack }: AiReportProps) {
     }
   };
 
+  const handleOpenAiPage = async () => {
+    setError(null);
+    setWaitingForBrowser(true);
+    try {
+      if (!(await openAiPage())) {
+        setError("سرور داخلی برنامه در حال اجرا نیست؛ شناسه و توکن را دستی وارد کنید.");
+        return;
+      }
+      const credentials = await waitForPuterCredentials();
+      if (!credentials) {
+        setError("اطلاعات Puter از مرورگر دریافت نشد. دوباره تلاش کنید.");
+        return;
+      }
+      setAppId(credentials.appId);
+      setAuthToken(credentials.authToken);
+      setPuterLoaded(await loadPuter(credentials.appId, credentials.authToken));
+    } catch (e) {
+      setError("باز کردن صفحه Puter ناموفق بود: " + String(e));
+    } finally {
+      setWaitingForBrowser(false);
+    }
+  };
+
   useEffect(() => {
     if (puterLoaded) return;
     if (isPuterAvailable()) setPuterLoaded(true);
@@ -516,6 +540,15 @@ export default function AiReport({ onBack }: AiReportProps) {
                 >
                   {applying ? "در حال اعمال…" : "اعمال"}
                 </motion.button>
+                <motion.button
+                  onClick={handleOpenAiPage}
+                  disabled={applying || waitingForBrowser}
+                  className="px-4 py-2.5 border border-blue-300 dark:border-blue-700 text-blue-700 dark:text-blue-300 hover:bg-blue-100 dark:hover:bg-blue-900/40 disabled:opacity-50 font-medium rounded-xl"
+                  whileHover={{ scale: 1.02 }}
+                  whileTap={{ scale: 0.98 }}
+                >
+                  {waitingForBrowser ? "در انتظار ورود در مرورگر…" : "ورود به Puter در مرورگر"}
+                </motion.button>
               </div>
             </div>
           )}
@@ -1,7 +1,7 @@
 import { useState } from "react";
 import { motion } from "framer-motion";
 import toast from "react-hot-toast";
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "../utils/invoke";
 
 interface DatabaseConfigProps {
   onConfigComplete: () => void;
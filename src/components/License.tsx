@@ -86,9 +86,9 @@ export default function License({ onLicenseValid }: LicenseProps) {
 
     try {
       // Validate the license key
-      const isValid = await validateLicenseKey(licenseKey.trim());
+      const info = await validateLicenseKey(licenseKey.trim());
       
-      if (isValid) {
+      if (info.valid) {
         // Store the license key
         await storeLicenseKey(licenseKey.trim());
         toast.success(translations.success.activated);
@@ -97,7 +97,7 @@ export default function License({ onLicenseValid }: LicenseProps) {
           setShowDatabaseConfig(true);
         }, 500);
       } else {
-        toast.error(translations.errors.invalidKey);
+        toast.error(info.reason ? `${translations.errors.invalidKey}: ${info.reason}` : translations.errors.invalidKey);
       }
     } catch (error: any) {
       console.error("Error validating license:", error);
@@ -1,7 +1,7 @@
 import { useState, useEffect } from "react";
 import { motion, AnimatePresence } from "framer-motion";
 import toast from "react-hot-toast";
-import { getUserById, updateUserProfile, type User } from "../utils/user";
+import { getProfile, updateUserProfile, type User } from "../utils/user";
 import Footer from "./Footer";
 
 // Dari translations
@@ -83,7 +83,7 @@ export default function ProfileEdit({ userId, onBack, onProfileUpdate }: Profile
     const loadUserData = async () => {
         try {
             setFetchLoading(true);
-            const userData = await getUserById(userId);
+            const userData = await getProfile();
             if (userData) {
                 setUser(userData);
                 setFormData({
@@ -126,7 +126,7 @@ export default function ProfileEdit({ userId, onBack, onProfileUpdate }: Profile
 
         try {
             setLoading(true);
-            const updatedUser = await updateUserProfile(userId, {
+            const updatedUser = await updateUserProfile({
                 username: formData.username,
                 email: formData.email,
                 full_name: formData.full_name || undefined,
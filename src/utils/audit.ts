@@ -0,0 +1,68 @@
+import { invoke } from "./invoke";
+import type { PaginatedResponse } from "./expense";
+
+/** One recorded change to a record */
+export interface AuditEntry {
+  id: number;
+  created_at: string;
+  user_id: number | null;
+  username: string | null;
+  /** Command that made the change, e.g. "update_sale" */
+  command: string | null;
+  /** Table of the changed record, e.g. "sales" */
+  entity: string;
+  entity_id: string | null;
+  action: "create" | "update" | "delete";
+  /** The record before the change (null when it was created) */
+  before: Record<string, unknown> | null;
+  /** The record after the change (null when it was deleted) */
+  after: Record<string, unknown> | null;
+  prev_hash: string | null;
+  hash: string | null;
+}
+
+export interface AuditFilter {
+  entity?: string | null;
+  entity_id?: string | null;
+  user_id?: number | null;
+  /** First day to include, YYYY-MM-DD */
+  from?: string | null;
+  /** Last day to include, YYYY-MM-DD */
+  to?: string | null;
+}
+
+export interface AuditVerification {
+  valid: boolean;
+  /** Entries checked */
+  entries: number;
+  /** First entry that was changed, removed or left out of the chain */
+  broken_at: number | null;
+  message: string;
+}
+
+/**
+ * Get audit log entries, newest first
+ * @param filter Entity, record, user and date filters
+ * @param page Page number
+ * @param perPage Items per page
+ * @returns Promise with paginated audit entries
+ */
+export async function getAuditLog(
+  filter: AuditFilter = {},
+  page: number = 1,
+  perPage: number = 20
+): Promise<PaginatedResponse<AuditEntry>> {
+  return await invoke<PaginatedResponse<AuditEntry>>("get_audit_log", {
+    filter,
+    page,
+    perPage,
+  });
+}
+
+/**
+ * Recompute the audit log hash chain to detect tampering
+ * @returns Promise with the verification result
+ */
+export async function verifyAuditChain(): Promise<AuditVerification> {
+  return await invoke<AuditVerification>("verify_audit_chain");
+}
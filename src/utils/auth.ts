@@ -1,16 +1,167 @@
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "./invoke";
+import type { LicenseMode } from "./license";
 
 export interface User {
   id: number;
   username: string;
   email: string;
+  role: string;
   created_at: string;
 }
 
+/** Something a role allows, e.g. "sales.write" or "users.manage" */
+export type Capability =
+  | "users.manage"
+  | "settings.manage"
+  | "database.manage"
+  | "database.query"
+  | "database.execute"
+  | "customers.read"
+  | "customers.write"
+  | "suppliers.read"
+  | "suppliers.write"
+  | "inventory.read"
+  | "inventory.write"
+  | "purchases.read"
+  | "purchases.write"
+  | "sales.read"
+  | "sales.write"
+  | "accounting.read"
+  | "accounting.write"
+  | "expenses.read"
+  | "expenses.write"
+  | "hr.read"
+  | "hr.write";
+
 export interface LoginResult {
   success: boolean;
   user: User | null;
   message: string;
+  /** What the logged-in user's role may do (empty unless success) */
+  capabilities: Capability[];
+  /** Opaque session token (only after a successful login) */
+  token: string | null;
+  session: SessionInfo | null;
+  /** The user must call changePassword() before anything else works */
+  must_change_password: boolean;
+  /** Seconds to wait before trying again, after too many failed attempts */
+  retry_after_seconds: number | null;
+  /** Set when the password was right but a code is needed: pass it to verifyTwoFactor() */
+  two_factor_challenge: string | null;
+  /** The role requires two-factor authentication; only the setup commands work until it is enabled */
+  must_enroll_two_factor: boolean;
+}
+
+export interface TwoFactorStatus {
+  enabled: boolean;
+  /** The user's role requires two-factor authentication */
+  required: boolean;
+  recovery_codes_left: number;
+}
+
+export interface TwoFactorEnrollment {
+  /** Base32 secret, for typing into an authenticator app by hand */
+  secret: string;
+  /** otpauth:// URI to show as a QR code */
+  uri: string;
+}
+
+/** Roles whose users must use two-factor authentication */
+export interface TwoFactorPolicy {
+  required_roles: string[];
+}
+
+/** Rules new passwords must follow */
+export interface PasswordPolicy {
+  min_length: number;
+  require_uppercase: boolean;
+  require_lowercase: boolean;
+  require_digit: boolean;
+  require_symbol: boolean;
+  /** How many previous passwords can't be used again (0 allows reuse) */
+  history: number;
+}
+
+export interface SessionInfo {
+  id: string;
+  user_id: number;
+  username: string;
+  role: string;
+  /** Where the session was opened, e.g. the desktop window label */
+  client: string;
+  created_at: string;
+  last_seen_at: string;
+  /** When the session ends unless it is used again before then */
+  expires_at: string;
+  /** The session of the window asking */
+  current: boolean;
+}
+
+/** A REST API token (the token itself is only returned by createApiToken) */
+export interface ApiToken {
+  id: number;
+  user_id: number;
+  name: string;
+  /** First characters of the token, to tell tokens apart */
+  prefix: string;
+  created_at: string;
+  last_used_at: string | null;
+  /** null for tokens that don't expire */
+  expires_at: string | null;
+}
+
+export interface IssuedApiToken extends ApiToken {
+  /** Send as `Authorization: Bearer <token>` to /api/v1 */
+  token: string;
+}
+
+export interface CurrentUser extends SessionInfo {
+  capabilities: Capability[];
+  must_change_password: boolean;
+  must_enroll_two_factor: boolean;
+}
+
+export interface Role {
+  name: string;
+  description: string | null;
+  capabilities: Capability[];
+  /** Shipped with the app: can't be deleted, and "admin" can't be edited */
+  built_in: boolean;
+}
+
+/** Error returned instead of running a command the current session may not call */
+export type AccessError =
+  | { kind: "unauthenticated"; command: string; message: string }
+  | { kind: "forbidden"; command: string; capability: Capability | null; message: string }
+  | { kind: "reauthentication_required"; command: string; message: string }
+  | { kind: "password_change_required"; command: string; message: string }
+  | { kind: "two_factor_setup_required"; command: string; message: string }
+  | { kind: "license_restricted"; command: string; mode: LicenseMode; message: string };
+
+/**
+ * Check whether a rejected command failed the access check
+ * @param error Error caught from invoke
+ * @returns True if nobody is logged in, the role lacks the command's capability, the user must confirm or change the password or set up two-factor authentication, or the license doesn't allow the command
+ */
+export function isAccessError(error: unknown): error is AccessError {
+  const kind = (error as AccessError | null)?.kind;
+  return (
+    kind === "unauthenticated" ||
+    kind === "forbidden" ||
+    kind === "reauthentication_required" ||
+    kind === "password_change_required" ||
+    kind === "two_factor_setup_required" ||
+    kind === "license_restricted"
+  );
+}
+
+/**
+ * Check whether a rejected command was refused for lack of a capability
+ * @param error Error caught from invoke
+ * @returns True if the logged-in role lacks the command's capability
+ */
+export function isForbiddenError(error: unknown): error is Extract<AccessError, { kind: "forbidden" }> {
+  return isAccessError(error) && error.kind === "forbidden";
 }
 
 /**
@@ -55,3 +206,278 @@ export async function loginUser(
     password,
   });
 }
+
+/**
+ * Second login step: check a code from the authenticator app, or a recovery code
+ * @param challenge two_factor_challenge from the LoginResult of loginUser()
+ * @param code 6-digit code or recovery code
+ * @returns Promise with LoginResult; on a wrong code it carries the challenge again until too many attempts
+ */
+export async function verifyTwoFactor(
+  challenge: string,
+  code: string
+): Promise<LoginResult> {
+  return await invoke<LoginResult>("verify_two_factor", {
+    challenge,
+    code,
+  });
+}
+
+/**
+ * Check whether a command was refused until two-factor authentication is set up
+ * @param error Error caught from invoke
+ * @returns True if beginTwoFactorEnrollment() must be completed first
+ */
+export function isTwoFactorSetupRequired(error: unknown): boolean {
+  return isAccessError(error) && error.kind === "two_factor_setup_required";
+}
+
+/**
+ * Check whether a command was refused by the license: none entered, expired past the grace period, or missing the module
+ * @param error Error caught from invoke
+ * @returns True if getLicenseStatus() explains what the license allows
+ */
+export function isLicenseRestricted(error: unknown): boolean {
+  return isAccessError(error) && error.kind === "license_restricted";
+}
+
+/**
+ * Get whether the logged-in user has two-factor authentication
+ * @returns Promise with the two-factor status
+ */
+export async function getTwoFactorStatus(): Promise<TwoFactorStatus> {
+  return await invoke<TwoFactorStatus>("get_two_factor_status");
+}
+
+/**
+ * Start setting up two-factor authentication with a new secret
+ * @returns Promise with the secret and the URI for the QR code
+ */
+export async function beginTwoFactorEnrollment(): Promise<TwoFactorEnrollment> {
+  return await invoke<TwoFactorEnrollment>("begin_two_factor_enrollment");
+}
+
+/**
+ * Finish setting up two-factor authentication with a code from the authenticator app
+ * @param code 6-digit code
+ * @returns Promise with the recovery codes, which are only shown this once
+ */
+export async function confirmTwoFactorEnrollment(code: string): Promise<string[]> {
+  return await invoke<string[]>("confirm_two_factor_enrollment", { code });
+}
+
+/**
+ * Turn off two-factor authentication (not allowed if the role requires it)
+ * @returns Promise that resolves once disabled
+ */
+export async function disableTwoFactor(): Promise<void> {
+  return await invoke<void>("disable_two_factor");
+}
+
+/**
+ * Replace the recovery codes; the old ones stop working
+ * @returns Promise with the new recovery codes
+ */
+export async function regenerateRecoveryCodes(): Promise<string[]> {
+  return await invoke<string[]>("regenerate_recovery_codes");
+}
+
+/**
+ * Remove a user's two-factor authentication, e.g. after a lost phone (admin only)
+ * @param userId User ID
+ * @returns Promise that resolves once reset
+ */
+export async function resetTwoFactor(userId: number): Promise<void> {
+  return await invoke<void>("reset_two_factor", { userId });
+}
+
+/**
+ * Get the roles that must use two-factor authentication (admin only)
+ * @returns Promise with the policy
+ */
+export async function getTwoFactorPolicy(): Promise<TwoFactorPolicy> {
+  return await invoke<TwoFactorPolicy>("get_two_factor_policy");
+}
+
+/**
+ * Change the roles that must use two-factor authentication (admin only)
+ * @param policy New policy
+ * @returns Promise with the saved policy
+ */
+export async function updateTwoFactorPolicy(policy: TwoFactorPolicy): Promise<TwoFactorPolicy> {
+  return await invoke<TwoFactorPolicy>("update_two_factor_policy", { policy });
+}
+
+/**
+ * Check whether a sensitive command was refused until the password is confirmed with reauthenticate()
+ * @param error Error caught from invoke
+ * @returns True if the command needs a recent password confirmation
+ */
+export function isReauthenticationRequired(error: unknown): boolean {
+  return isAccessError(error) && error.kind === "reauthentication_required";
+}
+
+/**
+ * End the session of this window
+ * @returns Promise with true if a session was open
+ */
+export async function logoutUser(): Promise<boolean> {
+  return await invoke<boolean>("logout_user");
+}
+
+/**
+ * Get the user logged in to this window
+ * @returns Promise with the current user, or null if the session is missing or expired
+ */
+export async function getCurrentUser(): Promise<CurrentUser | null> {
+  return await invoke<CurrentUser | null>("get_current_user");
+}
+
+/**
+ * Confirm the current user's password to unlock sensitive commands for a few minutes
+ * @param password Current user's password
+ * @returns Promise with true if the password was correct
+ */
+export async function reauthenticate(password: string): Promise<boolean> {
+  return await invoke<boolean>("reauthenticate", { password });
+}
+
+/**
+ * Check whether a command was refused because the user still has to choose a new password
+ * @param error Error caught from invoke
+ * @returns True if changePassword() must be called first
+ */
+export function isPasswordChangeRequired(error: unknown): boolean {
+  return isAccessError(error) && error.kind === "password_change_required";
+}
+
+/**
+ * Change the current user's password
+ * @param currentPassword Current password
+ * @param newPassword New password; must follow the password policy and not be a recent one
+ * @returns Promise that resolves once changed
+ */
+export async function changePassword(
+  currentPassword: string,
+  newPassword: string
+): Promise<void> {
+  return await invoke<void>("change_password", {
+    currentPassword,
+    newPassword,
+  });
+}
+
+/**
+ * Clear a user's failed logins and lift any lockout (admin only)
+ * @param userId User ID
+ * @returns Promise that resolves once unlocked
+ */
+export async function unlockUser(userId: number): Promise<void> {
+  return await invoke<void>("unlock_user", { userId });
+}
+
+/**
+ * Get the rules new passwords must follow
+ * @returns Promise with the password policy
+ */
+export async function getPasswordPolicy(): Promise<PasswordPolicy> {
+  return await invoke<PasswordPolicy>("get_password_policy");
+}
+
+/**
+ * Change the rules new passwords must follow (admin only)
+ * @param policy New password policy
+ * @returns Promise with the saved policy
+ */
+export async function updatePasswordPolicy(policy: PasswordPolicy): Promise<PasswordPolicy> {
+  return await invoke<PasswordPolicy>("update_password_policy", { policy });
+}
+
+/**
+ * List every open session (admin only)
+ * @returns Promise with the sessions, oldest first
+ */
+export async function listSessions(): Promise<SessionInfo[]> {
+  return await invoke<SessionInfo[]>("list_sessions");
+}
+
+/**
+ * End another session (admin only)
+ * @param id Session id from listSessions()
+ * @returns Promise that resolves once the session is ended
+ */
+export async function revokeSession(id: string): Promise<void> {
+  return await invoke<void>("revoke_session", { id });
+}
+
+/**
+ * Create a REST API token that acts as the current user (needs a recent password confirmation)
+ * @param name What the token is for, e.g. "Stock sync"
+ * @param expiresInDays Days until the token expires; null for a token that lasts until revoked
+ * @returns Promise with the token, which can't be shown again
+ */
+export async function createApiToken(name: string, expiresInDays: number | null): Promise<IssuedApiToken> {
+  return await invoke<IssuedApiToken>("create_api_token", { name, expiresInDays });
+}
+
+/**
+ * List the current user's API tokens, or another user's (admin only)
+ * @param userId User whose tokens to list; defaults to the current user
+ * @returns Promise with the tokens, newest first
+ */
+export async function getApiTokens(userId?: number): Promise<ApiToken[]> {
+  return await invoke<ApiToken[]>("get_api_tokens", { userId: userId ?? null });
+}
+
+/**
+ * Revoke an API token; requests using it are refused from then on
+ * @param id Token id from getApiTokens()
+ * @returns Promise that resolves once revoked
+ */
+export async function revokeApiToken(id: number): Promise<void> {
+  return await invoke<void>("revoke_api_token", { id });
+}
+
+/**
+ * List every capability a role can be granted
+ * @returns Promise with the capability names
+ */
+export async function listCapabilities(): Promise<Capability[]> {
+  return await invoke<Capability[]>("list_capabilities");
+}
+
+/**
+ * Get all roles: the built-in ones followed by custom roles
+ * @returns Promise with the roles
+ */
+export async function getRoles(): Promise<Role[]> {
+  return await invoke<Role[]>("get_roles");
+}
+
+/**
+ * Create a custom role or change the capabilities of an existing one
+ * @param name Role name (lowercase letters, digits and underscores)
+ * @param description Optional description
+ * @param capabilities Capabilities granted to the role
+ * @returns Promise with the saved role
+ */
+export async function saveRole(
+  name: string,
+  description: string | null,
+  capabilities: Capability[]
+): Promise<Role> {
+  return await invoke<Role>("save_role", {
+    name,
+    description,
+    capabilities,
+  });
+}
+
+/**
+ * Delete a custom role that no user is assigned to
+ * @param name Role name
+ * @returns Promise that resolves once deleted
+ */
+export async function deleteRole(name: string): Promise<void> {
+  return await invoke<void>("delete_role", { name });
+}
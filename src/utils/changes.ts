@@ -0,0 +1,36 @@
+import { isTauri } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
+
+/** Name of the Tauri event the backend emits after every committed change to a record */
+export const ENTITY_CHANGED = "entity_changed";
+
+/** Payload of `entity_changed` */
+export interface EntityChanged {
+  /** Table of the record, e.g. "sales" */
+  entity: string;
+  id: string | null;
+  action: "create" | "update" | "delete";
+  /** "server" for changes on the online SurrealDB server, which other clients may have made */
+  source: "local" | "server";
+}
+
+/**
+ * Call `handler` whenever a record changes, in this window or any other (and, in SurrealDB
+ * online mode, on another client)
+ * @param handler Called with each change
+ * @param entities Only report changes to these tables (all when omitted)
+ * @returns Promise with a function that stops listening; in a browser (LAN mode) nothing is reported
+ */
+export async function onEntityChanged(
+  handler: (change: EntityChanged) => void,
+  entities?: string[]
+): Promise<UnlistenFn> {
+  if (!isTauri()) {
+    return () => {};
+  }
+  return await listen<EntityChanged>(ENTITY_CHANGED, (event) => {
+    if (!entities || entities.includes(event.payload.entity)) {
+      handler(event.payload);
+    }
+  });
+}
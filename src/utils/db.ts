@@ -1,4 +1,4 @@
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "./invoke";
 
 export interface QueryResult {
   columns: string[];
@@ -17,6 +17,43 @@ export interface DatabaseConfig {
   database?: string | null;
   username?: string | null;
   password?: string | null;
+  conflict_policy?: ConflictPolicy;
+}
+
+export type ConflictPolicy = "last_writer_wins" | "server_wins" | "manual";
+
+export interface SyncReport {
+  pushed: number;
+  pulled: number;
+  conflicts_resolved: number;
+  conflicts_queued: number;
+}
+
+export interface TableChanges {
+  table: string;
+  count: number;
+}
+
+export interface SyncStatus {
+  conflict_policy: ConflictPolicy;
+  online: boolean;
+  last_sync_at: string | null;
+  pending_push: number;
+  pending_push_by_table: TableChanges[];
+  pending_pull: number | null;
+  conflicts: number;
+}
+
+export interface SyncConflict {
+  table: string;
+  key: number | string;
+  local_deleted: boolean;
+  local_changed_at: string;
+  local: Record<string, any> | null;
+  remote_deleted: boolean;
+  remote_changed_at: string;
+  remote: Record<string, any> | null;
+  detected_at: string;
 }
 
 /**
@@ -132,22 +169,29 @@ export async function isDatabaseOpen(): Promise<boolean> {
   return await isDatabaseOpenSurreal();
 }
 
+/**
+ * Values bound to `$name` placeholders in a SurrealQL query
+ */
+export type SurrealBindings = Record<string, unknown>;
+
 /**
  * Execute a SurrealQL query (CREATE, UPDATE, DELETE, etc.)
- * Note: params are not used in SurrealQL - values should be embedded in the query
+ * Note: never embed values in the query - reference them as `$name` and pass them in bindings
  * @param query SurrealQL query string
- * @param params Not used for SurrealDB (kept for backward compatibility)
+ * @param params Positional parameters for the SQLite fallback
+ * @param bindings Values for the `$name` placeholders in a SurrealQL query
  * @returns Promise with ExecuteResult containing rows_affected
  */
 export async function executeQuery(
   query: string,
-  params: any[] = []
+  params: any[] = [],
+  bindings: SurrealBindings = {}
 ): Promise<ExecuteResult> {
   // Try SurrealDB first
   try {
     const isOpen = await isDatabaseOpenSurreal();
     if (isOpen) {
-      return await invoke<ExecuteResult>("db_execute_surreal", { query });
+      return await invoke<ExecuteResult>("db_execute_surreal", { query, bindings });
     }
   } catch {
     // Fallback to SQLite
@@ -158,24 +202,123 @@ export async function executeQuery(
 
 /**
  * Execute a SurrealQL SELECT query and return results
- * Note: params are not used in SurrealQL - values should be embedded in the query
+ * Note: never embed values in the query - reference them as `$name` and pass them in bindings
  * @param query SurrealQL SELECT query string
  * @param params Not used for SurrealDB (kept for backward compatibility)
+ * @param bindings Values for the `$name` placeholders in the query
  * @returns Promise with QueryResult containing columns and rows
  */
 export async function queryDatabase(
   query: string,
-  params: any[] = []
+  params: any[] = [],
+  bindings: SurrealBindings = {}
 ): Promise<QueryResult> {
-  return await invoke<QueryResult>("db_query_surreal", { query });
+  return await invoke<QueryResult>("db_query_surreal", { query, bindings });
 }
 
 /**
- * Sync data between offline and online SurrealDB
+ * Sync data between offline and online SurrealDB (only changes since the last sync are sent)
+ * @returns Promise with the number of records pushed, pulled and in conflict
+ */
+export async function syncDatabase(): Promise<SyncReport> {
+  return await invoke<SyncReport>("db_sync");
+}
+
+/**
+ * Get pending local/server changes and the number of unresolved conflicts
+ * @returns Promise with the sync status
+ */
+export async function getSyncStatus(): Promise<SyncStatus> {
+  return await invoke<SyncStatus>("db_sync_status");
+}
+
+/**
+ * Get sync conflicts waiting for a manual decision
+ * @returns Promise with both versions of every conflicting record
+ */
+export async function getSyncConflicts(): Promise<SyncConflict[]> {
+  return await invoke<SyncConflict[]>("db_sync_conflicts");
+}
+
+/**
+ * Resolve a sync conflict by keeping one version of the record
+ * @param table Table of the conflicting record
+ * @param key Record id within the table
+ * @param keep Which version to keep
  * @returns Promise with success message
  */
-export async function syncDatabase(): Promise<string> {
-  return await invoke<string>("db_sync");
+export async function resolveSyncConflict(
+  table: string,
+  key: number | string,
+  keep: "local" | "remote"
+): Promise<string> {
+  return await invoke<string>("db_sync_resolve_conflict", { table, key, keep });
+}
+
+export interface TableTransfer {
+  table: string;
+  /** Rows in the source table */
+  source_rows: number;
+  /** Rows copied by this run */
+  transferred: number;
+  /** Rows copied by this run and by earlier, interrupted runs */
+  total_transferred: number;
+}
+
+export interface TransferReport {
+  tables: TableTransfer[];
+  transferred: number;
+}
+
+/**
+ * Copy every table from the SQLite file into SurrealDB (ids, links and timestamps are kept)
+ * @param restart Copy everything again instead of resuming after the last copied batch
+ * @returns Promise with per-table row counts
+ */
+export async function migrateSqliteToSurreal(restart: boolean = false): Promise<TransferReport> {
+  return await invoke<TransferReport>("migrate_sqlite_to_surreal", { restart });
+}
+
+/**
+ * Copy every table from SurrealDB back into the SQLite file
+ * @param restart Copy everything again instead of resuming after the last copied batch
+ * @returns Promise with per-table row counts
+ */
+export async function exportSurrealToSqlite(restart: boolean = false): Promise<TransferReport> {
+  return await invoke<TransferReport>("export_surreal_to_sqlite", { restart });
+}
+
+export interface AppliedMigration {
+  version: number;
+  name: string;
+}
+
+export interface MigrationReport {
+  from_version: number;
+  to_version: number;
+  applied: AppliedMigration[];
+}
+
+export interface SchemaVersion {
+  current: number;
+  latest: number;
+  pending: AppliedMigration[];
+}
+
+/**
+ * Run all pending SQLite schema migrations (all-or-nothing)
+ * @returns Promise with the migrations that were applied
+ */
+export async function migrateDatabase(): Promise<MigrationReport> {
+  return await invoke<MigrationReport>("db_migrate");
+}
+
+/**
+ * Get the current SQLite schema version and pending migrations
+ * @returns Promise with SchemaVersion
+ */
+export async function getSchemaVersion(): Promise<SchemaVersion> {
+  return await invoke<SchemaVersion>("db_schema_version");
 }
 
 /**
@@ -0,0 +1,43 @@
+import { invoke as tauriInvoke, isTauri, type InvokeArgs } from "@tauri-apps/api/core";
+
+/** Endpoint of the backend's commands when the app is opened in a browser (LAN mode) */
+export const RPC_URL = "/api/rpc";
+
+/** Error body of the HTTP server */
+interface ApiErrorBody {
+  error: { code: string; message: string; details?: Record<string, unknown> };
+}
+
+/** Error codes of the HTTP server that are access check failures, reported like the desktop's AccessError */
+const ACCESS_CODES = ["unauthenticated", "forbidden", "license_restricted"];
+
+/**
+ * Call a backend command: through Tauri in the desktop app, or through the LAN mode RPC
+ * endpoint when the app is opened in a browser on another device
+ * @param command Command name, e.g. "get_products"
+ * @param args Command arguments, named as for Tauri (camelCase)
+ * @returns Promise with the command's result; rejects with its error message, or an AccessError
+ */
+export async function invoke<T>(command: string, args?: InvokeArgs): Promise<T> {
+  if (isTauri()) {
+    return await tauriInvoke<T>(command, args);
+  }
+  const response = await fetch(RPC_URL, {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    credentials: "same-origin",
+    body: JSON.stringify({ command, args: args ?? {} }),
+  });
+  const body = await response.json().catch(() => null);
+  if (response.ok) {
+    return body as T;
+  }
+  const error = (body as ApiErrorBody | null)?.error;
+  if (!error) {
+    throw `The server answered ${response.status}`;
+  }
+  if (ACCESS_CODES.includes(error.code)) {
+    throw { kind: error.code, command, ...error.details, message: error.message };
+  }
+  throw error.message;
+}
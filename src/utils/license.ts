@@ -1,4 +1,4 @@
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "./invoke";
 
 /**
  * Get the machine ID for this device
@@ -8,9 +8,39 @@ export async function getMachineId(): Promise<string> {
   return await invoke<string>("get_machine_id");
 }
 
+/** Hardware or installation identity that goes into the machine ID */
+export type MachineComponent = "machine_uuid" | "cpu" | "disks" | "network" | "host";
+
+export interface ComponentCheck {
+  component: MachineComponent;
+  /** How much the component counts towards a match */
+  weight: number;
+  /** Whether the component could be read on this machine */
+  present: boolean;
+  matches: boolean;
+}
+
+export interface MachineDiagnosis {
+  machine_id: string;
+  /** Machine ID the license was issued for, null if no license could be read */
+  licensed_machine_id: string | null;
+  /** Component by component comparison, null for licenses with an older machine ID */
+  comparison: { components: ComponentCheck[]; score: number; matches: boolean } | null;
+  matches: boolean;
+}
+
+/**
+ * Show which hardware components changed since a license was issued
+ * @param key License key to check; the stored one if omitted
+ * @returns Promise with the comparison of this machine and the licensed one
+ */
+export async function diagnoseMachineId(key?: string): Promise<MachineDiagnosis> {
+  return await invoke<MachineDiagnosis>("diagnose_machine_id", { licenseKey: key ?? null });
+}
+
 /**
  * Store license key in secure storage
- * @param key The encrypted license key to store
+ * @param key The signed license key to store
  * @returns Promise that resolves when key is stored
  */
 export async function storeLicenseKey(key: string): Promise<void> {
@@ -26,29 +56,77 @@ export async function getLicenseKey(): Promise<string | null> {
   return key;
 }
 
+/** What a signed license grants */
+export interface License {
+  id: string;
+  machine_id: string;
+  customer: string;
+  edition: string;
+  features: string[];
+  issued_at: string;
+  /** Last day the license is valid (YYYY-MM-DD), null if it never expires */
+  expires_at: string | null;
+  /** Most users that may be active at once, null if unlimited */
+  seats: number | null;
+}
+
+/** Optional part of the app a license has to include */
+export type LicenseModule = "payroll" | "accounting" | "multi_currency";
+
+/**
+ * How far the app may be used: "expiring" and "grace" still work fully but should warn,
+ * "read_only" refuses changes until the license is renewed
+ */
+export type LicenseMode = "unlicensed" | "active" | "expiring" | "grace" | "read_only";
+
+export interface LicenseState {
+  mode: LicenseMode;
+  license: License | null;
+  modules: LicenseModule[];
+  /** Days until the license expires; negative once it has */
+  days_left: number | null;
+  /** Last day of the grace period (YYYY-MM-DD), after which the app is read-only */
+  grace_ends: string | null;
+  /** Message to show the user, if there is something to warn about */
+  warning: string | null;
+}
+
+export type LicenseStatus = "valid" | "malformed" | "invalid_signature" | "wrong_machine" | "expired";
+
+export interface LicenseInfo {
+  valid: boolean;
+  status: LicenseStatus;
+  /** Why the license was rejected */
+  reason: string | null;
+  /** License details, whenever the signature checked out */
+  license: License | null;
+}
+
 /**
- * Validate a license key by encrypting current machine ID and comparing
+ * Check a signed license key against this machine
  * @param key The license key to validate
- * @returns Promise with boolean indicating if key is valid
+ * @returns Promise with the license details, or the reason it was rejected
+ */
+export async function validateLicenseKey(key: string): Promise<LicenseInfo> {
+  return await invoke<LicenseInfo>("validate_license_key", { enteredKey: key });
+}
+
+/**
+ * Get what the stored license allows today; poll this to show expiry warnings
+ * @returns Promise with the license mode, modules and any warning
  */
-export async function validateLicenseKey(key: string): Promise<boolean> {
-  return await invoke<boolean>("validate_license_key", { enteredKey: key });
+export async function getLicenseStatus(): Promise<LicenseState> {
+  return await invoke<LicenseState>("get_license_status");
 }
 
 /**
- * Check if a valid license exists in secure storage
- * @returns Promise with boolean indicating if valid license exists
+ * Check if the stored license lets the app be used, possibly read-only
+ * @returns Promise with boolean indicating if a usable license exists
  */
 export async function isLicenseValid(): Promise<boolean> {
   try {
-    const storedKey = await getLicenseKey();
-    if (!storedKey) {
-      return false;
-    }
-    
-    // Validate the stored key
-    const isValid = await validateLicenseKey(storedKey);
-    return isValid;
+    const state = await getLicenseStatus();
+    return state.mode !== "unlicensed";
   } catch (error) {
     console.error("Error checking license validity:", error);
     return false;
@@ -1,3 +1,6 @@
+import { openUrl } from "@tauri-apps/plugin-opener";
+import { aiPageUrl, getServerAccess, SERVER_SECRET_HEADER } from "./server";
+
 export const LS_PUTER_APP_ID = "puter.app.id";
 export const LS_PUTER_TOKEN = "puter.auth.token";
 export const LS_PUTER_MODEL = "shafaf_puter_model";
@@ -8,7 +11,13 @@ export const PUTER_SCRIPT_BASE = "https://js.puter.com/v2/";
  */
 export async function loadPuterCredentialsFromServer(): Promise<boolean> {
   try {
-    const response = await fetch('http://127.0.0.1:5021/api/get-credentials');
+    const access = await getServerAccess();
+    if (!access) {
+      return false;
+    }
+    const response = await fetch(`${access.url}/api/get-credentials`, {
+      headers: { [SERVER_SECRET_HEADER]: access.secret },
+    });
     if (!response.ok) {
       return false;
     }
@@ -29,6 +38,41 @@ export async function loadPuterCredentialsFromServer(): Promise<boolean> {
   }
 }
 
+/**
+ * Open ai.html in the browser; signing in to Puter there sends the credentials back to the app
+ * @returns Promise<true> if the page was opened, Promise<false> if the built-in server isn't running
+ */
+export async function openAiPage(): Promise<boolean> {
+  const access = await getServerAccess();
+  if (!access) {
+    return false;
+  }
+  await openUrl(aiPageUrl(access));
+  return true;
+}
+
+/**
+ * Wait for ai.html to send Puter credentials, saving them to localStorage
+ * @param timeoutMs How long to wait
+ * @returns Promise with the credentials, or null if none arrived in time
+ */
+export async function waitForPuterCredentials(
+  timeoutMs: number = 300000,
+): Promise<{ appId: string; authToken: string } | null> {
+  const deadline = Date.now() + timeoutMs;
+  while (Date.now() < deadline) {
+    if (await loadPuterCredentialsFromServer()) {
+      const appId = localStorage.getItem(LS_PUTER_APP_ID);
+      const authToken = localStorage.getItem(LS_PUTER_TOKEN);
+      if (appId && authToken) {
+        return { appId, authToken };
+      }
+    }
+    await new Promise((resolve) => setTimeout(resolve, 3000));
+  }
+  return null;
+}
+
 /**
  * Periodically check for Puter credentials from the server
  * This is useful when credentials are set in ai.html
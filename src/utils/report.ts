@@ -50,7 +50,7 @@ export async function generateSalesReport(
     FROM sales s
     LEFT JOIN customers c ON s.customer_id = c.id
     LEFT JOIN currencies curr ON s.currency_id = curr.id
-    WHERE s.date >= ? AND s.date <= ?
+    WHERE s.date >= ? AND s.date <= ? AND s.deleted_at IS NULL
     ORDER BY s.date DESC, s.id DESC
   `;
 
@@ -214,7 +214,7 @@ export async function generatePurchaseReport(
     FROM purchases p
     LEFT JOIN suppliers s ON p.supplier_id = s.id
     LEFT JOIN currencies curr ON p.currency_id = curr.id
-    WHERE p.date >= ? AND p.date <= ?
+    WHERE p.date >= ? AND p.date <= ? AND p.deleted_at IS NULL
     ORDER BY p.date DESC, p.id DESC
   `;
 
@@ -364,7 +364,7 @@ export async function generateExpenseReport(
       e.description
     FROM expenses e
     LEFT JOIN expense_types et ON e.expense_type_id = et.id
-    WHERE e.date >= ? AND e.date <= ?
+    WHERE e.date >= ? AND e.date <= ? AND e.deleted_at IS NULL
     ORDER BY e.date DESC, e.id DESC
   `;
 
@@ -549,7 +549,7 @@ export async function generateProductReport(
     FROM sale_items si
     JOIN sales s ON si.sale_id = s.id
     JOIN products p ON si.product_id = p.id
-    WHERE s.date >= ? AND s.date <= ?
+    WHERE s.date >= ? AND s.date <= ? AND s.deleted_at IS NULL
     GROUP BY p.id, p.name
     ORDER BY total_sales_amount DESC
   `;
@@ -568,7 +568,7 @@ export async function generateProductReport(
     FROM purchase_items pi
     JOIN purchases pur ON pi.purchase_id = pur.id
     JOIN products p ON pi.product_id = p.id
-    WHERE pur.date >= ? AND pur.date <= ?
+    WHERE pur.date >= ? AND pur.date <= ? AND pur.deleted_at IS NULL
     GROUP BY p.id, p.name
     ORDER BY total_purchase_amount DESC
   `;
@@ -653,7 +653,7 @@ export async function generateCustomerReport(
       SUM(s.paid_amount) as total_paid,
       SUM(s.base_amount - s.paid_amount) as total_remaining
     FROM customers c
-    LEFT JOIN sales s ON c.id = s.customer_id AND s.date >= ? AND s.date <= ?
+    LEFT JOIN sales s ON c.id = s.customer_id AND s.date >= ? AND s.date <= ? AND s.deleted_at IS NULL
     GROUP BY c.id, c.full_name
     HAVING sale_count > 0
     ORDER BY total_sales DESC
@@ -728,7 +728,7 @@ export async function generateSupplierReport(
       COUNT(DISTINCT p.id) as purchase_count,
       SUM(p.total_amount) as total_purchases
     FROM suppliers s
-    LEFT JOIN purchases p ON s.id = p.supplier_id AND p.date >= ? AND p.date <= ?
+    LEFT JOIN purchases p ON s.id = p.supplier_id AND p.date >= ? AND p.date <= ? AND p.deleted_at IS NULL
     GROUP BY s.id, s.full_name
     HAVING purchase_count > 0
     ORDER BY total_purchases DESC
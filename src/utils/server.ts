@@ -0,0 +1,53 @@
+import { invoke } from "./invoke";
+
+/** Header carrying the per-install secret on the app's own /api/* requests */
+export const SERVER_SECRET_HEADER = "X-Shafaf-Secret";
+
+export interface ServerConfig {
+    bind_address: string;
+    port: number;
+    allowed_origins: string[];
+    tls: boolean;
+    /** Serve the app to browsers on the network, which log in and use it as a terminal */
+    lan_mode: boolean;
+}
+
+export interface ServerAccess {
+    url: string;
+    secret: string;
+    tls_fingerprint: string | null;
+}
+
+/**
+ * Get the built-in server's settings
+ * @returns Promise with bind address, port, allowed origins, TLS and LAN mode flags
+ */
+export async function getServerConfig(): Promise<ServerConfig> {
+    return await invoke<ServerConfig>("get_server_config");
+}
+
+/**
+ * Update the built-in server's settings; they apply after restarting the app
+ * @param config New settings
+ * @returns Promise with the stored (normalised) settings
+ */
+export async function updateServerConfig(config: ServerConfig): Promise<ServerConfig> {
+    return await invoke<ServerConfig>("update_server_config", { config });
+}
+
+/**
+ * Get the running server's URL and install secret
+ * @returns Promise with the access details, or null if the server isn't running
+ */
+export async function getServerAccess(): Promise<ServerAccess | null> {
+    return await invoke<ServerAccess | null>("get_server_access");
+}
+
+/**
+ * Link to ai.html that lets the page send Puter credentials back to the app
+ * @param access Running server's access details
+ * @returns URL with the secret in the fragment, which browsers never send to the server
+ */
+export function aiPageUrl(access: ServerAccess): string {
+    return `${access.url}/ai.html#secret=${encodeURIComponent(access.secret)}`;
+}
@@ -1,4 +1,4 @@
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "./invoke";
 import { syncDatabase, isDatabaseOpenSurreal } from "./db";
 
 export interface SyncStatus {
@@ -0,0 +1,111 @@
+import { invoke } from "./invoke";
+import type { PaginatedResponse } from "./expense";
+
+/** Kinds of records that go to the trash when deleted */
+export type TrashEntity =
+  | "customers"
+  | "suppliers"
+  | "products"
+  | "employees"
+  | "accounts"
+  | "sales"
+  | "purchases"
+  | "expenses"
+  | "salaries"
+  | "deductions";
+
+/** Restore command of each trash */
+const RESTORE_COMMANDS: Record<TrashEntity, string> = {
+  customers: "restore_customer",
+  suppliers: "restore_supplier",
+  products: "restore_product",
+  employees: "restore_employee",
+  accounts: "restore_account",
+  sales: "restore_sale",
+  purchases: "restore_purchase",
+  expenses: "restore_expense",
+  salaries: "restore_salary",
+  deductions: "restore_deduction",
+};
+
+/** A deleted record waiting in the trash */
+export interface TrashedRecord {
+  id: number;
+  /** Name, date or other details identifying the record */
+  label: string;
+  amount: number | null;
+  deleted_at: string;
+  deleted_by: number | null;
+  deleted_by_username: string | null;
+}
+
+export interface RetentionPolicy {
+  /** Days a record stays in the trash before it may be purged */
+  trash_days: number;
+  /** Years documents are kept after they were created, even once deleted */
+  document_years: number;
+}
+
+export interface PurgeReport {
+  /** Records permanently deleted, per entity */
+  purged: Partial<Record<TrashEntity, number>>;
+  /** Records kept because other records still link to them */
+  still_referenced: number;
+}
+
+/**
+ * Get the records in one trash, most recently deleted first
+ * @param entity Kind of record
+ * @param page Page number
+ * @param perPage Items per page
+ * @param search Optional search text
+ * @returns Promise with paginated trashed records
+ */
+export async function getDeletedRecords(
+  entity: TrashEntity,
+  page: number = 1,
+  perPage: number = 10,
+  search?: string
+): Promise<PaginatedResponse<TrashedRecord>> {
+  return await invoke<PaginatedResponse<TrashedRecord>>(`get_deleted_${entity}`, {
+    page,
+    perPage,
+    search: search || null,
+  });
+}
+
+/**
+ * Take a record out of the trash
+ * @param entity Kind of record
+ * @param id Record ID
+ * @returns Promise with success message
+ */
+export async function restoreRecord(entity: TrashEntity, id: number): Promise<string> {
+  return await invoke<string>(RESTORE_COMMANDS[entity], { id });
+}
+
+/**
+ * Permanently delete trashed records that are past the retention period
+ * @param entity Only purge this trash; all of them if omitted
+ * @returns Promise with what was purged
+ */
+export async function purgeTrash(entity?: TrashEntity): Promise<PurgeReport> {
+  return await invoke<PurgeReport>("purge_trash", { entity: entity ?? null });
+}
+
+/**
+ * Get how long deleted records and documents are kept
+ * @returns Promise with the retention policy
+ */
+export async function getRetentionPolicy(): Promise<RetentionPolicy> {
+  return await invoke<RetentionPolicy>("get_retention_policy");
+}
+
+/**
+ * Change how long deleted records and documents are kept
+ * @param policy New retention policy
+ * @returns Promise with the saved policy
+ */
+export async function updateRetentionPolicy(policy: RetentionPolicy): Promise<RetentionPolicy> {
+  return await invoke<RetentionPolicy>("update_retention_policy", { policy });
+}
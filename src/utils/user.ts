@@ -1,5 +1,4 @@
-import { invoke } from "@tauri-apps/api/core";
-import { queryDatabase, executeQuery, resultToObjects } from "./db";
+import { invoke } from "./invoke";
 
 export interface User {
     id: number;
@@ -32,64 +31,11 @@ export interface PaginatedResponse<T> {
 }
 
 /**
- * Initialize the users table with extended fields for user management
+ * Initialize the users table (the schema is managed by the backend migrations)
  * @returns Promise with success message
  */
 export async function initExtendedUsersTable(): Promise<string> {
-    const sql = `
-    CREATE TABLE IF NOT EXISTS users (
-      id INTEGER PRIMARY KEY AUTOINCREMENT,
-      username TEXT UNIQUE NOT NULL,
-      email TEXT UNIQUE NOT NULL,
-      password_hash TEXT NOT NULL,
-      full_name TEXT,
-      phone TEXT,
-      role TEXT DEFAULT 'user',
-      is_active INTEGER DEFAULT 1,
-      created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-      updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
-    )
-  `;
-    await executeQuery(sql);
-
-    // Add missing columns if table already exists
-    try {
-        await executeQuery("ALTER TABLE users ADD COLUMN password_hash TEXT");
-    } catch (e) {
-        // Column might already exist
-    }
-
-    try {
-        await executeQuery("ALTER TABLE users ADD COLUMN full_name TEXT");
-    } catch (e) {
-        // Column might already exist
-    }
-
-    try {
-        await executeQuery("ALTER TABLE users ADD COLUMN phone TEXT");
-    } catch (e) {
-        // Column might already exist
-    }
-
-    try {
-        await executeQuery("ALTER TABLE users ADD COLUMN role TEXT DEFAULT 'user'");
-    } catch (e) {
-        // Column might already exist
-    }
-
-    try {
-        await executeQuery("ALTER TABLE users ADD COLUMN is_active INTEGER DEFAULT 1");
-    } catch (e) {
-        // Column might already exist
-    }
-
-    try {
-        await executeQuery("ALTER TABLE users ADD COLUMN updated_at DATETIME DEFAULT CURRENT_TIMESTAMP");
-    } catch (e) {
-        // Column might already exist
-    }
-
-    return "Users table initialized with extended fields";
+    return await invoke<string>("init_users_table");
 }
 
 /**
@@ -118,182 +64,107 @@ export async function getUsers(
 
     return {
         ...response,
-        items: response.items.map((user) => ({
-            ...user,
-            is_active: Boolean(user.is_active),
-        })),
+        items: response.items.map(toUser),
+    };
+}
+
+/** Fields of a user that can be edited without changing their role, status or password */
+export interface UserProfile {
+    username: string;
+    email: string;
+    full_name: string | null;
+    phone: string | null;
+}
+
+function toUser(user: User): User {
+    return { ...user, is_active: Boolean(user.is_active) };
+}
+
+function toProfile(data: { username: string; email: string; full_name?: string; phone?: string }): UserProfile {
+    return {
+        username: data.username,
+        email: data.email,
+        full_name: data.full_name || null,
+        phone: data.phone || null,
     };
 }
 
 /**
  * Get a single user by ID
  * @param id User ID
- * @returns Promise with user or null
+ * @returns Promise with the user
  */
-export async function getUserById(id: number): Promise<User | null> {
-    const result = await queryDatabase(
-        "SELECT id, username, email, full_name, phone, role, is_active, created_at, updated_at FROM users WHERE id = ?",
-        [id]
-    );
-    const users = resultToObjects(result);
-    if (users.length === 0) return null;
-    return {
-        ...users[0],
-        is_active: Boolean(users[0].is_active),
-    } as User;
+export async function getUserById(id: number): Promise<User> {
+    return toUser(await invoke<User>("get_user", { id }));
 }
 
 /**
- * Create a new user
- * @param userData User data
- * @returns Promise with success message
+ * Get the logged-in user's own account
+ * @returns Promise with the user
  */
-export async function createUser(userData: UserFormData): Promise<string> {
-    // Hash password using bcrypt-like approach (SHA256 for now, should use invoke for proper hashing)
-    const hashedPassword = await invoke<string>("hash_password", { password: userData.password });
-
-    await executeQuery(
-        `INSERT INTO users (username, email, password_hash, full_name, phone, role, is_active, updated_at) 
-     VALUES (?, ?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP)`,
-        [
-            userData.username,
-            userData.email,
-            hashedPassword,
-            userData.full_name || null,
-            userData.phone || null,
-            userData.role || "user",
-            userData.is_active ? 1 : 0,
-        ]
-    );
-    return "User created successfully";
+export async function getProfile(): Promise<User> {
+    return toUser(await invoke<User>("get_profile"));
 }
 
 /**
- * Update an existing user
- * @param id User ID
+ * Create a new user; they must choose their own password at their first login
  * @param userData User data
- * @returns Promise with success message
+ * @returns Promise with the created user
  */
-export async function updateUser(id: number, userData: Partial<UserFormData>): Promise<string> {
-    const updates: string[] = [];
-    const params: any[] = [];
-
-    if (userData.username !== undefined) {
-        updates.push("username = ?");
-        params.push(userData.username);
-    }
-    if (userData.email !== undefined) {
-        updates.push("email = ?");
-        params.push(userData.email);
-    }
-    if (userData.password !== undefined && userData.password.length > 0) {
-        const hashedPassword = await invoke<string>("hash_password", { password: userData.password });
-        updates.push("password_hash = ?");
-        params.push(hashedPassword);
-    }
-    if (userData.full_name !== undefined) {
-        updates.push("full_name = ?");
-        params.push(userData.full_name || null);
-    }
-    if (userData.phone !== undefined) {
-        updates.push("phone = ?");
-        params.push(userData.phone || null);
-    }
-    if (userData.role !== undefined) {
-        updates.push("role = ?");
-        params.push(userData.role);
-    }
-    if (userData.is_active !== undefined) {
-        updates.push("is_active = ?");
-        params.push(userData.is_active ? 1 : 0);
+export async function createUser(userData: UserFormData): Promise<User> {
+    const user = await invoke<User>("create_user", {
+        profile: toProfile(userData),
+        password: userData.password,
+        role: userData.role,
+    });
+    if (!userData.is_active) {
+        return await setUserActive(user.id, false);
     }
-
-    updates.push("updated_at = CURRENT_TIMESTAMP");
-    params.push(id);
-
-    await executeQuery(
-        `UPDATE users SET ${updates.join(", ")} WHERE id = ?`,
-        params
-    );
-    return "User updated successfully";
+    return toUser(user);
 }
 
 /**
- * Update user's own profile
+ * Update an existing user; the role, status and password are only sent when they change
  * @param id User ID
- * @param profileData Profile data
- * @returns Promise with updated user
+ * @param userData User data
+ * @returns Promise with the updated user
  */
-export async function updateUserProfile(
-    id: number,
-    profileData: {
-        username?: string;
-        email?: string;
-        full_name?: string;
-        phone?: string;
-        currentPassword?: string;
-        newPassword?: string;
-    }
-): Promise<User | null> {
-    const updates: string[] = [];
-    const params: any[] = [];
-
-    if (profileData.username) {
-        updates.push("username = ?");
-        params.push(profileData.username);
-    }
-    if (profileData.email) {
-        updates.push("email = ?");
-        params.push(profileData.email);
+export async function updateUser(id: number, userData: UserFormData): Promise<User> {
+    const current = await getUserById(id);
+    let user = toUser(await invoke<User>("update_user", { id, profile: toProfile(userData) }));
+    if (userData.role !== current.role) {
+        user = await setUserRole(id, userData.role);
     }
-    if (profileData.full_name !== undefined) {
-        updates.push("full_name = ?");
-        params.push(profileData.full_name || null);
+    if (userData.is_active !== current.is_active) {
+        user = await setUserActive(id, userData.is_active);
     }
-    if (profileData.phone !== undefined) {
-        updates.push("phone = ?");
-        params.push(profileData.phone || null);
+    if (userData.password) {
+        await resetPassword(id, userData.password);
     }
+    return user;
+}
 
-    // Handle password change
+/**
+ * Update the logged-in user's own profile, and their password if a new one is given
+ * @param profileData Profile data
+ * @returns Promise with the updated user
+ */
+export async function updateUserProfile(profileData: {
+    username: string;
+    email: string;
+    full_name?: string;
+    phone?: string;
+    currentPassword?: string;
+    newPassword?: string;
+}): Promise<User> {
+    const user = await invoke<User>("update_profile", { profile: toProfile(profileData) });
     if (profileData.newPassword && profileData.currentPassword) {
-        // Verify current password
-        const result = await queryDatabase(
-            "SELECT password_hash FROM users WHERE id = ?",
-            [id]
-        );
-        const users = resultToObjects(result);
-        if (users.length === 0) {
-            throw new Error("User not found");
-        }
-
-        const isValid = await invoke<boolean>("verify_password", {
-            password: profileData.currentPassword,
-            hash: users[0].password_hash,
+        await invoke<void>("change_password", {
+            currentPassword: profileData.currentPassword,
+            newPassword: profileData.newPassword,
         });
-
-        if (!isValid) {
-            throw new Error("Current password is incorrect");
-        }
-
-        const hashedPassword = await invoke<string>("hash_password", {
-            password: profileData.newPassword,
-        });
-        updates.push("password_hash = ?");
-        params.push(hashedPassword);
-    }
-
-    if (updates.length > 0) {
-        updates.push("updated_at = CURRENT_TIMESTAMP");
-        params.push(id);
-
-        await executeQuery(
-            `UPDATE users SET ${updates.join(", ")} WHERE id = ?`,
-            params
-        );
     }
-
-    return getUserById(id);
+    return toUser(user);
 }
 
 /**
@@ -302,10 +173,20 @@ export async function updateUserProfile(
  * @returns Promise with success message
  */
 export async function deleteUser(id: number): Promise<string> {
-    await executeQuery("DELETE FROM users WHERE id = ?", [id]);
+    await invoke<void>("delete_user", { id });
     return "User deleted successfully";
 }
 
+/**
+ * Enable or disable a user; disabled users can't log in and are logged out
+ * @param id User ID
+ * @param active New active status
+ * @returns Promise with the updated user
+ */
+export async function setUserActive(id: number, active: boolean): Promise<User> {
+    return toUser(await invoke<User>("set_user_active", { id, active }));
+}
+
 /**
  * Toggle user active status
  * @param id User ID
@@ -313,13 +194,30 @@ export async function deleteUser(id: number): Promise<string> {
  * @returns Promise with success message
  */
 export async function toggleUserStatus(id: number, isActive: boolean): Promise<string> {
-    await executeQuery(
-        "UPDATE users SET is_active = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
-        [isActive ? 1 : 0, id]
-    );
+    await setUserActive(id, isActive);
     return "User status updated successfully";
 }
 
+/**
+ * Give a user another role
+ * @param id User ID
+ * @param role Role name
+ * @returns Promise with the updated user
+ */
+export async function setUserRole(id: number, role: string): Promise<User> {
+    return toUser(await invoke<User>("set_user_role", { id, role }));
+}
+
+/**
+ * Give a user a temporary password they must change at their next login
+ * @param id User ID
+ * @param newPassword Temporary password
+ * @returns Promise that resolves once the password is reset
+ */
+export async function resetPassword(id: number, newPassword: string): Promise<void> {
+    return await invoke<void>("reset_password", { id, newPassword });
+}
+
 /**
  * Get user stats
  * @returns Promise with user statistics
@@ -330,19 +228,5 @@ export async function getUserStats(): Promise<{
     inactive: number;
     admins: number;
 }> {
-    const result = await queryDatabase(`
-    SELECT 
-      COUNT(*) as total,
-      SUM(CASE WHEN is_active = 1 THEN 1 ELSE 0 END) as active,
-      SUM(CASE WHEN is_active = 0 THEN 1 ELSE 0 END) as inactive,
-      SUM(CASE WHEN role = 'admin' THEN 1 ELSE 0 END) as admins
-    FROM users
-  `);
-    const stats = resultToObjects(result)[0];
-    return {
-        total: stats.total || 0,
-        active: stats.active || 0,
-        inactive: stats.inactive || 0,
-        admins: stats.admins || 0,
-    };
+    return await invoke("get_user_stats");
 }
@@ -0,0 +1,114 @@
+import { invoke } from "./invoke";
+import type { PaginatedResponse } from "./expense";
+
+/** Events the backend sends to webhooks */
+export const WEBHOOK_EVENTS = [
+  "sale_created",
+  "payment_received",
+  "purchase_created",
+  "purchase_payment_made",
+  "expense_recorded",
+  "salary_paid",
+  "stock_low",
+] as const;
+
+export type WebhookEvent = (typeof WEBHOOK_EVENTS)[number];
+
+/** A URL that is POSTed the events it subscribes to */
+export interface Webhook {
+  id: number;
+  url: string;
+  /** Key of the `X-Shafaf-Signature` HMAC-SHA256, for the receiver to verify requests */
+  secret: string;
+  /** Subscribed events; empty for all of them */
+  events: WebhookEvent[];
+  is_active: boolean;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface WebhookInput {
+  url: string;
+  /** Leave empty to subscribe to every event */
+  events: WebhookEvent[];
+  is_active: boolean;
+}
+
+/** One event sent (or to be sent) to one webhook */
+export interface WebhookDelivery {
+  id: number;
+  webhook_id: number;
+  url: string;
+  event_id: number;
+  event: WebhookEvent;
+  status: "pending" | "delivered" | "failed";
+  attempts: number;
+  /** When it will be retried (UTC), for pending deliveries */
+  next_attempt_at: string | null;
+  /** HTTP status of the last attempt, if the webhook answered */
+  last_status: number | null;
+  last_error: string | null;
+  delivered_at: string | null;
+  created_at: string;
+}
+
+export interface DeliveryFilter {
+  webhook_id?: number | null;
+  status?: WebhookDelivery["status"] | null;
+  event?: WebhookEvent | null;
+}
+
+/**
+ * Get all webhooks
+ * @returns Promise with the webhooks
+ */
+export async function getWebhooks(): Promise<Webhook[]> {
+  return await invoke<Webhook[]>("get_webhooks");
+}
+
+/**
+ * Add a webhook; its signing secret is generated
+ * @param input URL, events and active flag
+ * @returns Promise with the created webhook
+ */
+export async function createWebhook(input: WebhookInput): Promise<Webhook> {
+  return await invoke<Webhook>("create_webhook", { input });
+}
+
+/**
+ * Change a webhook's URL, events or active flag
+ * @param id Webhook ID
+ * @param input URL, events and active flag
+ * @returns Promise with the updated webhook
+ */
+export async function updateWebhook(id: number, input: WebhookInput): Promise<Webhook> {
+  return await invoke<Webhook>("update_webhook", { id, input });
+}
+
+/**
+ * Remove a webhook and its delivery log
+ * @param id Webhook ID
+ * @returns Promise that resolves when it is removed
+ */
+export async function deleteWebhook(id: number): Promise<void> {
+  await invoke("delete_webhook", { id });
+}
+
+/**
+ * Get the webhook delivery log, newest first
+ * @param filter Webhook, status and event filters
+ * @param page Page number
+ * @param perPage Items per page
+ * @returns Promise with paginated deliveries
+ */
+export async function getWebhookDeliveries(
+  filter: DeliveryFilter = {},
+  page: number = 1,
+  perPage: number = 20
+): Promise<PaginatedResponse<WebhookDelivery>> {
+  return await invoke<PaginatedResponse<WebhookDelivery>>("get_webhook_deliveries", {
+    filter,
+    page,
+    perPage,
+  });
+}